@@ -14,7 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
  */
-use vsmtp_common::ContextFinished;
+use vsmtp_common::{rcpt::Rcpt, ContextFinished};
 use vsmtp_config::Config;
 use vsmtp_mail_parser::MessageBody;
 extern crate alloc;
@@ -84,6 +84,79 @@ mod tests {
             assert_eq!(q.to_string(), str);
         }
     }
+
+    async fn is_sent(
+        queue_manager: &crate::temp::QueueManager,
+        msg_uuid: &uuid::Uuid,
+    ) -> Vec<bool> {
+        queue_manager
+            .get_ctx(&QueueID::Deliver, msg_uuid)
+            .await
+            .unwrap()
+            .rcpt_to
+            .forward_paths
+            .into_iter()
+            .map(|rcpt| {
+                matches!(
+                    rcpt.email_status,
+                    vsmtp_common::transfer::EmailTransferStatus::Sent { .. }
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn update_rcpt_status_per_job() {
+        use vsmtp_common::transfer::{EmailTransferStatus, Transfer};
+
+        let config = alloc::sync::Arc::new(vsmtp_test::config::local_test());
+        let queue_manager = crate::temp::QueueManager::init(config).unwrap();
+
+        let new_rcpt = |address: &str, transfer_method: Transfer| Rcpt {
+            transfer_method,
+            ..Rcpt::new(address.parse().unwrap())
+        };
+        let sent = |job: &[Rcpt]| {
+            job.iter()
+                .cloned()
+                .map(|rcpt| Rcpt {
+                    email_status: EmailTransferStatus::Sent {
+                        timestamp: time::OffsetDateTime::now_utc(),
+                    },
+                    ..rcpt
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let deliver_job = vec![new_rcpt("a@remote.com", Transfer::Deliver)];
+        let maildir_job = vec![
+            new_rcpt("b@local.com", Transfer::Maildir),
+            new_rcpt("c@local.com", Transfer::Maildir),
+        ];
+
+        let mut ctx = vsmtp_test::config::local_ctx();
+        ctx.rcpt_to.forward_paths = deliver_job.iter().chain(&maildir_job).cloned().collect();
+        let msg_uuid = ctx.mail_from.message_uuid;
+        queue_manager
+            .write_ctx(&QueueID::Deliver, &ctx)
+            .await
+            .unwrap();
+
+        queue_manager
+            .update_rcpt_status(&QueueID::Deliver, &msg_uuid, &sent(&maildir_job))
+            .await
+            .unwrap();
+        assert_eq!(
+            is_sent(&queue_manager, &msg_uuid).await,
+            [false, true, true]
+        );
+
+        queue_manager
+            .update_rcpt_status(&QueueID::Deliver, &msg_uuid, &sent(&deliver_job))
+            .await
+            .unwrap();
+        assert_eq!(is_sent(&queue_manager, &msg_uuid).await, [true, true, true]);
+    }
 }
 
 ///
@@ -187,6 +260,35 @@ where
         ))
     }
 
+    /// Persist the status of a subset of the recipients of a message, leaving
+    /// the other recipients untouched.
+    ///
+    /// Used to track the progress of each transport independently, so that
+    /// the completion of one of them is not lost if another fails or crashes.
+    #[inline]
+    async fn update_rcpt_status(
+        &self,
+        queue: &QueueID,
+        msg_uuid: &uuid::Uuid,
+        rcpt: &[Rcpt],
+    ) -> anyhow::Result<()>
+    where
+        Self: Sized,
+    {
+        let mut ctx = self.get_ctx(queue, msg_uuid).await?;
+
+        for updated in rcpt {
+            if let Some(stored) = ctx.rcpt_to.forward_paths.iter_mut().find(|stored| {
+                stored.address == updated.address
+                    && stored.transfer_method == updated.transfer_method
+            }) {
+                stored.email_status = updated.email_status.clone();
+            }
+        }
+
+        self.write_ctx(queue, &ctx).await
+    }
+
     ///
     #[inline]
     async fn move_to_from_id(
@@ -132,6 +132,11 @@ mod tests {
   "reverse_path": "client@client.testserver.com",
   "mail_timestamp": "{mail_timestamp}",
   "message_uuid": "{msg_uuid}",
+  "dsn_return": null,
+  "envelop_id": null,
+  "use_smtputf8": false,
+  "auth_mailbox": null,
+  "require_tls": false,
   "forward_paths": [],
   "transaction_type": {{
     "incoming": null
@@ -219,6 +224,11 @@ Message body:
   "reverse_path": "client@client.testserver.com",
   "mail_timestamp": "{mail_timestamp}",
   "message_uuid": "{msg_uuid}",
+  "dsn_return": null,
+  "envelop_id": null,
+  "use_smtputf8": false,
+  "auth_mailbox": null,
+  "require_tls": false,
   "forward_paths": [],
   "transaction_type": {{
     "incoming": null
@@ -0,0 +1,75 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::{
+    private_key::PrivateKey,
+    record::{Type, Version},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Maximum length of a character-string in a DNS TXT record (RFC 1035 3.3).
+const TXT_CHUNK_MAX_LENGTH: usize = 255;
+
+///
+#[derive(Debug, thiserror::Error)]
+#[error("failed to encode the public key: {0}")]
+pub struct DnsRecordError(rsa::pkcs8::spki::Error);
+
+/// Produce the DNS TXT record publishing the public key of `private_key`,
+/// to add in the zone of the signing domain.
+///
+/// ```txt
+/// {selector}._domainkey IN TXT ( "v=DKIM1; k=rsa; p=..." "..." )
+/// ```
+///
+/// The value is split in strings of at most 255 characters.
+///
+/// # Errors
+///
+/// * Failed to encode the RSA public key.
+pub fn dns_record(private_key: &PrivateKey, selector: &str) -> Result<String, DnsRecordError> {
+    let (r#type, public_key) = match private_key {
+        PrivateKey::Rsa(rsa) => (
+            Type::Rsa,
+            rsa::pkcs8::EncodePublicKey::to_public_key_der(&rsa::RsaPublicKey::from(rsa.as_ref()))
+                .map_err(DnsRecordError)?
+                .as_ref()
+                .to_vec(),
+        ),
+        PrivateKey::Ed25519(ed25519) => (
+            Type::Ed25519,
+            ring_compat::ring::signature::KeyPair::public_key(ed25519.as_ref())
+                .as_ref()
+                .to_vec(),
+        ),
+    };
+
+    let value = format!(
+        "v={}; k={}; p={}",
+        Version::Dkim1,
+        r#type,
+        STANDARD.encode(public_key)
+    );
+
+    let chunks = value
+        .as_bytes()
+        .chunks(TXT_CHUNK_MAX_LENGTH)
+        .map(|chunk| format!("\"{}\"", String::from_utf8_lossy(chunk)))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Ok(format!("{selector}._domainkey IN TXT ( {chunks} )"))
+}
@@ -17,6 +17,7 @@
 
 mod algorithm;
 mod canonicalization;
+mod dns_record;
 mod private_key;
 mod public_key;
 mod record;
@@ -31,12 +32,14 @@ mod tests {
         mod signature_header;
     }
     mod canonicalization;
+    mod dns_record;
 }
 
 const RSA_MINIMUM_ACCEPTABLE_KEY_SIZE: usize = 1024;
 
 pub use algorithm::{HashAlgorithm, SigningAlgorithm};
 pub use canonicalization::Canonicalization;
+pub use dns_record::{dns_record, DnsRecordError};
 pub use private_key::PrivateKey;
 pub use public_key::PublicKey;
 pub use sign::{sign, SigningError};
@@ -94,6 +97,8 @@ mod verify {
             expected
         )]
         BodyHashMismatch { got: Vec<u8>, expected: String },
+        #[error("the body length tag (l={body_length}) is greater than the length of the body ({length})")]
+        BodyLengthExceeded { body_length: usize, length: usize },
         #[error("headers hash does not match, got `{0}`")]
         BackendError(BackendError),
         #[error("base64 error: {error}")]
@@ -113,6 +118,14 @@ mod verify {
         }
     }
 
+    impl VerifierError {
+        /// Is the error produced by a body length tag (`l=`) greater than the length of the body ?
+        #[must_use]
+        pub const fn is_body_length_exceeded(&self) -> bool {
+            matches!(self.0, InnerError::BodyLengthExceeded { .. })
+        }
+    }
+
     impl std::fmt::Display for VerifierError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             write!(f, "{}", self.0)
@@ -146,13 +159,20 @@ mod verify {
                 .unwrap_or_default(),
         );
 
-        #[allow(clippy::option_if_let_else)]
         let body_hash = signature
             .signing_algorithm
             .get_preferred_hash_algo()
             .hash(match signature.body_length {
+                // NOTE: the body has been truncated or altered in transit (RFC 6376 6.1.3)
+                Some(body_length) if body_length > body.len() => {
+                    return Err(InnerError::BodyLengthExceeded {
+                        body_length,
+                        length: body.len(),
+                    }
+                    .into());
+                }
                 // TODO: handle policy
-                Some(len) => &body[..std::cmp::min(body.len(), len)],
+                Some(body_length) => &body[..body_length],
                 None => &body,
             });
 
@@ -0,0 +1,57 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::dkim::{dns_record, private_key::PrivateKey, PublicKey};
+
+// Example key of RFC 8463 Appendix A.
+#[test]
+fn ed25519() {
+    let seed = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=",
+    )
+    .unwrap();
+    let key = ring_compat::ring::signature::Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+
+    assert_eq!(
+        dns_record(&PrivateKey::Ed25519(Box::new(key)), "brisbane").unwrap(),
+        r#"brisbane._domainkey IN TXT ( "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=" )"#
+    );
+}
+
+#[test]
+fn rsa_split_in_chunks() {
+    let key = <rsa::RsaPrivateKey as rsa::pkcs1::DecodeRsaPrivateKey>::from_pkcs1_pem(
+        include_str!("../../../../vsmtp-test/src/template/certs/private_key.rsa.key"),
+    )
+    .unwrap();
+    let expected = PublicKey::try_from(rsa::RsaPublicKey::from(&key)).unwrap();
+
+    let record = dns_record(&PrivateKey::Rsa(Box::new(key)), "2023").unwrap();
+
+    let chunks = record
+        .strip_prefix("2023._domainkey IN TXT ( \"")
+        .and_then(|record| record.strip_suffix("\" )"))
+        .unwrap()
+        .split("\" \"")
+        .collect::<Vec<_>>();
+
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 255));
+    assert!(chunks[0].starts_with("v=DKIM1; k=rsa; p="));
+
+    assert_eq!(chunks.concat().parse::<PublicKey>().unwrap(), expected);
+}
@@ -330,4 +330,43 @@ mod error {
         let err = verify(&signature, message.inner(), &public_key).unwrap_err();
         println!("{err}");
     }
+
+    #[test]
+    fn body_length_exceeded() {
+        let mut rng = rand::thread_rng();
+
+        let private_key = rsa::RsaPrivateKey::new(&mut rng, 1024).unwrap();
+        let public_key = rsa::RsaPublicKey::from(&private_key);
+
+        let mut message = local_msg();
+
+        let mut signature = sign(
+            message.inner(),
+            &PrivateKey::Rsa(Box::new(private_key)),
+            "localhost".to_string(),
+            "foobar".to_string(),
+            Canonicalization::new(
+                CanonicalizationAlgorithm::Relaxed,
+                CanonicalizationAlgorithm::Relaxed,
+            ),
+            vec![
+                "From".to_string(),
+                "To".to_string(),
+                "Subject".to_string(),
+                "Date".to_string(),
+                "From".to_string(),
+            ],
+            None,
+        )
+        .unwrap();
+
+        signature.body_length = Some(10_000);
+
+        message.prepend_header("DKIM-Signature", &signature.raw["DKIM-Signature: ".len()..]);
+
+        let public_key = PublicKey::try_from(public_key).unwrap();
+
+        let err = verify(&signature, message.inner(), &public_key).unwrap_err();
+        assert!(err.is_body_length_exceeded(), "{err}");
+    }
 }
@@ -17,5 +17,5 @@
 
 mod record;
 
-pub use record::ReceiverPolicy;
 pub use record::Record;
+pub use record::{AlignmentMode, ReceiverPolicy};
@@ -23,15 +23,38 @@ pub enum Version {
     Dmarc1,
 }
 
-#[derive(Debug, Default, Clone, strum::EnumString, strum::Display)]
-enum AlignmentMode {
+/// How two domains are compared to be considered aligned.
+/// See <https://www.rfc-editor.org/rfc/rfc7489#section-3.1>
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::Display)]
+pub enum AlignmentMode {
+    /// The organizational domains must be identical.
     #[default]
-    #[strum(serialize = "r")]
+    #[strum(to_string = "r", serialize = "relaxed")]
     Relaxed,
-    #[strum(serialize = "s")]
+    /// The domains must be identical.
+    #[strum(to_string = "s", serialize = "strict")]
     Strict,
 }
 
+impl AlignmentMode {
+    /// Are `rfc5322_from` and `domain` aligned according to this mode.
+    #[must_use]
+    pub fn is_aligned(self, rfc5322_from: &str, domain: &str) -> bool {
+        match self {
+            Self::Relaxed => match (get_root_domain(rfc5322_from), get_root_domain(domain)) {
+                (Ok(root_rfc5322_from), Ok(root_domain)) => {
+                    root_rfc5322_from.eq_ignore_ascii_case(&root_domain)
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    tracing::warn!("{e}");
+                    false
+                }
+            },
+            Self::Strict => rfc5322_from.eq_ignore_ascii_case(domain),
+        }
+    }
+}
+
 #[derive(Debug, Clone, strum::EnumString, strum::Display)]
 enum FailureReportOption {
     #[strum(serialize = "0")]
@@ -45,7 +68,7 @@ enum FailureReportOption {
 }
 
 ///
-#[derive(Debug, Clone, strum::EnumString, strum::Display)]
+#[derive(Debug, Clone, PartialEq, Eq, strum::EnumString, strum::Display)]
 #[strum(serialize_all = "lowercase")]
 pub enum ReceiverPolicy {
     ///
@@ -91,60 +114,33 @@ impl Record {
         self.receiver_policy.to_string()
     }
 
+    /// The policy requested for the subdomains (`sp=`), or the one of the domain (`p=`) if unset.
+    #[must_use]
+    pub const fn subdomain_policy(&self) -> &ReceiverPolicy {
+        match &self.receiver_policy_subdomain {
+            Some(policy) => policy,
+            None => &self.receiver_policy,
+        }
+    }
+
+    /// Is the message part of the percentage (`pct=`) of messages subjected to the policy.
     ///
+    /// `sample` identifies the message, the same value always produces the same answer.
     #[must_use]
-    pub fn dkim_is_aligned(&self, rfc5322_from: &str, dkim_domain: &str) -> bool {
-        match self.adkim {
-            AlignmentMode::Relaxed => {
-                let (root_rfc5322_from, root_domain_used) = (
-                    match get_root_domain(rfc5322_from) {
-                        Ok(root_rfc5322_from) => root_rfc5322_from,
-                        Err(e) => {
-                            tracing::warn!("{e}");
-                            return false;
-                        }
-                    },
-                    match get_root_domain(dkim_domain) {
-                        Ok(root_domain_used) => root_domain_used,
-                        Err(e) => {
-                            tracing::warn!("{e}");
-                            return false;
-                        }
-                    },
-                );
+    pub fn is_sampled(&self, sample: u128) -> bool {
+        sample % 100 < u128::from(self.percentage)
+    }
 
-                root_rfc5322_from == root_domain_used
-            }
-            AlignmentMode::Strict => rfc5322_from == dkim_domain,
-        }
+    ///
+    #[must_use]
+    pub fn dkim_is_aligned(&self, rfc5322_from: &str, dkim_domain: &str) -> bool {
+        self.adkim.is_aligned(rfc5322_from, dkim_domain)
     }
 
     ///
     #[must_use]
     pub fn spf_is_aligned(&self, rfc5322_from: &str, spf_domain: &str) -> bool {
-        match self.aspf {
-            AlignmentMode::Relaxed => {
-                let (root_rfc5322_from, root_spf_domain) = (
-                    match get_root_domain(rfc5322_from) {
-                        Ok(root_rfc5322_from) => root_rfc5322_from,
-                        Err(e) => {
-                            tracing::warn!("{e}");
-                            return false;
-                        }
-                    },
-                    match get_root_domain(spf_domain) {
-                        Ok(root_spf_domain) => root_spf_domain,
-                        Err(e) => {
-                            tracing::warn!("{e}");
-                            return false;
-                        }
-                    },
-                );
-
-                root_rfc5322_from == root_spf_domain
-            }
-            AlignmentMode::Strict => rfc5322_from == spf_domain,
-        }
+        self.aspf.is_aligned(rfc5322_from, spf_domain)
     }
 }
 
@@ -299,6 +295,28 @@ mod tests {
         assert_eq!(record.get_policy(), ReceiverPolicy::None.to_string());
     }
 
+    #[test]
+    fn subdomain_policy() {
+        let record = Record::from_str("v=DMARC1; p=reject; sp=quarantine").unwrap();
+        assert_eq!(record.subdomain_policy(), &ReceiverPolicy::Quarantine);
+
+        let record = Record::from_str("v=DMARC1; p=reject").unwrap();
+        assert_eq!(record.subdomain_policy(), &ReceiverPolicy::Reject);
+    }
+
+    #[test]
+    fn sampling() {
+        let record = Record::from_str("v=DMARC1; p=reject; pct=30").unwrap();
+        assert_eq!((0..100).filter(|i| record.is_sampled(*i)).count(), 30);
+        assert_eq!(record.is_sampled(1029), record.is_sampled(1029));
+
+        let record = Record::from_str("v=DMARC1; p=reject").unwrap();
+        assert!((0..100).all(|i| record.is_sampled(i)));
+
+        let record = Record::from_str("v=DMARC1; p=reject; pct=0").unwrap();
+        assert!(!(0..100).any(|i| record.is_sampled(i)));
+    }
+
     #[test]
     fn alignment_strict() {
         let record = Record {
@@ -345,6 +363,23 @@ mod tests {
         assert!(!record.spf_is_aligned("outlook.fr", "toto"));
     }
 
+    #[test]
+    fn alignment_mode() {
+        assert_eq!(
+            <AlignmentMode as std::str::FromStr>::from_str("relaxed").unwrap(),
+            AlignmentMode::Relaxed
+        );
+        assert_eq!(
+            <AlignmentMode as std::str::FromStr>::from_str("s").unwrap(),
+            AlignmentMode::Strict
+        );
+
+        assert!(AlignmentMode::Strict.is_aligned("Example.com", "example.com"));
+        assert!(!AlignmentMode::Strict.is_aligned("mail.example.com", "example.com"));
+        assert!(AlignmentMode::Relaxed.is_aligned("mail.example.com", "example.com"));
+        assert!(!AlignmentMode::Relaxed.is_aligned("example.org", "example.com"));
+    }
+
     mod error {
         use super::*;
 
@@ -0,0 +1,254 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+use super::Mechanism;
+
+/// The OAuth 2.0 bearer token sent with the `XOAUTH2` or `OAUTHBEARER` mechanisms.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BearerToken {
+    /// The user the client wants to authenticate as, empty if the token
+    /// identifies the user on its own (`OAUTHBEARER` only).
+    pub authid: String,
+    /// The token, to be verified by a [`TokenValidator`].
+    pub token: String,
+}
+
+impl std::fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BearerToken")
+            .field("authid", &self.authid)
+            .field("token", &"***")
+            .finish()
+    }
+}
+
+/// The reasons a bearer token has been refused.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TokenError {
+    /// The token is malformed, expired, revoked or invalid for another reason.
+    #[error("invalid token: {0}")]
+    Invalid(String),
+    /// The token is valid, but does not grant the access to the mail service.
+    #[error("insufficient scope, '{scope}' is required")]
+    InsufficientScope {
+        /// The scope required to authenticate.
+        scope: String,
+    },
+    /// The token could not be verified, for a reason unrelated to the client
+    /// (the introspection endpoint is unreachable, the keys could not be fetched, ...).
+    #[error("token verification unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl TokenError {
+    /// The error sent to the client as the last challenge of the exchange,
+    /// before the failure reply.
+    ///
+    /// See <https://datatracker.ietf.org/doc/html/rfc7628#section-3.2.2>
+    #[must_use]
+    pub fn to_challenge(&self) -> String {
+        match self {
+            Self::Invalid(_) | Self::Unavailable(_) => {
+                r#"{"status":"invalid_token","schemes":"bearer"}"#.to_owned()
+            }
+            Self::InsufficientScope { scope } => format!(
+                r#"{{"status":"insufficient_scope","schemes":"bearer","scope":"{}"}}"#,
+                scope.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+        }
+    }
+}
+
+/// Verify the bearer tokens sent with the `XOAUTH2` and `OAUTHBEARER` mechanisms,
+/// for instance by validating a JWT or by querying an introspection endpoint.
+///
+/// The credentials are then passed to the `authenticate` stage of the rules,
+/// which has the final word on the authentication.
+pub trait TokenValidator: Send + Sync {
+    /// Verify that `token` is valid and grants `token.authid` the access to the mail service.
+    ///
+    /// # Errors
+    ///
+    /// * the token is refused, see [`TokenError`]
+    fn validate(&self, token: &BearerToken) -> Result<(), TokenError>;
+}
+
+/// Decode the `saslname` of a GS2 header, where `,` and `=` are escaped.
+fn gs2_unescape(name: &str) -> Option<String> {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '=' => match (chars.next(), chars.next()) {
+                (Some('2'), Some('C')) => out.push(','),
+                (Some('3'), Some('D')) => out.push('='),
+                _ => return None,
+            },
+            otherwise => out.push(otherwise),
+        }
+    }
+    Some(out)
+}
+
+impl BearerToken {
+    /// Parse the initial response of the client.
+    ///
+    /// * `XOAUTH2`: `user=<authid>^Aauth=Bearer <token>^A^A`
+    /// * `OAUTHBEARER`: `<gs2-header>^A<key>=<value>^A...^A^A`,
+    ///   see <https://datatracker.ietf.org/doc/html/rfc7628#section-3.1>
+    ///
+    /// # Errors
+    ///
+    /// * the payload is malformed, or `mechanism` is not one of the above
+    pub fn parse(mechanism: Mechanism, payload: &[u8]) -> Result<Self, TokenError> {
+        let malformed = || TokenError::Invalid("malformed payload".to_owned());
+
+        let payload = std::str::from_utf8(payload).map_err(|_| malformed())?;
+        let payload = payload.strip_suffix("\x01\x01").ok_or_else(malformed)?;
+
+        let (authid, kvpairs) = match mechanism {
+            Mechanism::XOAuth2 => {
+                let (user, kvpairs) = payload.split_once('\x01').ok_or_else(malformed)?;
+                (
+                    user.strip_prefix("user=").ok_or_else(malformed)?.to_owned(),
+                    kvpairs,
+                )
+            }
+            Mechanism::OAuthBearer => {
+                let (gs2_header, kvpairs) = payload.split_once('\x01').ok_or_else(malformed)?;
+                // channel binding is not supported
+                let authzid = match gs2_header.split(',').collect::<Vec<_>>().as_slice() {
+                    ["n" | "y", "", ""] => String::new(),
+                    ["n" | "y", authzid, ""] => {
+                        gs2_unescape(authzid.strip_prefix("a=").ok_or_else(malformed)?)
+                            .ok_or_else(malformed)?
+                    }
+                    _ => return Err(malformed()),
+                };
+                (authzid, kvpairs)
+            }
+            Mechanism::Plain
+            | Mechanism::Login
+            | Mechanism::CramMd5
+            | Mechanism::Anonymous
+            | Mechanism::ScramSha1
+            | Mechanism::ScramSha256 => return Err(malformed()),
+        };
+
+        let token = kvpairs
+            .split('\x01')
+            .find_map(|kv| kv.strip_prefix("auth="))
+            .and_then(|auth| auth.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim().to_owned())
+            .filter(|token| !token.is_empty())
+            .ok_or_else(malformed)?;
+
+        Ok(Self { authid, token })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xoauth2() {
+        assert_eq!(
+            BearerToken::parse(
+                Mechanism::XOAuth2,
+                b"user=someuser@example.com\x01auth=Bearer ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg\x01\x01"
+            )
+            .unwrap(),
+            BearerToken {
+                authid: "someuser@example.com".to_owned(),
+                token: "ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn oauthbearer() {
+        assert_eq!(
+            BearerToken::parse(
+                Mechanism::OAuthBearer,
+                b"n,a=user@example.com,\x01host=server.example.com\x01port=143\x01auth=Bearer vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==\x01\x01"
+            )
+            .unwrap(),
+            BearerToken {
+                authid: "user@example.com".to_owned(),
+                token: "vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==".to_owned()
+            }
+        );
+
+        assert_eq!(
+            BearerToken::parse(
+                Mechanism::OAuthBearer,
+                b"n,a=foo=2Cbar=3D,\x01auth=Bearer token\x01\x01"
+            )
+            .unwrap()
+            .authid,
+            "foo,bar="
+        );
+
+        assert_eq!(
+            BearerToken::parse(Mechanism::OAuthBearer, b"n,,\x01auth=Bearer token\x01\x01")
+                .unwrap()
+                .authid,
+            ""
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for (mechanism, payload) in [
+            (Mechanism::XOAuth2, &b"user=foo\x01auth=Bearer token"[..]),
+            (Mechanism::XOAuth2, b"foo\x01auth=Bearer token\x01\x01"),
+            (Mechanism::XOAuth2, b"user=foo\x01auth=Basic token\x01\x01"),
+            (Mechanism::XOAuth2, b"user=foo\x01auth=Bearer \x01\x01"),
+            (
+                Mechanism::OAuthBearer,
+                b"p=tls-unique,,\x01auth=Bearer token\x01\x01",
+            ),
+            (
+                Mechanism::OAuthBearer,
+                b"n,a=foo=2,\x01auth=Bearer token\x01\x01",
+            ),
+            (Mechanism::OAuthBearer, b"n,,\x01host=foo\x01\x01"),
+            (Mechanism::Plain, b"user=foo\x01auth=Bearer token\x01\x01"),
+        ] {
+            assert_eq!(
+                BearerToken::parse(mechanism, payload),
+                Err(TokenError::Invalid("malformed payload".to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn challenge() {
+        assert_eq!(
+            TokenError::Invalid("expired".to_owned()).to_challenge(),
+            r#"{"status":"invalid_token","schemes":"bearer"}"#
+        );
+        assert_eq!(
+            TokenError::InsufficientScope {
+                scope: "https://mail.google.com/".to_owned()
+            }
+            .to_challenge(),
+            r#"{"status":"insufficient_scope","schemes":"bearer","scope":"https://mail.google.com/"}"#
+        );
+    }
+}
@@ -33,6 +33,20 @@ pub enum Credentials {
         /// [ email / 1*255TCHAR ]
         token: String,
     },
+    /// the client has proved the knowledge of the password with a `SCRAM-*` mechanism,
+    /// checked against the salted credentials of the [`CredentialsProvider`](super::CredentialsProvider)
+    Scram {
+        ///
+        authid: String,
+    },
+    /// the OAuth 2.0 bearer token sent with the `XOAUTH2` or `OAUTHBEARER` mechanisms,
+    /// already accepted by the [`TokenValidator`](super::TokenValidator) if any
+    Bearer {
+        /// empty if the token identifies the user on its own
+        authid: String,
+        ///
+        token: String,
+    },
 }
 
 #[cfg(not(debug_assertions))]
@@ -48,6 +62,15 @@ impl std::fmt::Debug for Credentials {
                 .debug_struct("Credentials::AnonymousToken")
                 .field("token", &"***")
                 .finish(),
+            Credentials::Scram { authid } => f
+                .debug_struct("Credentials::Scram")
+                .field("authid", authid)
+                .finish(),
+            Credentials::Bearer { authid, .. } => f
+                .debug_struct("Credentials::Bearer")
+                .field("authid", authid)
+                .field("token", &"***")
+                .finish(),
         }
     }
 }
@@ -73,6 +96,17 @@ impl serde::Serialize for Credentials {
                 s.serialize_field("token", "***")?;
                 s.end()
             }
+            Credentials::Scram { .. } => {
+                let mut s = serializer.serialize_struct_variant("Credentials", 2, "Scram", 1)?;
+                s.serialize_field("authid", "***")?;
+                s.end()
+            }
+            Credentials::Bearer { .. } => {
+                let mut s = serializer.serialize_struct_variant("Credentials", 3, "Bearer", 2)?;
+                s.serialize_field("authid", "***")?;
+                s.serialize_field("token", "***")?;
+                s.end()
+            }
         }
     }
 }
@@ -118,6 +152,16 @@ impl TryFrom<(&rsasl::callback::SessionData, &rsasl::callback::Context<'_>)> for
                     .ok_or(Error::MissingField)?
                     .to_string(),
             }),
+            mech if mech == Mechanism::ScramSha1.as_ref()
+                || mech == Mechanism::ScramSha256.as_ref() =>
+            {
+                Ok(Self::Scram {
+                    authid: context
+                        .get_ref::<rsasl::property::AuthId>()
+                        .ok_or(Error::MissingField)?
+                        .to_string(),
+                })
+            }
             // mech if mech == Mechanism::CramMd5.as_ref() => todo!(),
             _ => Err(Error::Unimplemented),
         }
@@ -43,19 +43,32 @@ pub enum Mechanism {
     /// Common
     /// See <https://datatracker.ietf.org/doc/html/rfc4505>
     Anonymous,
+    /// Salted challenge/response, superseded by `SCRAM-SHA-256`
+    /// See <https://datatracker.ietf.org/doc/html/rfc5802>
+    #[strum(serialize = "SCRAM-SHA-1")]
+    ScramSha1,
+    /// Salted challenge/response
+    /// See <https://datatracker.ietf.org/doc/html/rfc7677>
+    #[strum(serialize = "SCRAM-SHA-256")]
+    ScramSha256,
+    /// OAuth 2.0 bearer token, as used by Google and Microsoft
+    /// See <https://developers.google.com/gmail/imap/xoauth2-protocol>
+    #[strum(serialize = "XOAUTH2")]
+    XOAuth2,
+    /// OAuth 2.0 bearer token
+    /// See <https://datatracker.ietf.org/doc/html/rfc7628>
+    #[strum(serialize = "OAUTHBEARER")]
+    OAuthBearer,
     /*
     - EXTERNAL
     - SECURID
     - DIGEST-MD5
-    - SCRAM-SHA-1
     - SCRAM-SHA-1-PLUS
-    - SCRAM-SHA-256
     - SCRAM-SHA-256-PLUS
     - SAML20
     - OPENID20
     - GSSAPI
     - GS2-KRB5
-    - XOAUTH-2
     */
 }
 
@@ -64,7 +77,12 @@ impl Mechanism {
     #[must_use]
     pub const fn client_first(self) -> bool {
         match self {
-            Self::Plain | Self::Anonymous => true,
+            Self::Plain
+            | Self::Anonymous
+            | Self::ScramSha1
+            | Self::ScramSha256
+            | Self::XOAuth2
+            | Self::OAuthBearer => true,
             Self::Login | Self::CramMd5 => false,
         }
     }
@@ -73,7 +91,14 @@ impl Mechanism {
     #[must_use]
     pub const fn must_be_under_tls(self) -> bool {
         match self {
-            Self::Plain | Self::Login | Self::CramMd5 | Self::Anonymous => true,
+            Self::Plain
+            | Self::Login
+            | Self::CramMd5
+            | Self::Anonymous
+            | Self::ScramSha1
+            | Self::ScramSha256
+            | Self::XOAuth2
+            | Self::OAuthBearer => true,
         }
     }
 }
@@ -89,6 +114,22 @@ mod tests {
         assert_eq!(Mechanism::Login.to_string(), "LOGIN");
         assert_eq!(Mechanism::CramMd5.to_string(), "CRAM-MD5");
         assert_eq!(Mechanism::Anonymous.to_string(), "ANONYMOUS");
+        assert_eq!(Mechanism::ScramSha1.to_string(), "SCRAM-SHA-1");
+        assert_eq!(Mechanism::ScramSha256.to_string(), "SCRAM-SHA-256");
+        assert_eq!(Mechanism::XOAuth2.to_string(), "XOAUTH2");
+        assert_eq!(Mechanism::OAuthBearer.to_string(), "OAUTHBEARER");
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!(
+            <Mechanism as std::str::FromStr>::from_str("SCRAM-SHA-256").unwrap(),
+            Mechanism::ScramSha256
+        );
+        assert_eq!(
+            <Mechanism as std::str::FromStr>::from_str("SCRAM-SHA-1").unwrap(),
+            Mechanism::ScramSha1
+        );
     }
 
     #[test]
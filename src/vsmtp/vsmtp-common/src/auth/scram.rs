@@ -0,0 +1,304 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+use super::Mechanism;
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::Mac;
+
+/// The salted credentials of a user, stored by the server to verify the `SCRAM-*` mechanisms.
+/// The password itself is never stored.
+///
+/// See <https://datatracker.ietf.org/doc/html/rfc5802#section-3>
+#[derive(Clone, PartialEq, Eq)]
+pub struct ScramCredentials {
+    /// The mechanism these credentials have been derived for.
+    pub mechanism: Mechanism,
+    /// Iteration count of the PBKDF2 function.
+    pub iterations: u32,
+    /// Salt of the PBKDF2 function.
+    pub salt: Vec<u8>,
+    /// `H(HMAC(SaltedPassword, "Client Key"))`
+    pub stored_key: Vec<u8>,
+    /// `HMAC(SaltedPassword, "Server Key")`
+    pub server_key: Vec<u8>,
+}
+
+impl std::fmt::Debug for ScramCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScramCredentials")
+            .field("mechanism", &self.mechanism)
+            .field("iterations", &self.iterations)
+            .field("salt", &"***")
+            .field("stored_key", &"***")
+            .field("server_key", &"***")
+            .finish()
+    }
+}
+
+macro_rules! derive_keys {
+    ($hash:ty, $password:expr, $salt:expr, $iterations:expr) => {{
+        let mut salted_password = vec![0; <$hash as sha2::Digest>::output_size()];
+        pbkdf2::pbkdf2::<hmac::Hmac<$hash>>($password, $salt, $iterations, &mut salted_password);
+
+        let hmac_of = |key: &[u8], data: &[u8]| {
+            let mut mac = <hmac::Hmac<$hash> as Mac>::new_from_slice(key)
+                .expect("hmac accepts keys of any size");
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        };
+
+        let client_key = hmac_of(&salted_password, b"Client Key");
+        (
+            <$hash as sha2::Digest>::digest(client_key).to_vec(),
+            hmac_of(&salted_password, b"Server Key"),
+        )
+    }};
+}
+
+impl ScramCredentials {
+    /// Derive the credentials to store from the password of the user.
+    ///
+    /// The password is used as given, and is expected to be already normalized
+    /// with `SASLprep` (which is a no-op for ASCII passwords).
+    ///
+    /// Return `None` if the mechanism is not a `SCRAM-*` one.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn derive(
+        mechanism: Mechanism,
+        password: &[u8],
+        salt: Vec<u8>,
+        iterations: u32,
+    ) -> Option<Self> {
+        let (stored_key, server_key) = match mechanism {
+            Mechanism::ScramSha1 => derive_keys!(sha1::Sha1, password, &salt, iterations),
+            Mechanism::ScramSha256 => derive_keys!(sha2::Sha256, password, &salt, iterations),
+            Mechanism::Plain
+            | Mechanism::Login
+            | Mechanism::CramMd5
+            | Mechanism::Anonymous
+            | Mechanism::XOAuth2
+            | Mechanism::OAuthBearer => return None,
+        };
+
+        Some(Self {
+            mechanism,
+            iterations,
+            salt,
+            stored_key,
+            server_key,
+        })
+    }
+}
+
+/// Format: `{SCRAM-SHA-256}<iterations>,<salt>,<stored_key>,<server_key>`,
+/// with the binary values encoded in base64.
+impl std::fmt::Display for ScramCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{{}}}{},{},{},{}",
+            self.mechanism,
+            self.iterations,
+            STANDARD.encode(&self.salt),
+            STANDARD.encode(&self.stored_key),
+            STANDARD.encode(&self.server_key)
+        )
+    }
+}
+
+impl std::str::FromStr for ScramCredentials {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mechanism, keys) = s
+            .strip_prefix('{')
+            .and_then(|s| s.split_once('}'))
+            .with_context(|| format!("missing the mechanism in '{s}'"))?;
+
+        let mechanism = <Mechanism as std::str::FromStr>::from_str(mechanism)?;
+        if !matches!(mechanism, Mechanism::ScramSha1 | Mechanism::ScramSha256) {
+            anyhow::bail!("'{mechanism}' is not a SCRAM mechanism");
+        }
+
+        match keys.split(',').collect::<Vec<_>>().as_slice() {
+            [iterations, salt, stored_key, server_key] => Ok(Self {
+                mechanism,
+                iterations: iterations.parse()?,
+                salt: STANDARD.decode(salt)?,
+                stored_key: STANDARD.decode(stored_key)?,
+                server_key: STANDARD.decode(server_key)?,
+            }),
+            _ => anyhow::bail!("expected '<iterations>,<salt>,<stored_key>,<server_key>'"),
+        }
+    }
+}
+
+/// A source of the salted credentials used by the server to verify the `SCRAM-*` mechanisms.
+pub trait CredentialsProvider: Send + Sync {
+    /// Get the credentials of `authid` derived for `mechanism`,
+    /// or `None` if the user is unknown.
+    ///
+    /// # Errors
+    ///
+    /// * the backend failed to produce the credentials
+    fn scram_credentials(
+        &self,
+        mechanism: Mechanism,
+        authid: &str,
+    ) -> anyhow::Result<Option<ScramCredentials>>;
+}
+
+/// Credentials stored in a file, one entry per line: `<authid>:<credentials>`,
+/// where `<credentials>` is the [`ScramCredentials`] display format.
+///
+/// A user can have one entry per mechanism. Empty lines and lines starting with `#` are ignored.
+/// The file is read on each lookup, so that it can be updated without restarting the server.
+#[derive(Debug, Clone)]
+pub struct CredentialsFile {
+    path: std::path::PathBuf,
+}
+
+impl CredentialsFile {
+    /// Use the credentials stored at `path`.
+    #[must_use]
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn lookup(
+        content: &str,
+        mechanism: Mechanism,
+        authid: &str,
+    ) -> anyhow::Result<Option<ScramCredentials>> {
+        for line in content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            let (user, credentials) = line
+                .rsplit_once(':')
+                .with_context(|| format!("missing the authid in '{line}'"))?;
+
+            if user != authid {
+                continue;
+            }
+
+            let credentials = <ScramCredentials as std::str::FromStr>::from_str(credentials)?;
+            if credentials.mechanism == mechanism {
+                return Ok(Some(credentials));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl CredentialsProvider for CredentialsFile {
+    fn scram_credentials(
+        &self,
+        mechanism: Mechanism,
+        authid: &str,
+    ) -> anyhow::Result<Option<ScramCredentials>> {
+        let content = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read '{}'", self.path.display()))?;
+
+        Self::lookup(&content, mechanism, authid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the stored keys for the password "pencil" of the RFC 5802 and RFC 7677 examples
+    const SHA1: &str =
+        "{SCRAM-SHA-1}4096,QSXCR+Q6sek8bf92,6dlGYMOdZcOPutkcNY8U2g7vK9Y=,D+CSWLOshSulAsxiupA+qs2/fTE=";
+    const SHA256: &str = "{SCRAM-SHA-256}4096,W22ZaJ0SNY7soEsUEjb6gQ==,WG5d8oPm3OtcPnkdi4Uo7BkeZkBFzpcXkuLmtbsT4qY=,wfPLwcE6nTWhTAmQ7tl2KeoiWGPlZqQxSrmfPwDl2dU=";
+
+    #[test]
+    fn derive() {
+        let sha1 = ScramCredentials::derive(
+            Mechanism::ScramSha1,
+            b"pencil",
+            STANDARD.decode("QSXCR+Q6sek8bf92").unwrap(),
+            4096,
+        )
+        .unwrap();
+        assert_eq!(sha1.to_string(), SHA1);
+
+        let sha256 = ScramCredentials::derive(
+            Mechanism::ScramSha256,
+            b"pencil",
+            STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap(),
+            4096,
+        )
+        .unwrap();
+        assert_eq!(sha256.to_string(), SHA256);
+
+        assert!(ScramCredentials::derive(Mechanism::Plain, b"pencil", vec![], 4096).is_none());
+    }
+
+    #[test]
+    fn parse() {
+        let sha256 = <ScramCredentials as std::str::FromStr>::from_str(SHA256).unwrap();
+        assert_eq!(sha256.mechanism, Mechanism::ScramSha256);
+        assert_eq!(sha256.iterations, 4096);
+        assert_eq!(sha256.to_string(), SHA256);
+
+        for invalid in [
+            "4096,W22ZaJ0SNY7soEsUEjb6gQ==,AA==,AA==",
+            "{PLAIN}4096,W22ZaJ0SNY7soEsUEjb6gQ==,AA==,AA==",
+            "{SCRAM-SHA-256}4096,W22ZaJ0SNY7soEsUEjb6gQ==,AA==",
+            "{SCRAM-SHA-256}foo,W22ZaJ0SNY7soEsUEjb6gQ==,AA==,AA==",
+            "{SCRAM-SHA-256}4096,not base64,AA==,AA==",
+        ] {
+            assert!(<ScramCredentials as std::str::FromStr>::from_str(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let content = format!("# comment\n\nuser:{SHA1}\nuser:{SHA256}\nfoo@bar:{SHA256}\n");
+
+        assert_eq!(
+            CredentialsFile::lookup(&content, Mechanism::ScramSha1, "user")
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            SHA1
+        );
+        assert_eq!(
+            CredentialsFile::lookup(&content, Mechanism::ScramSha256, "user")
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            SHA256
+        );
+        assert!(
+            CredentialsFile::lookup(&content, Mechanism::ScramSha1, "foo@bar")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            CredentialsFile::lookup(&content, Mechanism::ScramSha256, "unknown")
+                .unwrap()
+                .is_none()
+        );
+        assert!(CredentialsFile::lookup("invalid", Mechanism::ScramSha256, "user").is_err());
+    }
+}
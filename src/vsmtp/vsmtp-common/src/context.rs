@@ -15,8 +15,8 @@
  *
 */
 use crate::{
-    auth::Credentials, rcpt::Rcpt, status::Status, Address, CipherSuite, ClientName,
-    ProtocolVersion,
+    auth::Credentials, rcpt::Rcpt, status::Status, transfer::DsnReturn, Address, CipherSuite,
+    ClientName, ProtocolVersion,
 };
 use vsmtp_auth::{dkim, spf};
 
@@ -215,6 +215,11 @@ impl Context {
                         reverse_path,
                         mail_timestamp: now,
                         message_uuid: uuid::Uuid::new_v4(),
+                        dsn_return: None,
+                        envelop_id: None,
+                        use_smtputf8: false,
+                        auth_mailbox: None,
+                        require_tls: false,
                     },
                 });
                 Ok(())
@@ -396,6 +401,32 @@ impl Context {
         }
     }
 
+    /// Restore the [`TlsProperties`] and [`AuthProperties`] of the connection,
+    /// when a new transaction is started on a connection which has already been
+    /// secured or authenticated.
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::Connect`] or [`Stage::Helo`]
+    pub fn restore_security(
+        &mut self,
+        tls: Option<TlsProperties>,
+        auth: Option<AuthProperties>,
+    ) -> Result<(), Error> {
+        match self {
+            Context::Empty => unreachable!(),
+            Context::Connect(ContextConnect { connect })
+            | Context::Helo(ContextHelo { connect, .. }) => {
+                connect.tls = tls;
+                connect.auth = auth;
+                Ok(())
+            }
+            Context::MailFrom(ContextMailFrom { .. })
+            | Context::RcptTo(ContextRcptTo { .. })
+            | Context::Finished(ContextFinished { .. }) => Err(Error::BadState),
+        }
+    }
+
     /// Get the name of the client.
     ///
     /// # Errors
@@ -488,6 +519,15 @@ impl Context {
         }
     }
 
+    /// Is the message a bounce, sent with a null reverse path (`MAIL FROM:<>`)?
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub fn is_bounce(&self) -> Result<bool, Error> {
+        self.reverse_path().map(Option::is_none)
+    }
+
     /// Set the reverse path.
     ///
     /// # Errors
@@ -505,6 +545,93 @@ impl Context {
         }
     }
 
+    /// Set the parameters of the delivery status notifications given with `MAIL FROM`.
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub fn set_dsn_parameters(
+        &mut self,
+        dsn_return: Option<DsnReturn>,
+        envelop_id: Option<String>,
+    ) -> Result<(), Error> {
+        match self {
+            Context::Empty | Context::Connect { .. } | Context::Helo { .. } => Err(Error::BadState),
+            Context::MailFrom(ContextMailFrom { mail_from, .. })
+            | Context::RcptTo(ContextRcptTo { mail_from, .. })
+            | Context::Finished(ContextFinished { mail_from, .. }) => {
+                mail_from.dsn_return = dsn_return;
+                mail_from.envelop_id = envelop_id;
+                Ok(())
+            }
+        }
+    }
+
+    /// Set the submitter of the message given with the `AUTH` parameter of `MAIL FROM`.
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub fn set_auth_mailbox(&mut self, auth_mailbox: Option<String>) -> Result<(), Error> {
+        match self {
+            Context::Empty | Context::Connect { .. } | Context::Helo { .. } => Err(Error::BadState),
+            Context::MailFrom(ContextMailFrom { mail_from, .. })
+            | Context::RcptTo(ContextRcptTo { mail_from, .. })
+            | Context::Finished(ContextFinished { mail_from, .. }) => {
+                mail_from.auth_mailbox = auth_mailbox;
+                Ok(())
+            }
+        }
+    }
+
+    /// Set if the client declared the `REQUIRETLS` parameter with `MAIL FROM`.
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub fn set_require_tls(&mut self, require_tls: bool) -> Result<(), Error> {
+        match self {
+            Context::Empty | Context::Connect { .. } | Context::Helo { .. } => Err(Error::BadState),
+            Context::MailFrom(ContextMailFrom { mail_from, .. })
+            | Context::RcptTo(ContextRcptTo { mail_from, .. })
+            | Context::Finished(ContextFinished { mail_from, .. }) => {
+                mail_from.require_tls = require_tls;
+                Ok(())
+            }
+        }
+    }
+
+    /// Set if the client declared the `SMTPUTF8` parameter with `MAIL FROM`.
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub fn set_smtputf8(&mut self, use_smtputf8: bool) -> Result<(), Error> {
+        match self {
+            Context::Empty | Context::Connect { .. } | Context::Helo { .. } => Err(Error::BadState),
+            Context::MailFrom(ContextMailFrom { mail_from, .. })
+            | Context::RcptTo(ContextRcptTo { mail_from, .. })
+            | Context::Finished(ContextFinished { mail_from, .. }) => {
+                mail_from.use_smtputf8 = use_smtputf8;
+                Ok(())
+            }
+        }
+    }
+
+    /// Did the client declare the `SMTPUTF8` parameter with `MAIL FROM`.
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub const fn use_smtputf8(&self) -> Result<bool, Error> {
+        match self {
+            Context::Empty | Context::Connect { .. } | Context::Helo { .. } => Err(Error::BadState),
+            Context::MailFrom(ContextMailFrom { mail_from, .. })
+            | Context::RcptTo(ContextRcptTo { mail_from, .. })
+            | Context::Finished(ContextFinished { mail_from, .. }) => Ok(mail_from.use_smtputf8),
+        }
+    }
+
     /// Get the [`time::OffsetDateTime`] when the `MAIL FROM` has been received.
     ///
     /// # Errors
@@ -557,6 +684,15 @@ impl Context {
     ///
     /// * state if not [`Stage::MailFrom`] or after
     pub fn add_forward_path(&mut self, forward_path: Address) -> Result<(), Error> {
+        self.add_rcpt(Rcpt::new(forward_path))
+    }
+
+    /// Add a recipient at the end of the list of forward paths, see [`Context::add_forward_path`].
+    ///
+    /// # Errors
+    ///
+    /// * state if not [`Stage::MailFrom`] or after
+    pub fn add_rcpt(&mut self, rcpt: Rcpt) -> Result<(), Error> {
         match self {
             Context::Empty | Context::Connect(_) | Context::Helo(_) => Err(Error::BadState),
             Context::MailFrom(ContextMailFrom {
@@ -569,7 +705,7 @@ impl Context {
                     helo: helo.clone(),
                     mail_from: mail_from.clone(),
                     rcpt_to: RcptToProperties {
-                        forward_paths: vec![Rcpt::new(forward_path)],
+                        forward_paths: vec![rcpt],
                         transaction_type: TransactionType::Internal, // FIXME: should not have default value
                     },
                 });
@@ -577,7 +713,7 @@ impl Context {
             }
             Context::RcptTo(ContextRcptTo { rcpt_to, .. })
             | Context::Finished(ContextFinished { rcpt_to, .. }) => {
-                rcpt_to.forward_paths.push(Rcpt::new(forward_path));
+                rcpt_to.forward_paths.push(rcpt);
                 Ok(())
             }
         }
@@ -867,6 +1003,23 @@ pub struct MailFromProperties {
     pub mail_timestamp: time::OffsetDateTime,
     ///
     pub message_uuid: uuid::Uuid,
+    /// Content of the message to return in the delivery status notifications (`RET`).
+    #[serde(default)]
+    pub dsn_return: Option<DsnReturn>,
+    /// Identifier of the envelope given by the client, reported in the delivery status notifications (`ENVID`).
+    #[serde(default)]
+    pub envelop_id: Option<String>,
+    /// The message requires the support of UTF-8 in the addresses and headers (`SMTPUTF8`, RFC 6531).
+    #[serde(default)]
+    pub use_smtputf8: bool,
+    /// The submitter of the message (`AUTH`, RFC 4954), `<>` if unknown, passed on to the
+    /// servers the message is relayed to.
+    #[serde(default)]
+    pub auth_mailbox: Option<String>,
+    /// The message must only be relayed over authenticated and encrypted connections
+    /// (`REQUIRETLS`, RFC 8689), bounced otherwise.
+    #[serde(default)]
+    pub require_tls: bool,
 }
 
 ///
@@ -48,20 +48,28 @@ mod types {
     pub mod address;
     pub mod client_name;
     pub mod code_id;
+    pub mod duplicate_params;
+    pub mod null_sender;
     pub mod reply;
     pub mod reply_code;
     pub mod tls_cipher_suite;
     pub mod tls_protocol_version;
+    pub mod unknown_params;
+    pub mod verify;
 }
 
 pub use types::{
     address::{Address, Domain},
     client_name::ClientName,
     code_id::CodeID,
+    duplicate_params::DuplicateParamsPolicy,
+    null_sender::NullSenderPolicy,
     reply::Reply,
     reply_code::*,
     tls_cipher_suite::CipherSuite,
     tls_protocol_version::ProtocolVersion,
+    unknown_params::UnknownParamsPolicy,
+    verify::VerifyPolicy,
 };
 
 ///
@@ -91,11 +99,15 @@ pub mod utils;
 
 /// Data related to ESMTP Authentication
 pub mod auth {
+    mod bearer;
     mod credentials;
     mod mechanism;
+    mod scram;
 
+    pub use bearer::{BearerToken, TokenError, TokenValidator};
     pub use credentials::{Credentials, Error};
     pub use mechanism::Mechanism;
+    pub use scram::{CredentialsFile, CredentialsProvider, ScramCredentials};
 }
 
 #[cfg(test)]
@@ -177,3 +177,78 @@ pub fn getpwuid(uid: libc::uid_t) -> anyhow::Result<std::path::PathBuf> {
         .to_str()?
         .into())
 }
+
+/// Get the space available to unprivileged users and the total size, in bytes,
+/// of the filesystem containing `@path`.
+///
+/// # Errors
+///
+/// * `@path` cannot be convert to `CString`
+/// * see statvfs(3) ERRORS
+#[allow(clippy::useless_conversion)] // the types of the fields are platform dependent
+pub fn statvfs(path: &std::path::Path) -> anyhow::Result<(u64, u64)> {
+    let path = std::ffi::CString::new(path.to_string_lossy().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    #[allow(unsafe_code)]
+    // SAFETY: ffi call, `stat` is valid for writes
+    match unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } {
+        0 => {
+            #[allow(unsafe_code)]
+            // SAFETY: `stat` has been initialized by the successful call above
+            let stat = unsafe { stat.assume_init() };
+            let fragment_size = u64::from(stat.f_frsize);
+
+            Ok((
+                u64::from(stat.f_bavail).saturating_mul(fragment_size),
+                u64::from(stat.f_blocks).saturating_mul(fragment_size),
+            ))
+        }
+        _ => Err(anyhow::anyhow!(
+            "statvfs: '{}'",
+            std::io::Error::last_os_error()
+        )),
+    }
+}
+
+/// Is the error caused by a filesystem without space left (`ENOSPC`),
+/// or a disk quota exceeded (`EDQUOT`).
+#[must_use]
+pub fn is_storage_full(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .and_then(std::io::Error::raw_os_error)
+            .map_or(false, |errno| {
+                errno == libc::ENOSPC || errno == libc::EDQUOT
+            })
+    })
+}
+
+/// Apply an exclusive advisory lock on the open file `@file` without blocking,
+/// returning `false` if the file is already locked. The lock is released when
+/// the file is closed.
+///
+/// # Errors
+///
+/// see flock(2) ERRORS
+pub fn try_flock_exclusive(file: &std::fs::File) -> anyhow::Result<bool> {
+    #[allow(unsafe_code)]
+    // SAFETY: ffi call, the file descriptor is valid while `file` is borrowed
+    match unsafe {
+        libc::flock(
+            std::os::unix::io::AsRawFd::as_raw_fd(file),
+            libc::LOCK_EX | libc::LOCK_NB,
+        )
+    } {
+        0 => Ok(true),
+        _ => {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                Ok(false)
+            } else {
+                Err(anyhow::anyhow!("flock: '{error}'"))
+            }
+        }
+    }
+}
@@ -15,7 +15,7 @@
  *
 */
 use crate::{
-    transfer::{EmailTransferStatus, Transfer},
+    transfer::{EmailTransferStatus, NotifyOn, OriginalRecipient, Transfer},
     Address,
 };
 
@@ -28,6 +28,12 @@ pub struct Rcpt {
     pub transfer_method: Transfer,
     /// Delivery status of the email bound to this recipient.
     pub email_status: EmailTransferStatus,
+    /// Delivery status notifications requested for this recipient.
+    #[serde(default)]
+    pub notify_on: NotifyOn,
+    /// Original recipient given by the client, reported in the delivery status notifications.
+    #[serde(default)]
+    pub original_forward_path: Option<OriginalRecipient>,
 }
 
 impl Rcpt {
@@ -39,6 +45,8 @@ impl Rcpt {
             address,
             transfer_method: Transfer::default(),
             email_status: EmailTransferStatus::default(),
+            notify_on: NotifyOn::default(),
+            original_forward_path: None,
         }
     }
 }
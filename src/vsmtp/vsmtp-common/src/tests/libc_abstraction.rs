@@ -14,7 +14,10 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::libc_abstraction::{chown, if_indextoname, if_nametoindex, setgid, setuid};
+use crate::libc_abstraction::{
+    chown, if_indextoname, if_nametoindex, is_storage_full, setgid, setuid, statvfs,
+    try_flock_exclusive,
+};
 
 #[test]
 fn test_setuid_current() {
@@ -73,3 +76,42 @@ fn test_chown_file() {
 
     std::fs::remove_file(file_to_create).unwrap();
 }
+
+#[test]
+fn test_is_storage_full() {
+    let enospc = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::ENOSPC));
+    assert!(is_storage_full(&enospc));
+    assert!(is_storage_full(
+        &enospc.context("failed to write the message")
+    ));
+
+    assert!(!is_storage_full(&anyhow::Error::from(
+        std::io::Error::from_raw_os_error(libc::EACCES)
+    )));
+    assert!(!is_storage_full(&anyhow::anyhow!(
+        "no space left on device"
+    )));
+}
+
+#[test]
+fn test_statvfs() {
+    let (available, total) = statvfs(&std::env::temp_dir()).unwrap();
+    assert!(available <= total);
+
+    assert!(statvfs(std::path::Path::new("./no_such_file_exist")).is_err());
+}
+
+#[test]
+fn test_try_flock_exclusive() {
+    let path = std::env::temp_dir().join(format!("flock-{}", uuid::Uuid::new_v4()));
+    let first = std::fs::File::create(&path).unwrap();
+    let second = std::fs::File::open(&path).unwrap();
+
+    assert!(try_flock_exclusive(&first).unwrap());
+    assert!(!try_flock_exclusive(&second).unwrap());
+
+    drop(first);
+    assert!(try_flock_exclusive(&second).unwrap());
+
+    std::fs::remove_file(path).unwrap();
+}
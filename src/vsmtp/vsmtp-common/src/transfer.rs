@@ -33,6 +33,19 @@ pub enum TransferErrorsVariant {
         /// Error
         error: String,
     },
+    /// For local delivery (Maildir), the mailbox exceeds its quota.
+    #[strum(to_string = "452 4.2.2 Mailbox full")]
+    MailboxFull {
+        /// Name of the mailbox
+        name: String,
+    },
+    /// For local delivery (Maildir / Mbox), the message has already been delivered
+    /// to the recipient, according to its `Delivered-To` headers.
+    #[strum(to_string = "554 5.4.6 Routing loop detected")]
+    MailLoop {
+        /// The recipient found in the headers
+        recipient: String,
+    },
 
     /// The recipient is still in status [`EmailTransferStatus::Waiting`] after the split_and_sort_and_send()
     StillWaiting,
@@ -58,6 +71,9 @@ pub enum TransferErrorsVariant {
     Smtp {
         ///
         error: String,
+        /// Commands and replies of the smtp session, if its capture was enabled.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transcript: Vec<String>,
     },
     ///
     DeliveryError {
@@ -69,6 +85,48 @@ pub enum TransferErrorsVariant {
     ///
     TlsNoCertificate {},
 
+    /// The server could not be authenticated with DANE (RFC 7672).
+    TlsaMismatch {
+        ///
+        error: String,
+    },
+
+    /// The delivery is not allowed by the MTA-STS policy of the domain (RFC 8461).
+    MtaSts {
+        ///
+        error: String,
+    },
+
+    /// The mail exchanger is an alias (CNAME), see `server.queues.delivery.mx_cname`.
+    MxIsAlias {
+        /// Name of the mail exchanger
+        mx: String,
+        /// Canonical name of the mail exchanger
+        target: String,
+    },
+
+    /// The message requires TLS (`REQUIRETLS`, RFC 8689), but could not be relayed over
+    /// an authenticated and encrypted connection.
+    RequireTls {
+        ///
+        error: String,
+    },
+
+    /// The domain has neither MX nor A/AAAA records, the mail cannot be routed (RFC 5321 5.1).
+    NoRoute {
+        ///
+        domain: String,
+    },
+
+    /// The domain does not exist (`NXDOMAIN`), cached for `server.queues.delivery.nxdomain_cache`.
+    NoSuchDomain {
+        ///
+        domain: String,
+    },
+
+    /// The time budget of the delivery attempt is over, see `server.queues.delivery.time_budget`.
+    TimeBudgetExhausted,
+
     ///
     MaxDeferredAttemptReached,
 
@@ -91,15 +149,24 @@ impl TransferErrorsVariant {
             TransferErrorsVariant::EnvelopIllFormed { .. }
             | TransferErrorsVariant::NoSuchMailbox { .. }
             | TransferErrorsVariant::MaxDeferredAttemptReached
-            | TransferErrorsVariant::LocalDeliveryError { .. } => true,
+            | TransferErrorsVariant::LocalDeliveryError { .. }
+            | TransferErrorsVariant::MailLoop { .. }
+            | TransferErrorsVariant::NoRoute { .. }
+            | TransferErrorsVariant::NoSuchDomain { .. }
+            | TransferErrorsVariant::RequireTls { .. } => true,
 
             TransferErrorsVariant::DnsRecord { .. }
             | TransferErrorsVariant::HasNullMX { .. }
             | TransferErrorsVariant::Smtp { .. }
             | TransferErrorsVariant::StillWaiting
+            | TransferErrorsVariant::MailboxFull { .. }
             | TransferErrorsVariant::RuleEngine(..)
             | TransferErrorsVariant::DeliveryError { .. }
-            | TransferErrorsVariant::TlsNoCertificate { .. } => false,
+            | TransferErrorsVariant::TlsNoCertificate { .. }
+            | TransferErrorsVariant::TlsaMismatch { .. }
+            | TransferErrorsVariant::MtaSts { .. }
+            | TransferErrorsVariant::MxIsAlias { .. }
+            | TransferErrorsVariant::TimeBudgetExhausted => false,
         }
     }
 }
@@ -249,6 +316,17 @@ pub enum ForwardTarget {
     Socket(std::net::SocketAddr),
 }
 
+/// address of the server receiving the emails via the lmtp protocol.
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, serde::Serialize, serde::Deserialize,
+)]
+pub enum LmtpTarget {
+    /// path of a unix socket.
+    Unix(std::path::PathBuf),
+    /// an ip address with an associated port.
+    Tcp(std::net::SocketAddr),
+}
+
 /// the delivery method / protocol used for a specific recipient.
 #[derive(
     Debug,
@@ -273,6 +351,31 @@ pub enum Transfer {
     Mbox,
     /// local delivery via the maildir protocol.
     Maildir,
+    /// delivery to a local delivery agent via the lmtp protocol (RFC 2033).
+    Lmtp(LmtpTarget),
+}
+
+impl Transfer {
+    /// is the email delivered on this host (or to a local delivery agent), rather than sent to a remote server.
+    #[must_use]
+    pub const fn is_local(&self) -> bool {
+        matches!(self, Self::Mbox | Self::Maildir | Self::Lmtp(_))
+    }
+}
+
+impl std::str::FromStr for LmtpTarget {
+    type Err = anyhow::Error;
+
+    /// an absolute path is a unix socket, anything else must be an ip address with a port.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('/') {
+            return Ok(Self::Unix(s.into()));
+        }
+
+        s.parse::<std::net::SocketAddr>()
+            .map(Self::Tcp)
+            .map_err(|_| anyhow::anyhow!("{s} could not be used as a lmtp target."))
+    }
 }
 
 impl std::str::FromStr for ForwardTarget {
@@ -307,6 +410,77 @@ impl std::str::FromStr for ForwardTarget {
     }
 }
 
+/// The content of the message returned in a delivery status notification,
+/// see the `RET` parameter of `MAIL FROM` (RFC 3461).
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    strum::AsRefStr,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum DsnReturn {
+    /// The full message.
+    #[strum(serialize = "FULL")]
+    Full,
+    /// Only the headers of the message.
+    #[default]
+    #[strum(serialize = "HDRS")]
+    Headers,
+}
+
+/// The events of the delivery of a recipient for which a delivery status
+/// notification is requested, see the `NOTIFY` parameter of `RCPT TO` (RFC 3461).
+///
+/// `NOTIFY=NEVER` is represented with all the fields set to `false`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NotifyOn {
+    /// The message has been delivered.
+    pub success: bool,
+    /// The delivery failed.
+    pub failure: bool,
+    /// The delivery has been delayed.
+    pub delay: bool,
+}
+
+impl Default for NotifyOn {
+    /// Without `NOTIFY`, only the failures are notified.
+    fn default() -> Self {
+        Self {
+            success: false,
+            failure: true,
+            delay: false,
+        }
+    }
+}
+
+impl NotifyOn {
+    /// `NOTIFY=NEVER`
+    #[must_use]
+    pub const fn never() -> Self {
+        Self {
+            success: false,
+            failure: false,
+            delay: false,
+        }
+    }
+}
+
+/// The original recipient of the message, before any rewriting,
+/// see the `ORCPT` parameter of `RCPT TO` (RFC 3461).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OriginalRecipient {
+    /// Type of the address, `rfc822` for an email address.
+    pub addr_type: String,
+    /// The address, decoded from xtext.
+    pub mailbox: String,
+}
+
 /// a transport using the smtp protocol.
 /// (mostly a new type over `lettre::SmtpTransport` to implement debug
 /// and make switching transport easy if needed)
@@ -44,13 +44,15 @@ pub enum CodeID {
     Greetings,
     ///
     Help,
-    ///
+    /// Reply to QUIT, the `{transaction_count}` placeholder is replaced by the
+    /// number of messages accepted during the session.
     Closing,
     ///
     Helo,
-    ///
+    /// Reply to EHLO, the `{message_size_limit}` placeholder is replaced by
+    /// the size limit of the messages.
     EhloPain,
-    ///
+    /// Reply to EHLO under TLS, see [`CodeID::EhloPain`].
     EhloSecured,
     ///
     DataStart,
@@ -78,6 +80,29 @@ pub enum CodeID {
     BadSequence,
     ///
     MessageSizeExceeded,
+    /// A non-ASCII address has been received without the `SMTPUTF8` parameter of `MAIL FROM`,
+    /// see `server.smtp.smtputf8_strict`.
+    SmtpUtf8Required,
+    /// A percent-hack (`user%host@relay`) or UUCP (`host!user@relay`) address has been
+    /// received, see `server.smtp.reject_legacy_routing`.
+    LegacyRouting,
+    /// An ESMTP parameter of `MAIL FROM` or `RCPT TO` is not supported by the server,
+    /// see `server.smtp.unknown_params`.
+    UnknownParam,
+    /// A message with a null reverse path has more than one recipient,
+    /// see `server.smtp.null_sender`.
+    NullSenderMultipleRcpt,
+    //
+    // VRFY and EXPN
+    //
+    /// Reply to VRFY when the address is not verified,
+    /// see `server.smtp.vrfy`.
+    VrfyCannotVerify,
+    /// Reply to EXPN when the list is not expanded,
+    /// see `server.smtp.expn`.
+    ExpnDisabled,
+    /// The argument of VRFY or EXPN does not match any user or list.
+    VerifyNotFound,
     //
     // TLS extension
     //
@@ -87,6 +112,9 @@ pub enum CodeID {
     TlsNotAvailable,
     ///
     AlreadyUnderTLS,
+    /// The `REQUIRETLS` parameter of `MAIL FROM` (RFC 8689) has been received on a connection
+    /// which is not encrypted.
+    RequireTlsNotSecured,
     //
     // Auth extension
     //
@@ -104,6 +132,8 @@ pub enum CodeID {
     AuthClientCanceled,
     ///
     AuthErrorDecode64,
+    /// A response of the client during the authentication is too long (RFC 4954 4).
+    AuthLineTooLong,
     /// This response to the AUTH command indicates that the authentication
     /// failed due to a temporary server failure.  The client SHOULD NOT
     /// prompt the user for another password in this case, and should instead
@@ -120,4 +150,51 @@ pub enum CodeID {
     Timeout,
     ///
     TooManyRecipients,
+    /// The server is stopping, the connection is closed.
+    ShuttingDown,
+    /// The client IP address has too many connections opened, see `server.rate_limit`.
+    ConnectionRateLimited,
+    /// The client IP address sent too many messages or recipients, see `server.rate_limit`.
+    RateLimited,
+    /// The triplet (client IP address, sender, recipient) is greylisted, see `app.greylist`.
+    Greylisted,
+    //
+    // Storage
+    //
+    /// The spool is full, the message could not be stored.
+    InsufficientStorage,
+    //
+    // Rule engine
+    //
+    /// The evaluation of a rule failed, see `app.vsl.fail_policy`.
+    RuleEngineFailure,
+    //
+    // Recipient rejection, the `{reason}` placeholder is replaced by [`CodeID::reason`]
+    //
+    /// The recipient is not handled by this server and the client is not allowed to relay.
+    RelayDenied,
+    /// The recipient does not exist.
+    UnknownRecipient,
+    /// The recipient's mailbox is over quota.
+    MailboxFull,
+}
+
+impl CodeID {
+    /// Machine-parseable token of the cause of a recipient rejection,
+    /// `None` if the code is not a rejection cause.
+    #[must_use]
+    pub const fn reason(self) -> Option<&'static str> {
+        match self {
+            Self::RelayDenied => Some("relay-denied"),
+            Self::UnknownRecipient => Some("user-unknown"),
+            Self::MailboxFull => Some("over-quota"),
+            _ => None,
+        }
+    }
+
+    /// Get the rejection cause identified by the `reason` token.
+    #[must_use]
+    pub fn from_reason(reason: &str) -> Option<Self> {
+        <Self as strum::IntoEnumIterator>::iter().find(|code| code.reason() == Some(reason))
+    }
 }
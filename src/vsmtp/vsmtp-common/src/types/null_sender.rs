@@ -0,0 +1,41 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+/// Which recipients are accepted for a message sent with a null reverse path
+/// (`MAIL FROM:<>`), such as the bounces and the delivery status notifications.
+#[derive(
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Copy,
+    Clone,
+    Hash,
+    strum::Display,
+    strum::AsRefStr,
+    strum::EnumString,
+    serde_with::SerializeDisplay,
+    serde_with::DeserializeFromStr,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum NullSenderPolicy {
+    /// The recipients are handled as for any other sender.
+    #[default]
+    Accept,
+    /// Only one recipient is accepted, the next ones are refused with a `550 5.5.3`,
+    /// a bounce being addressed to the sender of the original message (RFC 5321 4.5.5).
+    SingleRecipient,
+}
@@ -190,10 +190,50 @@ impl Reply {
     }
 }
 
+impl Reply {
+    /// Parse a reply in its multi-line wire format, every line but the last one
+    /// using a `-` after the code (ex: `214-first line\r\n214 last line\r\n`).
+    ///
+    /// Returns `None` if `s` is not in this format.
+    fn parse_multi_line(s: &str) -> Option<Self> {
+        let lines = s
+            .split("\r\n")
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>();
+        if lines.len() < 2 {
+            return None;
+        }
+
+        let mut code = None;
+        let mut text = Vec::with_capacity(lines.len());
+        for (idx, line) in lines.iter().enumerate() {
+            let separator = if idx == lines.len() - 1 { " " } else { "-" };
+            if !line.get(..3)?.bytes().all(|c| c.is_ascii_digit()) || line.get(3..4)? != separator {
+                return None;
+            }
+
+            let line = format!("{} {}", &line[..3], &line[4..]);
+            let (line_code, line_text) = ReplyCode::parse(&line).ok()?;
+            match &code {
+                Some(code) if *code != line_code => return None,
+                Some(_) => (),
+                None => code = Some(line_code),
+            }
+            text.push(line_text.to_string());
+        }
+
+        Some(Self::new(code?, text.join("\r\n")))
+    }
+}
+
 impl std::str::FromStr for Reply {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(reply) = Self::parse_multi_line(s) {
+            return Ok(reply);
+        }
+
         let (code, text) = ReplyCode::parse(s)?;
         Ok(Self::new(code, text.to_string()))
     }
@@ -329,6 +369,56 @@ mod tests {
             );
         }
 
+        #[test]
+        fn multi_line() {
+            let reply = "214-first line\r\n214-second line\r\n214 last line\r\n"
+                .parse::<Reply>()
+                .unwrap();
+            assert_eq!(
+                reply,
+                Reply {
+                    code: ReplyCode::Code { code: 214 },
+                    text: "first line\r\nsecond line\r\nlast line".to_string()
+                }
+            );
+            assert_eq!(
+                reply.fold(),
+                "214-first line\r\n214-second line\r\n214 last line\r\n"
+            );
+        }
+
+        #[test]
+        fn multi_line_enhanced() {
+            assert_eq!(
+                "250-2.0.0 first line\r\n250 2.0.0 last line"
+                    .parse::<Reply>()
+                    .unwrap(),
+                Reply {
+                    code: ReplyCode::Enhanced {
+                        code: 250,
+                        enhanced: "2.0.0".to_string()
+                    },
+                    text: "first line\r\nlast line".to_string()
+                }
+            );
+        }
+
+        #[test]
+        fn multi_line_inconsistent_codes() {
+            assert!("214-first line\r\n250 last line".parse::<Reply>().is_err());
+        }
+
+        #[test]
+        fn continuation_without_code() {
+            assert_eq!(
+                "214 first line\r\nsecond line".parse::<Reply>().unwrap(),
+                Reply {
+                    code: ReplyCode::Code { code: 214 },
+                    text: "first line\r\nsecond line".to_string()
+                }
+            );
+        }
+
         #[test]
         fn basic_enhanced() {
             assert_eq!(
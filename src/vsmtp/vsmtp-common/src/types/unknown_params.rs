@@ -0,0 +1,41 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+/// How to handle an ESMTP parameter which is not supported by the server in a `MAIL FROM`
+/// or `RCPT TO` command (ex: `MAIL FROM:<a@b> FUTURE=x`).
+#[derive(
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Copy,
+    Clone,
+    Hash,
+    strum::Display,
+    strum::AsRefStr,
+    strum::EnumString,
+    serde_with::SerializeDisplay,
+    serde_with::DeserializeFromStr,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum UnknownParamsPolicy {
+    /// The command is refused with a `555 5.5.4` (RFC 5321).
+    #[default]
+    Reject,
+    /// The parameter is ignored, and the command handled as if it was not given.
+    Ignore,
+}
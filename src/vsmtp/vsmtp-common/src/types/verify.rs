@@ -0,0 +1,43 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+/// How the `VRFY` and `EXPN` commands are answered.
+#[derive(
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Copy,
+    Clone,
+    Hash,
+    strum::Display,
+    strum::AsRefStr,
+    strum::EnumString,
+    serde_with::SerializeDisplay,
+    serde_with::DeserializeFromStr,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum VerifyPolicy {
+    /// The `vrfy` (or `expn`) function of the root filtering script is called with the
+    /// argument of the command, and the addresses it returns are sent to the client.
+    /// If the function is not defined, the command is answered as with [`VerifyPolicy::Disabled`].
+    #[default]
+    Callback,
+    /// The command is always answered with a `252` for `VRFY` and a `502` for `EXPN`,
+    /// see [`CodeID::VrfyCannotVerify`](crate::CodeID::VrfyCannotVerify)
+    /// and [`CodeID::ExpnDisabled`](crate::CodeID::ExpnDisabled).
+    Disabled,
+}
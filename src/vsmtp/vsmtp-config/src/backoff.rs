@@ -0,0 +1,55 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+use crate::field::FieldQueueDeliveryBackoff;
+
+impl FieldQueueDeliveryBackoff {
+    /// Delay to wait, after the last failure, before the next delivery attempt of a mail
+    /// whose delivery failed `count` times.
+    ///
+    /// The delays overflowing are saturated to `max` (or to [`std::time::Duration::MAX`]
+    /// for the linear strategy).
+    #[must_use]
+    pub fn delay(&self, count: u32) -> std::time::Duration {
+        if count == 0 {
+            return std::time::Duration::ZERO;
+        }
+
+        match self {
+            Self::Linear { base } => base.checked_mul(count).unwrap_or(std::time::Duration::MAX),
+            Self::Exponential {
+                base,
+                multiplier,
+                max,
+            } => multiplier
+                .checked_pow(count - 1)
+                .and_then(|factor| base.checked_mul(factor))
+                .map_or(*max, |delay| delay.min(*max)),
+            Self::Fibonacci { base, max } => {
+                let (mut previous, mut current) = (0_u32, 1_u32);
+                for _ in 1..count {
+                    match previous.checked_add(current) {
+                        Some(next) => (previous, current) = (current, next),
+                        None => return *max,
+                    }
+                }
+                base.checked_mul(current)
+                    .map_or(*max, |delay| delay.min(*max))
+            }
+        }
+    }
+}
@@ -17,12 +17,14 @@
 use super::{wants::WantsValidate, with::Builder};
 use crate::{
     config::field::{
-        FieldApp, FieldAppLogs, FieldAppVSL, FieldServer, FieldServerInterfaces, FieldServerLogs,
-        FieldServerQueues, FieldServerSMTP, FieldServerSMTPError, FieldServerSMTPTimeoutClient,
-        FieldServerSystem, FieldServerSystemThreadPool,
+        FieldApp, FieldAppGreylist, FieldAppLogs, FieldAppVSL, FieldServer, FieldServerInterfaces,
+        FieldServerLogs, FieldServerQueues, FieldServerSMTP, FieldServerSMTPError,
+        FieldServerSMTPNullSender, FieldServerSMTPTimeoutClient, FieldServerSystem,
+        FieldServerSystemThreadPool, RuleEngineFailPolicy,
     },
     Config,
 };
+use vsmtp_common::{DuplicateParamsPolicy, UnknownParamsPolicy, VerifyPolicy};
 
 impl Builder<WantsValidate> {
     ///
@@ -65,21 +67,27 @@ impl Builder<WantsValidate> {
                         processing: srv_syst.thread_pool_processing,
                         delivery: srv_syst.thread_pool_delivery,
                     },
+                    shutdown_grace_period: FieldServerSystem::default_shutdown_grace_period(),
                 },
                 interfaces: FieldServerInterfaces {
                     addr: srv_inet.addr,
                     addr_submission: srv_inet.addr_submission,
                     addr_submissions: srv_inet.addr_submissions,
+                    log_dialog: None,
                 },
                 logs: FieldServerLogs {
                     filename: srv_logs.filename,
                     level: srv_logs.level,
                     system: None,
+                    opentelemetry: None,
+                    admin_socket: None,
                 },
                 queues: FieldServerQueues {
                     dirpath: srv_delivery.dirpath,
                     working: srv_delivery.working,
                     delivery: srv_delivery.delivery,
+                    min_free_space: None,
+                    delegation_socket: None,
                 },
                 tls: srv_tls.tls,
                 smtp: FieldServerSMTP {
@@ -98,8 +106,27 @@ impl Builder<WantsValidate> {
                     },
                     codes: smtp_codes.codes,
                     auth: auth.auth,
+                    before_queue_filter: None,
+                    milters: vec![],
+                    duplicate_params: DuplicateParamsPolicy::default(),
+                    strict_syntax: false,
+                    smtputf8_strict: false,
+                    reject_legacy_routing: false,
+                    unknown_params: UnknownParamsPolicy::default(),
+                    null_sender: FieldServerSMTPNullSender::default(),
+                    vrfy: VerifyPolicy::default(),
+                    expn: VerifyPolicy::default(),
+                    lenient_quit: FieldServerSMTP::default_lenient_quit(),
+                    strip_headers: vec![],
                 },
                 dns: dns.config,
+                dns_fallback: false,
+                mx_overrides: std::collections::BTreeMap::default(),
+                ehlo_names: std::collections::BTreeMap::default(),
+                trusted_networks: vec![],
+                hosted_domains: vec![],
+                metrics: None,
+                rate_limit: None,
                 r#virtual: virtual_entries.r#virtual,
             },
             app: FieldApp {
@@ -107,10 +134,17 @@ impl Builder<WantsValidate> {
                 vsl: FieldAppVSL {
                     domain_dir: app_vsl.domain_dir,
                     filter_path: app_vsl.filter_path,
+                    register_domain_without_config: false,
+                    fail_policy: RuleEngineFailPolicy::default(),
+                    stage_scripts: std::collections::BTreeMap::default(),
                 },
                 logs: FieldAppLogs {
                     filename: app_logs.filename,
                 },
+                dkim: vec![],
+                greylist: FieldAppGreylist::default(),
+                rcpt_lookup: None,
+                rejected_samples: None,
             },
         })
     }
@@ -356,6 +356,11 @@ impl Builder<WantsServerTLSConfig> {
                         rustls::ProtocolVersion::TLSv1_3,
                     )],
                     cipher_suite: FieldServerTls::default_cipher_suite(),
+                    certificate: None,
+                    private_key: None,
+                    ocsp_response: None,
+                    ocsp_refresh_period: FieldServerTls::default_ocsp_refresh_period(),
+                    ocsp_max_age: FieldServerTls::default_ocsp_max_age(),
                 }),
             },
         })
@@ -505,10 +510,32 @@ impl Builder<WantsServerSMTPAuth> {
                     enable_dangerous_mechanism_in_clair,
                     mechanisms,
                     attempt_count_max,
+                    scram_credentials: None,
                 }),
             },
         }
     }
+
+    /// Same as [`Self::with_auth`], with the file of salted credentials
+    /// required by the `SCRAM-*` mechanisms.
+    #[must_use]
+    pub fn with_scram_auth(
+        self,
+        enable_dangerous_mechanism_in_clair: bool,
+        mechanisms: Vec<Mechanism>,
+        attempt_count_max: i64,
+        scram_credentials: impl Into<std::path::PathBuf>,
+    ) -> Builder<WantsApp> {
+        let mut builder = self.with_auth(
+            enable_dangerous_mechanism_in_clair,
+            mechanisms,
+            attempt_count_max,
+        );
+        if let Some(auth) = &mut builder.state.auth {
+            auth.scram_credentials = Some(scram_credentials.into());
+        }
+        builder
+    }
 }
 
 impl Builder<WantsApp> {
@@ -684,21 +711,33 @@ impl Builder<WantsServerVirtual> {
                         tls: None,
                         dns: None,
                         dkim: None,
+                        rcpt_count_max: None,
+                        message_size_limit: None,
+                        codes: std::collections::BTreeMap::new(),
                     },
                     (None, Some(dns_config)) => FieldServerVirtual {
                         tls: None,
                         dns: Some(dns_config),
                         dkim: None,
+                        rcpt_count_max: None,
+                        message_size_limit: None,
+                        codes: std::collections::BTreeMap::new(),
                     },
                     (Some((certificate, private_key)), None) => FieldServerVirtual {
                         tls: Some(FieldServerVirtualTls::from_path(certificate, private_key)?),
                         dns: None,
                         dkim: None,
+                        rcpt_count_max: None,
+                        message_size_limit: None,
+                        codes: std::collections::BTreeMap::new(),
                     },
                     (Some((certificate, private_key)), Some(dns_config)) => FieldServerVirtual {
                         tls: Some(FieldServerVirtualTls::from_path(certificate, private_key)?),
                         dns: Some(dns_config),
                         dkim: None,
+                        rcpt_count_max: None,
+                        message_size_limit: None,
+                        codes: std::collections::BTreeMap::new(),
                     },
                 },
             );
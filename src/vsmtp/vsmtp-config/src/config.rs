@@ -14,7 +14,10 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use vsmtp_common::{auth::Mechanism, CodeID, Reply};
+use vsmtp_common::{
+    auth::Mechanism, CodeID, DuplicateParamsPolicy, NullSenderPolicy, Reply, UnknownParamsPolicy,
+    VerifyPolicy,
+};
 
 /// This structure contains all the field to configure the server at the startup.
 ///
@@ -46,7 +49,10 @@ pub struct Config {
 /// The inner field of the `vSMTP`'s configuration.
 #[allow(clippy::module_name_repetitions)]
 pub mod field {
-    use super::{CodeID, Mechanism, Reply};
+    use super::{
+        CodeID, DuplicateParamsPolicy, Mechanism, NullSenderPolicy, Reply, UnknownParamsPolicy,
+        VerifyPolicy,
+    };
     use vsmtp_auth::dkim;
 
     /// This structure contains all the field to configure the server at the startup.
@@ -63,7 +69,8 @@ pub mod field {
         ///
         /// The client will be rejected if the server is full.
         ///
-        /// If this value is `-1`, then the server will accept any number of client.
+        /// If this value is `-1`, then the server will accept any number of client,
+        /// the other negative values are refused.
         #[serde(default = "FieldServer::default_client_count_max")]
         pub client_count_max: i64,
         /// Maximum size in bytes of the message.
@@ -89,6 +96,37 @@ pub mod field {
         /// see [`FieldServerDNS`]
         #[serde(default)]
         pub dns: FieldServerDNS,
+        /// If a DNS resolver (of the server or of a virtual entry) fails to initialize,
+        /// use the resolver of the system, or the google one, instead of aborting the startup.
+        #[serde(default)]
+        pub dns_fallback: bool,
+        /// Static MX records of a domain, used instead of the DNS resolution when delivering to it.
+        #[serde(default)]
+        pub mx_overrides: std::collections::BTreeMap<String, Vec<FieldServerMxOverride>>,
+        /// Name presented in the HELO/EHLO command when delivering the mails sent from a domain
+        /// (the domain of the `MAIL FROM`), instead of the server name.
+        #[serde(default)]
+        pub ehlo_names: std::collections::BTreeMap<String, String>,
+        /// Networks of the clients trusted to supply information about themselves or the message,
+        /// in the CIDR notation (`192.168.1.0/24`, `2001:db8::/32`), see [`FieldServer::is_trusted`].
+        ///
+        /// For instance, the headers listed in [`FieldServerSMTP::strip_headers`] are kept
+        /// in the messages sent by those clients.
+        ///
+        /// The server does not allow those clients to relay by itself: relaying is decided by the
+        /// rules, which can call `ctx::is_trusted()` (see `examples/anti_relaying`).
+        #[serde(default)]
+        pub trusted_networks: Vec<ipnet::IpNet>,
+        /// Domains hosted by the server without a [`FieldServerVirtual`] entry,
+        /// see [`crate::Config::hosted_domains`].
+        #[serde(default)]
+        pub hosted_domains: Vec<String>,
+        /// see [`FieldServerMetrics`]
+        #[serde(default)]
+        pub metrics: Option<FieldServerMetrics>,
+        /// see [`FieldServerRateLimit`]
+        #[serde(default)]
+        pub rate_limit: Option<FieldServerRateLimit>,
         /// see [`FieldServerVirtual`]
         #[serde(default)]
         pub r#virtual: std::collections::BTreeMap<String, FieldServerVirtual>,
@@ -130,6 +168,11 @@ pub mod field {
         /// see [`FieldServerSystemThreadPool`]
         #[serde(default)]
         pub thread_pool: FieldServerSystemThreadPool,
+        /// Time given, after a termination signal, to the sessions and the deliveries
+        /// in progress to complete before the server exits.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldServerSystem::default_shutdown_grace_period")]
+        pub shutdown_grace_period: std::time::Duration,
     }
 
     impl PartialEq for FieldServerSystem {
@@ -139,6 +182,7 @@ pub mod field {
                 && self.group_local.as_ref().map(users::Group::gid)
                     == other.group_local.as_ref().map(users::Group::gid)
                 && self.thread_pool == other.thread_pool
+                && self.shutdown_grace_period == other.shutdown_grace_period
         }
     }
 
@@ -182,6 +226,11 @@ pub mod field {
         #[serde(default)]
         #[serde(deserialize_with = "crate::parser::socket_addr::deserialize")]
         pub addr_submissions: Vec<std::net::SocketAddr>,
+        /// Interfaces, among the ones above, whose SMTP dialog (each command received
+        /// and reply sent) is logged at the `trace` level, all of them if not set.
+        #[serde(default)]
+        #[serde(deserialize_with = "crate::parser::socket_addr::opt_deserialize")]
+        pub log_dialog: Option<Vec<std::net::SocketAddr>>,
     }
 
     /// The field related to the logs.
@@ -202,6 +251,31 @@ pub mod field {
         pub level: Vec<tracing_subscriber::filter::Directive>,
         /// see [`FieldServerLogSystem`]
         pub system: Option<FieldServerLogSystem>,
+        /// see [`FieldServerLogsOpenTelemetry`]
+        #[serde(default)]
+        pub opentelemetry: Option<FieldServerLogsOpenTelemetry>,
+        /// Path of a unix socket accepting new values of [`FieldServerLogs::level`],
+        /// applied without restarting the server.
+        ///
+        /// Each line written is a comma separated list of directives (ex: `warn,vsmtp_delivery=trace`),
+        /// or `reset` to restore the configured level. The reply is `OK` or `ERR <reason>`.
+        #[serde(default)]
+        pub admin_socket: Option<std::path::PathBuf>,
+    }
+
+    /// Export of the spans to an OpenTelemetry collector, using OTLP over HTTP
+    /// (requires the `opentelemetry` feature).
+    ///
+    /// The spans of a message carry its identifier in the `uuid` attribute, from its
+    /// reception to its delivery.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerLogsOpenTelemetry {
+        /// URL of the traces endpoint of the collector (ex: `http://localhost:4318/v1/traces`).
+        pub endpoint: String,
+        /// Name of the service reported with the spans.
+        #[serde(default = "FieldServerLogsOpenTelemetry::default_service_name")]
+        pub service_name: String,
     }
 
     ///
@@ -272,7 +346,10 @@ pub mod field {
             #[serde(default)]
             socket: SyslogSocket,
         },
+        /// Parameters for the `journald` backend, only available on Linux.
         ///
+        /// The events are written with their fields as structured fields, along with the
+        /// fields of their spans, such as the `uuid` of the message being handled.
         Journald {
             ///
             #[serde_as(as = "serde_with::DisplayFromStr")]
@@ -287,6 +364,31 @@ pub mod field {
         /// Size of the channel queue communicating the mails from the `receiver` pool to the `processing` pool.
         #[serde(default = "FieldQueueWorking::default_channel_size")]
         pub channel_size: usize,
+        /// Maximum number of messages received and processed at the same time by the `receiver` pool,
+        /// before being written in the queues. Once reached, the `DATA` command is answered
+        /// only when another message is done. No limit if not set.
+        #[serde(default)]
+        pub processing_count_max: Option<usize>,
+        /// What to do with a message the MIME parser fails on, in the `processing` pool.
+        #[serde(default)]
+        pub mime_parse_failure: FieldQueueWorkingMimeParseFailure,
+    }
+
+    /// Policy applied to a message that cannot be parsed as MIME.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields, tag = "policy", rename_all = "lowercase")]
+    pub enum FieldQueueWorkingMimeParseFailure {
+        /// The message is processed and delivered as is, the rules needing the parsed
+        /// message can check `msg::parse_failed()`.
+        Accept,
+        /// The delivery fails for all the recipients with a `554 5.6.0` reply,
+        /// and the message is moved to the `dead` queue.
+        Reject,
+        /// The message is moved to the quarantine queue `name` without running the rules.
+        Quarantine {
+            ///
+            name: String,
+        },
     }
 
     /// The configuration of the `vqueue`
@@ -303,6 +405,191 @@ pub mod field {
         #[serde(with = "humantime_serde")]
         #[serde(default = "FieldQueueDelivery::default_deferred_retry_period")]
         pub deferred_retry_period: std::time::Duration,
+        /// Delay between two delivery attempts of a mail in the `deferred` queue.
+        #[serde(default)]
+        pub deferred_retry_backoff: FieldQueueDeliveryBackoff,
+        /// Local mailbox receiving, in its maildir, the mails whose delivery failed permanently,
+        /// instead of moving them to the `dead` queue.
+        #[serde(default)]
+        pub dead_fallback_mailbox: Option<vsmtp_common::Address>,
+        /// Number of times a connection to a remote server is retried, during the same delivery,
+        /// when it fails because of a network error, before trying the next MX or deferring the mail.
+        #[serde(default)]
+        pub connect_retry_max: usize,
+        /// Delay between two connection attempts, see `connect_retry_max`.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldQueueDelivery::default_connect_retry_delay")]
+        pub connect_retry_delay: std::time::Duration,
+        /// Record the smtp session of the deliveries, and attach it to the error if one fails.
+        #[serde(default)]
+        pub capture_transcript: bool,
+        /// Pools of connections to the remote servers.
+        #[serde(default)]
+        pub pool: FieldQueueDeliveryPool,
+        /// Maximum number of domains delivered at the same time, by all the deliveries.
+        /// No limit if not set.
+        #[serde(default)]
+        pub in_flight_max: Option<usize>,
+        /// Maximum number of deliveries to the same domain at the same time,
+        /// keeping a slow domain from taking all the slots of `in_flight_max`. No limit if not set.
+        #[serde(default)]
+        pub in_flight_per_domain_max: Option<usize>,
+        /// Maximum number of recipients of a domain sent in the same smtp transaction,
+        /// the recipients above are sent in other transactions. No limit if not set.
+        #[serde(default)]
+        pub rcpt_per_transaction_max: Option<usize>,
+        /// Maximum number of mail exchangers of a domain tried during the same delivery,
+        /// by order of priority, before deferring the mail. No limit if not set.
+        #[serde(default)]
+        pub mx_max: Option<usize>,
+        /// Time given to a delivery attempt of a message, for all its domains: the recipients
+        /// not delivered when it is over are deferred, to retry them later. No limit if not set.
+        #[serde(with = "humantime_serde")]
+        #[serde(default)]
+        pub time_budget: Option<std::time::Duration>,
+        /// Remember the domains answered with `NXDOMAIN` for this duration: their recipients
+        /// fail permanently, without looking the domain up again. Disabled if not set.
+        #[serde(with = "humantime_serde")]
+        #[serde(default)]
+        pub nxdomain_cache: Option<std::time::Duration>,
+        /// see [`FieldQueueDeliveryMxCname`]
+        #[serde(default)]
+        pub mx_cname: FieldQueueDeliveryMxCname,
+        /// Fail permanently the delivery to a domain without MX nor A/AAAA records, which
+        /// cannot be routed (RFC 5321 5.1), instead of deferring it as a temporary dns error.
+        #[serde(default = "FieldQueueDelivery::default_no_route_permanent")]
+        pub no_route_permanent: bool,
+        /// Domains whose servers must be authenticated with DANE (RFC 7672): the delivery
+        /// fails if the TLSA records of the mail exchangers are missing or do not match.
+        /// The TLSA records are only used if the resolver validates DNSSEC.
+        #[serde(default)]
+        pub dane_required: std::collections::BTreeSet<String>,
+        /// Enforce the MTA-STS policies (RFC 8461) of the recipients' domains
+        /// (requires the `mta-sts` feature).
+        #[serde(default)]
+        pub mta_sts: bool,
+        /// Deliveries adding the `X-VSMTP` header, holding the status of the rules, to the message.
+        #[serde(default)]
+        pub x_vsmtp_header: FieldQueueDeliveryXVsmtp,
+        /// Quota of the mailboxes of the `maildir` transport, not enforced if not set.
+        #[serde(default)]
+        pub maildir_quota: Option<FieldQueueDeliveryMaildirQuota>,
+        /// Time waited for the lock of a mailbox of the `mbox` transport, held by another
+        /// delivery or a mail reader, before deferring the mail.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldQueueDelivery::default_mbox_lock_timeout")]
+        pub mbox_lock_timeout: std::time::Duration,
+        /// see [`FieldQueueDeliveryLocalHeaders`]
+        #[serde(default)]
+        pub local_headers: FieldQueueDeliveryLocalHeaders,
+    }
+
+    /// Headers added to the messages delivered by the `mbox` and `maildir` transports.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldQueueDeliveryLocalHeaders {
+        /// Add a `Return-Path` header holding the sender of the envelope (RFC 5321 4.4).
+        #[serde(default = "FieldQueueDeliveryLocalHeaders::default_return_path")]
+        pub return_path: bool,
+        /// Add a `Delivered-To` header holding the recipient. The delivery fails
+        /// if the message already has one for this recipient, as it is looping.
+        #[serde(default = "FieldQueueDeliveryLocalHeaders::default_delivered_to")]
+        pub delivered_to: bool,
+    }
+
+    /// Quota of the mailboxes of the `maildir` transport, following Maildir++.
+    ///
+    /// The quota written by the IMAP server (Dovecot, Courier, ...) in the `maildirsize`
+    /// file of a mailbox is used, and the file is updated after each delivery. The mailboxes
+    /// without this file are checked against the quota below, summing the size of their messages.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldQueueDeliveryMaildirQuota {
+        /// Maximum size in bytes of the messages of a mailbox. No limit if not set.
+        #[serde(default)]
+        pub storage: Option<u64>,
+        /// Maximum number of messages of a mailbox. No limit if not set.
+        #[serde(default)]
+        pub messages: Option<u64>,
+        /// Fail the delivery of a message to a full mailbox, sending a delivery status
+        /// notification, instead of holding it back in the `deferred` queue.
+        #[serde(default)]
+        pub bounce: bool,
+    }
+
+    /// Deliveries of a message carrying the `X-VSMTP` header.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum FieldQueueDeliveryXVsmtp {
+        /// The header is added for all the recipients.
+        Always,
+        /// The header is only added for the recipients delivered locally (mbox, maildir, lmtp),
+        /// and never sent to a remote server.
+        Local,
+        /// The header is never added.
+        Never,
+    }
+
+    /// Delivery to a mail exchanger whose name is an alias (CNAME), which is not
+    /// allowed by RFC 2181 10.3 but commonly found.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum FieldQueueDeliveryMxCname {
+        /// The alias is followed to the address of the mail exchanger, and a warning is logged.
+        Follow,
+        /// The mail exchanger is skipped, the next ones are tried.
+        Reject,
+    }
+
+    /// The connections to a remote server kept open between the deliveries.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldQueueDeliveryPool {
+        /// Duration after which an idle connection is closed.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldQueueDeliveryPool::default_idle_timeout")]
+        pub idle_timeout: std::time::Duration,
+        /// Maximum number of connections per remote server,
+        /// `0` disables the pooling: a new connection is opened for each delivery.
+        #[serde(default = "FieldQueueDeliveryPool::default_max_size")]
+        pub max_size: u32,
+        /// Minimum number of idle connections kept per remote server.
+        #[serde(default = "FieldQueueDeliveryPool::default_min_idle")]
+        pub min_idle: u32,
+    }
+
+    /// Strategy computing the delay before the next delivery attempt of a deferred mail,
+    /// from the number of its failed attempts.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields, tag = "strategy", rename_all = "lowercase")]
+    pub enum FieldQueueDeliveryBackoff {
+        /// `base * count`.
+        Linear {
+            ///
+            #[serde(with = "humantime_serde")]
+            base: std::time::Duration,
+        },
+        /// `base * multiplier ^ (count - 1)`, up to `max`.
+        Exponential {
+            ///
+            #[serde(with = "humantime_serde")]
+            base: std::time::Duration,
+            ///
+            #[serde(default = "FieldQueueDeliveryBackoff::default_multiplier")]
+            multiplier: u32,
+            ///
+            #[serde(with = "humantime_serde")]
+            max: std::time::Duration,
+        },
+        /// `base * fibonacci(count)`, up to `max`.
+        Fibonacci {
+            ///
+            #[serde(with = "humantime_serde")]
+            base: std::time::Duration,
+            ///
+            #[serde(with = "humantime_serde")]
+            max: std::time::Duration,
+        },
     }
 
     /// The configuration of the filesystem for the mail queuer.
@@ -317,9 +604,35 @@ pub mod field {
         /// see [`FieldQueueDelivery`]
         #[serde(default)]
         pub delivery: FieldQueueDelivery,
+        /// see [`FieldServerQueuesMinFreeSpace`]
+        #[serde(default)]
+        pub min_free_space: Option<FieldServerQueuesMinFreeSpace>,
+        /// Path of a unix socket on which an external filter fetches the messages of
+        /// the `delegated` queue and pushes them back with its verdict.
+        ///
+        /// The request `PULL <uuid>` is answered by `OK <size>` followed by the message.
+        /// The request `PUSH <uuid> <verdict> <size>` followed by the filtered message
+        /// is answered by `OK`, the verdict being `resume`, `deny` or `quarantine=<name>`.
+        /// The reply is `ERR <reason>` on failure.
+        #[serde(default)]
+        pub delegation_socket: Option<std::path::PathBuf>,
+    }
+
+    /// Free space required on the filesystem of the spool to accept a message,
+    /// the `DATA` command is refused temporarily below these thresholds.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerQueuesMinFreeSpace {
+        /// Minimum number of bytes available.
+        #[serde(default)]
+        pub bytes: u64,
+        /// Minimum percentage of the filesystem available.
+        #[serde(default)]
+        pub percent: u8,
     }
 
     /// The configuration of one virtual entry for the server.
+    #[serde_with::serde_as]
     #[derive(Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
     #[serde(deny_unknown_fields)]
     pub struct FieldServerVirtual {
@@ -330,6 +643,21 @@ pub mod field {
         /// see [`FieldDkim`]
         // TODO: should not be an Option<> and should be under #[cfg(feature = "dkim")] ?
         pub dkim: Option<FieldDkim>,
+        /// Override of `server.smtp.rcpt_count_max` for the recipients of the domain.
+        /// The lowest limit of the domains of the transaction is applied.
+        #[serde(default)]
+        pub rcpt_count_max: Option<usize>,
+        /// Override of `server.message_size_limit` for the recipients of the domain.
+        /// The lowest limit of the domains of the transaction is applied, a recipient
+        /// being refused if the `SIZE` declared by `MAIL FROM` exceeds it.
+        ///
+        /// It cannot raise `server.message_size_limit`, which limits every message.
+        #[serde(default)]
+        pub message_size_limit: Option<usize>,
+        /// Override of `server.smtp.codes`, used once a recipient of the domain is received.
+        #[serde(default)]
+        #[serde_as(as = "std::collections::BTreeMap<serde_with::DisplayFromStr, _>")]
+        pub codes: std::collections::BTreeMap<CodeID, Reply>,
     }
 
     /// The TLS parameter for the **OUTGOING SIDE** of the virtual entry.
@@ -372,6 +700,28 @@ pub mod field {
         /// TLS cipher suite supported
         #[serde(default = "FieldServerTls::default_cipher_suite")]
         pub cipher_suite: Vec<vsmtp_common::CipherSuite>,
+        /// Certificate chain served when the client sent no SNI, or a name
+        /// which is not one of the `server.virtual` entries with a TLS configuration.
+        #[serde(default)]
+        pub certificate: Option<SecretFile<Vec<rustls::Certificate>>>,
+        /// Private key of [`FieldServerTls::certificate`].
+        #[serde(default)]
+        pub private_key: Option<SecretFile<rustls::PrivateKey>>,
+        /// OCSP response (DER) of [`FieldServerTls::certificate`], stapled to the handshakes.
+        /// The file is kept up to date by another tool, and read again every `ocsp_refresh_period`
+        /// and a few minutes before the `nextUpdate` of the response. The certificate is served
+        /// without it if the file is missing, invalid or stale.
+        #[serde(default)]
+        pub ocsp_response: Option<std::path::PathBuf>,
+        /// Delay between two reads of [`FieldServerTls::ocsp_response`], cannot be `0`.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldServerTls::default_ocsp_refresh_period")]
+        pub ocsp_refresh_period: std::time::Duration,
+        /// An OCSP response without `nextUpdate` is stale this delay after its `thisUpdate`,
+        /// and no longer stapled.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldServerTls::default_ocsp_max_age")]
+        pub ocsp_max_age: std::time::Duration,
     }
 
     /// Configuration of the client's error handling.
@@ -430,6 +780,49 @@ pub mod field {
         /// increasing the number of attempt failed, until `attempt_count_max`, producing an error.
         #[serde(default = "FieldServerSMTPAuth::default_attempt_count_max")]
         pub attempt_count_max: i64,
+        /// File containing the salted credentials of the users, required by the
+        /// `SCRAM-SHA-1` and `SCRAM-SHA-256` mechanisms.
+        ///
+        /// One entry per line: `<authid>:{<mechanism>}<iterations>,<salt>,<stored_key>,<server_key>`,
+        /// with the binary values encoded in base64.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub scram_credentials: Option<std::path::PathBuf>,
+    }
+
+    /// Before-queue content filter (amavis-like).
+    ///
+    /// Once the message is received, it is relayed over SMTP to the filter, and
+    /// the reply of the filter to the end of the message is the one sent to the client.
+    /// On success, the filter is responsible for the message, and must re-inject
+    /// it (possibly modified) on the `reinject` interface.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerSMTPBeforeQueueFilter {
+        /// Address of the SMTP service of the filter.
+        pub address: std::net::SocketAddr,
+        /// Interface on which the filter re-injects the messages, the messages
+        /// received on it are not sent to the filter again.
+        pub reinject: std::net::SocketAddr,
+        /// Maximum duration of the exchange with the filter.
+        #[serde(
+            default = "FieldServerSMTPBeforeQueueFilter::default_timeout",
+            with = "humantime_serde"
+        )]
+        pub timeout: std::time::Duration,
+    }
+
+    /// A milter (sendmail mail filter) the server connects to.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerSMTPMilter {
+        /// Address of the milter socket.
+        pub address: std::net::SocketAddr,
+        /// Maximum duration to wait for each reply of the milter.
+        #[serde(
+            default = "FieldServerSMTPMilter::default_timeout",
+            with = "humantime_serde"
+        )]
+        pub timeout: std::time::Duration,
     }
 
     /// Parameters of the SMTP.
@@ -453,6 +846,119 @@ pub mod field {
         /// SMTP's authentication policy.
         // TODO: should not be an Option<> and should be under #[cfg(feature = "esmtpa")]
         pub auth: Option<FieldServerSMTPAuth>,
+        /// Content filter the messages are proxied to before being queued.
+        #[serde(default)]
+        pub before_queue_filter: Option<FieldServerSMTPBeforeQueueFilter>,
+        /// Milters called at each stage of the transaction, in this order.
+        #[serde(default)]
+        pub milters: Vec<FieldServerSMTPMilter>,
+        /// Handling of a parameter given twice in a `MAIL FROM` or `RCPT TO` command.
+        #[serde(default)]
+        pub duplicate_params: DuplicateParamsPolicy,
+        /// Follow strictly the grammar of RFC 5321 for the arguments of `MAIL FROM` and `RCPT TO`,
+        /// otherwise whitespace are tolerated around the path and the parameters.
+        #[serde(default)]
+        pub strict_syntax: bool,
+        /// Reject the non-ASCII addresses of `MAIL FROM` and `RCPT TO` if the client did not
+        /// declare the `SMTPUTF8` parameter with `MAIL FROM` (RFC 6531), otherwise they are accepted.
+        #[serde(default)]
+        pub smtputf8_strict: bool,
+        /// Reject the percent-hack (`user%host@relay`) and UUCP (`host!user@relay`) addresses
+        /// of `MAIL FROM` and `RCPT TO`, which can be abused to relay through the server,
+        /// otherwise their local part is used as is.
+        #[serde(default)]
+        pub reject_legacy_routing: bool,
+        /// Handling of an ESMTP parameter of `MAIL FROM` or `RCPT TO` not supported by the server.
+        /// Refused by default, set it to `ignore` to accept them silently as before.
+        #[serde(default)]
+        pub unknown_params: UnknownParamsPolicy,
+        /// Handling of the messages sent with a null reverse path (`MAIL FROM:<>`).
+        #[serde(default)]
+        pub null_sender: FieldServerSMTPNullSender,
+        /// Handling of the `VRFY` command, answered with a `252` unless the
+        /// `vrfy` function of the root filtering script resolves the address.
+        #[serde(default)]
+        pub vrfy: VerifyPolicy,
+        /// Handling of the `EXPN` command, answered with a `502` unless the
+        /// `expn` function of the root filtering script expands the list.
+        #[serde(default)]
+        pub expn: VerifyPolicy,
+        /// Accept a `QUIT` command without the trailing CRLF when the client
+        /// closes the connection right after it.
+        #[serde(default = "FieldServerSMTP::default_lenient_quit")]
+        pub lenient_quit: bool,
+        /// Headers removed from the messages received, before the milters and the rules are run,
+        /// for instance `Authentication-Results` or `X-Originating-IP` which could be spoofed by the client.
+        ///
+        /// The headers added by the rules during the transaction, the messages coming back
+        /// from a delegation, and the ones sent by the [`FieldServer::trusted_networks`], are left untouched.
+        #[serde(default)]
+        pub strip_headers: Vec<String>,
+    }
+
+    /// Handling of the messages sent with a null reverse path (`MAIL FROM:<>`),
+    /// the bounces, see [`FieldServerSMTP::null_sender`].
+    #[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerSMTPNullSender {
+        /// Which recipients are accepted with a null reverse path.
+        #[serde(default)]
+        pub policy: NullSenderPolicy,
+        /// Name of the quarantine queue the messages with a null reverse path are written to,
+        /// instead of being delivered. The rules are still run, and can refuse them.
+        #[serde(default)]
+        pub quarantine: Option<String>,
+    }
+
+    /// A static MX record, see [`FieldServer::mx_overrides`].
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerMxOverride {
+        /// Hostname or ip address of the mail exchanger.
+        pub host: String,
+        /// Port of the SMTP service of the mail exchanger.
+        #[serde(default = "FieldServerMxOverride::default_port")]
+        pub port: u16,
+        /// Preference of the record, the lowest is tried first.
+        #[serde(default)]
+        pub priority: u16,
+    }
+
+    /// HTTP endpoint exposing the Prometheus metrics of the server
+    /// (requires the `metrics` feature).
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerMetrics {
+        /// Address of the endpoint, the metrics are served on `/metrics`.
+        pub addr: std::net::SocketAddr,
+        /// Period of the refresh of the number of messages in the queues.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldServerMetrics::default_queue_refresh_period")]
+        pub queue_refresh_period: std::time::Duration,
+    }
+
+    /// Limits applied to each client IP address, a missing limit is not enforced.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldServerRateLimit {
+        /// Maximum number of connections opened at the same time, the next ones
+        /// are closed with [`CodeID::ConnectionRateLimited`].
+        #[serde(default)]
+        pub connection_count_max: Option<u32>,
+        /// Maximum number of transactions (`MAIL FROM`) per minute, the next ones
+        /// are rejected with [`CodeID::RateLimited`].
+        #[serde(default)]
+        pub message_per_minute: Option<u32>,
+        /// Maximum number of recipients (`RCPT TO`) per hour, the next ones
+        /// are rejected with [`CodeID::RateLimited`]. The `VRFY` and `EXPN`
+        /// answered by the rules count as recipients.
+        #[serde(default)]
+        pub rcpt_per_hour: Option<u32>,
+        /// Period of the removal of the clients not connected and within their limits,
+        /// at least 1s.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldServerRateLimit::default_cleanup_period")]
+        pub cleanup_period: std::time::Duration,
     }
 
     /// Configuration of the DNS resolver.
@@ -486,6 +992,34 @@ pub mod field {
             #[serde(default)]
             options: ResolverOptsWrapper,
         },
+        /// A prioritized list of name servers, queried over UDP and TCP.
+        #[serde(rename = "upstreams")]
+        Upstreams {
+            /// Address of the name servers, the first one being the primary.
+            servers: Vec<std::net::SocketAddr>,
+            /// How the queries are spread over the name servers.
+            #[serde(default)]
+            policy: DnsUpstreamsPolicy,
+            /// Parameters
+            #[serde(default)]
+            options: ResolverOptsWrapper,
+        },
+    }
+
+    /// How the queries are spread over the name servers of [`FieldServerDNS::Upstreams`].
+    ///
+    /// Not to be confused with [`ResolverOptsWrapper::rotate`], which is about the records of the responses.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum DnsUpstreamsPolicy {
+        /// The name servers are queried one at a time in the order of the list,
+        /// the next one being used only if the previous ones failed.
+        #[default]
+        Failover,
+        /// The name servers are reordered for each query by their success rate and latency,
+        /// and `options.num_concurrent_reqs` of them are queried at once, spreading the load
+        /// over the healthy ones.
+        Rotate,
     }
 
     /// Parameter for the DNS resolver.
@@ -532,6 +1066,36 @@ pub mod field {
         pub domain_dir: Option<std::path::PathBuf>,
         /// Entry point for the rule engine.
         pub filter_path: Option<std::path::PathBuf>,
+        /// Register a domain of `domain_dir` without a `config.vsl` file as a virtual
+        /// entry with the default configuration, instead of skipping it.
+        #[serde(default)]
+        pub register_domain_without_config: bool,
+        /// What to do with the transaction when the evaluation of a rule fails,
+        /// see [`RuleEngineFailPolicy`].
+        #[serde(default)]
+        pub fail_policy: RuleEngineFailPolicy,
+        /// Scripts replacing the rules of `filter_path` for a stage, by name of the stage
+        /// (`connect`, `helo`, `authenticate`, `mail`, `rcpt`, `preq` (or `data`), `postq` or `delivery`).
+        ///
+        /// Each script has the syntax of `filter_path` and declares only the rules of its stage.
+        /// The rules of `domain_dir` are not affected.
+        #[serde(default)]
+        pub stage_scripts: std::collections::BTreeMap<String, std::path::PathBuf>,
+    }
+
+    /// Fate of the transaction when a rule or an action returns an error
+    /// (i.e. a bug in a `.vsl` script).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum RuleEngineFailPolicy {
+        /// Deny the transaction with [`CodeID::Denied`].
+        #[default]
+        Deny,
+        /// Log the error and continue with the next rule.
+        FailOpen,
+        /// Reject the transaction with [`CodeID::RuleEngineFailure`] (451),
+        /// so the client retries later.
+        FailClosed,
     }
 
     /// Application's parameter of the logs, same properties than [`FieldServerLogs`].
@@ -556,5 +1120,128 @@ pub mod field {
         /// see [`FieldAppLogs`]
         #[serde(default)]
         pub logs: FieldAppLogs,
+        /// Keys signing the outgoing mails, see [`FieldAppDkimSigner`].
+        ///
+        /// The keys of the domain of the `From` header are used, or else the ones of
+        /// the domain of the sender (`MAIL FROM`), each of them adding a signature.
+        /// The mails of the other domains are sent unsigned.
+        #[serde(default)]
+        pub dkim: Vec<FieldAppDkimSigner>,
+        /// see [`FieldAppGreylist`]
+        #[serde(default)]
+        pub greylist: FieldAppGreylist,
+        /// The users database the recipients are looked up in by `rcpt_exists()`,
+        /// see [`FieldAppLookup`].
+        #[serde(default)]
+        pub rcpt_lookup: Option<FieldAppLookup>,
+        /// Samples of the messages rejected after their reception, kept to tune the rules,
+        /// see [`FieldAppRejectedSamples`]. Disabled if `None`.
+        #[serde(default)]
+        pub rejected_samples: Option<FieldAppRejectedSamples>,
+    }
+
+    /// Capture of the messages rejected at the end of the `DATA` command (by the rules or
+    /// the milters), written to a directory for later analysis.
+    ///
+    /// A sample is made of the headers and the beginning of the body. Set `body_size` to `0`
+    /// to keep only the headers when the content of the messages must not be stored.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldAppRejectedSamples {
+        /// Set to `false` to stop writing samples, the expired ones still being removed.
+        #[serde(default = "FieldAppRejectedSamples::default_enabled")]
+        pub enabled: bool,
+        /// Folder the samples are written to, named after the id of the message.
+        #[serde(default = "FieldAppRejectedSamples::default_dirpath")]
+        pub dirpath: std::path::PathBuf,
+        /// Keep one rejected message out of `sampling`, picked at random.
+        #[serde(default = "FieldAppRejectedSamples::default_sampling")]
+        pub sampling: std::num::NonZeroU32,
+        /// Number of bytes of the body kept after the headers.
+        #[serde(default = "FieldAppRejectedSamples::default_body_size")]
+        pub body_size: usize,
+        /// Size limit of a sample, headers included.
+        #[serde(default = "FieldAppRejectedSamples::default_size_max")]
+        pub size_max: usize,
+        /// Time after which a sample is removed, the folder being checked every hour.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldAppRejectedSamples::default_retention")]
+        pub retention: std::time::Duration,
+    }
+
+    /// A key-value source, opened once when the rule engine is built and shared
+    /// by all the transactions.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields, tag = "type", rename_all = "lowercase")]
+    pub enum FieldAppLookup {
+        /// A text file with a key per line, optionally followed by whitespaces and a value.
+        /// The empty lines and the ones starting with `#` are ignored.
+        File {
+            ///
+            path: std::path::PathBuf,
+        },
+        /// A constant database file, built with `cdbmake` for example.
+        /// Needs vSMTP built with the `lookup-cdb` feature.
+        Cdb {
+            ///
+            path: std::path::PathBuf,
+        },
+        /// A SQLite database, opened read only.
+        /// Needs vSMTP built with the `lookup-sqlite` feature.
+        Sqlite {
+            ///
+            path: std::path::PathBuf,
+            /// The query run for each lookup, with the key bound to the parameter `?1`
+            /// (ex: "SELECT 1 FROM users WHERE address = ?1"). The key exists if a row
+            /// is returned, the value being its first column.
+            query: String,
+        },
+    }
+
+    /// Parameters of the `greylist()` function of the rules, greylisting the
+    /// (client IP address, sender, recipient) triplets.
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldAppGreylist {
+        /// Time a client must wait after its first attempt, the attempts until then
+        /// are rejected with [`CodeID::Greylisted`].
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldAppGreylist::default_delay")]
+        pub delay: std::time::Duration,
+        /// Time after the first attempt during which the client must retry,
+        /// the next attempt is then greylisted again.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldAppGreylist::default_retry_window")]
+        pub retry_window: std::time::Duration,
+        /// Time after which a triplet that did not pass the greylisting again,
+        /// or a client of the auto-whitelist without new delivery, is forgotten.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldAppGreylist::default_expire")]
+        pub expire: std::time::Duration,
+        /// Number of messages of a client IP address that must be delivered successfully
+        /// for the client to be no longer greylisted, `None` to disable the auto-whitelist.
+        #[serde(default = "FieldAppGreylist::default_auto_whitelist")]
+        pub auto_whitelist: Option<u32>,
+    }
+
+    /// A DKIM key signing the mails sent from a domain, before their delivery.
+    #[serde_with::serde_as]
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldAppDkimSigner {
+        /// Signing Domain Identifier, the domain whose mails are signed.
+        /// A domain can have several keys, for instance during a key rotation.
+        pub domain: String,
+        /// Selector of the public key, published at `<selector>._domainkey.<domain>`.
+        pub selector: String,
+        /// The private key used to sign the mail.
+        pub private_key: SecretFile<std::sync::Arc<dkim::PrivateKey>>,
+        /// Headers covered by the signature.
+        #[serde(default = "FieldAppDkimSigner::default_headers_field")]
+        pub headers_field: Vec<String>,
+        /// Canonicalization algorithm of the headers and the body (ex: "simple/relaxed").
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        #[serde(default = "FieldAppDkimSigner::default_canonicalization")]
+        pub canonicalization: dkim::Canonicalization,
     }
 }
@@ -17,15 +17,23 @@
 
 use crate::{
     config::field::{
-        FieldApp, FieldAppLogs, FieldAppVSL, FieldQueueDelivery, FieldQueueWorking, FieldServer,
-        FieldServerDNS, FieldServerInterfaces, FieldServerLogs, FieldServerQueues, FieldServerSMTP,
-        FieldServerSMTPAuth, FieldServerSMTPError, FieldServerSMTPTimeoutClient, FieldServerSystem,
-        FieldServerSystemThreadPool, FieldServerTls, FieldServerVirtual, ResolverOptsWrapper,
-        SyslogSocket,
+        FieldApp, FieldAppDkimSigner, FieldAppGreylist, FieldAppLogs, FieldAppRejectedSamples,
+        FieldAppVSL, FieldQueueDelivery, FieldQueueDeliveryBackoff, FieldQueueDeliveryLocalHeaders,
+        FieldQueueDeliveryMxCname, FieldQueueDeliveryPool, FieldQueueDeliveryXVsmtp,
+        FieldQueueWorking, FieldQueueWorkingMimeParseFailure, FieldServer, FieldServerDNS,
+        FieldServerInterfaces, FieldServerLogs, FieldServerLogsOpenTelemetry, FieldServerMetrics,
+        FieldServerMxOverride, FieldServerQueues, FieldServerRateLimit, FieldServerSMTP,
+        FieldServerSMTPAuth, FieldServerSMTPBeforeQueueFilter, FieldServerSMTPError,
+        FieldServerSMTPMilter, FieldServerSMTPNullSender, FieldServerSMTPTimeoutClient,
+        FieldServerSystem, FieldServerSystemThreadPool, FieldServerTls, FieldServerVirtual,
+        ResolverOptsWrapper, SyslogSocket,
     },
     Config,
 };
-use vsmtp_common::{auth::Mechanism, collection, CodeID, Reply, ReplyCode};
+use vsmtp_common::{
+    auth::Mechanism, collection, CodeID, DuplicateParamsPolicy, Reply, ReplyCode,
+    UnknownParamsPolicy, VerifyPolicy,
+};
 
 impl Default for Config {
     fn default() -> Self {
@@ -105,6 +113,7 @@ impl Config {
                     },
                     group_local: None,
                     thread_pool: FieldServerSystemThreadPool::default(),
+                    shutdown_grace_period: FieldServerSystem::default_shutdown_grace_period(),
                 },
                 // All of this is necessary since `FieldServer` implements a custom
                 // default function instead of using the derivative macro.
@@ -117,6 +126,13 @@ impl Config {
                 tls: None,
                 smtp: FieldServerSMTP::default(),
                 dns: FieldServerDNS::default(),
+                dns_fallback: false,
+                mx_overrides: std::collections::BTreeMap::default(),
+                ehlo_names: std::collections::BTreeMap::default(),
+                trusted_networks: vec![],
+                hosted_domains: vec![],
+                metrics: None,
+                rate_limit: None,
                 r#virtual: std::collections::BTreeMap::default(),
             },
             app: FieldApp::default(),
@@ -138,6 +154,13 @@ impl Default for FieldServer {
             tls: None,
             smtp: FieldServerSMTP::default(),
             dns: FieldServerDNS::default(),
+            dns_fallback: false,
+            mx_overrides: std::collections::BTreeMap::default(),
+            ehlo_names: std::collections::BTreeMap::default(),
+            trusted_networks: vec![],
+            hosted_domains: vec![],
+            metrics: None,
+            rate_limit: None,
             r#virtual: std::collections::BTreeMap::default(),
         }
     }
@@ -164,6 +187,7 @@ impl Default for FieldServerSystem {
             group: Self::default_group(),
             group_local: None,
             thread_pool: FieldServerSystemThreadPool::default(),
+            shutdown_grace_period: Self::default_shutdown_grace_period(),
         }
     }
 }
@@ -184,6 +208,10 @@ impl FieldServerSystem {
         })
         .expect("default group 'vsmtp' not found.")
     }
+
+    pub(crate) const fn default_shutdown_grace_period() -> std::time::Duration {
+        std::time::Duration::from_secs(5)
+    }
 }
 
 impl Default for FieldServerSystemThreadPool {
@@ -222,6 +250,7 @@ impl FieldServerInterfaces {
             addr: vec!["127.0.0.1:25".parse().expect("valid")],
             addr_submission: vec!["127.0.0.1:587".parse().expect("valid")],
             addr_submissions: vec!["127.0.0.1:465".parse().expect("valid")],
+            log_dialog: None,
         }
     }
 }
@@ -232,10 +261,18 @@ impl Default for FieldServerLogs {
             filename: Self::default_filename(),
             level: Self::default_level(),
             system: None,
+            opentelemetry: None,
+            admin_socket: None,
         }
     }
 }
 
+impl FieldServerLogsOpenTelemetry {
+    pub(crate) fn default_service_name() -> String {
+        "vsmtp".to_string()
+    }
+}
+
 impl FieldServerLogs {
     pub(crate) fn default_filename() -> std::path::PathBuf {
         "/var/log/vsmtp/vsmtp.log".into()
@@ -246,6 +283,12 @@ impl FieldServerLogs {
     }
 }
 
+impl FieldServerMxOverride {
+    pub(crate) const fn default_port() -> u16 {
+        vsmtp_common::SMTP_PORT
+    }
+}
+
 impl SyslogSocket {
     pub(crate) fn default_udp_local() -> std::net::SocketAddr {
         "127.0.0.1:0".parse().expect("valid")
@@ -289,6 +332,14 @@ impl FieldServerTls {
     pub(crate) const fn default_handshake_timeout() -> std::time::Duration {
         std::time::Duration::from_secs(1)
     }
+
+    pub(crate) const fn default_ocsp_refresh_period() -> std::time::Duration {
+        std::time::Duration::from_secs(60 * 60)
+    }
+
+    pub(crate) const fn default_ocsp_max_age() -> std::time::Duration {
+        std::time::Duration::from_secs(7 * 24 * 60 * 60)
+    }
 }
 
 impl Default for FieldServerQueues {
@@ -297,6 +348,8 @@ impl Default for FieldServerQueues {
             dirpath: Self::default_dirpath(),
             working: FieldQueueWorking::default(),
             delivery: FieldQueueDelivery::default(),
+            min_free_space: None,
+            delegation_socket: None,
         }
     }
 }
@@ -311,10 +364,18 @@ impl Default for FieldQueueWorking {
     fn default() -> Self {
         Self {
             channel_size: Self::default_channel_size(),
+            processing_count_max: None,
+            mime_parse_failure: FieldQueueWorkingMimeParseFailure::default(),
         }
     }
 }
 
+impl Default for FieldQueueWorkingMimeParseFailure {
+    fn default() -> Self {
+        Self::Accept
+    }
+}
+
 impl FieldQueueWorking {
     pub(crate) const fn default_channel_size() -> usize {
         32
@@ -327,6 +388,26 @@ impl Default for FieldQueueDelivery {
             channel_size: Self::default_channel_size(),
             deferred_retry_max: Self::default_deferred_retry_max(),
             deferred_retry_period: Self::default_deferred_retry_period(),
+            deferred_retry_backoff: FieldQueueDeliveryBackoff::default(),
+            dead_fallback_mailbox: None,
+            connect_retry_max: 0,
+            connect_retry_delay: Self::default_connect_retry_delay(),
+            capture_transcript: false,
+            pool: FieldQueueDeliveryPool::default(),
+            in_flight_max: None,
+            in_flight_per_domain_max: None,
+            rcpt_per_transaction_max: None,
+            mx_max: None,
+            time_budget: None,
+            nxdomain_cache: None,
+            mx_cname: FieldQueueDeliveryMxCname::default(),
+            no_route_permanent: Self::default_no_route_permanent(),
+            dane_required: std::collections::BTreeSet::new(),
+            mta_sts: false,
+            x_vsmtp_header: FieldQueueDeliveryXVsmtp::default(),
+            maildir_quota: None,
+            mbox_lock_timeout: Self::default_mbox_lock_timeout(),
+            local_headers: FieldQueueDeliveryLocalHeaders::default(),
         }
     }
 }
@@ -343,6 +424,87 @@ impl FieldQueueDelivery {
     pub(crate) const fn default_deferred_retry_period() -> std::time::Duration {
         std::time::Duration::from_secs(300)
     }
+
+    pub(crate) const fn default_connect_retry_delay() -> std::time::Duration {
+        std::time::Duration::from_secs(1)
+    }
+
+    pub(crate) const fn default_mbox_lock_timeout() -> std::time::Duration {
+        std::time::Duration::from_secs(30)
+    }
+
+    pub(crate) const fn default_no_route_permanent() -> bool {
+        true
+    }
+}
+
+impl Default for FieldQueueDeliveryPool {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Self::default_idle_timeout(),
+            max_size: Self::default_max_size(),
+            min_idle: Self::default_min_idle(),
+        }
+    }
+}
+
+impl FieldQueueDeliveryPool {
+    pub(crate) const fn default_idle_timeout() -> std::time::Duration {
+        std::time::Duration::from_secs(60)
+    }
+
+    pub(crate) const fn default_max_size() -> u32 {
+        3
+    }
+
+    pub(crate) const fn default_min_idle() -> u32 {
+        1
+    }
+}
+
+impl Default for FieldQueueDeliveryLocalHeaders {
+    fn default() -> Self {
+        Self {
+            return_path: Self::default_return_path(),
+            delivered_to: Self::default_delivered_to(),
+        }
+    }
+}
+
+impl FieldQueueDeliveryLocalHeaders {
+    pub(crate) const fn default_return_path() -> bool {
+        true
+    }
+
+    pub(crate) const fn default_delivered_to() -> bool {
+        true
+    }
+}
+
+impl Default for FieldQueueDeliveryMxCname {
+    fn default() -> Self {
+        Self::Follow
+    }
+}
+
+impl Default for FieldQueueDeliveryXVsmtp {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+impl Default for FieldQueueDeliveryBackoff {
+    fn default() -> Self {
+        Self::Linear {
+            base: std::time::Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl FieldQueueDeliveryBackoff {
+    pub(crate) const fn default_multiplier() -> u32 {
+        2
+    }
 }
 
 impl FieldServerVirtual {
@@ -358,6 +520,7 @@ impl Default for FieldServerSMTPAuth {
             ),
             mechanisms: Self::default_mechanisms(),
             attempt_count_max: Self::default_attempt_count_max(),
+            scram_credentials: None,
         }
     }
 }
@@ -386,15 +549,43 @@ impl Default for FieldServerSMTP {
             timeout_client: FieldServerSMTPTimeoutClient::default(),
             codes: Self::default_smtp_codes(),
             auth: None,
+            before_queue_filter: None,
+            milters: vec![],
+            duplicate_params: DuplicateParamsPolicy::default(),
+            strict_syntax: false,
+            smtputf8_strict: false,
+            reject_legacy_routing: false,
+            unknown_params: UnknownParamsPolicy::default(),
+            null_sender: FieldServerSMTPNullSender::default(),
+            vrfy: VerifyPolicy::default(),
+            expn: VerifyPolicy::default(),
+            lenient_quit: Self::default_lenient_quit(),
+            strip_headers: vec![],
         }
     }
 }
 
+impl FieldServerSMTPBeforeQueueFilter {
+    pub(crate) const fn default_timeout() -> std::time::Duration {
+        std::time::Duration::from_secs(60)
+    }
+}
+
+impl FieldServerSMTPMilter {
+    pub(crate) const fn default_timeout() -> std::time::Duration {
+        std::time::Duration::from_secs(30)
+    }
+}
+
 impl FieldServerSMTP {
     pub(crate) const fn default_rcpt_count_max() -> usize {
         1000
     }
 
+    pub(crate) const fn default_lenient_quit() -> bool {
+        true
+    }
+
     // TODO: should be const and compile time checked
     pub(crate) fn default_smtp_codes() -> std::collections::BTreeMap<CodeID, Reply> {
         let codes: std::collections::BTreeMap<CodeID, Reply> = collection! {
@@ -444,11 +635,35 @@ impl FieldServerSMTP {
                 ReplyCode::Code{ code: 503 }, "Bad sequence of commands\r\n"
             ),
             CodeID::MessageSizeExceeded => Reply::new(
-                ReplyCode::Enhanced { code: 552, enhanced: "4.3.1".to_string() }, "Message size exceeds fixed maximum message size\r\n"
+                ReplyCode::Enhanced { code: 552, enhanced: "5.3.4".to_string() }, "Message size exceeds fixed maximum message size\r\n"
+            ),
+            CodeID::SmtpUtf8Required => Reply::new(
+                ReplyCode::Enhanced{ code: 553, enhanced: "5.6.7".to_string() }, "Non-ASCII addresses require the SMTPUTF8 parameter\r\n"
+            ),
+            CodeID::LegacyRouting => Reply::new(
+                ReplyCode::Enhanced{ code: 553, enhanced: "5.1.3".to_string() }, "Percent-hack and UUCP addresses are not accepted\r\n"
+            ),
+            CodeID::UnknownParam => Reply::new(
+                ReplyCode::Enhanced{ code: 555, enhanced: "5.5.4".to_string() }, "Unsupported parameter\r\n"
+            ),
+            CodeID::NullSenderMultipleRcpt => Reply::new(
+                ReplyCode::Enhanced{ code: 550, enhanced: "5.5.3".to_string() }, "Messages with a null sender must have a single recipient\r\n"
+            ),
+            CodeID::VrfyCannotVerify => Reply::new(
+                ReplyCode::Enhanced{ code: 252, enhanced: "2.1.5".to_string() }, "Cannot VRFY user, but will accept message and attempt delivery\r\n"
+            ),
+            CodeID::ExpnDisabled => Reply::new(
+                ReplyCode::Enhanced{ code: 502, enhanced: "5.5.1".to_string() }, "EXPN command is disabled\r\n"
+            ),
+            CodeID::VerifyNotFound => Reply::new(
+                ReplyCode::Enhanced{ code: 550, enhanced: "5.1.1".to_string() }, "String does not match anything\r\n"
             ),
             CodeID::TlsGoAhead => Reply::new(
                 ReplyCode::Code{ code: 220 }, "TLS go ahead\r\n"
             ),
+            CodeID::RequireTlsNotSecured => Reply::new(
+                ReplyCode::Enhanced{ code: 530, enhanced: "5.7.10".to_string() }, "REQUIRETLS needs an encrypted connection\r\n"
+            ),
             CodeID::TlsNotAvailable => Reply::new(
                 ReplyCode::Code{ code: 454 }, "TLS not available due to temporary reason\r\n"
             ),
@@ -477,6 +692,9 @@ impl FieldServerSMTP {
             CodeID::AuthErrorDecode64 => Reply::new(
                 ReplyCode::Enhanced{ code: 501, enhanced: "5.5.2".to_string() }, "Invalid, not base64\r\n"
             ),
+            CodeID::AuthLineTooLong => Reply::new(
+                ReplyCode::Enhanced{ code: 500, enhanced: "5.5.6".to_string() }, "Authentication Exchange line is too long\r\n"
+            ),
             CodeID::AuthTempError => Reply::new(
                 ReplyCode::Enhanced{ code: 454, enhanced: "4.7.0".to_string() }, "Temporary authentication failure\r\n"
             ),
@@ -492,6 +710,33 @@ impl FieldServerSMTP {
             CodeID::TooManyRecipients => Reply::new(
                 ReplyCode::Code{ code: 452 }, "Requested action not taken: too many recipients\r\n"
             ),
+            CodeID::ShuttingDown => Reply::new(
+                ReplyCode::Enhanced{ code: 421, enhanced: "4.3.2".to_string() }, "Service shutting down\r\n"
+            ),
+            CodeID::ConnectionRateLimited => Reply::new(
+                ReplyCode::Enhanced{ code: 421, enhanced: "4.7.0".to_string() }, "Too many connections from your address, retry later\r\n"
+            ),
+            CodeID::RateLimited => Reply::new(
+                ReplyCode::Enhanced{ code: 452, enhanced: "4.7.0".to_string() }, "Rate limit exceeded, retry later\r\n"
+            ),
+            CodeID::Greylisted => Reply::new(
+                ReplyCode::Enhanced{ code: 451, enhanced: "4.7.1".to_string() }, "Greylisted, please retry later\r\n"
+            ),
+            CodeID::InsufficientStorage => Reply::new(
+                ReplyCode::Enhanced{ code: 452, enhanced: "4.3.1".to_string() }, "Insufficient system storage\r\n"
+            ),
+            CodeID::RuleEngineFailure => Reply::new(
+                ReplyCode::Enhanced{ code: 451, enhanced: "4.3.0".to_string() }, "Requested action aborted: local error in processing\r\n"
+            ),
+            CodeID::RelayDenied => Reply::new(
+                ReplyCode::Enhanced{ code: 554, enhanced: "5.7.1".to_string() }, "Relay access denied\r\n"
+            ),
+            CodeID::UnknownRecipient => Reply::new(
+                ReplyCode::Enhanced{ code: 550, enhanced: "5.1.1".to_string() }, "Recipient address rejected: user unknown\r\n"
+            ),
+            CodeID::MailboxFull => Reply::new(
+                ReplyCode::Enhanced{ code: 452, enhanced: "4.2.2".to_string() }, "Mailbox full\r\n"
+            ),
         };
 
         assert!(
@@ -506,6 +751,18 @@ impl FieldServerSMTP {
     }
 }
 
+impl FieldServerMetrics {
+    pub(crate) const fn default_queue_refresh_period() -> std::time::Duration {
+        std::time::Duration::from_secs(15)
+    }
+}
+
+impl FieldServerRateLimit {
+    pub(crate) const fn default_cleanup_period() -> std::time::Duration {
+        std::time::Duration::from_secs(5 * 60)
+    }
+}
+
 impl Default for FieldServerDNS {
     fn default() -> Self {
         Self::System
@@ -588,10 +845,96 @@ impl Default for FieldApp {
             dirpath: Self::default_dirpath(),
             vsl: FieldAppVSL::default(),
             logs: FieldAppLogs::default(),
+            dkim: vec![],
+            greylist: FieldAppGreylist::default(),
+            rcpt_lookup: None,
+            rejected_samples: None,
         }
     }
 }
 
+impl Default for FieldAppRejectedSamples {
+    fn default() -> Self {
+        Self {
+            enabled: Self::default_enabled(),
+            dirpath: Self::default_dirpath(),
+            sampling: Self::default_sampling(),
+            body_size: Self::default_body_size(),
+            size_max: Self::default_size_max(),
+            retention: Self::default_retention(),
+        }
+    }
+}
+
+impl FieldAppRejectedSamples {
+    pub(crate) const fn default_enabled() -> bool {
+        true
+    }
+
+    pub(crate) fn default_dirpath() -> std::path::PathBuf {
+        "/var/spool/vsmtp/app/rejected".into()
+    }
+
+    pub(crate) fn default_sampling() -> std::num::NonZeroU32 {
+        std::num::NonZeroU32::new(1).expect("not zero")
+    }
+
+    pub(crate) const fn default_body_size() -> usize {
+        1024
+    }
+
+    pub(crate) const fn default_size_max() -> usize {
+        64 * 1024
+    }
+
+    pub(crate) const fn default_retention() -> std::time::Duration {
+        std::time::Duration::from_secs(7 * 24 * 60 * 60)
+    }
+}
+
+impl Default for FieldAppGreylist {
+    fn default() -> Self {
+        Self {
+            delay: Self::default_delay(),
+            retry_window: Self::default_retry_window(),
+            expire: Self::default_expire(),
+            auto_whitelist: Self::default_auto_whitelist(),
+        }
+    }
+}
+
+impl FieldAppGreylist {
+    pub(crate) const fn default_delay() -> std::time::Duration {
+        std::time::Duration::from_secs(5 * 60)
+    }
+
+    pub(crate) const fn default_retry_window() -> std::time::Duration {
+        std::time::Duration::from_secs(2 * 24 * 60 * 60)
+    }
+
+    pub(crate) const fn default_expire() -> std::time::Duration {
+        std::time::Duration::from_secs(35 * 24 * 60 * 60)
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub(crate) const fn default_auto_whitelist() -> Option<u32> {
+        Some(5)
+    }
+}
+
+impl FieldAppDkimSigner {
+    pub(crate) fn default_headers_field() -> Vec<String> {
+        ["From", "To", "Date", "Subject", "From"]
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub(crate) fn default_canonicalization() -> vsmtp_auth::dkim::Canonicalization {
+        "simple/relaxed".parse().expect("valid canonicalization")
+    }
+}
+
 impl FieldApp {
     pub(crate) fn default_dirpath() -> std::path::PathBuf {
         "/var/spool/vsmtp/app".into()
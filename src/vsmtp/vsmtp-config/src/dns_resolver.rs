@@ -1,8 +1,12 @@
 use crate::{
-    field::{FieldServerDNS, ResolverOptsWrapper},
+    field::{DnsUpstreamsPolicy, FieldServerDNS, ResolverOptsWrapper},
     Config,
 };
-use trust_dns_resolver::{config::ResolverConfig, error::ResolveError, TokioAsyncResolver};
+use trust_dns_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ServerOrderingStrategy},
+    error::ResolveError,
+    TokioAsyncResolver,
+};
 
 ///
 #[derive(Debug)]
@@ -14,22 +18,75 @@ pub struct DnsResolvers {
 impl DnsResolvers {
     /// Initialize the DNS resolver from the [`Config`].
     ///
+    /// If `server.dns_fallback` is enabled, a resolver failing to initialize is replaced
+    /// by the resolver of the system, or the google one.
+    ///
     /// # Errors
     ///
     /// * could not initialize the DNS resolver for the root domain or any of the subdomains
+    ///   (nor its fallbacks)
     pub fn from_config(config: &Config) -> Result<Self, ResolveError> {
+        Self::from_config_with(config, Self::build_dns_from_config)
+    }
+
+    pub(crate) fn from_config_with(
+        config: &Config,
+        build: impl Fn(&FieldServerDNS) -> Result<TokioAsyncResolver, ResolveError>,
+    ) -> Result<Self, ResolveError> {
+        let build = |dns: &FieldServerDNS| {
+            if config.server.dns_fallback {
+                Self::build_with_fallback(dns, &build)
+            } else {
+                build(dns)
+            }
+        };
+
         Ok(Self {
-            root: Self::build_dns_from_config(&config.server.dns)?,
+            root: build(&config.server.dns)?,
             inner: config
                 .server
                 .r#virtual
                 .iter()
                 .filter_map(|(domain, c)| c.dns.as_ref().map(|c| (domain, c)))
-                .map(|(domain, c)| Self::build_dns_from_config(c).map(|c| (domain.clone(), c)))
+                .map(|(domain, c)| build(c).map(|c| (domain.clone(), c)))
                 .collect::<Result<std::collections::HashMap<_, _>, ResolveError>>()?,
         })
     }
 
+    fn build_with_fallback(
+        config: &FieldServerDNS,
+        build: &impl Fn(&FieldServerDNS) -> Result<TokioAsyncResolver, ResolveError>,
+    ) -> Result<TokioAsyncResolver, ResolveError> {
+        let error = match build(config) {
+            Ok(resolver) => return Ok(resolver),
+            Err(error) => error,
+        };
+
+        [
+            FieldServerDNS::System,
+            FieldServerDNS::Google {
+                options: ResolverOptsWrapper::default(),
+            },
+        ]
+        .iter()
+        .filter(|fallback| *fallback != config)
+        .find_map(|fallback| match build(fallback) {
+            Ok(resolver) => {
+                tracing::warn!(
+                    %error,
+                    ?fallback,
+                    "Failed to initialize the DNS resolver, using the fallback instead."
+                );
+                Some(resolver)
+            }
+            Err(e) => {
+                tracing::warn!(%e, ?fallback, "Failed to initialize the fallback DNS resolver.");
+                None
+            }
+        })
+        .ok_or(error)
+    }
+
     /// Build the DNS resolver from `/etc/resolv.conf` with no subdomains configured.
     ///
     /// # Errors
@@ -93,6 +150,44 @@ impl DnsResolvers {
             FieldServerDNS::Custom { config, options } => {
                 TokioAsyncResolver::tokio(config.clone(), Self::resolver_opts_from_config(options))
             }
+            FieldServerDNS::Upstreams {
+                servers,
+                policy,
+                options,
+            } => {
+                let (config, opts) = Self::upstreams_config(servers, *policy, options);
+                TokioAsyncResolver::tokio(config, opts)
+            }
+        }
+    }
+
+    fn upstreams_config(
+        servers: &[std::net::SocketAddr],
+        policy: DnsUpstreamsPolicy,
+        options: &ResolverOptsWrapper,
+    ) -> (ResolverConfig, trust_dns_resolver::config::ResolverOpts) {
+        let mut config = ResolverConfig::new();
+        // NOTE: the UDP and TCP name servers are separate pools, each one keeping the order of the list.
+        for socket_addr in servers {
+            for protocol in [Protocol::Udp, Protocol::Tcp] {
+                let mut name_server = NameServerConfig::new(*socket_addr, protocol);
+                // NOTE: a domain which does not exist is not an outage of the name server.
+                name_server.trust_nx_responses = true;
+                config.add_name_server(name_server);
+            }
         }
+
+        let mut opts = Self::resolver_opts_from_config(options);
+        match policy {
+            DnsUpstreamsPolicy::Failover => {
+                opts.server_ordering_strategy = ServerOrderingStrategy::UserProvidedOrder;
+                opts.num_concurrent_reqs = 1;
+            }
+            DnsUpstreamsPolicy::Rotate => {
+                opts.server_ordering_strategy = ServerOrderingStrategy::QueryStatistics;
+            }
+        }
+
+        (config, opts)
     }
 }
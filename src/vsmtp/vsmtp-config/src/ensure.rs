@@ -14,7 +14,10 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::{config::field::FieldServerSMTP, Config};
+use crate::{
+    config::field::{FieldServerDNS, FieldServerSMTP},
+    Config,
+};
 use vsmtp_common::{auth::Mechanism, CodeID, Reply, ReplyCode};
 
 fn mech_list_to_code(list: &[Mechanism]) -> String {
@@ -27,6 +30,45 @@ fn mech_list_to_code(list: &[Mechanism]) -> String {
     )
 }
 
+/// Check that `enhanced` is a `class.subject.detail` status code (RFC 3463)
+/// whose class is consistent with the basic `code`.
+fn check_enhanced_code(code: u16, enhanced: &str) -> anyhow::Result<()> {
+    let parts = enhanced.split('.').collect::<Vec<_>>();
+    let is_number = |part: &str, max_len: usize| {
+        !part.is_empty() && part.len() <= max_len && part.bytes().all(|c| c.is_ascii_digit())
+    };
+
+    match parts.as_slice() {
+        [class, subject, detail]
+            if matches!(*class, "2" | "4" | "5")
+                && is_number(subject, 3)
+                && is_number(detail, 3) =>
+        {
+            anyhow::ensure!(
+                class.parse::<u16>()? == code / 100,
+                "the class '{class}' of the enhanced status code '{enhanced}' does not match the code '{code}'"
+            );
+            Ok(())
+        }
+        _ => anyhow::bail!(
+            "'{enhanced}' is not a valid enhanced status code, expected 'class.subject.detail' with a class of 2, 4 or 5"
+        ),
+    }
+}
+
+/// Can `lhs` and `rhs` not be listened on at the same time: same port, and same ip or one of them
+/// is the wildcard address (the ipv6 one listening on ipv4 too, with the default dual-stack sockets).
+fn is_overlapping(lhs: &std::net::SocketAddr, rhs: &std::net::SocketAddr) -> bool {
+    let covers = |wildcard: std::net::IpAddr, ip: std::net::IpAddr| {
+        wildcard.is_unspecified() && (wildcard.is_ipv6() || ip.is_ipv4())
+    };
+
+    // NOTE: the port `0` is picked by the system, and is never the same twice.
+    lhs.port() != 0
+        && lhs.port() == rhs.port()
+        && (lhs.ip() == rhs.ip() || covers(lhs.ip(), rhs.ip()) || covers(rhs.ip(), lhs.ip()))
+}
+
 impl Config {
     pub(crate) fn ensure(mut config: Self) -> anyhow::Result<Self> {
         /*
@@ -44,6 +86,156 @@ impl Config {
             "Worker threads cannot be set to 0"
         );
 
+        anyhow::ensure!(
+            config.server.client_count_max >= -1,
+            "The maximum number of clients must be positive, or -1 for no limit"
+        );
+
+        anyhow::ensure!(
+            config.server.queues.delivery.rcpt_per_transaction_max != Some(0),
+            "The maximum number of recipients per transaction cannot be set to 0"
+        );
+
+        anyhow::ensure!(
+            config.server.queues.delivery.mx_max != Some(0),
+            "The maximum number of mail exchangers per delivery cannot be set to 0"
+        );
+
+        // NOTE: the domain names are case-insensitive, the recipients' domains are lowercased when looked up.
+        let mut mx_overrides = std::collections::BTreeMap::new();
+        for (domain, records) in std::mem::take(&mut config.server.mx_overrides) {
+            let lowercase = domain.to_lowercase();
+            anyhow::ensure!(
+                !mx_overrides.contains_key(&lowercase),
+                "The domain '{domain}' is defined multiple times in `mx_overrides` (domain names are case-insensitive)"
+            );
+            mx_overrides.insert(lowercase, records);
+        }
+        config.server.mx_overrides = mx_overrides;
+
+        let mut virtual_entries = std::collections::BTreeMap::new();
+        for (domain, entry) in std::mem::take(&mut config.server.r#virtual) {
+            let lowercase = domain.to_lowercase();
+            anyhow::ensure!(
+                !virtual_entries.contains_key(&lowercase),
+                "The domain '{domain}' is defined multiple times in `server.virtual` (domain names are case-insensitive)"
+            );
+            virtual_entries.insert(lowercase, entry);
+        }
+        config.server.r#virtual = virtual_entries;
+
+        if let Some(min_free_space) = &config.server.queues.min_free_space {
+            anyhow::ensure!(
+                min_free_space.percent <= 100,
+                "The minimum percentage of free space of the spool cannot exceed 100"
+            );
+        }
+
+        for dns in std::iter::once(&config.server.dns).chain(
+            config
+                .server
+                .r#virtual
+                .values()
+                .filter_map(|entry| entry.dns.as_ref()),
+        ) {
+            if let FieldServerDNS::Upstreams { servers, .. } = dns {
+                anyhow::ensure!(
+                    !servers.is_empty(),
+                    "The list of the DNS upstreams cannot be empty"
+                );
+            }
+        }
+
+        if let Some(tls) = &config.server.tls {
+            anyhow::ensure!(
+                tls.certificate.is_some() == tls.private_key.is_some(),
+                "The default TLS certificate and private key must be set together"
+            );
+            anyhow::ensure!(
+                !tls.ocsp_refresh_period.is_zero(),
+                "The OCSP response refresh period cannot be 0"
+            );
+        }
+
+        if let Some(rate_limit) = &config.server.rate_limit {
+            // NOTE: the clients are removed by a periodic task, which would hog its thread.
+            anyhow::ensure!(
+                rate_limit.cleanup_period >= std::time::Duration::from_secs(1),
+                "The rate limit cleanup period must be at least 1s, got '{:?}'",
+                rate_limit.cleanup_period
+            );
+        }
+
+        {
+            let interfaces = &config.server.interfaces;
+            let addresses = interfaces
+                .addr
+                .iter()
+                .map(|addr| ("addr", addr))
+                .chain(
+                    interfaces
+                        .addr_submission
+                        .iter()
+                        .map(|addr| ("addr_submission", addr)),
+                )
+                .chain(
+                    interfaces
+                        .addr_submissions
+                        .iter()
+                        .map(|addr| ("addr_submissions", addr)),
+                )
+                .collect::<Vec<_>>();
+
+            for (index, (lhs_field, lhs)) in addresses.iter().enumerate() {
+                for (rhs_field, rhs) in &addresses[index + 1..] {
+                    anyhow::ensure!(
+                        !is_overlapping(lhs, rhs),
+                        "The interfaces '{lhs}' (in `{lhs_field}`) and '{rhs}' (in `{rhs_field}`) overlap, they cannot be both listened on"
+                    );
+                }
+            }
+        }
+
+        {
+            let interfaces = &config.server.interfaces;
+            for log_dialog in interfaces.log_dialog.iter().flatten() {
+                anyhow::ensure!(
+                    interfaces
+                        .addr
+                        .iter()
+                        .chain(&interfaces.addr_submission)
+                        .chain(&interfaces.addr_submissions)
+                        .any(|addr| addr == log_dialog),
+                    "The interface '{log_dialog}' of `log_dialog` is not one of the interfaces"
+                );
+            }
+        }
+
+        if let Some(filter) = &config.server.smtp.before_queue_filter {
+            let interfaces = &config.server.interfaces;
+            anyhow::ensure!(
+                interfaces
+                    .addr
+                    .iter()
+                    .chain(&interfaces.addr_submission)
+                    .chain(&interfaces.addr_submissions)
+                    .any(|addr| *addr == filter.reinject),
+                "The before-queue filter re-injection address '{}' is not one of the interfaces",
+                filter.reinject
+            );
+        }
+
+        if let Some(auth) = &config.server.smtp.auth {
+            anyhow::ensure!(
+                auth.scram_credentials.is_some()
+                    || !auth
+                        .mechanisms
+                        .iter()
+                        .any(|m| matches!(m, Mechanism::ScramSha1 | Mechanism::ScramSha256)),
+                "The SCRAM mechanisms require the `scram_credentials` file of the users"
+            );
+        }
+
         {
             let auth_mechanism_list: Option<(Vec<Mechanism>, Vec<Mechanism>)> = config
                 .server
@@ -76,7 +268,12 @@ impl Config {
                             })
                             .unwrap_or_default(),
                         "STARTTLS\r\n",
+                        // NOTE: replaced when replying, to advertise the limit enforced by the receiver.
+                        "SIZE {message_size_limit}\r\n",
                         "8BITMIME\r\n",
+                        "CHUNKING\r\n",
+                        "PIPELINING\r\n",
+                        "DSN\r\n",
                         "SMTPUTF8\r\n",
                     ]
                     .concat(),
@@ -94,8 +291,13 @@ impl Config {
                             .as_ref()
                             .map(|(must_be_secured, _)| mech_list_to_code(must_be_secured))
                             .unwrap_or_default(),
+                        "SIZE {message_size_limit}\r\n",
                         "8BITMIME\r\n",
+                        "CHUNKING\r\n",
+                        "PIPELINING\r\n",
+                        "DSN\r\n",
                         "SMTPUTF8\r\n",
+                        "REQUIRETLS\r\n",
                     ]
                     .concat(),
                 ),
@@ -115,6 +317,25 @@ impl Config {
             });
         }
 
+        for (key, reply) in reply_codes.iter() {
+            if let ReplyCode::Enhanced { code, enhanced } = reply.code() {
+                check_enhanced_code(*code, enhanced)
+                    .map_err(|e| anyhow::anyhow!("Invalid reply for the code '{key}': {e}"))?;
+            }
+        }
+
+        for (domain, entry) in &mut config.server.r#virtual {
+            for (key, reply) in &mut entry.codes {
+                reply.set(reply.text().replace("{name}", &config.server.name));
+
+                if let ReplyCode::Enhanced { code, enhanced } = reply.code() {
+                    check_enhanced_code(*code, enhanced).map_err(|e| {
+                        anyhow::anyhow!("Invalid reply for the code '{key}' of '{domain}': {e}")
+                    })?;
+                }
+            }
+        }
+
         Ok(config)
     }
 }
@@ -0,0 +1,90 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+/// Replace the `${NAME}` in all the strings of `value` (the keys excepted) with the value
+/// of the environment variable `NAME`, `path` being the path of `value` in the configuration.
+///
+/// Only a `${` followed by a valid variable name (`[A-Za-z_][A-Za-z0-9_]*`) and a `}` is replaced,
+/// any other `$` is kept as is.
+///
+/// # Errors
+///
+/// * A referenced variable is not set, or its value is not valid unicode.
+pub(crate) fn interpolate_env(value: &mut serde_json::Value, path: &str) -> anyhow::Result<()> {
+    match value {
+        serde_json::Value::String(string) => {
+            *string = interpolate_str(string, path)?;
+        }
+        serde_json::Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                interpolate_env(value, &format!("{path}[{index}]"))?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                interpolate_env(value, &format!("{path}.{key}"))?;
+            }
+        }
+        serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {}
+    }
+
+    Ok(())
+}
+
+fn interpolate_str(input: &str, path: &str) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        match after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|name| is_variable_name(name))
+        {
+            Some(name) => {
+                let value = std::env::var(name).map_err(|error| match error {
+                    std::env::VarError::NotPresent => anyhow::anyhow!(
+                        "In the '{path}' configuration, the environment variable '{name}' is not set."
+                    ),
+                    std::env::VarError::NotUnicode(_) => anyhow::anyhow!(
+                        "In the '{path}' configuration, the environment variable '{name}' is not valid unicode."
+                    ),
+                })?;
+                output.push_str(&value);
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                output.push_str("${");
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
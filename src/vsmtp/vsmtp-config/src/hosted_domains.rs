@@ -0,0 +1,38 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+impl Config {
+    /// Every domain hosted by the server: the ones of [`FieldServer::r#virtual`]
+    /// and of [`FieldServer::hosted_domains`].
+    ///
+    /// The domains are lowercased, sorted and deduplicated.
+    ///
+    /// [`FieldServer::r#virtual`]: crate::field::FieldServer::r#virtual
+    /// [`FieldServer::hosted_domains`]: crate::field::FieldServer::hosted_domains
+    #[must_use]
+    pub fn hosted_domains(&self) -> Vec<String> {
+        self.server
+            .r#virtual
+            .keys()
+            .chain(&self.server.hosted_domains)
+            .map(|domain| domain.to_lowercase())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
@@ -17,13 +17,24 @@
 //!
 //! # Configuration
 //!
-//! The type [`Config`] expose two methods :
+//! The type [`Config`] expose four methods :
 //! * [`Config::builder`] to create a new configuration builder.
-//! * [`Config::from_vsl_file`] to read a configuration from a TOML file.
+//! * [`Config::from_vsl_file`] to read a configuration from a vsl file.
+//! * [`Config::from_vsl_reader`] to read a configuration from any [`std::io::Read`].
+//! * [`Config::from_toml_file`] to read a configuration from a static TOML file.
+//!
+//! # Environment variables
+//!
+//! All the string values of the configuration returned by `on_config` (and `on_domain_config`
+//! for the virtual domains), at any depth, are scanned for `${NAME}` and interpolated
+//! with the value of the environment variable `NAME`, before being deserialized.
+//! The loading fails if a referenced variable is not set.
+//!
+//! A `$` not followed by `{NAME}` is kept as is. The TOML files are not interpolated.
 //!
 //! # Example
 //!
-//! You can find examples of TOML file at <https://github.com/viridIT/vSMTP/tree/develop/examples/config>
+//! You can find examples of vsl file at <https://github.com/viridIT/vSMTP/tree/develop/examples/config>
 
 /*
  * vSMTP mail transfer agent
@@ -77,10 +88,16 @@ pub mod builder {
     pub use with::*;
 }
 
+mod backoff;
 mod config;
 mod default;
 mod ensure;
+mod env_interpolation;
+mod hosted_domains;
+mod limits;
+mod ocsp;
 mod rustls_helper;
+mod trusted_networks;
 mod virtual_tls;
 
 mod dns_resolver;
@@ -90,7 +107,9 @@ use config::field::FieldServerVirtual;
 pub use dns_resolver::DnsResolvers;
 
 pub use config::{field, Config};
-pub use rustls_helper::get_rustls_config;
+pub use limits::ScriptLimits;
+pub use parser::tracing_directive::from_str as parse_tracing_directives;
+pub use rustls_helper::{get_rustls_config, ocsp_refresh_delay, reload_rustls_config};
 
 use builder::{Builder, WantsVersion};
 
@@ -108,6 +127,7 @@ impl Config {
     /// # Errors
     ///
     /// * Data is not valid vsl.
+    /// * A referenced environment variable is not set.
     /// * Found an unknown field.
     /// * Version requirements are not fulfilled.
     /// * A mandatory field is missing. (when no default value is provided)
@@ -134,17 +154,101 @@ impl Config {
         Ok(config)
     }
 
+    /// Create a [`Config`] from vsl data read from any source (memory, database, network...).
+    ///
+    /// `resolve_path` is the directory used to resolve the `import` statements of the script.
+    ///
+    /// # Errors
+    ///
+    /// * Data could not be read or is not valid utf8.
+    /// * Data is not valid vsl.
+    /// * A referenced environment variable is not set.
+    /// * Found an unknown field.
+    /// * Version requirements are not fulfilled.
+    /// * A mandatory field is missing. (when no default value is provided)
+    pub fn from_vsl_reader(
+        mut reader: impl std::io::Read,
+        resolve_path: Option<&std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        let mut script = String::new();
+        reader
+            .read_to_string(&mut script)
+            .context("Cannot read the configuration")?;
+
+        Self::from_vsl_script(
+            script,
+            resolve_path.map(std::path::Path::to_path_buf).as_ref(),
+        )
+    }
+
     /// Create a [`Config`] from vsl data.
     ///
     /// # Errors
     ///
     /// * Data is not valid vsl.
+    /// * A referenced environment variable is not set.
     /// * Found an unknown field.
     /// * Version requirements are not fulfilled.
     /// * A mandatory field is missing. (when no default value is provided)
     pub fn from_vsl_script(
         script: impl AsRef<str>,
         resolve_path: Option<&std::path::PathBuf>,
+    ) -> anyhow::Result<Self> {
+        Self::from_vsl_script_with(script, resolve_path, |_| ())
+    }
+
+    /// Create a [`Config`] from vsl data, calling `engine_customizer` on the rhai engine
+    /// before the script is compiled, so that custom functions or modules can be registered.
+    ///
+    /// The module resolver of `resolve_path` and the default [`ScriptLimits`] are set up
+    /// before calling `engine_customizer`. A callback registered with [`rhai::Engine::on_progress`]
+    /// by `engine_customizer` replaces the one enforcing [`ScriptLimits::timeout`].
+    ///
+    /// # Errors
+    ///
+    /// * Data is not valid vsl.
+    /// * A referenced environment variable is not set.
+    /// * Found an unknown field.
+    /// * Version requirements are not fulfilled.
+    /// * A mandatory field is missing. (when no default value is provided)
+    /// * The evaluation exceeded the default [`ScriptLimits`].
+    pub fn from_vsl_script_with(
+        script: impl AsRef<str>,
+        resolve_path: Option<&std::path::PathBuf>,
+        engine_customizer: impl FnOnce(&mut rhai::Engine),
+    ) -> anyhow::Result<Self> {
+        Self::from_vsl_script_inner(
+            script,
+            resolve_path,
+            &ScriptLimits::default(),
+            engine_customizer,
+        )
+    }
+
+    /// Create a [`Config`] from vsl data, aborting the evaluation of the scripts
+    /// if it exceeds the given `limits`.
+    ///
+    /// # Errors
+    ///
+    /// * Data is not valid vsl.
+    /// * A referenced environment variable is not set.
+    /// * Found an unknown field.
+    /// * Version requirements are not fulfilled.
+    /// * A mandatory field is missing. (when no default value is provided)
+    /// * The evaluation exceeded the `limits`.
+    pub fn from_vsl_script_with_limits(
+        script: impl AsRef<str>,
+        resolve_path: Option<&std::path::PathBuf>,
+        limits: &ScriptLimits,
+    ) -> anyhow::Result<Self> {
+        Self::from_vsl_script_inner(script, resolve_path, limits, |_| ())
+    }
+
+    fn from_vsl_script_inner(
+        script: impl AsRef<str>,
+        resolve_path: Option<&std::path::PathBuf>,
+        limits: &ScriptLimits,
+        engine_customizer: impl FnOnce(&mut rhai::Engine),
     ) -> anyhow::Result<Self> {
         #[derive(serde::Serialize, serde::Deserialize)]
         struct VersionRequirement {
@@ -152,30 +256,24 @@ impl Config {
         }
 
         let script = script.as_ref();
-        let mut engine = rhai::Engine::new();
+        let mut engine = Self::new_engine(resolve_path);
 
-        if let Some(resolve_path) = resolve_path.as_ref() {
-            engine.set_module_resolver(
-                rhai::module_resolvers::FileModuleResolver::new_with_path_and_extension(
-                    resolve_path,
-                    "vsl",
-                ),
-            );
-        }
+        // NOTE: registered first, so `engine_customizer` has the final say on the engine.
+        limits.register(&mut engine);
+        engine_customizer(&mut engine);
 
         let ast = engine
             .compile(script)
             .context("Failed to compile root configuration (config.vsl)")?;
 
-        let user_config: rhai::Map = engine
-            .call_fn(
-                &mut rhai::Scope::new(),
-                &ast,
-                "on_config",
-                (Config::default_json()?,),
-            )
+        let user_config = limits
+            .call_fn(&engine, &ast, "on_config", Config::default_json()?)?
             .context("Could not get main configuration.")?;
 
+        let mut user_config =
+            serde_json::to_value(&user_config).context("The main configuration is malformed")?;
+        env_interpolation::interpolate_env(&mut user_config, "config")?;
+
         let raw_config =
             serde_json::to_string(&user_config).context("The main configuration is malformed")?;
 
@@ -186,6 +284,100 @@ impl Config {
             Err(error) => anyhow::bail!(Self::format_error(&error)?),
         };
 
+        Self::finalize(config, &engine, limits)
+    }
+
+    /// Create a [`Config`] from a static [TOML] file.
+    ///
+    /// Unlike vsl, the document is deserialized as is and no script is evaluated,
+    /// the configuration of the virtual domains (`app.vsl.domain_dir`) is still read from vsl.
+    ///
+    /// # Errors
+    ///
+    /// * Data is not valid TOML.
+    /// * Found an unknown field.
+    /// * Version requirements are not fulfilled.
+    /// * A mandatory field is missing. (when no default value is provided)
+    /// * File could not be opened or read.
+    ///
+    /// [TOML]: https://toml.io
+    pub fn from_toml_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        let vsmtp_config_dir = std::path::PathBuf::from(path.parent().ok_or_else(|| {
+            anyhow::anyhow!(
+                "File '{}' does not have a valid parent directory for configuration files",
+                path.display()
+            )
+        })?);
+
+        let document =
+            std::fs::read_to_string(path).context(format!("Cannot read file at {path:?}"))?;
+
+        let mut config = Self::from_toml_str(document, Some(&vsmtp_config_dir))?;
+
+        config.path = Some(path.to_path_buf());
+
+        Ok(config)
+    }
+
+    /// Create a [`Config`] from TOML data.
+    ///
+    /// `resolve_path` is the directory used to resolve the `import` statements
+    /// of the virtual domains scripts.
+    ///
+    /// # Errors
+    ///
+    /// * Data is not valid TOML.
+    /// * Found an unknown field.
+    /// * Version requirements are not fulfilled.
+    /// * A mandatory field is missing. (when no default value is provided)
+    pub fn from_toml_str(
+        document: impl AsRef<str>,
+        resolve_path: Option<&std::path::PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let mut deserializer = toml::Deserializer::new(document.as_ref());
+
+        let config = match serde_path_to_error::deserialize(&mut deserializer) {
+            Ok(config) => config,
+            Err(error) => anyhow::bail!(
+                "In the 'config.{}' configuration, {}",
+                error.path(),
+                error.inner()
+            ),
+        };
+        deserializer
+            .end()
+            .context("The configuration is malformed")?;
+
+        let limits = ScriptLimits::default();
+        let mut engine = Self::new_engine(resolve_path);
+        limits.register(&mut engine);
+
+        Self::finalize(config, &engine, &limits)
+    }
+
+    fn new_engine(resolve_path: Option<&std::path::PathBuf>) -> rhai::Engine {
+        let mut engine = rhai::Engine::new();
+
+        if let Some(resolve_path) = resolve_path {
+            engine.set_module_resolver(
+                rhai::module_resolvers::FileModuleResolver::new_with_path_and_extension(
+                    resolve_path,
+                    "vsl",
+                ),
+            );
+        }
+
+        engine
+    }
+
+    /// Shared by all the formats, once the configuration has been deserialized.
+    fn finalize(
+        config: Self,
+        engine: &rhai::Engine,
+        limits: &ScriptLimits,
+    ) -> anyhow::Result<Self> {
         let mut config = Self::ensure(config)?;
 
         let pkg_version = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
@@ -197,7 +389,7 @@ impl Config {
             );
         }
 
-        config.get_domain_config(&engine)?;
+        config.get_domain_config(engine, limits)?;
 
         Ok(config)
     }
@@ -251,8 +443,13 @@ impl Config {
     }
 
     /// Get the configuration for a virtual domain.
-    fn get_domain_config(&mut self, engine: &rhai::Engine) -> anyhow::Result<()> {
+    fn get_domain_config(
+        &mut self,
+        engine: &rhai::Engine,
+        limits: &ScriptLimits,
+    ) -> anyhow::Result<()> {
         if let Some(domains_path) = &self.app.vsl.domain_dir {
+            let mut domain_dirs = vec![];
             for entry in std::fs::read_dir(domains_path).with_context(|| {
                 format!(
                     "Cannot read domain directory in '{}'",
@@ -264,9 +461,24 @@ impl Config {
                     continue;
                 }
 
-                let domain_dir = entry.path();
-                let domain = entry.file_name().to_str().unwrap().to_owned();
+                domain_dirs.push((entry.file_name().to_str().unwrap().to_owned(), entry.path()));
+            }
+
+            // NOTE: `read_dir` order is platform dependent, domains are processed sorted.
+            domain_dirs.sort_unstable();
+
+            let mut seen = std::collections::HashSet::new();
+            for (domain, _) in &domain_dirs {
+                if !seen.insert(domain.to_lowercase()) {
+                    anyhow::bail!(
+                        "Domain directory '{}' is defined multiple times in '{}' (domain names are case-insensitive)",
+                        domain,
+                        domains_path.display()
+                    );
+                }
+            }
 
+            for (domain, domain_dir) in domain_dirs {
                 // NOTE: non readable file are ignored.
                 let files = std::fs::read_dir(&domain_dir)
                     .with_context(|| {
@@ -289,12 +501,19 @@ impl Config {
                         )
                     })?;
 
-                    let raw_domain_config: rhai::Map = match engine.call_fn(
-                        &mut rhai::Scope::new(),
-                        &ast,
-                        "on_domain_config",
-                        (FieldServerVirtual::default_json()?,),
-                    ) {
+                    let raw_domain_config = match limits
+                        .call_fn(
+                            engine,
+                            &ast,
+                            "on_domain_config",
+                            FieldServerVirtual::default_json()?,
+                        )
+                        .with_context(|| {
+                            format!(
+                                "Failed to evaluate configuration (config.vsl) for domain '{}'",
+                                domain_dir.display()
+                            )
+                        })? {
                         Ok(raw_domain_config) => raw_domain_config,
                         Err(err) => {
                             eprintln!("Could not get configuration for the '{domain}' domain because: {err}. The root domain config will be used by default.");
@@ -302,6 +521,16 @@ impl Config {
                         }
                     };
 
+                    let mut raw_domain_config = serde_json::to_value(&raw_domain_config)
+                        .context("The configuration is malformed")?;
+                    env_interpolation::interpolate_env(&mut raw_domain_config, "config")
+                        .with_context(|| {
+                            format!(
+                                "Failed to evaluate configuration (config.vsl) for domain '{}'",
+                                domain_dir.display()
+                            )
+                        })?;
+
                     let raw_domain_config = serde_json::to_string(&raw_domain_config)
                         .context("The configuration is malformed")?;
 
@@ -313,6 +542,10 @@ impl Config {
                     };
 
                     self.server.r#virtual.insert(domain.clone(), domain_config);
+                } else if self.app.vsl.register_domain_without_config {
+                    self.server
+                        .r#virtual
+                        .insert(domain, FieldServerVirtual::default());
                 }
             }
         }
@@ -0,0 +1,92 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+/// Limits applied to the evaluation of the vsl configuration scripts
+/// (`on_config` and `on_domain_config`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptLimits {
+    /// Maximum duration of the evaluation of a script.
+    pub timeout: std::time::Duration,
+    /// Maximum number of entries of a map, those of the nested maps included.
+    pub max_map_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            timeout: std::time::Duration::from_secs(10),
+            max_map_size: 10_000,
+        }
+    }
+}
+
+thread_local! {
+    /// Deadline of the evaluation in progress on this thread, see [`ScriptLimits::call_fn`].
+    static DEADLINE: std::cell::Cell<Option<std::time::Instant>> =
+        const { std::cell::Cell::new(None) };
+}
+
+/// Number of operations between two checks of the deadline.
+const DEADLINE_CHECK_PERIOD: u64 = 1024;
+
+impl ScriptLimits {
+    /// Make `engine` enforce the limits, the evaluation being terminated by the engine
+    /// as soon as one of them is exceeded.
+    pub(crate) fn register(&self, engine: &mut rhai::Engine) {
+        engine.set_max_map_size(self.max_map_size);
+        engine.on_progress(|operations| {
+            if operations % DEADLINE_CHECK_PERIOD != 0 {
+                return None;
+            }
+
+            DEADLINE.with(|deadline| match deadline.get() {
+                Some(deadline) if std::time::Instant::now() >= deadline => {
+                    Some(rhai::Dynamic::UNIT)
+                }
+                _ => None,
+            })
+        });
+    }
+
+    /// Call the function `name` of the script with an `engine` set up by [`Self::register`].
+    ///
+    /// The outer result holds the violations of the limits, the inner one the errors
+    /// of the script itself.
+    pub(crate) fn call_fn(
+        &self,
+        engine: &rhai::Engine,
+        ast: &rhai::AST,
+        name: &'static str,
+        arg: rhai::Map,
+    ) -> anyhow::Result<Result<rhai::Map, Box<rhai::EvalAltResult>>> {
+        DEADLINE.with(|deadline| deadline.set(Some(std::time::Instant::now() + self.timeout)));
+        let result = engine.call_fn::<rhai::Map>(&mut rhai::Scope::new(), ast, name, (arg,));
+        DEADLINE.with(|deadline| deadline.set(None));
+
+        match result.as_ref().map_err(|error| error.unwrap_inner()) {
+            Err(rhai::EvalAltResult::ErrorTerminated(..)) => anyhow::bail!(
+                "The evaluation of '{name}' has been aborted: it took more than {:?}",
+                self.timeout
+            ),
+            Err(rhai::EvalAltResult::ErrorDataTooLarge(..)) => anyhow::bail!(
+                "The evaluation of '{name}' has been aborted: a map has more than {} entries",
+                self.max_map_size
+            ),
+            _ => Ok(result),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use anyhow::Context;
+
+const ENUMERATED: u8 = 0x0a;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const EXPLICIT_0: u8 = 0xa0;
+
+/// The period an OCSP response is valid for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Validity {
+    /// `thisUpdate` of the response of the certificate.
+    pub this_update: std::time::SystemTime,
+    /// `nextUpdate` of the response of the certificate, `None` if the responder
+    /// did not set it.
+    pub next_update: Option<std::time::SystemTime>,
+}
+
+/// Split the first element of `input` in its tag, its content and the elements after it.
+fn element(input: &[u8]) -> anyhow::Result<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first().context("truncated element")?;
+    let (&length, input) = input.split_first().context("truncated length")?;
+
+    let (length, input) = if length < 0x80 {
+        (usize::from(length), input)
+    } else {
+        let count = usize::from(length & 0x7f);
+        anyhow::ensure!(
+            (1..=4).contains(&count) && count <= input.len(),
+            "invalid length"
+        );
+        let (length, input) = input.split_at(count);
+        (
+            length
+                .iter()
+                .fold(0, |length, byte| (length << 8) | usize::from(*byte)),
+            input,
+        )
+    };
+
+    anyhow::ensure!(length <= input.len(), "truncated content");
+    let (content, rest) = input.split_at(length);
+    Ok((tag, content, rest))
+}
+
+/// Split the first element of `input`, which must be tagged `expected`.
+fn expect(input: &[u8], expected: u8) -> anyhow::Result<(&[u8], &[u8])> {
+    let (tag, content, rest) = element(input)?;
+    anyhow::ensure!(
+        tag == expected,
+        "expected the tag {expected:#04x}, got {tag:#04x}"
+    );
+    Ok((content, rest))
+}
+
+/// Read a `GeneralizedTime` in UTC, the fraction of second being ignored.
+fn generalized_time(value: &[u8]) -> anyhow::Result<std::time::SystemTime> {
+    let value = std::str::from_utf8(value)?
+        .strip_suffix('Z')
+        .context("the time is not in UTC")?;
+    let value = value.split_once('.').map_or(value, |(value, _)| value);
+    anyhow::ensure!(
+        value.len() == 14 && value.bytes().all(|byte| byte.is_ascii_digit()),
+        "invalid time `{value}`"
+    );
+
+    let field = |range: std::ops::Range<usize>| value[range].parse::<u64>();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    anyhow::ensure!(
+        year >= 1970
+            && (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 61,
+        "invalid time `{value}`"
+    );
+
+    // NOTE: days since the epoch of a date of the proleptic Gregorian calendar,
+    //       the year starting in March to put the leap day at its end.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let (era, year_of_era) = (year / 400, year % 400);
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + (153 * month + 2) / 5 + day - 1;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Ok(std::time::UNIX_EPOCH
+        + std::time::Duration::from_secs(days * 86_400 + hour * 3_600 + minute * 60 + second))
+}
+
+/// Read the validity of the first response of a DER `OCSPResponse` (RFC 6960), the one
+/// of the certificate it is stapled to. Only the elements needed are decoded.
+///
+/// # Errors
+///
+/// * the response is not a valid DER `OCSPResponse`
+/// * the status of the response is not `successful`
+pub(crate) fn validity(response: &[u8]) -> anyhow::Result<Validity> {
+    let (response, _) = expect(response, SEQUENCE)?;
+    let (status, response) = expect(response, ENUMERATED)?;
+    anyhow::ensure!(
+        status == [0],
+        "the status of the response is not successful"
+    );
+
+    let (response_bytes, _) = expect(response, EXPLICIT_0)?;
+    let (response_bytes, _) = expect(response_bytes, SEQUENCE)?;
+    let (_, response_bytes) = expect(response_bytes, OBJECT_IDENTIFIER)?;
+    let (basic, _) = expect(response_bytes, OCTET_STRING)?;
+    let (basic, _) = expect(basic, SEQUENCE)?;
+
+    let (data, _) = expect(basic, SEQUENCE)?;
+    let data = match element(data)? {
+        (EXPLICIT_0, _, rest) => rest,
+        _ => data,
+    };
+    // responderID, then producedAt
+    let (_, _, data) = element(data)?;
+    let (_, data) = expect(data, GENERALIZED_TIME)?;
+
+    let (responses, _) = expect(data, SEQUENCE)?;
+    let (single, _) = expect(responses, SEQUENCE)?;
+    // certID, then certStatus
+    let (_, single) = expect(single, SEQUENCE)?;
+    let (_, _, single) = element(single)?;
+    let (this_update, single) = expect(single, GENERALIZED_TIME)?;
+    let next_update = match element(single) {
+        Ok((EXPLICIT_0, next_update, _)) => {
+            Some(generalized_time(expect(next_update, GENERALIZED_TIME)?.0)?)
+        }
+        _ => None,
+    };
+
+    Ok(Validity {
+        this_update: generalized_time(this_update)?,
+        next_update,
+    })
+}
@@ -30,6 +30,25 @@ where
         .map_err(serde::de::Error::custom)
 }
 
+pub fn opt_deserialize<'de, D>(
+    deserializer: D,
+) -> Result<Option<Vec<std::net::SocketAddr>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    <Option<Vec<String>> as serde::Deserialize>::deserialize(deserializer)?
+        .map(|addr| {
+            addr.into_iter()
+                .map(|s| {
+                    <std::net::SocketAddr as std::str::FromStr>::from_str(&s)
+                        .or_else(|_| ipv6_with_scope_id(&s))
+                })
+                .collect::<anyhow::Result<Vec<std::net::SocketAddr>>>()
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
+}
+
 #[cfg(test)]
 mod test {
     use vsmtp_common::libc_abstraction::{if_indextoname, if_nametoindex};
@@ -14,6 +14,22 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+/// Parse a comma separated list of directives, as in `RUST_LOG` (ex: `warn,vsmtp_delivery=trace`).
+///
+/// # Errors
+///
+/// * One of the directives is malformed.
+pub fn from_str(
+    value: &str,
+) -> Result<Vec<tracing_subscriber::filter::Directive>, tracing_subscriber::filter::ParseError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(<tracing_subscriber::filter::Directive as std::str::FromStr>::from_str)
+        .collect()
+}
+
 pub fn serialize<S: serde::Serializer>(
     value: &Vec<tracing_subscriber::filter::Directive>,
     serializer: S,
@@ -14,9 +14,13 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+use anyhow::Context;
 use rustls::ALL_CIPHER_SUITES;
 
-use crate::field::{FieldServerTls, FieldServerVirtual, FieldServerVirtualTls};
+use crate::{
+    field::{FieldServerTls, FieldServerVirtual, FieldServerVirtualTls},
+    parser::{tls_certificate, tls_private_key},
+};
 
 struct TlsLogger;
 impl rustls::KeyLog for TlsLogger {
@@ -45,6 +49,183 @@ fn to_supported_cipher_suite(
 pub fn get_rustls_config(
     config: &FieldServerTls,
     virtual_entries: &std::collections::BTreeMap<String, FieldServerVirtual>,
+) -> anyhow::Result<rustls::ServerConfig> {
+    build_rustls_config(
+        config,
+        config
+            .certificate
+            .as_ref()
+            .zip(config.private_key.as_ref())
+            .map(|(certificate, private_key)| {
+                (certificate.inner.clone(), private_key.inner.clone())
+            }),
+        virtual_tls(virtual_entries).map(|(virtual_name, tls)| {
+            Ok((
+                virtual_name,
+                tls.certificate.inner.clone(),
+                tls.private_key.inner.clone(),
+            ))
+        }),
+    )
+}
+
+/// Build the same configuration as [`get_rustls_config`], but with the certificates
+/// and the private keys read again from their files,
+/// to pick up the ones renewed since the configuration has been loaded.
+///
+/// # Errors
+///
+/// * a certificate or a private key cannot be read or is invalid
+/// * the configuration cannot be built, see [`get_rustls_config`]
+pub fn reload_rustls_config(
+    config: &FieldServerTls,
+    virtual_entries: &std::collections::BTreeMap<String, FieldServerVirtual>,
+) -> anyhow::Result<rustls::ServerConfig> {
+    let default = match config.certificate.as_ref().zip(config.private_key.as_ref()) {
+        Some((certificate, private_key)) => Some((
+            tls_certificate::from_path(&certificate.path.to_string_lossy())
+                .context("cannot read the default certificate")?,
+            tls_private_key::from_path(&private_key.path.to_string_lossy())
+                .context("cannot read the default private key")?,
+        )),
+        None => None,
+    };
+
+    build_rustls_config(
+        config,
+        default,
+        virtual_tls(virtual_entries).map(|(virtual_name, tls)| -> anyhow::Result<_> {
+            Ok((
+                virtual_name,
+                tls_certificate::from_path(&tls.certificate.path.to_string_lossy())
+                    .with_context(|| format!("cannot read the certificate of '{virtual_name}'"))?,
+                tls_private_key::from_path(&tls.private_key.path.to_string_lossy())
+                    .with_context(|| format!("cannot read the private key of '{virtual_name}'"))?,
+            ))
+        }),
+    )
+}
+
+fn virtual_tls(
+    virtual_entries: &std::collections::BTreeMap<String, FieldServerVirtual>,
+) -> impl Iterator<Item = (&String, &FieldServerVirtualTls)> {
+    virtual_entries
+        .iter()
+        .filter_map(|(virtual_name, params)| params.tls.as_ref().map(|tls| (virtual_name, tls)))
+}
+
+/// Serve the certificate of the virtual entry matching the SNI sent by the client,
+/// or the default certificate of `server.tls` if there is none.
+struct SniResolver {
+    by_name: rustls::server::ResolvesServerCertUsingSni,
+    names: std::collections::HashSet<String>,
+    default: Option<std::sync::Arc<rustls::sign::CertifiedKey>>,
+}
+
+impl rustls::server::ResolvesServerCert for SniResolver {
+    fn resolve(
+        &self,
+        client_hello: rustls::server::ClientHello<'_>,
+    ) -> Option<std::sync::Arc<rustls::sign::CertifiedKey>> {
+        let is_virtual = client_hello.server_name().map_or(false, |name| {
+            self.names.contains(&name.to_ascii_lowercase())
+        });
+
+        if is_virtual {
+            rustls::server::ResolvesServerCert::resolve(&self.by_name, client_hello)
+        } else {
+            self.default.clone()
+        }
+    }
+}
+
+fn certified_key(
+    certificate: Vec<rustls::Certificate>,
+    private_key: &rustls::PrivateKey,
+    ocsp: Option<Vec<u8>>,
+) -> anyhow::Result<rustls::sign::CertifiedKey> {
+    Ok(rustls::sign::CertifiedKey {
+        cert: certificate,
+        key: rustls::sign::any_supported_type(private_key)?,
+        ocsp,
+        // TODO: support SCT
+        sct_list: None,
+    })
+}
+
+/// An OCSP response is read again this long before its `nextUpdate`, to staple
+/// the one fetched by the tool keeping the file up to date before it expires.
+const OCSP_EXPIRY_MARGIN: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Read the OCSP response to staple to the default certificate.
+///
+/// The response is stale after its `nextUpdate`, or `ocsp_max_age` after its `thisUpdate`
+/// if the responder did not set `nextUpdate`.
+///
+/// A missing, unreadable, invalid or stale response is not an error: the certificate
+/// is served without it.
+pub(crate) fn read_ocsp_response(config: &FieldServerTls) -> Option<Vec<u8>> {
+    let path = config.ocsp_response.as_ref()?;
+
+    let read = || -> anyhow::Result<Vec<u8>> {
+        let response = std::fs::read(path)?;
+        let validity = crate::ocsp::validity(&response).context("invalid OCSP response")?;
+
+        let expiry = validity
+            .next_update
+            .or_else(|| validity.this_update.checked_add(config.ocsp_max_age));
+        if let Some(expired) = expiry.and_then(|expiry| expiry.elapsed().ok()) {
+            anyhow::bail!("the response is stale, expired {}s ago", expired.as_secs());
+        }
+        Ok(response)
+    };
+
+    match read() {
+        Ok(response) => Some(response),
+        Err(error) => {
+            tracing::warn!(
+                path = %path.display(),
+                error = %format!("{error:#}"),
+                "Cannot staple the OCSP response, serving the certificate without it."
+            );
+            None
+        }
+    }
+}
+
+/// Delay before reading again the OCSP response of `config`: `ocsp_refresh_period`,
+/// shortened to read it a few minutes before the response stapled at `now` expires,
+/// and once more at its expiry to stop stapling it.
+#[must_use]
+pub fn ocsp_refresh_delay(
+    config: &FieldServerTls,
+    now: std::time::SystemTime,
+) -> std::time::Duration {
+    let until_expiry = config
+        .ocsp_response
+        .as_ref()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|response| crate::ocsp::validity(&response).ok())
+        .and_then(|validity| validity.next_update)
+        .and_then(|next_update| next_update.duration_since(now).ok());
+
+    match until_expiry {
+        Some(until_expiry) if until_expiry > OCSP_EXPIRY_MARGIN => config
+            .ocsp_refresh_period
+            .min(until_expiry - OCSP_EXPIRY_MARGIN),
+        Some(until_expiry) if !until_expiry.is_zero() => {
+            config.ocsp_refresh_period.min(until_expiry)
+        }
+        _ => config.ocsp_refresh_period,
+    }
+}
+
+fn build_rustls_config<'a>(
+    config: &FieldServerTls,
+    default: Option<(Vec<rustls::Certificate>, rustls::PrivateKey)>,
+    certified_keys: impl Iterator<
+        Item = anyhow::Result<(&'a String, Vec<rustls::Certificate>, rustls::PrivateKey)>,
+    >,
 ) -> anyhow::Result<rustls::ServerConfig> {
     let protocol_version = match (
         config
@@ -62,31 +243,28 @@ pub fn get_rustls_config(
         (false, false) => anyhow::bail!("requested version is not supported"),
     };
 
-    let mut cert_resolver = rustls::server::ResolvesServerCertUsingSni::new();
-    let virtual_server_with_tls = virtual_entries
-        .iter()
-        .filter_map(|(virtual_name, params)| params.tls.as_ref().map(|tls| (virtual_name, tls)));
-    for (
-        virtual_name,
-        FieldServerVirtualTls {
-            certificate,
-            private_key,
-            ..
+    let mut cert_resolver = SniResolver {
+        by_name: rustls::server::ResolvesServerCertUsingSni::new(),
+        names: std::collections::HashSet::new(),
+        default: match default {
+            Some((certificate, private_key)) => Some(std::sync::Arc::new(
+                certified_key(certificate, &private_key, read_ocsp_response(config))
+                    .context("invalid default private key")?,
+            )),
+            None => None,
         },
-    ) in virtual_server_with_tls
-    {
+    };
+    for entry in certified_keys {
+        let (virtual_name, certificate, private_key) = entry?;
+        let virtual_name = virtual_name.to_ascii_lowercase();
         cert_resolver
+            .by_name
             .add(
-                virtual_name,
-                rustls::sign::CertifiedKey {
-                    cert: certificate.inner.clone(),
-                    key: rustls::sign::any_supported_type(&private_key.inner)?,
-                    // TODO: support OCSP and SCT
-                    ocsp: None,
-                    sct_list: None,
-                },
+                &virtual_name,
+                certified_key(certificate, &private_key, None)?,
             )
             .map_err(|e| anyhow::anyhow!("cannot add sni to resolver '{virtual_name}': {e}"))?;
+        cert_resolver.names.insert(virtual_name);
     }
 
     let mut tls_config = rustls::ServerConfig::builder()
@@ -0,0 +1,110 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldQueueDeliveryBackoff, Config};
+
+const MINUTE: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn delays(backoff: &FieldQueueDeliveryBackoff) -> Vec<std::time::Duration> {
+    (0..=6).map(|count| backoff.delay(count)).collect()
+}
+
+#[test]
+fn linear_by_default() {
+    let backoff = Config::default()
+        .server
+        .queues
+        .delivery
+        .deferred_retry_backoff;
+
+    assert_eq!(
+        delays(&backoff),
+        [0, 5, 10, 15, 20, 25, 30].map(|m| MINUTE * m).to_vec()
+    );
+}
+
+#[test]
+fn exponential() {
+    let backoff = FieldQueueDeliveryBackoff::Exponential {
+        base: MINUTE,
+        multiplier: 3,
+        max: MINUTE * 100,
+    };
+
+    assert_eq!(
+        delays(&backoff),
+        [0, 1, 3, 9, 27, 81, 100].map(|m| MINUTE * m).to_vec()
+    );
+    assert_eq!(backoff.delay(u32::MAX), MINUTE * 100);
+}
+
+#[test]
+fn fibonacci() {
+    let backoff = FieldQueueDeliveryBackoff::Fibonacci {
+        base: MINUTE,
+        max: MINUTE * 6,
+    };
+
+    assert_eq!(
+        delays(&backoff),
+        [0, 1, 1, 2, 3, 5, 6].map(|m| MINUTE * m).to_vec()
+    );
+    assert_eq!(backoff.delay(u32::MAX), MINUTE * 6);
+}
+
+#[test]
+fn from_script() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.delivery.deferred_retry_backoff = #{
+        strategy: "exponential",
+        base: "2m",
+        max: "1h",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.queues.delivery.deferred_retry_backoff,
+        FieldQueueDeliveryBackoff::Exponential {
+            base: MINUTE * 2,
+            multiplier: 2,
+            max: MINUTE * 60,
+        }
+    );
+}
+
+#[test]
+fn unknown_strategy() {
+    Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.delivery.deferred_retry_backoff = #{
+        strategy: "random",
+        base: "2m",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+}
@@ -0,0 +1,64 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+fn config_with_client_count_max(client_count_max: i64) -> anyhow::Result<Config> {
+    Config::from_vsl_script(
+        format!(
+            r#"
+fn on_config(config) {{
+    config.server.client_count_max = {client_count_max};
+    config
+}}
+"#
+        ),
+        None,
+    )
+}
+
+#[test]
+fn unlimited() {
+    assert_eq!(
+        config_with_client_count_max(-1)
+            .unwrap()
+            .server
+            .client_count_max,
+        -1
+    );
+}
+
+#[test]
+fn limited() {
+    assert_eq!(
+        config_with_client_count_max(100)
+            .unwrap()
+            .server
+            .client_count_max,
+        100
+    );
+}
+
+#[test]
+fn negative_refused() {
+    let error = config_with_client_count_max(-2).unwrap_err();
+
+    assert!(
+        format!("{error:#}")
+            .contains("The maximum number of clients must be positive, or -1 for no limit"),
+        "{error:#}"
+    );
+}
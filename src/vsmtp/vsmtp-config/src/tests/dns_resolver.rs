@@ -0,0 +1,179 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    field::{DnsUpstreamsPolicy, FieldServerDNS, ResolverOptsWrapper},
+    Config, DnsResolvers,
+};
+use trust_dns_resolver::{
+    config::ResolverConfig,
+    error::ResolveError,
+    proto::rr::{Name, RData, Record},
+    TokioAsyncResolver,
+};
+use vsmtp_test::dns::MockDns;
+
+fn config_with_broken_resolver(dns_fallback: bool) -> Config {
+    let mut config = Config::default();
+    config.server.dns = FieldServerDNS::Custom {
+        config: ResolverConfig::new(),
+        options: ResolverOptsWrapper::default(),
+    };
+    config.server.dns_fallback = dns_fallback;
+    config
+}
+
+// the custom resolver is considered broken, the others are built as usual.
+fn build(config: &FieldServerDNS) -> Result<TokioAsyncResolver, ResolveError> {
+    match config {
+        FieldServerDNS::Custom { .. } => Err(ResolveError::from("broken resolver")),
+        FieldServerDNS::System | FieldServerDNS::Google { .. } => {
+            TokioAsyncResolver::tokio(ResolverConfig::google(), Default::default())
+        }
+        FieldServerDNS::CloudFlare { .. } | FieldServerDNS::Upstreams { .. } => unreachable!(),
+    }
+}
+
+#[test]
+fn broken_resolver_strict() {
+    let error =
+        DnsResolvers::from_config_with(&config_with_broken_resolver(false), build).unwrap_err();
+
+    assert_eq!(error.to_string(), "broken resolver");
+}
+
+#[test]
+fn broken_resolver_fallback() {
+    DnsResolvers::from_config_with(&config_with_broken_resolver(true), build).unwrap();
+}
+
+#[test]
+fn broken_fallbacks() {
+    let error = DnsResolvers::from_config_with(&config_with_broken_resolver(true), |_| {
+        Err(ResolveError::from("broken resolver"))
+    })
+    .unwrap_err();
+
+    assert_eq!(error.to_string(), "broken resolver");
+}
+
+fn config_with_upstreams(servers: Vec<std::net::SocketAddr>, policy: DnsUpstreamsPolicy) -> Config {
+    let mut config = Config::default();
+    config.server.dns = FieldServerDNS::Upstreams {
+        servers,
+        policy,
+        options: ResolverOptsWrapper {
+            timeout: std::time::Duration::from_millis(500),
+            attempts: 1,
+            cache_size: 0,
+            ..ResolverOptsWrapper::default()
+        },
+    };
+    config
+}
+
+// the port of a socket just closed, on which no name server answers.
+fn unreachable_upstream() -> std::net::SocketAddr {
+    std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+async fn primary_unreachable(policy: DnsUpstreamsPolicy) {
+    let secondary = MockDns::serve(vec![Record::from_rdata(
+        "mx.foo.bar.".parse::<Name>().unwrap(),
+        300,
+        RData::A(std::net::Ipv4Addr::LOCALHOST),
+    )])
+    .await;
+
+    let resolvers = DnsResolvers::from_config(&config_with_upstreams(
+        vec![unreachable_upstream(), secondary.addr()],
+        policy,
+    ))
+    .unwrap();
+
+    let lookup = resolvers
+        .get_resolver_root()
+        .ipv4_lookup("mx.foo.bar.")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        lookup.iter().copied().collect::<Vec<_>>(),
+        vec![std::net::Ipv4Addr::LOCALHOST]
+    );
+}
+
+#[tokio::test]
+async fn upstreams_failover() {
+    primary_unreachable(DnsUpstreamsPolicy::Failover).await;
+}
+
+#[tokio::test]
+async fn upstreams_rotate() {
+    primary_unreachable(DnsUpstreamsPolicy::Rotate).await;
+}
+
+#[test]
+fn upstreams_empty() {
+    let error = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.dns = #{ type: "upstreams", servers: [] };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    assert!(
+        format!("{error:#}").contains("The list of the DNS upstreams cannot be empty"),
+        "{error:#}"
+    );
+}
+
+#[test]
+fn upstreams_parse() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.dns = #{
+        type: "upstreams",
+        servers: ["192.0.2.1:53", "192.0.2.2:53"],
+        policy: "rotate",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.dns,
+        FieldServerDNS::Upstreams {
+            servers: vec![
+                "192.0.2.1:53".parse().unwrap(),
+                "192.0.2.2:53".parse().unwrap()
+            ],
+            policy: DnsUpstreamsPolicy::Rotate,
+            options: ResolverOptsWrapper::default(),
+        }
+    );
+}
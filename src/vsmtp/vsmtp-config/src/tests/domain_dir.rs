@@ -0,0 +1,78 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+fn config_with_domain_dir(
+    domain_dir: &std::path::Path,
+    register_domain_without_config: bool,
+) -> anyhow::Result<Config> {
+    Config::from_vsl_script(
+        format!(
+            r#"
+fn on_config(config) {{
+    config.app.vsl.domain_dir = "{}";
+    config.app.vsl.register_domain_without_config = {register_domain_without_config};
+    config
+}}
+"#,
+            domain_dir.display()
+        ),
+        None,
+    )
+}
+
+fn temp_domain_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "vsmtp-config-domain-dir-{name}-{}",
+        std::process::id()
+    ))
+}
+
+#[test]
+fn reject_case_insensitive_duplicates() {
+    let domain_dir = temp_domain_dir("case");
+    std::fs::create_dir_all(domain_dir.join("example.com")).unwrap();
+    std::fs::create_dir_all(domain_dir.join("Example.com")).unwrap();
+
+    let error = config_with_domain_dir(&domain_dir, false).unwrap_err();
+    std::fs::remove_dir_all(&domain_dir).unwrap();
+
+    assert!(
+        error.to_string().contains("is defined multiple times"),
+        "{error}"
+    );
+}
+
+#[test]
+fn domain_without_config() {
+    let domain_dir = temp_domain_dir("without-config");
+    std::fs::create_dir_all(domain_dir.join("example.com")).unwrap();
+
+    let skipped = config_with_domain_dir(&domain_dir, false).unwrap();
+    let registered = config_with_domain_dir(&domain_dir, true).unwrap();
+    std::fs::remove_dir_all(&domain_dir).unwrap();
+
+    assert!(skipped.server.r#virtual.is_empty());
+    assert_eq!(
+        registered.server.r#virtual.keys().collect::<Vec<_>>(),
+        ["example.com"]
+    );
+    assert_eq!(
+        registered.server.r#virtual["example.com"],
+        crate::field::FieldServerVirtual::default()
+    );
+}
@@ -0,0 +1,61 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn with_custom_function() {
+    let config = Config::from_vsl_script_with(
+        r#"
+fn on_config(config) {
+    config.server.name = secret("server_name");
+    config
+}
+"#,
+        None,
+        |engine| {
+            engine.register_fn("secret", |key: &str| format!("{key}.from.vault"));
+        },
+    )
+    .unwrap();
+
+    assert_eq!(config.server.name, "server_name.from.vault");
+}
+
+#[test]
+fn with_custom_progress_callback() {
+    let operations = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let operations_ref = operations.clone();
+
+    Config::from_vsl_script_with(
+        r#"
+fn on_config(config) {
+    config.server.name = "testserver.com";
+    config
+}
+"#,
+        None,
+        move |engine| {
+            engine.on_progress(move |count| {
+                operations_ref.store(count, std::sync::atomic::Ordering::SeqCst);
+                None
+            });
+        },
+    )
+    .unwrap();
+
+    assert_ne!(operations.load(std::sync::atomic::Ordering::SeqCst), 0);
+}
@@ -0,0 +1,70 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+use vsmtp_common::{CodeID, ReplyCode};
+
+fn config_with_code(code: &str, reply: &str) -> anyhow::Result<Config> {
+    Config::from_vsl_script(
+        format!(
+            r#"
+fn on_config(config) {{
+    config.server.smtp.codes.{code} = "{reply}\r\n";
+    config
+}}
+"#
+        ),
+        None,
+    )
+}
+
+#[test]
+fn valid() {
+    for (code, reply, enhanced) in [
+        ("Denied", "554 5.7.1 denied", "5.7.1"),
+        ("Ok", "250 2.0.0 ok", "2.0.0"),
+        ("TooManyRecipients", "452 4.5.3 too many", "4.5.3"),
+        ("AuthTempError", "454 4.7.100 retry", "4.7.100"),
+    ] {
+        let config = config_with_code(code, reply).unwrap();
+        assert_eq!(
+            *config.server.smtp.codes[&code.parse::<CodeID>().unwrap()].code(),
+            ReplyCode::Enhanced {
+                code: reply[..3].parse().unwrap(),
+                enhanced: enhanced.to_string()
+            }
+        );
+    }
+}
+
+#[test]
+fn inconsistent_class() {
+    for (code, reply) in [
+        ("Denied", "554 2.0.0 denied"),
+        ("Ok", "250 5.0.0 ok"),
+        ("MessageSizeExceeded", "552 4.3.1 too big"),
+    ] {
+        let error = config_with_code(code, reply).unwrap_err();
+        assert!(error.to_string().contains(code), "{error}");
+    }
+}
+
+#[test]
+fn invalid_format() {
+    for reply in ["554 3.1.1 denied", "554 5.1 denied", "554 5.1.1000 denied"] {
+        assert!(config_with_code("Denied", reply).is_err(), "{reply}");
+    }
+}
@@ -0,0 +1,81 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn interpolated() {
+    std::env::set_var("VSMTP_TEST_INTERPOLATED_NAME", "mail");
+    std::env::set_var("VSMTP_TEST_INTERPOLATED_DIR", "/tmp/vsmtp");
+
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.name = "${VSMTP_TEST_INTERPOLATED_NAME}.example.com";
+    config.app.dirpath = "${VSMTP_TEST_INTERPOLATED_DIR}/app";
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(config.server.name, "mail.example.com");
+    assert_eq!(
+        config.app.dirpath,
+        std::path::PathBuf::from("/tmp/vsmtp/app")
+    );
+}
+
+#[test]
+fn literal_dollar() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.app.dirpath = "/tmp/$vsmtp/${not a variable}/${}/${";
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.app.dirpath,
+        std::path::PathBuf::from("/tmp/$vsmtp/${not a variable}/${}/${")
+    );
+}
+
+#[test]
+fn variable_not_set() {
+    std::env::remove_var("VSMTP_TEST_NOT_SET");
+
+    let error = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.app.dirpath = "${VSMTP_TEST_NOT_SET}/app";
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "In the 'config.app.dirpath' configuration, the environment variable 'VSMTP_TEST_NOT_SET' is not set."
+    );
+}
@@ -0,0 +1,54 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldAppGreylist, Config};
+
+#[test]
+fn default() {
+    assert_eq!(
+        Config::default().app.greylist,
+        FieldAppGreylist {
+            delay: std::time::Duration::from_secs(5 * 60),
+            retry_window: std::time::Duration::from_secs(2 * 24 * 60 * 60),
+            expire: std::time::Duration::from_secs(35 * 24 * 60 * 60),
+            auto_whitelist: Some(5),
+        }
+    );
+}
+
+#[test]
+fn parse() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.app.greylist = #{ delay: "1m", retry_window: "1day", auto_whitelist: () };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.app.greylist,
+        FieldAppGreylist {
+            delay: std::time::Duration::from_secs(60),
+            retry_window: std::time::Duration::from_secs(24 * 60 * 60),
+            auto_whitelist: None,
+            ..FieldAppGreylist::default()
+        }
+    );
+}
@@ -0,0 +1,86 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn none_by_default() {
+    assert!(Config::default().hosted_domains().is_empty());
+}
+
+#[test]
+fn virtual_and_explicit_list_merged() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.virtual["example.com"] = #{};
+    config.server.virtual["mail.example.org"] = #{};
+    config.server.hosted_domains = [
+        "example.net",
+        "Example.com",
+        "example.net",
+    ];
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.hosted_domains(),
+        ["example.com", "example.net", "mail.example.org"]
+    );
+}
+
+#[test]
+fn virtual_domains_lowercased() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.virtual["Example.COM"] = #{};
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.r#virtual.keys().collect::<Vec<_>>(),
+        ["example.com"]
+    );
+}
+
+#[test]
+fn duplicated_virtual_domain_refused() {
+    let error = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.virtual["example.com"] = #{};
+    config.server.virtual["Example.com"] = #{};
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    assert!(
+        format!("{error:#}").contains("is defined multiple times in `server.virtual`"),
+        "{error:#}"
+    );
+}
@@ -0,0 +1,149 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+fn with_interfaces(
+    addr: &str,
+    addr_submission: &str,
+    addr_submissions: &str,
+) -> anyhow::Result<Config> {
+    Config::from_vsl_script(
+        format!(
+            r#"
+fn on_config(config) {{
+    config.server.interfaces = #{{
+        addr: [{addr}],
+        addr_submission: [{addr_submission}],
+        addr_submissions: [{addr_submissions}],
+    }};
+    config
+}}
+"#
+        ),
+        None,
+    )
+}
+
+#[test]
+fn distinct() {
+    with_interfaces(
+        r#""127.0.0.1:25", "[::1]:25", "192.168.1.254:25""#,
+        r#""0.0.0.0:587""#,
+        r#""[::]:465""#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn overlapping() {
+    for (addr, addr_submission, addr_submissions, conflict) in [
+        (
+            r#""127.0.0.1:25", "127.0.0.1:25""#,
+            "",
+            "",
+            "'127.0.0.1:25' (in `addr`) and '127.0.0.1:25' (in `addr`)",
+        ),
+        (
+            r#""127.0.0.1:25""#,
+            r#""127.0.0.1:25""#,
+            "",
+            "'127.0.0.1:25' (in `addr`) and '127.0.0.1:25' (in `addr_submission`)",
+        ),
+        (
+            r#""0.0.0.0:25""#,
+            "",
+            r#""127.0.0.1:25""#,
+            "'0.0.0.0:25' (in `addr`) and '127.0.0.1:25' (in `addr_submissions`)",
+        ),
+        (
+            r#""192.168.1.254:465""#,
+            "",
+            r#""[::]:465""#,
+            "'192.168.1.254:465' (in `addr`) and '[::]:465' (in `addr_submissions`)",
+        ),
+    ] {
+        let error = with_interfaces(addr, addr_submission, addr_submissions)
+            .unwrap_err()
+            .to_string();
+
+        assert_eq!(
+            error,
+            format!("The interfaces {conflict} overlap, they cannot be both listened on")
+        );
+    }
+}
+
+#[test]
+fn log_dialog() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.interfaces = #{
+        addr: ["127.0.0.1:25"],
+        addr_submission: ["127.0.0.1:587"],
+        log_dialog: ["127.0.0.1:587"],
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        config.server.interfaces.log_dialog,
+        Some(vec!["127.0.0.1:587"
+            .parse::<std::net::SocketAddr>()
+            .unwrap()])
+    );
+
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.interfaces = #{
+        addr: ["127.0.0.1:25"],
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+    assert_eq!(config.server.interfaces.log_dialog, None);
+
+    let error = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.interfaces = #{
+        addr: ["127.0.0.1:25"],
+        log_dialog: ["127.0.0.1:587"],
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The interface '127.0.0.1:587' of `log_dialog` is not one of the interfaces"
+    );
+}
+
+#[test]
+fn ipv4_wildcard_does_not_cover_ipv6() {
+    with_interfaces(r#""0.0.0.0:25", "[::1]:25""#, "", "").unwrap();
+}
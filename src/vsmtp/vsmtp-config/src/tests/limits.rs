@@ -0,0 +1,77 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{Config, ScriptLimits};
+
+#[test]
+fn endless_script_aborted() {
+    let error = Config::from_vsl_script_with_limits(
+        r#"
+fn on_config(config) {
+    loop {}
+    config
+}
+"#,
+        None,
+        &ScriptLimits {
+            timeout: std::time::Duration::from_millis(200),
+            ..ScriptLimits::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(
+        error.to_string().contains("it took more than 200ms"),
+        "{error}"
+    );
+}
+
+#[test]
+fn huge_map_aborted() {
+    let error = Config::from_vsl_script_with_limits(
+        r#"
+fn on_config(config) {
+    let map = #{};
+    for i in 0..100 {
+        map[`key${i}`] = i;
+    }
+    config.app.huge = map;
+    config
+}
+"#,
+        None,
+        &ScriptLimits {
+            max_map_size: 50,
+            ..ScriptLimits::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(
+        error.to_string().contains("a map has more than 50 entries"),
+        "{error}"
+    );
+}
+
+#[test]
+fn within_limits() {
+    assert!(Config::from_vsl_script_with_limits(
+        "fn on_config(config) { config }",
+        None,
+        &ScriptLimits::default(),
+    )
+    .is_ok());
+}
@@ -0,0 +1,50 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldQueueDeliveryLocalHeaders, Config};
+
+#[test]
+fn added_by_default() {
+    assert_eq!(
+        Config::default().server.queues.delivery.local_headers,
+        FieldQueueDeliveryLocalHeaders {
+            return_path: true,
+            delivered_to: true,
+        }
+    );
+}
+
+#[test]
+fn parse() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.delivery.local_headers = #{ return_path: false };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.queues.delivery.local_headers,
+        FieldQueueDeliveryLocalHeaders {
+            return_path: false,
+            delivered_to: true,
+        }
+    );
+}
@@ -0,0 +1,68 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldQueueWorkingMimeParseFailure, Config};
+
+#[test]
+fn default() {
+    assert_eq!(
+        Config::default().server.queues.working.mime_parse_failure,
+        FieldQueueWorkingMimeParseFailure::Accept
+    );
+}
+
+#[test]
+fn reject() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.working.mime_parse_failure = #{ policy: "reject" };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.queues.working.mime_parse_failure,
+        FieldQueueWorkingMimeParseFailure::Reject
+    );
+}
+
+#[test]
+fn quarantine() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.working.mime_parse_failure = #{
+        policy: "quarantine",
+        name: "malformed",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.queues.working.mime_parse_failure,
+        FieldQueueWorkingMimeParseFailure::Quarantine {
+            name: "malformed".to_owned()
+        }
+    );
+}
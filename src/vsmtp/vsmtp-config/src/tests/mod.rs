@@ -22,4 +22,33 @@ mod root_example {
     mod tls;
 }
 
+mod backoff;
+mod client_count_max;
+mod dns_resolver;
+mod domain_dir;
+mod engine;
+mod enhanced_codes;
+mod env_interpolation;
+mod greylist;
+mod hosted_domains;
+mod interfaces;
+mod limits;
+mod local_headers;
+mod maildir_quota;
+mod mime_parse_failure;
+mod mta_sts;
+mod mx_cname;
+mod mx_overrides;
+mod ocsp;
+mod opentelemetry;
+mod pool;
+mod rate_limit;
+mod rcpt_lookup;
+mod reader;
+mod scram;
+mod sni;
+mod spool_free_space;
+mod toml;
+mod tracing_directive;
+mod trusted_networks;
 mod validate;
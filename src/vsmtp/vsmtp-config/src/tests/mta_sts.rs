@@ -0,0 +1,38 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn disabled_by_default() {
+    assert!(!Config::default().server.queues.delivery.mta_sts);
+}
+
+#[test]
+fn enabled() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.delivery.mta_sts = true;
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert!(config.server.queues.delivery.mta_sts);
+}
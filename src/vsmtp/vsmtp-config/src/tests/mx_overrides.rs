@@ -0,0 +1,58 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn domains_lowercased() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.mx_overrides = #{ "Foo.BAR": [#{ host: "127.0.0.1", priority: 10 }] };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.mx_overrides.keys().collect::<Vec<_>>(),
+        ["foo.bar"]
+    );
+}
+
+#[test]
+fn duplicated_domain_refused() {
+    let error = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.mx_overrides = #{
+        "foo.bar": [#{ host: "127.0.0.1", priority: 10 }],
+        "FOO.bar": [#{ host: "127.0.0.2", priority: 10 }],
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    assert!(
+        format!("{error:#}").contains("is defined multiple times in `mx_overrides`"),
+        "{error:#}"
+    );
+}
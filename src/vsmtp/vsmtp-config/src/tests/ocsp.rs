@@ -0,0 +1,299 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    field::{FieldServerTls, SecretFile},
+    get_rustls_config, ocsp_refresh_delay,
+    parser::{tls_certificate, tls_private_key},
+    rustls_helper::read_ocsp_response,
+};
+
+const FUTURE: &str = "20991231235959Z";
+const FUTURE_SECS: u64 = 4_102_444_799;
+const PAST: &str = "20000101000000Z";
+
+fn tls_config(ocsp_response: Option<std::path::PathBuf>) -> FieldServerTls {
+    FieldServerTls {
+        preempt_cipherlist: false,
+        handshake_timeout: std::time::Duration::from_millis(200),
+        protocol_version: vec![vsmtp_common::ProtocolVersion(
+            rustls::ProtocolVersion::TLSv1_3,
+        )],
+        cipher_suite: FieldServerTls::default_cipher_suite(),
+        certificate: Some(SecretFile {
+            inner: tls_certificate::from_string(include_str!("sni/default.crt")).unwrap(),
+            path: "in-memory".into(),
+        }),
+        private_key: Some(SecretFile {
+            inner: tls_private_key::from_string(include_str!("sni/default.key")).unwrap(),
+            path: "in-memory".into(),
+        }),
+        ocsp_response,
+        ocsp_refresh_period: FieldServerTls::default_ocsp_refresh_period(),
+        ocsp_max_age: FieldServerTls::default_ocsp_max_age(),
+    }
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match u8::try_from(content.len()) {
+        Ok(length) if length < 0x80 => out.push(length),
+        _ => {
+            out.push(0x82);
+            out.extend(u16::try_from(content.len()).unwrap().to_be_bytes());
+        }
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// A successful `OCSPResponse` with a single response, whose signature is not valid.
+fn ocsp_response(this_update: &str, next_update: Option<&str>) -> Vec<u8> {
+    let mut single = [
+        // certID
+        der(0x30, &der(0x02, &[0x01])),
+        // certStatus: good
+        vec![0x80, 0x00],
+        der(0x18, this_update.as_bytes()),
+    ]
+    .concat();
+    if let Some(next_update) = next_update {
+        single.extend(der(0xa0, &der(0x18, next_update.as_bytes())));
+    }
+
+    let data = [
+        // version
+        der(0xa0, &der(0x02, &[0x00])),
+        // responderID: byKey
+        der(0xa2, &der(0x04, b"responder key hash")),
+        // producedAt
+        der(0x18, this_update.as_bytes()),
+        der(0x30, &der(0x30, &single)),
+    ]
+    .concat();
+    let basic = der(
+        0x30,
+        &[
+            der(0x30, &data),
+            // sha256WithRSAEncryption
+            der(
+                0x30,
+                &der(
+                    0x06,
+                    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b],
+                ),
+            ),
+            der(0x03, &[0x00; 257]),
+        ]
+        .concat(),
+    );
+
+    der(
+        0x30,
+        &[
+            der(0x0a, &[0x00]),
+            der(
+                0xa0,
+                &der(
+                    0x30,
+                    &[
+                        // id-pkix-ocsp-basic
+                        der(
+                            0x06,
+                            &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01],
+                        ),
+                        der(0x04, &basic),
+                    ]
+                    .concat(),
+                ),
+            ),
+        ]
+        .concat(),
+    )
+}
+
+fn temp_ocsp_response(name: &str, content: &[u8]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "vsmtp-config-ocsp-{name}-{}.der",
+        std::process::id()
+    ));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn validity() {
+    let validity = crate::ocsp::validity(&ocsp_response("20230115123045Z", Some(FUTURE))).unwrap();
+    assert_eq!(
+        validity.this_update,
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_673_785_845)
+    );
+    assert_eq!(
+        validity.next_update,
+        Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(FUTURE_SECS))
+    );
+
+    let validity = crate::ocsp::validity(&ocsp_response("20240229000000.5Z", None)).unwrap();
+    assert_eq!(
+        validity.this_update,
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_709_164_800)
+    );
+    assert_eq!(validity.next_update, None);
+
+    // no responseBytes
+    crate::ocsp::validity(b"\x30\x03\x0a\x01\x01").unwrap_err();
+    crate::ocsp::validity(&ocsp_response("20230115123045", None)).unwrap_err();
+    let response = ocsp_response(PAST, Some(FUTURE));
+    crate::ocsp::validity(&response[..response.len() - 1]).unwrap_err();
+}
+
+#[test]
+fn fresh_response_stapled() {
+    let response = ocsp_response(PAST, Some(FUTURE));
+    let path = temp_ocsp_response("fresh", &response);
+    let config = tls_config(Some(path.clone()));
+
+    assert_eq!(read_ocsp_response(&config), Some(response));
+    assert!(get_rustls_config(&config, &std::collections::BTreeMap::new()).is_ok());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn stale_response_ignored() {
+    // a file freshly written does not make the response fresh
+    let path = temp_ocsp_response("stale", &ocsp_response(PAST, Some(PAST)));
+    let config = tls_config(Some(path.clone()));
+
+    assert_eq!(read_ocsp_response(&config), None);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn max_age_without_next_update() {
+    let response = ocsp_response(PAST, None);
+    let path = temp_ocsp_response("max-age", &response);
+    let mut config = tls_config(Some(path.clone()));
+
+    assert_eq!(read_ocsp_response(&config), None);
+
+    config.ocsp_max_age = std::time::Duration::from_secs(200 * 365 * 24 * 60 * 60);
+    assert_eq!(read_ocsp_response(&config), Some(response));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn invalid_response_ignored() {
+    let path = temp_ocsp_response("invalid", b"\x30\x03\x0a\x01\x00");
+    let config = tls_config(Some(path.clone()));
+
+    assert_eq!(read_ocsp_response(&config), None);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn missing_response_ignored() {
+    let config = tls_config(Some("/this/file/does/not/exist.der".into()));
+
+    assert_eq!(read_ocsp_response(&config), None);
+    assert!(get_rustls_config(&config, &std::collections::BTreeMap::new()).is_ok());
+}
+
+#[test]
+fn refresh_before_expiry() {
+    const MINUTE: std::time::Duration = std::time::Duration::from_secs(60);
+
+    let path = temp_ocsp_response("refresh", &ocsp_response(PAST, Some(FUTURE)));
+    let config = tls_config(Some(path.clone()));
+    let next_update = std::time::UNIX_EPOCH + std::time::Duration::from_secs(FUTURE_SECS);
+
+    // far from the expiry, every `ocsp_refresh_period`
+    assert_eq!(
+        ocsp_refresh_delay(&config, next_update - 24 * 60 * MINUTE),
+        config.ocsp_refresh_period
+    );
+    // a few minutes before the expiry
+    assert_eq!(
+        ocsp_refresh_delay(&config, next_update - 30 * MINUTE),
+        25 * MINUTE
+    );
+    // then at the expiry
+    assert_eq!(
+        ocsp_refresh_delay(&config, next_update - 3 * MINUTE),
+        3 * MINUTE
+    );
+    assert_eq!(
+        ocsp_refresh_delay(&config, next_update),
+        config.ocsp_refresh_period
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn refresh_period_zero() {
+    let error = crate::Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.tls = #{
+        protocol_version: ["TLSv1.3"],
+        ocsp_response: "/var/lib/vsmtp/ocsp.der",
+        ocsp_refresh_period: "0s",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    assert!(
+        format!("{error:#}").contains("The OCSP response refresh period cannot be 0"),
+        "{error:#}"
+    );
+}
+
+#[test]
+fn parse_ocsp_options() {
+    let config = crate::Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.tls = #{
+        protocol_version: ["TLSv1.3"],
+        ocsp_response: "/var/lib/vsmtp/ocsp.der",
+        ocsp_refresh_period: "30m",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    let tls = config.server.tls.unwrap();
+    assert_eq!(
+        tls.ocsp_response,
+        Some(std::path::PathBuf::from("/var/lib/vsmtp/ocsp.der"))
+    );
+    assert_eq!(
+        tls.ocsp_refresh_period,
+        std::time::Duration::from_secs(30 * 60)
+    );
+    assert_eq!(tls.ocsp_max_age, FieldServerTls::default_ocsp_max_age());
+}
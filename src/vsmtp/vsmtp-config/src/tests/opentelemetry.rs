@@ -0,0 +1,58 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldServerLogsOpenTelemetry, Config};
+
+#[test]
+fn none_by_default() {
+    assert_eq!(Config::default().server.logs.opentelemetry, None);
+}
+
+#[test]
+fn default_service_name() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.logs.opentelemetry = #{ endpoint: "http://localhost:4318/v1/traces" };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.logs.opentelemetry,
+        Some(FieldServerLogsOpenTelemetry {
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "vsmtp".to_string(),
+        })
+    );
+}
+
+#[test]
+fn unknown_field() {
+    assert!(Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.logs.opentelemetry = #{ endpoint: "http://localhost:4318", protocol: "grpc" };
+    config
+}
+"#,
+        None,
+    )
+    .is_err());
+}
@@ -0,0 +1,55 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldQueueDeliveryPool, Config};
+
+#[test]
+fn default() {
+    assert_eq!(
+        Config::default().server.queues.delivery.pool,
+        FieldQueueDeliveryPool {
+            idle_timeout: std::time::Duration::from_secs(60),
+            max_size: 3,
+            min_idle: 1,
+        }
+    );
+}
+
+#[test]
+fn from_script() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.queues.delivery.pool = #{
+        idle_timeout: "5m",
+        max_size: 0,
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.queues.delivery.pool,
+        FieldQueueDeliveryPool {
+            idle_timeout: std::time::Duration::from_secs(5 * 60),
+            max_size: 0,
+            min_idle: 1,
+        }
+    );
+}
@@ -0,0 +1,88 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldServerRateLimit, Config};
+
+#[test]
+fn none_by_default() {
+    assert_eq!(Config::default().server.rate_limit, None);
+}
+
+#[test]
+fn partial_limits() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.rate_limit = #{ connection_count_max: 5, rcpt_per_hour: 1000 };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.rate_limit,
+        Some(FieldServerRateLimit {
+            connection_count_max: Some(5),
+            message_per_minute: None,
+            rcpt_per_hour: Some(1000),
+            cleanup_period: std::time::Duration::from_secs(5 * 60),
+        })
+    );
+}
+
+#[test]
+fn cleanup_period() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.rate_limit = #{ message_per_minute: 10, cleanup_period: "30s" };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.server.rate_limit.unwrap().cleanup_period,
+        std::time::Duration::from_secs(30)
+    );
+}
+
+#[test]
+fn cleanup_period_too_small() {
+    for cleanup_period in ["0s", "500ms"] {
+        let error = Config::from_vsl_script(
+            &format!(
+                r#"
+fn on_config(config) {{
+    config.server.rate_limit = #{{ message_per_minute: 10, cleanup_period: "{cleanup_period}" }};
+    config
+}}
+"#
+            ),
+            None,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{error:#}").contains("The rate limit cleanup period must be at least 1s"),
+            "{error:#}"
+        );
+    }
+}
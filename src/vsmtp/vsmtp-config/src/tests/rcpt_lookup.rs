@@ -0,0 +1,83 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldAppLookup, Config};
+
+#[test]
+fn default() {
+    assert_eq!(Config::default().app.rcpt_lookup, None);
+}
+
+#[test]
+fn parse_file() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.app.rcpt_lookup = #{ type: "file", path: "/etc/vsmtp/users" };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.app.rcpt_lookup,
+        Some(FieldAppLookup::File {
+            path: "/etc/vsmtp/users".into()
+        })
+    );
+}
+
+#[test]
+fn parse_sqlite() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.app.rcpt_lookup = #{
+        type: "sqlite",
+        path: "/var/lib/vsmtp/users.db",
+        query: "SELECT 1 FROM users WHERE address = ?1",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.app.rcpt_lookup,
+        Some(FieldAppLookup::Sqlite {
+            path: "/var/lib/vsmtp/users.db".into(),
+            query: "SELECT 1 FROM users WHERE address = ?1".to_string(),
+        })
+    );
+}
+
+#[test]
+fn sqlite_without_query() {
+    Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.app.rcpt_lookup = #{ type: "sqlite", path: "/var/lib/vsmtp/users.db" };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap_err();
+}
@@ -0,0 +1,43 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn from_in_memory_reader() {
+    let path_to_config = std::path::PathBuf::from_iter([
+        env!("CARGO_MANIFEST_DIR"),
+        "../../../examples/config/simple.vsl",
+    ]);
+    let script = std::fs::read(&path_to_config).unwrap();
+
+    let mut expected = Config::from_vsl_file(&path_to_config).unwrap();
+    expected.path = None;
+
+    pretty_assertions::assert_eq!(
+        Config::from_vsl_reader(
+            std::io::Cursor::new(script),
+            Some(path_to_config.parent().unwrap())
+        )
+        .unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn from_invalid_utf8_reader() {
+    assert!(Config::from_vsl_reader(&[0xff, 0xfe, 0xfd][..], None).is_err());
+}
@@ -15,7 +15,11 @@
  *
 */
 use crate::{
-    config::field::{FieldQueueDelivery, FieldQueueWorking},
+    config::field::{
+        FieldQueueDelivery, FieldQueueDeliveryBackoff, FieldQueueDeliveryLocalHeaders,
+        FieldQueueDeliveryMxCname, FieldQueueDeliveryPool, FieldQueueDeliveryXVsmtp,
+        FieldQueueWorking, FieldQueueWorkingMimeParseFailure,
+    },
     Config,
 };
 use vsmtp_common::{collection, Stage};
@@ -39,11 +43,35 @@ fn parse() {
             .with_default_logs_settings()
             .with_spool_dir_and_queues(
                 "/var/spool/vsmtp",
-                FieldQueueWorking { channel_size: 16 },
+                FieldQueueWorking {
+                    channel_size: 16,
+                    processing_count_max: None,
+                    mime_parse_failure: FieldQueueWorkingMimeParseFailure::Accept,
+                },
                 FieldQueueDelivery {
                     channel_size: 16,
                     deferred_retry_max: 10,
-                    deferred_retry_period: std::time::Duration::from_secs(600)
+                    deferred_retry_period: std::time::Duration::from_secs(600),
+                    deferred_retry_backoff: FieldQueueDeliveryBackoff::default(),
+                    dead_fallback_mailbox: None,
+                    connect_retry_max: 0,
+                    connect_retry_delay: std::time::Duration::from_secs(1),
+                    capture_transcript: false,
+                    pool: FieldQueueDeliveryPool::default(),
+                    in_flight_max: None,
+                    in_flight_per_domain_max: None,
+                    rcpt_per_transaction_max: None,
+                    mx_max: None,
+                    time_budget: None,
+                    nxdomain_cache: None,
+                    mx_cname: FieldQueueDeliveryMxCname::Follow,
+                    no_route_permanent: true,
+                    dane_required: std::collections::BTreeSet::new(),
+                    mta_sts: false,
+                    x_vsmtp_header: FieldQueueDeliveryXVsmtp::Always,
+                    maildir_quota: None,
+                    mbox_lock_timeout: std::time::Duration::from_secs(30),
+                    local_headers: FieldQueueDeliveryLocalHeaders::default(),
                 }
             )
             .without_tls_support()
@@ -0,0 +1,61 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+use vsmtp_common::auth::Mechanism;
+
+#[test]
+fn scram_credentials() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.smtp.auth = #{
+        mechanisms: ["SCRAM-SHA-256", "SCRAM-SHA-1"],
+        scram_credentials: "/etc/vsmtp/scram_credentials",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    let auth = config.server.smtp.auth.unwrap();
+    assert_eq!(
+        auth.mechanisms,
+        vec![Mechanism::ScramSha256, Mechanism::ScramSha1]
+    );
+    assert_eq!(
+        auth.scram_credentials,
+        Some("/etc/vsmtp/scram_credentials".into())
+    );
+}
+
+#[test]
+fn scram_without_credentials() {
+    assert!(Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.smtp.auth = #{
+        mechanisms: ["PLAIN", "SCRAM-SHA-256"],
+    };
+    config
+}
+"#,
+        None,
+    )
+    .is_err());
+}
@@ -0,0 +1,179 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    field::{FieldServerTls, FieldServerVirtual, FieldServerVirtualTls, SecretFile},
+    get_rustls_config,
+    parser::{tls_certificate, tls_private_key},
+};
+
+const CA: &str = include_str!("sni/ca.crt");
+const DEFAULT: (&str, &str) = (
+    include_str!("sni/default.crt"),
+    include_str!("sni/default.key"),
+);
+const A: (&str, &str) = (include_str!("sni/a.crt"), include_str!("sni/a.key"));
+const B: (&str, &str) = (include_str!("sni/b.crt"), include_str!("sni/b.key"));
+
+fn certificate(pem: &str) -> SecretFile<Vec<rustls::Certificate>> {
+    SecretFile {
+        inner: tls_certificate::from_string(pem).unwrap(),
+        path: "in-memory".into(),
+    }
+}
+
+fn private_key(pem: &str) -> SecretFile<rustls::PrivateKey> {
+    SecretFile {
+        inner: tls_private_key::from_string(pem).unwrap(),
+        path: "in-memory".into(),
+    }
+}
+
+fn server_config(with_default: bool) -> std::sync::Arc<rustls::ServerConfig> {
+    let tls = FieldServerTls {
+        preempt_cipherlist: false,
+        handshake_timeout: std::time::Duration::from_millis(200),
+        protocol_version: vec![vsmtp_common::ProtocolVersion(
+            rustls::ProtocolVersion::TLSv1_3,
+        )],
+        cipher_suite: FieldServerTls::default_cipher_suite(),
+        certificate: with_default.then(|| certificate(DEFAULT.0)),
+        private_key: with_default.then(|| private_key(DEFAULT.1)),
+        ocsp_response: None,
+        ocsp_refresh_period: FieldServerTls::default_ocsp_refresh_period(),
+        ocsp_max_age: FieldServerTls::default_ocsp_max_age(),
+    };
+
+    let virtual_entries = [("a.example.com", A), ("B.example.com", B)]
+        .into_iter()
+        .map(|(name, (crt, key))| {
+            (
+                name.to_string(),
+                FieldServerVirtual {
+                    tls: Some(FieldServerVirtualTls {
+                        protocol_version: tls.protocol_version.clone(),
+                        certificate: certificate(crt),
+                        private_key: private_key(key),
+                    }),
+                    dns: None,
+                    dkim: None,
+                    rcpt_count_max: None,
+                    message_size_limit: None,
+                    codes: std::collections::BTreeMap::new(),
+                },
+            )
+        })
+        .collect();
+
+    std::sync::Arc::new(get_rustls_config(&tls, &virtual_entries).unwrap())
+}
+
+fn transfer(from: &mut rustls::Connection, to: &mut rustls::Connection) -> Option<()> {
+    let mut buffer = vec![];
+    while from.wants_write() {
+        from.write_tls(&mut buffer).unwrap();
+    }
+    let mut reader = buffer.as_slice();
+    while !reader.is_empty() {
+        to.read_tls(&mut reader).unwrap();
+        to.process_new_packets().ok()?;
+    }
+    Some(())
+}
+
+/// Run a TLS handshake in memory and return the certificate chain served for `server_name`,
+/// or `None` if the handshake failed.
+fn served_chain(
+    server_config: std::sync::Arc<rustls::ServerConfig>,
+    server_name: &str,
+) -> Option<Vec<rustls::Certificate>> {
+    let mut root_store = rustls::RootCertStore::empty();
+    for ca in tls_certificate::from_string(CA).unwrap() {
+        root_store.add(&ca).unwrap();
+    }
+
+    let client_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    let mut client = rustls::Connection::from(
+        rustls::ClientConnection::new(
+            std::sync::Arc::new(client_config),
+            server_name.try_into().unwrap(),
+        )
+        .unwrap(),
+    );
+    let mut server =
+        rustls::Connection::from(rustls::ServerConnection::new(server_config).unwrap());
+
+    while client.is_handshaking() || server.is_handshaking() {
+        if !client.wants_write() && !server.wants_write() {
+            return None;
+        }
+        transfer(&mut client, &mut server)?;
+        transfer(&mut server, &mut client)?;
+    }
+
+    client.peer_certificates().map(<[_]>::to_vec)
+}
+
+#[test]
+fn certificate_per_sni() {
+    let server_config = server_config(true);
+
+    assert_eq!(
+        served_chain(server_config.clone(), "a.example.com"),
+        Some(tls_certificate::from_string(A.0).unwrap())
+    );
+    assert_eq!(
+        served_chain(server_config.clone(), "b.example.com"),
+        Some(tls_certificate::from_string(B.0).unwrap())
+    );
+    assert_eq!(
+        served_chain(server_config, "mail.example.com"),
+        Some(tls_certificate::from_string(DEFAULT.0).unwrap())
+    );
+}
+
+#[test]
+fn no_default_certificate() {
+    let server_config = server_config(false);
+
+    assert_eq!(
+        served_chain(server_config.clone(), "a.example.com"),
+        Some(tls_certificate::from_string(A.0).unwrap())
+    );
+    // the server has no certificate to serve, and aborts the handshake
+    assert_eq!(served_chain(server_config, "mail.example.com"), None);
+}
+
+#[test]
+fn default_certificate_and_key_together() {
+    assert!(crate::Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.tls = #{
+        protocol_version: ["TLSv1.3"],
+        certificate: "./src/tests/sni/default.crt",
+    };
+    config
+}
+"#,
+        None,
+    )
+    .is_err());
+}
@@ -0,0 +1,56 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+fn config_with_min_free_percent(percent: u8) -> anyhow::Result<Config> {
+    Config::from_vsl_script(
+        format!(
+            r#"
+fn on_config(config) {{
+    config.server.queues.min_free_space = #{{ percent: {percent} }};
+    config
+}}
+"#
+        ),
+        None,
+    )
+}
+
+#[test]
+fn percent() {
+    assert_eq!(
+        config_with_min_free_percent(100)
+            .unwrap()
+            .server
+            .queues
+            .min_free_space
+            .unwrap()
+            .percent,
+        100
+    );
+}
+
+#[test]
+fn percent_above_100_refused() {
+    let error = config_with_min_free_percent(101).unwrap_err();
+
+    assert!(
+        format!("{error:#}")
+            .contains("The minimum percentage of free space of the spool cannot exceed 100"),
+        "{error:#}"
+    );
+}
@@ -0,0 +1,82 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+#[test]
+fn same_as_vsl() {
+    let examples =
+        std::path::PathBuf::from_iter([env!("CARGO_MANIFEST_DIR"), "../../../examples/config"]);
+
+    let mut expected = Config::from_vsl_file(examples.join("simple.vsl")).unwrap();
+    expected.path = None;
+
+    let mut config = Config::from_toml_file(examples.join("simple.toml")).unwrap();
+    assert_eq!(config.path, Some(examples.join("simple.toml")));
+    config.path = None;
+
+    pretty_assertions::assert_eq!(config, expected);
+}
+
+#[test]
+fn unknown_field() {
+    let error = Config::from_toml_str(
+        r#"
+version_requirement = ">=2.0.0, <3.0.0"
+
+[server.smtp]
+rcpt_count_max = 25
+not_a_field = true
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    let error = error.to_string();
+    assert!(error.contains("config.server.smtp"), "{error}");
+    assert!(error.contains("not_a_field"), "{error}");
+}
+
+#[test]
+fn invalid_type() {
+    let error = Config::from_toml_str(
+        r#"
+version_requirement = ">=2.0.0, <3.0.0"
+
+[server]
+message_size_limit = "not a number"
+"#,
+        None,
+    )
+    .unwrap_err();
+
+    assert!(
+        error
+            .to_string()
+            .contains("config.server.message_size_limit"),
+        "{error}"
+    );
+}
+
+#[test]
+fn version_requirement_not_fulfilled() {
+    assert!(Config::from_toml_str(r#"version_requirement = "<1.0.0""#, None).is_err());
+}
+
+#[test]
+fn not_toml() {
+    assert!(Config::from_toml_str("fn on_config(config) { config }", None).is_err());
+}
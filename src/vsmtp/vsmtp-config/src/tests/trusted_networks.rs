@@ -0,0 +1,93 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+
+fn is_trusted(config: &Config, ip: &str) -> bool {
+    config.server.is_trusted(ip.parse().unwrap())
+}
+
+#[test]
+fn nothing_trusted_by_default() {
+    let config = Config::default();
+
+    assert!(config.server.trusted_networks.is_empty());
+    assert!(!is_trusted(&config, "127.0.0.1"));
+    assert!(!is_trusted(&config, "::1"));
+}
+
+#[test]
+fn ipv4_and_ipv6() {
+    let config = Config::from_vsl_script(
+        r#"
+fn on_config(config) {
+    config.server.trusted_networks = [
+        "127.0.0.1/32",
+        "192.168.0.0/16",
+        "10.0.0.0/8",
+        "::1/128",
+        "2001:db8::/32",
+    ];
+    config
+}
+"#,
+        None,
+    )
+    .unwrap();
+
+    for trusted in [
+        "127.0.0.1",
+        "192.168.0.1",
+        "192.168.255.254",
+        "10.12.34.56",
+        "::1",
+        "2001:db8::1",
+        "2001:db8:ffff::25",
+        "::ffff:192.168.1.1",
+    ] {
+        assert!(is_trusted(&config, trusted), "{trusted}");
+    }
+
+    for untrusted in [
+        "127.0.0.2",
+        "192.169.0.1",
+        "11.0.0.1",
+        "8.8.8.8",
+        "::2",
+        "2001:db9::1",
+        "::ffff:8.8.8.8",
+    ] {
+        assert!(!is_trusted(&config, untrusted), "{untrusted}");
+    }
+}
+
+#[test]
+fn invalid_network() {
+    for invalid in [r#""192.168.0.0/33""#, r#""not a network""#, r#""10.0.0.1""#] {
+        assert!(Config::from_vsl_script(
+            format!(
+                r#"
+fn on_config(config) {{
+    config.server.trusted_networks = [{invalid}];
+    config
+}}
+"#
+            ),
+            None,
+        )
+        .is_err());
+    }
+}
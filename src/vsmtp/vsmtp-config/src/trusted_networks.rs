@@ -0,0 +1,34 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::field::FieldServer;
+
+impl FieldServer {
+    /// Is `ip` part of the [`FieldServer::trusted_networks`].
+    ///
+    /// An IPv4-mapped IPv6 address (`::ffff:192.168.1.1`) is matched against the IPv4 networks.
+    #[must_use]
+    pub fn is_trusted(&self, ip: std::net::IpAddr) -> bool {
+        let ip = match ip {
+            std::net::IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, std::net::IpAddr::V4),
+            std::net::IpAddr::V4(_) => ip,
+        };
+
+        self.trusted_networks
+            .iter()
+            .any(|network| network.contains(&ip))
+    }
+}
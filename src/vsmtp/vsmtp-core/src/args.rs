@@ -15,6 +15,8 @@
  *
 */
 
+use anyhow::Context;
+
 ///
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Timeout(pub std::time::Duration);
@@ -37,7 +39,7 @@ pub struct Args {
     #[clap(short, long, action)]
     pub version: bool,
 
-    /// Path of the vSMTP configuration file. (toml format)
+    /// Path of the vSMTP configuration file. (vsl script, or toml format with the `.toml` extension)
     #[clap(short, long, action)]
     pub config: Option<String>,
 
@@ -58,6 +60,31 @@ pub struct Args {
     pub timeout: Option<Timeout>,
 }
 
+impl Args {
+    /// Load the configuration file given with `--config`, as toml if its extension
+    /// is `.toml` and as a vsl script otherwise, or the default configuration.
+    ///
+    /// # Errors
+    ///
+    /// * The configuration file cannot be read or is invalid.
+    pub fn load_config(&self) -> anyhow::Result<vsmtp_config::Config> {
+        let path = match &self.config {
+            Some(path) => std::path::Path::new(path),
+            None => return Ok(vsmtp_config::Config::default()),
+        };
+
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "toml")
+        {
+            vsmtp_config::Config::from_toml_file(path)
+        } else {
+            vsmtp_config::Config::from_vsl_file(path)
+        }
+        .context("Cannot parse the configuration")
+    }
+}
+
 ///
 #[derive(Debug, clap::Subcommand, PartialEq, Eq)]
 pub enum Commands {
@@ -157,4 +184,36 @@ mod tests {
             .unwrap()
         );
     }
+
+    #[test]
+    fn load_config() {
+        let examples =
+            std::path::PathBuf::from_iter([env!("CARGO_MANIFEST_DIR"), "../../../examples/config"]);
+        let load = |file: &str| {
+            <Args as clap::Parser>::try_parse_from([
+                "",
+                "-c",
+                examples.join(file).to_str().unwrap(),
+                "config-show",
+            ])
+            .unwrap()
+            .load_config()
+            .unwrap()
+        };
+
+        let mut toml = load("simple.toml");
+        assert_eq!(toml.path, Some(examples.join("simple.toml")));
+        toml.path = None;
+        let mut vsl = load("simple.vsl");
+        vsl.path = None;
+        assert_eq!(toml, vsl);
+
+        assert_eq!(
+            <Args as clap::Parser>::try_parse_from([""])
+                .unwrap()
+                .load_config()
+                .unwrap(),
+            vsmtp_config::Config::default()
+        );
+    }
 }
@@ -14,7 +14,6 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
  */
-use anyhow::Context;
 use clap::{crate_name, crate_version};
 use vsmtp::{Args, Commands};
 use vsmtp_common::libc_abstraction::{daemon, initgroups};
@@ -57,10 +56,7 @@ fn try_main() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let config = args.config.as_ref().map_or_else(
-        || Ok(Config::default()),
-        |path| Config::from_vsl_file(path).context("Cannot parse the configuration"),
-    )?;
+    let config = args.load_config()?;
 
     if let Some(command) = args.command {
         match command {
@@ -122,5 +118,10 @@ fn try_main() -> anyhow::Result<()> {
         // setuid(config.server.system.user.uid())?;
     }
 
-    start_runtime(config, sockets, args.timeout.map(|t| t.0))
+    vsmtp::tracing_subscriber::start_opentelemetry(&config)?;
+    vsmtp::tracing_subscriber::start_admin_socket(&config)?;
+
+    let output = start_runtime(config, sockets, args.timeout.map(|t| t.0));
+    vsmtp::tracing_subscriber::shutdown_opentelemetry();
+    output
 }
@@ -19,6 +19,46 @@ use vsmtp_common::collection;
 use vsmtp_config::field::{FieldServerLogSystem, SyslogFormat, SyslogSocket};
 use vsmtp_config::Config;
 
+mod admin_socket;
+
+pub use admin_socket::start_admin_socket;
+
+/// The subscriber filtered by the log level.
+type Filtered = tracing_subscriber::layer::Layered<
+    tracing_subscriber::reload::Layer<tracing_subscriber::EnvFilter, tracing_subscriber::Registry>,
+    tracing_subscriber::Registry,
+>;
+
+/// Handle used to change the log level at runtime, see [`start_admin_socket`].
+static FILTER: std::sync::Mutex<
+    Option<
+        tracing_subscriber::reload::Handle<
+            tracing_subscriber::EnvFilter,
+            tracing_subscriber::Registry,
+        >,
+    >,
+> = std::sync::Mutex::new(None);
+
+#[cfg(feature = "opentelemetry")]
+type OpenTelemetryLayer =
+    Option<tracing_opentelemetry::OpenTelemetryLayer<Filtered, opentelemetry::sdk::trace::Tracer>>;
+
+/// Handle used to install the OpenTelemetry layer once the process has been daemonized,
+/// as the exporter's background thread would not survive the fork.
+#[cfg(feature = "opentelemetry")]
+static OPENTELEMETRY: std::sync::Mutex<
+    Option<tracing_subscriber::reload::Handle<OpenTelemetryLayer, Filtered>>,
+> = std::sync::Mutex::new(None);
+
+fn env_filter(
+    directives: &[tracing_subscriber::filter::Directive],
+) -> tracing_subscriber::EnvFilter {
+    directives.iter().fold(
+        tracing_subscriber::EnvFilter::default(),
+        |filter, directive| filter.add_directive(directive.clone()),
+    )
+}
+
 struct SyslogWriter {
     logger: either::Either<
         syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>,
@@ -213,13 +253,19 @@ pub fn initialize(args: &Args, config: &Config) -> anyhow::Result<()> {
     let writer_app = writer_app
         .with_filter(|metadata| metadata.target() == "vsmtp_rule_engine::api::logging::logging");
 
-    let subscriber = tracing_subscriber::registry().with({
-        let mut e = tracing_subscriber::EnvFilter::default();
-        for i in &config.server.logs.level {
-            e = e.add_directive(i.clone());
-        }
-        e
-    });
+    let subscriber: Filtered = {
+        let (layer, handle) =
+            tracing_subscriber::reload::Layer::new(env_filter(&config.server.logs.level));
+        *FILTER.lock().map_err(|e| anyhow::anyhow!("{e}"))? = Some(handle);
+        tracing_subscriber::registry().with(layer)
+    };
+
+    #[cfg(feature = "opentelemetry")]
+    let subscriber = {
+        let (layer, handle) = tracing_subscriber::reload::Layer::new(None);
+        *OPENTELEMETRY.lock().map_err(|e| anyhow::anyhow!("{e}"))? = Some(handle);
+        subscriber.with(layer)
+    };
 
     #[cfg(feature = "tokio_console")]
     let subscriber = subscriber.with(console_subscriber::spawn());
@@ -254,11 +300,12 @@ pub fn initialize(args: &Args, config: &Config) -> anyhow::Result<()> {
                     subscriber.try_init()
                 }
             }
+            #[cfg(target_os = "linux")]
             FieldServerLogSystem::Journald { level } => {
                 let level = *level;
                 let subscriber = subscriber.with(
                     tracing_journald::layer()
-                        .map_err(|e| anyhow::anyhow!("{e}"))?
+                        .map_err(|e| anyhow::anyhow!("cannot connect to journald: {e}"))?
                         .with_filter(tracing_subscriber::filter::filter_fn(move |i| {
                             *i.level() <= level
                         })),
@@ -272,6 +319,10 @@ pub fn initialize(args: &Args, config: &Config) -> anyhow::Result<()> {
                     subscriber.try_init()
                 }
             }
+            #[cfg(not(target_os = "linux"))]
+            FieldServerLogSystem::Journald { .. } => {
+                anyhow::bail!("the `journald` backend is only available on Linux")
+            }
         }
     } else if args.stdout {
         subscriber
@@ -282,3 +333,70 @@ pub fn initialize(args: &Args, config: &Config) -> anyhow::Result<()> {
     }
     .map_err(|e| anyhow::anyhow!("{e}"))
 }
+
+/// Start exporting the spans to the OpenTelemetry collector of `server.logs.opentelemetry`,
+/// does nothing if it is not configured.
+///
+/// Must be called after [`initialize`] and after the process has been daemonized.
+///
+/// # Errors
+///
+/// * Failed to build the OTLP exporter.
+#[cfg(feature = "opentelemetry")]
+pub fn start_opentelemetry(config: &Config) -> anyhow::Result<()> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let otel = match &config.server.logs.opentelemetry {
+        Some(otel) => otel,
+        None => return Ok(()),
+    };
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(&otel.endpoint),
+        )
+        .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
+            opentelemetry::sdk::Resource::new([opentelemetry::KeyValue::new(
+                "service.name",
+                otel.service_name.clone(),
+            )]),
+        ))
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)?;
+
+    OPENTELEMETRY
+        .lock()
+        .map_err(|e| anyhow::anyhow!("{e}"))?
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("tracing subsystem is not initialized"))?
+        .reload(Some(tracing_opentelemetry::layer().with_tracer(tracer)))?;
+
+    tracing::info!(endpoint = %otel.endpoint, "Exporting spans to OpenTelemetry");
+
+    Ok(())
+}
+
+/// Start exporting the spans to the OpenTelemetry collector of `server.logs.opentelemetry`,
+/// does nothing if it is not configured.
+///
+/// # Errors
+///
+/// * Never, vSMTP has been built without the `opentelemetry` feature.
+#[cfg(not(feature = "opentelemetry"))]
+pub fn start_opentelemetry(config: &Config) -> anyhow::Result<()> {
+    if config.server.logs.opentelemetry.is_some() {
+        tracing::warn!(
+            "`server.logs.opentelemetry` is set but vSMTP has been built without the `opentelemetry` feature"
+        );
+    }
+    Ok(())
+}
+
+/// Flush the pending spans to the OpenTelemetry collector.
+#[allow(clippy::missing_const_for_fn)]
+pub fn shutdown_opentelemetry() {
+    #[cfg(feature = "opentelemetry")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -0,0 +1,181 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+use super::{env_filter, FILTER};
+use std::io::{BufRead, Read, Write};
+use vsmtp_config::Config;
+
+/// Maximum length of a command, the connection is closed if it is longer.
+const LINE_SIZE_MAX: usize = 4096;
+
+/// Listen on `server.logs.admin_socket` to change the log level at runtime,
+/// does nothing if it is not configured.
+///
+/// Each line received is either a comma separated list of directives, replacing
+/// the log level (ex: `warn,vsmtp_delivery=trace`), or `reset` to restore `server.logs.level`.
+/// The reply is `OK`, or `ERR <reason>` if the directives are malformed.
+/// Each client is served on its own thread.
+///
+/// Must be called after [`super::initialize`] and after the process has been daemonized.
+///
+/// # Errors
+///
+/// * Failed to bind the socket.
+pub fn start_admin_socket(config: &Config) -> anyhow::Result<()> {
+    let path = match &config.server.logs.admin_socket {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let listener = vsmtp_server::unix_socket_bind_anyhow(path)?;
+
+    let default_level = std::sync::Arc::new(config.server.logs.level.clone());
+    std::thread::Builder::new()
+        .name("admin-socket".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        tracing::warn!(%error, "Admin socket connection failed.");
+                        continue;
+                    }
+                };
+                let default_level = default_level.clone();
+                if let Err(error) = std::thread::Builder::new()
+                    .name("admin-client".to_string())
+                    .spawn(move || {
+                        if let Err(error) = handle(&stream, &default_level) {
+                            tracing::warn!(%error, "Admin socket connection failed.");
+                        }
+                    })
+                {
+                    tracing::warn!(%error, "Admin socket client thread failed to start.");
+                }
+            }
+        })?;
+
+    tracing::info!(path = %path.display(), "Admin socket listening.");
+    Ok(())
+}
+
+fn handle(
+    stream: &std::os::unix::net::UnixStream,
+    default_level: &[tracing_subscriber::filter::Directive],
+) -> std::io::Result<()> {
+    let mut reader = std::io::BufReader::new(stream);
+    let mut writer = stream;
+
+    let mut line = String::new();
+    while (&mut reader)
+        .take(LINE_SIZE_MAX as u64)
+        .read_line(&mut line)?
+        != 0
+    {
+        if line.len() >= LINE_SIZE_MAX && !line.ends_with('\n') {
+            writer.write_all(b"ERR line too long\n")?;
+            return Ok(());
+        }
+
+        let reply = match apply(line.trim(), default_level) {
+            Ok(()) => "OK\n".to_string(),
+            Err(error) => format!("ERR {error}\n"),
+        };
+        writer.write_all(reply.as_bytes())?;
+        line.clear();
+    }
+    Ok(())
+}
+
+fn apply(
+    command: &str,
+    default_level: &[tracing_subscriber::filter::Directive],
+) -> anyhow::Result<()> {
+    let directives = if command == "reset" {
+        default_level.to_vec()
+    } else {
+        vsmtp_config::parse_tracing_directives(command)
+            .map_err(|e| anyhow::anyhow!("invalid directive: `{e}`"))?
+    };
+    anyhow::ensure!(!directives.is_empty(), "no directive");
+
+    FILTER
+        .lock()
+        .map_err(|e| anyhow::anyhow!("{e}"))?
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("tracing subsystem is not initialized"))?
+        .reload(env_filter(&directives))?;
+
+    tracing::warn!(level = command, "Log level changed.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{env_filter, handle, FILTER};
+    use std::io::{Read, Write};
+
+    fn reply_of(command: &str) -> String {
+        let (mut client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+        client.write_all(command.as_bytes()).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        handle(&server, &["info".parse().unwrap()]).unwrap();
+        drop(server);
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        reply
+    }
+
+    #[test]
+    fn malformed_directive() {
+        assert!(reply_of("vsmtp_delivery=foo\n").starts_with("ERR invalid directive: `"));
+    }
+
+    #[test]
+    fn empty_directive() {
+        assert_eq!(reply_of("\n"), "ERR no directive\n");
+    }
+
+    #[test]
+    fn line_too_long() {
+        assert_eq!(
+            reply_of(&format!("{}\nwarn\n", "a".repeat(5000))),
+            "ERR line too long\n"
+        );
+    }
+
+    #[test]
+    fn reload() {
+        let (layer, reload_handle) = tracing_subscriber::reload::Layer::new(env_filter(&[]));
+        let _subscriber =
+            tracing_subscriber::layer::SubscriberExt::with(tracing_subscriber::registry(), layer);
+        *FILTER.lock().unwrap() = Some(reload_handle.clone());
+
+        assert_eq!(reply_of("vsmtp_delivery=trace\n"), "OK\n");
+        assert_eq!(
+            reload_handle.with_current(ToString::to_string).unwrap(),
+            "vsmtp_delivery=trace"
+        );
+
+        assert_eq!(reply_of("reset\n"), "OK\n");
+        assert_eq!(
+            reload_handle.with_current(ToString::to_string).unwrap(),
+            "info"
+        );
+    }
+}
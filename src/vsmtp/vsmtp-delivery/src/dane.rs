@@ -0,0 +1,318 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+
+//! DNS-Based Authentication of Named Entities for SMTP (RFC 7672).
+
+use trust_dns_resolver::proto::rr::{
+    rdata::tlsa::{CertUsage, Matching, Selector},
+    RData, RecordType,
+};
+
+/// A TLSA record usable to authenticate a SMTP server.
+///
+/// Only the `DANE-EE(3)` usage is supported, the certificate of the server being pinned
+/// regardless of its issuer, names and validity (RFC 7672 3.1.1). The `PKIX-*` usages are
+/// unusable for SMTP (RFC 7672 3.1.3), and `DANE-TA(2)` requires the chain of the server
+/// which is not available.
+#[non_exhaustive]
+#[derive(Debug, Eq, Clone, Hash, PartialEq)]
+pub struct Tlsa {
+    /// The public key (`true`) or the full certificate (`false`) is matched.
+    pub spki: bool,
+    /// The hash algorithm of `data`, none if the content is compared as is.
+    pub matching: TlsaMatching,
+    /// Certificate association data.
+    pub data: Vec<u8>,
+}
+
+/// The matching type of a [`Tlsa`] record.
+#[non_exhaustive]
+#[derive(Debug, Eq, Clone, Copy, Hash, PartialEq)]
+pub enum TlsaMatching {
+    /// Exact match.
+    Raw,
+    /// SHA-256 hash.
+    Sha256,
+    /// SHA-512 hash.
+    Sha512,
+}
+
+impl Tlsa {
+    /// Convert the record if usable.
+    #[must_use]
+    #[inline]
+    pub fn from_rdata(rdata: &trust_dns_resolver::proto::rr::rdata::TLSA) -> Option<Self> {
+        if rdata.cert_usage() != CertUsage::DomainIssued {
+            return None;
+        }
+
+        Some(Self {
+            spki: match rdata.selector() {
+                Selector::Full => false,
+                Selector::Spki => true,
+                Selector::Unassigned(_) | Selector::Private => return None,
+            },
+            matching: match rdata.matching() {
+                Matching::Raw => TlsaMatching::Raw,
+                Matching::Sha256 => TlsaMatching::Sha256,
+                Matching::Sha512 => TlsaMatching::Sha512,
+                Matching::Unassigned(_) | Matching::Private => return None,
+            },
+            data: rdata.cert_data().to_vec(),
+        })
+    }
+
+    /// Does the certificate (DER encoded) of the server match the record.
+    #[must_use]
+    #[inline]
+    pub fn matches(&self, certificate: &[u8]) -> bool {
+        let content = if self.spki {
+            match subject_public_key_info(certificate) {
+                Some(spki) => spki,
+                None => return false,
+            }
+        } else {
+            certificate
+        };
+
+        match self.matching {
+            TlsaMatching::Raw => content == self.data.as_slice(),
+            TlsaMatching::Sha256 => {
+                <sha2::Sha256 as sha2::Digest>::digest(content).as_slice() == self.data.as_slice()
+            }
+            TlsaMatching::Sha512 => {
+                <sha2::Sha512 as sha2::Digest>::digest(content).as_slice() == self.data.as_slice()
+            }
+        }
+    }
+}
+
+/// The certificate of the server does not match any of its TLSA records.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TlsaMismatch {
+    /// The server authenticated.
+    pub host: String,
+}
+
+impl core::fmt::Display for TlsaMismatch {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the certificate of '{}' does not match its TLSA records",
+            self.host
+        )
+    }
+}
+
+impl std::error::Error for TlsaMismatch {}
+
+/// Fetch the usable TLSA records of the smtp server `host:port`.
+///
+/// # Errors
+///
+/// * the dns query failed, no records being not an error.
+#[inline]
+pub async fn lookup_tlsa(
+    resolver: &trust_dns_resolver::TokioAsyncResolver,
+    host: &str,
+    port: u16,
+) -> Result<Vec<Tlsa>, trust_dns_resolver::error::ResolveError> {
+    let records = match resolver
+        .lookup(
+            format!("_{port}._tcp.{}", host.trim_end_matches('.')),
+            RecordType::TLSA,
+        )
+        .await
+    {
+        Ok(records) => records,
+        Err(error)
+            if matches!(
+                error.kind(),
+                trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. }
+            ) =>
+        {
+            return Ok(vec![]);
+        }
+        Err(error) => return Err(error),
+    };
+
+    Ok(records
+        .iter()
+        .filter_map(|rdata| match rdata {
+            RData::TLSA(tlsa) => Tlsa::from_rdata(tlsa),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Split the DER element at the beginning of `input`, returns its tag,
+/// the element (header included), its content and the remaining bytes.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8], &[u8])> {
+    let (tag, first_length) = (*input.first()?, *input.get(1)?);
+
+    let (header_length, content_length) = if first_length < 0x80 {
+        (2, usize::from(first_length))
+    } else {
+        let count = usize::from(first_length & 0x7f);
+        if count == 0 || count > core::mem::size_of::<usize>() {
+            return None;
+        }
+        let length = input
+            .get(2..count.checked_add(2)?)?
+            .iter()
+            .fold(0_usize, |length, byte| {
+                (length << 8_usize) | usize::from(*byte)
+            });
+        (count.checked_add(2)?, length)
+    };
+
+    let end = header_length.checked_add(content_length)?;
+    Some((
+        tag,
+        input.get(..end)?,
+        input.get(header_length..end)?,
+        input.get(end..)?,
+    ))
+}
+
+/// Extract the `SubjectPublicKeyInfo` of a certificate (RFC 5280 4.1).
+fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (tag, _, certificate, _) = der_element(certificate)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, _, tbs_certificate, _) = der_element(certificate)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    let mut rest = tbs_certificate;
+    if rest.first() == Some(&VERSION) {
+        rest = der_element(rest)?.3;
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5_i32 {
+        rest = der_element(rest)?.3;
+    }
+
+    let (tag, spki, _, _) = der_element(rest)?;
+    (tag == SEQUENCE).then_some(spki)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn certificate() -> Vec<u8> {
+        pem::parse(
+            std::fs::read_to_string(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../vsmtp-test/src/template/certs/certificate.crt"
+            ))
+            .unwrap(),
+        )
+        .unwrap()
+        .contents
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        hex.as_bytes()
+            .chunks(2)
+            .map(|byte| u8::from_str_radix(core::str::from_utf8(byte).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn full_certificate() {
+        let certificate = certificate();
+
+        assert!(Tlsa {
+            spki: false,
+            matching: TlsaMatching::Raw,
+            data: certificate.clone(),
+        }
+        .matches(&certificate));
+        assert!(Tlsa {
+            spki: false,
+            matching: TlsaMatching::Sha256,
+            data: from_hex("dbeb64fe9e0c839e2b754b152be398cb96ab767617a985a5db4b1c478038b043"),
+        }
+        .matches(&certificate));
+    }
+
+    #[test]
+    fn public_key() {
+        let certificate = certificate();
+
+        assert!(Tlsa {
+            spki: true,
+            matching: TlsaMatching::Sha256,
+            data: from_hex("fae03a4995f695dcce166882b542617d3bf0a7845eb63079517917544e3ccc92"),
+        }
+        .matches(&certificate));
+        assert!(Tlsa {
+            spki: true,
+            matching: TlsaMatching::Sha512,
+            data: from_hex(concat!(
+                "3c664d629234414e081405010704e87ec2dbefd6aefa032cc8bc4164dc84cf68",
+                "f8eb84aed48a193f743936da25db324bc3d9a5e0dac8c67afe3b8f70bb0f508f"
+            )),
+        }
+        .matches(&certificate));
+    }
+
+    #[test]
+    fn mismatch() {
+        let certificate = certificate();
+
+        // NOTE: the hash of the full certificate used for the public key.
+        assert!(!Tlsa {
+            spki: true,
+            matching: TlsaMatching::Sha256,
+            data: from_hex("dbeb64fe9e0c839e2b754b152be398cb96ab767617a985a5db4b1c478038b043"),
+        }
+        .matches(&certificate));
+        assert!(!Tlsa {
+            spki: true,
+            matching: TlsaMatching::Raw,
+            data: vec![],
+        }
+        .matches(b"not a certificate"));
+    }
+
+    #[test]
+    fn unusable_records() {
+        let record = |usage| {
+            Tlsa::from_rdata(&trust_dns_resolver::proto::rr::rdata::TLSA::new(
+                usage,
+                Selector::Spki,
+                Matching::Sha256,
+                vec![0; 32],
+            ))
+        };
+
+        assert!(record(CertUsage::DomainIssued).is_some());
+        assert!(record(CertUsage::TrustAnchor).is_none());
+        assert!(record(CertUsage::CA).is_none());
+        assert!(record(CertUsage::Service).is_none());
+    }
+}
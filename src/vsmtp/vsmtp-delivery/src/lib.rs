@@ -41,11 +41,19 @@
     allow(clippy::unwrap_used, clippy::panic, clippy::std_instead_of_core)
 )]
 
+mod dane;
+mod mta_sts;
+mod nxdomain;
 mod send;
 mod sender;
 
-pub use send::{split_and_sort_and_send, SenderOutcome};
-pub use sender::{Sender, SenderParameters};
+pub use dane::{lookup_tlsa, Tlsa, TlsaMatching, TlsaMismatch};
+pub use mta_sts::{fetch_policy, MtaStsCache, MtaStsMode, MtaStsPolicy};
+pub use nxdomain::NxDomainCache;
+pub use send::{
+    outcome_of, send_by_transport, split_and_sort_and_send, split_by_transport, SenderOutcome,
+};
+pub use sender::{DeliverySlot, RequireTlsUnsupported, Sender, SenderParameters, TranscriptError};
 use vsmtp_common::{rcpt::Rcpt, Address};
 use vsmtp_config::Config;
 
@@ -75,14 +83,77 @@ fn get_cert_for_server(server_name: &str, config: &Config) -> Option<Vec<rustls:
         .and_then(|v| v.tls.as_ref().map(|tls| tls.certificate.inner.clone()))
 }
 
+/// Convert the error returned by [`Sender::send`], keeping the transcript of the session if any.
+fn to_transfer_error(error: &anyhow::Error) -> vsmtp_common::transfer::TransferErrorsVariant {
+    let transcript = error.downcast_ref::<TranscriptError>();
+    let inner = transcript.map_or(error, |e| &e.error);
+
+    if inner.downcast_ref::<TlsaMismatch>().is_some() {
+        return vsmtp_common::transfer::TransferErrorsVariant::TlsaMismatch {
+            error: error.to_string(),
+        };
+    }
+    if inner.downcast_ref::<RequireTlsUnsupported>().is_some() {
+        return vsmtp_common::transfer::TransferErrorsVariant::RequireTls {
+            error: error.to_string(),
+        };
+    }
+
+    vsmtp_common::transfer::TransferErrorsVariant::Smtp {
+        error: error.to_string(),
+        transcript: transcript.map(|e| e.transcript.clone()).unwrap_or_default(),
+    }
+}
+
+/// Are the answers of the resolver used for `domain` validated with DNSSEC.
+fn is_dnssec_enabled(domain: &str, config: &Config) -> bool {
+    match config
+        .server
+        .r#virtual
+        .get(domain)
+        .and_then(|v| v.dns.as_ref())
+        .unwrap_or(&config.server.dns)
+    {
+        vsmtp_config::field::FieldServerDNS::System => false,
+        vsmtp_config::field::FieldServerDNS::Google { options }
+        | vsmtp_config::field::FieldServerDNS::CloudFlare { options }
+        | vsmtp_config::field::FieldServerDNS::Custom { options, .. }
+        | vsmtp_config::field::FieldServerDNS::Upstreams { options, .. } => options.dnssec,
+    }
+}
+
+/// The name presented in the HELO/EHLO command, configured for the domain of the sender,
+/// or the name of the server.
+fn get_hello_name(from: &Option<Address>, server_name: &str, config: &Config) -> String {
+    from.as_ref()
+        .and_then(|from| config.server.ehlo_names.get(from.domain()))
+        .map_or_else(|| server_name.to_owned(), Clone::clone)
+}
+
 /// a few helpers to create systems that will deliver emails.
 pub mod transport {
     use vsmtp_common::{rcpt::Rcpt, Address, ContextFinished};
     use vsmtp_config::Config;
 
+    /// Metadata describing how a [`Transport`] delivers the emails.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub struct Capabilities {
+        /// The emails are delivered on this machine instead of being sent to a remote server.
+        pub local: bool,
+        /// Several recipients can be delivered in a single transaction.
+        pub multi_recipient: bool,
+    }
+
     ///
     #[async_trait::async_trait]
     pub trait Transport {
+        /// Name of the transport, as used in the configuration and the logs.
+        fn name(&self) -> &'static str;
+
+        /// Report how the transport delivers the emails.
+        fn capabilities(&self) -> Capabilities;
+
         /// Take the data required to deliver the email and return the updated version of the recipient.
         async fn deliver(
             self,
@@ -96,11 +167,14 @@ pub mod transport {
 
     mod deliver;
     mod forward;
+    mod lmtp;
+    mod local;
     mod maildir;
     mod mbox;
 
     pub use deliver::Deliver;
     pub use forward::Forward;
+    pub use lmtp::Lmtp;
     pub use maildir::Maildir;
     pub use mbox::MBox;
 }
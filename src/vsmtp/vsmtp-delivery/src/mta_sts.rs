@@ -0,0 +1,369 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+
+//! SMTP MTA Strict Transport Security (RFC 8461).
+
+use anyhow::Context;
+extern crate alloc;
+
+/// Maximum value of `max_age`, in seconds (RFC 8461 3.2).
+const MAX_AGE_MAX: u64 = 31_557_600;
+
+/// The `mode` of a [`MtaStsPolicy`].
+#[non_exhaustive]
+#[derive(Debug, Eq, Clone, Copy, PartialEq)]
+pub enum MtaStsMode {
+    /// The delivery to a mail exchanger not matching the policy, or whose certificate
+    /// is not valid, is not attempted.
+    Enforce,
+    /// The failures are reported, but the delivery proceeds.
+    Testing,
+    /// The domain does not have an active policy.
+    None,
+}
+
+/// A policy published at `https://mta-sts.<domain>/.well-known/mta-sts.txt`.
+#[non_exhaustive]
+#[derive(Debug, Eq, Clone, PartialEq)]
+pub struct MtaStsPolicy {
+    ///
+    pub mode: MtaStsMode,
+    /// Patterns of the mail exchangers allowed, a leading `*.` matching a single label.
+    pub mx: Vec<String>,
+    /// Duration for which the policy can be cached.
+    pub max_age: core::time::Duration,
+}
+
+impl core::str::FromStr for MtaStsPolicy {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut version, mut mode, mut max_age, mut mx) = (None, None, None, vec![]);
+
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line
+                .split_once(':')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .with_context(|| format!("invalid line in the MTA-STS policy: '{line}'"))?;
+
+            match key {
+                "version" => version = Some(value),
+                "mode" => {
+                    mode = Some(match value {
+                        "enforce" => MtaStsMode::Enforce,
+                        "testing" => MtaStsMode::Testing,
+                        "none" => MtaStsMode::None,
+                        _ => anyhow::bail!("unknown MTA-STS mode: '{value}'"),
+                    });
+                }
+                "max_age" => max_age = Some(value.parse::<u64>()?),
+                "mx" => mx.push(value.to_ascii_lowercase()),
+                // NOTE: the unknown fields are ignored (RFC 8461 3.2).
+                _ => {}
+            }
+        }
+
+        anyhow::ensure!(
+            version == Some("STSv1"),
+            "the version of the MTA-STS policy is not 'STSv1'"
+        );
+        let mode = mode.context("the MTA-STS policy has no mode")?;
+        let max_age = max_age.context("the MTA-STS policy has no max_age")?;
+        anyhow::ensure!(
+            mode == MtaStsMode::None || !mx.is_empty(),
+            "the MTA-STS policy has no mx"
+        );
+
+        Ok(Self {
+            mode,
+            mx,
+            max_age: core::time::Duration::from_secs(max_age.min(MAX_AGE_MAX)),
+        })
+    }
+}
+
+impl MtaStsPolicy {
+    /// Is the mail exchanger `host` allowed by the policy.
+    #[must_use]
+    #[inline]
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        self.mx.iter().any(|pattern| {
+            let pattern = pattern.trim_end_matches('.');
+            match pattern.strip_prefix("*.") {
+                Some(suffix) => host
+                    .split_once('.')
+                    .map_or(false, |(label, rest)| !label.is_empty() && rest == suffix),
+                None => host == pattern,
+            }
+        })
+    }
+}
+
+/// Time during which a domain without policy, or whose policy could not be fetched,
+/// is not looked up again.
+const NO_POLICY_TTL: core::time::Duration = core::time::Duration::from_secs(10 * 60);
+
+/// The policies fetched, evicted once their `max_age` is elapsed, and the domains
+/// without policy, evicted after [`NO_POLICY_TTL`].
+#[derive(Debug, Default)]
+pub struct MtaStsCache {
+    policies: std::sync::Mutex<
+        std::collections::HashMap<
+            String,
+            (std::time::Instant, Option<alloc::sync::Arc<MtaStsPolicy>>),
+        >,
+    >,
+}
+
+impl MtaStsCache {
+    /// Apply `f` to the entry of `domain`, if cached and not expired.
+    fn lookup<T>(
+        &self,
+        domain: &str,
+        f: fn(&Option<alloc::sync::Arc<MtaStsPolicy>>) -> Option<T>,
+    ) -> Option<T> {
+        let mut policies = self.policies.lock().ok()?;
+
+        let now = std::time::Instant::now();
+        policies.retain(|_, (expire_at, _)| *expire_at > now);
+
+        policies.get(domain).and_then(|(_, policy)| f(policy))
+    }
+
+    /// Get the policy of `domain`, if cached and not expired.
+    #[must_use]
+    #[inline]
+    pub fn get(&self, domain: &str) -> Option<alloc::sync::Arc<MtaStsPolicy>> {
+        self.lookup(domain, |policy| {
+            policy.as_ref().map(alloc::sync::Arc::clone)
+        })
+    }
+
+    /// Is `domain` known to have no policy, see [`Self::insert_none`].
+    #[must_use]
+    #[inline]
+    pub fn has_no_policy(&self, domain: &str) -> bool {
+        self.lookup(domain, |policy| policy.is_none().then_some(()))
+            .is_some()
+    }
+
+    /// Cache the policy of `domain` for its `max_age`.
+    #[inline]
+    pub fn insert(&self, domain: &str, policy: MtaStsPolicy) -> alloc::sync::Arc<MtaStsPolicy> {
+        let policy = alloc::sync::Arc::new(policy);
+        self.insert_entry(
+            domain,
+            policy.max_age,
+            Some(alloc::sync::Arc::clone(&policy)),
+        );
+        policy
+    }
+
+    /// Cache that `domain` has no policy, for [`NO_POLICY_TTL`].
+    #[inline]
+    pub fn insert_none(&self, domain: &str) {
+        self.insert_entry(domain, NO_POLICY_TTL, None);
+    }
+
+    fn insert_entry(
+        &self,
+        domain: &str,
+        ttl: core::time::Duration,
+        policy: Option<alloc::sync::Arc<MtaStsPolicy>>,
+    ) {
+        if let (Ok(mut policies), Some(expire_at)) = (
+            self.policies.lock(),
+            std::time::Instant::now().checked_add(ttl),
+        ) {
+            policies.insert(domain.to_owned(), (expire_at, policy));
+        }
+    }
+}
+
+/// Fetch the policy of `domain`, if the domain publishes one.
+///
+/// # Errors
+///
+/// * the `_mta-sts` TXT record could not be fetched.
+/// * the policy could not be fetched over HTTPS, or is invalid.
+#[cfg(feature = "mta-sts")]
+#[inline]
+pub async fn fetch_policy(
+    resolver: &trust_dns_resolver::TokioAsyncResolver,
+    domain: &str,
+) -> anyhow::Result<Option<MtaStsPolicy>> {
+    /// Maximum size of a policy (RFC 8461 3.3).
+    const POLICY_SIZE_MAX: usize = 64 * 1024;
+
+    let domain = domain.trim_end_matches('.');
+
+    match resolver.txt_lookup(format!("_mta-sts.{domain}")).await {
+        Ok(records) => {
+            if !records
+                .iter()
+                .any(|txt| txt.to_string().starts_with("v=STSv1"))
+            {
+                return Ok(None);
+            }
+        }
+        Err(error)
+            if matches!(
+                error.kind(),
+                trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. }
+            ) =>
+        {
+            return Ok(None);
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    // NOTE: the redirects must not be followed (RFC 8461 3.3).
+    let mut response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(core::time::Duration::from_secs(60))
+        .build()?
+        .get(format!("https://mta-sts.{domain}/.well-known/mta-sts.txt"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    anyhow::ensure!(
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map_or(false, |content_type| content_type.starts_with("text/plain")),
+        "the MTA-STS policy of '{domain}' is not served as 'text/plain'"
+    );
+
+    // NOTE: the body is read up to the maximum size, whatever its announced length.
+    anyhow::ensure!(
+        response
+            .content_length()
+            .and_then(|length| usize::try_from(length).ok())
+            .map_or(true, |length| length <= POLICY_SIZE_MAX),
+        "the MTA-STS policy of '{domain}' is too large"
+    );
+    let mut policy = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        anyhow::ensure!(
+            policy.len().saturating_add(chunk.len()) <= POLICY_SIZE_MAX,
+            "the MTA-STS policy of '{domain}' is too large"
+        );
+        policy.extend_from_slice(&chunk);
+    }
+
+    String::from_utf8(policy)?.parse().map(Some)
+}
+
+/// Fetch the policy of `domain`, always none without the `mta-sts` feature.
+///
+/// # Errors
+///
+/// * never.
+#[cfg(not(feature = "mta-sts"))]
+#[inline]
+#[allow(clippy::unused_async)]
+pub async fn fetch_policy(
+    _resolver: &trust_dns_resolver::TokioAsyncResolver,
+    _domain: &str,
+) -> anyhow::Result<Option<MtaStsPolicy>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = concat!(
+        "version: STSv1\r\n",
+        "mode: enforce\r\n",
+        "mx: mail.example.com\r\n",
+        "mx: *.example.net\r\n",
+        "mx: backupmx.example.com\r\n",
+        "max_age: 604800\r\n",
+    );
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            POLICY.parse::<MtaStsPolicy>().unwrap(),
+            MtaStsPolicy {
+                mode: MtaStsMode::Enforce,
+                mx: vec![
+                    "mail.example.com".to_owned(),
+                    "*.example.net".to_owned(),
+                    "backupmx.example.com".to_owned()
+                ],
+                max_age: core::time::Duration::from_secs(604_800),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("mode: enforce\nmx: mail.example.com\nmax_age: 86400\n"
+            .parse::<MtaStsPolicy>()
+            .is_err());
+        assert!("version: STSv1\nmode: enforce\nmax_age: 86400\n"
+            .parse::<MtaStsPolicy>()
+            .is_err());
+        assert!(
+            "version: STSv1\nmode: strict\nmx: mail.example.com\nmax_age: 86400\n"
+                .parse::<MtaStsPolicy>()
+                .is_err()
+        );
+        assert!("version: STSv1\nmode: none\nmax_age: 86400\n"
+            .parse::<MtaStsPolicy>()
+            .is_ok());
+    }
+
+    #[test]
+    fn matches() {
+        let policy = POLICY.parse::<MtaStsPolicy>().unwrap();
+
+        assert!(policy.matches("mail.example.com"));
+        assert!(policy.matches("MAIL.example.com."));
+        assert!(policy.matches("mx1.example.net"));
+        assert!(!policy.matches("example.net"));
+        assert!(!policy.matches("a.mx1.example.net"));
+        assert!(!policy.matches("mail.example.org"));
+    }
+
+    #[test]
+    fn cache() {
+        let cache = MtaStsCache::default();
+        let mut policy = POLICY.parse::<MtaStsPolicy>().unwrap();
+
+        cache.insert("example.com", policy.clone());
+        assert_eq!(*cache.get("example.com").unwrap(), policy);
+        assert!(cache.get("example.org").is_none());
+        assert!(!cache.has_no_policy("example.org"));
+
+        cache.insert_none("example.org");
+        assert!(cache.get("example.org").is_none());
+        assert!(cache.has_no_policy("example.org"));
+        assert!(!cache.has_no_policy("example.com"));
+
+        policy.max_age = core::time::Duration::ZERO;
+        cache.insert("example.com", policy);
+        assert!(cache.get("example.com").is_none());
+    }
+}
@@ -0,0 +1,52 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+
+//! Negative cache of the domains which do not exist, see `server.queues.delivery.nxdomain_cache`.
+
+/// The domains answered with `NXDOMAIN`, evicted once their duration is elapsed.
+#[derive(Debug, Default)]
+pub struct NxDomainCache {
+    domains: std::sync::Mutex<std::collections::HashMap<String, std::time::Instant>>,
+}
+
+impl NxDomainCache {
+    /// Is `domain` cached as not existing, and not expired.
+    #[must_use]
+    #[inline]
+    pub fn contains(&self, domain: &str) -> bool {
+        let mut domains = match self.domains.lock() {
+            Ok(domains) => domains,
+            Err(_) => return false,
+        };
+
+        let now = std::time::Instant::now();
+        domains.retain(|_, expire_at| *expire_at > now);
+
+        domains.contains_key(domain)
+    }
+
+    /// Cache `domain` as not existing for `duration`.
+    #[inline]
+    pub fn insert(&self, domain: &str, duration: core::time::Duration) {
+        if let (Ok(mut domains), Some(expire_at)) = (
+            self.domains.lock(),
+            std::time::Instant::now().checked_add(duration),
+        ) {
+            domains.insert(domain.to_owned(), expire_at);
+        }
+    }
+}
@@ -14,7 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::transport::{Deliver, Forward, MBox, Maildir, Transport};
+use crate::transport::{Deliver, Forward, Lmtp, MBox, Maildir, Transport};
 use crate::Sender;
 use vsmtp_common::{
     rcpt::Rcpt,
@@ -38,16 +38,11 @@ pub enum SenderOutcome {
     RemoveFromDisk,
 }
 
-///
-#[allow(clippy::unreachable)] // false positive
-#[tracing::instrument(name = "send", skip_all)]
-pub async fn split_and_sort_and_send(
-    config: &Config,
-    message_ctx: &mut ContextFinished,
-    message_body: &MessageBody,
-    resolvers: alloc::sync::Arc<DnsResolvers>,
-    sender: alloc::sync::Arc<Sender>,
-) -> SenderOutcome {
+/// Group the sendable recipients of the message by transport, each group
+/// being an independent delivery job.
+#[must_use]
+#[inline]
+pub fn split_by_transport(message_ctx: &ContextFinished) -> Vec<(Transfer, Vec<Rcpt>)> {
     let mut acc: std::collections::HashMap<Transfer, Vec<Rcpt>> = std::collections::HashMap::new();
     for i in message_ctx
         .rcpt_to
@@ -60,43 +55,92 @@ pub async fn split_and_sort_and_send(
             .or_insert_with(|| vec![i.clone()]);
     }
 
-    if acc.is_empty() {
-        tracing::warn!("No recipients to send to.");
-        return SenderOutcome::MoveToDead;
-    }
-
-    let message_content = message_body.inner().to_string();
+    acc.into_iter().collect()
+}
 
+/// Run a single job produced by [`split_by_transport`], returning the recipients
+/// of the job with their updated status.
+#[inline]
+pub async fn send_by_transport(
+    config: &Config,
+    message_ctx: &ContextFinished,
+    message_content: &str,
+    (transfer, to): (Transfer, Vec<Rcpt>),
+    resolvers: &DnsResolvers,
+    sender: &alloc::sync::Arc<Sender>,
+) -> Vec<Rcpt> {
     let from = &message_ctx.mail_from.reverse_path;
 
-    let futures = acc.into_iter().map(|(key, to)| match key {
+    match transfer {
         Transfer::Forward(forward_target) => {
             let resolver = match forward_target.clone() {
                 ForwardTarget::Domain(domain) => resolvers.get_resolver_or_root(&domain),
                 ForwardTarget::Ip(_) | ForwardTarget::Socket(_) => resolvers.get_resolver_root(),
             };
 
-            Forward::new(forward_target, resolver, alloc::sync::Arc::clone(&sender)).deliver(
-                config,
-                message_ctx,
-                from,
-                to,
-                &message_content,
+            Forward::new(forward_target, resolver, alloc::sync::Arc::clone(sender))
+                .deliver(config, message_ctx, from, to, message_content)
+                .await
+        }
+        Transfer::Deliver => {
+            Deliver::new(
+                resolvers.get_resolver_or_root(
+                    #[allow(clippy::expect_used)]
+                    to.get(0)
+                        .expect("at least one element in the group")
+                        .address
+                        .domain(),
+                ),
+                alloc::sync::Arc::clone(sender),
             )
+            .deliver(config, message_ctx, from, to, message_content)
+            .await
+        }
+        Transfer::Mbox => {
+            MBox.deliver(config, message_ctx, from, to, message_content)
+                .await
+        }
+        Transfer::Maildir => {
+            Maildir
+                .deliver(config, message_ctx, from, to, message_content)
+                .await
         }
-        Transfer::Deliver => Deliver::new(
-            resolvers.get_resolver_or_root(
-                #[allow(clippy::expect_used)]
-                to.get(0)
-                    .expect("at least one element in the group")
-                    .address
-                    .domain(),
-            ),
-            alloc::sync::Arc::clone(&sender),
+        Transfer::Lmtp(lmtp_target) => {
+            Lmtp::new(lmtp_target)
+                .deliver(config, message_ctx, from, to, message_content)
+                .await
+        }
+    }
+}
+
+///
+#[allow(clippy::unreachable)] // false positive
+#[tracing::instrument(name = "send", skip_all)]
+pub async fn split_and_sort_and_send(
+    config: &Config,
+    message_ctx: &mut ContextFinished,
+    message_body: &MessageBody,
+    resolvers: alloc::sync::Arc<DnsResolvers>,
+    sender: alloc::sync::Arc<Sender>,
+) -> SenderOutcome {
+    let jobs = split_by_transport(message_ctx);
+
+    if jobs.is_empty() {
+        tracing::warn!("No recipients to send to.");
+        return SenderOutcome::MoveToDead;
+    }
+
+    let message_content = message_body.inner().to_string();
+
+    let futures = jobs.into_iter().map(|job| {
+        send_by_transport(
+            config,
+            message_ctx,
+            &message_content,
+            job,
+            &resolvers,
+            &sender,
         )
-        .deliver(config, message_ctx, from, to, &message_content),
-        Transfer::Mbox => MBox.deliver(config, message_ctx, from, to, &message_content),
-        Transfer::Maildir => Maildir.deliver(config, message_ctx, from, to, &message_content),
     });
 
     message_ctx.rcpt_to.forward_paths = futures_util::future::join_all(futures)
@@ -105,6 +149,13 @@ pub async fn split_and_sort_and_send(
         .flatten()
         .collect::<Vec<_>>();
 
+    outcome_of(config, message_ctx)
+}
+
+/// Compute what to do with the message once all the jobs have been run,
+/// and update the status of the recipients accordingly.
+#[inline]
+pub fn outcome_of(config: &Config, message_ctx: &mut ContextFinished) -> SenderOutcome {
     tracing::debug!(rcpt = ?message_ctx.rcpt_to.forward_paths
         .iter().map(ToString::to_string).collect::<Vec<_>>(), "Sending.");
     tracing::trace!(rcpt = ?message_ctx.rcpt_to.forward_paths);
@@ -166,3 +217,70 @@ pub async fn split_and_sort_and_send(
 
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_common::{addr, transfer::NotifyOn};
+    use vsmtp_test::config::{local_ctx, local_test};
+
+    #[tokio::test]
+    async fn mixed_transports_complete_independently() {
+        let config = local_test();
+        let resolvers = DnsResolvers::from_config(&config).unwrap();
+        let sender = alloc::sync::Arc::new(Sender::default());
+
+        let mut ctx = local_ctx();
+        ctx.rcpt_to.forward_paths = vec![
+            Rcpt {
+                address: addr!(&format!(
+                    "{}@domain.com",
+                    users::get_current_username().unwrap().to_str().unwrap()
+                )),
+                transfer_method: Transfer::Maildir,
+                email_status: EmailTransferStatus::default(),
+                notify_on: NotifyOn::default(),
+                original_forward_path: None,
+            },
+            Rcpt {
+                address: addr!("john.doe@unreachable.com"),
+                transfer_method: Transfer::Forward(ForwardTarget::Socket(
+                    "127.0.0.1:1".parse().unwrap(),
+                )),
+                email_status: EmailTransferStatus::default(),
+                notify_on: NotifyOn::default(),
+                original_forward_path: None,
+            },
+        ];
+
+        let mut jobs = split_by_transport(&ctx);
+        jobs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert_eq!(jobs.len(), 2);
+
+        let mut results = vec![];
+        for job in jobs {
+            results.push(
+                send_by_transport(&config, &ctx, "Hello World!\r\n", job, &resolvers, &sender)
+                    .await,
+            );
+        }
+
+        #[allow(clippy::indexing_slicing)]
+        {
+            assert!(matches!(
+                results[0][0].email_status,
+                EmailTransferStatus::HeldBack { .. }
+            ));
+            assert!(matches!(
+                results[1][0].email_status,
+                EmailTransferStatus::Sent { .. }
+            ));
+        }
+
+        ctx.rcpt_to.forward_paths = results.into_iter().flatten().collect();
+        assert!(matches!(
+            outcome_of(&config, &mut ctx),
+            SenderOutcome::MoveToDeferred
+        ));
+    }
+}
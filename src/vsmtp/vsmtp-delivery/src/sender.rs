@@ -15,6 +15,11 @@
  *
  */
 
+use crate::{
+    dane::{Tlsa, TlsaMismatch},
+    mta_sts::MtaStsCache,
+    nxdomain::NxDomainCache,
+};
 use anyhow::Context;
 extern crate alloc;
 
@@ -31,7 +36,7 @@ pub struct SenderParameters {
     pub hello_name: String,
     ///
     pub pool_idle_timeout: core::time::Duration,
-    ///
+    /// Maximum number of connections in the pool, `0` to open a new connection for each mail.
     pub pool_max_size: u32,
     ///
     pub pool_min_idle: u32,
@@ -39,18 +44,176 @@ pub struct SenderParameters {
     pub port: u16,
     ///
     pub certificate: Vec<rustls::Certificate>,
-    // use_dane: bool,
+    /// Record the commands and replies of the session, returned in a [`TranscriptError`]
+    /// if the delivery fails. The connection is not pooled in that case.
+    pub capture_transcript: bool,
+    /// The TLSA records authenticating the server with DANE, instead of the root certificates.
+    /// The connection is not pooled if not empty.
+    pub tlsa: Vec<Tlsa>,
+    /// The submitter of the mail, passed on with the `AUTH=` parameter of `MAIL FROM`
+    /// if the server supports it. The connection is not pooled if set.
+    pub auth_mailbox: Option<String>,
+    /// The mail requires TLS (`REQUIRETLS`, RFC 8689): the parameter is passed on with
+    /// `MAIL FROM`, and the delivery fails with [`RequireTlsUnsupported`] if the server
+    /// does not support it. The connection is not pooled if set.
+    pub require_tls: bool,
+    /// Send the mail in clear text to a server which does not support STARTTLS, as asked
+    /// by the `TLS-Required: No` header (RFC 8689 5). Ignored with `require_tls` or `tlsa`.
+    pub tls_optional: bool,
+}
+
+/// The server does not support `REQUIRETLS` (RFC 8689), a mail requiring it cannot be relayed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct RequireTlsUnsupported {
+    /// The server the mail was relayed to.
+    pub host: String,
+}
+
+impl core::fmt::Display for RequireTlsUnsupported {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "'{}' does not support REQUIRETLS", self.host)
+    }
 }
 
+impl std::error::Error for RequireTlsUnsupported {}
+
+/// The failure of a delivery attempt, with the transcript of the smtp session.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TranscriptError {
+    /// The error which ended the session.
+    pub error: anyhow::Error,
+    /// The commands sent, prefixed by `C:`, and the replies received, prefixed by `S:`.
+    pub transcript: Vec<String>,
+}
+
+impl core::fmt::Display for TranscriptError {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for TranscriptError {}
+
 type SenderInner = alloc::sync::Arc<lettre::AsyncSmtpTransport<lettre::Tokio1Executor>>;
 
+/// A semaphore limiting the number of deliveries in progress, with its number of permits.
+type Limit = (usize, alloc::sync::Arc<tokio::sync::Semaphore>);
+
+/// Semaphores limiting the number of deliveries in progress.
+#[derive(Default)]
+struct InFlight {
+    global: Option<Limit>,
+    per_domain: std::collections::HashMap<String, Limit>,
+}
+
+impl InFlight {
+    /// The semaphore of `limit`, replaced if its number of permits is not `max` anymore.
+    /// The permits of the previous semaphore are released to it.
+    fn semaphore(
+        limit: &mut Option<Limit>,
+        max: usize,
+    ) -> alloc::sync::Arc<tokio::sync::Semaphore> {
+        match limit {
+            Some((permits, semaphore)) if *permits == max => alloc::sync::Arc::clone(semaphore),
+            _ => {
+                let semaphore = alloc::sync::Arc::new(tokio::sync::Semaphore::new(max));
+                *limit = Some((max, alloc::sync::Arc::clone(&semaphore)));
+                semaphore
+            }
+        }
+    }
+}
+
+/// A slot to deliver to a domain, released when dropped.
+#[must_use]
+pub struct DeliverySlot {
+    _per_domain: Option<tokio::sync::OwnedSemaphorePermit>,
+    _global: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
 ///
 #[derive(Default)]
 pub struct Sender {
     senders: std::sync::RwLock<std::collections::HashMap<SenderParameters, SenderInner>>,
+    in_flight: std::sync::Mutex<InFlight>,
+    mta_sts: MtaStsCache,
+    nxdomain: NxDomainCache,
 }
 
 impl Sender {
+    /// The MTA-STS policies of the domains delivered to.
+    #[must_use]
+    #[inline]
+    pub const fn mta_sts(&self) -> &MtaStsCache {
+        &self.mta_sts
+    }
+
+    /// The domains of the deliveries which do not exist.
+    #[must_use]
+    #[inline]
+    pub const fn nxdomain(&self) -> &NxDomainCache {
+        &self.nxdomain
+    }
+
+    /// Wait for a slot to deliver to `domain`, with at most `in_flight_max` deliveries
+    /// in progress in total, and `in_flight_per_domain_max` for the domain.
+    ///
+    /// The slot of the domain is acquired first, so that the deliveries waiting for a busy
+    /// domain do not hold the slots needed by the other domains.
+    ///
+    /// # Errors
+    ///
+    /// * The inner `Mutex` is poisoned.
+    #[inline]
+    pub async fn acquire_delivery_slot(
+        &self,
+        domain: &str,
+        in_flight_max: Option<usize>,
+        in_flight_per_domain_max: Option<usize>,
+    ) -> anyhow::Result<DeliverySlot> {
+        let (global, per_domain) = {
+            let mut in_flight = self
+                .in_flight
+                .lock()
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            // NOTE: the semaphores of the domains no delivery holds or waits for are dropped,
+            //       the waiting deliveries and the permits keep a reference to theirs.
+            in_flight
+                .per_domain
+                .retain(|_, (_, semaphore)| alloc::sync::Arc::strong_count(semaphore) > 1);
+
+            let global = in_flight_max.map(|max| InFlight::semaphore(&mut in_flight.global, max));
+            let per_domain = in_flight_per_domain_max.map(|max| {
+                let mut limit = in_flight.per_domain.remove(domain);
+                let semaphore = InFlight::semaphore(&mut limit, max);
+                in_flight
+                    .per_domain
+                    .extend(limit.map(|limit| (domain.to_owned(), limit)));
+                semaphore
+            });
+            (global, per_domain)
+        };
+
+        let per_domain = match per_domain {
+            Some(semaphore) => Some(semaphore.acquire_owned().await?),
+            None => None,
+        };
+        let global = match global {
+            Some(semaphore) => Some(semaphore.acquire_owned().await?),
+            None => None,
+        };
+
+        Ok(DeliverySlot {
+            _per_domain: per_domain,
+            _global: global,
+        })
+    }
+
     /// Send a mail to the transport using the given parameters.
     /// Create a new transport if none existing.
     ///
@@ -67,6 +230,22 @@ impl Sender {
     ) -> anyhow::Result<lettre::transport::smtp::response::Response> {
         use lettre::AsyncTransport;
 
+        // NOTE: a pool of size 0 disables the pooling, a new connection is opened for each mail.
+        //       The certificate of the server can only be checked against its TLSA records
+        //       on a connection managed here, as are the parameters of the `MAIL FROM` command.
+        if params.capture_transcript
+            || params.pool_max_size == 0
+            || !params.tlsa.is_empty()
+            || params.auth_mailbox.is_some()
+            || params.require_tls
+        {
+            return match Self::send_on_new_connection(params, envelop, message).await {
+                Ok(response) => Ok(response),
+                Err(error) if params.capture_transcript => Err(error.into()),
+                Err(error) => Err(error.error),
+            };
+        }
+
         let sender = {
             if !self
                 .senders
@@ -100,22 +279,189 @@ impl Sender {
             .context("fail to send email")
     }
 
-    fn build_sender(params: &SenderParameters) -> anyhow::Result<SenderInner> {
-        tracing::trace!(?params, "Creating a transport");
+    /// Send the mail on a new connection, recording the session.
+    async fn send_on_new_connection(
+        params: &SenderParameters,
+        envelop: &lettre::address::Envelope,
+        message: &[u8],
+    ) -> Result<lettre::transport::smtp::response::Response, TranscriptError> {
+        let mut transcript = vec![format!(
+            "connecting to {}:{}",
+            params.relay_target, params.port
+        )];
 
-        let builder = lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::builder_dangerous(
-            params.relay_target.clone(),
+        match Self::transcript_session(params, envelop, message, &mut transcript).await {
+            Ok(response) => Ok(response),
+            Err(error) => {
+                transcript.push(format!("error: {error}"));
+                Err(TranscriptError { error, transcript })
+            }
+        }
+    }
+
+    async fn transcript_session(
+        params: &SenderParameters,
+        envelop: &lettre::address::Envelope,
+        message: &[u8],
+        transcript: &mut Vec<String>,
+    ) -> anyhow::Result<lettre::transport::smtp::response::Response> {
+        use lettre::transport::smtp::{
+            authentication::Mechanism, client::AsyncSmtpConnection, commands,
+            extension::MailParameter,
+        };
+
+        let hello_name =
+            lettre::transport::smtp::extension::ClientId::Domain(params.hello_name.clone());
+
+        // NOTE: the greeting and the EHLO exchange are handled by lettre when connecting,
+        // only the resulting capabilities of the server are available.
+        let mut connection = AsyncSmtpConnection::connect_tokio1(
+            (params.relay_target.as_str(), params.port),
+            Some(core::time::Duration::from_secs(60)),
+            &hello_name,
+            None,
+            None,
         )
-        .port(params.port)
-        .hello_name(lettre::transport::smtp::extension::ClientId::Domain(
-            params.hello_name.clone(),
-        ))
-        .pool_config(
-            lettre::transport::smtp::PoolConfig::new()
-                .idle_timeout(params.pool_idle_timeout)
-                .max_size(params.pool_max_size)
-                .min_idle(params.pool_min_idle),
-        );
+        .await?;
+        transcript.push(format!("C: EHLO {}", params.hello_name));
+        transcript.push(format!("S: {}", connection.server_info()));
+
+        if connection.can_starttls() {
+            Self::secure_session(&mut connection, params, &hello_name, transcript).await?;
+        } else if params.tls_optional && !params.require_tls && params.tlsa.is_empty() {
+            transcript.push("the server does not support STARTTLS, TLS not required".to_owned());
+        } else {
+            anyhow::bail!("the server does not support STARTTLS");
+        }
+
+        let mut mail_parameters = match &params.auth_mailbox {
+            Some(mailbox)
+                if connection
+                    .server_info()
+                    .get_auth_mechanism(&[Mechanism::Plain, Mechanism::Login, Mechanism::Xoauth2])
+                    .is_some() =>
+            {
+                vec![MailParameter::Other {
+                    keyword: "AUTH".to_owned(),
+                    value: Some(encode_auth_mailbox(mailbox)),
+                }]
+            }
+            _ => vec![],
+        };
+        if params.require_tls {
+            mail_parameters.push(MailParameter::Other {
+                keyword: "REQUIRETLS".to_owned(),
+                value: None,
+            });
+        }
+
+        Self::transcript_command(
+            &mut connection,
+            commands::Mail::new(envelop.from().cloned(), mail_parameters),
+            transcript,
+        )
+        .await?;
+        for rcpt in envelop.to() {
+            Self::transcript_command(
+                &mut connection,
+                commands::Rcpt::new(rcpt.clone(), vec![]),
+                transcript,
+            )
+            .await?;
+        }
+        Self::transcript_command(&mut connection, commands::Data, transcript).await?;
+
+        transcript.push(format!("C: <{} bytes of data>", message.len()));
+        let response = connection.message(message).await?;
+        transcript.push(format!("S: {}", Self::reply_line(&response)));
+
+        if let Err(error) = connection.quit().await {
+            tracing::debug!(%error, "Failed to quit the smtp session.");
+        }
+
+        Ok(response)
+    }
+
+    /// Upgrade the session with STARTTLS, and authenticate the server with DANE
+    /// or check its support of `REQUIRETLS` if needed.
+    async fn secure_session(
+        connection: &mut lettre::transport::smtp::client::AsyncSmtpConnection,
+        params: &SenderParameters,
+        hello_name: &lettre::transport::smtp::extension::ClientId,
+        transcript: &mut Vec<String>,
+    ) -> anyhow::Result<()> {
+        transcript.push("C: STARTTLS".to_owned());
+        connection
+            .starttls(Self::tls_parameters(params)?, hello_name)
+            .await?;
+
+        if !params.tlsa.is_empty() {
+            let certificate = connection.peer_certificate()?;
+            if !params.tlsa.iter().any(|tlsa| tlsa.matches(&certificate)) {
+                return Err(TlsaMismatch {
+                    host: params.relay_target.clone(),
+                }
+                .into());
+            }
+            transcript.push("certificate authenticated with DANE".to_owned());
+        }
+
+        if params.require_tls {
+            // NOTE: lettre only keeps the capabilities it knows of, the EHLO is sent again
+            //       to read the others.
+            transcript.push(format!("C: EHLO {}", params.hello_name));
+            let response = connection
+                .command(lettre::transport::smtp::commands::Ehlo::new(
+                    hello_name.clone(),
+                ))
+                .await?;
+            transcript.push(format!("S: {}", Self::reply_line(&response)));
+
+            if !response
+                .message()
+                .any(|capability| capability.trim().eq_ignore_ascii_case("REQUIRETLS"))
+            {
+                return Err(RequireTlsUnsupported {
+                    host: params.relay_target.clone(),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn transcript_command<C: core::fmt::Display + Send>(
+        connection: &mut lettre::transport::smtp::client::AsyncSmtpConnection,
+        command: C,
+        transcript: &mut Vec<String>,
+    ) -> anyhow::Result<()> {
+        transcript.push(format!("C: {}", command.to_string().trim_end()));
+        let response = connection.command(command).await?;
+        transcript.push(format!("S: {}", Self::reply_line(&response)));
+        Ok(())
+    }
+
+    fn reply_line(response: &lettre::transport::smtp::response::Response) -> String {
+        format!(
+            "{} {}",
+            response.code(),
+            response.message().collect::<Vec<_>>().join(" ")
+        )
+    }
+
+    fn tls_parameters(
+        params: &SenderParameters,
+    ) -> anyhow::Result<lettre::transport::smtp::client::TlsParameters> {
+        // NOTE: with DANE-EE, the certificate is pinned by the TLSA records regardless of
+        //       its issuer, names and validity (RFC 7672 3.1.1), it is checked once connected.
+        if !params.tlsa.is_empty() {
+            return Ok(lettre::transport::smtp::client::TlsParameters::builder(
+                params.server_name.clone(),
+            )
+            .dangerous_accept_invalid_certs(true)
+            .build()?);
+        }
 
         // NOTE: there is no way to build `lettre::transport::smtp::client::Certificate` from `Vec<rustls::Certificate>`.
         // rustls::Certificate => PEM => lettre::transport::smtp::client::Certificate => rustls::Certificate
@@ -131,16 +477,405 @@ impl Sender {
             .flat_map(|c| c.as_bytes().to_vec())
             .collect::<Vec<_>>();
 
-        let builder = builder.tls(lettre::transport::smtp::client::Tls::Required(
+        Ok(
             lettre::transport::smtp::client::TlsParameters::builder(params.server_name.clone())
                 .add_root_certificate(lettre::transport::smtp::client::Certificate::from_pem(
                     &certs,
                 )?)
                 .build()?,
-        ));
+        )
+    }
+
+    fn build_sender(params: &SenderParameters) -> anyhow::Result<SenderInner> {
+        tracing::trace!(?params, "Creating a transport");
+
+        let builder = lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::builder_dangerous(
+            params.relay_target.clone(),
+        )
+        .port(params.port)
+        .hello_name(lettre::transport::smtp::extension::ClientId::Domain(
+            params.hello_name.clone(),
+        ))
+        .pool_config(
+            lettre::transport::smtp::PoolConfig::new()
+                .idle_timeout(params.pool_idle_timeout)
+                .max_size(params.pool_max_size)
+                .min_idle(params.pool_min_idle),
+        );
+
+        let tls_parameters = Self::tls_parameters(params)?;
+        let builder = builder.tls(if params.tls_optional {
+            lettre::transport::smtp::client::Tls::Opportunistic(tls_parameters)
+        } else {
+            lettre::transport::smtp::client::Tls::Required(tls_parameters)
+        });
 
         // builder.timeout(timeout)
 
         Ok(alloc::sync::Arc::new(builder.build()))
     }
 }
+
+/// Encode the mailbox as xtext (RFC 3461), the unknown submitter `<>` being sent as is.
+fn encode_auth_mailbox(mailbox: &str) -> String {
+    if mailbox == "<>" {
+        return mailbox.to_owned();
+    }
+
+    mailbox.bytes().fold(String::new(), |mut out, byte| {
+        if (b'!'..=b'~').contains(&byte) && byte != b'+' && byte != b'=' {
+            out.push(char::from(byte));
+        } else {
+            out.extend(format!("+{byte:02X}").chars());
+        }
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    use vsmtp_common::transfer::TransferErrorsVariant;
+
+    /// Accept a single smtp session, without support for STARTTLS.
+    async fn fake_smtp_server(listener: tokio::net::TcpListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = tokio::io::BufReader::new(stream);
+        stream.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+
+            let reply: &[u8] = if line.starts_with("EHLO") {
+                b"250-mock\r\n250 8BITMIME\r\n"
+            } else if line.starts_with("QUIT") {
+                b"221 bye\r\n"
+            } else {
+                b"502 command not implemented\r\n"
+            };
+            stream.write_all(reply).await.unwrap();
+        }
+    }
+
+    #[allow(clippy::indexing_slicing)]
+    #[test_log::test(tokio::test)]
+    async fn transcript_captured_on_failure() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_smtp_server(listener));
+
+        let params = SenderParameters {
+            relay_target: "127.0.0.1".to_owned(),
+            server_name: "localhost".to_owned(),
+            hello_name: "testserver.com".to_owned(),
+            pool_idle_timeout: core::time::Duration::from_secs(60),
+            pool_max_size: 3,
+            pool_min_idle: 1,
+            port,
+            certificate: vec![],
+            tlsa: vec![],
+            capture_transcript: true,
+            auth_mailbox: None,
+            require_tls: false,
+            tls_optional: false,
+        };
+        let envelop = lettre::address::Envelope::new(
+            Some("foo@domain.com".parse().unwrap()),
+            vec!["john.doe@domain.com".parse().unwrap()],
+        )
+        .unwrap();
+
+        let error = Sender::default()
+            .send(&params, &envelop, b"Subject: test\r\n\r\nbody\r\n")
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        let transcript = &error.downcast_ref::<TranscriptError>().unwrap().transcript;
+        assert_eq!(transcript[0], format!("connecting to 127.0.0.1:{port}"));
+        assert_eq!(transcript[1], "C: EHLO testserver.com");
+        assert!(transcript[2].starts_with("S: mock"), "{transcript:?}");
+        assert_eq!(
+            transcript.last().unwrap(),
+            "error: the server does not support STARTTLS"
+        );
+
+        match crate::to_transfer_error(&error) {
+            TransferErrorsVariant::Smtp {
+                error,
+                transcript: captured,
+            } => {
+                assert_eq!(error, "the server does not support STARTTLS");
+                assert_eq!(&captured, transcript);
+            }
+            otherwise => panic!("unexpected error {otherwise:?}"),
+        }
+    }
+
+    /// Accept a single smtp session in clear text, refusing the recipients.
+    async fn fake_smtp_server_refusing_rcpt(listener: tokio::net::TcpListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = tokio::io::BufReader::new(stream);
+        stream.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+
+            let reply: &[u8] = if line.starts_with("EHLO") {
+                b"250-mock\r\n250 8BITMIME\r\n"
+            } else if line.starts_with("MAIL FROM") {
+                b"250 2.1.0 sender ok\r\n"
+            } else if line.starts_with("RCPT TO") {
+                b"550 5.1.1 no such user\r\n"
+            } else if line.starts_with("QUIT") {
+                b"221 bye\r\n"
+            } else {
+                b"502 command not implemented\r\n"
+            };
+            stream.write_all(reply).await.unwrap();
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn transcript_of_refused_recipient() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_smtp_server_refusing_rcpt(listener));
+
+        let params = SenderParameters {
+            relay_target: "127.0.0.1".to_owned(),
+            server_name: "localhost".to_owned(),
+            hello_name: "testserver.com".to_owned(),
+            pool_idle_timeout: core::time::Duration::from_secs(60),
+            pool_max_size: 3,
+            pool_min_idle: 1,
+            port,
+            certificate: vec![],
+            tlsa: vec![],
+            capture_transcript: true,
+            auth_mailbox: None,
+            require_tls: false,
+            tls_optional: true,
+        };
+        let envelop = lettre::address::Envelope::new(
+            Some("foo@domain.com".parse().unwrap()),
+            vec!["john.doe@domain.com".parse().unwrap()],
+        )
+        .unwrap();
+
+        let error = Sender::default()
+            .send(&params, &envelop, b"TLS-Required: No\r\n\r\nbody\r\n")
+            .await
+            .unwrap_err();
+        server.abort();
+
+        let transcript = &error.downcast_ref::<TranscriptError>().unwrap().transcript;
+        assert_eq!(
+            transcript.get(3..).unwrap(),
+            [
+                "the server does not support STARTTLS, TLS not required",
+                "C: MAIL FROM:<foo@domain.com>",
+                "S: 250 2.1.0 sender ok",
+                "C: RCPT TO:<john.doe@domain.com>",
+                "error: permanent error (550): 5.1.1 no such user",
+            ],
+            "{transcript:?}"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn no_pool() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_smtp_server(listener));
+
+        let params = SenderParameters {
+            relay_target: "127.0.0.1".to_owned(),
+            server_name: "localhost".to_owned(),
+            hello_name: "testserver.com".to_owned(),
+            pool_idle_timeout: core::time::Duration::from_secs(60),
+            pool_max_size: 0,
+            pool_min_idle: 1,
+            port,
+            certificate: vec![],
+            tlsa: vec![],
+            capture_transcript: false,
+            auth_mailbox: None,
+            require_tls: false,
+            tls_optional: false,
+        };
+        let envelop = lettre::address::Envelope::new(
+            Some("foo@domain.com".parse().unwrap()),
+            vec!["john.doe@domain.com".parse().unwrap()],
+        )
+        .unwrap();
+
+        let sender = Sender::default();
+        let error = sender
+            .send(&params, &envelop, b"Subject: test\r\n\r\nbody\r\n")
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        assert_eq!(error.to_string(), "the server does not support STARTTLS");
+        assert!(error.downcast_ref::<TranscriptError>().is_none());
+        assert!(sender.senders.read().unwrap().is_empty());
+    }
+    /// Deliver to `domains` at the same time, returns the maximum number of deliveries in progress.
+    async fn max_in_flight(
+        domains: &[&str],
+        in_flight_max: Option<usize>,
+        in_flight_per_domain_max: Option<usize>,
+    ) -> usize {
+        let sender = &Sender::default();
+        let current = &core::sync::atomic::AtomicUsize::new(0);
+        let max = &core::sync::atomic::AtomicUsize::new(0);
+
+        futures_util::future::join_all(domains.iter().map(|domain| async move {
+            let _slot = sender
+                .acquire_delivery_slot(domain, in_flight_max, in_flight_per_domain_max)
+                .await
+                .unwrap();
+
+            let now = current
+                .fetch_add(1, core::sync::atomic::Ordering::SeqCst)
+                .saturating_add(1);
+            max.fetch_max(now, core::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(core::time::Duration::from_millis(20)).await;
+            current.fetch_sub(1, core::sync::atomic::Ordering::SeqCst);
+        }))
+        .await;
+
+        max.load(core::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn unlimited_in_flight() {
+        assert_eq!(
+            max_in_flight(&["a", "b", "c", "d", "e"], None, None).await,
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight_max() {
+        assert_eq!(
+            max_in_flight(&["a", "b", "c", "d", "e"], Some(2), None).await,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight_per_domain_max() {
+        assert_eq!(max_in_flight(&["a", "a", "a", "b"], None, Some(1)).await, 2);
+    }
+
+    #[tokio::test]
+    async fn busy_domain_does_not_starve_others() {
+        // NOTE: the deliveries to `slow` waiting for their domain do not take
+        //       the second global slot, `fast` is delivered alongside.
+        assert_eq!(
+            max_in_flight(&["slow", "slow", "slow", "fast"], Some(2), Some(1)).await,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_domain_evicted() {
+        let sender = Sender::default();
+
+        let slot = sender
+            .acquire_delivery_slot("a", None, Some(1))
+            .await
+            .unwrap();
+        drop(slot);
+        let _slot = sender
+            .acquire_delivery_slot("b", None, Some(1))
+            .await
+            .unwrap();
+
+        let in_flight = sender.in_flight.lock().unwrap();
+        assert_eq!(
+            in_flight.per_domain.keys().collect::<Vec<_>>(),
+            vec![&"b".to_owned()]
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight_per_domain_max_changed() {
+        let sender = Sender::default();
+
+        let _first = sender
+            .acquire_delivery_slot("a", None, Some(1))
+            .await
+            .unwrap();
+        let _second = tokio::time::timeout(
+            core::time::Duration::from_millis(100),
+            sender.acquire_delivery_slot("a", None, Some(2)),
+        )
+        .await
+        .expect("the new limit of the domain applies")
+        .unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn tls_optional() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_smtp_server(listener));
+
+        let params = SenderParameters {
+            relay_target: "127.0.0.1".to_owned(),
+            server_name: "localhost".to_owned(),
+            hello_name: "testserver.com".to_owned(),
+            pool_idle_timeout: core::time::Duration::from_secs(60),
+            pool_max_size: 3,
+            pool_min_idle: 1,
+            port,
+            certificate: vec![],
+            tlsa: vec![],
+            capture_transcript: true,
+            auth_mailbox: None,
+            require_tls: false,
+            tls_optional: true,
+        };
+        let envelop = lettre::address::Envelope::new(
+            Some("foo@domain.com".parse().unwrap()),
+            vec!["john.doe@domain.com".parse().unwrap()],
+        )
+        .unwrap();
+
+        let error = Sender::default()
+            .send(&params, &envelop, b"TLS-Required: No\r\n\r\nbody\r\n")
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        // NOTE: the session goes on in clear text, until the mock refuses the transaction.
+        let transcript = &error.downcast_ref::<TranscriptError>().unwrap().transcript;
+        assert!(
+            transcript.contains(&"C: MAIL FROM:<foo@domain.com>".to_owned()),
+            "{transcript:?}"
+        );
+    }
+
+    #[test]
+    fn auth_mailbox_encoding() {
+        assert_eq!(encode_auth_mailbox("<>"), "<>");
+        assert_eq!(
+            encode_auth_mailbox("john.doe@domain.com"),
+            "john.doe@domain.com"
+        );
+        assert_eq!(
+            encode_auth_mailbox("john+doe=x y@domain.com"),
+            "john+2Bdoe+3Dx+20y@domain.com"
+        );
+    }
+}
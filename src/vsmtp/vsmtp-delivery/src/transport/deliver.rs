@@ -14,15 +14,22 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use super::Transport;
-use crate::{get_cert_for_server, to_lettre_envelope, Sender, SenderParameters};
-use trust_dns_resolver::TokioAsyncResolver;
+use super::{Capabilities, Transport};
+use crate::{
+    fetch_policy, get_cert_for_server, get_hello_name, is_dnssec_enabled, lookup_tlsa,
+    to_lettre_envelope, to_transfer_error, MtaStsMode, MtaStsPolicy, Sender, SenderParameters,
+    Tlsa, TranscriptError,
+};
+use trust_dns_resolver::{
+    proto::rr::{RData, RecordType},
+    TokioAsyncResolver,
+};
 use vsmtp_common::{
     rcpt::Rcpt,
     transfer::{EmailTransferStatus, TransferErrorsVariant},
     Address, ContextFinished, SMTP_PORT,
 };
-use vsmtp_config::Config;
+use vsmtp_config::{field::FieldQueueDeliveryMxCname, Config};
 extern crate alloc;
 
 /// the email will be sent to another mail exchanger via mx record resolution & smtp.
@@ -44,14 +51,26 @@ impl<'resolver> Deliver<'resolver> {
 }
 
 impl Deliver<'_> {
-    /// fetch mx records for a specific domain and order them by priority.
+    /// fetch mx records for a specific domain and order them by priority,
+    /// as a list of host and port.
+    ///
+    /// The records of the domain in `mx_overrides` are used instead of the dns if present.
     async fn get_mx_records(
         &self,
+        config: &Config,
         query: &str,
-    ) -> Result<
-        Vec<trust_dns_resolver::proto::rr::rdata::MX>,
-        trust_dns_resolver::error::ResolveError,
-    > {
+    ) -> Result<Vec<(String, u16)>, trust_dns_resolver::error::ResolveError> {
+        if let Some(overrides) = config.server.mx_overrides.get(&query.to_lowercase()) {
+            tracing::debug!("Using the static mx records of '{query}'.");
+
+            let mut records_by_priority = overrides.iter().collect::<Vec<_>>();
+            records_by_priority.sort_by_key(|record| record.priority);
+            return Ok(records_by_priority
+                .into_iter()
+                .map(|record| (record.host.clone(), record.port))
+                .collect());
+        }
+
         let mut records_by_priority = self
             .resolver
             .mx_lookup(query)
@@ -59,7 +78,125 @@ impl Deliver<'_> {
             .into_iter()
             .collect::<Vec<_>>();
         records_by_priority.sort_by_key(trust_dns_resolver::proto::rr::rdata::MX::preference);
-        Ok(records_by_priority)
+        Ok(records_by_priority
+            .into_iter()
+            .map(|record| (record.exchange().to_string(), SMTP_PORT))
+            .collect())
+    }
+
+    /// get the canonical name of the mail exchanger `mx` if it is an alias (CNAME).
+    async fn get_mx_alias(&self, mx: &str) -> Option<String> {
+        match self.resolver.lookup(mx, RecordType::CNAME).await {
+            Ok(lookup) => lookup.iter().find_map(|record| {
+                if let RData::CNAME(target) = record {
+                    Some(target.to_string())
+                } else {
+                    None
+                }
+            }),
+            Err(error) => {
+                tracing::trace!(%error, %mx, "No alias found for the mail exchanger.");
+                None
+            }
+        }
+    }
+
+    /// check that `domain`, without MX records, has an address to deliver to.
+    ///
+    /// A domain without A/AAAA records cannot be routed, which is a permanent failure
+    /// unless disabled by `no_route_permanent`.
+    async fn check_implicit_mx(
+        &self,
+        config: &Config,
+        domain: &str,
+    ) -> Result<(), TransferErrorsVariant> {
+        match self.resolver.lookup_ip(domain).await {
+            Ok(_) => Ok(()),
+            Err(error)
+                if is_no_records_found(&error)
+                    && config.server.queues.delivery.no_route_permanent =>
+            {
+                tracing::error!(%domain, "The domain has neither MX nor A/AAAA records.");
+                Err(TransferErrorsVariant::NoRoute {
+                    domain: domain.to_owned(),
+                })
+            }
+            Err(error) => Err(TransferErrorsVariant::DnsRecord {
+                error: error.to_string(),
+            }),
+        }
+    }
+
+    /// get the MTA-STS policy of `domain`, from the cache or fetched.
+    ///
+    /// A policy which cannot be fetched is ignored (RFC 8461 5), and the domains
+    /// without policy are not looked up again for a while.
+    async fn get_mta_sts_policy(&self, domain: &str) -> Option<alloc::sync::Arc<MtaStsPolicy>> {
+        if let Some(policy) = self.senders.mta_sts().get(domain) {
+            return Some(policy);
+        }
+        if self.senders.mta_sts().has_no_policy(domain) {
+            return None;
+        }
+
+        match fetch_policy(self.resolver, domain).await {
+            Ok(Some(policy)) => Some(self.senders.mta_sts().insert(domain, policy)),
+            Ok(None) => {
+                self.senders.mta_sts().insert_none(domain);
+                None
+            }
+            Err(error) => {
+                tracing::warn!(%error, %domain, "Failed to fetch the MTA-STS policy.");
+                self.senders.mta_sts().insert_none(domain);
+                None
+            }
+        }
+    }
+
+    /// fetch the TLSA records used to authenticate the mail exchanger `host:port` of `domain`.
+    ///
+    /// The records are ignored if the resolver does not validate DNSSEC, and a failure to
+    /// fetch them is an error only if `domain` requires DANE, and the message does not
+    /// ask to ignore it (`tls_optional`).
+    async fn get_tlsa_records(
+        &self,
+        config: &Config,
+        domain: &str,
+        host: &str,
+        port: u16,
+        tls_optional: bool,
+    ) -> Result<Vec<Tlsa>, TransferErrorsVariant> {
+        let required =
+            !tls_optional && config.server.queues.delivery.dane_required.contains(domain);
+
+        if !is_dnssec_enabled(domain, config) {
+            if required {
+                return Err(TransferErrorsVariant::TlsaMismatch {
+                    error: format!(
+                        "'{domain}' requires DANE, but the resolver does not validate DNSSEC"
+                    ),
+                });
+            }
+            return Ok(vec![]);
+        }
+
+        match lookup_tlsa(self.resolver, host, port).await {
+            Ok(records) if records.is_empty() && required => {
+                Err(TransferErrorsVariant::TlsaMismatch {
+                    error: format!(
+                        "'{domain}' requires DANE, but '{host}' has no usable TLSA records"
+                    ),
+                })
+            }
+            Ok(records) => Ok(records),
+            Err(error) if required => Err(TransferErrorsVariant::TlsaMismatch {
+                error: format!("failed to fetch the TLSA records of '{host}': {error}"),
+            }),
+            Err(error) => {
+                tracing::warn!(%error, %host, "Failed to fetch the TLSA records, delivering without DANE.");
+                Ok(vec![])
+            }
+        }
     }
 
     async fn deliver_one_domain(
@@ -68,11 +205,11 @@ impl Deliver<'_> {
         ctx: &ContextFinished,
         message: &str,
         from: &Option<Address>,
-        domain: String,
+        domain: &str,
         mut rcpt: Vec<Rcpt>,
     ) -> Vec<Rcpt> {
         match self
-            .deliver_one_domain_inner(config, ctx, message, from, &domain, &rcpt)
+            .deliver_one_domain_inner(config, ctx, message, from, domain, &rcpt)
             .await
         {
             Ok(()) => {
@@ -107,6 +244,7 @@ impl Deliver<'_> {
         }
     }
 
+    #[allow(clippy::too_many_lines)]
     async fn deliver_one_domain_inner(
         &self,
         config: &Config,
@@ -119,48 +257,126 @@ impl Deliver<'_> {
         let envelop = to_lettre_envelope(from, rcpt);
         tracing::trace!(?envelop);
 
-        let records =
-            self.get_mx_records(domain)
-                .await
-                .map_err(|e| TransferErrorsVariant::DnsRecord {
-                    error: e.to_string(),
-                })?;
+        let nxdomain_cache = config.server.queues.delivery.nxdomain_cache;
+        if nxdomain_cache.is_some() && self.senders.nxdomain().contains(domain) {
+            tracing::debug!(%domain, "The domain does not exist (cached), skipping the lookup.");
+            return Err(TransferErrorsVariant::NoSuchDomain {
+                domain: domain.to_owned(),
+            });
+        }
+
+        let records = match (self.get_mx_records(config, domain).await, nxdomain_cache) {
+            (Ok(records), _) => records,
+            (Err(error), Some(duration)) if is_nxdomain(&error) => {
+                tracing::error!(%domain, "The domain does not exist.");
+                self.senders.nxdomain().insert(domain, duration);
+                return Err(TransferErrorsVariant::NoSuchDomain {
+                    domain: domain.to_owned(),
+                });
+            }
+            // NOTE: without MX records, the domain is its own mail exchanger.
+            (Err(error), _) if is_no_records_found(&error) => vec![],
+            (Err(error), _) => {
+                return Err(TransferErrorsVariant::DnsRecord {
+                    error: error.to_string(),
+                })
+            }
+        };
         tracing::trace!(?records);
 
+        // NOTE: the `TLS-Required` header is ignored if the message requires TLS (RFC 8689 4.1).
+        let require_tls = ctx.mail_from.require_tls;
+        let tls_optional = !require_tls && is_tls_optional(message);
+        if tls_optional {
+            tracing::info!(%domain, "The message asks to ignore the TLS policies of the domain.");
+        }
+
+        let policy = if tls_optional || !config.server.queues.delivery.mta_sts {
+            None
+        } else {
+            self.get_mta_sts_policy(domain)
+                .await
+                .filter(|policy| policy.mode != MtaStsMode::None)
+        };
+        // NOTE: with an enforced policy, the certificate must be valid for the mail exchanger.
+        let enforced = policy
+            .as_ref()
+            .map_or(false, |policy| policy.mode == MtaStsMode::Enforce);
+        // NOTE: the static mx records are trusted.
+        let from_dns = !config
+            .server
+            .mx_overrides
+            .contains_key(&domain.to_lowercase());
+
+        // NOTE: the mail exchangers must be authenticated to relay a message requiring TLS (RFC 8689 4.2.1).
+        if require_tls && from_dns && policy.is_none() && !is_dnssec_enabled(domain, config) {
+            return Err(TransferErrorsVariant::RequireTls {
+                error: format!(
+                    "the mail exchangers of '{domain}' are not authenticated by DNSSEC nor MTA-STS"
+                ),
+            });
+        }
+
         if records.is_empty() {
             // using directly the AAAA record instead of an mx record.
             // see https://www.rfc-editor.org/rfc/rfc5321#section-5.1
             tracing::warn!("empty set of MX records found for '{domain}'");
 
-            self.senders
-                .send(
-                    &SenderParameters {
-                        relay_target: domain.to_owned(),
-                        server_name: domain.to_owned(),
-                        hello_name: ctx.connect.server_name.clone(),
-                        pool_idle_timeout: core::time::Duration::from_secs(60),
-                        pool_max_size: 3,
-                        pool_min_idle: 1,
-                        port: SMTP_PORT,
-                        certificate: get_cert_for_server(&ctx.connect.server_name, config)
-                            .ok_or(TransferErrorsVariant::TlsNoCertificate {})?,
-                    },
-                    &envelop,
-                    message.as_bytes(),
-                )
-                .await
-                .map_err(|e| TransferErrorsVariant::Smtp {
-                    error: e.to_string(),
-                })?;
+            self.check_implicit_mx(config, domain).await?;
+
+            if let Some(error) = policy
+                .as_ref()
+                .and_then(|policy| check_mta_sts_policy(policy, domain, domain))
+            {
+                return Err(error);
+            }
+
+            let params = SenderParameters {
+                relay_target: domain.to_owned(),
+                server_name: domain.to_owned(),
+                hello_name: get_hello_name(from, &ctx.connect.server_name, config),
+                pool_idle_timeout: config.server.queues.delivery.pool.idle_timeout,
+                pool_max_size: config.server.queues.delivery.pool.max_size,
+                pool_min_idle: config.server.queues.delivery.pool.min_idle,
+                port: SMTP_PORT,
+                certificate: get_cert_for_server(&ctx.connect.server_name, config)
+                    .ok_or(TransferErrorsVariant::TlsNoCertificate {})?,
+                capture_transcript: config.server.queues.delivery.capture_transcript,
+                tlsa: self
+                    .get_tlsa_records(config, domain, domain, SMTP_PORT, tls_optional)
+                    .await?,
+                auth_mailbox: ctx.mail_from.auth_mailbox.clone(),
+                require_tls,
+                tls_optional,
+            };
+
+            with_retry(
+                config.server.queues.delivery.connect_retry_max,
+                config.server.queues.delivery.connect_retry_delay,
+                is_connection_error,
+                || self.senders.send(&params, &envelop, message.as_bytes()),
+            )
+            .await
+            .map_err(|e| to_transfer_error(&e))?;
             return Ok(());
         }
 
-        let mxs = records
-            .into_iter()
-            .map(|r| r.exchange().to_string())
-            .collect::<Vec<_>>();
+        let mut dane_error = None;
+        let mut mta_sts_error = None;
+        let mut cname_error = None;
+        let mut require_tls_error = None;
 
-        for mx in &mxs {
+        let mx_max = config.server.queues.delivery.mx_max.unwrap_or(usize::MAX);
+        if records.len() > mx_max {
+            tracing::warn!(
+                count = records.len(),
+                mx_max,
+                "Too many MX records found for '{domain}', only the first ones are tried."
+            );
+        }
+        let records = records.into_iter().take(mx_max).collect::<Vec<_>>();
+
+        for (mx, port) in &records {
             tracing::debug!("Trying to send an email.");
             tracing::trace!(%mx);
 
@@ -176,24 +392,75 @@ impl Deliver<'_> {
                 });
             }
 
-            match self
-                .senders
-                .send(
-                    &SenderParameters {
-                        relay_target: mx.clone(),
-                        server_name: domain.to_owned(),
-                        hello_name: ctx.connect.server_name.clone(),
-                        pool_idle_timeout: core::time::Duration::from_secs(60),
-                        pool_max_size: 3,
-                        pool_min_idle: 1,
-                        port: SMTP_PORT,
-                        certificate: get_cert_for_server(&ctx.connect.server_name, config)
-                            .ok_or(TransferErrorsVariant::TlsNoCertificate {})?,
-                    },
-                    &envelop,
-                    message.as_bytes(),
-                )
+            if let Some(error) = policy
+                .as_ref()
+                .and_then(|policy| check_mta_sts_policy(policy, domain, mx))
+            {
+                mta_sts_error = Some(error);
+                continue;
+            }
+
+            let alias = if from_dns {
+                self.get_mx_alias(mx).await
+            } else {
+                None
+            };
+            if let Some(target) = alias {
+                match config.server.queues.delivery.mx_cname {
+                    FieldQueueDeliveryMxCname::Follow => {
+                        tracing::warn!(%mx, %target, "The mail exchanger is an alias (CNAME), following it.");
+                    }
+                    FieldQueueDeliveryMxCname::Reject => {
+                        tracing::error!(%mx, %target, "The mail exchanger is an alias (CNAME), skipping it.");
+                        cname_error = Some(TransferErrorsVariant::MxIsAlias {
+                            mx: mx.clone(),
+                            target,
+                        });
+                        continue;
+                    }
+                }
+            }
+
+            let tlsa = match self
+                .get_tlsa_records(config, domain, mx, *port, tls_optional)
                 .await
+            {
+                Ok(tlsa) => tlsa,
+                Err(error) => {
+                    tracing::error!(?mx, ?error, "failed to authenticate the server with DANE");
+                    dane_error = Some(error);
+                    continue;
+                }
+            };
+
+            let params = SenderParameters {
+                relay_target: mx.clone(),
+                server_name: if enforced {
+                    mx.trim_end_matches('.').to_owned()
+                } else {
+                    domain.to_owned()
+                },
+                hello_name: get_hello_name(from, &ctx.connect.server_name, config),
+                pool_idle_timeout: config.server.queues.delivery.pool.idle_timeout,
+                pool_max_size: config.server.queues.delivery.pool.max_size,
+                pool_min_idle: config.server.queues.delivery.pool.min_idle,
+                port: *port,
+                certificate: get_cert_for_server(&ctx.connect.server_name, config)
+                    .ok_or(TransferErrorsVariant::TlsNoCertificate {})?,
+                capture_transcript: config.server.queues.delivery.capture_transcript,
+                tlsa,
+                auth_mailbox: ctx.mail_from.auth_mailbox.clone(),
+                require_tls,
+                tls_optional,
+            };
+
+            match with_retry(
+                config.server.queues.delivery.connect_retry_max,
+                config.server.queues.delivery.connect_retry_delay,
+                is_connection_error,
+                || self.senders.send(&params, &envelop, message.as_bytes()),
+            )
+            .await
             {
                 Ok(response) => {
                     tracing::info!("Email sent successfully");
@@ -208,16 +475,200 @@ impl Deliver<'_> {
                         %err,
                         "failed to send message"
                     );
+                    if let Some(error) = err.downcast_ref::<TranscriptError>() {
+                        tracing::debug!(?mx, transcript = ?error.transcript);
+                    }
+                    #[allow(clippy::wildcard_enum_match_arm)]
+                    match to_transfer_error(&err) {
+                        error @ TransferErrorsVariant::TlsaMismatch { .. } => {
+                            dane_error = Some(error);
+                        }
+                        error @ TransferErrorsVariant::RequireTls { .. } => {
+                            require_tls_error = Some(error);
+                        }
+                        _ => {}
+                    }
                 }
             }
         }
 
-        Err(TransferErrorsVariant::DeliveryError { targets: mxs })
+        // NOTE: a server failing the authentication is reported rather than silently
+        //       hidden behind a generic delivery error.
+        Err(require_tls_error
+            .or(dane_error)
+            .or(mta_sts_error)
+            .or(cname_error)
+            .unwrap_or_else(|| TransferErrorsVariant::DeliveryError {
+                targets: records.into_iter().map(|(mx, _)| mx).collect(),
+            }))
     }
 }
 
+/// Check that the mail exchanger `mx` of `domain` is allowed by its MTA-STS `policy`,
+/// returning the error preventing the delivery if the policy is enforced.
+fn check_mta_sts_policy(
+    policy: &MtaStsPolicy,
+    domain: &str,
+    mx: &str,
+) -> Option<TransferErrorsVariant> {
+    if policy.matches(mx) {
+        return None;
+    }
+
+    if policy.mode == MtaStsMode::Enforce {
+        tracing::error!(%mx, %domain, "The mail exchanger does not match the MTA-STS policy.");
+        return Some(TransferErrorsVariant::MtaSts {
+            error: format!("'{mx}' does not match the MTA-STS policy of '{domain}'"),
+        });
+    }
+
+    tracing::warn!(%mx, %domain, "The mail exchanger does not match the MTA-STS policy, testing mode.");
+    None
+}
+
+/// Does the message ask to deliver it even if the TLS policies (MTA-STS, DANE) of the
+/// recipient domain are not met, with the `TLS-Required: No` header (RFC 8689 5).
+fn is_tls_optional(message: &str) -> bool {
+    message
+        .lines()
+        .take_while(|line| !line.trim_end_matches('\r').is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("TLS-Required")
+                && value.trim().eq_ignore_ascii_case("No")
+        })
+}
+
+/// Is the lookup answered with no records (`NXDOMAIN` or an empty answer),
+/// rather than failed.
+fn is_no_records_found(error: &trust_dns_resolver::error::ResolveError) -> bool {
+    matches!(
+        error.kind(),
+        trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. }
+    )
+}
+
+/// Is the lookup answered with `NXDOMAIN`: the domain does not exist at all.
+fn is_nxdomain(error: &trust_dns_resolver::error::ResolveError) -> bool {
+    matches!(
+        error.kind(),
+        trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound {
+            response_code: trust_dns_resolver::proto::op::ResponseCode::NXDomain,
+            ..
+        }
+    )
+}
+
+/// Run `operation`, and retry it up to `retry_max` times after `delay`
+/// as long as it fails with an error accepted by `is_retryable`.
+async fn with_retry<T, Fut>(
+    retry_max: usize,
+    delay: core::time::Duration,
+    is_retryable: impl Fn(&anyhow::Error) -> bool,
+    mut operation: impl FnMut() -> Fut,
+) -> anyhow::Result<T>
+where
+    Fut: core::future::Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt: usize = 0;
+    loop {
+        match operation().await {
+            Err(error) if attempt < retry_max && is_retryable(&error) => {
+                attempt = attempt.saturating_add(1);
+                tracing::warn!(%error, %attempt, "Connection failed, retrying.");
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Split `rcpt` in groups of at most `rcpt_max` recipients (a single group if `None`),
+/// and run `transaction` for each group one after the other, returning the updated recipients.
+async fn in_transactions<Fut>(
+    rcpt: Vec<Rcpt>,
+    rcpt_max: Option<usize>,
+    mut transaction: impl FnMut(Vec<Rcpt>) -> Fut,
+) -> Vec<Rcpt>
+where
+    Fut: core::future::Future<Output = Vec<Rcpt>>,
+{
+    let size = rcpt_max.unwrap_or(rcpt.len()).max(1);
+    let mut updated = Vec::with_capacity(rcpt.len());
+
+    let mut rcpt = rcpt.into_iter().peekable();
+    while rcpt.peek().is_some() {
+        updated.extend(transaction(rcpt.by_ref().take(size).collect()).await);
+    }
+
+    updated
+}
+
+/// Run `transaction` for `rcpt` until `deadline` if any, after which it is cancelled
+/// and the recipients are deferred.
+async fn within_budget<Fut>(
+    deadline: Option<tokio::time::Instant>,
+    rcpt: Vec<Rcpt>,
+    transaction: impl FnOnce(Vec<Rcpt>) -> Fut,
+) -> Vec<Rcpt>
+where
+    Fut: core::future::Future<Output = Vec<Rcpt>>,
+{
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return transaction(rcpt).await,
+    };
+
+    match tokio::time::timeout_at(deadline, transaction(rcpt.clone())).await {
+        Ok(updated) => updated,
+        Err(_elapsed) => budget_exhausted(rcpt),
+    }
+}
+
+/// Defer `rcpt`, the time budget of the delivery being over.
+fn budget_exhausted(rcpt: Vec<Rcpt>) -> Vec<Rcpt> {
+    tracing::warn!(
+        rcpt = ?rcpt.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "Delivery time budget exhausted, deferring the recipients."
+    );
+    rcpt.into_iter()
+        .map(|mut i| {
+            i.email_status
+                .held_back(TransferErrorsVariant::TimeBudgetExhausted);
+            i
+        })
+        .collect()
+}
+
+/// Is the error produced by the network (connection refused / reset, timeout ...),
+/// rather than by a reply of the remote server.
+fn is_connection_error(error: &anyhow::Error) -> bool {
+    let error = error
+        .downcast_ref::<TranscriptError>()
+        .map_or(error, |e| &e.error);
+
+    error
+        .downcast_ref::<lettre::transport::smtp::Error>()
+        .map_or(false, |error| {
+            !error.is_permanent() && !error.is_transient() && !error.is_client()
+        })
+}
+
 #[async_trait::async_trait]
 impl Transport for Deliver<'_> {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "deliver"
+    }
+
+    #[inline]
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            local: false,
+            multi_recipient: true,
+        }
+    }
+
     #[inline]
     async fn deliver(
         self,
@@ -235,8 +686,48 @@ impl Transport for Deliver<'_> {
                 .or_insert_with(|| vec![rcpt.clone()]);
         }
 
-        let futures = rcpt_by_domain.into_iter().map(|(domain, rcpt)| {
-            self.deliver_one_domain(config, ctx, message, from, domain, rcpt)
+        // NOTE: the recipients not delivered when the budget is over are deferred.
+        let deadline = config
+            .server
+            .queues
+            .delivery
+            .time_budget
+            .map(|budget| tokio::time::Instant::now() + budget);
+
+        let this = &self;
+        let futures = rcpt_by_domain.into_iter().map(|(domain, rcpt)| async move {
+            let slot = this.senders.acquire_delivery_slot(
+                &domain,
+                config.server.queues.delivery.in_flight_max,
+                config.server.queues.delivery.in_flight_per_domain_max,
+            );
+            // NOTE: the wait for a slot counts in the time budget.
+            let slot = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, slot).await {
+                    Ok(slot) => slot,
+                    Err(_elapsed) => return budget_exhausted(rcpt),
+                },
+                None => slot.await,
+            };
+            let _slot = match slot {
+                Ok(slot) => Some(slot),
+                Err(error) => {
+                    tracing::error!(%error, %domain, "Failed to acquire a delivery slot.");
+                    None
+                }
+            };
+
+            let domain = domain.as_str();
+            in_transactions(
+                rcpt,
+                config.server.queues.delivery.rcpt_per_transaction_max,
+                |rcpt| {
+                    within_budget(deadline, rcpt, |rcpt| {
+                        this.deliver_one_domain(config, ctx, message, from, domain, rcpt)
+                    })
+                },
+            )
+            .await
         });
 
         futures_util::future::join_all(futures)
@@ -254,15 +745,40 @@ mod test {
         transport::{deliver::Deliver, Transport},
         Sender,
     };
+    use core::str::FromStr;
     use trust_dns_resolver::{
         config::{ResolverConfig, ResolverOpts},
+        proto::rr::{
+            rdata::{MX, TXT},
+            Name, Record,
+        },
         TokioAsyncResolver,
     };
     use vsmtp_common::{
         rcpt::Rcpt,
-        transfer::{EmailTransferStatus, Transfer, TransferErrorsVariant},
+        transfer::{EmailTransferStatus, NotifyOn, Transfer, TransferErrorsVariant},
+    };
+    use vsmtp_config::field::{FieldServerMxOverride, FieldServerVirtual, FieldServerVirtualTls};
+    use vsmtp_test::{
+        config::{local_ctx, local_msg, local_test},
+        dns::MockDns,
     };
-    use vsmtp_test::config::{local_ctx, local_msg, local_test};
+
+    #[test_log::test(tokio::test)]
+    async fn capabilities() {
+        let resolver =
+            TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap();
+        let deliver = Deliver::new(&resolver, alloc::sync::Arc::new(Sender::default()));
+
+        assert_eq!(deliver.name(), "deliver");
+        assert_eq!(
+            deliver.capabilities(),
+            Capabilities {
+                local: false,
+                multi_recipient: true
+            }
+        );
+    }
 
     #[test_log::test(tokio::test)]
     async fn test_delivery() {
@@ -282,20 +798,775 @@ mod test {
                 address: "root@foo.bar".parse().unwrap(),
                 transfer_method: Transfer::Deliver,
                 email_status: EmailTransferStatus::default(),
+                notify_on: NotifyOn::default(),
+                original_forward_path: None,
             }],
             &msg.inner().to_string(),
         )
         .await;
 
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::Failed { error } => assert_eq!(
+                error.variant,
+                TransferErrorsVariant::NoRoute {
+                    domain: "foo.bar".to_owned(),
+                }
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn hello_name_per_sending_domain() {
+        let mut config = local_test();
+        config
+            .server
+            .ehlo_names
+            .insert("a.example".to_owned(), "mail.a.example".to_owned());
+
+        assert_eq!(
+            get_hello_name(
+                &Some("john.doe@a.example".parse().unwrap()),
+                "testserver.com",
+                &config
+            ),
+            "mail.a.example"
+        );
+        assert_eq!(
+            get_hello_name(
+                &Some("john.doe@b.example".parse().unwrap()),
+                "testserver.com",
+                &config
+            ),
+            "testserver.com"
+        );
+        assert_eq!(
+            get_hello_name(&None, "testserver.com", &config),
+            "testserver.com"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn retry_until_success() {
+        let attempts = &std::sync::atomic::AtomicUsize::new(0);
+
+        let result = with_retry(
+            3,
+            core::time::Duration::from_millis(10),
+            |_| true,
+            || async move {
+                match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => Err(anyhow::anyhow!("connection reset by peer")),
+                    _ => Ok("250 Ok"),
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "250 Ok");
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn retry_exhausted() {
+        let attempts = &std::sync::atomic::AtomicUsize::new(0);
+
+        let result = with_retry(
+            2,
+            core::time::Duration::from_millis(10),
+            |_| true,
+            || async move {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err::<(), _>(anyhow::anyhow!("connection reset by peer"))
+            },
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn no_retry_on_other_errors() {
+        let attempts = &std::sync::atomic::AtomicUsize::new(0);
+
+        let result = with_retry(
+            2,
+            core::time::Duration::from_millis(10),
+            is_connection_error,
+            || async move {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err::<(), _>(anyhow::anyhow!("not a connection error"))
+            },
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    fn with_mx_overrides() -> Config {
+        let mut config = local_test();
+        config.server.mx_overrides.insert(
+            "foo.bar".to_owned(),
+            vec![
+                FieldServerMxOverride {
+                    host: "mx2.foo.bar".to_owned(),
+                    port: 10025,
+                    priority: 20,
+                },
+                FieldServerMxOverride {
+                    host: "127.0.0.1".to_owned(),
+                    port: 10025,
+                    priority: 10,
+                },
+            ],
+        );
+        config
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn mx_overrides() {
+        let config = with_mx_overrides();
+        let resolver =
+            TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap();
+        let deliver = Deliver::new(&resolver, alloc::sync::Arc::new(Sender::default()));
+
+        assert_eq!(
+            deliver.get_mx_records(&config, "foo.bar").await.unwrap(),
+            vec![
+                ("127.0.0.1".to_owned(), 10025),
+                ("mx2.foo.bar".to_owned(), 10025)
+            ]
+        );
+        assert_eq!(
+            deliver.get_mx_records(&config, "Foo.BAR").await.unwrap(),
+            deliver.get_mx_records(&config, "foo.bar").await.unwrap(),
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_delivery_with_mx_overrides() {
+        let config = with_mx_overrides();
+        let ctx = local_ctx();
+        let msg = local_msg();
+
+        let updated_rcpt = Deliver::new(
+            &TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap(),
+            alloc::sync::Arc::new(Sender::default()),
+        )
+        .deliver(
+            &config,
+            &ctx,
+            &Some("root@foo.bar".parse().unwrap()),
+            vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+            &msg.inner().to_string(),
+        )
+        .await;
+
+        // NOTE: the dns is not queried for `foo.bar` anymore, the delivery to the
+        //       overriding host then stops because the test config has no certificate.
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::HeldBack { errors } => assert_eq!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::TlsNoCertificate {}
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn dane_required_without_dnssec() {
+        let mut config = with_mx_overrides();
+        config
+            .server
+            .queues
+            .delivery
+            .dane_required
+            .insert("foo.bar".to_owned());
+        let ctx = local_ctx();
+        let msg = local_msg();
+
+        let updated_rcpt = Deliver::new(
+            &TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap(),
+            alloc::sync::Arc::new(Sender::default()),
+        )
+        .deliver(
+            &config,
+            &ctx,
+            &Some("root@foo.bar".parse().unwrap()),
+            vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+            &msg.inner().to_string(),
+        )
+        .await;
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::HeldBack { errors } => assert_eq!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::TlsaMismatch {
+                    error: "'foo.bar' requires DANE, but the resolver does not validate DNSSEC"
+                        .to_owned()
+                }
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn mta_sts_enforced() {
+        let mut config = with_mx_overrides();
+        config.server.queues.delivery.mta_sts = true;
+        let ctx = local_ctx();
+        let msg = local_msg();
+
+        let sender = alloc::sync::Arc::new(Sender::default());
+        sender.mta_sts().insert(
+            "foo.bar",
+            "version: STSv1\nmode: enforce\nmx: mx.foo.bar\nmax_age: 86400\n"
+                .parse()
+                .unwrap(),
+        );
+
+        let updated_rcpt = Deliver::new(
+            &TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap(),
+            sender,
+        )
+        .deliver(
+            &config,
+            &ctx,
+            &Some("root@foo.bar".parse().unwrap()),
+            vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+            &msg.inner().to_string(),
+        )
+        .await;
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::HeldBack { errors } => assert_eq!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::MtaSts {
+                    error: "'mx2.foo.bar' does not match the MTA-STS policy of 'foo.bar'"
+                        .to_owned()
+                }
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn mx_max() {
+        let mut config = local_test();
+        config.server.queues.delivery.mx_max = Some(3);
+        config.server.mx_overrides.insert(
+            "foo.bar".to_owned(),
+            (1..=10)
+                .map(|i| FieldServerMxOverride {
+                    host: format!("127.0.0.{i}"),
+                    // NOTE: nothing listens on this port, the connections are refused.
+                    port: 1,
+                    priority: i,
+                })
+                .collect(),
+        );
+        config.server.r#virtual.insert(
+            "testserver.com".to_owned(),
+            FieldServerVirtual {
+                tls: Some(
+                    FieldServerVirtualTls::from_path(
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/../vsmtp-test/src/template/certs/certificate.crt"
+                        ),
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/../vsmtp-test/src/template/certs/private_key.rsa.key"
+                        ),
+                    )
+                    .unwrap(),
+                ),
+                dns: None,
+                dkim: None,
+                rcpt_count_max: None,
+                message_size_limit: None,
+                codes: std::collections::BTreeMap::new(),
+            },
+        );
+
+        let updated_rcpt = Deliver::new(
+            &TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap(),
+            alloc::sync::Arc::new(Sender::default()),
+        )
+        .deliver(
+            &config,
+            &local_ctx(),
+            &Some("root@foo.bar".parse().unwrap()),
+            vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+            &local_msg().inner().to_string(),
+        )
+        .await;
+
         #[allow(clippy::wildcard_enum_match_arm)]
         match &updated_rcpt.first().unwrap().email_status {
             EmailTransferStatus::HeldBack { errors } => assert_eq!(
                 errors.first().unwrap().variant,
-                TransferErrorsVariant::DnsRecord {
-                    error: "no record found for Query { name: Name(\"foo.bar.\"), query_type: MX, query_class: IN }".to_owned(),
+                TransferErrorsVariant::DeliveryError {
+                    targets: vec![
+                        "127.0.0.1".to_owned(),
+                        "127.0.0.2".to_owned(),
+                        "127.0.0.3".to_owned()
+                    ],
                 }
             ),
             _ => panic!(),
         }
     }
+
+    #[test_log::test(tokio::test)]
+    async fn time_budget() {
+        // NOTE: the server accepts the connections, but never sends its greeting.
+        let slow = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let slow_port = slow.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut clients = vec![];
+            loop {
+                clients.push(slow.accept().await.unwrap());
+            }
+        });
+
+        let mut config = local_test();
+        config.server.queues.delivery.time_budget = Some(core::time::Duration::from_millis(500));
+        config.server.mx_overrides.insert(
+            "slow.bar".to_owned(),
+            vec![FieldServerMxOverride {
+                host: "127.0.0.1".to_owned(),
+                port: slow_port,
+                priority: 10,
+            }],
+        );
+        config.server.mx_overrides.insert(
+            "foo.bar".to_owned(),
+            vec![FieldServerMxOverride {
+                host: "127.0.0.1".to_owned(),
+                // NOTE: nothing listens on this port, the connections are refused.
+                port: 1,
+                priority: 10,
+            }],
+        );
+        config.server.r#virtual.insert(
+            "testserver.com".to_owned(),
+            FieldServerVirtual {
+                tls: Some(
+                    FieldServerVirtualTls::from_path(
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/../vsmtp-test/src/template/certs/certificate.crt"
+                        ),
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/../vsmtp-test/src/template/certs/private_key.rsa.key"
+                        ),
+                    )
+                    .unwrap(),
+                ),
+                dns: None,
+                dkim: None,
+                rcpt_count_max: None,
+                message_size_limit: None,
+                codes: std::collections::BTreeMap::new(),
+            },
+        );
+
+        // NOTE: the MTA-STS policies are not found right away.
+        let dns = MockDns::serve(vec![]).await;
+
+        let start = std::time::Instant::now();
+        let updated_rcpt = Deliver::new(&dns.resolver(), alloc::sync::Arc::new(Sender::default()))
+            .deliver(
+                &config,
+                &local_ctx(),
+                &Some("root@foo.bar".parse().unwrap()),
+                vec![
+                    Rcpt::new("john@slow.bar".parse().unwrap()),
+                    Rcpt::new("jane@foo.bar".parse().unwrap()),
+                ],
+                &local_msg().inner().to_string(),
+            )
+            .await;
+        assert!(start.elapsed() < core::time::Duration::from_secs(5));
+
+        let status_of = |address: &str| {
+            #[allow(clippy::wildcard_enum_match_arm)]
+            match &updated_rcpt
+                .iter()
+                .find(|i| i.address.full() == address)
+                .unwrap()
+                .email_status
+            {
+                EmailTransferStatus::HeldBack { errors } => errors.first().unwrap().variant.clone(),
+                _ => panic!(),
+            }
+        };
+        assert_eq!(
+            status_of("john@slow.bar"),
+            TransferErrorsVariant::TimeBudgetExhausted
+        );
+        assert_eq!(
+            status_of("jane@foo.bar"),
+            TransferErrorsVariant::DeliveryError {
+                targets: vec!["127.0.0.1".to_owned()],
+            }
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn time_budget_waiting_for_a_slot() {
+        let mut config = local_test();
+        config.server.queues.delivery.time_budget = Some(core::time::Duration::from_millis(200));
+        config.server.queues.delivery.in_flight_per_domain_max = Some(1);
+
+        let sender = alloc::sync::Arc::new(Sender::default());
+        // NOTE: another delivery to the domain holds its only slot.
+        let _slot = sender
+            .acquire_delivery_slot("foo.bar", None, Some(1))
+            .await
+            .unwrap();
+
+        let start = std::time::Instant::now();
+        let updated_rcpt = Deliver::new(
+            &TokioAsyncResolver::tokio(ResolverConfig::google(), ResolverOpts::default()).unwrap(),
+            sender.clone(),
+        )
+        .deliver(
+            &config,
+            &local_ctx(),
+            &Some("root@foo.bar".parse().unwrap()),
+            vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+            &local_msg().inner().to_string(),
+        )
+        .await;
+        assert!(start.elapsed() < core::time::Duration::from_secs(5));
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::HeldBack { errors } => assert_eq!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::TimeBudgetExhausted
+            ),
+            _ => panic!(),
+        }
+    }
+
+    async fn mock_dns_with_cname_at_mx() -> MockDns {
+        let name = |name: &str| Name::from_str(name).unwrap();
+
+        MockDns::serve(vec![
+            Record::from_rdata(
+                name("foo.bar."),
+                300,
+                RData::MX(MX::new(10, name("mx.foo.bar."))),
+            ),
+            Record::from_rdata(
+                name("mx.foo.bar."),
+                300,
+                RData::CNAME(name("real.foo.bar.")),
+            ),
+            Record::from_rdata(
+                name("real.foo.bar."),
+                300,
+                RData::A(std::net::Ipv4Addr::LOCALHOST),
+            ),
+        ])
+        .await
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn mx_cname_rejected() {
+        let mut config = local_test();
+        config.server.queues.delivery.mx_cname = FieldQueueDeliveryMxCname::Reject;
+        let dns = mock_dns_with_cname_at_mx().await;
+
+        let updated_rcpt = Deliver::new(&dns.resolver(), alloc::sync::Arc::new(Sender::default()))
+            .deliver(
+                &config,
+                &local_ctx(),
+                &Some("root@foo.bar".parse().unwrap()),
+                vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+                &local_msg().inner().to_string(),
+            )
+            .await;
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::HeldBack { errors } => assert_eq!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::MxIsAlias {
+                    mx: "mx.foo.bar.".to_owned(),
+                    target: "real.foo.bar.".to_owned(),
+                }
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn mx_cname_followed() {
+        let config = local_test();
+        let dns = mock_dns_with_cname_at_mx().await;
+
+        let updated_rcpt = Deliver::new(&dns.resolver(), alloc::sync::Arc::new(Sender::default()))
+            .deliver(
+                &config,
+                &local_ctx(),
+                &Some("root@foo.bar".parse().unwrap()),
+                vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+                &local_msg().inner().to_string(),
+            )
+            .await;
+
+        // NOTE: the delivery goes on with the alias, then stops because the test
+        //       config has no certificate.
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::HeldBack { errors } => assert_eq!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::TlsNoCertificate {}
+            ),
+            _ => panic!(),
+        }
+    }
+
+    async fn deliver_with_dns(
+        config: &Config,
+        resolver: &TokioAsyncResolver,
+    ) -> EmailTransferStatus {
+        Deliver::new(resolver, alloc::sync::Arc::new(Sender::default()))
+            .deliver(
+                config,
+                &local_ctx(),
+                &Some("root@foo.bar".parse().unwrap()),
+                vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+                &local_msg().inner().to_string(),
+            )
+            .await
+            .first()
+            .unwrap()
+            .email_status
+            .clone()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn no_route() {
+        let dns = MockDns::serve(vec![Record::from_rdata(
+            Name::from_str("foo.bar.").unwrap(),
+            300,
+            RData::TXT(TXT::new(vec!["v=spf1 -all".to_owned()])),
+        )])
+        .await;
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match deliver_with_dns(&local_test(), &dns.resolver()).await {
+            EmailTransferStatus::Failed { error } => assert_eq!(
+                error.variant,
+                TransferErrorsVariant::NoRoute {
+                    domain: "foo.bar".to_owned(),
+                }
+            ),
+            otherwise => panic!("unexpected status {otherwise:?}"),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn no_route_deferred() {
+        let mut config = local_test();
+        config.server.queues.delivery.no_route_permanent = false;
+        let dns = MockDns::serve(vec![]).await;
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match deliver_with_dns(&config, &dns.resolver()).await {
+            EmailTransferStatus::HeldBack { errors } => assert!(matches!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::DnsRecord { .. }
+            )),
+            otherwise => panic!("unexpected status {otherwise:?}"),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn dns_failure_is_temporary() {
+        let dns = MockDns::serve(vec![]).await;
+        let resolver = dns.resolver();
+        drop(dns);
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match deliver_with_dns(&local_test(), &resolver).await {
+            EmailTransferStatus::HeldBack { errors } => assert!(matches!(
+                errors.first().unwrap().variant,
+                TransferErrorsVariant::DnsRecord { .. }
+            )),
+            otherwise => panic!("unexpected status {otherwise:?}"),
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn nxdomain_cached() {
+        let mut config = local_test();
+        config.server.queues.delivery.nxdomain_cache = Some(core::time::Duration::from_secs(60));
+        let dns = MockDns::serve(vec![]).await;
+        let resolver = dns.resolver();
+        let senders = alloc::sync::Arc::new(Sender::default());
+
+        let deliver = || async {
+            Deliver::new(&resolver, alloc::sync::Arc::clone(&senders))
+                .deliver(
+                    &config,
+                    &local_ctx(),
+                    &Some("root@foo.bar".parse().unwrap()),
+                    vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+                    &local_msg().inner().to_string(),
+                )
+                .await
+                .first()
+                .unwrap()
+                .email_status
+                .clone()
+        };
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        let assert_no_such_domain = |status: EmailTransferStatus| match status {
+            EmailTransferStatus::Failed { error } => assert_eq!(
+                error.variant,
+                TransferErrorsVariant::NoSuchDomain {
+                    domain: "foo.bar".to_owned(),
+                }
+            ),
+            otherwise => panic!("unexpected status {otherwise:?}"),
+        };
+
+        assert_no_such_domain(deliver().await);
+        // NOTE: the dns is gone, a lookup would be deferred as a temporary error.
+        drop(dns);
+        assert_no_such_domain(deliver().await);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn require_tls_unauthenticated_mx() {
+        let dns = mock_dns_with_cname_at_mx().await;
+        let mut ctx = local_ctx();
+        ctx.mail_from.require_tls = true;
+
+        let updated_rcpt = Deliver::new(&dns.resolver(), alloc::sync::Arc::new(Sender::default()))
+            .deliver(
+                &local_test(),
+                &ctx,
+                &Some("root@foo.bar".parse().unwrap()),
+                vec![Rcpt::new("root@foo.bar".parse().unwrap())],
+                &local_msg().inner().to_string(),
+            )
+            .await;
+
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match &updated_rcpt.first().unwrap().email_status {
+            EmailTransferStatus::Failed { error } => assert_eq!(
+                error.variant,
+                TransferErrorsVariant::RequireTls {
+                    error: "the mail exchangers of 'foo.bar' are not authenticated by DNSSEC nor MTA-STS".to_owned(),
+                }
+            ),
+            otherwise => panic!("unexpected status {otherwise:?}"),
+        }
+    }
+
+    #[test]
+    fn tls_required_header() {
+        assert!(is_tls_optional(
+            "From: a@b\r\nTLS-Required: No\r\nSubject: c\r\n\r\nbody\r\n"
+        ));
+        assert!(is_tls_optional("tls-required:no\r\n\r\n"));
+        assert!(!is_tls_optional("From: a@b\r\nSubject: c\r\n\r\nbody\r\n"));
+        assert!(!is_tls_optional("From: a@b\r\n\r\nTLS-Required: No\r\n"));
+    }
+
+    fn rcpt_list(count: usize) -> Vec<Rcpt> {
+        (0..count)
+            .map(|i| Rcpt::new(format!("rcpt{i}@foo.bar").parse().unwrap()))
+            .collect()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn rcpt_per_transaction_max() {
+        let transactions = &std::sync::Mutex::new(Vec::<Vec<String>>::new());
+
+        let updated = in_transactions(rcpt_list(5), Some(2), |mut rcpt| async move {
+            let mut transactions = transactions.lock().unwrap();
+            // NOTE: the second transaction is rejected.
+            let rejected = transactions.len() == 1;
+            for i in &mut rcpt {
+                i.email_status = if rejected {
+                    EmailTransferStatus::failed(TransferErrorsVariant::DeliveryError {
+                        targets: vec!["mx.foo.bar".to_owned()],
+                    })
+                } else {
+                    EmailTransferStatus::sent()
+                };
+            }
+            transactions.push(rcpt.iter().map(|i| i.address.to_string()).collect());
+            rcpt
+        })
+        .await;
+
+        assert_eq!(
+            *transactions.lock().unwrap(),
+            vec![
+                vec!["rcpt0@foo.bar", "rcpt1@foo.bar"],
+                vec!["rcpt2@foo.bar", "rcpt3@foo.bar"],
+                vec!["rcpt4@foo.bar"],
+            ]
+        );
+        assert_eq!(
+            updated
+                .iter()
+                .map(|i| (
+                    i.address.to_string(),
+                    matches!(i.email_status, EmailTransferStatus::Sent { .. })
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("rcpt0@foo.bar".to_owned(), true),
+                ("rcpt1@foo.bar".to_owned(), true),
+                ("rcpt2@foo.bar".to_owned(), false),
+                ("rcpt3@foo.bar".to_owned(), false),
+                ("rcpt4@foo.bar".to_owned(), true),
+            ]
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn no_rcpt_per_transaction_max() {
+        let transactions = &std::sync::atomic::AtomicUsize::new(0);
+
+        let updated = in_transactions(rcpt_list(5), None, |rcpt| async move {
+            transactions.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            rcpt
+        })
+        .await;
+
+        assert_eq!(updated, rcpt_list(5));
+        assert_eq!(transactions.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn mta_sts_testing() {
+        let policy = "version: STSv1\nmode: testing\nmx: mx.foo.bar\nmax_age: 86400\n"
+            .parse::<MtaStsPolicy>()
+            .unwrap();
+
+        assert_eq!(check_mta_sts_policy(&policy, "foo.bar", "mx.foo.bar"), None);
+        assert_eq!(
+            check_mta_sts_policy(&policy, "foo.bar", "mx2.foo.bar"),
+            None
+        );
+    }
 }
@@ -14,8 +14,11 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use super::Transport;
-use crate::{get_cert_for_server, to_lettre_envelope, Sender, SenderParameters};
+use super::{Capabilities, Transport};
+use crate::{
+    get_cert_for_server, get_hello_name, to_lettre_envelope, to_transfer_error, Sender,
+    SenderParameters,
+};
 use trust_dns_resolver::TokioAsyncResolver;
 use vsmtp_common::{
     rcpt::Rcpt,
@@ -107,26 +110,42 @@ impl Forward<'_> {
                 &SenderParameters {
                     relay_target: server.clone(),
                     server_name: server,
-                    hello_name: ctx.connect.server_name.clone(),
-                    pool_idle_timeout: core::time::Duration::from_secs(60),
-                    pool_max_size: 3,
-                    pool_min_idle: 1,
+                    hello_name: get_hello_name(from, &ctx.connect.server_name, config),
+                    pool_idle_timeout: config.server.queues.delivery.pool.idle_timeout,
+                    pool_max_size: config.server.queues.delivery.pool.max_size,
+                    pool_min_idle: config.server.queues.delivery.pool.min_idle,
                     port: port.unwrap_or(SMTP_PORT),
                     certificate: get_cert_for_server(&ctx.connect.server_name, config)
                         .ok_or(TransferErrorsVariant::TlsNoCertificate {})?,
+                    capture_transcript: config.server.queues.delivery.capture_transcript,
+                    tlsa: vec![],
+                    auth_mailbox: ctx.mail_from.auth_mailbox.clone(),
+                    require_tls: ctx.mail_from.require_tls,
+                    tls_optional: false,
                 },
                 &envelop,
                 message.as_bytes(),
             )
             .await
-            .map_err(|e| TransferErrorsVariant::Smtp {
-                error: e.to_string(),
-            })
+            .map_err(|e| to_transfer_error(&e))
     }
 }
 
 #[async_trait::async_trait]
 impl Transport for Forward<'_> {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "forward"
+    }
+
+    #[inline]
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            local: false,
+            multi_recipient: true,
+        }
+    }
+
     #[tracing::instrument(name = "forward", skip_all)]
     async fn deliver(
         mut self,
@@ -170,10 +189,29 @@ mod tests {
     use trust_dns_resolver::TokioAsyncResolver;
     use vsmtp_common::{
         rcpt::Rcpt,
-        transfer::{EmailTransferStatus, ForwardTarget, Transfer, TransferErrorsVariant},
+        transfer::{EmailTransferStatus, ForwardTarget, NotifyOn, Transfer, TransferErrorsVariant},
     };
     use vsmtp_test::config::{local_ctx, local_msg, with_tls};
 
+    #[test_log::test(tokio::test)]
+    async fn capabilities() {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap();
+        let forward = Forward::new(
+            ForwardTarget::Socket("127.0.0.1:9999".parse().unwrap()),
+            &resolver,
+            alloc::sync::Arc::new(Sender::default()),
+        );
+
+        assert_eq!(forward.name(), "forward");
+        assert_eq!(
+            forward.capabilities(),
+            Capabilities {
+                local: false,
+                multi_recipient: true
+            }
+        );
+    }
+
     #[test_log::test(tokio::test)]
     async fn forward() {
         let config = with_tls();
@@ -195,6 +233,8 @@ mod tests {
                 address: "root@localhost".parse().unwrap(),
                 transfer_method: Transfer::Forward(target),
                 email_status: EmailTransferStatus::default(),
+                notify_on: NotifyOn::default(),
+                original_forward_path: None,
             }],
             &msg.inner().to_string(),
         )
@@ -0,0 +1,449 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::{Capabilities, Transport};
+use crate::get_hello_name;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+use vsmtp_common::{
+    rcpt::Rcpt,
+    transfer::{EmailTransferStatus, LmtpTarget, TransferErrorsVariant},
+    Address, ContextFinished,
+};
+use vsmtp_config::Config;
+
+/// Maximum duration of a lmtp session.
+const LMTP_TIMEOUT: core::time::Duration = core::time::Duration::from_secs(5 * 60);
+
+/// the email is handed to a local delivery agent (dovecot, cyrus ...) using the lmtp protocol.
+/// see <https://www.rfc-editor.org/rfc/rfc2033>
+#[non_exhaustive]
+pub struct Lmtp {
+    to: LmtpTarget,
+}
+
+impl Lmtp {
+    /// create a new lmtp transport, connecting to the target.
+    #[must_use]
+    #[inline]
+    pub const fn new(to: LmtpTarget) -> Self {
+        Self { to }
+    }
+}
+
+/// A reply of the lmtp server.
+struct Reply {
+    code: u16,
+    text: String,
+}
+
+impl Reply {
+    const fn is_positive(&self) -> bool {
+        matches!(self.code, 200..=399)
+    }
+
+    fn to_error(&self) -> TransferErrorsVariant {
+        TransferErrorsVariant::Smtp {
+            error: self.text.clone(),
+            transcript: vec![],
+        }
+    }
+
+    /// Update the status of the recipient with the reply received for it.
+    fn apply(&self, rcpt: &mut Rcpt) {
+        match self.code {
+            200..=299 => rcpt.email_status = EmailTransferStatus::sent(),
+            500..=599 => rcpt.email_status = EmailTransferStatus::failed(self.to_error()),
+            _ => rcpt.email_status.held_back(self.to_error()),
+        }
+    }
+}
+
+fn io_error(error: &std::io::Error) -> TransferErrorsVariant {
+    TransferErrorsVariant::Smtp {
+        error: error.to_string(),
+        transcript: vec![],
+    }
+}
+
+struct Session<S> {
+    stream: tokio::io::BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Session<S> {
+    async fn read_reply(&mut self) -> Result<Reply, TransferErrorsVariant> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            if self
+                .stream
+                .read_line(&mut line)
+                .await
+                .map_err(|e| io_error(&e))?
+                == 0
+            {
+                return Err(TransferErrorsVariant::Smtp {
+                    error: "connection closed by the lmtp server".to_owned(),
+                    transcript: vec![],
+                });
+            }
+
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| TransferErrorsVariant::Smtp {
+                    error: format!("invalid reply from the lmtp server: '{}'", line.trim_end()),
+                    transcript: vec![],
+                })?;
+            text.push_str(&line);
+
+            if line.get(3..4) != Some("-") {
+                return Ok(Reply {
+                    code,
+                    text: text.trim_end().to_owned(),
+                });
+            }
+        }
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Reply, TransferErrorsVariant> {
+        tracing::trace!(%command, "Sending lmtp command.");
+
+        self.stream
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .map_err(|e| io_error(&e))?;
+        self.stream.flush().await.map_err(|e| io_error(&e))?;
+
+        self.read_reply().await
+    }
+
+    async fn expect_positive(&mut self, command: &str) -> Result<(), TransferErrorsVariant> {
+        let reply = self.command(command).await?;
+        if reply.is_positive() {
+            Ok(())
+        } else {
+            Err(reply.to_error())
+        }
+    }
+
+    /// Send the message, dot-stuffed, and the terminating `.` line.
+    async fn send_data(&mut self, message: &str) -> Result<(), TransferErrorsVariant> {
+        for line in message.split_inclusive('\n') {
+            if line.starts_with('.') {
+                self.stream
+                    .write_all(b".")
+                    .await
+                    .map_err(|e| io_error(&e))?;
+            }
+            self.stream
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| io_error(&e))?;
+        }
+
+        if !message.is_empty() && !message.ends_with('\n') {
+            self.stream
+                .write_all(b"\r\n")
+                .await
+                .map_err(|e| io_error(&e))?;
+        }
+
+        self.stream
+            .write_all(b".\r\n")
+            .await
+            .map_err(|e| io_error(&e))?;
+        self.stream.flush().await.map_err(|e| io_error(&e))
+    }
+}
+
+/// Run a lmtp transaction and return the reply received for each recipient.
+///
+/// The recipients rejected at the `RCPT TO` command get the reply of that command,
+/// the others get the one received after the end of the data, as specified by the RFC 2033.
+async fn transaction<S: AsyncRead + AsyncWrite + Unpin + Send>(
+    stream: S,
+    hello_name: &str,
+    from: &Option<Address>,
+    to: &[Rcpt],
+    message: &str,
+) -> Result<Vec<Reply>, TransferErrorsVariant> {
+    let mut session = Session {
+        stream: tokio::io::BufReader::new(stream),
+    };
+
+    let greetings = session.read_reply().await?;
+    if !greetings.is_positive() {
+        return Err(greetings.to_error());
+    }
+
+    session
+        .expect_positive(&format!("LHLO {hello_name}"))
+        .await?;
+    session
+        .expect_positive(&format!(
+            "MAIL FROM:<{}>",
+            from.as_ref().map_or("", Address::full)
+        ))
+        .await?;
+
+    let mut replies = Vec::with_capacity(to.len());
+    for rcpt in to {
+        replies.push(
+            session
+                .command(&format!("RCPT TO:<{}>", rcpt.address.full()))
+                .await?,
+        );
+    }
+
+    if replies.iter().any(Reply::is_positive) {
+        session.expect_positive("DATA").await?;
+        session.send_data(message).await?;
+
+        for reply in &mut replies {
+            if reply.is_positive() {
+                *reply = session.read_reply().await?;
+            }
+        }
+    }
+
+    if let Err(error) = session.command("QUIT").await {
+        tracing::debug!(%error, "Failed to quit the lmtp session.");
+    }
+
+    Ok(replies)
+}
+
+impl Lmtp {
+    async fn deliver_inner(
+        &self,
+        hello_name: &str,
+        from: &Option<Address>,
+        to: &[Rcpt],
+        message: &str,
+    ) -> Result<Vec<Reply>, TransferErrorsVariant> {
+        let session = async {
+            match &self.to {
+                LmtpTarget::Tcp(address) => {
+                    let stream = tokio::net::TcpStream::connect(address)
+                        .await
+                        .map_err(|e| io_error(&e))?;
+                    transaction(stream, hello_name, from, to, message).await
+                }
+                LmtpTarget::Unix(path) => {
+                    let stream = tokio::net::UnixStream::connect(path)
+                        .await
+                        .map_err(|e| io_error(&e))?;
+                    transaction(stream, hello_name, from, to, message).await
+                }
+            }
+        };
+
+        tokio::time::timeout(LMTP_TIMEOUT, session)
+            .await
+            .map_err(|_elapsed| TransferErrorsVariant::Smtp {
+                error: format!("lmtp session timed out after {LMTP_TIMEOUT:?}"),
+                transcript: vec![],
+            })?
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for Lmtp {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "lmtp"
+    }
+
+    #[inline]
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            local: true,
+            multi_recipient: true,
+        }
+    }
+
+    #[tracing::instrument(name = "lmtp", skip_all)]
+    async fn deliver(
+        self,
+        config: &Config,
+        ctx: &ContextFinished,
+        from: &Option<Address>,
+        mut to: Vec<Rcpt>,
+        message: &str,
+    ) -> Vec<Rcpt> {
+        let hello_name = get_hello_name(from, &ctx.connect.server_name, config);
+
+        match self.deliver_inner(&hello_name, from, &to, message).await {
+            Ok(replies) => {
+                for (rcpt, reply) in to.iter_mut().zip(replies) {
+                    tracing::debug!(%rcpt, code = reply.code, "Lmtp reply received.");
+                    reply.apply(rcpt);
+                }
+            }
+            Err(error) => {
+                tracing::error!(%error, target = ?self.to, "Lmtp delivery failure.");
+
+                for rcpt in &mut to {
+                    rcpt.email_status.held_back(error.clone());
+                }
+            }
+        }
+
+        to
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+    use vsmtp_common::transfer::Transfer;
+    use vsmtp_test::config::{local_ctx, local_test};
+
+    #[test]
+    fn capabilities() {
+        let lmtp = Lmtp::new(LmtpTarget::Unix("/run/dovecot/lmtp".into()));
+
+        assert_eq!(lmtp.name(), "lmtp");
+        assert_eq!(
+            lmtp.capabilities(),
+            Capabilities {
+                local: true,
+                multi_recipient: true
+            }
+        );
+    }
+
+    /// Accept a single lmtp session and return the data received.
+    async fn fake_lmtp_server(listener: tokio::net::TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        stream
+            .write_all(b"220 localhost LMTP ready\r\n")
+            .await
+            .unwrap();
+
+        let mut data = String::new();
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+
+            let reply: &[u8] = match line.trim_end() {
+                "LHLO testserver.com" => b"250-localhost\r\n250 PIPELINING\r\n",
+                "MAIL FROM:<foo@domain.com>" => b"250 2.1.0 OK\r\n",
+                "RCPT TO:<unknown@domain.com>" => b"550 5.1.1 unknown user\r\n",
+                "RCPT TO:<john.doe@domain.com>" | "RCPT TO:<full@domain.com>" => {
+                    b"250 2.1.5 OK\r\n"
+                }
+                "DATA" => {
+                    stream.write_all(b"354 go ahead\r\n").await.unwrap();
+                    loop {
+                        let mut line = String::new();
+                        stream.read_line(&mut line).await.unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    // one reply for each accepted recipient.
+                    b"250 2.0.0 delivered\r\n452 4.2.2 mailbox full\r\n"
+                }
+                "QUIT" => {
+                    stream.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"500 5.5.2 unknown command\r\n",
+            };
+            stream.write_all(reply).await.unwrap();
+        }
+
+        data
+    }
+
+    fn rcpt(address: &str, target: &LmtpTarget) -> Rcpt {
+        let mut rcpt = Rcpt::new(address.parse().unwrap());
+        rcpt.transfer_method = Transfer::Lmtp(target.clone());
+        rcpt
+    }
+
+    #[allow(clippy::indexing_slicing)]
+    #[test_log::test(tokio::test)]
+    async fn status_per_recipient() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = LmtpTarget::Tcp(listener.local_addr().unwrap());
+        let server = tokio::spawn(fake_lmtp_server(listener));
+
+        let result = Lmtp::new(target.clone())
+            .deliver(
+                &local_test(),
+                &local_ctx(),
+                &Some("foo@domain.com".parse().unwrap()),
+                vec![
+                    rcpt("john.doe@domain.com", &target),
+                    rcpt("unknown@domain.com", &target),
+                    rcpt("full@domain.com", &target),
+                ],
+                "Subject: hello\r\n\r\n.hidden line\r\nHello World!\r\n",
+            )
+            .await;
+
+        assert!(matches!(
+            result[0].email_status,
+            EmailTransferStatus::Sent { .. }
+        ));
+        assert_eq!(
+            result[1].email_status,
+            EmailTransferStatus::failed(TransferErrorsVariant::Smtp {
+                error: "550 5.1.1 unknown user".to_owned(),
+                transcript: vec![]
+            })
+        );
+        assert!(matches!(
+            &result[2].email_status,
+            EmailTransferStatus::HeldBack { errors } if errors[0].variant == TransferErrorsVariant::Smtp {
+                error: "452 4.2.2 mailbox full".to_owned(),
+                transcript: vec![]
+            }
+        ));
+
+        assert_eq!(
+            server.await.unwrap(),
+            "Subject: hello\r\n\r\n..hidden line\r\nHello World!\r\n"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn unreachable_server() {
+        let target = LmtpTarget::Unix("/tmp/vsmtp-test-lmtp-does-not-exist.sock".into());
+
+        let result = Lmtp::new(target.clone())
+            .deliver(
+                &local_test(),
+                &local_ctx(),
+                &Some("foo@domain.com".parse().unwrap()),
+                vec![rcpt("john.doe@domain.com", &target)],
+                "Hello World!\r\n",
+            )
+            .await;
+
+        assert!(matches!(
+            result.first().unwrap().email_status,
+            EmailTransferStatus::HeldBack { .. }
+        ));
+    }
+}
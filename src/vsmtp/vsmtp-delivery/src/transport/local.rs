@@ -0,0 +1,138 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use vsmtp_common::{rcpt::Rcpt, transfer::TransferErrorsVariant, Address};
+use vsmtp_config::field::FieldQueueDeliveryLocalHeaders;
+
+/// Build the headers prepended to the message delivered locally to `rcpt`,
+/// see [`FieldQueueDeliveryLocalHeaders`].
+///
+/// # Errors
+///
+/// * [`TransferErrorsVariant::MailLoop`] if the message has already been delivered to `rcpt`.
+pub(super) fn build_headers(
+    config: &FieldQueueDeliveryLocalHeaders,
+    from: &Option<Address>,
+    rcpt: &Rcpt,
+    content: &str,
+) -> Result<String, TransferErrorsVariant> {
+    let return_path = config.return_path.then(|| {
+        format!(
+            "Return-Path: <{}>\n",
+            from.as_ref().map_or("", Address::full)
+        )
+    });
+
+    let delivered_to = if config.delivered_to {
+        if is_delivered_to(content, &rcpt.address) {
+            return Err(TransferErrorsVariant::MailLoop {
+                recipient: rcpt.address.full().to_owned(),
+            });
+        }
+        Some(format!("Delivered-To: {rcpt}\n"))
+    } else {
+        None
+    };
+
+    Ok(return_path.into_iter().chain(delivered_to).collect())
+}
+
+/// Does the header section of `content` have a `Delivered-To` header for `address`.
+fn is_delivered_to(content: &str, address: &Address) -> bool {
+    content
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("Delivered-To"))
+        .any(|(_, value)| {
+            value
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .eq_ignore_ascii_case(address.full())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vsmtp_common::addr;
+
+    const ALL: FieldQueueDeliveryLocalHeaders = FieldQueueDeliveryLocalHeaders {
+        return_path: true,
+        delivered_to: true,
+    };
+
+    #[test]
+    fn headers_added() {
+        let rcpt = Rcpt::new(addr!("jane@doe.com"));
+        let content = "From: john@doe.com\r\n\r\nHello World!\r\n";
+
+        assert_eq!(
+            build_headers(&ALL, &Some(addr!("john@doe.com")), &rcpt, content).unwrap(),
+            "Return-Path: <john@doe.com>\nDelivered-To: jane@doe.com\n"
+        );
+        assert_eq!(
+            build_headers(&ALL, &None, &rcpt, content).unwrap(),
+            "Return-Path: <>\nDelivered-To: jane@doe.com\n"
+        );
+        assert_eq!(
+            build_headers(
+                &FieldQueueDeliveryLocalHeaders {
+                    return_path: false,
+                    delivered_to: false,
+                },
+                &None,
+                &rcpt,
+                content
+            )
+            .unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn loop_detected() {
+        let rcpt = Rcpt::new(addr!("jane@doe.com"));
+        let content =
+            "Delivered-To: john@doe.com\r\ndelivered-to: <Jane@Doe.com>\r\n\r\nHello World!\r\n";
+
+        assert_eq!(
+            build_headers(&ALL, &None, &rcpt, content).unwrap_err(),
+            TransferErrorsVariant::MailLoop {
+                recipient: "jane@doe.com".to_owned()
+            }
+        );
+        assert!(build_headers(
+            &FieldQueueDeliveryLocalHeaders {
+                return_path: true,
+                delivered_to: false,
+            },
+            &None,
+            &rcpt,
+            content
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn loop_only_in_headers() {
+        let rcpt = Rcpt::new(addr!("jane@doe.com"));
+        let content = "Delivered-To: john@doe.com\r\n\r\nDelivered-To: jane@doe.com\r\n";
+
+        assert!(build_headers(&ALL, &None, &rcpt, content).is_ok());
+    }
+}
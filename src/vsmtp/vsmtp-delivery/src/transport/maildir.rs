@@ -14,7 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use super::Transport;
+use super::{local::build_headers, Capabilities, Transport};
 use anyhow::Context;
 use vsmtp_common::{
     libc_abstraction::{chown, getpwuid},
@@ -22,7 +22,7 @@ use vsmtp_common::{
     transfer::{EmailTransferStatus, TransferErrorsVariant},
     Address, ContextFinished,
 };
-use vsmtp_config::Config;
+use vsmtp_config::{field::FieldQueueDeliveryMaildirQuota, Config};
 
 /// see <https://en.wikipedia.org/wiki/Maildir>
 //
@@ -33,23 +33,52 @@ pub struct Maildir;
 
 #[async_trait::async_trait]
 impl Transport for Maildir {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "maildir"
+    }
+
+    #[inline]
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            local: true,
+            multi_recipient: false,
+        }
+    }
+
     #[tracing::instrument(name = "maildir", skip_all)]
     async fn deliver(
         self,
         config: &Config,
-        ctx: &ContextFinished,
-        _: &Option<Address>,
+        _: &ContextFinished,
+        from: &Option<Address>,
         mut to: Vec<Rcpt>,
         content: &str,
     ) -> Vec<Rcpt> {
-        let msg_uuid = &ctx.mail_from.message_uuid;
+        let quota = config.server.queues.delivery.maildir_quota.as_ref();
         for rcpt in &mut to {
+            let headers = match build_headers(
+                &config.server.queues.delivery.local_headers,
+                from,
+                rcpt,
+                content,
+            ) {
+                Ok(headers) => headers,
+                Err(error) => {
+                    tracing::warn!(%error, "Email delivery failure.");
+
+                    rcpt.email_status = EmailTransferStatus::failed(error);
+                    continue;
+                }
+            };
+
             match users::get_user_by_name(rcpt.address.local_part()).map(|user| {
                 Self::write_to_maildir(
-                    rcpt,
                     &user,
                     config.server.system.group_local.as_ref(),
-                    msg_uuid,
+                    quota,
+                    &config.server.name,
+                    &headers,
                     content,
                 )
             }) {
@@ -58,6 +87,18 @@ impl Transport for Maildir {
 
                     rcpt.email_status = EmailTransferStatus::sent();
                 }
+                Some(Err(error)) if error.is::<MailboxFull>() => {
+                    tracing::warn!(%error, "Email delivery failure.");
+
+                    let error = TransferErrorsVariant::MailboxFull {
+                        name: rcpt.address.local_part().to_owned(),
+                    };
+                    if quota.map_or(false, |quota| quota.bounce) {
+                        rcpt.email_status = EmailTransferStatus::failed(error);
+                    } else {
+                        rcpt.email_status.held_back(error);
+                    }
+                }
                 Some(Err(error)) => {
                     tracing::error!(%error, "Email delivery failure.");
 
@@ -114,10 +155,11 @@ impl Maildir {
     }
 
     fn write_to_maildir(
-        rcpt: &Rcpt,
         user: &users::User,
         group_local: Option<&users::Group>,
-        msg_uuid: &uuid::Uuid,
+        quota: Option<&FieldQueueDeliveryMaildirQuota>,
+        hostname: &str,
+        headers: &str,
         content: &str,
     ) -> anyhow::Result<()> {
         let maildir = std::path::PathBuf::from_iter([getpwuid(user.uid())?, "Maildir".into()]);
@@ -126,24 +168,223 @@ impl Maildir {
             Self::create_and_chown(&maildir.join(dir), user, group_local)?;
         }
 
-        let file_in_maildir_inbox = maildir.join(format!("new/{msg_uuid}.eml"));
+        let size = u64::try_from(headers.len().saturating_add(content.len()))?;
+
+        let maildirsize = maildir.join("maildirsize");
+        if let Some(quota) = quota {
+            Self::check_quota(&maildir, &maildirsize, quota, size)?;
+        }
+
+        Self::write_message(&maildir, user, group_local, hostname, headers, content)?;
+
+        // NOTE: the file is only updated if the IMAP server created it.
+        if quota.is_some() && maildirsize.exists() {
+            let mut maildirsize = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&maildirsize)
+                .with_context(|| format!("failed to open {}", maildirsize.display()))?;
+
+            std::io::Write::write_all(&mut maildirsize, format!("{size} 1\n").as_bytes())?;
+        }
 
+        Ok(())
+    }
+
+    /// Write the message in `tmp/` then move it to `new/`, so that the readers
+    /// of the mailbox never see a partial file.
+    fn write_message(
+        maildir: &std::path::Path,
+        user: &users::User,
+        group_local: Option<&users::Group>,
+        hostname: &str,
+        headers: &str,
+        content: &str,
+    ) -> anyhow::Result<()> {
+        let filename = Self::unique_name(hostname);
+        let file_in_maildir_tmp = maildir.join("tmp").join(&filename);
+        let file_in_maildir_inbox = maildir.join("new").join(&filename);
+
+        let written = Self::write_to_tmp(&file_in_maildir_tmp, user, group_local, headers, content)
+            .and_then(|()| {
+                std::fs::rename(&file_in_maildir_tmp, &file_in_maildir_inbox)
+                    .with_context(|| format!("failed to move {}", file_in_maildir_tmp.display()))
+            });
+        if written.is_err() && file_in_maildir_tmp.exists() {
+            if let Err(error) = std::fs::remove_file(&file_in_maildir_tmp) {
+                tracing::warn!(%error, "Failed to remove the temporary file.");
+            }
+        }
+        written
+    }
+
+    /// Write the message in `tmp/` and flush it to the disk.
+    fn write_to_tmp(
+        path: &std::path::Path,
+        user: &users::User,
+        group_local: Option<&users::Group>,
+        headers: &str,
+        content: &str,
+    ) -> anyhow::Result<()> {
         let mut email = std::fs::OpenOptions::new()
-            .create(true)
+            .create_new(true)
             .write(true)
-            .open(&file_in_maildir_inbox)?;
+            .open(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
 
-        std::io::Write::write_all(&mut email, format!("Delivered-To: {rcpt}\n").as_bytes())?;
+        std::io::Write::write_all(&mut email, headers.as_bytes())?;
         std::io::Write::write_all(&mut email, content.as_bytes())?;
+        email.sync_all()?;
 
-        chown(
-            &file_in_maildir_inbox,
-            Some(user.uid()),
-            group_local.map(users::Group::gid),
-        )?;
+        chown(path, Some(user.uid()), group_local.map(users::Group::gid))?;
 
         Ok(())
     }
+
+    /// A file name unique across the deliveries, "<time>.<pid>_<seq>.<host>",
+    /// see <https://cr.yp.to/proto/maildir.html>.
+    fn unique_name(hostname: &str) -> String {
+        static SEQUENCE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+        format!(
+            "{}.{}_{}.{}",
+            time::OffsetDateTime::now_utc().unix_timestamp(),
+            std::process::id(),
+            SEQUENCE.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            hostname.replace('/', "\\057").replace(':', "\\072")
+        )
+    }
+
+    /// Fail with [`MailboxFull`] if a message of `size` bytes does not fit in the mailbox.
+    fn check_quota(
+        maildir: &std::path::Path,
+        maildirsize: &std::path::Path,
+        quota: &FieldQueueDeliveryMaildirQuota,
+        size: u64,
+    ) -> anyhow::Result<()> {
+        let (quota, usage) = match std::fs::read_to_string(maildirsize) {
+            Ok(content) => Usage::from_maildirsize(&content),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => (
+                Quota {
+                    storage: quota.storage,
+                    messages: quota.messages,
+                },
+                Usage::from_maildir(maildir)?,
+            ),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read {}", maildirsize.display()))
+            }
+        };
+
+        tracing::debug!(?quota, ?usage, size, "Checking the quota.");
+
+        if quota.is_exceeded_by(usage, size) {
+            anyhow::bail!(MailboxFull);
+        }
+
+        Ok(())
+    }
+}
+
+/// The mailbox has no room left for the message.
+#[derive(Debug)]
+struct MailboxFull;
+
+impl core::fmt::Display for MailboxFull {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "the mailbox exceeds its quota")
+    }
+}
+
+impl std::error::Error for MailboxFull {}
+
+/// The quota of a mailbox, see <https://www.courier-mta.org/imap/README.maildirquota.html>.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Quota {
+    storage: Option<u64>,
+    messages: Option<u64>,
+}
+
+impl Quota {
+    /// Parse the quota definition of the first line of `maildirsize`, ex: "1000000S,1000C".
+    // NOTE: a limit of 0 is no limit, as written by Dovecot.
+    fn parse(definition: &str) -> Self {
+        let mut quota = Self::default();
+        for limit in definition.trim().split(',') {
+            let parse = |value: &str| value.parse::<u64>().ok().filter(|value| *value != 0);
+
+            if let Some(storage) = limit.strip_suffix('S') {
+                quota.storage = parse(storage);
+            } else if let Some(messages) = limit.strip_suffix('C') {
+                quota.messages = parse(messages);
+            } else {
+                tracing::debug!(limit, "Unknown quota limit ignored.");
+            }
+        }
+        quota
+    }
+
+    fn is_exceeded_by(self, usage: Usage, size: u64) -> bool {
+        self.storage.map_or(false, |storage| {
+            usage.storage.saturating_add(size) > storage
+        }) || self
+            .messages
+            .map_or(false, |messages| usage.messages >= messages)
+    }
+}
+
+/// The size in bytes and the number of messages of a mailbox.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Usage {
+    storage: u64,
+    messages: u64,
+}
+
+impl Usage {
+    /// Read the quota and the usage from `maildirsize`, whose lines after the
+    /// first one are "<bytes> <count>" changes of the usage.
+    fn from_maildirsize(content: &str) -> (Quota, Self) {
+        let mut lines = content.lines();
+        let quota = lines.next().map(Quota::parse).unwrap_or_default();
+
+        let (mut storage, mut messages) = (0_i64, 0_i64);
+        for line in lines {
+            let mut change = line
+                .split_whitespace()
+                .map(|value| value.parse::<i64>().unwrap_or_default());
+            storage = storage.saturating_add(change.next().unwrap_or_default());
+            messages = messages.saturating_add(change.next().unwrap_or_default());
+        }
+
+        (
+            quota,
+            Self {
+                storage: u64::try_from(storage).unwrap_or_default(),
+                messages: u64::try_from(messages).unwrap_or_default(),
+            },
+        )
+    }
+
+    /// Sum the size of the messages in the `new` and `cur` folders of `maildir`.
+    fn from_maildir(maildir: &std::path::Path) -> anyhow::Result<Self> {
+        let mut usage = Self::default();
+        for dir in ["new", "cur"] {
+            let dir = maildir.join(dir);
+            for entry in std::fs::read_dir(&dir)
+                .with_context(|| format!("failed to read {}", dir.display()))?
+            {
+                // NOTE: the message could have been moved or removed meanwhile.
+                if let Ok(metadata) = entry.and_then(|entry| entry.metadata()) {
+                    if metadata.is_file() {
+                        usage.storage = usage.storage.saturating_add(metadata.len());
+                        usage.messages = usage.messages.saturating_add(1);
+                    }
+                }
+            }
+        }
+        Ok(usage)
+    }
 }
 
 #[cfg(test)]
@@ -151,9 +392,173 @@ mod test {
 
     use super::*;
     use users::os::unix::UserExt;
-    use vsmtp_common::{addr, transfer::Transfer};
+    use vsmtp_common::{
+        addr,
+        transfer::{NotifyOn, Transfer},
+    };
     use vsmtp_test::config::{local_ctx, local_test};
 
+    #[test]
+    fn capabilities() {
+        assert_eq!(Maildir::default().name(), "maildir");
+        assert_eq!(
+            Maildir::default().capabilities(),
+            Capabilities {
+                local: true,
+                multi_recipient: false
+            }
+        );
+    }
+
+    #[test]
+    fn quota_definition() {
+        assert_eq!(
+            Quota::parse("1000000S,1000C\n"),
+            Quota {
+                storage: Some(1_000_000),
+                messages: Some(1000)
+            }
+        );
+        assert_eq!(
+            Quota::parse("0S,10C"),
+            Quota {
+                storage: None,
+                messages: Some(10)
+            }
+        );
+        assert_eq!(Quota::parse(""), Quota::default());
+    }
+
+    #[test]
+    fn maildirsize() {
+        let (quota, usage) = Usage::from_maildirsize("5000S\n 1200 2\n300 1\n-200 -1\n");
+
+        assert_eq!(
+            quota,
+            Quota {
+                storage: Some(5000),
+                messages: None
+            }
+        );
+        assert_eq!(
+            usage,
+            Usage {
+                storage: 1300,
+                messages: 2
+            }
+        );
+        assert!(!quota.is_exceeded_by(usage, 3700));
+        assert!(quota.is_exceeded_by(usage, 3701));
+    }
+
+    #[test]
+    fn quota_messages() {
+        let quota = Quota {
+            storage: None,
+            messages: Some(2),
+        };
+
+        assert!(!quota.is_exceeded_by(
+            Usage {
+                storage: 10_000,
+                messages: 1
+            },
+            10_000
+        ));
+        assert!(quota.is_exceeded_by(
+            Usage {
+                storage: 0,
+                messages: 2
+            },
+            0
+        ));
+    }
+
+    #[test]
+    fn check_quota() {
+        let maildir = std::env::temp_dir().join(format!("maildir-{}", uuid::Uuid::new_v4()));
+        for dir in ["new", "tmp", "cur"] {
+            std::fs::create_dir_all(maildir.join(dir)).unwrap();
+        }
+        std::fs::write(maildir.join("new/1.eml"), "x".repeat(600)).unwrap();
+        std::fs::write(maildir.join("cur/2.eml"), "x".repeat(300)).unwrap();
+
+        let maildirsize = maildir.join("maildirsize");
+        let quota = FieldQueueDeliveryMaildirQuota {
+            storage: Some(1000),
+            messages: None,
+            bounce: false,
+        };
+
+        Maildir::check_quota(&maildir, &maildirsize, &quota, 100).unwrap();
+        assert!(Maildir::check_quota(&maildir, &maildirsize, &quota, 101)
+            .unwrap_err()
+            .is::<MailboxFull>());
+
+        // the quota of the IMAP server has precedence
+        std::fs::write(&maildirsize, "2000S\n900 2\n").unwrap();
+        Maildir::check_quota(&maildir, &maildirsize, &quota, 1100).unwrap();
+
+        std::fs::remove_dir_all(maildir).unwrap();
+    }
+
+    #[test]
+    fn unique_name() {
+        let first = Maildir::unique_name("mx.example.com");
+        let second = Maildir::unique_name("mx.example.com");
+        assert_ne!(first, second);
+
+        let (time, rest) = first.split_once('.').unwrap();
+        assert!(time.parse::<i64>().is_ok());
+        let (pid_seq, host) = rest.split_once('.').unwrap();
+        assert!(pid_seq.starts_with(&format!("{}_", std::process::id())));
+        assert_eq!(host, "mx.example.com");
+
+        assert!(Maildir::unique_name("a/b:c").ends_with(".a\\057b\\072c"));
+    }
+
+    #[test]
+    fn write_message() {
+        let maildir = std::env::temp_dir().join(format!("maildir-{}", uuid::Uuid::new_v4()));
+        for dir in ["new", "tmp", "cur"] {
+            std::fs::create_dir_all(maildir.join(dir)).unwrap();
+        }
+        let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+
+        for _ in 0..3 {
+            Maildir::write_message(
+                &maildir,
+                &user,
+                None,
+                "testserver.com",
+                "Return-Path: <foo@domain.com>\n",
+                "Hello World!\r\n",
+            )
+            .unwrap();
+        }
+
+        assert_eq!(std::fs::read_dir(maildir.join("tmp")).unwrap().count(), 0);
+        let messages = std::fs::read_dir(maildir.join("new"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 3);
+        for message in messages {
+            assert!(message
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .ends_with(".testserver.com"));
+            assert_eq!(
+                std::fs::read_to_string(message).unwrap(),
+                "Return-Path: <foo@domain.com>\nHello World!\r\n"
+            );
+        }
+
+        std::fs::remove_dir_all(maildir).unwrap();
+    }
+
     #[allow(clippy::std_instead_of_core)]
     #[rstest::rstest]
     #[case::not_existing("foobar", Err(TransferErrorsVariant::NoSuchMailbox {
@@ -183,6 +588,8 @@ mod test {
                         address: addr!(&format!("{mailbox}@domain.com")),
                         transfer_method: Transfer::Maildir,
                         email_status: EmailTransferStatus::default(),
+                        notify_on: NotifyOn::default(),
+                        original_forward_path: None,
                     }],
                     fake_message,
                 )
@@ -199,7 +606,7 @@ mod test {
                         result[0].email_status,
                         EmailTransferStatus::Sent { .. }
                     ));
-                    let filepath = std::path::PathBuf::from_iter([
+                    let inbox = std::path::PathBuf::from_iter([
                         users::get_user_by_uid(users::get_current_uid())
                             .unwrap()
                             .home_dir()
@@ -208,12 +615,14 @@ mod test {
                             .unwrap(),
                         "Maildir",
                         "new",
-                        &format!("{}.eml", context.mail_from.message_uuid),
                     ]);
-                    assert_eq!(
-                        std::fs::read_to_string(filepath).unwrap(),
-                        format!("Delivered-To: {mailbox}@domain.com\nHello World!\r\n")
+                    let expected = format!(
+                        "Return-Path: <foo@domain.com>\nDelivered-To: {mailbox}@domain.com\nHello World!\r\n"
                     );
+                    assert!(std::fs::read_dir(inbox)
+                        .unwrap()
+                        .filter_map(|entry| std::fs::read_to_string(entry.unwrap().path()).ok())
+                        .any(|message| message == expected));
                 }
                 Err(error) => match result[0].email_status {
                     EmailTransferStatus::HeldBack { ref errors } => {
@@ -224,4 +633,37 @@ mod test {
             }
         });
     }
+
+    #[test]
+    fn mail_loop() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async move {
+            let mailbox = users::get_current_username().unwrap();
+            let rcpt = format!("{}@domain.com", mailbox.to_str().unwrap());
+
+            let result = Maildir::default()
+                .deliver(
+                    &local_test(),
+                    &local_ctx(),
+                    &Some(addr!("foo@domain.com")),
+                    vec![Rcpt::new(addr!(&rcpt))],
+                    &format!("Delivered-To: {rcpt}\r\n\r\nHello World!\r\n"),
+                )
+                .await;
+
+            #[allow(clippy::indexing_slicing)]
+            {
+                assert_eq!(
+                    result[0].email_status,
+                    EmailTransferStatus::failed(TransferErrorsVariant::MailLoop {
+                        recipient: rcpt
+                    })
+                );
+            }
+        });
+    }
 }
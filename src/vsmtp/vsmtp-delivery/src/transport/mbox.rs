@@ -15,10 +15,10 @@
  *
 */
 
-use super::Transport;
+use super::{local::build_headers, Capabilities, Transport};
 use anyhow::Context;
 use vsmtp_common::{
-    libc_abstraction::chown,
+    libc_abstraction::{chown, try_flock_exclusive},
     rcpt::Rcpt,
     transfer::{EmailTransferStatus, TransferErrorsVariant},
     Address, ContextFinished,
@@ -29,6 +29,12 @@ const CTIME_FORMAT: &[time::format_description::FormatItem<'_>] = time::macros::
     "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]:[second] [year]"
 );
 
+/// A dot-lock older than that has been left by a crashed process, and is removed.
+const STALE_LOCK: core::time::Duration = core::time::Duration::from_secs(300);
+
+/// Delay between two attempts to lock a mailbox.
+const LOCK_RETRY_DELAY: core::time::Duration = core::time::Duration::from_millis(100);
+
 /// resolver use to write emails on the system following the
 /// application/mbox Media Type.
 /// (see [rfc4155](https://datatracker.ietf.org/doc/html/rfc4155#appendix-A))
@@ -40,6 +46,19 @@ pub struct MBox;
 
 #[async_trait::async_trait]
 impl Transport for MBox {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "mbox"
+    }
+
+    #[inline]
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            local: true,
+            multi_recipient: false,
+        }
+    }
+
     #[inline]
     async fn deliver(
         self,
@@ -50,33 +69,26 @@ impl Transport for MBox {
         content: &str,
     ) -> Vec<Rcpt> {
         let timestamp = get_mbox_timestamp_format(&ctx.connect.connect_timestamp);
-        let content = build_mbox_message(from, &timestamp, content);
 
         for rcpt in &mut to {
-            match users::get_user_by_name(rcpt.address.local_part()).map(|user| {
-                // NOTE: only linux system is supported here, is the
-                //       path to all mboxes always /var/mail ?
-                write_content_to_mbox(
-                    rcpt,
-                    &std::path::PathBuf::from_iter(["/", "var", "mail", rcpt.address.local_part()]),
-                    &user,
-                    config.server.system.group_local.as_ref(),
-                    &content,
-                )
-            }) {
-                Some(Ok(_)) => {
-                    tracing::info!("Email delivered.");
+            let headers = match build_headers(
+                &config.server.queues.delivery.local_headers,
+                from,
+                rcpt,
+                content,
+            ) {
+                Ok(headers) => headers,
+                Err(error) => {
+                    tracing::warn!(%error, "Email delivery failure.");
 
-                    rcpt.email_status = EmailTransferStatus::sent();
+                    rcpt.email_status = EmailTransferStatus::failed(error);
+                    continue;
                 }
-                Some(Err(error)) => {
-                    tracing::error!(%error, "Email delivery failure.");
+            };
+            let message = build_mbox_message(from, &timestamp, &format!("{headers}{content}"));
 
-                    rcpt.email_status
-                        .held_back(TransferErrorsVariant::LocalDeliveryError {
-                            error: error.to_string(),
-                        });
-                }
+            let user = match users::get_user_by_name(rcpt.address.local_part()) {
+                Some(user) => user,
                 None => {
                     tracing::error!(
                         error = format!("user not found: {}", rcpt.address.local_part()),
@@ -87,6 +99,37 @@ impl Transport for MBox {
                         .held_back(TransferErrorsVariant::NoSuchMailbox {
                             name: rcpt.address.local_part().to_owned(),
                         });
+                    continue;
+                }
+            };
+
+            // NOTE: only linux system is supported here, is the
+            //       path to all mboxes always /var/mail ?
+            match write_content_to_mbox(
+                &std::path::PathBuf::from_iter(["/", "var", "mail", rcpt.address.local_part()]),
+                &user,
+                config.server.system.group_local.as_ref(),
+                config.server.queues.delivery.mbox_lock_timeout,
+                &message,
+            )
+            .await
+            {
+                Ok(()) => {
+                    tracing::info!("Email delivered.");
+
+                    rcpt.email_status = EmailTransferStatus::sent();
+                }
+                Err(error) => {
+                    if error.is::<MboxLocked>() {
+                        tracing::warn!(%error, "Email delivery failure.");
+                    } else {
+                        tracing::error!(%error, "Email delivery failure.");
+                    }
+
+                    rcpt.email_status
+                        .held_back(TransferErrorsVariant::LocalDeliveryError {
+                            error: error.to_string(),
+                        });
                 }
             }
         }
@@ -100,31 +143,124 @@ fn get_mbox_timestamp_format(timestamp: &time::OffsetDateTime) -> String {
         .unwrap_or_else(|_| String::default())
 }
 
+/// Prefix the message with its `From ` separator line, and quote the lines of the
+/// message that would be read as a separator, as the "mboxrd" format does.
 fn build_mbox_message(from: &Option<Address>, timestamp: &str, content: &str) -> String {
-    format!(
-        "From {} {timestamp}\n{content}\n",
+    let mut message = format!(
+        "From {} {timestamp}\n",
         from.as_ref()
             .map_or_else(|| "null".to_owned(), ToString::to_string)
-    )
+    );
+    for line in content.split_inclusive('\n') {
+        if line.trim_start_matches('>').starts_with("From ") {
+            message.push('>');
+        }
+        message.push_str(line);
+    }
+    message.push('\n');
+    message
+}
+
+/// The mailbox is locked by another process.
+#[derive(Debug)]
+struct MboxLocked;
+
+impl core::fmt::Display for MboxLocked {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "the mbox is locked by another process")
+    }
+}
+
+impl std::error::Error for MboxLocked {}
+
+/// The `<mbox>.lock` file created for the readers of the mailbox using dot-locking,
+/// removed when dropped.
+struct DotLock(std::path::PathBuf);
+
+impl DotLock {
+    /// Create the dot-lock of `mbox`, or `None` if it is held by another process.
+    fn try_create(mbox: &std::path::Path) -> anyhow::Result<Option<Self>> {
+        let mut path = mbox.as_os_str().to_owned();
+        path.push(".lock");
+        let path = std::path::PathBuf::from(path);
+
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => Ok(Some(Self(path))),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                let is_stale = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .map_or(false, |age| age > STALE_LOCK);
+                if is_stale {
+                    tracing::warn!(lock = ?path.display(), "Removing a stale mbox lock.");
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("failed to remove '{}'", path.display()))?;
+                }
+                Ok(None)
+            }
+            Err(error) => {
+                Err(error).with_context(|| format!("failed to create '{}'", path.display()))
+            }
+        }
+    }
+}
+
+impl Drop for DotLock {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_file(&self.0) {
+            tracing::warn!(%error, lock = ?self.0.display(), "Failed to remove the mbox lock.");
+        }
+    }
+}
+
+/// Open `mbox` holding both its dot-lock and an exclusive `flock`, waiting for the
+/// other processes to release them up to `timeout`.
+async fn lock_mbox(
+    mbox: &std::path::Path,
+    timeout: core::time::Duration,
+) -> anyhow::Result<(DotLock, std::fs::File)> {
+    tokio::time::timeout(timeout, wait_for_lock(mbox))
+        .await
+        .unwrap_or_else(|_elapsed| Err(anyhow::anyhow!(MboxLocked)))
 }
 
-fn write_content_to_mbox(
-    rcpt: &Rcpt,
+async fn wait_for_lock(mbox: &std::path::Path) -> anyhow::Result<(DotLock, std::fs::File)> {
+    loop {
+        if let Some(dot_lock) = DotLock::try_create(mbox)? {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(mbox)?;
+
+            if try_flock_exclusive(&file)? {
+                return Ok((dot_lock, file));
+            }
+        }
+
+        tokio::time::sleep(LOCK_RETRY_DELAY).await;
+    }
+}
+
+async fn write_content_to_mbox(
     mbox: &std::path::Path,
     user: &users::User,
     group_local: Option<&users::Group>,
+    lock_timeout: core::time::Duration,
     content: &str,
 ) -> anyhow::Result<()> {
-    let mut file = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(mbox)?;
+    let (_dot_lock, mut file) = lock_mbox(mbox, lock_timeout).await?;
 
     chown(mbox, Some(user.uid()), group_local.map(users::Group::gid))
         .with_context(|| format!("could not set owner for '{mbox:?}' mbox"))?;
 
-    std::io::Write::write_all(&mut file, format!("Delivered-To: {rcpt}\n").as_bytes())?;
     std::io::Write::write_all(&mut file, content.as_bytes())?;
+    file.sync_all()?;
 
     Ok(())
 }
@@ -136,6 +272,18 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn capabilities() {
+        assert_eq!(MBox::default().name(), "mbox");
+        assert_eq!(
+            MBox::default().capabilities(),
+            Capabilities {
+                local: true,
+                multi_recipient: false
+            }
+        );
+    }
+
     #[test]
     fn test_mbox_time_format() {
         // FIXME: I did not find a proper way to compare timestamps because the system time
@@ -169,8 +317,24 @@ This is a raw email.
     }
 
     #[test]
+    fn test_mbox_message_escaping() {
+        let timestamp = get_mbox_timestamp_format(&time::OffsetDateTime::UNIX_EPOCH);
+
+        let message = build_mbox_message(
+            &None,
+            &timestamp,
+            "subject: test email\r\n\r\nFrom here\r\n>From there\r\n From nowhere\r\n",
+        );
+
+        assert_eq!(
+            message,
+            "From null Thu Jan  1 00:00:00 1970\nsubject: test email\r\n\r\n>From here\r\n>>From there\r\n From nowhere\r\n\n"
+        );
+    }
+
+    #[tokio::test]
     #[ignore]
-    fn test_writing_to_mbox() {
+    async fn test_writing_to_mbox() {
         let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
         let content = "From 0 john@doe.com\nfrom: john doe <john@doe.com>\n";
         let mbox =
@@ -178,16 +342,97 @@ This is a raw email.
 
         std::fs::create_dir_all("./tests/generated/").unwrap();
         write_content_to_mbox(
-            &Rcpt::new(addr!("john.doe@example.com")),
             &mbox,
             &user,
             None,
+            core::time::Duration::from_secs(1),
             content,
         )
+        .await
         .unwrap();
 
         assert_eq!(content.to_owned(), std::fs::read_to_string(&mbox).unwrap());
 
         std::fs::remove_file(mbox).unwrap();
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_deliveries() {
+        let mbox = std::env::temp_dir().join(format!("mbox-{}", uuid::Uuid::new_v4()));
+        let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+        let timestamp = get_mbox_timestamp_format(&time::OffsetDateTime::UNIX_EPOCH);
+
+        let messages = ["a", "b"].map(|body| {
+            build_mbox_message(
+                &Some(addr!("john@doe.com")),
+                &timestamp,
+                &format!("subject: {body}\n\n{}\n", body.repeat(1_000_000)),
+            )
+        });
+
+        let deliveries = messages.clone().map(|message| {
+            let mbox = mbox.clone();
+            let user = user.clone();
+            tokio::spawn(async move {
+                write_content_to_mbox(
+                    &mbox,
+                    &user,
+                    None,
+                    core::time::Duration::from_secs(10),
+                    &message,
+                )
+                .await
+            })
+        });
+        for delivery in deliveries {
+            delivery.await.unwrap().unwrap();
+        }
+
+        let content = std::fs::read_to_string(&mbox).unwrap();
+        let [first, second] = messages;
+        assert!(
+            content == format!("{first}{second}") || content == format!("{second}{first}"),
+            "the messages have been interleaved"
+        );
+
+        let mut lock = mbox.as_os_str().to_owned();
+        lock.push(".lock");
+        assert!(!std::path::Path::new(&lock).exists());
+
+        std::fs::remove_file(mbox).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lock_timeout() {
+        let mbox = std::env::temp_dir().join(format!("mbox-{}", uuid::Uuid::new_v4()));
+        let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+
+        let dot_lock = DotLock::try_create(&mbox).unwrap().unwrap();
+        assert!(DotLock::try_create(&mbox).unwrap().is_none());
+
+        let error = write_content_to_mbox(
+            &mbox,
+            &user,
+            None,
+            core::time::Duration::from_millis(300),
+            "From null Thu Jan  1 00:00:00 1970\n\n",
+        )
+        .await
+        .unwrap_err();
+        assert!(error.is::<MboxLocked>());
+        assert!(!mbox.exists());
+
+        drop(dot_lock);
+        write_content_to_mbox(
+            &mbox,
+            &user,
+            None,
+            core::time::Duration::from_millis(300),
+            "From null Thu Jan  1 00:00:00 1970\n\n",
+        )
+        .await
+        .unwrap();
+
+        std::fs::remove_file(mbox).unwrap();
+    }
 }
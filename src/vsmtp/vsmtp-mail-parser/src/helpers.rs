@@ -24,6 +24,43 @@ pub fn start_with_fws(input: &str) -> bool {
     input.starts_with(|c| c == ' ' || c == '\t')
 }
 
+/// Get the addresses of an address list (RFC 5322 3.4), without the display names
+/// and the group names.
+pub fn split_address_list(input: &str) -> Vec<String> {
+    let mut mailboxes = vec![];
+    let mut current = String::new();
+    let (mut quoted, mut in_angle) = (false, false);
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                if in_angle {
+                    current.push(c);
+                }
+            }
+            '<' if !quoted => {
+                // NOTE: the display name is dropped.
+                current.clear();
+                in_angle = true;
+            }
+            '>' if !quoted => in_angle = false,
+            // NOTE: the name of a group.
+            ':' if !quoted && !in_angle => current.clear(),
+            ',' | ';' if !quoted && !in_angle => mailboxes.push(std::mem::take(&mut current)),
+            _ if quoted && !in_angle => {}
+            _ => current.push(c),
+        }
+    }
+    mailboxes.push(current);
+
+    mailboxes
+        .into_iter()
+        .map(|mailbox| mailbox.trim().to_string())
+        .filter(|mailbox| !mailbox.is_empty())
+        .collect()
+}
+
 /*
 /// See <https://datatracker.ietf.org/doc/html/rfc5322#page-11>
 pub fn remove_comments(line: &str) -> anyhow::Result<String> {
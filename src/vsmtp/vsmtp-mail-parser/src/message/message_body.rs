@@ -15,7 +15,10 @@
  *
 */
 
-use crate::{implementation::basic_parser::BasicParser, Mail, MailParser, RawBody};
+use crate::{
+    helpers::split_address_list, implementation::basic_parser::BasicParser, Mail, MailParser,
+    RawBody,
+};
 
 // NOTE: should it be a tristate enum?
 // enum {
@@ -104,6 +107,36 @@ impl MessageBody {
             .map_or_else(|| self.raw.count_header(name), |p| p.count_header(name))
     }
 
+    /// Get the addresses of the recipients listed in the `To`, `Cc` and `Bcc` headers.
+    ///
+    /// They are not the recipients of the envelope (`RCPT TO`), to which the message is delivered.
+    #[must_use]
+    pub fn header_recipients(&self) -> Vec<String> {
+        self.header_addresses(&["to", "cc", "bcc"])
+    }
+
+    /// Get the addresses of the authors listed in the `From` headers.
+    ///
+    /// A `From` header can hold several addresses, and a malformed message can have several `From` headers,
+    /// all of them are returned. It is not the sender of the envelope (`MAIL FROM`).
+    #[must_use]
+    pub fn header_from(&self) -> Vec<String> {
+        self.header_addresses(&["from"])
+    }
+
+    fn header_addresses(&self, names: &[&str]) -> Vec<String> {
+        self.raw
+            .headers()
+            .into_iter()
+            .filter(|(name, _)| {
+                names
+                    .iter()
+                    .any(|header| name.trim().eq_ignore_ascii_case(header))
+            })
+            .flat_map(|(_, value)| split_address_list(&value))
+            .collect()
+    }
+
     /// rewrite a header with a new value or add it to the header section.
     pub fn set_header(&mut self, name: &str, value: &str) {
         if let Some(parsed) = &mut self.parsed {
@@ -177,7 +177,12 @@ impl RawBody {
                 .to_lowercase()
                 .starts_with(&format!("{}:", name.to_lowercase()))
         }) {
-            self.headers.remove(index);
+            // NOTE: the folded lines of the header are removed with it.
+            let folded = self.headers[index + 1..]
+                .iter()
+                .take_while(|line| line.starts_with(' ') || line.starts_with('\t'))
+                .count();
+            self.headers.drain(index..=index + folded);
             true
         } else {
             false
@@ -89,3 +89,87 @@ fn test_set_and_append_header() {
         Some(new_header_message.to_string())
     );
 }
+
+#[test]
+fn test_header_recipients() {
+    let message = MessageBody::new(
+        [
+            "From: john <john@example.com>\r\n",
+            "To: green@example.com, \"Doe, Jane\" <jane.doe@example.com>\r\n",
+            "Cc: friends: alice@example.com, <bob@example.com>;\r\n",
+            "Bcc: hidden@example.com,\r\n",
+            " \"Another\" <another@example.com>\r\n",
+            "Subject: test message\r\n",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect(),
+        "Hello world!\r\n".to_string(),
+    );
+
+    assert_eq!(
+        message.header_recipients(),
+        [
+            "green@example.com",
+            "jane.doe@example.com",
+            "alice@example.com",
+            "bob@example.com",
+            "hidden@example.com",
+            "another@example.com",
+        ]
+    );
+}
+
+#[test]
+fn test_header_from() {
+    let message = MessageBody::new(
+        [
+            "From: john <john@example.com>, \"Doe, Jane\" <jane.doe@example.org>\r\n",
+            "To: green@example.com\r\n",
+            "Subject: test message\r\n",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect(),
+        "Hello world!\r\n".to_string(),
+    );
+
+    assert_eq!(
+        message.header_from(),
+        ["john@example.com", "jane.doe@example.org"]
+    );
+
+    let message = MessageBody::new(
+        ["To: green@example.com\r\n".to_string()].to_vec(),
+        "Hello world!\r\n".to_string(),
+    );
+
+    assert!(message.header_from().is_empty());
+}
+
+#[test]
+fn test_remove_folded_header() {
+    let mut message = MessageBody::new(
+        [
+            "From: john <john@example.com>\r\n",
+            "Bcc: hidden@example.com,\r\n",
+            " another@example.com\r\n",
+            "Subject: test message\r\n",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect(),
+        "Hello world!\r\n".to_string(),
+    );
+
+    assert!(message.remove_header("Bcc"));
+    assert_eq!(
+        message.inner().to_string(),
+        concat!(
+            "From: john <john@example.com>\r\n",
+            "Subject: test message\r\n",
+            "\r\n",
+            "Hello world!\r\n",
+        )
+    );
+}
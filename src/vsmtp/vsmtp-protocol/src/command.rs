@@ -16,7 +16,11 @@
 */
 
 use crate::ConnectionKind;
-use vsmtp_common::{auth::Mechanism, ClientName};
+use vsmtp_common::{
+    auth::Mechanism,
+    transfer::{DsnReturn, NotifyOn, OriginalRecipient},
+    ClientName, DuplicateParamsPolicy, UnknownParamsPolicy,
+};
 extern crate alloc;
 
 /// Buffer received from the client.
@@ -91,6 +95,23 @@ pub enum MimeBodyType {
 }
 
 /// Information received from the client at the MAIL FROM command.
+///
+/// The accepted grammar of the arguments of `MAIL FROM` and `RCPT TO` is:
+///
+/// ```text
+/// ; with `ArgsPolicy::strict_syntax`, as RFC 5321
+/// args    = "<" [ path ] ">" *( SP param ) CRLF
+/// ; otherwise
+/// args    = *WSP "<" [ path ] ">" *( 1*WSP param ) *WSP CRLF
+///
+/// path    = 1*( any character except whitespace and ">" )
+/// param   = keyword [ "=" value ]
+/// keyword = ( ALPHA / DIGIT ) *( ALPHA / DIGIT / "-" )
+/// value   = 1*( %d33-60 / %d62-126 )
+/// ```
+///
+/// In the lenient grammar, the empty parameters produced by the extra
+/// whitespace are ignored.
 #[non_exhaustive]
 pub struct MailFromArgs {
     /// Sender address.
@@ -98,10 +119,19 @@ pub struct MailFromArgs {
     pub reverse_path: Option<String>,
     /// (8BITMIME)
     pub mime_body_type: Option<MimeBodyType>,
-    // TODO:
-    // Option<String>       (AUTH)
-    // Option<usize>        (SIZE)
-    // use_smtputf8: bool,
+    /// Declared size of the message in bytes (SIZE), `0` meaning the size is unknown.
+    pub message_size: Option<usize>,
+    /// Content of the message to return in the delivery status notifications (DSN, `RET`).
+    pub dsn_return: Option<DsnReturn>,
+    /// Identifier of the envelope, decoded from xtext (DSN, `ENVID`).
+    pub envelop_id: Option<String>,
+    /// The message requires the support of UTF-8 in the addresses and headers (SMTPUTF8, RFC 6531).
+    pub use_smtputf8: bool,
+    /// Submitter of the message, decoded from xtext, or `<>` if unknown (AUTH, RFC 4954).
+    pub auth_mailbox: Option<String>,
+    /// The message must only be relayed over authenticated and encrypted connections
+    /// (REQUIRETLS, RFC 8689).
+    pub require_tls: bool,
 }
 
 /// Information received from the client at the RCPT TO command.
@@ -110,6 +140,10 @@ pub struct RcptToArgs {
     /// Recipient address.
     // TODO: wrap in a type mailbox
     pub forward_path: String,
+    /// Delivery status notifications requested for this recipient (DSN, `NOTIFY`).
+    pub notify_on: Option<NotifyOn>,
+    /// Original recipient, decoded from xtext (DSN, `ORCPT`).
+    pub original_forward_path: Option<OriginalRecipient>,
 }
 
 /// Information received from the client at the AUTH command.
@@ -122,6 +156,43 @@ pub struct AuthArgs {
     pub initial_response: Option<Vec<u8>>,
 }
 
+/// Information received from the client at the BDAT command.
+///
+/// ```text
+/// bdat-cmd   = "BDAT" SP chunk-size [ SP end-marker ] CRLF
+/// chunk-size = 1*DIGIT
+/// end-marker = "LAST"
+/// ```
+#[non_exhaustive]
+pub struct BdatArgs {
+    /// Size of the chunk in bytes, the chunk follows the command.
+    pub chunk_size: usize,
+    /// The chunk is the last one of the message (LAST).
+    pub is_last: bool,
+}
+
+/// Information received from the client at the VRFY command.
+///
+/// ```text
+/// vrfy = "VRFY" SP String CRLF
+/// ```
+#[non_exhaustive]
+pub struct VrfyArgs {
+    /// The user name or mailbox to verify.
+    pub query: String,
+}
+
+/// Information received from the client at the EXPN command.
+///
+/// ```text
+/// expn = "EXPN" SP String CRLF
+/// ```
+#[non_exhaustive]
+pub struct ExpnArgs {
+    /// The mailing list to expand.
+    pub list: String,
+}
+
 /// Error while parsing the arguments of a command.
 #[non_exhaustive]
 pub enum ParseArgsError {
@@ -136,6 +207,12 @@ pub enum ParseArgsError {
         /// actual size of the buffer we got
         got: usize,
     },
+    /// An ESMTP parameter has been given more than once.
+    DuplicateParam(String),
+    /// A percent-hack or UUCP address, see [`ArgsPolicy::reject_legacy_routing`].
+    LegacyRouting(String),
+    /// An ESMTP parameter not supported, see [`ArgsPolicy::unknown_params`].
+    UnknownParam(String),
     /// Other
     // FIXME: improve that
     InvalidArgs,
@@ -162,6 +239,47 @@ impl TryFrom<UnparsedArgs> for HeloArgs {
     }
 }
 
+/// Parse the `SP String CRLF` argument of VRFY and EXPN.
+fn parse_string_arg(value: UnparsedArgs) -> Result<String, ParseArgsError> {
+    let value = value
+        .0
+        .strip_suffix(b"\r\n")
+        .and_then(|value| value.strip_prefix(b" "))
+        .ok_or(ParseArgsError::InvalidArgs)?
+        .to_vec();
+
+    let value = String::from_utf8(value).map_err(ParseArgsError::InvalidUtf8)?;
+    let value = value.trim();
+
+    if value.is_empty() {
+        return Err(ParseArgsError::InvalidArgs);
+    }
+
+    Ok(value.to_owned())
+}
+
+impl TryFrom<UnparsedArgs> for VrfyArgs {
+    type Error = ParseArgsError;
+
+    #[inline]
+    fn try_from(value: UnparsedArgs) -> Result<Self, Self::Error> {
+        Ok(Self {
+            query: parse_string_arg(value)?,
+        })
+    }
+}
+
+impl TryFrom<UnparsedArgs> for ExpnArgs {
+    type Error = ParseArgsError;
+
+    #[inline]
+    fn try_from(value: UnparsedArgs) -> Result<Self, Self::Error> {
+        Ok(Self {
+            list: parse_string_arg(value)?,
+        })
+    }
+}
+
 impl TryFrom<UnparsedArgs> for EhloArgs {
     type Error = ParseArgsError;
 
@@ -206,6 +324,41 @@ impl TryFrom<UnparsedArgs> for EhloArgs {
     }
 }
 
+impl TryFrom<UnparsedArgs> for BdatArgs {
+    type Error = ParseArgsError;
+
+    #[inline]
+    fn try_from(value: UnparsedArgs) -> Result<Self, Self::Error> {
+        let value = value
+            .0
+            .strip_suffix(b"\r\n")
+            .ok_or(ParseArgsError::InvalidArgs)?;
+
+        let (chunk_size, is_last) = match value.iter().position(|c| *c == b' ') {
+            Some(idx) => {
+                let (chunk_size, end_marker) = value.split_at(idx);
+                if !end_marker.eq_ignore_ascii_case(b" LAST") {
+                    return Err(ParseArgsError::InvalidArgs);
+                }
+                (chunk_size, true)
+            }
+            None => (value, false),
+        };
+
+        if chunk_size.is_empty() || !chunk_size.iter().all(u8::is_ascii_digit) {
+            return Err(ParseArgsError::InvalidArgs);
+        }
+
+        Ok(Self {
+            chunk_size: core::str::from_utf8(chunk_size)
+                .map_err(|_err| ParseArgsError::InvalidArgs)?
+                .parse()
+                .map_err(|_err| ParseArgsError::InvalidArgs)?,
+            is_last,
+        })
+    }
+}
+
 impl TryFrom<UnparsedArgs> for AuthArgs {
     type Error = ParseArgsError;
 
@@ -236,8 +389,10 @@ impl TryFrom<UnparsedArgs> for AuthArgs {
             (value.to_vec(), None)
         };
 
+        // NOTE: the names of the mechanisms are case-insensitive, as the other keywords.
         let mechanism = String::from_utf8(mechanism)
             .map_err(ParseArgsError::InvalidUtf8)?
+            .to_ascii_uppercase()
             .parse()
             .map_err(|_err| ParseArgsError::InvalidArgs)?;
 
@@ -248,41 +403,312 @@ impl TryFrom<UnparsedArgs> for AuthArgs {
     }
 }
 
-impl TryFrom<UnparsedArgs> for MailFromArgs {
-    type Error = ParseArgsError;
+/// Options of the parser of the `MAIL FROM` and `RCPT TO` arguments.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ArgsPolicy {
+    /// Handling of a parameter given more than once.
+    pub duplicate_params: DuplicateParamsPolicy,
+    /// Follow strictly the grammar of RFC 5321, see [`MailFromArgs`].
+    pub strict_syntax: bool,
+    /// Refuse the percent-hack (`user%host@relay`) and UUCP (`host!user@relay`) paths,
+    /// instead of reading them as plain local parts.
+    pub reject_legacy_routing: bool,
+    /// Handling of a parameter not supported.
+    pub unknown_params: UnknownParamsPolicy,
+}
 
+impl ArgsPolicy {
+    /// Create the options of the parser.
     #[inline]
-    fn try_from(value: UnparsedArgs) -> Result<Self, Self::Error> {
-        let value = value
-            .0
-            .strip_suffix(b"\r\n")
-            .ok_or(ParseArgsError::InvalidArgs)?;
+    #[must_use]
+    pub const fn new(
+        duplicate_params: DuplicateParamsPolicy,
+        strict_syntax: bool,
+        reject_legacy_routing: bool,
+        unknown_params: UnknownParamsPolicy,
+    ) -> Self {
+        Self {
+            duplicate_params,
+            strict_syntax,
+            reject_legacy_routing,
+            unknown_params,
+        }
+    }
+}
+
+/// Is `path` routed through its domain to another host, with a `%` (percent-hack)
+/// or a `!` (UUCP) in its local part. A quoted local part is a single mailbox.
+fn is_legacy_routing(path: &[u8]) -> bool {
+    let local_part = path
+        .iter()
+        .rposition(|c| *c == b'@')
+        .map_or(path, |idx| &path[..idx]);
+
+    !local_part.starts_with(b"\"") && local_part.iter().any(|c| matches!(c, b'%' | b'!'))
+}
+
+/// Refuse the legacy routing paths if required by `policy`.
+fn check_legacy_routing(path: &[u8], policy: ArgsPolicy) -> Result<(), ParseArgsError> {
+    if policy.reject_legacy_routing && is_legacy_routing(path) {
+        return Err(ParseArgsError::LegacyRouting(
+            String::from_utf8_lossy(path).into_owned(),
+        ));
+    }
+    Ok(())
+}
 
-        let mut words = value
+/// Handle the parameter `keyword`, which is not one of the `supported` ones of the command,
+/// or is one of them but with a value given when none is expected (or conversely).
+fn check_unknown_param(
+    keyword: &[u8],
+    supported: &[&str],
+    policy: UnknownParamsPolicy,
+) -> Result<(), ParseArgsError> {
+    if supported
+        .iter()
+        .any(|i| keyword.eq_ignore_ascii_case(i.as_bytes()))
+    {
+        return Err(ParseArgsError::InvalidArgs);
+    }
+
+    match policy {
+        UnknownParamsPolicy::Reject => Err(ParseArgsError::UnknownParam(
+            String::from_utf8_lossy(keyword).to_ascii_uppercase(),
+        )),
+        UnknownParamsPolicy::Ignore => Ok(()),
+    }
+}
+
+/// Split the arguments of a `MAIL FROM` or `RCPT TO` command into the path
+/// (without the angle brackets) and the parameters.
+fn split_path_and_params(
+    value: &[u8],
+    strict: bool,
+) -> Result<(&[u8], Vec<&[u8]>), ParseArgsError> {
+    let value = value
+        .strip_suffix(b"\r\n")
+        .ok_or(ParseArgsError::InvalidArgs)?;
+
+    let value = if strict {
+        value
+    } else {
+        let start = value
+            .iter()
+            .position(|c| !c.is_ascii_whitespace())
+            .unwrap_or(value.len());
+        &value[start..]
+    };
+
+    let end = value
+        .iter()
+        .position(|c| *c == b'>')
+        .ok_or(ParseArgsError::InvalidArgs)?;
+    let path = value[..end]
+        .strip_prefix(b"<")
+        .ok_or(ParseArgsError::InvalidArgs)?;
+    if path.iter().any(u8::is_ascii_whitespace) {
+        return Err(ParseArgsError::InvalidArgs);
+    }
+
+    let params = &value[end + 1..];
+    let params = if params.is_empty() {
+        vec![]
+    } else if strict {
+        // NOTE: exactly one space before each parameter, and nothing after the last one.
+        let params = params
+            .strip_prefix(b" ")
+            .ok_or(ParseArgsError::InvalidArgs)?
+            .split(|c| *c == b' ')
+            .collect::<Vec<_>>();
+        if params
+            .iter()
+            .any(|i| i.is_empty() || i.iter().any(u8::is_ascii_whitespace))
+        {
+            return Err(ParseArgsError::InvalidArgs);
+        }
+        params
+    } else {
+        // NOTE: the parameters must be separated from the path.
+        if !params[0].is_ascii_whitespace() {
+            return Err(ParseArgsError::InvalidArgs);
+        }
+        params
             .split(u8::is_ascii_whitespace)
-            .filter(|s| !s.is_empty());
+            .filter(|i| !i.is_empty())
+            .collect()
+    };
 
-        let mailbox = if let Some(s) = words.next() {
-            let mailbox = s
-                .strip_prefix(b"<")
-                .ok_or(ParseArgsError::InvalidArgs)?
-                .strip_suffix(b">")
-                .ok_or(ParseArgsError::InvalidArgs)?;
-            if mailbox.is_empty() {
-                None
-            } else {
-                Some(String::from_utf8(mailbox.to_vec()).map_err(ParseArgsError::InvalidUtf8)?)
+    Ok((path, params))
+}
+
+/// `esmtp-keyword` of RFC 5321.
+fn is_param_keyword(keyword: &[u8]) -> bool {
+    keyword.first().map_or(false, u8::is_ascii_alphanumeric)
+        && keyword
+            .iter()
+            .all(|c| c.is_ascii_alphanumeric() || *c == b'-')
+}
+
+/// `esmtp-value` of RFC 5321, any printable character except "=".
+fn is_param_value(value: &[u8]) -> bool {
+    !value.is_empty() && value.iter().all(|c| c.is_ascii_graphic() && *c != b'=')
+}
+
+/// Split the ESMTP parameters (`keyword[=value]`) of a `MAIL FROM` or `RCPT TO` command.
+fn parse_params<'a>(
+    words: &[&'a [u8]],
+    policy: DuplicateParamsPolicy,
+) -> Result<Vec<(&'a [u8], Option<&'a [u8]>)>, ParseArgsError> {
+    let mut params = Vec::<(&[u8], Option<&[u8]>)>::new();
+
+    for word in words.iter().copied() {
+        let (keyword, value) = match word.iter().position(|c| *c == b'=') {
+            Some(idx) => (&word[..idx], Some(&word[idx + 1..])),
+            None => (word, None),
+        };
+        if !is_param_keyword(keyword) || !value.map_or(true, is_param_value) {
+            return Err(ParseArgsError::InvalidArgs);
+        }
+
+        if let Some(idx) = params
+            .iter()
+            .position(|(i, _)| i.eq_ignore_ascii_case(keyword))
+        {
+            match policy {
+                DuplicateParamsPolicy::Reject => {
+                    return Err(ParseArgsError::DuplicateParam(
+                        String::from_utf8_lossy(keyword).to_ascii_uppercase(),
+                    ))
+                }
+                DuplicateParamsPolicy::LastWins => {
+                    params.remove(idx);
+                }
             }
+        }
+        params.push((keyword, value));
+    }
+
+    Ok(params)
+}
+
+/// Decode a `xtext` value of RFC 3461, where the characters outside of
+/// `"!"` to `"~"`, `"+"` and `"="` are encoded as `"+" 2HEXDIG`.
+fn decode_xtext(value: &[u8]) -> Result<String, ParseArgsError> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut iter = value.iter().copied();
+    while let Some(c) = iter.next() {
+        if c == b'+' {
+            let hex = [
+                iter.next().ok_or(ParseArgsError::InvalidArgs)?,
+                iter.next().ok_or(ParseArgsError::InvalidArgs)?,
+            ];
+            if !hex
+                .iter()
+                .all(|c| c.is_ascii_digit() || matches!(c, b'A'..=b'F'))
+            {
+                return Err(ParseArgsError::InvalidArgs);
+            }
+            #[allow(clippy::expect_used)]
+            out.push(
+                u8::from_str_radix(
+                    core::str::from_utf8(&hex).expect("hexadecimal digits are valid utf8"),
+                    16,
+                )
+                .expect("valid hexadecimal"),
+            );
+        } else {
+            out.push(c);
+        }
+    }
+
+    String::from_utf8(out).map_err(ParseArgsError::InvalidUtf8)
+}
+
+/// `notify-esmtp-value` of RFC 3461.
+///
+/// ```text
+/// notify-esmtp-value = "NEVER" / 1#notify-list-element
+/// notify-list-element = "SUCCESS" / "FAILURE" / "DELAY"
+/// ```
+fn parse_notify(value: &[u8]) -> Result<NotifyOn, ParseArgsError> {
+    if value.eq_ignore_ascii_case(b"NEVER") {
+        return Ok(NotifyOn::never());
+    }
+
+    let mut notify_on = NotifyOn::never();
+    for element in value.split(|c| *c == b',') {
+        if element.eq_ignore_ascii_case(b"SUCCESS") {
+            notify_on.success = true;
+        } else if element.eq_ignore_ascii_case(b"FAILURE") {
+            notify_on.failure = true;
+        } else if element.eq_ignore_ascii_case(b"DELAY") {
+            notify_on.delay = true;
         } else {
             return Err(ParseArgsError::InvalidArgs);
+        }
+    }
+
+    Ok(notify_on)
+}
+
+/// `orcpt-value` of RFC 3461.
+///
+/// ```text
+/// orcpt-value = addr-type ";" xtext
+/// addr-type   = atom
+/// ```
+fn parse_original_recipient(value: &[u8]) -> Result<OriginalRecipient, ParseArgsError> {
+    let separator = value
+        .iter()
+        .position(|c| *c == b';')
+        .ok_or(ParseArgsError::InvalidArgs)?;
+    let (addr_type, mailbox) = (&value[..separator], &value[separator + 1..]);
+    if addr_type.is_empty() || mailbox.is_empty() {
+        return Err(ParseArgsError::InvalidArgs);
+    }
+
+    Ok(OriginalRecipient {
+        addr_type: String::from_utf8(addr_type.to_vec()).map_err(ParseArgsError::InvalidUtf8)?,
+        mailbox: decode_xtext(mailbox)?,
+    })
+}
+
+impl TryFrom<UnparsedArgs> for MailFromArgs {
+    type Error = ParseArgsError;
+
+    #[inline]
+    fn try_from(value: UnparsedArgs) -> Result<Self, Self::Error> {
+        Self::try_from((value, ArgsPolicy::default()))
+    }
+}
+
+impl TryFrom<(UnparsedArgs, ArgsPolicy)> for MailFromArgs {
+    type Error = ParseArgsError;
+
+    #[inline]
+    fn try_from((value, policy): (UnparsedArgs, ArgsPolicy)) -> Result<Self, Self::Error> {
+        let (mailbox, params) = split_path_and_params(&value.0, policy.strict_syntax)?;
+        check_legacy_routing(mailbox, policy)?;
+
+        let mailbox = if mailbox.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8(mailbox.to_vec()).map_err(ParseArgsError::InvalidUtf8)?)
         };
 
         let mut mime_body_type = None;
+        let mut message_size = None;
+        let mut dsn_return = None;
+        let mut envelop_id = None;
+        let mut use_smtputf8 = false;
+        let mut auth_mailbox = None;
+        let mut require_tls = false;
 
         #[allow(clippy::expect_used)]
-        for args in words {
-            match args.strip_prefix(b"BODY=") {
-                Some(args_mime_body_type) if mime_body_type.is_none() => {
+        for (keyword, value) in parse_params(&params, policy.duplicate_params)? {
+            match value {
+                Some(args_mime_body_type) if keyword.eq_ignore_ascii_case(b"BODY") => {
                     mime_body_type = <MimeBodyType as strum::VariantNames>::VARIANTS
                         .iter()
                         .find(|i| {
@@ -294,13 +720,70 @@ impl TryFrom<UnparsedArgs> for MailFromArgs {
                         })
                         .map(|body| body.parse().expect("body found above"));
                 }
-                _ => return Err(ParseArgsError::InvalidArgs),
+                Some(size) if keyword.eq_ignore_ascii_case(b"SIZE") => {
+                    // size-value = 1*20DIGIT (RFC 1870)
+                    if size.len() > 20 || !size.iter().all(u8::is_ascii_digit) {
+                        return Err(ParseArgsError::InvalidArgs);
+                    }
+                    message_size = Some(
+                        core::str::from_utf8(size)
+                            .expect("digits are valid utf8")
+                            .parse()
+                            // NOTE: a size bigger than `usize::MAX` is bigger than any limit.
+                            .unwrap_or(usize::MAX),
+                    );
+                }
+                Some(ret) if keyword.eq_ignore_ascii_case(b"RET") => {
+                    dsn_return = Some(if ret.eq_ignore_ascii_case(b"FULL") {
+                        DsnReturn::Full
+                    } else if ret.eq_ignore_ascii_case(b"HDRS") {
+                        DsnReturn::Headers
+                    } else {
+                        return Err(ParseArgsError::InvalidArgs);
+                    });
+                }
+                Some(id) if keyword.eq_ignore_ascii_case(b"ENVID") => {
+                    // NOTE: the encoded value is limited to 100 characters (RFC 3461)
+                    if id.len() > 100 {
+                        return Err(ParseArgsError::InvalidArgs);
+                    }
+                    envelop_id = Some(decode_xtext(id)?);
+                }
+                None if keyword.eq_ignore_ascii_case(b"SMTPUTF8") => {
+                    use_smtputf8 = true;
+                }
+                None if keyword.eq_ignore_ascii_case(b"REQUIRETLS") => {
+                    require_tls = true;
+                }
+                Some(mailbox) if keyword.eq_ignore_ascii_case(b"AUTH") => {
+                    // NOTE: "<>" is left unchanged by the decoding.
+                    auth_mailbox = Some(decode_xtext(mailbox)?);
+                }
+                _ => check_unknown_param(
+                    keyword,
+                    &[
+                        "BODY",
+                        "SIZE",
+                        "RET",
+                        "ENVID",
+                        "SMTPUTF8",
+                        "REQUIRETLS",
+                        "AUTH",
+                    ],
+                    policy.unknown_params,
+                )?,
             }
         }
 
         Ok(Self {
             reverse_path: mailbox,
             mime_body_type,
+            message_size,
+            dsn_return,
+            envelop_id,
+            use_smtputf8,
+            auth_mailbox,
+            require_tls,
         })
     }
 }
@@ -310,36 +793,47 @@ impl TryFrom<UnparsedArgs> for RcptToArgs {
 
     #[inline]
     fn try_from(value: UnparsedArgs) -> Result<Self, Self::Error> {
-        let value = value
-            .0
-            .strip_suffix(b"\r\n")
-            .ok_or(ParseArgsError::InvalidArgs)?;
+        Self::try_from((value, ArgsPolicy::default()))
+    }
+}
 
-        let mut word = value
-            .split(u8::is_ascii_whitespace)
-            .filter(|s| !s.is_empty());
-
-        let mailbox = if let Some(s) = word.next() {
-            String::from_utf8(
-                s.strip_prefix(b"<")
-                    .ok_or(ParseArgsError::InvalidArgs)?
-                    .strip_suffix(b">")
-                    .ok_or(ParseArgsError::InvalidArgs)?
-                    .to_vec(),
-            )
-            .map_err(ParseArgsError::InvalidUtf8)?
-        } else {
-            return Err(ParseArgsError::InvalidArgs);
-        };
+impl TryFrom<(UnparsedArgs, ArgsPolicy)> for RcptToArgs {
+    type Error = ParseArgsError;
+
+    #[inline]
+    fn try_from((value, policy): (UnparsedArgs, ArgsPolicy)) -> Result<Self, Self::Error> {
+        let (mailbox, params) = split_path_and_params(&value.0, policy.strict_syntax)?;
+        check_legacy_routing(mailbox, policy)?;
+
+        let mailbox = String::from_utf8(mailbox.to_vec()).map_err(ParseArgsError::InvalidUtf8)?;
+
+        let mut notify_on = None;
+        let mut original_forward_path = None;
+
+        for (keyword, value) in parse_params(&params, policy.duplicate_params)? {
+            match value {
+                Some(notify) if keyword.eq_ignore_ascii_case(b"NOTIFY") => {
+                    notify_on = Some(parse_notify(notify)?);
+                }
+                Some(orcpt) if keyword.eq_ignore_ascii_case(b"ORCPT") => {
+                    original_forward_path = Some(parse_original_recipient(orcpt)?);
+                }
+                _ => check_unknown_param(keyword, &["NOTIFY", "ORCPT"], policy.unknown_params)?,
+            }
+        }
 
         Ok(Self {
             forward_path: mailbox,
+            notify_on,
+            original_forward_path,
         })
     }
 }
 
 /// SMTP Command.
-#[derive(Debug, strum::AsRefStr, strum::EnumString, strum::EnumVariantNames)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr, strum::EnumString, strum::EnumVariantNames,
+)]
 #[non_exhaustive]
 pub enum Verb {
     /// Used to identify the SMTP client to the SMTP server. (historical)
@@ -377,6 +871,15 @@ pub enum Verb {
     /// and return more specific information as a response.
     #[strum(serialize = "HELP")]
     Help,
+    /// This command asks the receiver to confirm that the argument
+    /// identifies a user or mailbox.
+    #[strum(serialize = "VRFY")]
+    Vrfy,
+    /// This command asks the receiver to confirm that the argument
+    /// identifies a mailing list, and if so, to return the membership of
+    /// that list.
+    #[strum(serialize = "EXPN")]
+    Expn,
     /// This command does not affect any parameters or previously entered
     /// commands.
     #[strum(serialize = "NOOP\r\n")]
@@ -389,6 +892,10 @@ pub enum Verb {
     /// <https://datatracker.ietf.org/doc/html/rfc4954>
     #[strum(serialize = "AUTH ")]
     Auth,
+    /// Send a chunk of the message, without dot-stuffing
+    /// <https://datatracker.ietf.org/doc/html/rfc3030>
+    #[strum(serialize = "BDAT ")]
+    Bdat,
     /// Any other buffer received while expecting a command is considered an
     /// unknown.
     Unknown,
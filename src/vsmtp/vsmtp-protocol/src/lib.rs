@@ -54,8 +54,8 @@ mod smtp_sasl;
 mod stream;
 
 pub use command::{
-    AcceptArgs, AuthArgs, EhloArgs, HeloArgs, MailFromArgs, ParseArgsError, RcptToArgs,
-    UnparsedArgs, Verb,
+    AcceptArgs, ArgsPolicy, AuthArgs, BdatArgs, EhloArgs, ExpnArgs, HeloArgs, MailFromArgs,
+    ParseArgsError, RcptToArgs, UnparsedArgs, Verb, VrfyArgs,
 };
 pub use connection_kind::ConnectionKind;
 pub use receiver::{Receiver, ReceiverContext};
@@ -16,17 +16,19 @@
 */
 use crate::{
     sink::Sink,
-    stream::{Error, Stream},
-    AcceptArgs, AuthArgs, ConnectionKind, EhloArgs, HeloArgs, MailFromArgs, ParseArgsError,
-    RcptToArgs, ReceiverHandler, Verb,
+    stream::{chunks_as_message_stream, Error, Stream},
+    AcceptArgs, ArgsPolicy, AuthArgs, BdatArgs, ConnectionKind, EhloArgs, ExpnArgs, HeloArgs,
+    MailFromArgs, ParseArgsError, RcptToArgs, ReceiverHandler, Verb, VrfyArgs,
 };
 use tokio_rustls::rustls;
 use tokio_stream::StreamExt;
-use vsmtp_common::{auth::Mechanism, Stage};
+use vsmtp_common::{auth::Mechanism, Reply, Stage};
 extern crate alloc;
 
 enum HandshakeOutcome {
     Message,
+    /// The message has been received with `BDAT` commands.
+    ChunkedMessage(Result<Vec<u8>, Error>),
     UpgradeTLS {
         config: alloc::sync::Arc<rustls::ServerConfig>,
         handshake_timeout: std::time::Duration,
@@ -38,6 +40,14 @@ enum HandshakeOutcome {
     Quit,
 }
 
+/// The body of a message received with `BDAT` commands.
+#[derive(Default)]
+struct Chunks {
+    body: Vec<u8>,
+    /// Total size of the chunks, the body is dropped once it exceeds the limit.
+    size: usize,
+}
+
 pub struct ErrorCounter {
     pub error_count: i64,
     pub threshold_soft_error: i64,
@@ -94,9 +104,13 @@ pub struct Receiver<
     pub(crate) sink: Sink<W>,
     pub(crate) stream: Stream<R>,
     error_counter: ErrorCounter,
-    context: ReceiverContext,
+    pub(crate) context: ReceiverContext,
     kind: ConnectionKind,
     message_size_max: usize,
+    args_policy: ArgsPolicy,
+    lenient_quit: bool,
+    /// Set after the first `BDAT` of a transaction, until the `LAST` one.
+    chunks: Option<Chunks>,
     v: std::marker::PhantomData<V>,
 }
 
@@ -111,6 +125,16 @@ where
         handshake_timeout: std::time::Duration,
     ) -> impl tokio_stream::Stream<Item = std::io::Result<()>> {
         async_stream::try_stream! {
+            // NOTE: the commands pipelined after `STARTTLS` must not be processed
+            //       once the TLS session is established (RFC 2920 and RFC 3207).
+            if self.stream.buffered_len() != 0 {
+                tracing::warn!(
+                    "Discarding {} bytes received after STARTTLS.",
+                    self.stream.buffered_len()
+                );
+            }
+
+            let log_dialog = self.sink.log_dialog;
             let tcp_stream = self
                 .sink
                 .inner
@@ -141,7 +165,7 @@ where
             // FIXME: see https://github.com/tokio-rs/tls/issues/40
             let (read, write) = tokio::io::split(tls_tcp_stream);
 
-            let (stream, sink) = (Stream::new(read), Sink::new(write));
+            let (stream, sink) = (Stream::new(read), Sink::new(write, log_dialog));
 
             let secured_receiver = Receiver {
                 sink,
@@ -151,6 +175,9 @@ where
                 error_counter: self.error_counter,
                 kind: self.kind,
                 message_size_max: self.message_size_max,
+                args_policy: self.args_policy,
+                lenient_quit: self.lenient_quit,
+                chunks: None,
                 v: self.v,
             }.into_secured_stream(
                 sni,
@@ -168,6 +195,7 @@ where
 
     /// Create a new [`Receiver`] from a TCP/IP stream.
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tcp_stream: tokio::net::TcpStream,
         kind: ConnectionKind,
@@ -175,9 +203,11 @@ where
         threshold_soft_error: i64,
         threshold_hard_error: i64,
         message_size_max: usize,
+        args_policy: ArgsPolicy,
+        lenient_quit: bool,
     ) -> Self {
         let (read, write) = tcp_stream.into_split();
-        let (stream, sink) = (Stream::new(read), Sink::new(write));
+        let (stream, sink) = (Stream::new(read), Sink::new(write, true));
         Self {
             handler,
             sink,
@@ -190,10 +220,22 @@ where
             context: ReceiverContext { outcome: None },
             kind,
             message_size_max,
+            args_policy,
+            lenient_quit,
+            chunks: None,
             v: std::marker::PhantomData,
         }
     }
 
+    /// Log (or not) the SMTP dialog, that is each command received and reply sent,
+    /// at the `trace` level. Enabled by default.
+    #[inline]
+    #[must_use]
+    pub fn with_dialog_log(mut self, log_dialog: bool) -> Self {
+        self.sink.log_dialog = log_dialog;
+        self
+    }
+
     /// Handle the inner stream to produce a [`tokio_stream::Stream`], each item
     /// being a successful SMTP transaction.
     #[inline]
@@ -219,7 +261,9 @@ where
             let produced_context_accept = std::mem::take(&mut self.context);
             if let Some(outcome) = produced_context_accept.outcome {
                 match outcome {
-                    HandshakeOutcome::Message | HandshakeOutcome::Authenticate { .. } => todo!(),
+                    HandshakeOutcome::Message
+                    | HandshakeOutcome::ChunkedMessage(_)
+                    | HandshakeOutcome::Authenticate { .. } => todo!(),
                     HandshakeOutcome::UpgradeTLS { config, handshake_timeout } => {
                         for await i in self.upgrade_tls(config, handshake_timeout) {
                             yield i?;
@@ -247,6 +291,17 @@ where
 
                         yield ();
                     },
+                    HandshakeOutcome::ChunkedMessage(body) => {
+                        let message_stream = chunks_as_message_stream(body);
+                        tokio::pin!(message_stream);
+
+                        let reply = self.handler.on_message(&mut self.context, message_stream).await;
+                        self.sink
+                            .send_reply(&mut self.context, &mut self.error_counter, &mut self.handler, reply)
+                            .await?;
+
+                        yield ();
+                    },
                     HandshakeOutcome::UpgradeTLS { config, handshake_timeout } => {
                         for await i in self.upgrade_tls(config, handshake_timeout) {
                             yield i?;
@@ -323,6 +378,17 @@ where
 
                         yield ();
                     },
+                    HandshakeOutcome::ChunkedMessage(body) => {
+                        let message_stream = chunks_as_message_stream(body);
+                        tokio::pin!(message_stream);
+
+                        let reply = self.handler.on_message(&mut self.context, message_stream).await;
+                        self.sink
+                            .send_reply(&mut self.context, &mut self.error_counter, &mut self.handler, reply)
+                            .await?;
+
+                        yield ();
+                    },
                     HandshakeOutcome::UpgradeTLS { .. } => todo!(),
                     HandshakeOutcome::Authenticate { mechanism, initial_response } => {
                         let auth_result = self.authenticate(mechanism, initial_response).await;
@@ -345,6 +411,54 @@ where
         }
     }
 
+    /// Read the chunk following a `BDAT` command, the message is produced
+    /// after the `LAST` one.
+    async fn receive_chunk(&mut self, args: BdatArgs) -> std::io::Result<Option<Reply>> {
+        // NOTE: the checks of `DATA` are run before the first chunk of the message.
+        if self.chunks.is_none() {
+            let reply = self.handler.on_data().await;
+            if reply.code().is_error() {
+                let chunk_stream = self.stream.as_chunk_stream(args.chunk_size);
+                tokio::pin!(chunk_stream);
+                while chunk_stream.try_next().await?.is_some() {}
+                return Ok(Some(reply));
+            }
+        }
+
+        let chunks = self.chunks.get_or_insert_with(Chunks::default);
+
+        let chunk_stream = self.stream.as_chunk_stream(args.chunk_size);
+        tokio::pin!(chunk_stream);
+
+        while let Some(part) = chunk_stream.try_next().await? {
+            chunks.size += part.len();
+            if chunks.size > self.message_size_max {
+                chunks.body = vec![];
+            } else {
+                chunks.body.extend(part);
+            }
+        }
+
+        if !args.is_last {
+            return Ok(Some(self.handler.on_chunk(args.chunk_size).await));
+        }
+
+        #[allow(clippy::expect_used)]
+        let chunks = self.chunks.take().expect("set above");
+        self.context.outcome = Some(HandshakeOutcome::ChunkedMessage(
+            if chunks.size > self.message_size_max {
+                Err(Error::BufferTooLong {
+                    expected: self.message_size_max,
+                    got: chunks.size,
+                })
+            } else {
+                Ok(chunks.body)
+            },
+        ));
+
+        Ok(None)
+    }
+
     /// SMTP handshake (generate the envelope and metadata).
     ///
     /// # Returns
@@ -367,14 +481,19 @@ where
             };
         }
 
-        let command_stream = self
-            .stream
-            .as_command_stream()
-            .timeout(std::time::Duration::from_secs(30));
-        tokio::pin!(command_stream);
-
         loop {
-            let command = match command_stream.try_next().await {
+            // NOTE: the command stream is re-created for each command, as the
+            //       stream is also used to read the chunks of `BDAT`.
+            let command = {
+                let command_stream = self
+                    .stream
+                    .as_command_stream(self.lenient_quit)
+                    .timeout(std::time::Duration::from_secs(30));
+                tokio::pin!(command_stream);
+                command_stream.try_next().await
+            };
+
+            let command = match command {
                 Ok(Some(command)) => command,
                 Ok(None) => return Ok(HandshakeOutcome::Quit),
                 Err(e) => {
@@ -416,49 +535,114 @@ where
                     Error::Io(io) => return Err(io),
                 },
             };
-            tracing::trace!("<< {:?} ; {:?}", verb, std::str::from_utf8(&args.0));
+            if self.sink.log_dialog {
+                tracing::trace!("<< {:?} ; {:?}", verb, std::str::from_utf8(&args.0));
+            }
+            let started = std::time::Instant::now();
+
+            if !matches!(verb, Verb::Quit) {
+                if let Some(reply) = self.handler.on_shutdown().await {
+                    if let Err(e) = self
+                        .sink
+                        .send_reply(
+                            &mut self.context,
+                            &mut self.error_counter,
+                            &mut self.handler,
+                            reply,
+                        )
+                        .await
+                    {
+                        tracing::debug!("Failed to send the last reply: {}", e);
+                    }
+                    return Ok(HandshakeOutcome::Quit);
+                }
+            }
 
             let stage = self.handler.get_stage();
             let reply = match (verb, stage) {
+                // NOTE: only these commands can be interleaved with `BDAT` (RFC 3030)
+                (verb, stage)
+                    if self.chunks.is_some()
+                        && !matches!(verb, Verb::Bdat | Verb::Rset | Verb::Noop | Verb::Quit) =>
+                {
+                    Some(self.handler.on_bad_sequence((verb, stage)).await)
+                }
                 (Verb::Helo, _) => Some(handle_args!(HeloArgs, args, on_helo)),
                 (Verb::Ehlo, _) => Some(handle_args!(EhloArgs, args, on_ehlo)),
                 (Verb::Noop, _) => Some(self.handler.on_noop().await),
-                (Verb::Rset, _) => Some(self.handler.on_rset().await),
+                (Verb::Rset, _) => {
+                    self.chunks = None;
+                    Some(self.handler.on_rset().await)
+                }
                 (Verb::StartTls, Stage::Connect | Stage::Helo) => {
                     Some(self.handler.on_starttls(&mut self.context).await)
                 }
                 (Verb::Auth, Stage::Connect | Stage::Helo) => {
                     handle_args!(AuthArgs, args, Option: on_auth)
                 }
-                (Verb::MailFrom, Stage::Helo | Stage::MailFrom) => {
-                    Some(handle_args!(MailFromArgs, args, on_mail_from))
-                }
-                (Verb::RcptTo, Stage::MailFrom | Stage::RcptTo) => {
-                    Some(handle_args!(RcptToArgs, args, on_rcpt_to))
-                }
+                (Verb::MailFrom, Stage::Helo | Stage::MailFrom) => Some(handle_args!(
+                    MailFromArgs,
+                    (args, self.args_policy),
+                    on_mail_from
+                )),
+                (Verb::RcptTo, Stage::MailFrom | Stage::RcptTo) => Some(handle_args!(
+                    RcptToArgs,
+                    (args, self.args_policy),
+                    on_rcpt_to
+                )),
                 (Verb::Data, Stage::RcptTo) => {
-                    self.context.outcome = Some(HandshakeOutcome::Message);
-                    Some(self.handler.on_data().await)
+                    let reply = self.handler.on_data().await;
+                    if !reply.code().is_error() {
+                        self.context.outcome = Some(HandshakeOutcome::Message);
+                    }
+                    Some(reply)
+                }
+                (Verb::Bdat, Stage::RcptTo) => match BdatArgs::try_from(args) {
+                    Ok(args) => self.receive_chunk(args).await?,
+                    Err(e) => Some(self.handler.on_args_error(e).await),
+                },
+                (Verb::Bdat, stage) => {
+                    // NOTE: the chunk is read anyway, so it is not interpreted as commands.
+                    if let Ok(args) = BdatArgs::try_from(args) {
+                        let chunk_stream = self.stream.as_chunk_stream(args.chunk_size);
+                        tokio::pin!(chunk_stream);
+                        while chunk_stream.try_next().await?.is_some() {}
+                    }
+                    Some(self.handler.on_bad_sequence((Verb::Bdat, stage)).await)
                 }
                 (Verb::Quit, _) => {
                     self.context.outcome = Some(HandshakeOutcome::Quit);
                     Some(self.handler.on_quit().await)
                 }
                 (Verb::Help, _) => Some(self.handler.on_help(args).await),
+                (Verb::Vrfy, _) => Some(handle_args!(VrfyArgs, args, on_vrfy)),
+                (Verb::Expn, _) => Some(handle_args!(ExpnArgs, args, on_expn)),
                 (Verb::Unknown, _) => Some(self.handler.on_unknown(args.0).await),
                 otherwise => Some(self.handler.on_bad_sequence(otherwise).await),
             };
 
             if let Some(reply) = reply {
-                self.sink
+                let sent = self
+                    .sink
                     .send_reply(
                         &mut self.context,
                         &mut self.error_counter,
                         &mut self.handler,
                         reply,
                     )
-                    .await?;
+                    .await;
+
+                match sent {
+                    // NOTE: the client could have closed the connection right after QUIT.
+                    Err(e) if matches!(self.context.outcome, Some(HandshakeOutcome::Quit)) => {
+                        tracing::debug!("Failed to send the last reply: {}", e);
+                    }
+                    otherwise => otherwise?,
+                }
             }
+            self.handler
+                .on_command_handled(verb, started.elapsed())
+                .await;
 
             let produced_context = std::mem::take(&mut self.context);
             if let Some(done) = produced_context.outcome {
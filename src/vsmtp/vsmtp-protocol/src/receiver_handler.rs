@@ -16,11 +16,15 @@
 */
 use crate::{
     receiver::ReceiverContext, smtp_sasl::CallbackWrap, stream::Error, AcceptArgs, AuthArgs,
-    AuthError, EhloArgs, HeloArgs, MailFromArgs, ParseArgsError, RcptToArgs, UnparsedArgs, Verb,
+    AuthError, EhloArgs, ExpnArgs, HeloArgs, MailFromArgs, ParseArgsError, RcptToArgs,
+    UnparsedArgs, Verb, VrfyArgs,
 };
 use tokio_rustls::rustls;
 // TODO: should we move these type in this crate
-use vsmtp_common::{Reply, Stage};
+use vsmtp_common::{
+    auth::{BearerToken, TokenError},
+    Reply, Stage,
+};
 
 // NOTE: could have 3 trait to make the implementation easier
 // PreTransactionHandler + TransactionHandler + PostTransactionHandler
@@ -54,6 +58,15 @@ pub trait ReceiverHandler {
     /// Called after receiving a [`Verb::Auth`] command.
     async fn on_auth(&mut self, ctx: &mut ReceiverContext, args: AuthArgs) -> Option<Reply>;
 
+    /// Called with the bearer token sent with the `XOAUTH2` or `OAUTHBEARER` mechanisms.
+    ///
+    /// On error, the reason is sent to the client as a last challenge before failing.
+    async fn on_bearer_token(
+        &mut self,
+        ctx: &mut ReceiverContext,
+        token: BearerToken,
+    ) -> Result<(), TokenError>;
+
     /// Called after a successful SASL handshake.
     async fn on_post_auth(
         &mut self,
@@ -73,7 +86,7 @@ pub trait ReceiverHandler {
     /// Called after receiving a [`Verb::RcptTo`] command.
     async fn on_rcpt_to(&mut self, ctx: &mut ReceiverContext, args: RcptToArgs) -> Reply;
 
-    /// Called after receiving a [`Verb::Data`] command.
+    /// Called after receiving a [`Verb::Data`] command, or the last chunk of [`Verb::Bdat`].
     /// The stream is the body of the message, with dot-stuffing handled.
     /// The stream return `None` when the message is finished (`.<CRLF>`).
     async fn on_message(
@@ -91,7 +104,10 @@ pub trait ReceiverHandler {
     /// Called after receiving a [`Verb::Rset`] command.
     async fn on_rset(&mut self) -> Reply;
 
-    /// Called after receiving a [`Verb::Data`] command.
+    /// Called after receiving a [`Verb::Data`] command, and before the first chunk
+    /// of a [`Verb::Bdat`] command, whose reply is then only used if it is an error.
+    ///
+    /// An error reply refuses the command, and the message is not received.
     #[inline]
     async fn on_data(&mut self) -> Reply {
         #[allow(clippy::expect_used)]
@@ -100,6 +116,16 @@ pub trait ReceiverHandler {
             .expect("valid syntax")
     }
 
+    /// Called after receiving a chunk of a [`Verb::Bdat`] command, except the last one
+    /// which produces the message.
+    #[inline]
+    async fn on_chunk(&mut self, chunk_size: usize) -> Reply {
+        #[allow(clippy::expect_used)]
+        format!("250 2.0.0 {chunk_size} octets received\r\n")
+            .parse()
+            .expect("valid syntax")
+    }
+
     /// Called after receiving a [`Verb::Quit`] command.
     #[inline]
     async fn on_quit(&mut self) -> Reply {
@@ -109,6 +135,17 @@ pub trait ReceiverHandler {
             .expect("valid syntax")
     }
 
+    /// Called after receiving a command, before handling it.
+    /// If the server is stopping, the returned reply is sent and the connection is closed.
+    #[inline]
+    async fn on_shutdown(&mut self) -> Option<Reply> {
+        None
+    }
+
+    /// Called once a command has been handled and its reply sent, with the time taken.
+    #[inline]
+    async fn on_command_handled(&mut self, _: Verb, _: std::time::Duration) {}
+
     /// Called after receiving a [`Verb::Noop`] command.
     #[inline]
     async fn on_noop(&mut self) -> Reply {
@@ -125,10 +162,28 @@ pub trait ReceiverHandler {
             .expect("valid syntax")
     }
 
+    /// Called after receiving a [`Verb::Vrfy`] command.
+    #[inline]
+    async fn on_vrfy(&mut self, _: &mut ReceiverContext, _: VrfyArgs) -> Reply {
+        #[allow(clippy::expect_used)]
+        "252 Cannot VRFY user, but will accept message and attempt delivery\r\n"
+            .parse()
+            .expect("valid syntax")
+    }
+
+    /// Called after receiving a [`Verb::Expn`] command.
+    #[inline]
+    async fn on_expn(&mut self, _: &mut ReceiverContext, _: ExpnArgs) -> Reply {
+        #[allow(clippy::expect_used)]
+        "502 Command not implemented\r\n"
+            .parse()
+            .expect("valid syntax")
+    }
+
     /// Called after receiving an unknown command (unrecognized or unimplemented).
     #[inline]
     async fn on_unknown(&mut self, buffer: Vec<u8>) -> Reply {
-        let unimplemented_command = [b"VRFY".as_slice(), b"EXPN".as_slice(), b"TURN".as_slice()];
+        let unimplemented_command = [b"TURN".as_slice()];
 
         #[allow(clippy::expect_used)]
         if unimplemented_command.iter().any(|c| {
@@ -20,15 +20,22 @@ use vsmtp_common::Reply;
 
 pub struct Sink<W: tokio::io::AsyncWrite + Unpin + Send> {
     pub inner: W,
+    /// Log the commands received and the replies sent at the `trace` level.
+    pub log_dialog: bool,
 }
 
 impl<W: tokio::io::AsyncWrite + Unpin + Send> Sink<W> {
-    pub const fn new(tcp_sink: W) -> Self {
-        Self { inner: tcp_sink }
+    pub const fn new(tcp_sink: W, log_dialog: bool) -> Self {
+        Self {
+            inner: tcp_sink,
+            log_dialog,
+        }
     }
 
     async fn write_all(&mut self, buffer: &str) -> std::io::Result<()> {
-        tracing::trace!(">> {:?}", buffer);
+        if self.log_dialog {
+            tracing::trace!(">> {:?}", buffer);
+        }
         self.inner.write_all(buffer.as_bytes()).await
     }
 
@@ -19,7 +19,7 @@ use crate::{Receiver, ReceiverHandler};
 use base64::{engine::general_purpose::STANDARD, Engine};
 use tokio::io::AsyncWriteExt;
 use tokio_stream::StreamExt;
-use vsmtp_common::auth::Mechanism;
+use vsmtp_common::auth::{BearerToken, Mechanism, TokenError};
 
 ///
 #[repr(transparent)]
@@ -70,6 +70,14 @@ pub enum AuthError {
         #[source]
         source: base64::DecodeError,
     },
+    /// A response of the client during the SASL handshake is longer than allowed.
+    #[error("sasl response is not supposed to be longer than {expected} bytes but got {got}")]
+    LineTooLong {
+        /// Maximum size expected.
+        expected: usize,
+        /// Actual size.
+        got: usize,
+    },
     /// Error while reading/writing to the underlying stream.
     #[error("io error: {0}")]
     IO(#[from] std::io::Error),
@@ -79,6 +87,9 @@ pub enum AuthError {
     /// Error while initializing the SASL backend.
     #[error("error while initializing the SASL backend: {0}")]
     ConfigError(#[from] rsasl::prelude::SASLError),
+    /// The bearer token could not be verified, for a reason unrelated to the client.
+    #[error("token verification unavailable: {0}")]
+    TokenUnavailable(String),
 }
 
 impl<
@@ -124,6 +135,10 @@ where
                 block_on! { tokio::io::AsyncWriteExt::flush(&mut self.0) }
             }
         }
+        if matches!(mechanism, Mechanism::XOAuth2 | Mechanism::OAuthBearer) {
+            return self.authenticate_bearer(mechanism, initial_response).await;
+        }
+
         let callback = self.handler.generate_sasl_callback();
 
         let rsasl_config = rsasl::config::SASLConfig::builder()
@@ -183,18 +198,28 @@ where
             (Some(data), false) => Some(STANDARD.decode(data)?),
         };
 
-        #[allow(clippy::wildcard_enum_match_arm)]
-        while session
-            .step(data.as_deref(), &mut adapter)
-            .map_err(|e| match e {
-                rsasl::prelude::SessionError::ValidationError(
-                    rsasl::validate::ValidationError::Boxed(e),
-                ) => AuthError::ValidationError(e),
-                otherwise => AuthError::SessionError(otherwise),
-            })?
-            .is_running()
-        {
+        let state = loop {
+            #[allow(clippy::wildcard_enum_match_arm)]
+            let state = session
+                .step(data.as_deref(), &mut adapter)
+                .map_err(|e| match e {
+                    rsasl::prelude::SessionError::ValidationError(
+                        rsasl::validate::ValidationError::Boxed(e),
+                    ) => AuthError::ValidationError(e),
+                    otherwise => AuthError::SessionError(otherwise),
+                })?;
+
+            if !state.is_running() {
+                break state;
+            }
             data = next_challenge_line!(challenge_stream);
+        };
+
+        // NOTE: the last message of the server (the server signature of SCRAM) is sent
+        // as a challenge, which the client must acknowledge with an empty response.
+        // See <https://datatracker.ietf.org/doc/html/rfc4954#section-4>
+        if state.has_sent_message() {
+            next_challenge_line!(challenge_stream);
         }
 
         #[allow(clippy::todo)]
@@ -203,4 +228,82 @@ where
             |_v| Ok(()),
         )
     }
+
+    /// The `XOAUTH2` and `OAUTHBEARER` mechanisms are handled outside of the SASL backend,
+    /// the token being verified by the [`ReceiverHandler`].
+    ///
+    /// On failure, the error is sent to the client as a challenge, which the client
+    /// acknowledges with a dummy response, before the failure reply.
+    async fn authenticate_bearer(
+        &mut self,
+        mechanism: Mechanism,
+        initial_response: Option<Vec<u8>>,
+    ) -> Result<(), AuthError> {
+        let payload = match initial_response {
+            Some(data) => STANDARD.decode(data)?,
+            None => {
+                self.sink.inner.write_all(b"334 \r\n").await?;
+                self.sink.inner.flush().await?;
+                STANDARD.decode(self.read_sasl_response().await?)?
+            }
+        };
+
+        let result = match BearerToken::parse(mechanism, &payload) {
+            Ok(token) => self.handler.on_bearer_token(&mut self.context, token).await,
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(TokenError::Unavailable(reason)) => Err(AuthError::TokenUnavailable(reason)),
+            Err(error) => {
+                self.sink
+                    .inner
+                    .write_all(
+                        format!("334 {}\r\n", STANDARD.encode(error.to_challenge())).as_bytes(),
+                    )
+                    .await?;
+                self.sink.inner.flush().await?;
+                // NOTE: the content of the response does not matter, even `*`
+                match self.read_sasl_response().await {
+                    Ok(_) | Err(AuthError::Canceled) => (),
+                    Err(otherwise) => return Err(otherwise),
+                }
+
+                Err(AuthError::ValidationError(Box::new(error)))
+            }
+        }
+    }
+
+    async fn read_sasl_response(&mut self) -> Result<Vec<u8>, AuthError> {
+        /// Maximum length of a line of the SASL handshake (RFC 4954 4).
+        const LINE_SIZE_MAX: usize = 12288;
+
+        let line_stream = self.stream.as_line_stream();
+        tokio::pin!(line_stream);
+
+        let line = line_stream.next().await.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed during the SASL handshake",
+            )
+        })??;
+
+        if line.len() > LINE_SIZE_MAX {
+            return Err(AuthError::LineTooLong {
+                expected: LINE_SIZE_MAX,
+                got: line.len(),
+            });
+        }
+
+        match line.strip_suffix(b"\r\n") {
+            Some(b"*") => Err(AuthError::Canceled),
+            Some(response) => Ok(response.to_vec()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Line does not end with \r\n",
+            )
+            .into()),
+        }
+    }
 }
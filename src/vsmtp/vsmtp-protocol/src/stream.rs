@@ -24,9 +24,25 @@ fn find(bytes: &[u8], search: &[u8]) -> Option<usize> {
         .position(|window| window == search)
 }
 
+/// Interpret a line (terminated by CRLF) of a message received with `DATA`:
+/// a line containing only a `.` ends the message, and the leading `.` of the
+/// other lines is removed (RFC 5321 4.5.2). Returns `None` if the line ends the message.
+fn unstuff_line(line: Vec<u8>) -> Option<Vec<u8>> {
+    if line == b".\r\n" {
+        None
+    } else if line.first() == Some(&b'.') {
+        Some(line[1..].to_vec())
+    } else {
+        Some(line)
+    }
+}
+
 pub struct Stream<R: tokio::io::AsyncRead + Unpin + Send> {
     pub(super) inner: R,
-    initial_capacity: usize,
+    /// Bytes read but not consumed yet, kept between the streams produced,
+    /// as a client can pipeline several commands (RFC 2920) or send a chunk
+    /// of `BDAT` right after the command.
+    buffer: bytes::BytesMut,
     additional_reserve: usize,
 }
 
@@ -48,48 +64,74 @@ pub enum Error {
     Io(#[from] std::io::Error),
 }
 
-// TODO: handle PIPELINING
 impl<R: tokio::io::AsyncRead + Unpin + Send> Stream<R> {
     #[must_use]
-    pub const fn new(tcp_stream: R) -> Self {
+    pub fn new(tcp_stream: R) -> Self {
         Self {
             inner: tcp_stream,
-            initial_capacity: 80,
+            buffer: bytes::BytesMut::with_capacity(80),
             additional_reserve: 100,
         }
     }
 
+    /// Number of bytes read but not consumed yet.
+    #[must_use]
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
     pub fn as_line_stream(
         &mut self,
     ) -> impl tokio_stream::Stream<Item = std::io::Result<Vec<u8>>> + '_ {
         async_stream::try_stream! {
-            let mut buffer = bytes::BytesMut::with_capacity(self.initial_capacity);
-            let mut n = 0;
-
             loop {
-                if let Some(pos) = find(&buffer[..n], b"\r\n") {
-                    let out = buffer.split_to(pos + 2);
-                    n -= out.len();
-
-                    // PIPELINING: handle buffer here
-                    // TODO: should we return the extra bytes read?
-
+                if let Some(pos) = find(&self.buffer, b"\r\n") {
+                    // NOTE: only the line is consumed, the pipelined commands
+                    //       (or message after `DATA`) stay in the buffer.
+                    let out = self.buffer.split_to(pos + 2);
                     yield Vec::<u8>::from(out);
                 } else {
-                    buffer.reserve(self.additional_reserve);
-                    let read_size = self.inner.read_buf(&mut buffer).await?;
+                    self.buffer.reserve(self.additional_reserve);
+                    let read_size = self.inner.read_buf(&mut self.buffer).await?;
                     if read_size == 0 {
-                        if !buffer.is_empty() {
-                            todo!("what about the remaining buffer? {:?}", buffer);
+                        // NOTE: the remaining bytes are not terminated by CRLF,
+                        //       it is up to the caller to handle them.
+                        if !self.buffer.is_empty() {
+                            yield Vec::<u8>::from(self.buffer.split());
                         }
                         return;
                     }
-                    n += read_size;
                 }
             }
         }
     }
 
+    /// Read exactly `size` bytes, yielded in several parts as they are received.
+    pub fn as_chunk_stream(
+        &mut self,
+        size: usize,
+    ) -> impl tokio_stream::Stream<Item = std::io::Result<Vec<u8>>> + '_ {
+        const MAX_RESERVE: usize = 64 * 1024;
+
+        async_stream::try_stream! {
+            let mut remaining = size;
+
+            while remaining != 0 {
+                if self.buffer.is_empty() {
+                    self.buffer.reserve(remaining.min(MAX_RESERVE));
+                    if self.inner.read_buf(&mut self.buffer).await? == 0 {
+                        Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+                    }
+                }
+
+                let out = self.buffer.split_to(remaining.min(self.buffer.len()));
+                remaining -= out.len();
+
+                yield Vec::<u8>::from(out);
+            }
+        }
+    }
+
     pub fn as_message_stream(
         &mut self,
         size_limit: usize,
@@ -98,37 +140,53 @@ impl<R: tokio::io::AsyncRead + Unpin + Send> Stream<R> {
             let mut size = 0;
 
             for await line in self.as_line_stream() {
-                let mut line = line?;
+                let line = line?;
                 tracing::trace!("{:?}", std::str::from_utf8(&line));
 
-                if line == b".\r\n" {
+                if !line.ends_with(b"\r\n") {
+                    yield Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
                     return;
-                } else {
-                    if line.first() == Some(&b'.') {
-                        line = line[1..].to_vec();
-                    }
+                }
 
-                    // TODO: handle line length max ?
+                let line = match unstuff_line(line) {
+                    Some(line) => line,
+                    None => return,
+                };
 
-                    size += line.len();
-                    if size >= size_limit {
-                        yield Err(Error::BufferTooLong { expected: size_limit, got: size });
-                        return;
-                    }
+                // TODO: handle line length max ?
 
-                    yield Ok(line);
+                size += line.len();
+                if size >= size_limit {
+                    yield Err(Error::BufferTooLong { expected: size_limit, got: size });
+                    return;
                 }
+
+                yield Ok(line);
             }
         }
     }
 
+    /// If `lenient_quit` is set, a `QUIT` without CRLF right before the end of
+    /// the stream is accepted.
     pub fn as_command_stream(
         &mut self,
+        lenient_quit: bool,
     ) -> impl tokio_stream::Stream<Item = Result<Command<Verb, UnparsedArgs>, Error>> + '_ {
         async_stream::stream! {
             for await line in self.as_line_stream() {
                 let line = line?;
 
+                if !line.ends_with(b"\r\n") {
+                    let command = line.strip_suffix(b"\r").unwrap_or(&line);
+                    if lenient_quit && command.eq_ignore_ascii_case(b"QUIT") {
+                        tracing::debug!("QUIT received without CRLF before the end of the stream.");
+                        yield Ok((Verb::Quit, UnparsedArgs(vec![])));
+                    } else {
+                        yield Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    return;
+                }
+
                 // TODO: put value as a parameter
                 if line.len() >= 512 {
                     yield Err(Error::BufferTooLong { expected: 512, got: line.len() });
@@ -148,3 +206,28 @@ impl<R: tokio::io::AsyncRead + Unpin + Send> Stream<R> {
         }
     }
 }
+
+/// Produce the lines of a message received with `BDAT` commands, as
+/// [`Stream::as_message_stream`] would do for `DATA`.
+///
+/// The `.` have no meaning in the chunks (RFC 3030), the lines are produced as is.
+pub fn chunks_as_message_stream(
+    body: Result<Vec<u8>, Error>,
+) -> impl tokio_stream::Stream<Item = Result<Vec<u8>, Error>> {
+    async_stream::stream! {
+        let mut body = match body {
+            Ok(body) => bytes::BytesMut::from(body.as_slice()),
+            Err(e) => {
+                yield Err(e);
+                return;
+            }
+        };
+
+        while let Some(pos) = find(&body, b"\r\n") {
+            yield Ok(Vec::<u8>::from(body.split_to(pos + 2)));
+        }
+        if !body.is_empty() {
+            yield Ok(Vec::<u8>::from(body));
+        }
+    }
+}
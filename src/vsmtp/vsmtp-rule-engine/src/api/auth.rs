@@ -62,6 +62,17 @@ mod auth {
                 tracing::trace!(token);
                 Ok(state::deny())
             }
+            Some(Credentials::Scram { authid }) => {
+                tracing::warn!(
+                    authid,
+                    "Cannot authenticate unix user with SCRAM credentials"
+                );
+                Ok(state::deny())
+            }
+            Some(Credentials::Bearer { authid, .. }) => {
+                tracing::warn!(authid, "Cannot authenticate unix user with a bearer token");
+                Ok(state::deny())
+            }
             None => {
                 tracing::warn!("No credentials found to authenticate a unix user with");
                 Ok(state::deny())
@@ -157,7 +168,7 @@ mod auth {
     ///        action "log auth type" || {
     ///             let credentials = auth::credentials();
     ///
-    ///             // Logs here will output 'Verify' or 'AnonymousToken'.
+    ///             // Logs here will output 'Verify', 'AnonymousToken', 'Scram' or 'Bearer'.
     ///             // depending on the authentication type.
     ///             log("info", `credentials type: ${credentials.type}`);
     ///         },
@@ -171,7 +182,12 @@ mod auth {
     }
 
     /// Get the `authid` property of the connection.
-    /// Can only be use on 'Verify' authentication typed credentials.
+    /// Can only be use on 'Verify', 'Scram' or 'Bearer' authentication typed credentials.
+    ///
+    /// With 'Scram' credentials, the password of the client has already been verified
+    /// against the salted credentials stored by the server.
+    ///
+    /// With 'Bearer' credentials, the `authid` is empty if the token identifies the user on its own.
     ///
     /// # Effective smtp stage
     ///
@@ -199,7 +215,9 @@ mod auth {
     #[rhai_fn(global, get = "authid", return_raw, pure)]
     pub fn get_authid(credentials: &mut Credentials) -> EngineResult<String> {
         match credentials {
-            Credentials::Verify { authid, .. } => Ok(authid.clone()),
+            Credentials::Verify { authid, .. }
+            | Credentials::Scram { authid }
+            | Credentials::Bearer { authid, .. } => Ok(authid.clone()),
             Credentials::AnonymousToken { .. } => {
                 Err(format!("no `authid` available in credentials of type `{credentials}`").into())
             }
@@ -236,7 +254,9 @@ mod auth {
     pub fn get_authpass(credentials: &mut Credentials) -> EngineResult<String> {
         match credentials {
             Credentials::Verify { authpass, .. } => Ok(authpass.clone()),
-            Credentials::AnonymousToken { .. } => Err(format!(
+            Credentials::AnonymousToken { .. }
+            | Credentials::Scram { .. }
+            | Credentials::Bearer { .. } => Err(format!(
                 "no `authpass` available in credentials of type `{credentials}`"
             )
             .into()),
@@ -273,8 +293,55 @@ mod auth {
     pub fn get_anonymous_token(credentials: &mut Credentials) -> EngineResult<String> {
         match credentials {
             Credentials::AnonymousToken { token } => Ok(token.clone()),
-            Credentials::Verify { .. } => Err(format!(
-                "no `anonymous_token` available in credentials of type `{credentials}`"
+            Credentials::Verify { .. } | Credentials::Scram { .. } | Credentials::Bearer { .. } => {
+                Err(format!(
+                    "no `anonymous_token` available in credentials of type `{credentials}`"
+                )
+                .into())
+            }
+        }
+    }
+
+    /// Get the OAuth 2.0 bearer token sent with the `XOAUTH2` or `OAUTHBEARER` mechanisms.
+    /// Can only be use on 'Bearer' authentication typed credentials.
+    ///
+    /// If a token validator is plugged in the server, the token has already been accepted by it.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `authenticate` only.
+    ///
+    /// # Return
+    ///
+    /// * `String` - the token.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # vsmtp_test::vsl::run(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///     authenticate: [
+    ///        rule "check bearer token" || {
+    ///             let credentials = auth::credentials();
+    ///             if credentials.type == "Bearer" && credentials.bearer_token == "my-secret-token" {
+    ///                 state::accept()
+    ///             } else {
+    ///                 state::deny()
+    ///             }
+    ///         },
+    ///     ]
+    /// }
+    /// # "#)?.build()));
+    /// ```
+    #[rhai_fn(global, get = "bearer_token", return_raw, pure)]
+    pub fn get_bearer_token(credentials: &mut Credentials) -> EngineResult<String> {
+        match credentials {
+            Credentials::Bearer { token, .. } => Ok(token.clone()),
+            Credentials::Verify { .. }
+            | Credentials::AnonymousToken { .. }
+            | Credentials::Scram { .. } => Err(format!(
+                "no `bearer_token` available in credentials of type `{credentials}`"
             )
             .into()),
         }
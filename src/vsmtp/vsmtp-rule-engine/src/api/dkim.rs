@@ -276,6 +276,51 @@ mod dkim {
         Ok(result)
     }
 
+    /// Verify every `DKIM-Signature` header of the message, and return a result for each of them.
+    ///
+    /// Unlike `dkim::verify`, the results are not stored in the `ctx()`
+    /// and no `Authentication-Results` header is added.
+    ///
+    /// # Return
+    ///
+    /// An array with a map for each signature, in the order of the headers:
+    /// * `status` - "pass", "fail", "neutral", "temperror", "permerror" or "none" (the key is in testing mode).
+    /// * `type` - the cause of the failure, among "signature_parsing_failed", "key_parsing_failed",
+    ///   "invalid_argument", "temp_dns_error", "perm_dns_error", "key_not_found", "signature_expired",
+    ///   "body_length_exceeded" and "signature_mismatch".
+    /// * `inner` - the description of the failure.
+    /// * `sdid` & `auid` - the identifiers of the signature, if it has been parsed.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `preq` and onwards.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    /// #{
+    ///   preq: [
+    ///     rule "verify all dkim signatures" || {
+    ///       for result in dkim::verify_all() {
+    ///         log("info", `dkim signature of ${result.sdid}: ${result.status}`);
+    ///       }
+    ///
+    ///       state::next()
+    ///     },
+    ///   ]
+    /// }
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "verify_all", return_raw)]
+    pub fn verify_all(ncc: NativeCallContext) -> EngineResult<rhai::Array> {
+        super::Impl::verify_all_signatures(
+            &get_global!(ncc, msg)?,
+            &get_global!(ncc, srv)?,
+            "cycle",
+            100,
+        )
+    }
+
     /// Produce a `DKIM-Signature` header.
     ///
     /// # Args
@@ -424,6 +469,27 @@ pub enum DkimErrors {
         inner: DnsError,
     },
     ///
+    #[strum(message = "permerror", detailed_message = "key_not_found")]
+    #[error("no public key found: `{inner}`")]
+    KeyNotFound {
+        ///
+        inner: DnsError,
+    },
+    ///
+    #[strum(message = "permerror", detailed_message = "signature_expired")]
+    #[error("the signature of `{sdid}` has expired")]
+    SignatureExpired {
+        /// Signing Domain Identifier of the signature.
+        sdid: String,
+    },
+    ///
+    #[strum(message = "permerror", detailed_message = "body_length_exceeded")]
+    #[error("the body length tag does not match the body: `{inner}`")]
+    BodyLengthExceeded {
+        ///
+        inner: VerifierError,
+    },
+    ///
     #[strum(message = "fail", detailed_message = "signature_mismatch")]
     #[error("the signature does not match: `{inner}`")]
     SignatureMismatch {
@@ -495,8 +561,13 @@ impl Impl {
         signature: &Signature,
         key: &PublicKey,
     ) -> Result<(), DkimErrors> {
-        verify(signature, message.inner(), key)
-            .map_err(|inner| DkimErrors::SignatureMismatch { inner })
+        verify(signature, message.inner(), key).map_err(|inner| {
+            if inner.is_body_length_exceeded() {
+                DkimErrors::BodyLengthExceeded { inner }
+            } else {
+                DkimErrors::SignatureMismatch { inner }
+            }
+        })
     }
 
     #[tracing::instrument(skip(server), ret, err)]
@@ -519,12 +590,13 @@ impl Impl {
         let txt_record =
             block_on!(resolver.txt_lookup(signature.get_dns_query())).map_err(|e| {
                 use trust_dns_resolver::error::ResolveErrorKind;
-                if matches!(
+                if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
+                    DkimErrors::KeyNotFound { inner: DnsError(e) }
+                } else if matches!(
                     e.kind(),
                     ResolveErrorKind::Message(_)
                         | ResolveErrorKind::Msg(_)
                         | ResolveErrorKind::NoConnections
-                        | ResolveErrorKind::NoRecordsFound { .. }
                 ) {
                     DkimErrors::PermDnsError { inner: DnsError(e) }
                 } else {
@@ -660,6 +732,9 @@ impl Impl {
 
             if signature.has_expired(expiration_epsilon) {
                 tracing::warn!("DKIM signature expired, continuing ...");
+                last_error = Some(Self::get_dkim_error_status(&DkimErrors::SignatureExpired {
+                    sdid: signature.sdid,
+                }));
                 continue;
             }
 
@@ -699,6 +774,100 @@ impl Impl {
         )]))
     }
 
+    /// Verify every `DKIM-Signature` header of the message, independently of each other.
+    ///
+    /// # Return
+    ///
+    /// A rhai array with a map for each signature, in the order of the headers, with a
+    /// status property, the type & description of the error if the verification failed,
+    /// and sdid & auid if the signature has been parsed.
+    ///
+    /// # Errors
+    ///
+    /// * `get_header_untouched` failed.
+    pub fn verify_all_signatures(
+        msg: &Message,
+        srv: &Server,
+        on_multiple_key_records: &str,
+        expiration_epsilon: u64,
+    ) -> EngineResult<rhai::Array> {
+        crate::api::message::Impl::get_header_untouched(msg, "DKIM-Signature")?
+            .into_iter()
+            .map(|input| {
+                let signature = match Self::parse_signature(&input.to_string()) {
+                    Ok(signature) => signature,
+                    Err(error) => return Ok(Self::error_to_map(&error).into()),
+                };
+
+                let mut result = match Self::verify_signature(
+                    msg,
+                    srv,
+                    &signature,
+                    on_multiple_key_records,
+                    expiration_epsilon,
+                )? {
+                    Ok(status) => rhai::Map::from_iter([("status".into(), status.into())]),
+                    Err(error) => Self::error_to_map(&error),
+                };
+                result.insert("sdid".into(), signature.sdid.into());
+                result.insert("auid".into(), signature.auid.into());
+
+                Ok(result.into())
+            })
+            .collect()
+    }
+
+    /// Verify one signature with the public keys fetched from the DNS.
+    ///
+    /// Return `pass`, or `none` if the only valid key is in testing mode.
+    fn verify_signature(
+        msg: &Message,
+        srv: &Server,
+        signature: &Signature,
+        on_multiple_key_records: &str,
+        expiration_epsilon: u64,
+    ) -> EngineResult<Result<&'static str, DkimErrors>> {
+        if signature.has_expired(expiration_epsilon) {
+            return Ok(Err(DkimErrors::SignatureExpired {
+                sdid: signature.sdid.clone(),
+            }));
+        }
+
+        let keys = match Self::get_public_key(srv, signature, on_multiple_key_records) {
+            Ok(keys) if keys.is_empty() => {
+                return Ok(Err(DkimErrors::KeyNotFound {
+                    inner: DnsError::default(),
+                }))
+            }
+            Ok(keys) => keys,
+            Err(error) => return Ok(Err(error)),
+        };
+
+        let mut result = Ok("none");
+        for key in &keys {
+            match Self::verify(&*vsl_guard_ok!(msg.read()), signature, key) {
+                Ok(()) if key.has_debug_flag() => result = Ok("none"),
+                Ok(()) => return Ok(Ok("pass")),
+                Err(error) => result = Err(error),
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn error_to_map(error: &DkimErrors) -> rhai::Map {
+        rhai::Map::from_iter([
+            ("status".into(), Self::get_dkim_error_status(error).into()),
+            (
+                "type".into(),
+                strum::EnumMessage::get_detailed_message(error)
+                    .expect("`DkimErrors` must have a `detailed message` for each variant")
+                    .into(),
+            ),
+            ("inner".into(), error.to_string().into()),
+        ])
+    }
+
     /// Get the dkim status from an error produced by this module.
     fn get_dkim_error_status(error: &DkimErrors) -> String {
         strum::EnumMessage::get_message(error)
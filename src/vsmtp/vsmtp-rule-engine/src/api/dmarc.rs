@@ -17,7 +17,8 @@
 
 use crate::api::{EngineResult, Message, Server};
 use rhai::plugin::{
-    Dynamic, FnAccess, FnNamespace, Module, NativeCallContext, PluginFunction, RhaiResult, TypeId,
+    mem, Dynamic, FnAccess, FnNamespace, Module, NativeCallContext, PluginFunction, RhaiResult,
+    TypeId,
 };
 use rhai::EvalAltResult;
 use vsmtp_common::Address;
@@ -123,6 +124,109 @@ mod dmarc {
             }
         })
     }
+
+    /// WARNING: Low level API, use `dmarc::check` instead.
+    ///
+    /// Evaluate the DMARC policy of the domain of the sender (RFC5322.From) using
+    /// the results of a previous SPF and DKIM verification, without querying them again.
+    ///
+    /// The record is fetched at `_dmarc.<domain>`, or at the organizational domain
+    /// using its subdomain policy (`sp=`). The `pct=` tag is applied with the id of the
+    /// message, so the same message always gets the same disposition.
+    ///
+    /// # Args
+    ///
+    /// * `spf` - the map returned by `spf::check_raw()`.
+    /// * `dkim` - the map returned by `dkim::verify()`, or the array returned by `dkim::verify_all()`.
+    ///
+    /// # Return
+    ///
+    /// * `map` - the result of the evaluation, contains the keys:
+    ///   * `result` - "pass", "fail", or "none" if the domain has no DMARC record.
+    ///   * `domain` - the domain of the RFC5322.From header.
+    ///   * `policy` - the policy requested by the domain, "none", "quarantine" or "reject".
+    ///   * `disposition` - the policy to apply to this message, "none" if it passed, or if
+    ///     it is not part of the `pct=` sampling (which then lowers the policy by one level, RFC 7489-6.6.4).
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `preq` and onwards.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    /// #{
+    ///   preq: [
+    ///     rule "check dmarc" || {
+    ///       const dmarc = dmarc::check_raw(spf::check_raw(), dkim::verify_all());
+    ///
+    ///       switch dmarc.disposition {
+    ///         "reject" => state::deny(),
+    ///         "quarantine" => state::quarantine("dmarc"),
+    ///         _ => state::next(),
+    ///       }
+    ///     },
+    ///   ]
+    /// }
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "check_raw", return_raw)]
+    pub fn check_raw(
+        ncc: NativeCallContext,
+        spf: rhai::Map,
+        dkim: rhai::Dynamic,
+    ) -> EngineResult<rhai::Map> {
+        let msg = get_global!(ncc, msg)?;
+        let srv = get_global!(ncc, srv)?;
+        let ctx = get_global!(ncc, ctx)?;
+
+        let rfc5322_from = super::parse_rfc5322_from(&msg)?;
+        let rfc5322_from = rfc5322_from.domain();
+
+        let (spf_domain, sample) = {
+            let ctx = vsl_guard_ok!(ctx.read());
+            (
+                match vsl_generic_ok!(ctx.reverse_path()) {
+                    Some(reverse_path) => reverse_path.domain().to_string(),
+                    None => vsl_generic_ok!(ctx.client_name()).to_string(),
+                },
+                vsl_generic_ok!(ctx.message_uuid()).as_u128(),
+            )
+        };
+
+        let dkim = if dkim.is::<rhai::Array>() {
+            dkim.cast::<rhai::Array>()
+        } else {
+            vec![dkim]
+        };
+        let dkim = dkim
+            .into_iter()
+            .filter_map(rhai::Dynamic::try_cast::<rhai::Map>)
+            .collect::<Vec<_>>();
+
+        let spf_result = spf
+            .get("result")
+            .map(std::string::ToString::to_string)
+            .unwrap_or_default();
+
+        Ok(match super::find_dmarc_record(&srv, rfc5322_from) {
+            Some((record, is_subdomain_policy)) => super::evaluate(
+                &record,
+                is_subdomain_policy,
+                rfc5322_from,
+                &dkim,
+                &spf_domain,
+                &spf_result,
+                sample,
+            ),
+            None => rhai::Map::from_iter([
+                ("result".into(), "none".into()),
+                ("domain".into(), rfc5322_from.into()),
+                ("policy".into(), "none".into()),
+                ("disposition".into(), "none".into()),
+            ]),
+        })
+    }
 }
 
 fn dmarc_check(
@@ -131,6 +235,15 @@ fn dmarc_check(
     dkim_result: &rhai::Map,
     spf_mail_from: &str,
     spf_result: &str,
+) -> bool {
+    dkim_check(record, rfc5322_from, dkim_result)
+        || (record.spf_is_aligned(rfc5322_from, spf_mail_from) && spf_result == "pass")
+}
+
+fn dkim_check(
+    record: &vsmtp_auth::dmarc::Record,
+    rfc5322_from: &str,
+    dkim_result: &rhai::Map,
 ) -> bool {
     let dkim_domain: String = match dkim_result
         .get("sdid")
@@ -149,15 +262,49 @@ fn dmarc_check(
         None => return false,
     };
 
-    if record.dkim_is_aligned(rfc5322_from, &dkim_domain) && dkim_status == "pass" {
-        return true;
-    }
+    record.dkim_is_aligned(rfc5322_from, &dkim_domain) && dkim_status == "pass"
+}
 
-    if record.spf_is_aligned(rfc5322_from, spf_mail_from) && spf_result == "pass" {
-        return true;
-    }
+/// Evaluate the DMARC record against the authenticated identifiers, see `dmarc::check_raw`.
+fn evaluate(
+    record: &vsmtp_auth::dmarc::Record,
+    is_subdomain_policy: bool,
+    rfc5322_from: &str,
+    dkim_results: &[rhai::Map],
+    spf_mail_from: &str,
+    spf_result: &str,
+    sample: u128,
+) -> rhai::Map {
+    use vsmtp_auth::dmarc::ReceiverPolicy;
+
+    let dmarc_pass = dkim_results
+        .iter()
+        .any(|dkim| dkim_check(record, rfc5322_from, dkim))
+        || (record.spf_is_aligned(rfc5322_from, spf_mail_from) && spf_result == "pass");
 
-    false
+    let policy = if is_subdomain_policy {
+        record.subdomain_policy().clone()
+    } else {
+        record.receiver_policy.clone()
+    };
+
+    let disposition = match (dmarc_pass, record.is_sampled(sample), &policy) {
+        (true, _, _)
+        | (false, _, ReceiverPolicy::None)
+        | (false, false, ReceiverPolicy::Quarantine) => ReceiverPolicy::None,
+        (false, false, ReceiverPolicy::Reject) => ReceiverPolicy::Quarantine,
+        (false, true, policy) => policy.clone(),
+    };
+
+    rhai::Map::from_iter([
+        (
+            "result".into(),
+            if dmarc_pass { "pass" } else { "fail" }.into(),
+        ),
+        ("domain".into(), rfc5322_from.into()),
+        ("policy".into(), policy.to_string().into()),
+        ("disposition".into(), disposition.to_string().into()),
+    ])
 }
 
 /// Get the address of the sender in the message body, also known as RFC5322.From
@@ -178,6 +325,30 @@ fn parse_rfc5322_from(msg: &Message) -> EngineResult<Address> {
         .map_err::<Box<EvalAltResult>, _>(|e| e.to_string().into())
 }
 
+/// Get the DMARC record of the domain, or the one of its organizational domain.
+///
+/// The boolean is `true` if the record comes from the organizational domain,
+/// its subdomain policy then applies.
+fn find_dmarc_record(server: &Server, domain: &str) -> Option<(vsmtp_auth::dmarc::Record, bool)> {
+    match get_dmarc_record(server, domain) {
+        Ok(record) => return Some((record, false)),
+        Err(error) => tracing::debug!(%error, %domain, "DMARC record not found."),
+    }
+
+    let organizational_domain = crate::api::utils::get_root_domain(domain);
+    if organizational_domain == domain {
+        return None;
+    }
+
+    match get_dmarc_record(server, &organizational_domain) {
+        Ok(record) => Some((record, true)),
+        Err(error) => {
+            tracing::debug!(%error, domain = %organizational_domain, "DMARC record not found.");
+            None
+        }
+    }
+}
+
 fn get_dmarc_record(server: &Server, domain: &str) -> EngineResult<vsmtp_auth::dmarc::Record> {
     let resolver = server.resolvers.get_resolver_root();
 
@@ -199,3 +370,116 @@ fn get_dmarc_record(server: &Server, domain: &str) -> EngineResult<vsmtp_auth::d
 
     Ok(first)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::evaluate;
+
+    fn record(s: &str) -> vsmtp_auth::dmarc::Record {
+        s.parse().unwrap()
+    }
+
+    fn dkim(status: &str, sdid: &str) -> rhai::Map {
+        rhai::Map::from_iter([
+            ("status".into(), status.into()),
+            ("sdid".into(), sdid.into()),
+        ])
+    }
+
+    fn get(map: &rhai::Map, key: &str) -> String {
+        map.get(key).unwrap().to_string()
+    }
+
+    #[test]
+    fn pass_with_any_aligned_dkim_signature() {
+        let result = evaluate(
+            &record("v=DMARC1; p=reject"),
+            false,
+            "example.com",
+            &[
+                dkim("fail", "example.com"),
+                dkim("pass", "mail.example.com"),
+            ],
+            "other.org",
+            "fail",
+            0,
+        );
+
+        assert_eq!(get(&result, "result"), "pass");
+        assert_eq!(get(&result, "policy"), "reject");
+        assert_eq!(get(&result, "disposition"), "none");
+    }
+
+    #[test]
+    fn pass_with_aligned_spf() {
+        let result = evaluate(
+            &record("v=DMARC1; p=reject; aspf=s"),
+            false,
+            "example.com",
+            &[],
+            "example.com",
+            "pass",
+            0,
+        );
+
+        assert_eq!(get(&result, "result"), "pass");
+        assert_eq!(get(&result, "disposition"), "none");
+    }
+
+    #[test]
+    fn fail_unaligned() {
+        let result = evaluate(
+            &record("v=DMARC1; p=quarantine; adkim=s"),
+            false,
+            "example.com",
+            &[dkim("pass", "mail.example.com")],
+            "other.org",
+            "pass",
+            0,
+        );
+
+        assert_eq!(get(&result, "result"), "fail");
+        assert_eq!(get(&result, "disposition"), "quarantine");
+    }
+
+    #[test]
+    fn subdomain_policy() {
+        let result = evaluate(
+            &record("v=DMARC1; p=reject; sp=none"),
+            true,
+            "mail.example.com",
+            &[],
+            "other.org",
+            "pass",
+            0,
+        );
+
+        assert_eq!(get(&result, "result"), "fail");
+        assert_eq!(get(&result, "policy"), "none");
+        assert_eq!(get(&result, "disposition"), "none");
+    }
+
+    #[test]
+    fn sampling() {
+        let record = record("v=DMARC1; p=reject; pct=50");
+        let disposition = |sample| {
+            get(
+                &evaluate(
+                    &record,
+                    false,
+                    "example.com",
+                    &[],
+                    "other.org",
+                    "fail",
+                    sample,
+                ),
+                "disposition",
+            )
+        };
+
+        assert_eq!(disposition(10), "reject");
+        assert_eq!(disposition(10), "reject");
+        assert_eq!(disposition(60), "quarantine");
+        assert_eq!(disposition(160), "quarantine");
+    }
+}
@@ -60,7 +60,7 @@ mod envelop {
         ncc: NativeCallContext,
         new_addr: &str,
     ) -> EngineResult<()> {
-        super::rewrite_mail_from_envelop(&mut get_global!(ncc, ctx)?, new_addr)
+        super::rewrite_mail_from_envelop(&get_global!(ncc, ctx)?, new_addr)
     }
 
     /// Rewrite the sender received from the `MAIL FROM` command.
@@ -91,7 +91,7 @@ mod envelop {
         ncc: NativeCallContext,
         new_addr: SharedObject,
     ) -> EngineResult<()> {
-        super::rewrite_mail_from_envelop(&mut get_global!(ncc, ctx)?, &new_addr.to_string())
+        super::rewrite_mail_from_envelop(&get_global!(ncc, ctx)?, &new_addr.to_string())
     }
 
     /// Replace a recipient received by a `RCPT TO` command.
@@ -230,8 +230,8 @@ mod envelop {
         )
     }
 
-    /// Add a new recipient to the envelop. Note that this does not add
-    /// the recipient to the `To` header. Use `msg::add_rcpt` for that.
+    /// Add a new recipient to the envelop, if it is not already one. Note that this
+    /// does not add the recipient to the `To` header. Use `msg::add_rcpt` for that.
     ///
     /// # Args
     ///
@@ -257,11 +257,11 @@ mod envelop {
     #[allow(clippy::needless_pass_by_value)]
     #[rhai_fn(name = "add_rcpt", return_raw)]
     pub fn add_rcpt_envelop_str(ncc: NativeCallContext, new_addr: &str) -> EngineResult<()> {
-        super::add_rcpt_envelop(&mut get_global!(ncc, ctx)?, new_addr)
+        super::add_rcpt_envelop(&get_global!(ncc, ctx)?, new_addr).map(|_| ())
     }
 
-    /// Add a new recipient to the envelop. Note that this does not add
-    /// the recipient to the `To` header. Use `msg::add_rcpt` for that.
+    /// Add a new recipient to the envelop, if it is not already one. Note that this
+    /// does not add the recipient to the `To` header. Use `msg::add_rcpt` for that.
     ///
     /// # Args
     ///
@@ -290,7 +290,7 @@ mod envelop {
         ncc: NativeCallContext,
         new_addr: SharedObject,
     ) -> EngineResult<()> {
-        super::add_rcpt_envelop(&mut get_global!(ncc, ctx)?, &new_addr.to_string())
+        super::add_rcpt_envelop(&get_global!(ncc, ctx)?, &new_addr.to_string()).map(|_| ())
     }
 
     /// Alias for `envelop::add_rcpt`.
@@ -334,7 +334,7 @@ mod envelop {
     #[allow(clippy::needless_pass_by_value)]
     #[rhai_fn(name = "rm_rcpt", return_raw)]
     pub fn remove_rcpt_envelop_str(ncc: NativeCallContext, addr: &str) -> EngineResult<()> {
-        super::remove_rcpt_envelop(&mut get_global!(ncc, ctx)?, addr)
+        super::remove_rcpt_envelop(&get_global!(ncc, ctx)?, addr).map(|_| ())
     }
 
     /// Remove a recipient from the envelop. Note that this does not remove
@@ -364,11 +364,16 @@ mod envelop {
     #[rhai_fn(name = "rm_rcpt", return_raw)]
     #[allow(clippy::needless_pass_by_value)]
     pub fn remove_rcpt_envelop_obj(ncc: NativeCallContext, addr: SharedObject) -> EngineResult<()> {
-        super::remove_rcpt_envelop(&mut get_global!(ncc, ctx)?, &addr.to_string())
+        super::remove_rcpt_envelop(&get_global!(ncc, ctx)?, &addr.to_string()).map(|_| ())
     }
 }
 
-fn rewrite_mail_from_envelop(context: &mut Context, new_addr: &str) -> EngineResult<()> {
+/// Replace the sender of the envelope, also used by `ctx::set_mail_from`.
+///
+/// # Errors
+/// * `new_addr` is not a valid address.
+/// * The function is called pre-mail stage.
+pub(crate) fn rewrite_mail_from_envelop(context: &Context, new_addr: &str) -> EngineResult<()> {
     vsl_guard_ok!(context.write())
         .set_reverse_path(Some(vsl_conversion_ok!(
             "address",
@@ -398,21 +403,41 @@ fn rewrite_rcpt(context: &mut Context, old_addr: &str, new_addr: &str) -> Engine
     Ok(())
 }
 
-fn add_rcpt_envelop(context: &mut Context, new_addr: &str) -> EngineResult<()> {
-    vsl_guard_ok!(context.write())
-        .add_forward_path(vsl_conversion_ok!(
-            "address",
-            <Address as std::str::FromStr>::from_str(new_addr)
-        ))
-        .map_err(|err| format!("failed to run `add_rcpt_envelop`: {err}").into())
+/// Add a recipient to the envelope if it is not already one, also used by `ctx::add_rcpt`.
+///
+/// # Errors
+/// * `new_addr` is not a valid address.
+/// * The function is called pre-mail stage.
+pub(crate) fn add_rcpt_envelop(context: &Context, new_addr: &str) -> EngineResult<bool> {
+    let new_addr = vsl_conversion_ok!(
+        "address",
+        <Address as std::str::FromStr>::from_str(new_addr)
+    );
+
+    let mut context = vsl_guard_ok!(context.write());
+    if context.forward_paths().map_or(false, |rcpt| {
+        rcpt.iter().any(|rcpt| rcpt.address == new_addr)
+    }) {
+        return Ok(false);
+    }
+
+    context
+        .add_forward_path(new_addr)
+        .map_err::<Box<rhai::EvalAltResult>, _>(|err| {
+            format!("failed to run `add_rcpt_envelop`: {err}").into()
+        })?;
+    Ok(true)
 }
 
-fn remove_rcpt_envelop(context: &mut Context, addr: &str) -> EngineResult<()> {
+/// Remove a recipient from the envelope, also used by `ctx::remove_rcpt`.
+///
+/// # Errors
+/// * `addr` is not a valid address.
+/// * The function is called pre-rcpt stage.
+pub(crate) fn remove_rcpt_envelop(context: &Context, addr: &str) -> EngineResult<bool> {
     let addr = vsl_conversion_ok!("address", <Address as std::str::FromStr>::from_str(addr));
 
-    let mut context = vsl_guard_ok!(context.write());
-    context
+    vsl_guard_ok!(context.write())
         .remove_forward_path(&addr)
-        .map_err::<Box<rhai::EvalAltResult>, _>(|e| e.to_string().into())?;
-    Ok(())
+        .map_err(|e| e.to_string().into())
 }
@@ -0,0 +1,116 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    api::EngineResult,
+    get_global,
+    greylist::{check, GreylistKey, GreylistVerdict},
+    ExecutionStage,
+};
+use rhai::plugin::{
+    Dynamic, FnAccess, FnNamespace, Module, NativeCallContext, PluginFunction, RhaiResult, TypeId,
+};
+use vsmtp_common::{status::Status, CodeID, ReplyOrCodeID};
+
+pub use greylist::*;
+
+/// Greylisting of the (client IP address, sender, recipient) triplets,
+/// configured in `app.greylist`.
+#[rhai::plugin::export_module]
+mod greylist {
+
+    /// Reject temporarily the first attempt of a (client IP address, sender, recipient)
+    /// triplet, using the last recipient received. The client is accepted if it retries
+    /// after `app.greylist.delay`, and until `app.greylist.expire` after its last pass.
+    ///
+    /// The clients which had `app.greylist.auto_whitelist` messages delivered are no longer greylisted.
+    ///
+    /// The records are stored in memory, unless the server provides another store.
+    ///
+    /// # Return
+    ///
+    /// * `info()` - the triplet is greylisted, with the reply `Greylisted` of
+    ///              `config.server.smtp.codes` (`451 4.7.1` by default). Only this
+    ///              recipient is refused, the session is kept open.
+    /// * `next()` - the triplet passed the greylisting.
+    ///
+    /// # Error
+    ///
+    /// * The store of the records is not available.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `rcpt` and onwards.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// #{
+    ///   rcpt: [
+    ///     rule "greylist unknown senders" || {
+    ///       if ctx::is_trusted() { state::next() } else { greylist() }
+    ///     },
+    ///   ]
+    /// }
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, return_raw)]
+    pub fn greylist(ncc: NativeCallContext) -> EngineResult<Status> {
+        let srv = get_global!(ncc, srv)?;
+        let key = {
+            let ctx = get_global!(ncc, ctx)?;
+            let ctx = vsl_guard_ok!(ctx.read());
+            let reverse_path = vsl_missing_ok!(
+                ref ctx.reverse_path().ok(),
+                "mail_from",
+                ExecutionStage::MailFrom
+            );
+            let forward_path = vsl_missing_ok!(
+                ref vsl_missing_ok!(
+                    ref ctx.forward_paths().ok(),
+                    "rcpt",
+                    ExecutionStage::RcptTo
+                )
+                .last(),
+                "rcpt",
+                ExecutionStage::RcptTo
+            );
+
+            GreylistKey::Triplet {
+                client_ip: ctx.client_addr().ip(),
+                sender: reverse_path
+                    .as_ref()
+                    .map_or_else(String::new, |reverse_path| reverse_path.full().to_string()),
+                recipient: forward_path.address.full().to_string(),
+            }
+        };
+
+        let verdict = check(
+            &*srv.greylist_store,
+            &srv.config.app.greylist,
+            &key,
+            std::time::SystemTime::now(),
+        )
+        .map_err::<Box<rhai::EvalAltResult>, _>(|error| format!("{error:#}").into())?;
+
+        tracing::debug!(%key, ?verdict, "Greylisting.");
+
+        Ok(match verdict {
+            GreylistVerdict::Greylisted => Status::Info(ReplyOrCodeID::Left(CodeID::Greylisted)),
+            GreylistVerdict::Passed | GreylistVerdict::Whitelisted => Status::Next,
+        })
+    }
+}
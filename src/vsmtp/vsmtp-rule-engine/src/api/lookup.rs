@@ -0,0 +1,102 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    api::{EngineResult, SharedObject},
+    get_global,
+};
+use rhai::plugin::{
+    mem, Dynamic, FnAccess, FnNamespace, ImmutableString, Module, NativeCallContext,
+    PluginFunction, RhaiResult, TypeId,
+};
+use vsmtp_common::Address;
+
+pub use lookup::*;
+
+/// Lookup of the recipients in the users database configured in `app.rcpt_lookup`.
+#[rhai::plugin::export_module]
+mod lookup {
+
+    /// Check if a recipient exists in the users database configured in `app.rcpt_lookup`
+    /// (a text file, a constant database or a `SQLite` database), the key being the
+    /// full address. The database is opened once when the server starts.
+    ///
+    /// # Args
+    ///
+    /// * `addr` - the address of the recipient.
+    ///
+    /// # Return
+    ///
+    /// * `bool` - true if the recipient is in the database, false otherwise.
+    ///
+    /// # Error
+    ///
+    /// * `addr` is not a valid address.
+    /// * `app.rcpt_lookup` is not configured.
+    /// * The database is not available.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// All of them.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// #{
+    ///   rcpt: [
+    ///     rule "unknown users" || {
+    ///       if rcpt_exists(ctx::rcpt()) {
+    ///         state::next()
+    ///       } else {
+    ///         state::deny(code(550, "5.1.1", "No such user"))
+    ///       }
+    ///     },
+    ///   ]
+    /// }
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "rcpt_exists", return_raw)]
+    pub fn rcpt_exists_str(ncc: NativeCallContext, addr: &str) -> EngineResult<bool> {
+        super::rcpt_exists(&ncc, addr)
+    }
+
+    /// Check if a recipient exists in the users database, see `rcpt_exists(str)`.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "rcpt_exists", return_raw)]
+    pub fn rcpt_exists_obj(ncc: NativeCallContext, addr: SharedObject) -> EngineResult<bool> {
+        super::rcpt_exists(&ncc, &addr.to_string())
+    }
+}
+
+fn rcpt_exists(ncc: &NativeCallContext, addr: &str) -> EngineResult<bool> {
+    let addr = vsl_conversion_ok!("address", <Address as std::str::FromStr>::from_str(addr));
+    let srv = get_global!(ncc, srv)?;
+    let rcpt_lookup = srv
+        .rcpt_lookup
+        .as_ref()
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            "`rcpt_exists` needs a users database in `app.rcpt_lookup`".into()
+        })?;
+
+    let exists = rcpt_lookup
+        .get(addr.full())
+        .map_err::<Box<rhai::EvalAltResult>, _>(|error| format!("{error:#}").into())?
+        .is_some();
+
+    tracing::debug!(%addr, exists, "Recipient lookup.");
+
+    Ok(exists)
+}
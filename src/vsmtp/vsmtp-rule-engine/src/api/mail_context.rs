@@ -24,7 +24,8 @@ use crate::{
     get_global, ExecutionStage,
 };
 use rhai::plugin::{
-    Dynamic, FnAccess, FnNamespace, Module, NativeCallContext, PluginFunction, RhaiResult, TypeId,
+    mem, Dynamic, FnAccess, FnNamespace, ImmutableString, Module, NativeCallContext,
+    PluginFunction, RhaiResult, TypeId,
 };
 
 pub use mail_context::*;
@@ -106,6 +107,51 @@ mod mail_context {
             .to_string())
     }
 
+    /// Is the client part of the networks trusted to supply information about themselves
+    /// or the message (`config.server.trusted_networks`), for instance to allow relaying.
+    ///
+    /// The server does not check it before relaying, the rules must call it to deny
+    /// the untrusted clients, as in the example below.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// All of them.
+    ///
+    /// # Return
+    ///
+    /// * `bool` - true if the ip of the client is in one of the trusted networks.
+    ///
+    /// # Example
+    ///
+    ///```
+    /// # let mut config = vsmtp_test::config::local_test();
+    /// # config.server.trusted_networks = vec!["127.0.0.0/8".parse().unwrap()];
+    /// # let states = vsmtp_test::vsl::run_with_msg_and_config(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   rcpt: [
+    ///     rule "anti relay" || {
+    ///       if ctx::is_trusted() {
+    ///         state::accept()
+    ///       } else {
+    ///         state::deny_for("relay-denied")
+    ///       }
+    ///     },
+    ///   ],
+    /// }
+    /// # "#)?.build()), None, config);
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # assert_eq!(states[&vsmtp_rule_engine::ExecutionStage::RcptTo].2, Status::Accept(either::Left(CodeID::Ok)));
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "is_trusted", return_raw)]
+    pub fn is_trusted(ncc: NativeCallContext) -> EngineResult<bool> {
+        let client_ip = vsl_guard_ok!(get_global!(ncc, ctx)?.read())
+            .client_addr()
+            .ip();
+        Ok(get_global!(ncc, srv)?.config.server.is_trusted(client_ip))
+    }
+
     /// Get the ip port of the client.
     ///
     /// # Effective smtp stage
@@ -401,8 +447,44 @@ mod mail_context {
         )))
     }
 
+    /// Is the message a bounce, sent with a null reverse path (`MAIL FROM:<>`)?
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `mail` and onwards.
+    ///
+    /// # Return
+    ///
+    /// * bool - `true` if the reverse path is null, `false` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # vsmtp_test::vsl::run(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///     mail: [
+    ///        action "log bounce" || log("info", `received a bounce: ${ctx::is_bounce()}`),
+    ///     ]
+    /// }
+    /// # "#)?.build()));
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "is_bounce", return_raw)]
+    pub fn is_bounce(ncc: NativeCallContext) -> EngineResult<bool> {
+        let is_bounce = vsl_guard_ok!(get_global!(ncc, ctx)?.read()).is_bounce();
+        Ok(vsl_missing_ok!(
+            ref is_bounce.ok(),
+            "is_bounce",
+            ExecutionStage::MailFrom
+        ))
+    }
+
     /// Get the list of recipients received by the client.
     ///
+    /// Those are the recipients of the envelope (`RCPT TO`), to which the message is delivered,
+    /// and not the ones of the `To`, `Cc` and `Bcc` headers (see `msg::header_rcpt_list()`).
+    ///
     /// # Effective smtp stage
     ///
     /// `rcpt` and onwards. Note that you will not have all recipients received
@@ -488,6 +570,156 @@ mod mail_context {
         )))
     }
 
+    /// Replace the sender of the envelope (`MAIL FROM`). Note that this does not
+    /// change the `From` header, use `msg::rw_mail_from` for that.
+    ///
+    /// # Args
+    ///
+    /// * `new_addr` - the new sender address.
+    ///
+    /// # Error
+    ///
+    /// * `new_addr` is not a valid address.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `mail` and onwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let states = vsmtp_test::vsl::run(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///     preq: [
+    ///        action "rewrite sender" || {
+    ///          ctx::set_mail_from("no-reply@example.com");
+    ///          ctx::set_mail_from(address("bounces@example.com"));
+    ///        },
+    ///     ]
+    /// }
+    /// # "#)?.build()));
+    /// # assert_eq!(
+    /// #   states[&vsmtp_rule_engine::ExecutionStage::PreQ].0.reverse_path().unwrap().as_ref().unwrap().full(),
+    /// #   "bounces@example.com"
+    /// # );
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "set_mail_from", return_raw)]
+    pub fn set_mail_from_str(ncc: NativeCallContext, new_addr: &str) -> EngineResult<()> {
+        super::set_mail_from(&get_global!(ncc, ctx)?, new_addr)
+    }
+
+    /// Replace the sender of the envelope (`MAIL FROM`), see `ctx::set_mail_from(str)`.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "set_mail_from", return_raw)]
+    pub fn set_mail_from_obj(ncc: NativeCallContext, new_addr: SharedObject) -> EngineResult<()> {
+        super::set_mail_from(&get_global!(ncc, ctx)?, &new_addr.to_string())
+    }
+
+    /// Add a recipient to the envelope, the message is delivered to it like to
+    /// the ones received with `RCPT TO`. Note that this does not add the recipient
+    /// to the `To` header, use `msg::add_rcpt` for that.
+    ///
+    /// # Args
+    ///
+    /// * `new_addr` - the recipient to add.
+    ///
+    /// # Return
+    ///
+    /// * `bool` - true if the recipient has been added, false if it already was a recipient.
+    ///
+    /// # Error
+    ///
+    /// * `new_addr` is not a valid address.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `mail` and onwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let states = vsmtp_test::vsl::run(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///     preq: [
+    ///        rule "forward to the team" || {
+    ///          if ctx::add_rcpt("team@example.com") && !ctx::add_rcpt(address("team@example.com")) {
+    ///            state::accept()
+    ///          } else {
+    ///            state::deny()
+    ///          }
+    ///        },
+    ///     ]
+    /// }
+    /// # "#)?.build()));
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # let (context, _, status) = &states[&vsmtp_rule_engine::ExecutionStage::PreQ];
+    /// # assert_eq!(*status, Status::Accept(either::Left(CodeID::Ok)));
+    /// # assert_eq!(
+    /// #   context.forward_paths().unwrap().iter().filter(|rcpt| rcpt.address.full() == "team@example.com").count(),
+    /// #   1
+    /// # );
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "add_rcpt", return_raw)]
+    pub fn add_rcpt_str(ncc: NativeCallContext, new_addr: &str) -> EngineResult<bool> {
+        super::add_rcpt(&get_global!(ncc, ctx)?, new_addr)
+    }
+
+    /// Add a recipient to the envelope, see `ctx::add_rcpt(str)`.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "add_rcpt", return_raw)]
+    pub fn add_rcpt_obj(ncc: NativeCallContext, new_addr: SharedObject) -> EngineResult<bool> {
+        super::add_rcpt(&get_global!(ncc, ctx)?, &new_addr.to_string())
+    }
+
+    /// Remove a recipient from the envelope, the message is no longer delivered to it.
+    /// Note that this does not remove the recipient from the `To` header, use
+    /// `msg::rm_rcpt` for that.
+    ///
+    /// # Args
+    ///
+    /// * `addr` - the recipient to remove.
+    ///
+    /// # Return
+    ///
+    /// * `bool` - true if the recipient has been removed, false if it was not a recipient.
+    ///
+    /// # Error
+    ///
+    /// * `addr` is not a valid address.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `rcpt` and onwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # vsmtp_test::vsl::run(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///     preq: [
+    ///        action "never deliver to the archive" || ctx::remove_rcpt("archive@example.com"),
+    ///     ]
+    /// }
+    /// # "#)?.build()));
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "remove_rcpt", return_raw)]
+    pub fn remove_rcpt_str(ncc: NativeCallContext, addr: &str) -> EngineResult<bool> {
+        super::remove_rcpt(&get_global!(ncc, ctx)?, addr)
+    }
+
+    /// Remove a recipient from the envelope, see `ctx::remove_rcpt(str)`.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "remove_rcpt", return_raw)]
+    pub fn remove_rcpt_obj(ncc: NativeCallContext, addr: SharedObject) -> EngineResult<bool> {
+        super::remove_rcpt(&get_global!(ncc, ctx)?, &addr.to_string())
+    }
+
     /// Get the time of reception of the email.
     ///
     /// # Effective smtp stage
@@ -564,3 +796,30 @@ pub fn message_id(context: &Context) -> EngineResult<String> {
     )
     .to_string())
 }
+
+/// Replace the sender of the envelope, see `envelop::rw_mail_from`.
+///
+/// # Errors
+/// * `new_addr` is not a valid address.
+/// * The function is called pre-mail stage.
+pub fn set_mail_from(context: &Context, new_addr: &str) -> EngineResult<()> {
+    super::envelop::rewrite_mail_from_envelop(context, new_addr)
+}
+
+/// Add a recipient to the envelope, if it is not already one, see `envelop::add_rcpt`.
+///
+/// # Errors
+/// * `new_addr` is not a valid address.
+/// * The function is called pre-mail stage.
+pub fn add_rcpt(context: &Context, new_addr: &str) -> EngineResult<bool> {
+    super::envelop::add_rcpt_envelop(context, new_addr)
+}
+
+/// Remove a recipient from the envelope, see `envelop::rm_rcpt`.
+///
+/// # Errors
+/// * `addr` is not a valid address.
+/// * The function is called pre-rcpt stage.
+pub fn remove_rcpt(context: &Context, addr: &str) -> EngineResult<bool> {
+    super::envelop::remove_rcpt_envelop(context, addr)
+}
@@ -28,6 +28,7 @@ use rhai::plugin::{
 
 pub use message::*;
 use vsmtp_common::Address;
+use vsmtp_plugin_vsl::objects::Object;
 
 /// Inspect incoming messages.
 #[rhai::plugin::export_module]
@@ -622,6 +623,66 @@ mod message {
         super::Impl::append_header(&get_global!(ncc, msg)?, &header, &value.to_string())
     }
 
+    /// Add a new header **at the end** of the header list in the message,
+    /// same as `append_header`.
+    ///
+    /// # Args
+    ///
+    /// * `header` - the name of the header to add.
+    /// * `value` - the value of the header to add.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// All of them. Even though the email is not received at the current stage,
+    /// vsmtp stores new headers and will add them on top of the ones received once
+    /// the `preq` stage is reached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let msg = vsmtp_mail_parser::MessageBody::try_from(concat!(
+    /// "Subject: Unit test are cool\r\n",
+    /// "\r\n",
+    /// "Hello world!\r\n",
+    /// # )).unwrap();
+    ///
+    /// # let states = vsmtp_test::vsl::run_with_msg(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   preq: [
+    ///     action "flag as spam" || {
+    ///       msg::add_header("X-Spam-Flag", "YES");
+    ///       msg::add_header("X-Spam-Level", identifier("*****"));
+    ///     }
+    ///   ]
+    /// }
+    /// # "#)?.build()), Some(msg));
+    /// # assert_eq!(*states[&vsmtp_rule_engine::ExecutionStage::PreQ].1.inner().raw_headers(), vec![
+    /// #   "Subject: Unit test are cool\r\n".to_string(),
+    /// #   "X-Spam-Flag: YES\r\n".to_string(),
+    /// #   "X-Spam-Level: *****\r\n".to_string(),
+    /// # ]);
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "add_header", return_raw)]
+    pub fn add_header(ncc: NativeCallContext, header: &str, value: &str) -> EngineResult<()> {
+        super::Impl::append_header(&get_global!(ncc, msg)?, &header, &value)
+    }
+
+    /// Add a new header **at the end** of the header list in the message,
+    /// same as `append_header`.
+    ///
+    /// See `add_header` above.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "add_header", return_raw)]
+    pub fn add_header_str_obj(
+        ncc: NativeCallContext,
+        header: &str,
+        value: SharedObject,
+    ) -> EngineResult<()> {
+        super::Impl::append_header(&get_global!(ncc, msg)?, &header, &value.to_string())
+    }
+
     /// Add a new header on top all other headers in the message.
     ///
     /// # Args
@@ -1076,6 +1137,46 @@ mod message {
             .to_string())
     }
 
+    /// Check if the message cannot be parsed as MIME, in which case the functions
+    /// needing the parsed message (`dkim`, attachments, ...) fail.
+    ///
+    /// In the `postq` stage, the policy applied to those messages is set by
+    /// `server.queues.working.mime_parse_failure`.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `preq` and onwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// // A message without the mandatory `Date` header.
+    /// # let msg = vsmtp_mail_parser::MessageBody::try_from(concat!(
+    /// "From: john.doe@example.com\r\n",
+    /// "Subject: Unit test are cool\r\n",
+    /// "\r\n",
+    /// "Hello world!\r\n",
+    /// # )).unwrap();
+    ///
+    /// # let states = vsmtp_test::vsl::run_with_msg(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   preq: [
+    ///     rule "malformed message" || if msg::parse_failed() { state::deny() } else { state::next() },
+    ///   ]
+    /// }
+    /// # "#)?.build()), Some(msg));
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # assert_eq!(states[&vsmtp_rule_engine::ExecutionStage::PreQ].2, Status::Deny(either::Left(CodeID::Denied)));
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "parse_failed", return_raw)]
+    pub fn parse_failed(ncc: NativeCallContext) -> EngineResult<bool> {
+        Ok(vsl_guard_ok!(get_global!(ncc, msg)?.write())
+            .parsed::<vsmtp_mail_parser::MailMimeParser>()
+            .is_err())
+    }
+
     /// Remove an existing header from the message.
     ///
     /// # Args
@@ -1182,6 +1283,69 @@ mod message {
         super::Impl::remove_header(&get_global!(ncc, msg)?, &header.to_string())
     }
 
+    /// Remove an existing header from the message, same as `rm_header`.
+    ///
+    /// # Args
+    ///
+    /// * `header` - the name of the header to remove.
+    ///
+    /// # Return
+    ///
+    /// * a boolean value, true if a header has been removed, false otherwise.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// All of them, although it is most useful in the `preq` stage because this
+    /// is when the email body is received.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let msg = vsmtp_mail_parser::MessageBody::try_from(concat!(
+    /// "X-Spam-Flag: YES\r\n",
+    /// "Subject: The initial header value\r\n",
+    /// "\r\n",
+    /// "Hello world!\r\n",
+    /// # )).unwrap();
+    ///
+    /// # let states = vsmtp_test::vsl::run_with_msg(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   preq: [
+    ///     rule "remove_header" || {
+    ///       if msg::remove_header("X-Spam-Flag") && !msg::remove_header(identifier("X-Spam-Flag")) {
+    ///         state::accept()
+    ///       } else {
+    ///         state::deny()
+    ///       }
+    ///     }
+    ///   ]
+    /// }
+    /// # "#)?.build()), Some(msg));
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # assert_eq!(states[&vsmtp_rule_engine::ExecutionStage::PreQ].2, Status::Accept(either::Left(CodeID::Ok)));
+    /// # assert_eq!(*states[&vsmtp_rule_engine::ExecutionStage::PreQ].1.inner().raw_headers(), vec![
+    /// #   "Subject: The initial header value\r\n".to_string(),
+    /// # ]);
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "remove_header", return_raw)]
+    pub fn remove_header_by_name(ncc: NativeCallContext, header: &str) -> EngineResult<bool> {
+        super::Impl::remove_header(&get_global!(ncc, msg)?, header)
+    }
+
+    /// Remove an existing header from the message, same as `rm_header`.
+    ///
+    /// See `remove_header` above.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "remove_header", return_raw)]
+    pub fn remove_header_by_name_obj(
+        ncc: NativeCallContext,
+        header: SharedObject,
+    ) -> EngineResult<bool> {
+        super::Impl::remove_header(&get_global!(ncc, msg)?, &header.to_string())
+    }
+
     /// Change the sender's address in the `From` header of the message.
     ///
     /// # Args
@@ -1244,6 +1408,158 @@ mod message {
         super::Impl::rewrite_mail_from_message(&get_global!(ncc, msg)?, &new_addr.to_string())
     }
 
+    /// Get the list of recipients in the `To`, `Cc` and `Bcc` headers of the message.
+    ///
+    /// Those are not the recipients of the envelope (see `ctx::rcpt_list()`), to which
+    /// the message is delivered. The `Bcc` header is removed from the message on delivery.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `preq` and onwards.
+    ///
+    /// # Return
+    ///
+    /// * `Array of addresses` - the recipients of the headers, the invalid addresses are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let msg = vsmtp_mail_parser::MessageBody::try_from(concat!(
+    /// "To: John Doe <john.doe@example.com>\r\n",
+    /// "Bcc: jenny.doe@example.com\r\n",
+    /// "\r\n",
+    /// "Hello world!\r\n",
+    /// # )).unwrap();
+    ///
+    /// # let states = vsmtp_test::vsl::run_with_msg(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   preq: [
+    ///     rule "hidden recipients" || {
+    ///       let recipients = msg::header_rcpt_list();
+    ///       if recipients.len() == 2 && "jenny.doe@example.com" in recipients {
+    ///         state::accept();
+    ///       } else {
+    ///         state::deny();
+    ///       }
+    ///     }
+    ///   ]
+    /// }
+    /// # "#)?.build()), Some(msg));
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # assert_eq!(states[&vsmtp_rule_engine::ExecutionStage::PreQ].2, Status::Accept(either::Left(CodeID::Ok)));
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "header_rcpt_list", return_raw)]
+    pub fn header_rcpt_list(ncc: NativeCallContext) -> EngineResult<rhai::Array> {
+        Ok(vsl_guard_ok!(get_global!(ncc, msg)?.read())
+            .header_recipients()
+            .into_iter()
+            .filter_map(|rcpt| <Address as std::str::FromStr>::from_str(&rcpt).ok())
+            .map(Object::Address)
+            .map(std::sync::Arc::new)
+            .map(rhai::Dynamic::from)
+            .collect())
+    }
+
+    /// Check that the domains of the addresses in the `From` header are aligned
+    /// with the domain of the sender of the envelope (`MAIL FROM`), to spot spoofed authors.
+    ///
+    /// # Args
+    ///
+    /// * `mode` - `"strict"` (or `"s"`): the domains must be identical,
+    ///   `"relaxed"` (or `"r"`): the organizational domains must be identical (`mail.example.com` and `example.com`).
+    ///
+    /// # Effective smtp stage
+    ///
+    /// `preq` and onwards.
+    ///
+    /// # Return
+    ///
+    /// * `bool` - true if every address of the `From` headers is aligned, false if one of them is not,
+    ///   if the message has no `From` header, or if the envelope has a null sender.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let msg = vsmtp_mail_parser::MessageBody::try_from(concat!(
+    /// "From: John Doe <john.doe@testserver.com>\r\n",
+    /// "To: jenny.doe@example.com\r\n",
+    /// "\r\n",
+    /// "Hello world!\r\n",
+    /// # )).unwrap();
+    ///
+    /// # let states = vsmtp_test::vsl::run_with_msg(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   preq: [
+    ///     // the sender of the envelope is `client@client.testserver.com`.
+    ///     rule "anti-spoofing" || {
+    ///       if msg::from_aligned("relaxed") && !msg::from_aligned("strict") {
+    ///         state::accept();
+    ///       } else {
+    ///         state::deny();
+    ///       }
+    ///     }
+    ///   ]
+    /// }
+    /// # "#)?.build()), Some(msg));
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # assert_eq!(states[&vsmtp_rule_engine::ExecutionStage::PreQ].2, Status::Accept(either::Left(CodeID::Ok)));
+    /// ```
+    ///
+    /// ```
+    /// # let msg = vsmtp_mail_parser::MessageBody::try_from(concat!(
+    /// "From: john.doe@testserver.com, Spoofer <ceo@example.com>\r\n",
+    /// "To: jenny.doe@example.com\r\n",
+    /// "\r\n",
+    /// "Hello world!\r\n",
+    /// # )).unwrap();
+    ///
+    /// # let states = vsmtp_test::vsl::run_with_msg(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   preq: [
+    ///     rule "anti-spoofing" || {
+    ///       if msg::from_aligned("relaxed") {
+    ///         state::accept();
+    ///       } else {
+    ///         state::deny();
+    ///       }
+    ///     }
+    ///   ]
+    /// }
+    /// # "#)?.build()), Some(msg));
+    /// # use vsmtp_common::{status::Status, CodeID};
+    /// # assert_eq!(states[&vsmtp_rule_engine::ExecutionStage::PreQ].2, Status::Deny(either::Left(CodeID::Denied)));
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(name = "from_aligned", return_raw)]
+    pub fn from_aligned(ncc: NativeCallContext, mode: &str) -> EngineResult<bool> {
+        let mode = vsl_generic_ok!(
+            <vsmtp_auth::dmarc::AlignmentMode as std::str::FromStr>::from_str(mode)
+        );
+
+        let reverse_path = vsl_guard_ok!(get_global!(ncc, ctx)?.read())
+            .reverse_path()
+            .ok()
+            .cloned()
+            .flatten();
+        let reverse_path = match reverse_path {
+            Some(reverse_path) => reverse_path,
+            None => return Ok(false),
+        };
+
+        let authors = vsl_guard_ok!(get_global!(ncc, msg)?.read()).header_from();
+
+        Ok(!authors.is_empty()
+            && authors.iter().all(|author| {
+                <Address as std::str::FromStr>::from_str(author).map_or(false, |author| {
+                    mode.is_aligned(author.domain(), reverse_path.domain())
+                })
+            }))
+    }
+
     /// Replace a recipient by an other in the `To` header of the message.
     ///
     /// # Args
@@ -30,7 +30,7 @@ const SPF_HEADER: &str = "Received-SPF";
 
 pub use spf::*;
 
-/// Implementation of the Sender Policy Framework (SPF), described by RFC 4408. (<https://www.ietf.org/rfc/rfc4408.txt>)
+/// Implementation of the Sender Policy Framework (SPF), described by RFC 7208. (<https://www.rfc-editor.org/rfc/rfc7208>)
 #[rhai::plugin::export_module]
 mod spf {
     use crate::api::{message::Impl, state};
@@ -249,6 +249,8 @@ mod spf {
     ///
     /// # Return
     /// * `map` - the result of the spf check, contains the `result`, `mechanism` and `problem` keys.
+    ///   `result` is one of `pass`, `fail`, `softfail`, `neutral`, `none`, `temperror` or `permerror`.
+    ///   The evaluation is aborted with `permerror` after 10 DNS lookups (RFC 7208-4.6.4).
     ///
     /// # Effective smtp stage
     ///
@@ -341,10 +343,9 @@ pub fn key_value_list(
     format!(
         r#"receiver={};
  client-ip={};
- envelope_from={};
+ envelope-from={};
  identity=mailfrom;
- {}`
-        "#,
+ {}"#,
         hostname,
         client_ip,
         sender,
@@ -399,3 +400,37 @@ fn result_to_map(spf: &vsmtp_auth::spf::Result) -> rhai::Map {
         },
     ])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn received_spf_header() {
+        let spf = vsmtp_auth::spf::Result {
+            result: "pass".to_string(),
+            details: vsmtp_auth::spf::Details::Mechanism("ip4:192.0.2.0/24".to_string()),
+        };
+
+        assert_eq!(
+            spf_header(&spf, "mx.example.com", "john@example.com", "192.0.2.1"),
+            "pass receiver=mx.example.com;\n client-ip=192.0.2.1;\n envelope-from=john@example.com;\n identity=mailfrom;\n mechanism=ip4:192.0.2.0/24;"
+        );
+    }
+
+    #[test]
+    fn result_map() {
+        let spf = vsmtp_auth::spf::Result {
+            result: "permerror".to_string(),
+            details: vsmtp_auth::spf::Details::Problem("too many dns lookups".to_string()),
+        };
+
+        let map = result_to_map(&spf);
+        assert_eq!(map.get("result").unwrap().to_string(), "permerror");
+        assert_eq!(
+            map.get("problem").unwrap().to_string(),
+            "too many dns lookups"
+        );
+        assert!(map.get("mechanism").is_none());
+    }
+}
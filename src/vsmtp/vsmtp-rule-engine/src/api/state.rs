@@ -424,6 +424,44 @@ mod state {
         reply_or_code_id_from_string(code).map(Status::Deny)
     }
 
+    /// Stop rules evaluation and send the reply configured for a rejection cause.
+    ///
+    /// # Args
+    ///
+    /// * `reason` - The cause of the rejection, one of `relay-denied`, `user-unknown` or `over-quota`.
+    ///              The reply is read from `config.server.smtp.codes` (`RelayDenied`, `UnknownRecipient`
+    ///              and `MailboxFull`), and the `{reason}` placeholder of its text is replaced by `reason`.
+    ///
+    /// # Error
+    ///
+    /// * The reason is not a known rejection cause.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// all of them.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// #{
+    ///     rcpt: [
+    ///         rule "unknown users" || {
+    ///            if ctx::rcpt().local_part == "nobody" {
+    ///                state::deny_for("user-unknown")
+    ///            } else {
+    ///                state::next()
+    ///            }
+    ///        },
+    ///     ],
+    /// }
+    /// ```
+    #[rhai_fn(return_raw)]
+    pub fn deny_for(reason: &str) -> EngineResult<Status> {
+        CodeID::from_reason(reason)
+            .map(|code| Status::Deny(ReplyOrCodeID::Left(code)))
+            .ok_or_else(|| format!("{reason:?} is not a rejection cause").into())
+    }
+
     /// Ask the client to retry to send the current command by sending an information code.
     ///
     /// # Args
@@ -23,7 +23,7 @@ use rhai::plugin::{
     mem, Dynamic, EvalAltResult, FnAccess, FnNamespace, ImmutableString, Module, NativeCallContext,
     PluginFunction, RhaiResult, TypeId,
 };
-use vsmtp_common::transfer::{ForwardTarget, Transfer};
+use vsmtp_common::transfer::{ForwardTarget, LmtpTarget, Transfer};
 
 pub use transport::*;
 
@@ -767,6 +767,92 @@ mod transport {
     pub fn maildir_all(ncc: NativeCallContext) -> EngineResult<()> {
         set_transport_foreach(&get_global!(ncc, ctx)?, &Transfer::Maildir)
     }
+
+    /// Set the delivery method to lmtp for a recipient.
+    /// After all rules are evaluated, the email will be handed to a local
+    /// delivery agent (dovecot, cyrus ...) using the lmtp protocol.
+    ///
+    /// # Args
+    ///
+    /// * `rcpt` - the recipient to apply the method to.
+    /// * `target` - the path of the unix socket, or the address and port of the lmtp server.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// All of them.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #{
+    ///     delivery: [
+    ///        action "setup lmtp" || transport::lmtp("john.doe@example.com", "/run/dovecot/lmtp"),
+    ///     ]
+    /// }
+    /// ```
+    ///
+    /// ```
+    /// # let states = vsmtp_test::vsl::run(
+    /// # |builder| Ok(builder.add_root_filter_rules(r#"
+    /// #{
+    ///   rcpt: [
+    ///     action "setup lmtp" || {
+    ///         envelop::add_rcpt("doe@example.com");
+    ///         envelop::add_rcpt("a@example.com");
+    ///         transport::lmtp("doe@example.com", "/run/dovecot/lmtp");
+    ///         transport::lmtp("a@example.com", "127.0.0.1:24");
+    ///     },
+    ///   ],
+    /// }
+    /// # "#)?.build()));
+    ///
+    /// # use vsmtp_common::transfer::{LmtpTarget, Transfer};
+    /// # let forward_paths = states[&vsmtp_rule_engine::ExecutionStage::RcptTo].0.forward_paths().unwrap();
+    /// # assert_eq!(
+    /// #   forward_paths[0].transfer_method,
+    /// #   Transfer::Lmtp(LmtpTarget::Unix("/run/dovecot/lmtp".into()))
+    /// # );
+    /// # assert_eq!(
+    /// #   forward_paths[1].transfer_method,
+    /// #   Transfer::Lmtp(LmtpTarget::Tcp("127.0.0.1:24".parse().unwrap()))
+    /// # );
+    /// ```
+    #[rhai_fn(name = "lmtp", return_raw)]
+    pub fn lmtp(ncc: NativeCallContext, rcpt: &str, target: &str) -> EngineResult<()> {
+        let target = <LmtpTarget as std::str::FromStr>::from_str(target)
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+
+        set_transport_for_one(&get_global!(ncc, ctx)?, rcpt, &Transfer::Lmtp(target))
+    }
+
+    /// Set the delivery method to lmtp for all recipients.
+    /// After all rules are evaluated, the email will be handed to a local
+    /// delivery agent (dovecot, cyrus ...) using the lmtp protocol.
+    ///
+    /// # Args
+    ///
+    /// * `target` - the path of the unix socket, or the address and port of the lmtp server.
+    ///
+    /// # Effective smtp stage
+    ///
+    /// All of them.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #{
+    ///     delivery: [
+    ///        action "setup lmtp" || transport::lmtp_all("/run/dovecot/lmtp"),
+    ///     ]
+    /// }
+    /// ```
+    #[rhai_fn(return_raw)]
+    pub fn lmtp_all(ncc: NativeCallContext, target: &str) -> EngineResult<()> {
+        let target = <LmtpTarget as std::str::FromStr>::from_str(target)
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+
+        set_transport_foreach(&get_global!(ncc, ctx)?, &Transfer::Lmtp(target))
+    }
 }
 
 fn set_transport_for_one(context: &Context, search: &str, method: &Transfer) -> EngineResult<()> {
@@ -0,0 +1,420 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+use vsmtp_config::field::FieldAppGreylist;
+
+/// The key of a record of the greylisting.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GreylistKey {
+    /// A (client IP address, sender, recipient) triplet, the sender being empty
+    /// for the null reverse path.
+    Triplet {
+        /// IP address of the client.
+        client_ip: std::net::IpAddr,
+        /// Address of the sender (`MAIL FROM`).
+        sender: String,
+        /// Address of the recipient (`RCPT TO`).
+        recipient: String,
+    },
+    /// A client IP address, counting its delivered messages for the auto-whitelist.
+    Client(std::net::IpAddr),
+}
+
+impl std::fmt::Display for GreylistKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Triplet {
+                client_ip,
+                sender,
+                recipient,
+            } => write!(f, "triplet/{client_ip}/{sender}/{recipient}"),
+            Self::Client(client_ip) => write!(f, "client/{client_ip}"),
+        }
+    }
+}
+
+/// A record of the greylisting.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GreylistRecord {
+    /// Time of the first attempt of the triplet, or of the first delivery of the client.
+    pub first_seen: std::time::SystemTime,
+    /// Number of times the triplet passed the greylisting, or of messages of the client delivered.
+    pub passed: u32,
+}
+
+/// The storage of the greylisting records, shared by the sessions.
+///
+/// The default is [`MemoryStore`], a store shared by several instances
+/// (Redis, SQL database, ...) can be provided with
+/// [`RuleEngine::with_greylist_store`](crate::RuleEngine::with_greylist_store).
+#[allow(clippy::module_name_repetitions)]
+pub trait GreylistStore: std::fmt::Debug + Send + Sync {
+    /// Get the record of `key`, `None` if there is none or if it expired.
+    ///
+    /// # Errors
+    ///
+    /// * the storage is not available
+    fn get(&self, key: &GreylistKey) -> anyhow::Result<Option<GreylistRecord>>;
+
+    /// Insert or replace the record of `key`, which expires after `ttl`.
+    ///
+    /// # Errors
+    ///
+    /// * the storage is not available
+    fn set(
+        &self,
+        key: &GreylistKey,
+        record: GreylistRecord,
+        ttl: std::time::Duration,
+    ) -> anyhow::Result<()>;
+}
+
+/// The expired records are removed at most once per period, when inserting.
+const PURGE_PERIOD: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct MemoryStoreInner {
+    records: std::collections::HashMap<GreylistKey, (GreylistRecord, std::time::SystemTime)>,
+    last_purge: Option<std::time::SystemTime>,
+}
+
+/// A [`GreylistStore`] in memory, lost when the server restarts.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    inner: std::sync::Mutex<MemoryStoreInner>,
+}
+
+impl MemoryStore {
+    fn get_at(
+        &self,
+        key: &GreylistKey,
+        now: std::time::SystemTime,
+    ) -> anyhow::Result<Option<GreylistRecord>> {
+        let inner = self
+            .inner
+            .lock()
+            .map_err(|_| anyhow::anyhow!("greylist store poisoned"))?;
+
+        Ok(inner
+            .records
+            .get(key)
+            .filter(|(_, expires_at)| now < *expires_at)
+            .map(|(record, _)| *record))
+    }
+
+    fn set_at(
+        &self,
+        key: &GreylistKey,
+        record: GreylistRecord,
+        ttl: std::time::Duration,
+        now: std::time::SystemTime,
+    ) -> anyhow::Result<()> {
+        let mut inner = self
+            .inner
+            .lock()
+            .map_err(|_| anyhow::anyhow!("greylist store poisoned"))?;
+
+        if inner.last_purge.map_or(true, |last_purge| {
+            now.duration_since(last_purge)
+                .map_or(false, |elapsed| elapsed >= PURGE_PERIOD)
+        }) {
+            inner.records.retain(|_, (_, expires_at)| now < *expires_at);
+            inner.last_purge = Some(now);
+        }
+
+        inner.records.insert(key.clone(), (record, now + ttl));
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.inner.lock().unwrap().records.len()
+    }
+}
+
+impl GreylistStore for MemoryStore {
+    fn get(&self, key: &GreylistKey) -> anyhow::Result<Option<GreylistRecord>> {
+        self.get_at(key, std::time::SystemTime::now())
+    }
+
+    fn set(
+        &self,
+        key: &GreylistKey,
+        record: GreylistRecord,
+        ttl: std::time::Duration,
+    ) -> anyhow::Result<()> {
+        self.set_at(key, record, ttl, std::time::SystemTime::now())
+    }
+}
+
+/// Outcome of the greylisting of a triplet.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GreylistVerdict {
+    /// First attempt of the triplet, or retry before the end of the delay.
+    Greylisted,
+    /// Retry after the delay, or triplet which already passed.
+    Passed,
+    /// Enough messages of the client were delivered for it to be no longer greylisted.
+    Whitelisted,
+}
+
+/// Greylist the triplet `key` (a [`GreylistKey::Triplet`]) attempted at `now`.
+///
+/// # Errors
+///
+/// * the storage is not available
+pub fn check(
+    store: &dyn GreylistStore,
+    config: &FieldAppGreylist,
+    key: &GreylistKey,
+    now: std::time::SystemTime,
+) -> anyhow::Result<GreylistVerdict> {
+    let client = match key {
+        GreylistKey::Triplet { client_ip, .. } => GreylistKey::Client(*client_ip),
+        GreylistKey::Client(_) => anyhow::bail!("greylisting a client, not a triplet: {key}"),
+    };
+
+    if let Some(auto_whitelist) = config.auto_whitelist {
+        if store
+            .get(&client)?
+            .map_or(false, |record| record.passed >= auto_whitelist)
+        {
+            return Ok(GreylistVerdict::Whitelisted);
+        }
+    }
+
+    let record = match store.get(key)? {
+        None => {
+            store.set(
+                key,
+                GreylistRecord {
+                    first_seen: now,
+                    passed: 0,
+                },
+                config.retry_window,
+            )?;
+            return Ok(GreylistVerdict::Greylisted);
+        }
+        Some(record)
+            if record.passed == 0
+                && now
+                    .duration_since(record.first_seen)
+                    .map_or(true, |elapsed| elapsed < config.delay) =>
+        {
+            return Ok(GreylistVerdict::Greylisted);
+        }
+        Some(record) => record,
+    };
+
+    store.set(
+        key,
+        GreylistRecord {
+            passed: record.passed.saturating_add(1),
+            ..record
+        },
+        config.expire,
+    )?;
+
+    Ok(GreylistVerdict::Passed)
+}
+
+/// Count a message of `client_ip` successfully delivered at `now`, for the auto-whitelist.
+///
+/// # Errors
+///
+/// * the storage is not available
+pub fn record_delivery(
+    store: &dyn GreylistStore,
+    config: &FieldAppGreylist,
+    client_ip: std::net::IpAddr,
+    now: std::time::SystemTime,
+) -> anyhow::Result<()> {
+    if config.auto_whitelist.is_none() {
+        return Ok(());
+    }
+
+    let client = GreylistKey::Client(client_ip);
+    let record = store.get(&client)?.unwrap_or(GreylistRecord {
+        first_seen: now,
+        passed: 0,
+    });
+    store.set(
+        &client,
+        GreylistRecord {
+            passed: record.passed.saturating_add(1),
+            ..record
+        },
+        config.expire,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check, record_delivery, GreylistKey, GreylistRecord, GreylistStore, GreylistVerdict,
+        MemoryStore, PURGE_PERIOD,
+    };
+    use vsmtp_config::field::FieldAppGreylist;
+
+    const MINUTE: std::time::Duration = std::time::Duration::from_secs(60);
+
+    fn triplet(recipient: &str) -> GreylistKey {
+        GreylistKey::Triplet {
+            client_ip: "192.0.2.1".parse().unwrap(),
+            sender: "john@doe.com".to_string(),
+            recipient: recipient.to_string(),
+        }
+    }
+
+    /// A store whose clock is set by the test, the records never expiring.
+    #[derive(Debug, Default)]
+    struct Store(std::sync::Mutex<std::collections::HashMap<GreylistKey, GreylistRecord>>);
+
+    impl GreylistStore for Store {
+        fn get(&self, key: &GreylistKey) -> anyhow::Result<Option<GreylistRecord>> {
+            Ok(self.0.lock().unwrap().get(key).copied())
+        }
+
+        fn set(
+            &self,
+            key: &GreylistKey,
+            record: GreylistRecord,
+            _: std::time::Duration,
+        ) -> anyhow::Result<()> {
+            self.0.lock().unwrap().insert(key.clone(), record);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn greylisted_until_delay() {
+        let (store, config) = (Store::default(), FieldAppGreylist::default());
+        let now = std::time::SystemTime::now();
+
+        for elapsed in [0, 1, 4] {
+            assert_eq!(
+                check(&store, &config, &triplet("a@b"), now + elapsed * MINUTE).unwrap(),
+                GreylistVerdict::Greylisted
+            );
+        }
+        // the retries do not delay the first attempt
+        assert_eq!(store.get(&triplet("a@b")).unwrap().unwrap().first_seen, now);
+
+        assert_eq!(
+            check(&store, &config, &triplet("a@b"), now + 5 * MINUTE).unwrap(),
+            GreylistVerdict::Passed
+        );
+        assert_eq!(
+            check(&store, &config, &triplet("a@b"), now + 6 * MINUTE).unwrap(),
+            GreylistVerdict::Passed
+        );
+        assert_eq!(
+            check(&store, &config, &triplet("c@d"), now + 6 * MINUTE).unwrap(),
+            GreylistVerdict::Greylisted
+        );
+    }
+
+    #[test]
+    fn auto_whitelist() {
+        let store = Store::default();
+        let config = FieldAppGreylist {
+            auto_whitelist: Some(2),
+            ..FieldAppGreylist::default()
+        };
+        let now = std::time::SystemTime::now();
+        let client_ip = "192.0.2.1".parse().unwrap();
+
+        // passing the greylisting does not count, only the deliveries do
+        for recipient in ["a@b", "c@d"] {
+            check(&store, &config, &triplet(recipient), now).unwrap();
+            assert_eq!(
+                check(&store, &config, &triplet(recipient), now + 5 * MINUTE).unwrap(),
+                GreylistVerdict::Passed
+            );
+        }
+        assert_eq!(store.get(&GreylistKey::Client(client_ip)).unwrap(), None);
+
+        record_delivery(&store, &config, client_ip, now + 5 * MINUTE).unwrap();
+        assert_eq!(
+            check(&store, &config, &triplet("e@f"), now + 5 * MINUTE).unwrap(),
+            GreylistVerdict::Greylisted
+        );
+
+        record_delivery(&store, &config, client_ip, now + 6 * MINUTE).unwrap();
+        assert_eq!(
+            check(&store, &config, &triplet("g@h"), now + 6 * MINUTE).unwrap(),
+            GreylistVerdict::Whitelisted
+        );
+        assert_eq!(store.get(&triplet("g@h")).unwrap(), None);
+    }
+
+    #[test]
+    fn auto_whitelist_disabled() {
+        let store = Store::default();
+        let config = FieldAppGreylist {
+            auto_whitelist: None,
+            ..FieldAppGreylist::default()
+        };
+        let now = std::time::SystemTime::now();
+        let client_ip = "192.0.2.1".parse().unwrap();
+
+        for _ in 0..10 {
+            record_delivery(&store, &config, client_ip, now).unwrap();
+        }
+
+        assert_eq!(
+            check(&store, &config, &triplet("a@b"), now).unwrap(),
+            GreylistVerdict::Greylisted
+        );
+        assert_eq!(store.get(&GreylistKey::Client(client_ip)).unwrap(), None);
+    }
+
+    #[test]
+    fn memory_store_expire() {
+        let store = MemoryStore::default();
+        let now = std::time::SystemTime::now();
+        let record = GreylistRecord {
+            first_seen: now,
+            passed: 0,
+        };
+
+        store
+            .set_at(&triplet("a@b"), record, 2 * MINUTE, now)
+            .unwrap();
+        assert_eq!(
+            store.get_at(&triplet("a@b"), now + MINUTE).unwrap(),
+            Some(record)
+        );
+        assert_eq!(
+            store.get_at(&triplet("a@b"), now + 2 * MINUTE).unwrap(),
+            None
+        );
+
+        // the expired record is removed by the next purge
+        store
+            .set_at(&triplet("c@d"), record, MINUTE, now + PURGE_PERIOD / 2)
+            .unwrap();
+        assert_eq!(store.len(), 2);
+        store
+            .set_at(&triplet("e@f"), record, MINUTE, now + 2 * MINUTE)
+            .unwrap();
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -62,6 +62,12 @@ pub use execution_state::ExecutionStage;
 pub use rule_engine::RuleEngine;
 pub use rule_state::RuleState;
 
+/// Greylisting of the (client IP address, sender, recipient) triplets.
+pub mod greylist;
+
+/// Key-value sources looked up by the rules.
+pub mod lookup;
+
 // TODO: restrain this to the rule engine import / allow only in cfg debug.
 /// Build sub domain hierarchy configurations.
 pub mod sub_domain_hierarchy;
@@ -99,9 +105,13 @@ pub mod api {
     pub mod envelop;
     /// API to write of the message on disk.
     pub mod fs;
+    /// Greylisting of the senders.
+    pub mod greylist;
     /// Log a message of `level` in the `app` target, which will be written to the
     /// the fie you specified in the field `app.logs.filename` form the [`vsmtp_config::Config`].
     pub mod logging;
+    /// Lookup of the recipients in a users database.
+    pub mod lookup;
     /// Extensions for the [`MailContext`](vsmtp_common::Context) type.
     pub mod mail_context;
     /// Extensions for the [`MessageBody`](vsmtp_mail_parser::MessageBody) type.
@@ -136,7 +146,7 @@ pub mod api {
 
     /// Get vsmtp static modules.
     #[must_use]
-    pub fn vsmtp_static_modules() -> [(&'static str, rhai::Module); 19] {
+    pub fn vsmtp_static_modules() -> [(&'static str, rhai::Module); 21] {
         [
             ("state", rhai::exported_module!(state)),
             ("envelop", rhai::exported_module!(envelop)),
@@ -150,6 +160,8 @@ pub mod api {
             ("spf", rhai::exported_module!(spf)),
             ("dkim", rhai::exported_module!(dkim)),
             ("dmarc", rhai::exported_module!(dmarc)),
+            ("greylist", rhai::exported_module!(greylist)),
+            ("lookup", rhai::exported_module!(lookup)),
             ("transport", rhai::exported_module!(transports)),
             ("utils", rhai::exported_module!(utils)),
             ("ctx", rhai::exported_module!(mail_context)),
@@ -0,0 +1,265 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use anyhow::Context;
+use vsmtp_config::field::FieldAppLookup;
+
+/// A key-value source the rules look the keys up in, see [`open`].
+pub trait Lookup: std::fmt::Debug + Send + Sync {
+    /// Get the value of `key`, `None` if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// * the source is not available
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+}
+
+/// Open the source described in the configuration.
+///
+/// # Errors
+///
+/// * the file of the source cannot be read
+/// * the query of a `SQLite` source is invalid
+/// * the source needs a feature vSMTP is built without
+pub fn open(config: &FieldAppLookup) -> anyhow::Result<std::sync::Arc<dyn Lookup>> {
+    Ok(match config {
+        FieldAppLookup::File { path } => std::sync::Arc::new(FileLookup::open(path)?),
+        #[cfg(feature = "lookup-cdb")]
+        FieldAppLookup::Cdb { path } => std::sync::Arc::new(CdbLookup::open(path)?),
+        #[cfg(not(feature = "lookup-cdb"))]
+        FieldAppLookup::Cdb { .. } => {
+            anyhow::bail!("cdb lookups need vSMTP built with the `lookup-cdb` feature")
+        }
+        #[cfg(feature = "lookup-sqlite")]
+        FieldAppLookup::Sqlite { path, query } => {
+            std::sync::Arc::new(SqliteLookup::open(path, query)?)
+        }
+        #[cfg(not(feature = "lookup-sqlite"))]
+        FieldAppLookup::Sqlite { .. } => {
+            anyhow::bail!("SQLite lookups need vSMTP built with the `lookup-sqlite` feature")
+        }
+    })
+}
+
+/// A text file, read entirely in memory when opened.
+#[derive(Debug)]
+struct FileLookup {
+    entries: std::collections::HashMap<String, String>,
+}
+
+impl FileLookup {
+    fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the lookup file {path:?}"))?;
+
+        Ok(Self::parse(&content))
+    }
+
+    fn parse(content: &str) -> Self {
+        Self {
+            entries: content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| {
+                    line.split_once(char::is_whitespace).map_or_else(
+                        || (line.to_string(), String::new()),
+                        |(key, value)| (key.to_string(), value.trim_start().to_string()),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Lookup for FileLookup {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.entries.get(key).cloned())
+    }
+}
+
+/// A constant database, read on each lookup.
+#[cfg(feature = "lookup-cdb")]
+struct CdbLookup {
+    path: std::path::PathBuf,
+    cdb: cdb::CDB,
+}
+
+#[cfg(feature = "lookup-cdb")]
+impl std::fmt::Debug for CdbLookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CdbLookup")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "lookup-cdb")]
+impl CdbLookup {
+    fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            cdb: cdb::CDB::open(path)
+                .with_context(|| format!("failed to open the lookup cdb {path:?}"))?,
+        })
+    }
+}
+
+#[cfg(feature = "lookup-cdb")]
+impl Lookup for CdbLookup {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        self.cdb
+            .get(key.as_bytes())
+            .transpose()
+            .with_context(|| format!("failed to read the lookup cdb {:?}", self.path))
+            .map(|value| value.map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+}
+
+/// A `SQLite` database, the connection being shared by the lookups.
+#[cfg(feature = "lookup-sqlite")]
+#[derive(Debug)]
+struct SqliteLookup {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+    query: String,
+}
+
+#[cfg(feature = "lookup-sqlite")]
+impl SqliteLookup {
+    fn open(path: &std::path::Path, query: &str) -> anyhow::Result<Self> {
+        let connection =
+            rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .with_context(|| format!("failed to open the lookup database {path:?}"))?;
+
+        // NOTE: checking the query once here instead of on each lookup.
+        connection
+            .prepare_cached(query)
+            .with_context(|| format!("invalid lookup query `{query}`"))?;
+
+        Ok(Self {
+            connection: std::sync::Mutex::new(connection),
+            query: query.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "lookup-sqlite")]
+impl Lookup for SqliteLookup {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        use rusqlite::{types::ValueRef, OptionalExtension};
+
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("lookup database poisoned"))?;
+
+        let result = connection
+            .prepare_cached(&self.query)?
+            .query_row([key], |row| {
+                Ok(match row.get_ref(0)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(value) => value.to_string(),
+                    ValueRef::Real(value) => value.to_string(),
+                    ValueRef::Text(value) | ValueRef::Blob(value) => {
+                        String::from_utf8_lossy(value).into_owned()
+                    }
+                })
+            })
+            .optional()
+            .with_context(|| format!("failed to run the lookup query `{}`", self.query));
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file() {
+        let lookup = FileLookup::parse(
+            "# users\n\njohn@doe.com\n  jane@doe.com   mailbox/jane  \n#james@doe.com\n",
+        );
+
+        assert_eq!(lookup.get("john@doe.com").unwrap(), Some(String::new()));
+        assert_eq!(
+            lookup.get("jane@doe.com").unwrap(),
+            Some("mailbox/jane".to_string())
+        );
+        assert_eq!(lookup.get("james@doe.com").unwrap(), None);
+        assert_eq!(lookup.get("#james@doe.com").unwrap(), None);
+    }
+
+    #[test]
+    fn file_missing() {
+        let dir = tempfile::tempdir().unwrap();
+
+        open(&FieldAppLookup::File {
+            path: dir.path().join("users"),
+        })
+        .unwrap_err();
+    }
+
+    #[cfg(feature = "lookup-cdb")]
+    #[test]
+    fn cdb() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.cdb");
+
+        let mut writer = cdb::CDBWriter::create(path.display().to_string()).unwrap();
+        writer.add(b"john@doe.com", b"mailbox/john").unwrap();
+        writer.finish().unwrap();
+
+        let lookup = open(&FieldAppLookup::Cdb { path }).unwrap();
+
+        assert_eq!(
+            lookup.get("john@doe.com").unwrap(),
+            Some("mailbox/john".to_string())
+        );
+        assert_eq!(lookup.get("jane@doe.com").unwrap(), None);
+    }
+
+    #[cfg(feature = "lookup-sqlite")]
+    #[test]
+    fn sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.db");
+
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE users (address TEXT, quota INTEGER);
+                 INSERT INTO users VALUES ('john@doe.com', 100);",
+            )
+            .unwrap();
+
+        let lookup = open(&FieldAppLookup::Sqlite {
+            path: path.clone(),
+            query: "SELECT quota FROM users WHERE address = ?1".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(lookup.get("john@doe.com").unwrap(), Some("100".to_string()));
+        assert_eq!(lookup.get("jane@doe.com").unwrap(), None);
+
+        open(&FieldAppLookup::Sqlite {
+            path,
+            query: "SELECT 1 FROM unknown WHERE address = ?1".to_string(),
+        })
+        .unwrap_err();
+    }
+}
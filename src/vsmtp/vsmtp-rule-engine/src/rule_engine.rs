@@ -20,6 +20,7 @@ use crate::{
         directives::{Directive, Directives},
         smtp::service,
     },
+    greylist::{GreylistStore, MemoryStore},
     rule_state::RuleState,
     server_api::ServerAPI,
     sub_domain_hierarchy::{Builder, DomainDirectives, Script, SubDomainHierarchy},
@@ -31,7 +32,10 @@ use rhai::{module_resolvers::FileModuleResolver, packages::Package, Engine, Scop
 use rhai_dylib::module_resolvers::libloading::DylibModuleResolver;
 use vqueue::{GenericQueueManager, QueueID};
 use vsmtp_common::{status::Status, CodeID, Domain, Reply, ReplyOrCodeID, TransactionType};
-use vsmtp_config::{field::FieldAppVSL, Config, DnsResolvers};
+use vsmtp_config::{
+    field::{FieldAppVSL, RuleEngineFailPolicy},
+    Config, DnsResolvers,
+};
 use vsmtp_mail_parser::MessageBody;
 
 /// a sharable rhai engine.
@@ -137,10 +141,12 @@ impl RuleEngine {
                 FieldAppVSL {
                     filter_path: Some(filter_path),
                     domain_dir,
+                    ..
                 } => {
                     tracing::info!("Analyzing vSL rules at {filter_path:?}");
 
                     SubDomainHierarchy::new(&engine, filter_path, domain_dir.as_deref())?
+                        .with_stage_scripts(&engine, &config.app.vsl.stage_scripts)?
                 }
                 FieldAppVSL {
                     filter_path: None, ..
@@ -150,6 +156,7 @@ impl RuleEngine {
                         );
 
                     SubDomainHierarchy::new_empty(&engine)?
+                        .with_stage_scripts(&engine, &config.app.vsl.stage_scripts)?
                 }
             },
 
@@ -157,6 +164,14 @@ impl RuleEngine {
             either::Either::Right(builder) => builder(Builder::new(&engine)?)?,
         };
 
+        let rcpt_lookup = config
+            .app
+            .rcpt_lookup
+            .as_ref()
+            .map(crate::lookup::open)
+            .transpose()
+            .context("failed to open the recipients lookup")?;
+
         tracing::info!("Rule engine initialized.");
 
         #[cfg(debug_assertions)]
@@ -173,11 +188,42 @@ impl RuleEngine {
                 config,
                 resolvers,
                 queue_manager,
+                greylist_store: std::sync::Arc::new(MemoryStore::default()),
+                rcpt_lookup,
             }),
             rules,
         })
     }
 
+    /// Store the records of `greylist()` in `greylist_store` instead of the memory,
+    /// to share them between several instances or keep them across restarts.
+    #[must_use]
+    pub fn with_greylist_store(
+        mut self,
+        greylist_store: std::sync::Arc<dyn GreylistStore>,
+    ) -> Self {
+        self.server = std::sync::Arc::new(ServerAPI {
+            greylist_store,
+            ..(*self.server).clone()
+        });
+        self
+    }
+
+    /// Count a message of `client_ip` delivered successfully, for the auto-whitelist
+    /// of the greylisting.
+    ///
+    /// # Errors
+    ///
+    /// * the greylist store is not available
+    pub fn record_greylist_delivery(&self, client_ip: std::net::IpAddr) -> anyhow::Result<()> {
+        crate::greylist::record_delivery(
+            &*self.server.greylist_store,
+            &self.server.config.app.greylist,
+            client_ip,
+            std::time::SystemTime::now(),
+        )
+    }
+
     ///
     #[must_use]
     pub fn spawn(&self) -> std::sync::Arc<RuleState> {
@@ -199,6 +245,11 @@ impl RuleEngine {
             (mail_context.clone(), self.server.clone(), message.clone());
 
         let mut engine = rhai::Engine::new_raw();
+        // NOTE: the rules are not limited, `Engine::set_max_operations` & co are only
+        //       used for the evaluation of the configuration.
+        engine
+            .set_max_expr_depths(0, 0)
+            .set_max_call_levels(usize::MAX);
 
         // NOTE: on_var is not deprecated, just subject to change in future releases.
         #[allow(deprecated)]
@@ -418,7 +469,13 @@ impl RuleEngine {
             }
         };
 
-        let status = Self::execute_directives(rule_state, &script.ast, directive, smtp_state);
+        let status = Self::execute_directives(
+            rule_state,
+            &script.ast,
+            directive,
+            smtp_state,
+            self.server.config.app.vsl.fail_policy,
+        );
 
         if status.is_finished() {
             tracing::info!(
@@ -460,7 +517,7 @@ impl RuleEngine {
     ) -> anyhow::Result<&'a Script> {
         match smtp_state {
             ExecutionStage::Connect | ExecutionStage::Helo | ExecutionStage::Authenticate => {
-                Ok(&self.rules.root_filter)
+                Ok(self.rules.root_filter_of(smtp_state))
             }
 
             ExecutionStage::MailFrom => {
@@ -472,7 +529,7 @@ impl RuleEngine {
                             Ok(&self.rules.fallback)
                         }, |domain_directives| Ok(&domain_directives.outgoing))
                     }
-                    Some(_) | None => Ok(&self.rules.root_filter),
+                    Some(_) | None => Ok(self.rules.root_filter_of(smtp_state)),
                 }
             }
 
@@ -512,7 +569,7 @@ impl RuleEngine {
                             }
                         }
                     }
-                    None => Ok(&self.rules.root_filter),
+                    None => Ok(self.rules.root_filter_of(smtp_state)),
                     Some(_) => {
                         // Sender domain unknown, running incoming rules for each recipient which the domain is handled by the configuration,
                         // otherwise run the fallback script.
@@ -524,7 +581,7 @@ impl RuleEngine {
                             Ok(&rules.incoming)
                         } else {
                             tracing::debug!(%rcpt, "Recipient unknown in unknown sender context, running fallback script.");
-                            Ok(&self.rules.root_filter)
+                            Ok(self.rules.root_filter_of(smtp_state))
                         }
                     }
                 }
@@ -556,7 +613,7 @@ impl RuleEngine {
                             }
                         }
                     }
-                    None => Ok(&self.rules.root_filter),
+                    None => Ok(self.rules.root_filter_of(smtp_state)),
                     Some(_) => {
                         // Sender domain unknown, running incoming rules for each recipient which the domain is handled by the configuration,
                         // otherwise run the fallback script.
@@ -569,7 +626,7 @@ impl RuleEngine {
                             }
                             TransactionType::Incoming(None) => {
                                 tracing::info!("No recipient has a domain handled by your configuration, running root incoming script");
-                                Ok(&self.rules.root_filter)
+                                Ok(self.rules.root_filter_of(smtp_state))
                             }
                             TransactionType::Outgoing { .. } | TransactionType::Internal => {
                                 tracing::error!("email is supposed to incoming but was marked has outgoing, running fallback scripts.");
@@ -600,16 +657,27 @@ impl RuleEngine {
         ast: &rhai::AST,
         directives: &[Directive],
         smtp_state: ExecutionStage,
+        fail_policy: RuleEngineFailPolicy,
     ) -> Status {
         let mut status = Status::Next;
 
         for directive in directives {
             status = directive
                 .execute(rule_state, ast, smtp_state)
-                .unwrap_or_else(|e| {
-                    let error_status = deny();
-                    println!("error: {e:?}");
-                    // tracing::warn!(%e, "error while executing directive returning: {:?}", error_status);
+                .unwrap_or_else(|error| {
+                    let error_status = match fail_policy {
+                        RuleEngineFailPolicy::Deny => deny(),
+                        RuleEngineFailPolicy::FailOpen => Status::Next,
+                        RuleEngineFailPolicy::FailClosed => {
+                            Status::Deny(ReplyOrCodeID::Left(CodeID::RuleEngineFailure))
+                        }
+                    };
+                    tracing::error!(
+                        directive = directive.name(),
+                        %error,
+                        ?fail_policy,
+                        "Failed to evaluate the directive, returning {error_status:?}."
+                    );
                     error_status
                 });
 
@@ -625,6 +693,9 @@ impl RuleEngine {
     #[must_use]
     pub fn new_rhai_engine() -> rhai::Engine {
         let mut engine = Engine::new();
+        engine
+            .set_max_expr_depths(0, 0)
+            .set_max_call_levels(usize::MAX);
 
         // NOTE: on_parse_token is not deprecated, just subject to change in future releases.
         #[allow(deprecated)]
@@ -855,6 +926,58 @@ impl RuleEngine {
         }
     }
 
+    /// Call the `name` function of the root filtering script with `argument`, to answer
+    /// the `VRFY` (`vrfy`) and `EXPN` (`expn`) commands.
+    ///
+    /// The function returns a string, an array of strings, or `()` if the argument
+    /// does not match anything.
+    ///
+    /// # Return
+    ///
+    /// `None` if the function is not defined, otherwise the addresses returned by it.
+    ///
+    /// # Errors
+    /// * The function failed.
+    /// * The function returned another type.
+    pub fn run_verify(
+        &self,
+        rule_state: &RuleState,
+        name: &str,
+        argument: &str,
+    ) -> anyhow::Result<Option<Vec<String>>> {
+        let ast = &self.rules.root_filter.ast;
+
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == 1)
+        {
+            return Ok(None);
+        }
+
+        let result = rule_state
+            .engine()
+            .call_fn::<rhai::Dynamic>(&mut rhai::Scope::new(), ast, name, (argument.to_string(),))
+            .map_err(|e| anyhow::anyhow!("the '{name}' function failed: {e}"))?;
+
+        let invalid_type = |_| {
+            anyhow::anyhow!("the '{name}' function must return a string, an array of strings or ()")
+        };
+
+        if result.is_unit() {
+            Ok(Some(vec![]))
+        } else if result.is_array() {
+            result
+                .into_array()
+                .map_err(invalid_type)?
+                .into_iter()
+                .map(|address| address.into_string().map_err(invalid_type))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map(Some)
+        } else {
+            Ok(Some(vec![result.into_string().map_err(invalid_type)?]))
+        }
+    }
+
     /// Find the delegate directive that matches the given socket.
     #[must_use]
     #[cfg(feature = "delegation")]
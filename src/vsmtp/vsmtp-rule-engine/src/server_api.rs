@@ -14,6 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+use crate::{greylist::GreylistStore, lookup::Lookup};
 use vqueue::GenericQueueManager;
 use vsmtp_config::{Config, DnsResolvers};
 
@@ -23,4 +24,6 @@ pub struct ServerAPI {
     pub config: std::sync::Arc<Config>,
     pub resolvers: std::sync::Arc<DnsResolvers>,
     pub queue_manager: std::sync::Arc<dyn GenericQueueManager>,
+    pub greylist_store: std::sync::Arc<dyn GreylistStore>,
+    pub rcpt_lookup: Option<std::sync::Arc<dyn Lookup>>,
 }
@@ -15,7 +15,7 @@
  *
 */
 
-use crate::{dsl::directives::Directives, RuleEngine};
+use crate::{dsl::directives::Directives, ExecutionStage, RuleEngine};
 use anyhow::Context;
 
 /// Rules that automatically deny the transaction once run.
@@ -32,6 +32,8 @@ pub struct SubDomainHierarchy {
     pub root_filter: Script,
     /// Used if an error occurred in the hierarchy's logic.
     pub fallback: Script,
+    /// Rules replacing the ones of `root_filter` for a stage.
+    pub stages: std::collections::BTreeMap<ExecutionStage, Script>,
     /// Domain specific rules, executed following the transaction context.
     pub domains: std::collections::BTreeMap<String, DomainDirectives>,
 }
@@ -137,6 +139,7 @@ impl SubDomainHierarchy {
             .context("failed to load your root filtering script (filter.vsl)")?,
             fallback: Self::rules_from_script(engine, DEFAULT_FALLBACK_RULES)
                 .context("failed to load fallback rules: this is a bug, please report it.")?,
+            stages: std::collections::BTreeMap::new(),
             domains: hierarchy,
         })
     }
@@ -149,10 +152,67 @@ impl SubDomainHierarchy {
         Ok(Self {
             root_filter: Self::rules_from_script(engine, DEFAULT_ROOT_FILTERING_RULES)?,
             fallback: Self::rules_from_script(engine, DEFAULT_FALLBACK_RULES)?,
+            stages: std::collections::BTreeMap::new(),
             domains: std::collections::BTreeMap::new(),
         })
     }
 
+    /// Compile the scripts replacing the root filtering rules of a stage, by name of the stage.
+    /// The `data` stage is another name of the `preq` stage, run once the message is received.
+    ///
+    /// # Errors
+    /// * The stage does not exist, or is given under both its names.
+    /// * Failed to read or to compile a script.
+    /// * A script declares rules for another stage.
+    pub fn with_stage_scripts(
+        mut self,
+        engine: &rhai::Engine,
+        stage_scripts: &std::collections::BTreeMap<String, std::path::PathBuf>,
+    ) -> anyhow::Result<Self> {
+        for (stage, path) in stage_scripts {
+            let stage = match stage.as_str() {
+                "data" => Ok(ExecutionStage::PreQ),
+                stage => ExecutionStage::try_from(stage),
+            }
+            .map_err(|_| anyhow::anyhow!("the '{stage}' smtp stage does not exist."))?;
+            anyhow::ensure!(
+                !self.stages.contains_key(&stage),
+                "the script of the '{stage}' stage is given twice."
+            );
+
+            let source = std::fs::read_to_string(path).with_context(|| {
+                format!(
+                    "cannot read the script of the '{stage}' stage at '{}'",
+                    path.display()
+                )
+            })?;
+            let script = Self::rules_from_script(engine, &source).with_context(|| {
+                format!(
+                    "failed to compile the script of the '{stage}' stage at '{}'",
+                    path.display()
+                )
+            })?;
+
+            if let Some(other) = script.directives.keys().find(|other| **other != stage) {
+                anyhow::bail!(
+                    "the script of the '{stage}' stage at '{}' declares rules for the '{other}' stage",
+                    path.display()
+                );
+            }
+
+            tracing::info!("Analyzing vSL rules of the '{stage}' stage at {path:?}");
+            self.stages.insert(stage, script);
+        }
+
+        Ok(self)
+    }
+
+    /// The root filtering rules to run at `stage`.
+    #[must_use]
+    pub fn root_filter_of(&self, stage: ExecutionStage) -> &Script {
+        self.stages.get(&stage).unwrap_or(&self.root_filter)
+    }
+
     /// Create rules from a path, use a default script if the default path could not be loaded
     #[tracing::instrument(skip(engine, default), err)]
     fn rules_from_path_or_default(
@@ -212,6 +272,18 @@ impl<'a> Builder<'a> {
         Ok(self)
     }
 
+    /// compile a script replacing the root filtering rules of `stage` and add it to the hierarchy.
+    ///
+    /// # Errors
+    /// * Failed to compile the script.
+    pub fn add_stage_rules(mut self, stage: ExecutionStage, script: &str) -> anyhow::Result<Self> {
+        self.inner.stages.insert(
+            stage,
+            SubDomainHierarchy::rules_from_script(self.engine, script)?,
+        );
+        Ok(self)
+    }
+
     /// compile incoming, outgoing & internal scripts and add them to a domain of the hierarchy.
     ///     ///
     /// # Errors
@@ -52,6 +52,7 @@ fn run_benchmark(body_size: u64, port: u16) {
                     queue_manager.clone(),
                     working_channel.0.clone(),
                     delivery_channel.0.clone(),
+                    std::sync::Arc::default(),
                 )
                 .unwrap()
                 .listen_and_serve((
@@ -54,6 +54,7 @@ fn run_benchmark(body_size: u64, port: u16) {
                     .unwrap(),
                     working_channel.0.clone(),
                     delivery_channel.0.clone(),
+                    std::sync::Arc::default(),
                 )
                 .unwrap()
                 .listen_and_serve((
@@ -0,0 +1,509 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+use crate::ProcessMessage;
+use anyhow::Context;
+use vqueue::{GenericQueueManager, QueueID};
+use vsmtp_common::{
+    transfer::{EmailTransferStatus, RuleEngineVariants, TransferErrorsVariant},
+    CodeID, ContextFinished, ReplyOrCodeID,
+};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_rule_engine::ExecutionStage;
+
+const DELEGATION_HEADER: &str = "X-VSMTP-DELEGATION";
+
+/// Maximum length of a request line of the delegation socket.
+const REQUEST_LINE_MAX: u64 = 1024;
+
+/// The decision of an external filter on a delegated message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DelegationVerdict {
+    /// Swap the message and resume the rules after the delegation directive.
+    Resume,
+    /// Reject the message, all recipients are marked as failed.
+    Deny(ReplyOrCodeID),
+    /// Move the message to the given quarantine queue.
+    Quarantine(String),
+}
+
+impl std::str::FromStr for DelegationVerdict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "resume" => Ok(Self::Resume),
+            None if s == "deny" => Ok(Self::Deny(either::Left(CodeID::Denied))),
+            Some(("quarantine", name)) if !name.is_empty() => {
+                Ok(Self::Quarantine(name.to_string()))
+            }
+            _ => anyhow::bail!("invalid verdict `{s}`"),
+        }
+    }
+}
+
+/// Fetch a message waiting in the delegated queue, so that an external
+/// process can filter it.
+///
+/// # Errors
+///
+/// * the message is not in the delegated queue.
+pub async fn pull_delegated<Q: GenericQueueManager + ?Sized>(
+    queue_manager: &Q,
+    message_uuid: &uuid::Uuid,
+) -> anyhow::Result<(ContextFinished, MessageBody)> {
+    queue_manager
+        .get_both(&QueueID::Delegated, message_uuid)
+        .await
+}
+
+/// Push back the result of an external filter for a delegated message.
+///
+/// The stored message is replaced by `message`, and depending on the verdict
+/// the email is either handed back to the process that delegated it, moved to
+/// the dead queue or quarantined.
+///
+/// # Errors
+///
+/// * the message is not in the delegated queue.
+/// * the delegation header of the original message is missing or ill-formed.
+/// * failed to write the message or move the context.
+/// * failed to notify the next process.
+pub async fn resume_delegated<Q: GenericQueueManager + ?Sized>(
+    queue_manager: &Q,
+    working_sender: &tokio::sync::mpsc::Sender<ProcessMessage>,
+    delivery_sender: &tokio::sync::mpsc::Sender<ProcessMessage>,
+    message_uuid: &uuid::Uuid,
+    mut message: MessageBody,
+    verdict: DelegationVerdict,
+) -> anyhow::Result<()> {
+    let (mut ctx, original) = pull_delegated(queue_manager, message_uuid).await?;
+
+    // NOTE: the rule engine needs this header to know where to resume, a filter
+    //       rewriting the message could have dropped it.
+    let header = original
+        .get_header(DELEGATION_HEADER)
+        .context("delegated message does not have a delegation header")?;
+    if message.get_header(DELEGATION_HEADER).is_none() {
+        message.prepend_header(DELEGATION_HEADER, &header);
+    }
+
+    queue_manager.write_msg(message_uuid, &message).await?;
+
+    match verdict {
+        DelegationVerdict::Resume => {
+            let stage = vsmtp_mail_parser::get_mime_header(DELEGATION_HEADER, &header)
+                .args
+                .get("stage")
+                .context("delegation header does not have a stage")?
+                .parse::<ExecutionStage>()
+                .map_err(|_| anyhow::anyhow!("delegation header has an invalid stage"))?;
+
+            match stage {
+                ExecutionStage::PostQ => working_sender,
+                ExecutionStage::Delivery => delivery_sender,
+                otherwise => anyhow::bail!("cannot resume a delegation from stage '{otherwise}'"),
+            }
+            .send(ProcessMessage {
+                message_uuid: *message_uuid,
+                delegated: true,
+            })
+            .await?;
+
+            tracing::debug!(%stage, "Delegated message resumed.");
+        }
+        DelegationVerdict::Deny(code) => {
+            for rcpt in &mut ctx.rcpt_to.forward_paths {
+                rcpt.email_status = EmailTransferStatus::failed(TransferErrorsVariant::RuleEngine(
+                    RuleEngineVariants::Denied(code.clone()),
+                ));
+            }
+
+            queue_manager
+                .move_to(&QueueID::Delegated, &QueueID::Dead, &ctx)
+                .await?;
+        }
+        DelegationVerdict::Quarantine(name) => {
+            queue_manager
+                .move_to(&QueueID::Delegated, &QueueID::Quarantine { name }, &ctx)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serve the messages of the `delegated` queue on `server.queues.delegation_socket`,
+/// see [`pull_delegated`] and [`resume_delegated`].
+pub async fn serve_delegation(
+    listener: tokio::net::UnixListener,
+    queue_manager: std::sync::Arc<dyn GenericQueueManager>,
+    working_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+    delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+) {
+    tracing::info!(addr = ?listener.local_addr(), "Serving the delegated messages.");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let queue_manager = queue_manager.clone();
+                let working_sender = working_sender.clone();
+                let delivery_sender = delivery_sender.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = handle_client(
+                        stream,
+                        queue_manager.as_ref(),
+                        &working_sender,
+                        &delivery_sender,
+                    )
+                    .await
+                    {
+                        tracing::warn!(%error, "Delegation socket connection failed.");
+                    }
+                });
+            }
+            Err(error) => tracing::warn!(%error, "Delegation socket accept failure."),
+        }
+    }
+}
+
+/// A request received on the delegation socket.
+enum Request {
+    Pull(uuid::Uuid),
+    Push(uuid::Uuid, DelegationVerdict, usize),
+}
+
+impl Request {
+    fn parse(line: &str, message_size_max: usize) -> anyhow::Result<Self> {
+        let parse_uuid = |uuid: &str| {
+            uuid::Uuid::parse_str(uuid).map_err(|_| anyhow::anyhow!("invalid uuid `{uuid}`"))
+        };
+
+        match line.split(' ').collect::<Vec<_>>()[..] {
+            ["PULL", uuid] => Ok(Self::Pull(parse_uuid(uuid)?)),
+            ["PUSH", uuid, verdict, size] => {
+                let size = size
+                    .parse::<usize>()
+                    .map_err(|_| anyhow::anyhow!("invalid size `{size}`"))?;
+                anyhow::ensure!(
+                    size <= message_size_max,
+                    "message size {size} exceeds the limit of {message_size_max}"
+                );
+                Ok(Self::Push(parse_uuid(uuid)?, verdict.parse()?, size))
+            }
+            _ => anyhow::bail!("unknown request"),
+        }
+    }
+}
+
+async fn handle_client<S: tokio::io::AsyncRead + tokio::io::AsyncWrite>(
+    stream: S,
+    queue_manager: &dyn GenericQueueManager,
+    working_sender: &tokio::sync::mpsc::Sender<ProcessMessage>,
+    delivery_sender: &tokio::sync::mpsc::Sender<ProcessMessage>,
+) -> std::io::Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    let message_size_max = queue_manager.get_config().server.message_size_limit;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = tokio::io::BufReader::new(reader);
+
+    loop {
+        let mut line = String::new();
+        if (&mut reader)
+            .take(REQUEST_LINE_MAX)
+            .read_line(&mut line)
+            .await?
+            == 0
+        {
+            return Ok(());
+        }
+
+        // NOTE: the message following an invalid request cannot be told apart from
+        //       the next request, the connection is closed.
+        let request = match line
+            .strip_suffix('\n')
+            .context("request line too long")
+            .and_then(|line| Request::parse(line.trim_end_matches('\r'), message_size_max))
+        {
+            Ok(request) => request,
+            Err(error) => {
+                writer
+                    .write_all(format!("ERR {error}\n").as_bytes())
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let reply = match request {
+            Request::Pull(uuid) => {
+                pull_delegated(queue_manager, &uuid)
+                    .await
+                    .map(|(_, message)| {
+                        let message = message.inner().to_string();
+                        format!("OK {}\n{message}", message.len())
+                    })
+            }
+            Request::Push(uuid, verdict, size) => {
+                let mut message = vec![0; size];
+                reader.read_exact(&mut message).await?;
+
+                async {
+                    let message = MessageBody::try_from(std::str::from_utf8(&message)?)?;
+                    resume_delegated(
+                        queue_manager,
+                        working_sender,
+                        delivery_sender,
+                        &uuid,
+                        message,
+                        verdict,
+                    )
+                    .await
+                }
+                .await
+                .map(|()| "OK\n".to_string())
+            }
+        };
+
+        writer
+            .write_all(
+                reply
+                    .unwrap_or_else(|error| format!("ERR {error}\n"))
+                    .as_bytes(),
+            )
+            .await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_common::status::Status;
+    use vsmtp_config::DnsResolvers;
+    use vsmtp_rule_engine::RuleEngine;
+    use vsmtp_test::config::{local_ctx, local_msg, local_test};
+
+    // NOTE: the state of a message right after the `delegate` directive of the
+    //       postq stage sent it to the service.
+    async fn delegated(queue_manager: &std::sync::Arc<vqueue::temp::QueueManager>) -> uuid::Uuid {
+        let message_uuid = uuid::Uuid::new_v4();
+        let mut ctx = local_ctx();
+        ctx.mail_from.message_uuid = message_uuid;
+        ctx.connect.skipped = Some(Status::DelegationResult);
+
+        let mut msg = local_msg();
+        msg.prepend_header(
+            DELEGATION_HEADER,
+            &format!(
+                "sent; stage={}; directive=\"filter\"; id=\"{message_uuid}\"",
+                ExecutionStage::PostQ
+            ),
+        );
+
+        queue_manager
+            .write_both(&QueueID::Delegated, &ctx, &msg)
+            .await
+            .unwrap();
+
+        message_uuid
+    }
+
+    #[tokio::test]
+    async fn delegate_modify_resume() {
+        let config = std::sync::Arc::new(local_test());
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let message_uuid = delegated(&queue_manager).await;
+
+        let (_, mut message) = pull_delegated(queue_manager.as_ref(), &message_uuid)
+            .await
+            .unwrap();
+        message.remove_header(DELEGATION_HEADER);
+        message.append_header("X-Filtered", "yes");
+
+        let (working_sender, mut working_receiver) = tokio::sync::mpsc::channel(10);
+        let (delivery_sender, mut delivery_receiver) = tokio::sync::mpsc::channel(10);
+
+        resume_delegated(
+            queue_manager.as_ref(),
+            &working_sender,
+            &delivery_sender,
+            &message_uuid,
+            message,
+            DelegationVerdict::Resume,
+        )
+        .await
+        .unwrap();
+
+        let process_message = working_receiver.recv().await.unwrap();
+        assert_eq!(process_message.message_uuid, message_uuid);
+        assert!(process_message.delegated);
+
+        let stored = queue_manager.get_msg(&message_uuid).await.unwrap();
+        assert_eq!(stored.get_header("X-Filtered").as_deref(), Some("yes"));
+        assert!(stored.get_header(DELEGATION_HEADER).is_some());
+
+        crate::processing::handle_one_in_working_queue(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::with_hierarchy(
+                    config.clone(),
+                    |builder| {
+                        Ok(builder
+                            .add_root_filter_rules(&format!(
+                                r#"
+const service = smtp::connect(#{{
+    receiver: "127.0.0.1:10026",
+    delegator: #{{ address: "127.0.0.1:10025" }},
+}});
+
+#{{
+    {}: [
+        delegate service "filter" || if has_header("X-Filtered") {{ state::next() }} else {{ state::deny() }},
+    ]
+}}"#,
+                                ExecutionStage::PostQ
+                            ))?
+                            .build())
+                    },
+                    resolvers,
+                    queue_manager.clone(),
+                )
+                .unwrap(),
+            ),
+            queue_manager.clone(),
+            process_message,
+            delivery_sender,
+        )
+        .await
+        .unwrap();
+
+        let process_message = delivery_receiver.recv().await.unwrap();
+        assert_eq!(process_message.message_uuid, message_uuid);
+        assert!(!process_message.delegated);
+        queue_manager
+            .get_ctx(&QueueID::Delegated, &message_uuid)
+            .await
+            .unwrap_err();
+        queue_manager
+            .get_ctx(&QueueID::Deliver, &message_uuid)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn delegate_deny() {
+        let config = std::sync::Arc::new(local_test());
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config).unwrap();
+
+        let message_uuid = delegated(&queue_manager).await;
+        let (working_sender, _working_receiver) = tokio::sync::mpsc::channel(10);
+        let (delivery_sender, _delivery_receiver) = tokio::sync::mpsc::channel(10);
+
+        resume_delegated(
+            queue_manager.as_ref(),
+            &working_sender,
+            &delivery_sender,
+            &message_uuid,
+            local_msg(),
+            DelegationVerdict::Deny(either::Left(vsmtp_common::CodeID::Denied)),
+        )
+        .await
+        .unwrap();
+
+        queue_manager
+            .get_ctx(&QueueID::Delegated, &message_uuid)
+            .await
+            .unwrap_err();
+        let ctx = queue_manager
+            .get_ctx(&QueueID::Dead, &message_uuid)
+            .await
+            .unwrap();
+        assert!(ctx
+            .rcpt_to
+            .forward_paths
+            .iter()
+            .all(|rcpt| matches!(rcpt.email_status, EmailTransferStatus::Failed { .. })));
+    }
+
+    #[tokio::test]
+    async fn delegation_socket() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+        let config = std::sync::Arc::new(local_test());
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config).unwrap();
+        let message_uuid = delegated(&queue_manager).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("delegation.sock");
+        let listener = crate::unix_socket_bind_anyhow(&path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        let (working_sender, mut working_receiver) = tokio::sync::mpsc::channel(10);
+        let (delivery_sender, _delivery_receiver) = tokio::sync::mpsc::channel(10);
+        tokio::spawn(serve_delegation(
+            tokio::net::UnixListener::from_std(listener).unwrap(),
+            queue_manager.clone(),
+            working_sender,
+            delivery_sender,
+        ));
+
+        let mut client =
+            tokio::io::BufReader::new(tokio::net::UnixStream::connect(&path).await.unwrap());
+        let mut reply = String::new();
+
+        client
+            .write_all(format!("PULL {message_uuid}\n").as_bytes())
+            .await
+            .unwrap();
+        client.read_line(&mut reply).await.unwrap();
+        let size = reply
+            .strip_prefix("OK ")
+            .and_then(|size| size.trim_end().parse::<usize>().ok())
+            .unwrap();
+        let mut message = vec![0; size];
+        client.read_exact(&mut message).await.unwrap();
+        let message = String::from_utf8(message).unwrap();
+        assert!(message.contains(DELEGATION_HEADER), "{message}");
+
+        let filtered = format!("X-Filtered: yes\r\n{message}");
+        client
+            .write_all(
+                format!("PUSH {message_uuid} resume {}\n{filtered}", filtered.len()).as_bytes(),
+            )
+            .await
+            .unwrap();
+        reply.clear();
+        client.read_line(&mut reply).await.unwrap();
+        assert_eq!(reply, "OK\n");
+
+        let process_message = working_receiver.recv().await.unwrap();
+        assert_eq!(process_message.message_uuid, message_uuid);
+        assert!(process_message.delegated);
+        let stored = queue_manager.get_msg(&message_uuid).await.unwrap();
+        assert_eq!(stored.get_header("X-Filtered").as_deref(), Some("yes"));
+
+        client.write_all(b"PUSH foo resume 0\n").await.unwrap();
+        reply.clear();
+        client.read_line(&mut reply).await.unwrap();
+        assert_eq!(reply, "ERR invalid uuid `foo`\n");
+    }
+}
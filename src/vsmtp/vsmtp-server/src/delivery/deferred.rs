@@ -14,19 +14,24 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::ProcessMessage;
+use crate::{
+    delivery::{fallback_to_maildir, remove_bcc, send_by_transport_jobs},
+    ProcessMessage,
+};
 use anyhow::Context;
-use time::ext::NumericalDuration;
 use vqueue::{GenericQueueManager, QueueID};
 use vsmtp_common::transfer::EmailTransferStatus;
 use vsmtp_config::{Config, DnsResolvers};
-use vsmtp_delivery::{split_and_sort_and_send, Sender, SenderOutcome};
+use vsmtp_delivery::{Sender, SenderOutcome};
+use vsmtp_rule_engine::RuleEngine;
 
 pub async fn flush_deferred_queue<Q: GenericQueueManager + Sized + 'static>(
     config: std::sync::Arc<Config>,
     resolvers: std::sync::Arc<DnsResolvers>,
     queue_manager: std::sync::Arc<Q>,
+    rule_engine: std::sync::Arc<RuleEngine>,
     sender: std::sync::Arc<Sender>,
+    delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
     flushing_at: time::OffsetDateTime,
 ) {
     let queued = match queue_manager.list(&QueueID::Deferred).await {
@@ -58,7 +63,9 @@ pub async fn flush_deferred_queue<Q: GenericQueueManager + Sized + 'static>(
                 message_uuid,
                 delegated: false,
             },
+            rule_engine.clone(),
             sender.clone(),
+            delivery_sender.clone(),
             flushing_at,
         )
         .await
@@ -74,7 +81,9 @@ async fn handle_one_in_deferred_queue<Q: GenericQueueManager + Sized + 'static>(
     resolvers: std::sync::Arc<DnsResolvers>,
     queue_manager: std::sync::Arc<Q>,
     process_message: ProcessMessage,
+    rule_engine: std::sync::Arc<RuleEngine>,
     sender: std::sync::Arc<Sender>,
+    delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
     flushing_at: time::OffsetDateTime,
 ) -> anyhow::Result<()> {
     tracing::debug!("Processing email.");
@@ -93,45 +102,82 @@ async fn handle_one_in_deferred_queue<Q: GenericQueueManager + Sized + 'static>(
         })
         .min();
 
-    let held_back_count = ctx
+    // NOTE: each failed attempt adds an error to the recipients held back.
+    let attempt_count = ctx
         .rcpt_to
         .forward_paths
         .iter()
-        .filter(|i| matches!(i.email_status, EmailTransferStatus::HeldBack { .. }))
-        .count() as i64;
-
-    match last_error {
-        Some(last_error)
-            // last error + (error_count * 5min)
-            if last_error
-                .checked_add(held_back_count.seconds() * 60 * 5)
-                .unwrap()
-                > flushing_at =>
-        {
-            tracing::debug!("Email is not ready to be flushed.");
+        .filter_map(|i| match &i.email_status {
+            EmailTransferStatus::HeldBack { errors } => Some(errors.len()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or_default();
+
+    if let Some(last_error) = last_error {
+        let delay = config
+            .server
+            .queues
+            .delivery
+            .deferred_retry_backoff
+            .delay(u32::try_from(attempt_count).unwrap_or(u32::MAX));
+
+        let next_attempt = time::Duration::try_from(delay)
+            .ok()
+            .and_then(|delay| last_error.checked_add(delay));
+
+        if next_attempt.map_or(true, |next_attempt| next_attempt > flushing_at) {
+            tracing::debug!(?delay, "Email is not ready to be flushed.");
             return Ok(());
         }
-        _ => {}
     }
 
-    let msg = queue_manager.get_msg(&process_message.message_uuid).await?;
+    #[cfg(feature = "metrics")]
+    crate::metrics::METRICS.record_deferred_retry();
 
-    match split_and_sort_and_send(&config, &mut ctx, &msg, resolvers, sender).await {
-        SenderOutcome::MoveToDead => queue_manager
-            .move_to(&QueueID::Deferred, &QueueID::Dead, &ctx)
-            .await
-            .with_context(|| {
-                format!(
-                    "cannot move file from `{}` to `{}`",
-                    QueueID::Deferred,
-                    QueueID::Dead
-                )
-            }),
+    let mut msg = queue_manager.get_msg(&process_message.message_uuid).await?;
+    // NOTE: the message could have been queued before the blind copy headers were removed.
+    remove_bcc(&mut msg);
+
+    match send_by_transport_jobs(
+        &config,
+        queue_manager.as_ref(),
+        &QueueID::Deferred,
+        &mut ctx,
+        &msg,
+        resolvers,
+        sender,
+        &delivery_sender,
+    )
+    .await?
+    {
+        SenderOutcome::MoveToDead => {
+            if fallback_to_maildir(&config, &ctx, &msg).await {
+                return queue_manager
+                    .remove_both(&QueueID::Deferred, &process_message.message_uuid)
+                    .await;
+            }
+
+            queue_manager
+                .move_to(&QueueID::Deferred, &QueueID::Dead, &ctx)
+                .await
+                .with_context(|| {
+                    format!(
+                        "cannot move file from `{}` to `{}`",
+                        QueueID::Deferred,
+                        QueueID::Dead
+                    )
+                })
+        }
         SenderOutcome::MoveToDeferred => queue_manager
             .write_ctx(&QueueID::Deferred, &ctx)
             .await
             .with_context(|| format!("failed to update context in `{}`", QueueID::Deferred)),
         SenderOutcome::RemoveFromDisk => {
+            if let Err(error) = rule_engine.record_greylist_delivery(ctx.connect.client_addr.ip()) {
+                tracing::warn!(%error, "Failed to count the delivery for the greylisting.");
+            }
+
             queue_manager
                 .remove_both(&QueueID::Deferred, &process_message.message_uuid)
                 .await
@@ -165,6 +211,15 @@ mod tests {
             .unwrap();
 
         let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+        let rule_engine = std::sync::Arc::new(
+            RuleEngine::with_hierarchy(
+                config.clone(),
+                |builder| Ok(builder.add_root_filter_rules("#{}")?.build()),
+                resolvers.clone(),
+                queue_manager.clone(),
+            )
+            .unwrap(),
+        );
         let sender = std::sync::Arc::new(Sender::default());
 
         handle_one_in_deferred_queue(
@@ -175,7 +230,9 @@ mod tests {
                 message_uuid,
                 delegated: false,
             },
+            rule_engine,
             sender,
+            tokio::sync::mpsc::channel(10).0,
             time::OffsetDateTime::UNIX_EPOCH,
         )
         .await
@@ -196,6 +253,141 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn not_ready_with_backoff() {
+        let mut config = local_test();
+        config.server.queues.delivery.deferred_retry_backoff =
+            vsmtp_config::field::FieldQueueDeliveryBackoff::Exponential {
+                base: std::time::Duration::from_secs(60 * 60),
+                multiplier: 2,
+                max: std::time::Duration::from_secs(24 * 60 * 60),
+            };
+        let config = std::sync::Arc::new(config);
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+
+        let mut ctx = local_ctx();
+        let message_uuid = uuid::Uuid::new_v4();
+        ctx.mail_from.message_uuid = message_uuid;
+        let mut rcpt =
+            Rcpt::new(<Address as std::str::FromStr>::from_str("test@localhost").unwrap());
+        rcpt.email_status
+            .held_back(vsmtp_common::transfer::TransferErrorsVariant::StillWaiting);
+        ctx.rcpt_to.forward_paths.push(rcpt);
+
+        queue_manager
+            .write_both(&QueueID::Deferred, &ctx, &local_msg())
+            .await
+            .unwrap();
+
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+        let rule_engine = std::sync::Arc::new(
+            RuleEngine::with_hierarchy(
+                config.clone(),
+                |builder| Ok(builder.add_root_filter_rules("#{}")?.build()),
+                resolvers.clone(),
+                queue_manager.clone(),
+            )
+            .unwrap(),
+        );
+        let sender = std::sync::Arc::new(Sender::default());
+
+        // NOTE: the default linear backoff would retry after 5 minutes.
+        handle_one_in_deferred_queue(
+            config.clone(),
+            resolvers,
+            queue_manager.clone(),
+            ProcessMessage {
+                message_uuid,
+                delegated: false,
+            },
+            rule_engine,
+            sender,
+            tokio::sync::mpsc::channel(10).0,
+            time::OffsetDateTime::now_utc() + time::Duration::minutes(10),
+        )
+        .await
+        .unwrap();
+
+        let ctx = queue_manager
+            .get_ctx(&QueueID::Deferred, &message_uuid)
+            .await
+            .unwrap();
+        assert!(matches!(
+            &ctx.rcpt_to.forward_paths[0].email_status,
+            EmailTransferStatus::HeldBack { errors } if errors.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn backoff_from_attempt_count() {
+        let mut config = local_test();
+        config.server.queues.delivery.deferred_retry_backoff =
+            vsmtp_config::field::FieldQueueDeliveryBackoff::Linear {
+                base: std::time::Duration::from_secs(5 * 60),
+            };
+        let config = std::sync::Arc::new(config);
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+
+        let mut ctx = local_ctx();
+        let message_uuid = uuid::Uuid::new_v4();
+        ctx.mail_from.message_uuid = message_uuid;
+        let mut rcpt =
+            Rcpt::new(<Address as std::str::FromStr>::from_str("test@localhost").unwrap());
+        // NOTE: the recipient has been deferred twice.
+        for _ in 0..2 {
+            rcpt.email_status
+                .held_back(vsmtp_common::transfer::TransferErrorsVariant::StillWaiting);
+        }
+        ctx.rcpt_to.forward_paths.push(rcpt);
+
+        queue_manager
+            .write_both(&QueueID::Deferred, &ctx, &local_msg())
+            .await
+            .unwrap();
+
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+        let rule_engine = std::sync::Arc::new(
+            RuleEngine::with_hierarchy(
+                config.clone(),
+                |builder| Ok(builder.add_root_filter_rules("#{}")?.build()),
+                resolvers.clone(),
+                queue_manager.clone(),
+            )
+            .unwrap(),
+        );
+        let sender = std::sync::Arc::new(Sender::default());
+
+        // NOTE: a single attempt would be retried after 5 minutes, the second one after 10.
+        handle_one_in_deferred_queue(
+            config.clone(),
+            resolvers,
+            queue_manager.clone(),
+            ProcessMessage {
+                message_uuid,
+                delegated: false,
+            },
+            rule_engine,
+            sender,
+            tokio::sync::mpsc::channel(10).0,
+            time::OffsetDateTime::now_utc() + time::Duration::minutes(6),
+        )
+        .await
+        .unwrap();
+
+        let ctx = queue_manager
+            .get_ctx(&QueueID::Deferred, &message_uuid)
+            .await
+            .unwrap();
+        assert!(matches!(
+            &ctx.rcpt_to.forward_paths[0].email_status,
+            EmailTransferStatus::HeldBack { errors } if errors.len() == 2
+        ));
+    }
+
     #[tokio::test]
     async fn move_to_dead() {
         let config = std::sync::Arc::new(local_test());
@@ -212,6 +404,15 @@ mod tests {
             .await
             .unwrap();
         let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+        let rule_engine = std::sync::Arc::new(
+            RuleEngine::with_hierarchy(
+                config.clone(),
+                |builder| Ok(builder.add_root_filter_rules("#{}")?.build()),
+                resolvers.clone(),
+                queue_manager.clone(),
+            )
+            .unwrap(),
+        );
         let sender = std::sync::Arc::new(Sender::default());
 
         handle_one_in_deferred_queue(
@@ -222,7 +423,9 @@ mod tests {
                 message_uuid,
                 delegated: false,
             },
+            rule_engine,
             sender,
+            tokio::sync::mpsc::channel(10).0,
             time::OffsetDateTime::UNIX_EPOCH,
         )
         .await
@@ -238,4 +441,81 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    async fn move_to_fallback_mailbox() {
+        let mut config = local_test();
+        let username = users::get_current_username().unwrap();
+        config.server.queues.delivery.dead_fallback_mailbox = Some(
+            format!("{}@localhost", username.to_str().unwrap())
+                .parse()
+                .unwrap(),
+        );
+        let config = std::sync::Arc::new(config);
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+
+        let mut ctx = local_ctx();
+        let message_uuid = uuid::Uuid::new_v4();
+        ctx.mail_from.message_uuid = message_uuid;
+
+        let mut message = local_msg();
+        message.append_header("Message-ID", &format!("<{message_uuid}@localhost>"));
+        queue_manager
+            .write_both(&QueueID::Deferred, &ctx, &message)
+            .await
+            .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+        let rule_engine = std::sync::Arc::new(
+            RuleEngine::with_hierarchy(
+                config.clone(),
+                |builder| Ok(builder.add_root_filter_rules("#{}")?.build()),
+                resolvers.clone(),
+                queue_manager.clone(),
+            )
+            .unwrap(),
+        );
+        let sender = std::sync::Arc::new(Sender::default());
+
+        handle_one_in_deferred_queue(
+            config.clone(),
+            resolvers,
+            queue_manager.clone(),
+            ProcessMessage {
+                message_uuid,
+                delegated: false,
+            },
+            rule_engine,
+            sender,
+            tokio::sync::mpsc::channel(10).0,
+            time::OffsetDateTime::UNIX_EPOCH,
+        )
+        .await
+        .unwrap();
+
+        queue_manager
+            .get_ctx(&QueueID::Deferred, &message_uuid)
+            .await
+            .unwrap_err();
+        queue_manager
+            .get_ctx(&QueueID::Dead, &message_uuid)
+            .await
+            .unwrap_err();
+
+        let home = vsmtp_common::libc_abstraction::getpwuid(users::get_current_uid()).unwrap();
+        let delivered = std::fs::read_dir(home.join("Maildir/new"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                std::fs::read_to_string(path)
+                    .map_or(false, |content| content.contains(&message_uuid.to_string()))
+            })
+            .unwrap();
+        let content = std::fs::read_to_string(&delivered).unwrap();
+        std::fs::remove_file(&delivered).unwrap();
+
+        assert!(content.contains("Subject: Happy new year\r\n"));
+    }
 }
@@ -14,7 +14,14 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::{delegate, delivery::add_trace_information, ProcessMessage};
+use crate::{
+    delegate,
+    delivery::{
+        add_dkim_signatures, add_trace_information, fallback_to_maildir, remove_bcc,
+        send_by_transport_jobs,
+    },
+    ProcessMessage,
+};
 use anyhow::Context;
 use vqueue::{GenericQueueManager, QueueID};
 use vsmtp_common::{
@@ -22,7 +29,7 @@ use vsmtp_common::{
     transfer::{EmailTransferStatus, RuleEngineVariants, TransferErrorsVariant},
 };
 use vsmtp_config::{Config, DnsResolvers};
-use vsmtp_delivery::{split_and_sort_and_send, Sender, SenderOutcome};
+use vsmtp_delivery::{Sender, SenderOutcome};
 use vsmtp_rule_engine::{ExecutionStage, RuleEngine};
 
 pub async fn flush_deliver_queue<Q: GenericQueueManager + Sized + 'static>(
@@ -31,6 +38,7 @@ pub async fn flush_deliver_queue<Q: GenericQueueManager + Sized + 'static>(
     queue_manager: std::sync::Arc<Q>,
     rule_engine: std::sync::Arc<RuleEngine>,
     sender: std::sync::Arc<Sender>,
+    delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
 ) {
     // FIXME: add span on the function.
     tracing::info!("Flushing deliver queue.");
@@ -69,6 +77,7 @@ pub async fn flush_deliver_queue<Q: GenericQueueManager + Sized + 'static>(
             },
             rule_engine.clone(),
             sender.clone(),
+            delivery_sender.clone(),
         )
         .await;
     }
@@ -93,6 +102,7 @@ pub async fn handle_one_in_delivery_queue<Q: GenericQueueManager + Sized + 'stat
     process_message: ProcessMessage,
     rule_engine: std::sync::Arc<RuleEngine>,
     sender: std::sync::Arc<Sender>,
+    delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
 ) -> anyhow::Result<()> {
     let queue = if process_message.delegated {
         QueueID::Delegated
@@ -173,10 +183,31 @@ pub async fn handle_one_in_delivery_queue<Q: GenericQueueManager + Sized + 'stat
         None => {}
     };
 
-    add_trace_information(&ctx, &mut mail_message, &result)?;
+    remove_bcc(&mut mail_message);
+    add_trace_information(&config, &ctx, &mut mail_message, &result)?;
+    if let Err(error) = add_dkim_signatures(&config, &ctx, &mut mail_message) {
+        tracing::error!(%error, "Failed to sign the message, sending it unsigned.");
+    }
 
-    match split_and_sort_and_send(&config, &mut ctx, &mail_message, resolvers, sender).await {
+    match send_by_transport_jobs(
+        &config,
+        queue_manager.as_ref(),
+        &queue,
+        &mut ctx,
+        &mail_message,
+        resolvers,
+        sender,
+        &delivery_sender,
+    )
+    .await?
+    {
         SenderOutcome::MoveToDead => {
+            if fallback_to_maildir(&config, &ctx, &mail_message).await {
+                return queue_manager
+                    .remove_both(&queue, &process_message.message_uuid)
+                    .await;
+            }
+
             queue_manager.move_to(&queue, &QueueID::Dead, &ctx).await?;
 
             queue_manager
@@ -193,6 +224,10 @@ pub async fn handle_one_in_delivery_queue<Q: GenericQueueManager + Sized + 'stat
                 .await
         }
         SenderOutcome::RemoveFromDisk => {
+            if let Err(error) = rule_engine.record_greylist_delivery(ctx.connect.client_addr.ip()) {
+                tracing::warn!(%error, "Failed to count the delivery for the greylisting.");
+            }
+
             queue_manager
                 .remove_both(&queue, &process_message.message_uuid)
                 .await
@@ -245,6 +280,7 @@ mod tests {
                 .unwrap(),
             ),
             sender,
+            tokio::sync::mpsc::channel(10).0,
         )
         .await
         .unwrap();
@@ -303,6 +339,7 @@ mod tests {
                 .unwrap(),
             ),
             sender,
+            tokio::sync::mpsc::channel(10).0,
         )
         .await
         .unwrap();
@@ -322,4 +359,93 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bcc_removed() {
+        let config = std::sync::Arc::new(local_test());
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+
+        let username = users::get_current_username().unwrap();
+        let mut rcpt = Rcpt::new(
+            format!("{}@localhost", username.to_str().unwrap())
+                .parse()
+                .unwrap(),
+        );
+        rcpt.transfer_method = vsmtp_common::transfer::Transfer::Maildir;
+
+        let mut ctx = local_ctx();
+        let message_uuid = uuid::Uuid::new_v4();
+        ctx.mail_from.message_uuid = message_uuid;
+        ctx.rcpt_to.forward_paths.push(rcpt);
+        ctx.rcpt_to.forward_paths.push(Rcpt::new(
+            <Address as std::str::FromStr>::from_str("test@foobar.com").unwrap(),
+        ));
+
+        let mut message = local_msg();
+        message.append_header("Bcc", "hidden@example.com");
+        message.append_header("Resent-Bcc", "resent.hidden@example.com");
+        message.append_header("Bcc", "another.hidden@example.com");
+        message.append_header("Message-ID", &format!("<{message_uuid}@localhost>"));
+
+        queue_manager
+            .write_both(&QueueID::Deliver, &ctx, &message)
+            .await
+            .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+        let sender = std::sync::Arc::new(Sender::default());
+
+        handle_one_in_delivery_queue(
+            config.clone(),
+            resolvers.clone(),
+            queue_manager.clone(),
+            ProcessMessage {
+                message_uuid,
+                delegated: false,
+            },
+            std::sync::Arc::new(
+                RuleEngine::with_hierarchy(
+                    config.clone(),
+                    |builder| Ok(builder.add_root_filter_rules("#{}")?.build()),
+                    resolvers,
+                    queue_manager.clone(),
+                )
+                .unwrap(),
+            ),
+            sender,
+            tokio::sync::mpsc::channel(10).0,
+        )
+        .await
+        .unwrap();
+
+        queue_manager
+            .get_ctx(&QueueID::Deliver, &message_uuid)
+            .await
+            .unwrap_err();
+
+        let home = vsmtp_common::libc_abstraction::getpwuid(users::get_current_uid()).unwrap();
+        let delivered = std::fs::read_dir(home.join("Maildir/new"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                std::fs::read_to_string(path)
+                    .map_or(false, |content| content.contains(&message_uuid.to_string()))
+            })
+            .unwrap();
+        let content = std::fs::read_to_string(&delivered).unwrap();
+        std::fs::remove_file(&delivered).unwrap();
+
+        assert!(content.contains("Subject: Happy new year\r\n"));
+        assert!(!content.contains("hidden@example.com"));
+
+        // NOTE: the copy kept for the recipient held back is stripped too.
+        queue_manager
+            .get_ctx(&QueueID::Deferred, &message_uuid)
+            .await
+            .unwrap();
+        let deferred = queue_manager.get_msg(&message_uuid).await.unwrap();
+        assert_eq!(deferred.get_header("Bcc"), None);
+        assert_eq!(deferred.get_header("Resent-Bcc"), None);
+    }
 }
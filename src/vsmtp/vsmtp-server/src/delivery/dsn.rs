@@ -0,0 +1,537 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+//! Delivery status notifications, see RFC 3461 and RFC 3464.
+
+use crate::ProcessMessage;
+use anyhow::Context;
+use std::fmt::Write;
+use time::format_description::well_known::Rfc2822;
+use vqueue::{GenericQueueManager, QueueID};
+use vsmtp_common::{
+    rcpt::Rcpt,
+    transfer::{DsnReturn, EmailTransferStatus, NotifyOn, TransferErrorsVariant},
+    ClientName, ConnectProperties, ContextFinished, FinishedProperties, HeloProperties,
+    MailFromProperties, RcptToProperties, TransactionType,
+};
+use vsmtp_config::Config;
+use vsmtp_mail_parser::MessageBody;
+
+/// Outcome of the delivery of a recipient reported in a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub(super) enum Action {
+    /// Delivered by a local transport.
+    Delivered,
+    /// Handed off to a remote server, which does not receive the DSN parameters.
+    Relayed,
+    /// Not delivered yet, the delivery is retried.
+    Delayed,
+    /// Not delivered, the delivery is abandoned.
+    Failed,
+}
+
+/// The enhanced status code (RFC 3463) reported for `rcpt`, taken from the last
+/// error of its delivery.
+fn status(rcpt: &Rcpt, action: Action) -> String {
+    let (class, error) = match (&rcpt.email_status, action) {
+        (_, Action::Delivered | Action::Relayed) => return "2.0.0".to_owned(),
+        (EmailTransferStatus::HeldBack { errors }, Action::Delayed) => {
+            ('4', errors.last().map(|error| &error.variant))
+        }
+        (EmailTransferStatus::Failed { error }, Action::Failed) => ('5', Some(&error.variant)),
+        (_, Action::Delayed) => ('4', None),
+        (_, Action::Failed) => ('5', None),
+    };
+
+    format!(
+        "{class}.{}",
+        error.map_or_else(|| "0.0".to_owned(), subject_detail)
+    )
+}
+
+/// The subject and detail of the enhanced status code of `error`, the class being
+/// the one of the action reported.
+fn subject_detail(error: &TransferErrorsVariant) -> String {
+    let subject_detail = match error {
+        TransferErrorsVariant::Smtp { error, .. } => match find_subject_detail(error) {
+            Some(subject_detail) => return subject_detail,
+            None => "0.0",
+        },
+        TransferErrorsVariant::NoSuchMailbox { .. } => "1.1",
+        TransferErrorsVariant::NoSuchDomain { .. } => "1.2",
+        TransferErrorsVariant::EnvelopIllFormed { .. } => "1.3",
+        TransferErrorsVariant::HasNullMX { .. } => "1.10",
+        TransferErrorsVariant::MailboxFull { .. } => "2.2",
+        TransferErrorsVariant::LocalDeliveryError { .. } => "3.0",
+        TransferErrorsVariant::DeliveryError { .. } => "4.1",
+        TransferErrorsVariant::DnsRecord { .. } => "4.3",
+        TransferErrorsVariant::NoRoute { .. } | TransferErrorsVariant::MxIsAlias { .. } => "4.4",
+        TransferErrorsVariant::MailLoop { .. } => "4.6",
+        TransferErrorsVariant::TimeBudgetExhausted
+        | TransferErrorsVariant::MaxDeferredAttemptReached => "4.7",
+        TransferErrorsVariant::RuleEngine(..) => "7.1",
+        TransferErrorsVariant::TlsNoCertificate { .. }
+        | TransferErrorsVariant::TlsaMismatch { .. }
+        | TransferErrorsVariant::MtaSts { .. } => "7.5",
+        TransferErrorsVariant::RequireTls { .. } => "7.10",
+        TransferErrorsVariant::StillWaiting => "0.0",
+    };
+    subject_detail.to_owned()
+}
+
+/// Find the subject and detail of an enhanced status code (`class.subject.detail`)
+/// in the error returned by a remote server.
+fn find_subject_detail(error: &str) -> Option<String> {
+    let is_number = |part: &str| {
+        !part.is_empty() && part.len() <= 3 && part.bytes().all(|b| b.is_ascii_digit())
+    };
+
+    error
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .map(|token| token.trim_end_matches('.'))
+        .find_map(|token| {
+            let mut parts = token.split('.');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some("2" | "4" | "5"), Some(subject), Some(detail), None)
+                    if is_number(subject) && is_number(detail) =>
+                {
+                    Some(format!("{subject}.{detail}"))
+                }
+                _ => None,
+            }
+        })
+}
+
+/// Compare the status of the recipients before and after a delivery attempt,
+/// and produce the ones which requested to be notified of their new status.
+///
+/// A delay is notified only once, on the first failed attempt.
+pub(super) fn recipients_to_notify<'rcpt>(
+    before: &[Rcpt],
+    after: &'rcpt [Rcpt],
+) -> Vec<(&'rcpt Rcpt, Action)> {
+    after
+        .iter()
+        .filter_map(|rcpt| {
+            let previous = before
+                .iter()
+                .find(|i| i.address == rcpt.address)
+                .map(|i| &i.email_status);
+
+            let action = match (&rcpt.email_status, previous) {
+                (EmailTransferStatus::Sent { .. }, Some(EmailTransferStatus::Sent { .. }))
+                | (EmailTransferStatus::Failed { .. }, Some(EmailTransferStatus::Failed { .. })) => {
+                    None
+                }
+                (EmailTransferStatus::Sent { .. }, _) if rcpt.transfer_method.is_local() => {
+                    Some(Action::Delivered)
+                }
+                (EmailTransferStatus::Sent { .. }, _) => Some(Action::Relayed),
+                (EmailTransferStatus::Failed { .. }, _) => Some(Action::Failed),
+                (EmailTransferStatus::HeldBack { errors }, _) if errors.len() == 1 => {
+                    Some(Action::Delayed)
+                }
+                _ => None,
+            }?;
+
+            let NotifyOn {
+                success,
+                failure,
+                delay,
+            } = rcpt.notify_on;
+            match action {
+                Action::Delivered | Action::Relayed if success => Some((rcpt, action)),
+                Action::Delayed if delay => Some((rcpt, action)),
+                Action::Failed if failure => Some((rcpt, action)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Build the notification sent back to the reverse path of `ctx`.
+///
+/// Return `None` if there is nothing to notify, or if the reverse path is null.
+pub(super) fn build(
+    config: &Config,
+    ctx: &ContextFinished,
+    message: &MessageBody,
+    recipients: &[(&Rcpt, Action)],
+) -> anyhow::Result<Option<(ContextFinished, MessageBody)>> {
+    let reverse_path = match &ctx.mail_from.reverse_path {
+        Some(reverse_path) if !recipients.is_empty() => reverse_path.clone(),
+        _ => return Ok(None),
+    };
+
+    let now = time::OffsetDateTime::now_utc();
+    let message_uuid = uuid::Uuid::new_v4();
+    let server_name = &config.server.name;
+    let boundary = uuid::Uuid::new_v4().simple().to_string();
+
+    let headers = vec![
+        format!("From: Mail Delivery System <MAILER-DAEMON@{server_name}>\r\n"),
+        format!("To: <{reverse_path}>\r\n"),
+        "Subject: Delivery Status Notification\r\n".to_owned(),
+        format!("Date: {}\r\n", now.format(&Rfc2822)?),
+        format!("Message-ID: <{message_uuid}@{server_name}>\r\n"),
+        "Auto-Submitted: auto-replied\r\n".to_owned(),
+        "MIME-Version: 1.0\r\n".to_owned(),
+        format!(
+            "Content-Type: multipart/report; report-type=delivery-status; boundary=\"{boundary}\"\r\n"
+        ),
+    ];
+
+    let mut body = String::new();
+    write!(
+        body,
+        "--{boundary}\r\nContent-Type: text/plain; charset=us-ascii\r\n\r\nThis is the mail system at host {server_name}.\r\n\r\n"
+    )?;
+    for (rcpt, action) in recipients {
+        match (&rcpt.email_status, action) {
+            (EmailTransferStatus::Failed { error }, Action::Failed) => {
+                write!(body, "<{}>: failed ({})\r\n", rcpt.address, error.variant)?
+            }
+            (_, Action::Delayed) => write!(
+                body,
+                "<{}>: delayed, the delivery will be retried\r\n",
+                rcpt.address
+            )?,
+            _ => write!(body, "<{}>: {}\r\n", rcpt.address, action.as_ref())?,
+        }
+    }
+
+    write!(
+        body,
+        "\r\n--{boundary}\r\nContent-Type: message/delivery-status\r\n\r\nReporting-MTA: dns; {server_name}\r\n"
+    )?;
+    if let Some(envelop_id) = &ctx.mail_from.envelop_id {
+        write!(body, "Original-Envelope-Id: {envelop_id}\r\n")?;
+    }
+    write!(
+        body,
+        "Arrival-Date: {}\r\n",
+        ctx.mail_from.mail_timestamp.format(&Rfc2822)?
+    )?;
+    for (rcpt, action) in recipients {
+        body.push_str("\r\n");
+        if let Some(original) = &rcpt.original_forward_path {
+            write!(
+                body,
+                "Original-Recipient: {};{}\r\n",
+                original.addr_type, original.mailbox
+            )?;
+        }
+        write!(
+            body,
+            "Final-Recipient: rfc822; {}\r\nAction: {}\r\nStatus: {}\r\n",
+            rcpt.address,
+            action.as_ref(),
+            status(rcpt, *action)
+        )?;
+    }
+
+    match ctx.mail_from.dsn_return.unwrap_or_default() {
+        DsnReturn::Full => write!(
+            body,
+            "\r\n--{boundary}\r\nContent-Type: message/rfc822\r\n\r\n{}",
+            message.inner()
+        )?,
+        DsnReturn::Headers => write!(
+            body,
+            "\r\n--{boundary}\r\nContent-Type: text/rfc822-headers\r\n\r\n{}",
+            message.inner().headers_lines().collect::<String>()
+        )?,
+    }
+    write!(body, "\r\n--{boundary}--\r\n")?;
+
+    // NOTE: the notification is a new message of the server, nothing of the
+    //       transaction of the original message applies to it.
+    let dsn_ctx = ContextFinished {
+        connect: ConnectProperties {
+            connect_timestamp: now,
+            connect_uuid: uuid::Uuid::new_v4(),
+            client_addr: ctx.connect.server_addr,
+            server_addr: ctx.connect.server_addr,
+            server_name: server_name.clone(),
+            skipped: None,
+            tls: None,
+            auth: None,
+        },
+        helo: HeloProperties {
+            client_name: ClientName::Domain(server_name.clone()),
+            using_deprecated: false,
+        },
+        mail_from: MailFromProperties {
+            reverse_path: None,
+            mail_timestamp: now,
+            message_uuid,
+            dsn_return: None,
+            envelop_id: None,
+            use_smtputf8: !reverse_path.full().is_ascii(),
+            auth_mailbox: None,
+            require_tls: false,
+        },
+        rcpt_to: RcptToProperties {
+            forward_paths: vec![{
+                let mut rcpt = Rcpt::new(reverse_path);
+                // NOTE: a notification must never produce another one.
+                rcpt.notify_on = NotifyOn::never();
+                rcpt
+            }],
+            transaction_type: TransactionType::Outgoing {
+                domain: server_name.clone(),
+            },
+        },
+        finished: FinishedProperties {
+            dkim: None,
+            spf: None,
+        },
+    };
+
+    Ok(Some((dsn_ctx, MessageBody::new(headers, body))))
+}
+
+/// Build the notification of the recipients whose status changed between `before`
+/// and `ctx`, and write it in the deliver queue, to be sent with `delivery_sender`.
+///
+/// If the channel is full, the notification is moved to the deferred queue instead.
+pub(super) async fn enqueue<Q: GenericQueueManager + Sized + 'static>(
+    config: &Config,
+    queue_manager: &Q,
+    delivery_sender: &tokio::sync::mpsc::Sender<ProcessMessage>,
+    before: &[Rcpt],
+    ctx: &ContextFinished,
+    message: &MessageBody,
+) -> anyhow::Result<()> {
+    let recipients = recipients_to_notify(before, &ctx.rcpt_to.forward_paths);
+
+    if let Some((dsn_ctx, dsn_message)) = build(config, ctx, message, &recipients)? {
+        tracing::info!(
+            uuid = %dsn_ctx.mail_from.message_uuid,
+            "Delivery status notification generated."
+        );
+
+        queue_manager
+            .write_both(&QueueID::Deliver, &dsn_ctx, &dsn_message)
+            .await
+            .context("failed to write the delivery status notification")?;
+
+        if let Err(error) = delivery_sender.try_send(ProcessMessage {
+            message_uuid: dsn_ctx.mail_from.message_uuid,
+            delegated: false,
+        }) {
+            tracing::warn!(%error, "Delivery status notification deferred.");
+            queue_manager
+                .move_to(&QueueID::Deliver, &QueueID::Deferred, &dsn_ctx)
+                .await
+                .context("failed to defer the delivery status notification")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_common::{
+        addr,
+        transfer::{OriginalRecipient, Transfer},
+    };
+    use vsmtp_test::config::{local_ctx, local_msg, local_test};
+
+    fn rcpt(address: &str, notify_on: NotifyOn, email_status: EmailTransferStatus) -> Rcpt {
+        let mut rcpt = Rcpt::new(addr!(address));
+        rcpt.notify_on = notify_on;
+        rcpt.email_status = email_status;
+        rcpt
+    }
+
+    const ALL: NotifyOn = NotifyOn {
+        success: true,
+        failure: true,
+        delay: true,
+    };
+
+    #[test]
+    fn notify_new_status_only() {
+        let mut held_back = EmailTransferStatus::default();
+        held_back.held_back(TransferErrorsVariant::StillWaiting);
+        let mut held_back_twice = held_back.clone();
+        held_back_twice.held_back(TransferErrorsVariant::StillWaiting);
+
+        let local = |mut rcpt: Rcpt| {
+            rcpt.transfer_method = Transfer::Maildir;
+            rcpt
+        };
+
+        let before = vec![
+            rcpt("sent@foo", ALL, EmailTransferStatus::default()),
+            local(rcpt("local@foo", ALL, EmailTransferStatus::default())),
+            rcpt("already.sent@foo", ALL, EmailTransferStatus::sent()),
+            rcpt("delayed@foo", ALL, EmailTransferStatus::default()),
+            rcpt("delayed.again@foo", ALL, held_back.clone()),
+            rcpt("failed@foo", ALL, held_back.clone()),
+            rcpt(
+                "never@foo",
+                NotifyOn::never(),
+                EmailTransferStatus::default(),
+            ),
+            rcpt(
+                "default@foo",
+                NotifyOn::default(),
+                EmailTransferStatus::default(),
+            ),
+        ];
+        let failed = EmailTransferStatus::failed(TransferErrorsVariant::MaxDeferredAttemptReached);
+        let after = vec![
+            rcpt("sent@foo", ALL, EmailTransferStatus::sent()),
+            local(rcpt("local@foo", ALL, EmailTransferStatus::sent())),
+            rcpt("already.sent@foo", ALL, EmailTransferStatus::sent()),
+            rcpt("delayed@foo", ALL, held_back.clone()),
+            rcpt("delayed.again@foo", ALL, held_back_twice),
+            rcpt("failed@foo", ALL, failed.clone()),
+            rcpt("never@foo", NotifyOn::never(), failed.clone()),
+            rcpt("default@foo", NotifyOn::default(), held_back),
+        ];
+
+        assert_eq!(
+            recipients_to_notify(&before, &after)
+                .into_iter()
+                .map(|(rcpt, action)| (rcpt.address.to_string(), action))
+                .collect::<Vec<_>>(),
+            vec![
+                ("sent@foo".to_owned(), Action::Relayed),
+                ("local@foo".to_owned(), Action::Delivered),
+                ("delayed@foo".to_owned(), Action::Delayed),
+                ("failed@foo".to_owned(), Action::Failed),
+            ]
+        );
+    }
+
+    #[test]
+    fn status_of_the_error() {
+        let mut held_back = EmailTransferStatus::default();
+        held_back.held_back(TransferErrorsVariant::Smtp {
+            error: "permanent error (452): 4.2.2 mailbox full".to_owned(),
+            transcript: vec![],
+        });
+        held_back.held_back(TransferErrorsVariant::DeliveryError { targets: vec![] });
+
+        for (email_status, action, expected) in [
+            (EmailTransferStatus::sent(), Action::Relayed, "2.0.0"),
+            (held_back, Action::Delayed, "4.4.1"),
+            (
+                EmailTransferStatus::failed(TransferErrorsVariant::Smtp {
+                    error: "permanent error (550): 5.1.1 <john@doe>: user unknown".to_owned(),
+                    transcript: vec![],
+                }),
+                Action::Failed,
+                "5.1.1",
+            ),
+            (
+                EmailTransferStatus::failed(TransferErrorsVariant::Smtp {
+                    error: "connection refused".to_owned(),
+                    transcript: vec![],
+                }),
+                Action::Failed,
+                "5.0.0",
+            ),
+            (
+                EmailTransferStatus::failed(TransferErrorsVariant::NoSuchDomain {
+                    domain: "doe".to_owned(),
+                }),
+                Action::Failed,
+                "5.1.2",
+            ),
+        ] {
+            assert_eq!(
+                status(&rcpt("john@doe", ALL, email_status), action),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn failure_report() {
+        let config = local_test();
+        let mut ctx = local_ctx();
+        ctx.mail_from.envelop_id = Some("QQ314159".to_owned());
+        ctx.mail_from.require_tls = true;
+
+        let mut failed = rcpt(
+            "john.doe@example.com",
+            NotifyOn::default(),
+            EmailTransferStatus::failed(TransferErrorsVariant::MaxDeferredAttemptReached),
+        );
+        failed.original_forward_path = Some(OriginalRecipient {
+            addr_type: "rfc822".to_owned(),
+            mailbox: "john@example.com".to_owned(),
+        });
+
+        let (dsn_ctx, dsn_message) =
+            build(&config, &ctx, &local_msg(), &[(&failed, Action::Failed)])
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(dsn_ctx.mail_from.reverse_path, None);
+        assert_eq!(dsn_ctx.mail_from.envelop_id, None);
+        assert!(!dsn_ctx.mail_from.require_tls);
+        assert_ne!(dsn_ctx.connect.connect_uuid, ctx.connect.connect_uuid);
+        assert_eq!(
+            dsn_ctx.rcpt_to.forward_paths,
+            vec![{
+                let mut rcpt = Rcpt::new(addr!("client@client.testserver.com"));
+                rcpt.notify_on = NotifyOn::never();
+                rcpt
+            }]
+        );
+
+        let dsn = dsn_message.inner().to_string();
+        for expected in [
+            "To: <client@client.testserver.com>\r\n",
+            "Content-Type: multipart/report; report-type=delivery-status;",
+            "Reporting-MTA: dns; testserver.com\r\n",
+            "Original-Envelope-Id: QQ314159\r\n",
+            "\r\nOriginal-Recipient: rfc822;john@example.com\r\nFinal-Recipient: rfc822; john.doe@example.com\r\nAction: failed\r\nStatus: 5.4.7\r\n",
+            "Content-Type: text/rfc822-headers\r\n\r\nFrom: NoBody <nobody@domain.tld>\r\n",
+        ] {
+            assert!(dsn.contains(expected), "{expected:?} not found in {dsn}");
+        }
+        assert!(!dsn.contains("Be happy!"));
+    }
+
+    #[test]
+    fn null_reverse_path() {
+        let config = local_test();
+        let mut ctx = local_ctx();
+        ctx.mail_from.reverse_path = None;
+
+        let failed = rcpt(
+            "john.doe@example.com",
+            NotifyOn::default(),
+            EmailTransferStatus::failed(TransferErrorsVariant::MaxDeferredAttemptReached),
+        );
+
+        assert!(
+            build(&config, &ctx, &local_msg(), &[(&failed, Action::Failed)])
+                .unwrap()
+                .is_none()
+        );
+    }
+}
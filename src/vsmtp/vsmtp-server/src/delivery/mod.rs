@@ -23,24 +23,39 @@ use crate::{
 };
 use anyhow::Context;
 use time::format_description::well_known::Rfc2822;
-use vqueue::GenericQueueManager;
+use tokio_stream::StreamExt;
+use vqueue::{GenericQueueManager, QueueID};
+use vsmtp_common::rcpt::Rcpt;
 use vsmtp_common::status::Status;
+use vsmtp_common::transfer::EmailTransferStatus;
 use vsmtp_common::ContextFinished;
-use vsmtp_config::{Config, DnsResolvers};
-use vsmtp_delivery::Sender;
+use vsmtp_config::{field::FieldQueueDeliveryXVsmtp, Config, DnsResolvers};
+use vsmtp_delivery::{
+    outcome_of, send_by_transport, split_by_transport,
+    transport::{Maildir, Transport},
+    Sender, SenderOutcome,
+};
 use vsmtp_mail_parser::MessageBody;
 use vsmtp_rule_engine::RuleEngine;
 
 mod deferred;
 mod deliver;
+mod dsn;
 
+/// Deliver the messages received on `delivery_receiver`, and flush the `deferred`
+/// queue periodically.
+///
+/// Once `drain` is set to `true`, no more delivery is started, and this function
+/// returns when the ones in progress are over.
 pub async fn start<Q: GenericQueueManager + Sized + 'static>(
     config: std::sync::Arc<Config>,
     rule_engine: std::sync::Arc<RuleEngine>,
     resolvers: std::sync::Arc<DnsResolvers>,
     queue_manager: std::sync::Arc<Q>,
     mut delivery_receiver: tokio::sync::mpsc::Receiver<ProcessMessage>,
+    delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
     sender: std::sync::Arc<Sender>,
+    mut drain: tokio::sync::watch::Receiver<bool>,
 ) {
     flush_deliver_queue(
         config.clone(),
@@ -48,58 +63,300 @@ pub async fn start<Q: GenericQueueManager + Sized + 'static>(
         queue_manager.clone(),
         rule_engine.clone(),
         sender.clone(),
+        delivery_sender.clone(),
     )
     .await;
 
     let mut flush_deferred_interval =
         tokio::time::interval(config.server.queues.delivery.deferred_retry_period);
+    let mut tasks = tokio::task::JoinSet::new();
 
     loop {
         tokio::select! {
             Some(pm) = delivery_receiver.recv() => {
-                tokio::spawn(
-                    handle_one_in_delivery_queue(
-                        config.clone(),
-                        resolvers.clone(),
-                        queue_manager.clone(),
+                let (config, resolvers, queue_manager, rule_engine, sender, delivery_sender) = (
+                    config.clone(),
+                    resolvers.clone(),
+                    queue_manager.clone(),
+                    rule_engine.clone(),
+                    sender.clone(),
+                    delivery_sender.clone(),
+                );
+                tasks.spawn(async move {
+                    let _err = handle_one_in_delivery_queue(
+                        config,
+                        resolvers,
+                        queue_manager,
                         pm,
-                        rule_engine.clone(),
-                        sender.clone(),
+                        rule_engine,
+                        sender,
+                        delivery_sender,
                     )
-                );
+                    .await;
+                });
             }
             _ = flush_deferred_interval.tick() => {
                 tracing::info!("cronjob delay elapsed `{}s`, flushing queue.",
                     config.server.queues.delivery.deferred_retry_period.as_secs());
-                tokio::spawn(
+                tasks.spawn(
                     flush_deferred_queue(
                         config.clone(),
                         resolvers.clone(),
                         queue_manager.clone(),
+                        rule_engine.clone(),
                         sender.clone(),
+                        delivery_sender.clone(),
                         time::OffsetDateTime::now_utc(),
                     )
                 );
             }
+            Some(_) = tasks.join_next(), if !tasks.is_empty() => {}
+            () = crate::drained(&mut drain) => break,
         };
     }
+
+    tracing::info!(
+        deliveries = tasks.len(),
+        "Draining, waiting for the deliveries in progress."
+    );
+    while tasks.join_next().await.is_some() {}
+    tracing::info!("All deliveries are over.");
+}
+
+/// Split the message in one job per transport and run them concurrently.
+///
+/// The status of the recipients of each job is written to `queue` as soon as
+/// the job is over, so a crash while a transport is still running does not
+/// make the recipients already handled by another transport being sent twice.
+///
+/// If the status of a job cannot be written, the whole context is written again once
+/// all the jobs are over, and the error is returned if it fails again.
+///
+/// The delivery status notification is then written in the deliver queue,
+/// and the error is returned if it cannot be.
+#[tracing::instrument(name = "send", skip_all)]
+async fn send_by_transport_jobs<Q: GenericQueueManager + Sized + 'static>(
+    config: &Config,
+    queue_manager: &Q,
+    queue: &QueueID,
+    ctx: &mut ContextFinished,
+    message: &MessageBody,
+    resolvers: std::sync::Arc<DnsResolvers>,
+    sender: std::sync::Arc<Sender>,
+    delivery_sender: &tokio::sync::mpsc::Sender<ProcessMessage>,
+) -> anyhow::Result<SenderOutcome> {
+    let jobs = split_by_transport(ctx);
+
+    if jobs.is_empty() {
+        tracing::warn!("No recipients to send to.");
+        return Ok(SenderOutcome::MoveToDead);
+    }
+
+    // NOTE: the context must be on disk before any job update its recipients.
+    queue_manager.write_ctx(queue, ctx).await?;
+
+    let before = ctx.rcpt_to.forward_paths.clone();
+    let message_content = message.inner().to_string();
+    let relay_content = relay_content(config, message);
+    let ctx_ref = &*ctx;
+
+    let mut pending = jobs
+        .into_iter()
+        .map(|job| {
+            let content = match &relay_content {
+                Some(relay_content) if !job.0.is_local() => relay_content,
+                _ => &message_content,
+            };
+            send_by_transport(config, ctx_ref, content, job, &resolvers, &sender)
+        })
+        .collect::<futures_util::stream::FuturesUnordered<_>>();
+
+    let mut forward_paths = vec![];
+    let mut persisted = true;
+    while let Some(rcpt) = pending.next().await {
+        if let Err(error) = queue_manager
+            .update_rcpt_status(queue, &ctx_ref.mail_from.message_uuid, &rcpt)
+            .await
+        {
+            tracing::error!(%error, "Failed to persist the status of a transport job.");
+            persisted = false;
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::METRICS.record_delivery(&rcpt);
+        forward_paths.extend(rcpt);
+    }
+    drop(pending);
+
+    ctx.rcpt_to.forward_paths = forward_paths;
+
+    if !persisted {
+        queue_manager
+            .write_ctx(queue, ctx)
+            .await
+            .context("failed to persist the status of the recipients")?;
+    }
+
+    let outcome = outcome_of(config, ctx);
+
+    dsn::enqueue(
+        config,
+        queue_manager,
+        delivery_sender,
+        &before,
+        ctx,
+        message,
+    )
+    .await
+    .context("failed to enqueue the delivery status notification")?;
+
+    Ok(outcome)
+}
+
+/// Deliver the message in the maildir of the `dead_fallback_mailbox`, if configured.
+///
+/// Return `true` if the message has been delivered, and can be removed from the queues
+/// instead of being moved to the `dead` queue.
+async fn fallback_to_maildir(
+    config: &Config,
+    ctx: &ContextFinished,
+    message: &MessageBody,
+) -> bool {
+    let mailbox = match &config.server.queues.delivery.dead_fallback_mailbox {
+        Some(mailbox) => mailbox,
+        None => return false,
+    };
+
+    let rcpt = Maildir::default()
+        .deliver(
+            config,
+            ctx,
+            &ctx.mail_from.reverse_path,
+            vec![Rcpt::new(mailbox.clone())],
+            &message.inner().to_string(),
+        )
+        .await;
+
+    match rcpt.first().map(|rcpt| &rcpt.email_status) {
+        Some(EmailTransferStatus::Sent { .. }) => {
+            tracing::info!(%mailbox, "Undeliverable email moved to the fallback mailbox.");
+            true
+        }
+        status => {
+            tracing::error!(%mailbox, ?status, "Fallback to the maildir failed, moving to dead.");
+            false
+        }
+    }
+}
+
+/// Remove the `Bcc` and `Resent-Bcc` headers, so the recipients of the envelope do not
+/// see who else received a blind copy of the message (RFC 5322 3.6.3).
+fn remove_bcc(message: &mut MessageBody) {
+    for header in ["Bcc", "Resent-Bcc"] {
+        while message.remove_header(header) {}
+    }
+}
+
+/// Prepend a `DKIM-Signature` header for each key configured in `app.dkim` for the
+/// domain of the `From` header, or else for the domain of the sender.
+///
+/// The message is left unsigned if none of those domains has a key.
+///
+/// Must be called after [`add_trace_information`], the trace headers are not signed.
+fn add_dkim_signatures(
+    config: &Config,
+    ctx: &ContextFinished,
+    message: &mut MessageBody,
+) -> anyhow::Result<()> {
+    let signers_of = |domain: &str| {
+        config
+            .app
+            .dkim
+            .iter()
+            .filter(|signer| signer.domain.eq_ignore_ascii_case(domain))
+            .collect::<Vec<_>>()
+    };
+
+    let from_domain = message
+        .header_from()
+        .first()
+        .and_then(|from| from.rsplit_once('@').map(|(_, domain)| domain.to_string()));
+    let signers = Some(from_domain.as_deref().map(signers_of).unwrap_or_default())
+        .filter(|signers| !signers.is_empty())
+        .or_else(|| {
+            ctx.mail_from
+                .reverse_path
+                .as_ref()
+                .map(|reverse_path| signers_of(reverse_path.domain()))
+        })
+        .unwrap_or_default();
+
+    if signers.is_empty() {
+        tracing::debug!(
+            from = ?from_domain,
+            sender = ?ctx.mail_from.reverse_path.as_ref().map(vsmtp_common::Address::domain),
+            "No DKIM key for the domains of the message, sending it unsigned."
+        );
+        return Ok(());
+    }
+
+    for signer in signers {
+        let signature = vsmtp_auth::dkim::sign(
+            message.inner(),
+            &signer.private_key.inner,
+            signer.domain.clone(),
+            signer.selector.clone(),
+            signer.canonicalization,
+            signer.headers_field.clone(),
+        )
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "failed to sign the message with the selector '{}' of '{}': {e}",
+                signer.selector,
+                signer.domain
+            )
+        })?;
+
+        message.prepend_header("DKIM-Signature", &signature.get_signature_value());
+    }
+
+    Ok(())
+}
+
+/// The content of the message sent to the remote servers, without the `X-VSMTP` header,
+/// if it must only be delivered locally (see `queues.delivery.x_vsmtp_header`).
+///
+/// Return `None` if the message is sent as is.
+fn relay_content(config: &Config, message: &MessageBody) -> Option<String> {
+    match config.server.queues.delivery.x_vsmtp_header {
+        FieldQueueDeliveryXVsmtp::Local => {
+            let mut message = message.clone();
+            // NOTE: the header added by `add_trace_information` is the first one,
+            //       the headers of the previous hops are kept.
+            message.remove_header("X-VSMTP");
+            Some(message.inner().to_string())
+        }
+        FieldQueueDeliveryXVsmtp::Always | FieldQueueDeliveryXVsmtp::Never => None,
+    }
 }
 
 // <https://datatracker.ietf.org/doc/html/rfc5321#section-4.4>
 fn add_trace_information(
+    config: &Config,
     ctx: &ContextFinished,
     message: &mut MessageBody,
     status: &Status,
 ) -> anyhow::Result<()> {
-    message.prepend_header(
-        "X-VSMTP",
-        &format!(
-            "id=\"{message_uuid}\"; version=\"{version}\"; status=\"{status}\"",
-            message_uuid = ctx.mail_from.message_uuid,
-            version = env!("CARGO_PKG_VERSION"),
-            status = status.as_ref()
-        ),
-    );
+    if config.server.queues.delivery.x_vsmtp_header != FieldQueueDeliveryXVsmtp::Never {
+        message.prepend_header(
+            "X-VSMTP",
+            &format!(
+                "id=\"{message_uuid}\"; version=\"{version}\"; status=\"{status}\"",
+                message_uuid = ctx.mail_from.message_uuid,
+                version = env!("CARGO_PKG_VERSION"),
+                status = status.as_ref()
+            ),
+        );
+    }
 
     message.prepend_header(
         "Received",
@@ -121,20 +378,152 @@ fn add_trace_information(
 
 #[cfg(test)]
 mod test {
-    use super::add_trace_information;
+    use super::{add_dkim_signatures, add_trace_information, relay_content, remove_bcc};
     use time::format_description::well_known::Rfc2822;
     use vsmtp_common::status::Status;
+    use vsmtp_config::field::{FieldAppDkimSigner, FieldQueueDeliveryXVsmtp, SecretFile};
     use vsmtp_mail_parser::{MessageBody, RawBody};
-    use vsmtp_test::config::local_ctx;
+    use vsmtp_test::config::{local_ctx, local_msg, local_test};
+
+    const TEST_DKIM_KEY: &str = "../vsmtp-test/src/template/certs/private_key.rsa.key";
+    const TEST_DKIM_KEY_2: &str = "../vsmtp-test/src/template/certs/private_key.rsa2.key";
+
+    fn dkim_signer(domain: &str, selector: &str, path: &str) -> FieldAppDkimSigner {
+        let key =
+            <rsa::RsaPrivateKey as rsa::pkcs1::DecodeRsaPrivateKey>::read_pkcs1_pem_file(path)
+                .unwrap();
+
+        FieldAppDkimSigner {
+            domain: domain.to_string(),
+            selector: selector.to_string(),
+            private_key: SecretFile {
+                inner: std::sync::Arc::new(vsmtp_auth::dkim::PrivateKey::Rsa(Box::new(key))),
+                path: path.into(),
+            },
+            headers_field: ["From", "To", "Date", "Subject", "From"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            canonicalization: "simple/relaxed".parse().unwrap(),
+        }
+    }
+
+    fn signatures(message: &MessageBody) -> Vec<String> {
+        message
+            .inner()
+            .headers()
+            .into_iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("DKIM-Signature"))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    fn message_from(from: &str) -> MessageBody {
+        MessageBody::try_from(
+            [
+                &format!("From: {from}\r\n"),
+                "To: jenny@example.com\r\n",
+                "Subject: test\r\n",
+                "Date: Wed, 1 Feb 2023 10:00:00 +0000\r\n",
+                "\r\n",
+                "Hello\r\n",
+            ]
+            .concat()
+            .as_str(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sign_with_the_sender_domain_key() {
+        let mut config = local_test();
+        config.app.dkim = vec![
+            dkim_signer("example.com", "2023-01", TEST_DKIM_KEY),
+            dkim_signer("client.testserver.com", "2023-01", TEST_DKIM_KEY),
+        ];
+
+        let ctx = local_ctx();
+        let mut message = local_msg();
+        add_trace_information(&config, &ctx, &mut message, &Status::Next).unwrap();
+        add_dkim_signatures(&config, &ctx, &mut message).unwrap();
+
+        let signatures = signatures(&message);
+        assert_eq!(signatures.len(), 1);
+        assert!(signatures[0].contains("d=client.testserver.com"));
+        assert!(signatures[0].contains("s=2023-01"));
+        // the signature is placed above the trace headers.
+        assert!(message.inner().headers()[0]
+            .0
+            .eq_ignore_ascii_case("DKIM-Signature"));
+    }
+
+    #[test]
+    fn sign_with_the_from_domain_key() {
+        let mut config = local_test();
+        config.app.dkim = vec![
+            dkim_signer("example.com", "selector-a", TEST_DKIM_KEY),
+            dkim_signer("example.org", "selector-b", TEST_DKIM_KEY_2),
+            dkim_signer("client.testserver.com", "2023-01", TEST_DKIM_KEY),
+        ];
+
+        let ctx = local_ctx();
+
+        let mut from_a = message_from("John <john@Example.com>");
+        add_dkim_signatures(&config, &ctx, &mut from_a).unwrap();
+        let from_a = signatures(&from_a);
+        assert_eq!(from_a.len(), 1);
+        assert!(from_a[0].contains("d=example.com"));
+        assert!(from_a[0].contains("s=selector-a"));
+
+        let mut from_b = message_from("jane@example.org");
+        add_dkim_signatures(&config, &ctx, &mut from_b).unwrap();
+        let from_b = signatures(&from_b);
+        assert_eq!(from_b.len(), 1);
+        assert!(from_b[0].contains("d=example.org"));
+        assert!(from_b[0].contains("s=selector-b"));
+    }
+
+    #[test]
+    fn sign_with_all_the_keys_of_the_domain() {
+        let mut config = local_test();
+        config.app.dkim = vec![
+            dkim_signer("example.com", "2023-01", TEST_DKIM_KEY),
+            dkim_signer("example.com", "2023-02", TEST_DKIM_KEY_2),
+            dkim_signer("example.org", "2023-01", TEST_DKIM_KEY),
+        ];
+
+        let mut message = message_from("john@example.com");
+        add_dkim_signatures(&config, &local_ctx(), &mut message).unwrap();
+
+        let signatures = signatures(&message);
+        assert_eq!(signatures.len(), 2);
+        assert!(signatures.iter().all(|i| i.contains("d=example.com")));
+        assert!(signatures.iter().any(|i| i.contains("s=2023-01")));
+        assert!(signatures.iter().any(|i| i.contains("s=2023-02")));
+    }
+
+    #[test]
+    fn no_key_for_the_sender_domain() {
+        let mut config = local_test();
+        config.app.dkim = vec![dkim_signer("example.com", "2023-01", TEST_DKIM_KEY)];
+
+        let ctx = local_ctx();
+        let mut message = local_msg();
+        let before = message.inner().to_string();
+        add_dkim_signatures(&config, &ctx, &mut message).unwrap();
+
+        assert_eq!(message.inner().to_string(), before);
+    }
 
     #[test]
     fn test_add_trace_information() {
+        let config = local_test();
         let mut ctx = local_ctx();
 
         let mut message = MessageBody::default();
         let msg_uuid = uuid::Uuid::nil();
         ctx.mail_from.message_uuid = msg_uuid;
-        add_trace_information(&ctx, &mut message, &Status::Next).unwrap();
+        add_trace_information(&config, &ctx, &mut message, &Status::Next).unwrap();
 
         pretty_assertions::assert_eq!(
             *message.inner(),
@@ -155,4 +544,78 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn trace_information_without_x_vsmtp() {
+        let mut config = local_test();
+        config.server.queues.delivery.x_vsmtp_header = FieldQueueDeliveryXVsmtp::Never;
+
+        let mut message = MessageBody::default();
+        add_trace_information(&config, &local_ctx(), &mut message, &Status::Next).unwrap();
+
+        assert!(message.get_header("Received").is_some());
+        assert!(message.get_header("X-VSMTP").is_none());
+        assert!(relay_content(&config, &message).is_none());
+    }
+
+    #[test]
+    fn x_vsmtp_only_delivered_locally() {
+        let mut config = local_test();
+        config.server.queues.delivery.x_vsmtp_header = FieldQueueDeliveryXVsmtp::Local;
+
+        let mut message = local_msg();
+        message.prepend_header("X-VSMTP", "id=\"previous hop\"");
+        add_trace_information(&config, &local_ctx(), &mut message, &Status::Next).unwrap();
+
+        let local = message.inner().to_string();
+        let relay = relay_content(&config, &message).unwrap();
+
+        assert_eq!(local.matches("X-VSMTP:").count(), 2);
+        assert_eq!(relay.matches("X-VSMTP:").count(), 1);
+        assert!(relay.contains("X-VSMTP: id=\"previous hop\"\r\n"));
+        assert!(relay.starts_with("Received: "));
+    }
+
+    #[test]
+    fn x_vsmtp_always_relayed() {
+        let config = local_test();
+
+        let mut message = local_msg();
+        add_trace_information(&config, &local_ctx(), &mut message, &Status::Next).unwrap();
+
+        assert!(message.get_header("X-VSMTP").is_some());
+        assert!(relay_content(&config, &message).is_none());
+    }
+
+    #[test]
+    fn test_remove_bcc() {
+        let mut message = MessageBody::new(
+            [
+                "From: NoBody <nobody@domain.tld>\r\n",
+                "Bcc: hidden@domain.tld\r\n",
+                "To: Hei <hei@domain.tld>\r\n",
+                "Resent-Bcc: resent.hidden@domain.tld,\r\n",
+                " another.hidden@domain.tld\r\n",
+                "BCC: \r\n",
+                "Subject: Happy new year\r\n",
+            ]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+            "Be happy!\r\n".to_string(),
+        );
+
+        remove_bcc(&mut message);
+
+        pretty_assertions::assert_eq!(
+            message.inner().to_string(),
+            concat!(
+                "From: NoBody <nobody@domain.tld>\r\n",
+                "To: Hei <hei@domain.tld>\r\n",
+                "Subject: Happy new year\r\n",
+                "\r\n",
+                "Be happy!\r\n",
+            )
+        );
+    }
 }
@@ -30,24 +30,33 @@
 #![allow(clippy::use_self)]
 
 mod channel_message;
+mod delegation;
 mod delivery;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod on_mail;
 mod processing;
+mod rate_limiter;
 mod runtime;
 mod server;
 
 mod receiver {
+    mod before_queue_filter;
     pub mod handler;
+    mod milter;
     mod post_transaction;
     pub mod pre_transaction;
+    pub mod rejected_sample;
 }
 
 pub use channel_message::ProcessMessage;
+pub use delegation::{pull_delegated, resume_delegated, serve_delegation, DelegationVerdict};
 pub use on_mail::{MailHandler, OnMail};
+pub use rate_limiter::{ConnectionGuard, RateLimiter};
 pub use receiver::handler::Handler;
 pub use receiver::pre_transaction::ValidationVSL;
 pub use runtime::start_runtime;
-pub use server::{socket_bind_anyhow, Server};
+pub use server::{socket_bind_anyhow, unix_socket_bind_anyhow, Server};
 
 use anyhow::Context;
 use vsmtp_common::transfer::SmtpConnection;
@@ -65,6 +74,18 @@ pub enum Process {
     Delivery,
 }
 
+/// Wait until the server is asked to drain, that is to stop taking new work and
+/// to let the work in progress complete.
+///
+/// Never returns if the sender is dropped without asking for it.
+pub(crate) async fn drained(drain: &mut tokio::sync::watch::Receiver<bool>) {
+    while !*drain.borrow() {
+        if drain.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
 /// delegate a message to another service.
 pub(crate) fn delegate(
     delegator: &SmtpConnection,
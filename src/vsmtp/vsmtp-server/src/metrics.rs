@@ -0,0 +1,433 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+//! Prometheus metrics of the server, served over HTTP on `server.metrics.addr`.
+
+use vqueue::{GenericQueueManager, QueueID};
+use vsmtp_common::{rcpt::Rcpt, transfer::EmailTransferStatus};
+use vsmtp_protocol::Verb;
+
+/// Upper bounds (in seconds) of the buckets of the command duration histogram.
+const COMMAND_DURATION_BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Delay for a client to send its request.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Maximum size of a request (request line and headers), the rest is ignored.
+const REQUEST_SIZE_MAX: u64 = 8 * 1024;
+
+/// The queues whose number of messages is exposed.
+const QUEUES: [QueueID; 4] = [
+    QueueID::Working,
+    QueueID::Deliver,
+    QueueID::Deferred,
+    QueueID::Dead,
+];
+
+struct Histogram {
+    /// Cumulative count of each bucket of [`COMMAND_DURATION_BUCKETS`].
+    buckets: [u64; COMMAND_DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// Metrics of the server, see [`METRICS`].
+pub struct Metrics {
+    queues: [std::sync::atomic::AtomicUsize; QUEUES.len()],
+    delivery_sent: std::sync::atomic::AtomicU64,
+    delivery_failed: std::sync::atomic::AtomicU64,
+    delivery_held_back: std::sync::atomic::AtomicU64,
+    deferred_retry: std::sync::atomic::AtomicU64,
+    connections: std::sync::atomic::AtomicI64,
+    commands: std::sync::Mutex<Vec<(&'static str, Histogram)>>,
+}
+
+/// The metrics recorded by the receiver and the delivery.
+pub static METRICS: Metrics = Metrics::new();
+
+const fn command_name(verb: Verb) -> &'static str {
+    match verb {
+        Verb::Helo => "HELO",
+        Verb::Ehlo => "EHLO",
+        Verb::MailFrom => "MAIL",
+        Verb::RcptTo => "RCPT",
+        Verb::Data => "DATA",
+        Verb::Quit => "QUIT",
+        Verb::Rset => "RSET",
+        Verb::Help => "HELP",
+        Verb::Vrfy => "VRFY",
+        Verb::Expn => "EXPN",
+        Verb::Noop => "NOOP",
+        Verb::StartTls => "STARTTLS",
+        Verb::Auth => "AUTH",
+        Verb::Bdat => "BDAT",
+        _ => "unknown",
+    }
+}
+
+impl Metrics {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const fn new() -> Self {
+        const ZERO: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        Self {
+            queues: [ZERO; QUEUES.len()],
+            delivery_sent: std::sync::atomic::AtomicU64::new(0),
+            delivery_failed: std::sync::atomic::AtomicU64::new(0),
+            delivery_held_back: std::sync::atomic::AtomicU64::new(0),
+            deferred_retry: std::sync::atomic::AtomicU64::new(0),
+            connections: std::sync::atomic::AtomicI64::new(0),
+            commands: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Count the outcome of the delivery of `rcpt`.
+    pub fn record_delivery(&self, rcpt: &[Rcpt]) {
+        for i in rcpt {
+            let counter = match i.email_status {
+                EmailTransferStatus::Sent { .. } => &self.delivery_sent,
+                EmailTransferStatus::Failed { .. } => &self.delivery_failed,
+                EmailTransferStatus::HeldBack { .. } => &self.delivery_held_back,
+                EmailTransferStatus::Waiting { .. } => continue,
+            };
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Count a new delivery attempt of a message of the `deferred` queue.
+    pub fn record_deferred_retry(&self) {
+        self.deferred_retry
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// A client connected (`delta = 1`) or disconnected (`delta = -1`).
+    pub fn record_connection(&self, delta: i64) {
+        self.connections
+            .fetch_add(delta, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Add the duration of the handling of a command to its histogram.
+    pub fn record_command(&self, verb: Verb, elapsed: std::time::Duration) {
+        let name = command_name(verb);
+        let elapsed = elapsed.as_secs_f64();
+
+        let mut commands = self.commands.lock().unwrap();
+        let histogram = match commands.iter().position(|(i, _)| *i == name) {
+            Some(index) => &mut commands[index].1,
+            None => {
+                commands.push((
+                    name,
+                    Histogram {
+                        buckets: [0; COMMAND_DURATION_BUCKETS.len()],
+                        sum: 0.0,
+                        count: 0,
+                    },
+                ));
+                &mut commands.last_mut().expect("pushed above").1
+            }
+        };
+
+        for (bucket, upper_bound) in histogram.buckets.iter_mut().zip(COMMAND_DURATION_BUCKETS) {
+            if elapsed <= upper_bound {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += elapsed;
+        histogram.count += 1;
+    }
+
+    /// Update the number of messages in the queues.
+    pub async fn refresh_queues(&self, queue_manager: &dyn GenericQueueManager) {
+        for (queue, gauge) in QUEUES.iter().zip(&self.queues) {
+            match queue_manager.list(queue).await {
+                Ok(messages) => gauge.store(messages.len(), std::sync::atomic::Ordering::Relaxed),
+                Err(error) => tracing::warn!(%queue, %error, "Failed to list the queue."),
+            }
+        }
+    }
+
+    /// Format the metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        use std::fmt::Write;
+
+        let load = std::sync::atomic::Ordering::Relaxed;
+        let mut out = String::new();
+
+        out.push_str("# HELP vsmtp_queue_messages Number of messages in the queue.\n");
+        out.push_str("# TYPE vsmtp_queue_messages gauge\n");
+        for (queue, gauge) in QUEUES.iter().zip(&self.queues) {
+            let _ = writeln!(
+                out,
+                "vsmtp_queue_messages{{queue=\"{queue}\"}} {}",
+                gauge.load(load)
+            );
+        }
+
+        out.push_str("# HELP vsmtp_delivery_total Number of recipients per delivery outcome.\n");
+        out.push_str("# TYPE vsmtp_delivery_total counter\n");
+        for (outcome, counter) in [
+            ("sent", &self.delivery_sent),
+            ("failed", &self.delivery_failed),
+            ("held_back", &self.delivery_held_back),
+        ] {
+            let _ = writeln!(
+                out,
+                "vsmtp_delivery_total{{outcome=\"{outcome}\"}} {}",
+                counter.load(load)
+            );
+        }
+
+        out.push_str(
+            "# HELP vsmtp_deferred_retry_total Number of delivery attempts of deferred messages.\n",
+        );
+        out.push_str("# TYPE vsmtp_deferred_retry_total counter\n");
+        let _ = writeln!(
+            out,
+            "vsmtp_deferred_retry_total {}",
+            self.deferred_retry.load(load)
+        );
+
+        out.push_str("# HELP vsmtp_connections Number of clients connected.\n");
+        out.push_str("# TYPE vsmtp_connections gauge\n");
+        let _ = writeln!(out, "vsmtp_connections {}", self.connections.load(load));
+
+        out.push_str(
+            "# HELP vsmtp_command_duration_seconds Time taken to handle the SMTP commands.\n",
+        );
+        out.push_str("# TYPE vsmtp_command_duration_seconds histogram\n");
+        for (command, histogram) in self.commands.lock().unwrap().iter() {
+            for (bucket, upper_bound) in histogram.buckets.iter().zip(COMMAND_DURATION_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "vsmtp_command_duration_seconds_bucket{{command=\"{command}\",le=\"{upper_bound}\"}} {bucket}",
+                );
+            }
+            let _ = writeln!(
+                out,
+                "vsmtp_command_duration_seconds_bucket{{command=\"{command}\",le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "vsmtp_command_duration_seconds_sum{{command=\"{command}\"}} {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "vsmtp_command_duration_seconds_count{{command=\"{command}\"}} {}",
+                histogram.count
+            );
+        }
+
+        out
+    }
+}
+
+async fn handle_request(
+    metrics: &Metrics,
+    mut stream: tokio::net::TcpStream,
+    timeout: std::time::Duration,
+) -> std::io::Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    let mut request = tokio::io::BufReader::new((&mut stream).take(REQUEST_SIZE_MAX));
+
+    let mut request_line = String::new();
+    tokio::time::timeout(timeout, async {
+        request.read_line(&mut request_line).await?;
+        loop {
+            let mut header = String::new();
+            if request.read_line(&mut header).await? == 0 || header == "\r\n" || header == "\n" {
+                break;
+            }
+        }
+        std::io::Result::Ok(())
+    })
+    .await
+    .map_err(|_elapsed| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    drop(request);
+
+    let response = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", "/metrics", _] => {
+            let body = metrics.render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serve [`METRICS`] on `listener`, and refresh the number of messages in the queues
+/// every `queue_refresh_period`.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    queue_manager: std::sync::Arc<dyn GenericQueueManager>,
+    queue_refresh_period: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(queue_refresh_period);
+        loop {
+            interval.tick().await;
+            METRICS.refresh_queues(queue_manager.as_ref()).await;
+        }
+    });
+
+    tracing::info!(addr = ?listener.local_addr(), "Serving the metrics.");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(error) = handle_request(&METRICS, stream, REQUEST_TIMEOUT).await {
+                        tracing::debug!(%error, "Metrics request failure.");
+                    }
+                });
+            }
+            Err(error) => tracing::warn!(%error, "Metrics endpoint accept failure."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_common::Address;
+    use vsmtp_test::config::{local_ctx, local_msg, local_test};
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new();
+
+        let mut sent = Rcpt::new(<Address as std::str::FromStr>::from_str("a@b.com").unwrap());
+        sent.email_status = EmailTransferStatus::Sent {
+            timestamp: time::OffsetDateTime::now_utc(),
+        };
+        let mut held_back = sent.clone();
+        held_back.email_status = EmailTransferStatus::Waiting {
+            timestamp: time::OffsetDateTime::now_utc(),
+        };
+        held_back
+            .email_status
+            .held_back(vsmtp_common::transfer::TransferErrorsVariant::StillWaiting);
+        metrics.record_delivery(&[sent.clone(), sent, held_back]);
+        metrics.record_deferred_retry();
+        metrics.record_connection(1);
+        metrics.record_command(Verb::Ehlo, std::time::Duration::from_millis(20));
+        metrics.record_command(Verb::Ehlo, std::time::Duration::from_secs(2));
+
+        let rendered = metrics.render();
+        for expected in [
+            "vsmtp_queue_messages{queue=\"deferred\"} 0",
+            "vsmtp_delivery_total{outcome=\"sent\"} 2",
+            "vsmtp_delivery_total{outcome=\"failed\"} 0",
+            "vsmtp_delivery_total{outcome=\"held_back\"} 1",
+            "vsmtp_deferred_retry_total 1",
+            "vsmtp_connections 1",
+            "vsmtp_command_duration_seconds_bucket{command=\"EHLO\",le=\"0.01\"} 0",
+            "vsmtp_command_duration_seconds_bucket{command=\"EHLO\",le=\"0.05\"} 1",
+            "vsmtp_command_duration_seconds_bucket{command=\"EHLO\",le=\"5\"} 2",
+            "vsmtp_command_duration_seconds_bucket{command=\"EHLO\",le=\"+Inf\"} 2",
+            "vsmtp_command_duration_seconds_count{command=\"EHLO\"} 2",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == expected),
+                "{expected}\n{rendered}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn serve_queues() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let config = std::sync::Arc::new(local_test());
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config).unwrap();
+        queue_manager
+            .write_both(&QueueID::Deferred, &local_ctx(), &local_msg())
+            .await
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            listener,
+            queue_manager,
+            std::time::Duration::from_millis(10),
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(
+            response
+                .lines()
+                .any(|l| l == "vsmtp_queue_messages{queue=\"deferred\"} 1"),
+            "{response}"
+        );
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{response}"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        // NOTE: the headers are never terminated.
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n")
+            .await
+            .unwrap();
+        let error = handle_request(
+            &Metrics::new(),
+            stream,
+            std::time::Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.is_empty(), "{response}");
+    }
+}
@@ -47,20 +47,41 @@ pub struct MailHandler {
     pub(crate) delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
 }
 
+/// Number of messages refused because the spool was full.
+static STORAGE_FULL_COUNT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
 #[must_use]
 #[derive(Debug, thiserror::Error)]
 pub enum MailHandlerError {
     #[error("could not write to `mails` folder: `{0}`")]
-    WriteMessageBody(std::io::Error),
+    WriteMessageBody(anyhow::Error),
     #[error("could not write to queue `{0}` got: `{1}`")]
-    WriteToQueue(QueueID, String),
+    WriteToQueue(QueueID, anyhow::Error),
     #[error("could not send message to next process `{0}` got: `{1}`")]
     SendToNextProcess(Process, tokio::sync::mpsc::error::SendError<ProcessMessage>),
     #[error("delegate directive cannot be used in preq stage")]
     InvalidDelegation,
 }
 
+impl MailHandlerError {
+    fn is_storage_full(&self) -> bool {
+        match self {
+            Self::WriteMessageBody(error) | Self::WriteToQueue(_, error) => {
+                vsmtp_common::libc_abstraction::is_storage_full(error)
+            }
+            Self::SendToNextProcess(..) | Self::InvalidDelegation => false,
+        }
+    }
+}
+
 impl MailHandler {
+    /// Number of messages refused since the start of the server because the
+    /// spool was full.
+    #[must_use]
+    pub fn storage_full_count() -> u64 {
+        STORAGE_FULL_COUNT.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
     /// create a new mail handler
     #[must_use]
     pub const fn new(
@@ -91,7 +112,7 @@ impl MailHandler {
                 queue_manager
                     .write_ctx(&quarantine, &mail_context)
                     .await
-                    .map_err(|err| MailHandlerError::WriteToQueue(quarantine, err.to_string()))?;
+                    .map_err(|err| MailHandlerError::WriteToQueue(quarantine, err))?;
 
                 tracing::warn!(status = status.as_ref(), "Rules skipped.");
                 (None, None, false)
@@ -134,15 +155,13 @@ impl MailHandler {
         queue_manager
             .write_msg(&message_uuid, &mail_message)
             .await
-            .map_err(|e| MailHandlerError::WriteMessageBody(e.downcast().unwrap()))?;
+            .map_err(MailHandlerError::WriteMessageBody)?;
 
         if let Some(queue) = write_to_queue {
             queue_manager
                 .write_ctx(&queue, &mail_context)
                 .await
-                .map_err(|error| {
-                    MailHandlerError::WriteToQueue(queue.clone(), error.to_string())
-                })?;
+                .map_err(|error| MailHandlerError::WriteToQueue(queue.clone(), error))?;
         }
 
         // TODO: even if it's a rare case, a result of None should remove the
@@ -163,7 +182,7 @@ impl MailHandler {
 
 #[async_trait::async_trait]
 impl OnMail for MailHandler {
-    #[tracing::instrument(name = "preq", skip_all)]
+    #[tracing::instrument(name = "preq", skip_all, fields(uuid = %mail.mail_from.message_uuid))]
     async fn on_mail(
         &mut self,
         mail: Box<ContextFinished>,
@@ -172,6 +191,16 @@ impl OnMail for MailHandler {
     ) -> CodeID {
         match self.on_mail_priv(mail, message, &queue_manager).await {
             Ok(_) => CodeID::Ok,
+            Err(error) if error.is_storage_full() => {
+                let count =
+                    STORAGE_FULL_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                tracing::error!(
+                    %error,
+                    storage_full_count = count,
+                    "Spool is full, the message is refused temporarily."
+                );
+                CodeID::InsufficientStorage
+            }
             Err(error) => {
                 tracing::warn!(%error, "Mail processing failure");
                 CodeID::Denied
@@ -179,3 +208,43 @@ impl OnMail for MailHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_test::config::{local_ctx, local_msg, local_test};
+
+    #[tokio::test]
+    async fn spool_full() {
+        let mut config = local_test();
+        config.server.queues.dirpath =
+            std::env::temp_dir().join(format!("vsmtp-spool-full-{}", uuid::Uuid::new_v4()));
+        let config = std::sync::Arc::new(config);
+        let queue_manager =
+            <vqueue::temp::QueueManager as GenericQueueManager>::init(config.clone()).unwrap();
+
+        let mut ctx = local_ctx();
+        let message_uuid = uuid::Uuid::new_v4();
+        ctx.mail_from.message_uuid = message_uuid;
+
+        // NOTE: writing to /dev/full always fails with ENOSPC.
+        let mails = config.server.queues.dirpath.join("mails");
+        std::fs::create_dir_all(&mails).unwrap();
+        std::os::unix::fs::symlink("/dev/full", mails.join(format!("{message_uuid}.eml"))).unwrap();
+
+        let (working_sender, mut working_receiver) = tokio::sync::mpsc::channel(1);
+        let (delivery_sender, _delivery_receiver) = tokio::sync::mpsc::channel(1);
+        let count = MailHandler::storage_full_count();
+
+        assert_eq!(
+            MailHandler::new(working_sender, delivery_sender)
+                .on_mail(Box::new(ctx), local_msg(), queue_manager)
+                .await,
+            CodeID::InsufficientStorage
+        );
+        assert!(MailHandler::storage_full_count() > count);
+        assert!(working_receiver.try_recv().is_err());
+
+        std::fs::remove_dir_all(&config.server.queues.dirpath).unwrap();
+    }
+}
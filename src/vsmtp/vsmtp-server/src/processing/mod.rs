@@ -20,10 +20,14 @@ use vqueue::{GenericQueueManager, QueueID};
 use vsmtp_common::{
     status::Status,
     transfer::{EmailTransferStatus, RuleEngineVariants, TransferErrorsVariant},
+    Reply, ReplyCode,
 };
+use vsmtp_config::{field::FieldQueueWorkingMimeParseFailure, Config};
+use vsmtp_mail_parser::MailMimeParser;
 use vsmtp_rule_engine::{ExecutionStage, RuleEngine};
 
 pub async fn start<Q: GenericQueueManager + Sized + 'static>(
+    config: std::sync::Arc<Config>,
     rule_engine: std::sync::Arc<RuleEngine>,
     queue_manager: std::sync::Arc<Q>,
     mut working_receiver: tokio::sync::mpsc::Receiver<ProcessMessage>,
@@ -31,22 +35,29 @@ pub async fn start<Q: GenericQueueManager + Sized + 'static>(
 ) {
     loop {
         if let Some(pm) = working_receiver.recv().await {
+            let config = config.clone();
             let rule_engine = rule_engine.clone();
             let queue_manager = queue_manager.clone();
             let delivery_sender = delivery_sender.clone();
 
             tokio::spawn(async move {
-                let _err =
-                    handle_one_in_working_queue(rule_engine, queue_manager, pm, delivery_sender)
-                        .await;
+                let _err = handle_one_in_working_queue(
+                    config,
+                    rule_engine,
+                    queue_manager,
+                    pm,
+                    delivery_sender,
+                )
+                .await;
             });
         }
     }
 }
 
 #[allow(clippy::too_many_lines)]
-#[tracing::instrument(name = "working", skip_all)]
-async fn handle_one_in_working_queue<Q: GenericQueueManager + Sized + 'static>(
+#[tracing::instrument(name = "working", skip_all, fields(uuid = %process_message.message_uuid))]
+pub(crate) async fn handle_one_in_working_queue<Q: GenericQueueManager + Sized + 'static>(
+    config: std::sync::Arc<Config>,
     rule_engine: std::sync::Arc<RuleEngine>,
     queue_manager: std::sync::Arc<Q>,
     process_message: ProcessMessage,
@@ -65,11 +76,36 @@ async fn handle_one_in_working_queue<Q: GenericQueueManager + Sized + 'static>(
         QueueID::Working
     };
 
-    let (ctx, mail_message) = queue_manager
+    let (ctx, mut mail_message) = queue_manager
         .get_both(&queue, &process_message.message_uuid)
         .await?;
 
     let mut skipped = ctx.connect.skipped.clone();
+
+    // NOTE: the message is parsed before running the rules, so a malformed message is
+    //       handled as configured instead of making fail each rule needing the parsed form.
+    if skipped.is_none() {
+        if let Err(error) = mail_message.parse::<MailMimeParser>() {
+            tracing::warn!(%error, "Failed to parse the message.");
+
+            skipped = match &config.server.queues.working.mime_parse_failure {
+                FieldQueueWorkingMimeParseFailure::Accept => None,
+                FieldQueueWorkingMimeParseFailure::Reject => {
+                    Some(Status::Deny(either::Right(Reply::new(
+                        ReplyCode::Enhanced {
+                            code: 554,
+                            enhanced: "5.6.0".to_owned(),
+                        },
+                        "the message could not be parsed\r\n",
+                    ))))
+                }
+                FieldQueueWorkingMimeParseFailure::Quarantine { name } => {
+                    Some(Status::Quarantine(name.clone()))
+                }
+            };
+        }
+    }
+
     let (ctx, mail_message, _) = rule_engine.just_run_when(
         &mut skipped,
         ExecutionStage::PostQ,
@@ -205,6 +241,7 @@ mod tests {
                 .unwrap();
 
         assert!(handle_one_in_working_queue(
+            config.clone(),
             std::sync::Arc::new(
                 RuleEngine::with_hierarchy(
                     config,
@@ -245,6 +282,7 @@ mod tests {
         let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
 
         handle_one_in_working_queue(
+            config.clone(),
             std::sync::Arc::new(
                 RuleEngine::with_hierarchy(
                     config.clone(),
@@ -299,6 +337,7 @@ mod tests {
         let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
 
         handle_one_in_working_queue(
+            config.clone(),
             std::sync::Arc::new(
                 RuleEngine::with_hierarchy(
                     config.clone(),
@@ -357,6 +396,7 @@ mod tests {
         let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
 
         handle_one_in_working_queue(
+            config.clone(),
             std::sync::Arc::new(
                 RuleEngine::with_hierarchy(
                     config.clone(),
@@ -402,4 +442,131 @@ mod tests {
             .await
             .unwrap_err();
     }
+
+    async fn run_with_mime_parse_failure(
+        mime_parse_failure: FieldQueueWorkingMimeParseFailure,
+    ) -> (
+        std::sync::Arc<vqueue::temp::QueueManager>,
+        uuid::Uuid,
+        tokio::sync::mpsc::Receiver<ProcessMessage>,
+    ) {
+        let mut config = local_test();
+        config.server.queues.working.mime_parse_failure = mime_parse_failure;
+        let config = std::sync::Arc::new(config);
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+
+        // NOTE: the message has no `Date` header, which is mandatory for the MIME parser.
+        let mut ctx = local_ctx();
+        let message_uuid = uuid::Uuid::new_v4();
+        ctx.mail_from.message_uuid = message_uuid;
+        ctx.rcpt_to
+            .forward_paths
+            .push(vsmtp_common::rcpt::Rcpt::new(
+                "john.doe@example.com".parse().unwrap(),
+            ));
+        queue_manager
+            .write_both(&QueueID::Working, &ctx, &local_msg())
+            .await
+            .unwrap();
+
+        let (delivery_sender, delivery_receiver) = tokio::sync::mpsc::channel::<ProcessMessage>(10);
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        handle_one_in_working_queue(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::with_hierarchy(
+                    config.clone(),
+                    |builder| {
+                        Ok(builder
+                            .add_root_filter_rules(&format!(
+                                r#"#{{ {}: [ rule "parse" || if msg::parse_failed() {{ state::next() }} else {{ state::deny() }} ] }}"#,
+                                ExecutionStage::PostQ
+                            ))?
+                            .build())
+                    },
+                    resolvers,
+                    queue_manager.clone(),
+                )
+                .unwrap(),
+            ),
+            queue_manager.clone(),
+            ProcessMessage {
+                message_uuid,
+                delegated: false,
+            },
+            delivery_sender,
+        )
+        .await
+        .unwrap();
+
+        (queue_manager, message_uuid, delivery_receiver)
+    }
+
+    #[tokio::test]
+    async fn mime_parse_failure_accept() {
+        let (queue_manager, message_uuid, mut delivery_receiver) =
+            run_with_mime_parse_failure(FieldQueueWorkingMimeParseFailure::Accept).await;
+
+        assert_eq!(
+            delivery_receiver.recv().await.unwrap().message_uuid,
+            message_uuid
+        );
+        queue_manager
+            .get_ctx(&QueueID::Deliver, &message_uuid)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn mime_parse_failure_reject() {
+        let (queue_manager, message_uuid, mut delivery_receiver) =
+            run_with_mime_parse_failure(FieldQueueWorkingMimeParseFailure::Reject).await;
+
+        assert!(delivery_receiver.try_recv().is_err());
+
+        let ctx = queue_manager
+            .get_ctx(&QueueID::Dead, &message_uuid)
+            .await
+            .unwrap();
+        assert_eq!(
+            ctx.rcpt_to.forward_paths.first().unwrap().email_status,
+            EmailTransferStatus::failed(TransferErrorsVariant::RuleEngine(
+                RuleEngineVariants::Denied(either::Right(Reply::new(
+                    ReplyCode::Enhanced {
+                        code: 554,
+                        enhanced: "5.6.0".to_owned(),
+                    },
+                    "the message could not be parsed\r\n",
+                )))
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn mime_parse_failure_quarantine() {
+        let (queue_manager, message_uuid, mut delivery_receiver) =
+            run_with_mime_parse_failure(FieldQueueWorkingMimeParseFailure::Quarantine {
+                name: "malformed".to_owned(),
+            })
+            .await;
+
+        assert!(delivery_receiver.try_recv().is_err());
+
+        queue_manager
+            .get_ctx(
+                &QueueID::Quarantine {
+                    name: "malformed".to_owned(),
+                },
+                &message_uuid,
+            )
+            .await
+            .unwrap();
+        queue_manager
+            .get_ctx(&QueueID::Deliver, &message_uuid)
+            .await
+            .unwrap_err();
+    }
 }
@@ -0,0 +1,316 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+//! Limits of `server.rate_limit`, keyed by the IP address of the clients and
+//! shared by the sessions.
+
+use vsmtp_config::field::FieldServerRateLimit;
+
+/// A bucket of `capacity` tokens, refilled continuously over `period`.
+struct TokenBucket {
+    tokens: f64,
+    last: std::time::Instant,
+}
+
+impl TokenBucket {
+    fn full(now: std::time::Instant, capacity: u32) -> Self {
+        Self {
+            tokens: f64::from(capacity),
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: std::time::Instant, capacity: u32, period: std::time::Duration) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        let capacity = f64::from(capacity);
+
+        self.tokens = capacity.min(self.tokens + elapsed * capacity / period.as_secs_f64());
+        self.last = now;
+    }
+
+    fn take(
+        &mut self,
+        now: std::time::Instant,
+        capacity: u32,
+        period: std::time::Duration,
+    ) -> bool {
+        self.refill(now, capacity, period);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    fn is_full(
+        &mut self,
+        now: std::time::Instant,
+        capacity: u32,
+        period: std::time::Duration,
+    ) -> bool {
+        self.refill(now, capacity, period);
+        self.tokens >= f64::from(capacity)
+    }
+}
+
+/// The capacity of the buckets, refilled over `period`.
+struct Limit {
+    capacity: u32,
+    period: std::time::Duration,
+}
+
+impl Limit {
+    fn new(capacity: Option<u32>, period: std::time::Duration) -> Option<Self> {
+        capacity.map(|capacity| Self { capacity, period })
+    }
+}
+
+struct Client {
+    connections: u32,
+    messages: TokenBucket,
+    rcpts: TokenBucket,
+}
+
+/// Connections, messages and recipients accounted for each client IP address.
+pub struct RateLimiter {
+    connection_count_max: Option<u32>,
+    messages: Option<Limit>,
+    rcpts: Option<Limit>,
+    clients: std::sync::Mutex<std::collections::HashMap<std::net::IpAddr, Client>>,
+}
+
+/// A connection of a client, released when dropped.
+pub struct ConnectionGuard {
+    rate_limiter: std::sync::Arc<RateLimiter>,
+    ip: std::net::IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut clients = self
+            .rate_limiter
+            .clients
+            .lock()
+            .expect("rate limiter poisoned");
+        if let Some(client) = clients.get_mut(&self.ip) {
+            client.connections = client.connections.saturating_sub(1);
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Create a limiter enforcing the limits of `config`.
+    #[must_use]
+    pub fn new(config: &FieldServerRateLimit) -> Self {
+        Self {
+            connection_count_max: config.connection_count_max,
+            messages: Limit::new(
+                config.message_per_minute,
+                std::time::Duration::from_secs(60),
+            ),
+            rcpts: Limit::new(
+                config.rcpt_per_hour,
+                std::time::Duration::from_secs(60 * 60),
+            ),
+            clients: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    fn with_client<T>(
+        &self,
+        now: std::time::Instant,
+        ip: std::net::IpAddr,
+        f: impl FnOnce(&mut Client) -> T,
+    ) -> T {
+        let mut clients = self.clients.lock().expect("rate limiter poisoned");
+        let client = clients.entry(ip).or_insert_with(|| Client {
+            connections: 0,
+            messages: TokenBucket::full(now, self.messages.as_ref().map_or(0, |l| l.capacity)),
+            rcpts: TokenBucket::full(now, self.rcpts.as_ref().map_or(0, |l| l.capacity)),
+        });
+        f(client)
+    }
+
+    /// Account a new connection of `ip`, `None` if it has too many connections opened.
+    #[must_use]
+    pub fn acquire_connection(
+        self: &std::sync::Arc<Self>,
+        ip: std::net::IpAddr,
+    ) -> Option<ConnectionGuard> {
+        let max = self.connection_count_max;
+        let acquired = self.with_client(std::time::Instant::now(), ip, |client| {
+            if max.map_or(false, |max| client.connections >= max) {
+                return false;
+            }
+            client.connections += 1;
+            true
+        });
+
+        acquired.then(|| ConnectionGuard {
+            rate_limiter: self.clone(),
+            ip,
+        })
+    }
+
+    /// Account a new message of `ip`, `false` if it exceeds `message_per_minute`.
+    #[must_use]
+    pub fn take_message(&self, ip: std::net::IpAddr) -> bool {
+        self.take_message_at(std::time::Instant::now(), ip)
+    }
+
+    fn take_message_at(&self, now: std::time::Instant, ip: std::net::IpAddr) -> bool {
+        match &self.messages {
+            Some(limit) => self.with_client(now, ip, |client| {
+                client.messages.take(now, limit.capacity, limit.period)
+            }),
+            None => true,
+        }
+    }
+
+    /// Account a new recipient of `ip`, `false` if it exceeds `rcpt_per_hour`.
+    #[must_use]
+    pub fn take_rcpt(&self, ip: std::net::IpAddr) -> bool {
+        self.take_rcpt_at(std::time::Instant::now(), ip)
+    }
+
+    fn take_rcpt_at(&self, now: std::time::Instant, ip: std::net::IpAddr) -> bool {
+        match &self.rcpts {
+            Some(limit) => self.with_client(now, ip, |client| {
+                client.rcpts.take(now, limit.capacity, limit.period)
+            }),
+            None => true,
+        }
+    }
+
+    /// Remove the clients without connection and whose buckets are full again,
+    /// they are in the same state as a client never seen.
+    pub fn cleanup(&self) {
+        self.cleanup_at(std::time::Instant::now());
+    }
+
+    fn cleanup_at(&self, now: std::time::Instant) {
+        let (messages, rcpts) = (&self.messages, &self.rcpts);
+        let mut clients = self.clients.lock().expect("rate limiter poisoned");
+
+        clients.retain(|_, client| {
+            client.connections != 0
+                || !messages
+                    .as_ref()
+                    .map_or(true, |l| client.messages.is_full(now, l.capacity, l.period))
+                || !rcpts
+                    .as_ref()
+                    .map_or(true, |l| client.rcpts.is_full(now, l.capacity, l.period))
+        });
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.clients.lock().expect("rate limiter poisoned").len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use vsmtp_config::field::FieldServerRateLimit;
+
+    fn limiter(
+        connection_count_max: Option<u32>,
+        message_per_minute: Option<u32>,
+        rcpt_per_hour: Option<u32>,
+    ) -> std::sync::Arc<RateLimiter> {
+        std::sync::Arc::new(RateLimiter::new(&FieldServerRateLimit {
+            connection_count_max,
+            message_per_minute,
+            rcpt_per_hour,
+            cleanup_period: std::time::Duration::from_secs(60),
+        }))
+    }
+
+    const IP: std::net::IpAddr = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER_IP: std::net::IpAddr = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn connection_count_max() {
+        let limiter = limiter(Some(2), None, None);
+
+        let first = limiter.acquire_connection(IP).unwrap();
+        let _second = limiter.acquire_connection(IP).unwrap();
+        assert!(limiter.acquire_connection(IP).is_none());
+        assert!(limiter.acquire_connection(OTHER_IP).is_some());
+
+        drop(first);
+        assert!(limiter.acquire_connection(IP).is_some());
+    }
+
+    #[test]
+    fn message_per_minute() {
+        let limiter = limiter(None, Some(2), None);
+        let now = std::time::Instant::now();
+
+        assert!(limiter.take_message_at(now, IP));
+        assert!(limiter.take_message_at(now, IP));
+        assert!(!limiter.take_message_at(now, IP));
+        assert!(limiter.take_message_at(now, OTHER_IP));
+
+        // one token every 30 seconds
+        assert!(!limiter.take_message_at(now + std::time::Duration::from_secs(29), IP));
+        assert!(limiter.take_message_at(now + std::time::Duration::from_secs(31), IP));
+        assert!(!limiter.take_message_at(now + std::time::Duration::from_secs(31), IP));
+    }
+
+    #[test]
+    fn rcpt_per_hour() {
+        let limiter = limiter(None, None, Some(1));
+        let now = std::time::Instant::now();
+
+        assert!(limiter.take_rcpt_at(now, IP));
+        assert!(!limiter.take_rcpt_at(now, IP));
+        assert!(limiter.take_rcpt_at(now + std::time::Duration::from_secs(60 * 60), IP));
+    }
+
+    #[test]
+    fn no_limit() {
+        let limiter = limiter(None, None, None);
+
+        let _guards = (0..100)
+            .map(|_| limiter.acquire_connection(IP).unwrap())
+            .collect::<Vec<_>>();
+        assert!((0..100).all(|_| limiter.take_message(IP) && limiter.take_rcpt(IP)));
+    }
+
+    #[test]
+    fn cleanup_idle_clients() {
+        let limiter = limiter(Some(1), Some(1), None);
+        let now = std::time::Instant::now();
+
+        let guard = limiter.acquire_connection(IP).unwrap();
+        assert!(limiter.take_message_at(now, OTHER_IP));
+        assert_eq!(limiter.len(), 2);
+
+        // connected, and not refilled yet
+        limiter.cleanup_at(now);
+        assert_eq!(limiter.len(), 2);
+
+        drop(guard);
+        limiter.cleanup_at(now);
+        assert_eq!(limiter.len(), 1);
+
+        limiter.cleanup_at(now + std::time::Duration::from_secs(60));
+        assert_eq!(limiter.len(), 0);
+        assert!(limiter.take_message_at(now + std::time::Duration::from_secs(60), OTHER_IP));
+    }
+}
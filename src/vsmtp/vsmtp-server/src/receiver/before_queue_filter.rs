@@ -0,0 +1,146 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use anyhow::Context;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use vsmtp_common::{ContextFinished, Reply, ReplyCode};
+use vsmtp_config::field::FieldServerSMTPBeforeQueueFilter;
+use vsmtp_mail_parser::MessageBody;
+
+type FilterStream = tokio::io::BufReader<tokio::net::TcpStream>;
+
+/// Read a (possibly multi-line) reply of the filter.
+async fn read_reply(stream: &mut FilterStream) -> anyhow::Result<Reply> {
+    let mut code = None;
+    let mut text = String::new();
+
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("filter closed the connection");
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        let is_last = line.chars().nth(3) != Some('-');
+
+        let (line_code, line_text) =
+            ReplyCode::parse(&line.replacen('-', " ", usize::from(!is_last)))
+                .map(|(line_code, line_text)| (line_code, line_text.to_owned()))
+                .with_context(|| format!("filter replied an invalid line: '{line}'"))?;
+
+        code = Some(line_code);
+        text.push_str(&line_text);
+        text.push_str("\r\n");
+
+        if is_last {
+            return Ok(Reply::new(code.expect("has been set above"), text));
+        }
+    }
+}
+
+async fn command(stream: &mut FilterStream, command: &str) -> anyhow::Result<Reply> {
+    stream.get_mut().write_all(command.as_bytes()).await?;
+    read_reply(stream).await
+}
+
+/// Relay the message to the filter, and return the verdict of the filter:
+///
+/// * `Ok(reply)` with a positive code if the filter accepted the message,
+/// * `Ok(reply)` with an error code if the filter (or its downstream) refused the message,
+/// * `Err(_)` if the exchange with the filter failed.
+#[tracing::instrument(name = "before-queue-filter", skip_all, err, fields(filter = %filter.address))]
+pub(super) async fn relay(
+    filter: &FieldServerSMTPBeforeQueueFilter,
+    ctx: &ContextFinished,
+    message: &MessageBody,
+) -> anyhow::Result<Reply> {
+    tokio::time::timeout(filter.timeout, relay_inner(filter, ctx, message))
+        .await
+        .context("filter timed out")?
+}
+
+async fn relay_inner(
+    filter: &FieldServerSMTPBeforeQueueFilter,
+    ctx: &ContextFinished,
+    message: &MessageBody,
+) -> anyhow::Result<Reply> {
+    let mut stream = tokio::io::BufReader::new(
+        tokio::net::TcpStream::connect(filter.address)
+            .await
+            .context("failed to connect to the filter")?,
+    );
+
+    let greetings = read_reply(&mut stream).await?;
+    if greetings.code().is_error() {
+        return Ok(greetings);
+    }
+
+    for cmd in std::iter::once(format!("EHLO {}\r\n", ctx.connect.server_name))
+        .chain(std::iter::once(format!(
+            "MAIL FROM:<{}>\r\n",
+            ctx.mail_from
+                .reverse_path
+                .as_ref()
+                .map(vsmtp_common::Address::full)
+                .unwrap_or_default()
+        )))
+        .chain(
+            ctx.rcpt_to
+                .forward_paths
+                .iter()
+                .map(|rcpt| format!("RCPT TO:<{}>\r\n", rcpt.address.full())),
+        )
+    {
+        let reply = command(&mut stream, &cmd).await?;
+        if reply.code().is_error() {
+            return Ok(reply);
+        }
+    }
+
+    let reply = command(&mut stream, "DATA\r\n").await?;
+    if !matches!(
+        reply.code(),
+        ReplyCode::Code { code: 354 } | ReplyCode::Enhanced { code: 354, .. }
+    ) {
+        return Ok(reply);
+    }
+
+    let mut content = message
+        .inner()
+        .to_string()
+        .split_inclusive("\r\n")
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{line}")
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<String>();
+    if !content.ends_with("\r\n") {
+        content.push_str("\r\n");
+    }
+    content.push_str(".\r\n");
+
+    let verdict = command(&mut stream, &content).await?;
+
+    if let Err(error) = command(&mut stream, "QUIT\r\n").await {
+        tracing::debug!(%error, "Filter did not close the connection cleanly.");
+    }
+
+    tracing::info!(verdict = %verdict.code(), "Message filtered.");
+
+    Ok(verdict)
+}
@@ -14,14 +14,20 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::on_mail::OnMail;
+use super::milter::{Milters, Verdict};
+use crate::{on_mail::OnMail, rate_limiter::RateLimiter};
 use tokio_rustls::rustls;
 use vqueue::GenericQueueManager;
-use vsmtp_common::{status::Status, Address, CodeID, Reply, Stage, TransactionType};
-use vsmtp_config::Config;
+use vsmtp_common::{
+    auth::{BearerToken, TokenError, TokenValidator},
+    rcpt::Rcpt,
+    status::Status,
+    Address, CodeID, NullSenderPolicy, Reply, ReplyCode, Stage, TransactionType, VerifyPolicy,
+};
+use vsmtp_config::{field::FieldServerVirtual, Config};
 use vsmtp_protocol::{
-    AcceptArgs, AuthArgs, AuthError, CallbackWrap, EhloArgs, Error, HeloArgs, MailFromArgs,
-    RcptToArgs, ReceiverContext,
+    AcceptArgs, AuthArgs, AuthError, CallbackWrap, EhloArgs, Error, ExpnArgs, HeloArgs,
+    MailFromArgs, ParseArgsError, RcptToArgs, ReceiverContext, UnparsedArgs, VrfyArgs,
 };
 use vsmtp_rule_engine::{ExecutionStage, RuleEngine, RuleState};
 
@@ -44,6 +50,24 @@ pub struct Handler<M: OnMail> {
     pub(super) rustls_config: Option<std::sync::Arc<rustls::ServerConfig>>,
     pub(super) rule_engine: std::sync::Arc<RuleEngine>,
     pub(super) queue_manager: std::sync::Arc<dyn GenericQueueManager>,
+    pub(super) milters: Milters,
+    pub(super) shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    /// Number of messages accepted during the session.
+    pub(super) transaction_count: u64,
+    /// Slots of `server.queues.working.processing_count_max`, shared by the sessions.
+    pub(super) processing_limit: Option<std::sync::Arc<tokio::sync::Semaphore>>,
+    /// Slot held from the `DATA` command until the message is processed.
+    pub(super) processing_permit: Option<tokio::sync::OwnedSemaphorePermit>,
+    /// Verify the bearer tokens of the `XOAUTH2` and `OAUTHBEARER` mechanisms.
+    pub(super) token_validator: Option<std::sync::Arc<dyn TokenValidator>>,
+    /// Limits of `server.rate_limit`, shared by the sessions.
+    pub(super) rate_limiter: Option<std::sync::Arc<RateLimiter>>,
+    /// Lowercase domains of the recipients accepted in the transaction, the latest
+    /// accepted last, used to resolve the virtual entries of `server.virtual`.
+    pub(super) rcpt_domains: Vec<String>,
+    /// Size declared with the `SIZE` parameter of `MAIL FROM`, checked against
+    /// the overrides of the recipients' domains.
+    pub(super) message_size: Option<usize>,
 }
 
 impl<M: OnMail> Handler<M> {
@@ -55,6 +79,7 @@ impl<M: OnMail> Handler<M> {
         rustls_config: Option<std::sync::Arc<rustls::ServerConfig>>,
         rule_engine: std::sync::Arc<RuleEngine>,
         queue_manager: std::sync::Arc<dyn GenericQueueManager>,
+        shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>,
     ) -> Self {
         Self {
             on_mail,
@@ -65,27 +90,269 @@ impl<M: OnMail> Handler<M> {
             rustls_config,
             rule_engine,
             queue_manager,
+            milters: Milters::default(),
+            shutdown,
+            transaction_count: 0,
+            processing_limit: None,
+            processing_permit: None,
+            token_validator: None,
+            rate_limiter: None,
+            rcpt_domains: vec![],
+            message_size: None,
         }
     }
+
+    /// Limit the number of messages processed at the same time, the permits of
+    /// `processing_limit` being shared with the other sessions.
+    #[must_use]
+    pub fn with_processing_limit(
+        mut self,
+        processing_limit: Option<std::sync::Arc<tokio::sync::Semaphore>>,
+    ) -> Self {
+        self.processing_limit = processing_limit;
+        self
+    }
+
+    /// Verify the bearer tokens of the `XOAUTH2` and `OAUTHBEARER` mechanisms with
+    /// `token_validator`, before running the `authenticate` stage of the rules.
+    ///
+    /// Without validator, the rules are the only ones to verify the tokens.
+    #[must_use]
+    pub fn with_token_validator(
+        mut self,
+        token_validator: Option<std::sync::Arc<dyn TokenValidator>>,
+    ) -> Self {
+        self.token_validator = token_validator;
+        self
+    }
+
+    /// Limit the messages and recipients of the client IP address with `rate_limiter`,
+    /// the buckets being shared with the other sessions.
+    #[must_use]
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<std::sync::Arc<RateLimiter>>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
 }
 
 impl<M: OnMail + Send> Handler<M> {
     pub(super) fn reply_in_config(&self, code: CodeID) -> Reply {
-        self.config
-            .server
-            .smtp
-            .codes
-            .get(&code)
+        self.reply_in_virtual_config(code, None)
+    }
+
+    /// Get the reply of `code` in the virtual entry of `domain`, then in the ones of
+    /// the recipients of the transaction (the latest first), and finally in `server.smtp.codes`.
+    pub(super) fn reply_in_virtual_config(&self, code: CodeID, domain: Option<&str>) -> Reply {
+        domain
+            .into_iter()
+            .chain(self.rcpt_domains.iter().rev().map(String::as_str))
+            .filter_map(|domain| self.config.server.r#virtual.get(domain))
+            .find_map(|entry| entry.codes.get(&code))
+            .or_else(|| self.config.server.smtp.codes.get(&code))
             .expect("config ill formed")
             .clone()
     }
 
+    /// Answer `VRFY` or `EXPN` with the addresses returned by the `callback` function of
+    /// the root filtering script, or with `disabled` if the function is not used.
+    ///
+    /// Each answer takes a recipient token of `server.rate_limit`, and the CR and LF
+    /// of the addresses are removed so they cannot inject lines in the reply.
+    fn reply_verify(
+        &self,
+        policy: VerifyPolicy,
+        callback: &str,
+        argument: &str,
+        disabled: CodeID,
+    ) -> Reply {
+        if policy == VerifyPolicy::Disabled {
+            return self.reply_in_config(disabled);
+        }
+
+        if !self.is_within_rate_limit(RateLimiter::take_rcpt) {
+            return self.reply_in_config(CodeID::RateLimited);
+        }
+
+        match self.rule_engine.run_verify(&self.state, callback, argument) {
+            Ok(None) => self.reply_in_config(disabled),
+            Ok(Some(addresses)) => {
+                let addresses = addresses
+                    .iter()
+                    .map(|address| address.replace(|c: char| c == '\r' || c == '\n', ""))
+                    .filter(|address| !address.is_empty())
+                    .collect::<Vec<_>>();
+
+                if addresses.is_empty() {
+                    return self.reply_in_config(CodeID::VerifyNotFound);
+                }
+
+                Reply::new(
+                    ReplyCode::Enhanced {
+                        code: 250,
+                        enhanced: "2.1.5".to_string(),
+                    },
+                    format!("{}\r\n", addresses.join("\r\n")),
+                )
+            }
+            Err(error) => {
+                tracing::warn!(%error, "Failed to answer the {callback} command.");
+                self.reply_in_config(CodeID::Failure)
+            }
+        }
+    }
+
+    /// Get the most restrictive limit among the domains of the recipients of the
+    /// transaction and `domain`, `default` being used for the domains without override.
+    pub(super) fn limit_in_virtual_config(
+        &self,
+        limit: fn(&FieldServerVirtual) -> Option<usize>,
+        default: usize,
+        domain: Option<&str>,
+    ) -> usize {
+        domain
+            .into_iter()
+            .chain(self.rcpt_domains.iter().map(String::as_str))
+            .map(|domain| {
+                self.config
+                    .server
+                    .r#virtual
+                    .get(domain)
+                    .and_then(limit)
+                    .unwrap_or(default)
+            })
+            .min()
+            .unwrap_or(default)
+    }
+
+    /// Take a token of the client IP address with `take`, logging the address
+    /// if its limit is exceeded.
+    fn is_within_rate_limit(&self, take: fn(&RateLimiter, std::net::IpAddr) -> bool) -> bool {
+        let rate_limiter = match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter,
+            None => return true,
+        };
+
+        let ip = self
+            .state
+            .context()
+            .read()
+            .expect("state poisoned")
+            .client_addr()
+            .ip();
+
+        if take(rate_limiter, ip) {
+            return true;
+        }
+        tracing::warn!(%ip, "Rate limit reached, rejecting command.");
+        false
+    }
+
+    /// Override the reply of the rules with the verdict of the milters.
+    pub(super) fn reply_with_milter_verdict(
+        ctx: &mut ReceiverContext,
+        reply: Reply,
+        verdict: Verdict,
+        deny_on_reject: bool,
+    ) -> Reply {
+        match verdict {
+            Verdict::Reject(milter_reply) | Verdict::TempFail(milter_reply) => {
+                if deny_on_reject {
+                    ctx.deny();
+                }
+                milter_reply
+            }
+            Verdict::Continue | Verdict::Discard | Verdict::Quarantine(_) => reply,
+        }
+    }
+
+    /// Wait for a slot to process a message, if the number of messages processed is limited.
+    pub(super) async fn acquire_processing_permit(&mut self) {
+        if self.processing_permit.is_some() {
+            return;
+        }
+
+        if let Some(processing_limit) = &self.processing_limit {
+            if processing_limit.available_permits() == 0 {
+                tracing::warn!("Processing count max reached, waiting for a message to be done.");
+            }
+
+            self.processing_permit = Some(
+                processing_limit
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed"),
+            );
+        }
+    }
+
+    /// Has a recipient been accepted in the transaction, the refused ones being removed.
+    fn has_recipients(&self) -> bool {
+        std::iter::once(&self.state)
+            .chain(self.state_internal.as_ref())
+            .any(|state| {
+                state
+                    .context()
+                    .read()
+                    .expect("state poisoned")
+                    .forward_paths()
+                    .map_or(false, |forward_paths| !forward_paths.is_empty())
+            })
+    }
+
+    /// Check the thresholds of free space on the spool, if configured.
+    pub(super) fn has_enough_free_space(&self) -> bool {
+        let min_free_space = match &self.config.server.queues.min_free_space {
+            Some(min_free_space) => min_free_space,
+            None => return true,
+        };
+
+        match vsmtp_common::libc_abstraction::statvfs(&self.config.server.queues.dirpath) {
+            Ok((available, total)) => {
+                let enough = available >= min_free_space.bytes
+                    && u128::from(available) * 100
+                        >= u128::from(total) * u128::from(min_free_space.percent);
+
+                if !enough {
+                    tracing::error!(
+                        available,
+                        total,
+                        min_bytes = min_free_space.bytes,
+                        min_percent = min_free_space.percent,
+                        "Not enough free space on the spool, refusing the message."
+                    );
+                }
+                enough
+            }
+            Err(error) => {
+                tracing::warn!(%error, "Failed to get the free space on the spool.");
+                true
+            }
+        }
+    }
+
     pub(super) fn reply_or_code_in_config(
         &self,
         code_or_reply: either::Either<CodeID, Reply>,
+    ) -> Reply {
+        self.reply_or_code_in_virtual_config(code_or_reply, None)
+    }
+
+    /// Same as [`Self::reply_or_code_in_config`], the code being looked up in the
+    /// virtual entry of `domain` first.
+    fn reply_or_code_in_virtual_config(
+        &self,
+        code_or_reply: either::Either<CodeID, Reply>,
+        domain: Option<&str>,
     ) -> Reply {
         match code_or_reply {
-            either::Left(code) => self.reply_in_config(code),
+            either::Left(code) => {
+                let mut reply = self.reply_in_virtual_config(code, domain);
+                if let Some(reason) = code.reason() {
+                    reply.set(reply.text().replace("{reason}", reason));
+                }
+                reply
+            }
             either::Right(reply) => reply,
         }
     }
@@ -98,7 +365,15 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
     }
 
     async fn on_accept(&mut self, ctx: &mut ReceiverContext, args: AcceptArgs) -> Reply {
-        self.on_accept_inner(ctx, &args)
+        let reply = self.on_accept_inner(ctx, &args);
+
+        if reply.code().is_error() || self.config.server.smtp.milters.is_empty() {
+            return reply;
+        }
+
+        self.milters = Milters::connect(&self.config.server.smtp.milters).await;
+        let verdict = self.milters.on_connect(args.client_addr).await;
+        Self::reply_with_milter_verdict(ctx, reply, verdict, true)
     }
 
     async fn on_post_tls_handshake(
@@ -126,6 +401,14 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
         self.on_auth_inner(ctx, args)
     }
 
+    async fn on_bearer_token(
+        &mut self,
+        _: &mut ReceiverContext,
+        token: BearerToken,
+    ) -> Result<(), TokenError> {
+        self.on_bearer_token_inner(token)
+    }
+
     async fn on_post_auth(
         &mut self,
         ctx: &mut ReceiverContext,
@@ -135,14 +418,67 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
     }
 
     async fn on_helo(&mut self, ctx: &mut ReceiverContext, args: HeloArgs) -> Reply {
-        self.on_helo_inner(ctx, args)
+        let client_name = args.client_name.clone();
+        let reply = self.on_helo_inner(ctx, args);
+
+        if reply.code().is_error() {
+            return reply;
+        }
+
+        let verdict = self.milters.on_helo(&client_name).await;
+        Self::reply_with_milter_verdict(ctx, reply, verdict, false)
     }
 
     async fn on_ehlo(&mut self, ctx: &mut ReceiverContext, args: EhloArgs) -> Reply {
-        self.on_ehlo_inner(ctx, args)
+        let client_name = args.client_name.to_string();
+        let reply = self.on_ehlo_inner(ctx, args);
+
+        if reply.code().is_error() {
+            return reply;
+        }
+
+        let verdict = self.milters.on_helo(&client_name).await;
+        Self::reply_with_milter_verdict(ctx, reply, verdict, false)
     }
 
     async fn on_mail_from(&mut self, ctx: &mut ReceiverContext, args: MailFromArgs) -> Reply {
+        self.rcpt_domains.clear();
+        self.message_size = args.message_size;
+
+        if !self.is_within_rate_limit(RateLimiter::take_message) {
+            return self.reply_in_config(CodeID::RateLimited);
+        }
+
+        // NOTE: `SIZE=0` means the size is unknown, the limit is then checked while receiving the message.
+        if args
+            .message_size
+            .map_or(false, |size| size > self.config.server.message_size_limit)
+        {
+            return self.reply_in_config(CodeID::MessageSizeExceeded);
+        }
+
+        if self.config.server.smtp.smtputf8_strict
+            && !args.use_smtputf8
+            && args
+                .reverse_path
+                .as_ref()
+                .map_or(false, |reverse_path| !reverse_path.is_ascii())
+        {
+            return self.reply_in_config(CodeID::SmtpUtf8Required);
+        }
+
+        if args.require_tls
+            && !self
+                .state
+                .context()
+                .read()
+                .expect("state poisoned")
+                .is_secured()
+        {
+            return self.reply_in_config(CodeID::RequireTlsNotSecured);
+        }
+
+        let milter_reverse_path = args.reverse_path.clone().unwrap_or_default();
         let reverse_path = args
             .reverse_path
             .map(|reverse_path| reverse_path.parse().expect("handle invalid mailbox"));
@@ -153,6 +489,45 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
             .expect("state poisoned")
             .to_mail_from(reverse_path)
             .expect("bad state");
+        self.state
+            .context()
+            .write()
+            .expect("state poisoned")
+            .set_dsn_parameters(args.dsn_return, args.envelop_id)
+            .expect("bad state");
+        self.state
+            .context()
+            .write()
+            .expect("state poisoned")
+            .set_smtputf8(args.use_smtputf8)
+            .expect("bad state");
+        self.state
+            .context()
+            .write()
+            .expect("state poisoned")
+            .set_require_tls(args.require_tls)
+            .expect("bad state");
+
+        // NOTE: the submitter given by a client which is not authenticated is not trusted (RFC 4954 5).
+        let auth_mailbox = args.auth_mailbox.map(|auth_mailbox| {
+            if self
+                .state
+                .context()
+                .read()
+                .expect("state poisoned")
+                .is_authenticated()
+            {
+                auth_mailbox
+            } else {
+                "<>".to_owned()
+            }
+        });
+        self.state
+            .context()
+            .write()
+            .expect("state poisoned")
+            .set_auth_mailbox(auth_mailbox)
+            .expect("bad state");
 
         let e = match self.rule_engine.run_when(
             &self.state,
@@ -170,11 +545,25 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
             Status::Delegated(_) => unreachable!(),
         };
 
-        self.reply_or_code_in_config(e)
+        let reply = self.reply_or_code_in_config(e);
+        if reply.code().is_error() {
+            return reply;
+        }
+
+        let verdict = self.milters.on_mail_from(&milter_reverse_path).await;
+        Self::reply_with_milter_verdict(ctx, reply, verdict, false)
     }
 
     #[allow(clippy::too_many_lines)]
     async fn on_rcpt_to(&mut self, ctx: &mut ReceiverContext, args: RcptToArgs) -> Reply {
+        let forward_path: Address = args
+            .forward_path
+            .parse()
+            .expect("todo: handle invalid mailbox");
+        // NOTE: the domain names are case-insensitive, the keys of `server.virtual` are lowercase.
+        let lowercase_domain = forward_path.domain().to_lowercase();
+        let domain = lowercase_domain.as_str();
+
         // FIXME: handle internal state too ??
         if self
             .state
@@ -183,15 +572,60 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
             .expect("state poisoned")
             .forward_paths()
             .map_or(0, Vec::len)
-            >= self.config.server.smtp.rcpt_count_max
+            >= self.limit_in_virtual_config(
+                |entry| entry.rcpt_count_max,
+                self.config.server.smtp.rcpt_count_max,
+                Some(domain),
+            )
         {
-            return self.reply_in_config(CodeID::TooManyRecipients);
+            return self.reply_in_virtual_config(CodeID::TooManyRecipients, Some(domain));
         }
 
-        let forward_path = args
-            .forward_path
-            .parse()
-            .expect("todo: handle invalid mailbox");
+        // NOTE: `server.message_size_limit` is checked by `MAIL FROM`, the overrides
+        // of the virtual entries once the domains of the recipients are known.
+        if self.message_size.map_or(false, |size| {
+            size > self.limit_in_virtual_config(
+                |entry| entry.message_size_limit,
+                self.config.server.message_size_limit,
+                Some(domain),
+            )
+        }) {
+            return self.reply_in_virtual_config(CodeID::MessageSizeExceeded, Some(domain));
+        }
+
+        if self.config.server.smtp.null_sender.policy == NullSenderPolicy::SingleRecipient {
+            let ctx = self.state.context();
+            let ctx = ctx.read().expect("state poisoned");
+
+            if ctx.is_bounce().expect("bad state") && ctx.forward_paths().map_or(0, Vec::len) != 0 {
+                tracing::warn!("Message with a null sender to more than one recipient.");
+                return self.reply_in_virtual_config(CodeID::NullSenderMultipleRcpt, Some(domain));
+            }
+        }
+
+        if !self.is_within_rate_limit(RateLimiter::take_rcpt) {
+            return self.reply_in_virtual_config(CodeID::RateLimited, Some(domain));
+        }
+
+        if self.config.server.smtp.smtputf8_strict
+            && !args.forward_path.is_ascii()
+            && !self
+                .state
+                .context()
+                .read()
+                .expect("state poisoned")
+                .use_smtputf8()
+                .expect("bad state")
+        {
+            return self.reply_in_virtual_config(CodeID::SmtpUtf8Required, Some(domain));
+        }
+
+        let rcpt = {
+            let mut rcpt = Rcpt::new(forward_path.clone());
+            rcpt.notify_on = args.notify_on.unwrap_or_default();
+            rcpt.original_forward_path = args.original_forward_path;
+            rcpt
+        };
 
         let is_internal = {
             let ctx = self.state.context();
@@ -240,9 +674,7 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
                         .expect("has been set above")
                         .context();
                     let mut internal_guard = internal_ctx.write().expect("state poisoned");
-                    internal_guard
-                        .add_forward_path(forward_path)
-                        .expect("bad state");
+                    internal_guard.add_rcpt(rcpt).expect("bad state");
                     internal_guard
                         .set_transaction_type(TransactionType::Internal)
                         .expect("bad state");
@@ -264,7 +696,7 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
                         forward_path.domain()
                     );
 
-                    ctx.add_forward_path(forward_path).expect("bad state");
+                    ctx.add_rcpt(rcpt).expect("bad state");
                     ctx.set_transaction_type(reverse_path.as_ref().map_or(
                         TransactionType::Incoming(None),
                         |reverse_path| TransactionType::Outgoing {
@@ -290,21 +722,21 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
                         },
                     ))
                     .expect("bad state");
-                    ctx.add_forward_path(forward_path).unwrap();
+                    ctx.add_rcpt(rcpt).unwrap();
 
                     false
                 }
             }
         };
 
-        let state = match self.state_internal.as_mut() {
-            Some(state_internal) if is_internal => state_internal,
-            _ => &mut self.state,
+        let state = match self.state_internal.as_ref() {
+            Some(state_internal) if is_internal => state_internal.clone(),
+            _ => self.state.clone(),
         };
 
         let e = match self
             .rule_engine
-            .run_when(state, &mut self.skipped, ExecutionStage::RcptTo)
+            .run_when(&state, &mut self.skipped, ExecutionStage::RcptTo)
         {
             Status::Info(e) | Status::Faccept(e) | Status::Accept(e) => e,
             Status::Quarantine(_) | Status::Next | Status::DelegationResult => {
@@ -317,7 +749,64 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
             Status::Delegated(_) => unreachable!(),
         };
 
-        self.reply_or_code_in_config(e)
+        let reply = self.reply_or_code_in_virtual_config(e, Some(domain));
+        let reply = if reply.code().is_error() {
+            reply
+        } else {
+            let verdict = self.milters.on_rcpt_to(&args.forward_path).await;
+            Self::reply_with_milter_verdict(ctx, reply, verdict, false)
+        };
+
+        if reply.code().is_error() {
+            // NOTE: a recipient refused without closing the session (`state::info`,
+            //       `greylist()`) is not part of the transaction.
+            state
+                .context()
+                .write()
+                .expect("state poisoned")
+                .remove_forward_path(&forward_path)
+                .expect("bad state");
+        } else {
+            self.rcpt_domains.retain(|i| i != domain);
+            self.rcpt_domains.push(domain.to_owned());
+        }
+
+        reply
+    }
+
+    async fn on_data(&mut self) -> Reply {
+        if !self.has_recipients() {
+            return self.reply_in_config(CodeID::BadSequence);
+        }
+
+        if self.has_enough_free_space() {
+            self.acquire_processing_permit().await;
+            self.reply_in_config(CodeID::DataStart)
+        } else {
+            self.reply_in_config(CodeID::InsufficientStorage)
+        }
+    }
+
+    async fn on_help(&mut self, _: UnparsedArgs) -> Reply {
+        self.reply_in_config(CodeID::Help)
+    }
+
+    async fn on_vrfy(&mut self, _: &mut ReceiverContext, args: VrfyArgs) -> Reply {
+        self.reply_verify(
+            self.config.server.smtp.vrfy,
+            "vrfy",
+            &args.query,
+            CodeID::VrfyCannotVerify,
+        )
+    }
+
+    async fn on_expn(&mut self, _: &mut ReceiverContext, args: ExpnArgs) -> Reply {
+        self.reply_verify(
+            self.config.server.smtp.expn,
+            "expn",
+            &args.list,
+            CodeID::ExpnDisabled,
+        )
     }
 
     async fn on_rset(&mut self) -> Reply {
@@ -328,6 +817,8 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
             .reset();
 
         self.state_internal = None;
+        self.rcpt_domains.clear();
+        self.milters.abort().await;
 
         // TODO: reset message?
 
@@ -339,7 +830,74 @@ impl<M: OnMail + Send + Sync> vsmtp_protocol::ReceiverHandler for Handler<M> {
         ctx: &mut ReceiverContext,
         stream: impl tokio_stream::Stream<Item = Result<Vec<u8>, Error>> + Send + Unpin,
     ) -> Reply {
-        self.on_message_inner(ctx, stream).await
+        // NOTE: the permit is acquired by `DATA` and the first `BDAT`, this is a safeguard.
+        self.acquire_processing_permit().await;
+        let reply = self.on_message_inner(ctx, stream).await;
+        self.processing_permit = None;
+        self.rcpt_domains.clear();
+
+        if !reply.code().is_error() {
+            self.transaction_count += 1;
+        }
+        reply
+    }
+
+    async fn on_quit(&mut self) -> Reply {
+        {
+            let ctx = self.state.context();
+            let ctx = ctx.read().expect("state poisoned");
+            tracing::info!(
+                client = %ctx.client_addr(),
+                duration = ?(time::OffsetDateTime::now_utc() - *ctx.connection_timestamp()),
+                transactions = self.transaction_count,
+                "Session closed by the client."
+            );
+        }
+
+        let mut reply = self.reply_in_config(CodeID::Closing);
+        reply.set(
+            reply
+                .text()
+                .replace("{transaction_count}", &self.transaction_count.to_string()),
+        );
+        reply
+    }
+
+    async fn on_args_error(&mut self, error: ParseArgsError) -> Reply {
+        match error {
+            ParseArgsError::DuplicateParam(keyword) => {
+                tracing::warn!(%keyword, "Parameter given more than once.");
+            }
+            ParseArgsError::LegacyRouting(path) => {
+                tracing::warn!(%path, "Percent-hack or UUCP address refused.");
+                return self.reply_in_config(CodeID::LegacyRouting);
+            }
+            ParseArgsError::UnknownParam(keyword) => {
+                tracing::warn!(%keyword, "Parameter not supported.");
+                return self.reply_in_config(CodeID::UnknownParam);
+            }
+            _ => {}
+        }
+
+        self.reply_in_config(CodeID::SyntaxErrorParams)
+    }
+
+    async fn on_shutdown(&mut self) -> Option<Reply> {
+        if self.shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+            tracing::info!("Server is shutting down, closing the connection.");
+            Some(self.reply_in_config(CodeID::ShuttingDown))
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    async fn on_command_handled(
+        &mut self,
+        verb: vsmtp_protocol::Verb,
+        elapsed: std::time::Duration,
+    ) {
+        crate::metrics::METRICS.record_command(verb, elapsed);
     }
 
     async fn on_hard_error(&mut self, ctx: &mut ReceiverContext, reply: Reply) -> Reply {
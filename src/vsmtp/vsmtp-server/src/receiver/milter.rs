@@ -0,0 +1,553 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+
+//! Client side of the milter protocol (version 6), as spoken by sendmail and
+//! postfix to the mail filters (opendkim, spf-milter, ...).
+
+use anyhow::Context;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use vsmtp_common::{Reply, ReplyCode};
+use vsmtp_config::field::FieldServerSMTPMilter;
+use vsmtp_mail_parser::MessageBody;
+
+const VERSION: u32 = 6;
+/// Maximum size of a body chunk.
+const CHUNK_SIZE: usize = 65535;
+/// Maximum size of a packet sent by a milter.
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+mod command {
+    pub const ABORT: u8 = b'A';
+    pub const BODY: u8 = b'B';
+    pub const CONNECT: u8 = b'C';
+    pub const BODYEOB: u8 = b'E';
+    pub const HELO: u8 = b'H';
+    pub const HEADER: u8 = b'L';
+    pub const MAIL: u8 = b'M';
+    pub const EOH: u8 = b'N';
+    pub const OPTNEG: u8 = b'O';
+    pub const RCPT: u8 = b'R';
+    pub const DATA: u8 = b'T';
+}
+
+mod response {
+    pub const ADDRCPT: u8 = b'+';
+    pub const DELRCPT: u8 = b'-';
+    pub const ACCEPT: u8 = b'a';
+    pub const REPLBODY: u8 = b'b';
+    pub const CONTINUE: u8 = b'c';
+    pub const DISCARD: u8 = b'd';
+    pub const ADDHEADER: u8 = b'h';
+    pub const INSHEADER: u8 = b'i';
+    pub const CHGHEADER: u8 = b'm';
+    pub const PROGRESS: u8 = b'p';
+    pub const QUARANTINE: u8 = b'q';
+    pub const REJECT: u8 = b'r';
+    pub const SKIP: u8 = b's';
+    pub const TEMPFAIL: u8 = b't';
+    pub const REPLYCODE: u8 = b'y';
+}
+
+/// Modifications of the message the milters are allowed to request.
+mod action {
+    pub const ADDHDRS: u32 = 0x01;
+    pub const CHGBODY: u32 = 0x02;
+    pub const CHGHDRS: u32 = 0x10;
+    pub const QUARANTINE: u32 = 0x20;
+}
+
+/// Steps a milter can ask not to receive (`NO*`) or not to reply to (`NR_*`).
+mod protocol {
+    pub const NOCONNECT: u32 = 0x01;
+    pub const NOHELO: u32 = 0x02;
+    pub const NOMAIL: u32 = 0x04;
+    pub const NORCPT: u32 = 0x08;
+    pub const NOBODY: u32 = 0x10;
+    pub const NOHDRS: u32 = 0x20;
+    pub const NOEOH: u32 = 0x40;
+    pub const NR_HDR: u32 = 0x80;
+    pub const NODATA: u32 = 0x200;
+    pub const NR_CONN: u32 = 0x1000;
+    pub const NR_HELO: u32 = 0x2000;
+    pub const NR_MAIL: u32 = 0x4000;
+    pub const NR_RCPT: u32 = 0x8000;
+    pub const NR_DATA: u32 = 0x10000;
+    pub const NR_EOH: u32 = 0x40000;
+    pub const NR_BODY: u32 = 0x80000;
+
+    pub const SUPPORTED: u32 = NOCONNECT
+        | NOHELO
+        | NOMAIL
+        | NORCPT
+        | NOBODY
+        | NOHDRS
+        | NOEOH
+        | NR_HDR
+        | NODATA
+        | NR_CONN
+        | NR_HELO
+        | NR_MAIL
+        | NR_RCPT
+        | NR_DATA
+        | NR_EOH
+        | NR_BODY;
+
+    /// The flags (skip, no reply) associated with a command.
+    pub const fn flags_of(command: u8) -> (u32, u32) {
+        match command {
+            super::command::CONNECT => (NOCONNECT, NR_CONN),
+            super::command::HELO => (NOHELO, NR_HELO),
+            super::command::MAIL => (NOMAIL, NR_MAIL),
+            super::command::RCPT => (NORCPT, NR_RCPT),
+            super::command::DATA => (NODATA, NR_DATA),
+            super::command::HEADER => (NOHDRS, NR_HDR),
+            super::command::EOH => (NOEOH, NR_EOH),
+            super::command::BODY => (NOBODY, NR_BODY),
+            _ => (0, 0),
+        }
+    }
+}
+
+/// The decision of the milters for a stage of the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Verdict {
+    /// Go on with the transaction.
+    Continue,
+    /// Refuse the command with the given reply.
+    Reject(Reply),
+    /// Refuse the command temporarily with the given reply.
+    TempFail(Reply),
+    /// Accept the message but silently drop it.
+    Discard,
+    /// Accept the message and put it in quarantine.
+    Quarantine(String),
+}
+
+struct Milter {
+    address: std::net::SocketAddr,
+    stream: tokio::net::TcpStream,
+    timeout: std::time::Duration,
+    /// Protocol flags negotiated with the milter.
+    protocol: u32,
+    /// The milter accepted the message, it must not be called until the next transaction.
+    accepted: bool,
+}
+
+impl Milter {
+    async fn connect(config: &FieldServerSMTPMilter) -> anyhow::Result<Self> {
+        let stream = tokio::time::timeout(
+            config.timeout,
+            tokio::net::TcpStream::connect(config.address),
+        )
+        .await
+        .context("connection timed out")??;
+
+        let mut milter = Self {
+            address: config.address,
+            stream,
+            timeout: config.timeout,
+            protocol: 0,
+            accepted: false,
+        };
+
+        let mut negotiation = Vec::with_capacity(12);
+        negotiation.extend_from_slice(&VERSION.to_be_bytes());
+        negotiation.extend_from_slice(
+            &(action::ADDHDRS | action::CHGBODY | action::CHGHDRS | action::QUARANTINE)
+                .to_be_bytes(),
+        );
+        negotiation.extend_from_slice(&protocol::SUPPORTED.to_be_bytes());
+        milter.write(command::OPTNEG, &negotiation).await?;
+
+        let (code, data) = milter.read().await?;
+        anyhow::ensure!(
+            code == command::OPTNEG && data.len() >= 12,
+            "invalid option negotiation"
+        );
+        let version = u32::from_be_bytes(data[0..4].try_into().expect("length checked"));
+        anyhow::ensure!(version >= 2, "unsupported milter version {version}");
+        milter.protocol = u32::from_be_bytes(data[8..12].try_into().expect("length checked"))
+            & protocol::SUPPORTED;
+
+        Ok(milter)
+    }
+
+    async fn write(&mut self, code: u8, data: &[u8]) -> anyhow::Result<()> {
+        let len = u32::try_from(data.len() + 1).context("packet too long")?;
+
+        let mut packet = Vec::with_capacity(data.len() + 5);
+        packet.extend_from_slice(&len.to_be_bytes());
+        packet.push(code);
+        packet.extend_from_slice(data);
+
+        tokio::time::timeout(self.timeout, self.stream.write_all(&packet))
+            .await
+            .context("write timed out")?
+            .map_err(Into::into)
+    }
+
+    async fn read(&mut self) -> anyhow::Result<(u8, Vec<u8>)> {
+        tokio::time::timeout(self.timeout, async {
+            let len = usize::try_from(self.stream.read_u32().await?)?;
+            anyhow::ensure!(
+                (1..=MAX_PACKET_SIZE).contains(&len),
+                "invalid packet length {len}"
+            );
+
+            let mut packet = vec![0; len];
+            self.stream.read_exact(&mut packet).await?;
+            let data = packet.split_off(1);
+            Ok((packet[0], data))
+        })
+        .await
+        .context("read timed out")?
+    }
+
+    /// Send a command and wait for the verdict of the milter, taking into account
+    /// the steps it does not want to receive or to reply to.
+    async fn call(&mut self, code: u8, data: &[u8]) -> anyhow::Result<Verdict> {
+        let (skip, no_reply) = protocol::flags_of(code);
+        if self.accepted || self.protocol & skip != 0 {
+            return Ok(Verdict::Continue);
+        }
+
+        self.write(code, data).await?;
+        if self.protocol & no_reply != 0 {
+            return Ok(Verdict::Continue);
+        }
+
+        loop {
+            let (code, data) = self.read().await?;
+            match self.verdict(code, &data)? {
+                Some(verdict) => return Ok(verdict),
+                None => continue,
+            }
+        }
+    }
+
+    /// Convert a response to a verdict, `None` if the milter is not done yet.
+    fn verdict(&mut self, code: u8, data: &[u8]) -> anyhow::Result<Option<Verdict>> {
+        Ok(Some(match code {
+            response::CONTINUE | response::SKIP => Verdict::Continue,
+            response::ACCEPT => {
+                self.accepted = true;
+                Verdict::Continue
+            }
+            response::REJECT => Verdict::Reject(Reply::new(
+                ReplyCode::Enhanced {
+                    code: 550,
+                    enhanced: "5.7.1".to_string(),
+                },
+                "Command rejected\r\n",
+            )),
+            response::TEMPFAIL => Verdict::TempFail(Reply::new(
+                ReplyCode::Enhanced {
+                    code: 451,
+                    enhanced: "4.7.1".to_string(),
+                },
+                "Service unavailable - try again later\r\n",
+            )),
+            response::DISCARD => {
+                self.accepted = true;
+                Verdict::Discard
+            }
+            response::REPLYCODE => {
+                let text = c_strings(data).next().unwrap_or_default();
+                let (code, text) = ReplyCode::parse(&text)
+                    .with_context(|| format!("milter replied an invalid code: '{text}'"))?;
+                let class = match code {
+                    ReplyCode::Code { code } | ReplyCode::Enhanced { code, .. } => code / 100,
+                };
+                let reply = Reply::new(code, format!("{text}\r\n"));
+
+                match class {
+                    4 => Verdict::TempFail(reply),
+                    5 => Verdict::Reject(reply),
+                    _ => Verdict::Continue,
+                }
+            }
+            response::PROGRESS => return Ok(None),
+            otherwise => anyhow::bail!("unexpected milter response '{}'", otherwise as char),
+        }))
+    }
+
+    /// Send the message and apply the modifications requested by the milter.
+    async fn end_of_message(&mut self, message: &mut MessageBody) -> anyhow::Result<Verdict> {
+        if self.accepted {
+            return Ok(Verdict::Continue);
+        }
+
+        let verdict = self.call(command::DATA, &[]).await?;
+        if verdict != Verdict::Continue {
+            return Ok(verdict);
+        }
+
+        for (name, value) in message.inner().headers() {
+            let value = value.strip_prefix(' ').unwrap_or(&value);
+            let value = value.strip_suffix("\r\n").unwrap_or(value);
+            let verdict = self
+                .call(command::HEADER, &c_string_pair(&name, value))
+                .await?;
+            if verdict != Verdict::Continue {
+                return Ok(verdict);
+            }
+        }
+
+        let verdict = self.call(command::EOH, &[]).await?;
+        if verdict != Verdict::Continue {
+            return Ok(verdict);
+        }
+
+        if let Some(body) = message.inner().body().clone() {
+            for chunk in body.as_bytes().chunks(CHUNK_SIZE) {
+                let verdict = self.call(command::BODY, chunk).await?;
+                if verdict != Verdict::Continue {
+                    return Ok(verdict);
+                }
+            }
+        }
+
+        if self.accepted {
+            return Ok(Verdict::Continue);
+        }
+
+        self.write(command::BODYEOB, &[]).await?;
+
+        let mut quarantine = None;
+        let mut new_body: Option<Vec<u8>> = None;
+        let verdict = loop {
+            let (code, data) = self.read().await?;
+            match code {
+                response::ADDHEADER => {
+                    let mut fields = c_strings(&data);
+                    let (name, value) = (fields.next(), fields.next());
+                    if let (Some(name), Some(value)) = (name, value) {
+                        message.append_header(&name, &value);
+                    }
+                }
+                response::INSHEADER | response::CHGHEADER if data.len() >= 4 => {
+                    let index = u32::from_be_bytes(data[0..4].try_into().expect("length checked"));
+                    let mut fields = c_strings(&data[4..]);
+                    let (name, value) = (fields.next(), fields.next());
+                    if let (Some(name), Some(value)) = (name, value) {
+                        // NOTE: the position of the header is only honored for the
+                        //       first one, the others are appended / modified in place.
+                        match code {
+                            response::INSHEADER if index == 0 => {
+                                message.prepend_header(&name, &value);
+                            }
+                            response::INSHEADER => message.append_header(&name, &value),
+                            _ if value.is_empty() => {
+                                message.remove_header(&name);
+                            }
+                            _ => message.set_header(&name, &value),
+                        }
+                    }
+                }
+                response::REPLBODY => new_body.get_or_insert_with(Vec::new).extend(data),
+                response::QUARANTINE => {
+                    quarantine = Some(c_strings(&data).next().unwrap_or_default());
+                }
+                response::ADDRCPT | response::DELRCPT => {
+                    tracing::warn!(milter = %self.address, "Recipient modification is not supported.");
+                }
+                _ => match self.verdict(code, &data)? {
+                    Some(verdict) => break verdict,
+                    None => continue,
+                },
+            }
+        };
+
+        if let Some(body) = new_body {
+            *message = MessageBody::new(
+                message.inner().raw_headers().clone(),
+                String::from_utf8_lossy(&body).into_owned(),
+            );
+        }
+
+        Ok(match (verdict, quarantine) {
+            (Verdict::Continue, Some(reason)) => Verdict::Quarantine(reason),
+            (verdict, _) => verdict,
+        })
+    }
+}
+
+/// Split null terminated strings.
+fn c_strings(data: &[u8]) -> impl Iterator<Item = String> + '_ {
+    data.split(|byte| *byte == 0)
+        .map(|field| String::from_utf8_lossy(field).into_owned())
+}
+
+fn c_string_pair(first: &str, second: &str) -> Vec<u8> {
+    [first.as_bytes(), &[0], second.as_bytes(), &[0]].concat()
+}
+
+/// The milters connected for a SMTP session.
+#[derive(Default)]
+pub(super) struct Milters {
+    milters: Vec<Milter>,
+    /// A transaction has been started on the milters side.
+    in_transaction: bool,
+    /// A milter asked to discard the message of the current transaction.
+    discard: bool,
+}
+
+impl Milters {
+    /// Connect to all the milters of the configuration, a milter that cannot
+    /// be reached is ignored for this session.
+    pub(super) async fn connect(config: &[FieldServerSMTPMilter]) -> Self {
+        let mut milters = vec![];
+        for milter in config {
+            match Milter::connect(milter).await {
+                Ok(milter) => milters.push(milter),
+                Err(error) => {
+                    tracing::warn!(milter = %milter.address, %error, "Milter unavailable.");
+                }
+            }
+        }
+
+        Self {
+            milters,
+            ..Self::default()
+        }
+    }
+
+    /// Call each milter in turn, stopping at the first one refusing the command.
+    async fn call(&mut self, code: u8, data: &[u8]) -> Verdict {
+        let mut verdict = Verdict::Continue;
+        let mut failed = vec![];
+
+        for (idx, milter) in self.milters.iter_mut().enumerate() {
+            match milter.call(code, data).await {
+                Ok(Verdict::Continue) => (),
+                Ok(Verdict::Discard) => self.discard = true,
+                Ok(otherwise) => {
+                    verdict = otherwise;
+                    break;
+                }
+                Err(error) => {
+                    tracing::warn!(milter = %milter.address, %error, "Milter failure, ignoring it for this session.");
+                    failed.push(idx);
+                }
+            }
+        }
+
+        for idx in failed.into_iter().rev() {
+            self.milters.remove(idx);
+        }
+
+        verdict
+    }
+
+    pub(super) async fn on_connect(&mut self, client_addr: std::net::SocketAddr) -> Verdict {
+        let ip = client_addr.ip().to_string();
+
+        let mut data = format!("[{ip}]").into_bytes();
+        data.push(0);
+        data.push(if client_addr.is_ipv4() { b'4' } else { b'6' });
+        data.extend_from_slice(&client_addr.port().to_be_bytes());
+        data.extend_from_slice(ip.as_bytes());
+        data.push(0);
+
+        self.call(command::CONNECT, &data).await
+    }
+
+    pub(super) async fn on_helo(&mut self, client_name: &str) -> Verdict {
+        self.call(command::HELO, &[client_name.as_bytes(), &[0]].concat())
+            .await
+    }
+
+    pub(super) async fn on_mail_from(&mut self, reverse_path: &str) -> Verdict {
+        if self.in_transaction {
+            self.abort().await;
+        }
+        self.in_transaction = true;
+
+        self.call(
+            command::MAIL,
+            &[format!("<{reverse_path}>").as_bytes(), &[0]].concat(),
+        )
+        .await
+    }
+
+    pub(super) async fn on_rcpt_to(&mut self, forward_path: &str) -> Verdict {
+        self.call(
+            command::RCPT,
+            &[format!("<{forward_path}>").as_bytes(), &[0]].concat(),
+        )
+        .await
+    }
+
+    /// Run the message through the milters, which can modify it.
+    pub(super) async fn on_message(&mut self, message: &mut MessageBody) -> Verdict {
+        let mut verdict = Verdict::Continue;
+        let mut failed = vec![];
+
+        for (idx, milter) in self.milters.iter_mut().enumerate() {
+            match milter.end_of_message(message).await {
+                Ok(Verdict::Continue) => (),
+                Ok(Verdict::Discard) => self.discard = true,
+                Ok(quarantine @ Verdict::Quarantine(_)) => verdict = quarantine,
+                Ok(otherwise) => {
+                    verdict = otherwise;
+                    break;
+                }
+                Err(error) => {
+                    tracing::warn!(milter = %milter.address, %error, "Milter failure, ignoring it for this session.");
+                    failed.push(idx);
+                }
+            }
+        }
+
+        for idx in failed.into_iter().rev() {
+            self.milters.remove(idx);
+        }
+
+        let discard = self.discard;
+        if matches!(verdict, Verdict::Reject(_) | Verdict::TempFail(_)) {
+            // NOTE: the milters after the one refusing the message did not see its end.
+            self.abort().await;
+        } else {
+            self.end_transaction();
+        }
+
+        match verdict {
+            Verdict::Continue | Verdict::Quarantine(_) if discard => Verdict::Discard,
+            otherwise => otherwise,
+        }
+    }
+
+    /// Cancel the current transaction.
+    pub(super) async fn abort(&mut self) {
+        if self.in_transaction {
+            for milter in &mut self.milters {
+                if let Err(error) = milter.write(command::ABORT, &[]).await {
+                    tracing::debug!(milter = %milter.address, %error, "Failed to abort.");
+                }
+            }
+        }
+        self.end_transaction();
+    }
+
+    fn end_transaction(&mut self) {
+        self.in_transaction = false;
+        self.discard = false;
+        for milter in &mut self.milters {
+            milter.accepted = false;
+        }
+    }
+}
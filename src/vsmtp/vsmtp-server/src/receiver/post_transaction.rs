@@ -15,14 +15,30 @@
  *
 */
 
+use super::{before_queue_filter, milter::Verdict, rejected_sample};
 use crate::{Handler, OnMail};
 use tokio_stream::StreamExt;
-use vsmtp_common::{status::Status, CodeID, Reply};
+use vsmtp_common::{status::Status, CodeID, ContextFinished, Reply};
 use vsmtp_mail_parser::{BasicParser, Mail, MailParser, MessageBody, ParserError, RawBody};
 use vsmtp_protocol::{Error, ReceiverContext};
 use vsmtp_rule_engine::{ExecutionStage, RuleEngine, RuleState};
 
 impl<M: OnMail + Send> Handler<M> {
+    /// Write the messages with a null reverse path to the quarantine of
+    /// `server.smtp.null_sender.quarantine`, unless the rules already did.
+    fn quarantine_null_sender(&self, mail_ctx: &ContextFinished, status: Status) -> Status {
+        match &self.config.server.smtp.null_sender.quarantine {
+            Some(queue)
+                if mail_ctx.mail_from.reverse_path.is_none()
+                    && !matches!(status, Status::Quarantine(_)) =>
+            {
+                tracing::info!(%queue, "Message with a null sender, writing it to quarantine.");
+                Status::Quarantine(queue.clone())
+            }
+            _ => status,
+        }
+    }
+
     pub(super) fn handle_preq_header(
         rule_engine: &RuleEngine,
         state: &RuleState,
@@ -67,6 +83,115 @@ impl<M: OnMail + Send> Handler<M> {
         status
     }
 
+    /// Remove the headers listed in `server.smtp.strip_headers` from the message sent by the client,
+    /// so that neither the milters nor the rules trust them.
+    ///
+    /// The messages coming back from a delegation or from the before-queue filter,
+    /// and the ones sent by the `server.trusted_networks`, are left untouched.
+    fn strip_headers(&self, mail: &mut either::Either<RawBody, Mail>) {
+        if self.config.server.smtp.strip_headers.is_empty()
+            || matches!(self.skipped, Some(Status::DelegationResult))
+        {
+            return;
+        }
+
+        let (client_addr, server_addr) = {
+            let ctx = self.state.context();
+            let ctx = ctx.read().expect("state poisoned");
+            (*ctx.client_addr(), *ctx.server_addr())
+        };
+        if self.config.server.is_trusted(client_addr.ip())
+            || self
+                .config
+                .server
+                .smtp
+                .before_queue_filter
+                .as_ref()
+                .map_or(false, |filter| filter.reinject == server_addr)
+        {
+            return;
+        }
+
+        for header in &self.config.server.smtp.strip_headers {
+            let mut count = 0_usize;
+            while match mail {
+                either::Left(raw) => raw.remove_header(header),
+                either::Right(parsed) => parsed.remove_header(header),
+            } {
+                count += 1;
+            }
+
+            if count != 0 {
+                tracing::info!(%header, %count, "Inbound headers stripped.");
+            }
+        }
+    }
+
+    /// Run the message through the milters, returning the status they impose if any.
+    async fn milter_message(
+        &mut self,
+        mail: either::Either<RawBody, Mail>,
+    ) -> (either::Either<RawBody, Mail>, Option<Status>) {
+        if self.config.server.smtp.milters.is_empty() {
+            return (mail, None);
+        }
+
+        let mut message = MessageBody::from(mail);
+        let status = match self.milters.on_message(&mut message).await {
+            Verdict::Continue => None,
+            Verdict::Reject(reply) | Verdict::TempFail(reply) => {
+                Some(Status::Info(either::Right(reply)))
+            }
+            Verdict::Discard => {
+                tracing::info!("Message discarded by a milter.");
+                Some(Status::Info(either::Left(CodeID::Ok)))
+            }
+            Verdict::Quarantine(reason) => {
+                tracing::warn!(%reason, "Message quarantined by a milter.");
+                Some(Status::Quarantine("milter".to_string()))
+            }
+        };
+
+        (either::Left(message.inner().clone()), status)
+    }
+
+    /// A denial of the rules prevails over the verdict of the milters.
+    fn with_milter_status(status: Status, milter_status: Option<Status>) -> Status {
+        match (status, milter_status) {
+            (deny @ Status::Deny(_), _) | (deny, None) => deny,
+            (_, Some(milter_status)) => milter_status,
+        }
+    }
+
+    /// Send the message to the before-queue filter if any, or to the queues.
+    async fn filter_or_queue(&mut self, mail_ctx: ContextFinished, message: MessageBody) -> Reply {
+        match &self.config.server.smtp.before_queue_filter {
+            Some(filter)
+                if mail_ctx.connect.server_addr != filter.reinject
+                    && !matches!(
+                        mail_ctx.connect.skipped,
+                        Some(Status::Quarantine(_) | Status::DelegationResult)
+                    ) =>
+            {
+                match before_queue_filter::relay(filter, &mail_ctx, &message).await {
+                    Ok(reply) => reply,
+                    Err(error) => {
+                        tracing::warn!(%error, "Before-queue filter failure.");
+                        self.reply_in_config(CodeID::Failure)
+                    }
+                }
+            }
+            _ => {
+                let code = self
+                    .on_mail
+                    .on_mail(Box::new(mail_ctx), message, self.queue_manager.clone())
+                    .await;
+
+                self.reply_in_config(code)
+            }
+        }
+    }
+
     #[allow(clippy::too_many_lines)]
     pub(super) async fn on_message_inner(
         &mut self,
@@ -74,29 +199,60 @@ impl<M: OnMail + Send> Handler<M> {
         stream: impl tokio_stream::Stream<Item = Result<Vec<u8>, Error>> + Send + Unpin,
     ) -> Reply {
         tracing::info!("SMTP handshake completed, fetching email...");
-        let stream = stream.map(|l| match l {
-            Ok(l) => Ok(l),
+        // NOTE: the protocol limits the stream with `server.message_size_limit`,
+        // the overrides of the recipients' domains are applied here.
+        let message_size_limit = self.limit_in_virtual_config(
+            |entry| entry.message_size_limit,
+            self.config.server.message_size_limit,
+            None,
+        );
+        let mut message_size = 0;
+        let mut stream = stream.map(move |l| match l {
+            Ok(l) => {
+                message_size += l.len();
+                if message_size >= message_size_limit {
+                    Err(ParserError::BufferTooLong {
+                        expected: message_size_limit,
+                        got: message_size,
+                    })
+                } else {
+                    Ok(l)
+                }
+            }
             Err(Error::Io(io)) => Err(ParserError::Io(io)),
             Err(Error::BufferTooLong { expected, got }) => {
                 Err(ParserError::BufferTooLong { expected, got })
             }
         });
 
-        let mail = match BasicParser::default().parse(stream).await {
+        let mut mail = match BasicParser::default().parse(&mut stream).await {
             Ok(mail) => mail,
-            Err(ParserError::BufferTooLong { .. }) => {
+            Err(ParserError::BufferTooLong { expected, got }) => {
+                tracing::warn!(
+                    message_size = got,
+                    message_size_limit = expected,
+                    "Message size exceeds the limit."
+                );
+                // NOTE: the rest of the message is discarded, not read as commands.
+                while stream.next().await.is_some() {}
                 return self.reply_in_config(CodeID::MessageSizeExceeded);
             }
             Err(otherwise) => todo!("handle error cleanly {:?}", otherwise),
         };
         tracing::info!("Message body fully received, processing...");
 
+        self.strip_headers(&mut mail);
+        let (mail, milter_status) = self.milter_message(mail).await;
+
         let internal_reply = if let Some(state_internal) = &self.state_internal {
-            let status = Self::handle_preq_header(
-                &self.rule_engine,
-                state_internal,
-                self.skipped.clone(),
-                mail.clone(),
+            let status = Self::with_milter_status(
+                Self::handle_preq_header(
+                    &self.rule_engine,
+                    state_internal,
+                    self.skipped.clone(),
+                    mail.clone(),
+                ),
+                milter_status.clone(),
             );
 
             let (mail_ctx, message) = std::mem::replace(&mut self.state_internal, None)
@@ -107,20 +263,24 @@ impl<M: OnMail + Send> Handler<M> {
                 .expect("has been set to finished");
 
             let reply = match status {
-                Status::Info(code_or_reply) => self.reply_or_code_in_config(code_or_reply),
+                Status::Info(code_or_reply) => {
+                    let reply = self.reply_or_code_in_config(code_or_reply);
+                    // NOTE: the rejections of the milters.
+                    if reply.code().is_error() {
+                        rejected_sample::write(&self.config, &mail_ctx, &message, &reply).await;
+                    }
+                    reply
+                }
                 Status::Deny(code_or_reply) => {
                     ctx.deny();
-                    self.reply_or_code_in_config(code_or_reply)
+                    let reply = self.reply_or_code_in_config(code_or_reply);
+                    rejected_sample::write(&self.config, &mail_ctx, &message, &reply).await;
+                    reply
                 }
                 Status::Delegated(_) => unreachable!(),
                 status => {
                     mail_ctx.connect.skipped = Some(status);
-                    let code = self
-                        .on_mail
-                        .on_mail(Box::new(mail_ctx), message, self.queue_manager.clone())
-                        .await;
-
-                    self.reply_in_config(code)
+                    self.filter_or_queue(mail_ctx, message).await
                 }
             };
             Some(reply)
@@ -128,11 +288,14 @@ impl<M: OnMail + Send> Handler<M> {
             None
         };
         let reply = {
-            let status = Self::handle_preq_header(
-                &self.rule_engine,
-                &self.state,
-                self.skipped.clone(),
-                mail,
+            let status = Self::with_milter_status(
+                Self::handle_preq_header(
+                    &self.rule_engine,
+                    &self.state,
+                    self.skipped.clone(),
+                    mail,
+                ),
+                milter_status,
             );
             let (mail_ctx, message) =
                 std::mem::replace(&mut self.state, self.rule_engine.spawn()).take();
@@ -152,6 +315,14 @@ impl<M: OnMail + Send> Handler<M> {
                     mail_ctx.connect.connect_uuid,
                 )
                 .expect("bad state")
+                // NOTE: the TLS and AUTH state belong to the connection, not to the transaction.
+                .restore_security(mail_ctx.connect.tls.clone(), mail_ctx.connect.auth.clone())
+                .expect("bad state");
+
+            self.state
+                .context()
+                .write()
+                .expect("state poisoned")
                 .to_helo(
                     mail_ctx.helo.client_name.clone(),
                     mail_ctx.helo.using_deprecated,
@@ -162,20 +333,25 @@ impl<M: OnMail + Send> Handler<M> {
                 None
             } else {
                 let reply = match status {
-                    Status::Info(code_or_reply) => self.reply_or_code_in_config(code_or_reply),
+                    Status::Info(code_or_reply) => {
+                        let reply = self.reply_or_code_in_config(code_or_reply);
+                        // NOTE: the rejections of the milters.
+                        if reply.code().is_error() {
+                            rejected_sample::write(&self.config, &mail_ctx, &message, &reply).await;
+                        }
+                        reply
+                    }
                     Status::Deny(code_or_reply) => {
                         ctx.deny();
-                        self.reply_or_code_in_config(code_or_reply)
+                        let reply = self.reply_or_code_in_config(code_or_reply);
+                        rejected_sample::write(&self.config, &mail_ctx, &message, &reply).await;
+                        reply
                     }
                     Status::Delegated(_) => unreachable!(),
                     status => {
-                        mail_ctx.connect.skipped = Some(status);
-                        let code = self
-                            .on_mail
-                            .on_mail(Box::new(mail_ctx), message, self.queue_manager.clone())
-                            .await;
-
-                        self.reply_in_config(code)
+                        mail_ctx.connect.skipped =
+                            Some(self.quarantine_null_sender(&mail_ctx, status));
+                        self.filter_or_queue(mail_ctx, message).await
                     }
                 };
 
@@ -17,7 +17,11 @@
 
 use crate::{Handler, OnMail};
 use tokio_rustls::rustls;
-use vsmtp_common::{auth::Credentials, status::Status, ClientName, CodeID, Reply};
+use vsmtp_common::{
+    auth::{BearerToken, Credentials, CredentialsFile, CredentialsProvider, Mechanism, TokenError},
+    status::Status,
+    ClientName, CodeID, Reply,
+};
 use vsmtp_protocol::{
     AcceptArgs, AuthArgs, AuthError, CallbackWrap, ConnectionKind, EhloArgs, HeloArgs,
     ReceiverContext,
@@ -56,7 +60,36 @@ impl<M: OnMail + Send> Handler<M> {
                 Status::Delegated(_) => unreachable!(),
             };
 
-        self.reply_or_code_in_config(e)
+        match e {
+            either::Left(code @ (CodeID::EhloPain | CodeID::EhloSecured)) => self.ehlo_reply(code),
+            otherwise => self.reply_or_code_in_config(otherwise),
+        }
+    }
+
+    /// The EHLO replies of the configuration are built with `server.name`,
+    /// replace it by the name the client connected to (SNI) if any.
+    fn ehlo_reply(&self, code: CodeID) -> Reply {
+        let mut reply = self.reply_in_config(code);
+        reply.set(reply.text().replace(
+            "{message_size_limit}",
+            &self.config.server.message_size_limit.to_string(),
+        ));
+
+        let server_name = self
+            .state
+            .context()
+            .read()
+            .expect("state poisoned")
+            .server_name()
+            .clone();
+
+        if server_name != self.config.server.name {
+            if let Some(capabilities) = reply.text().strip_prefix(&self.config.server.name) {
+                reply.set(format!("{server_name}{capabilities}"));
+            }
+        }
+
+        reply
     }
 
     pub(super) fn on_accept_inner(
@@ -132,6 +165,17 @@ impl<M: OnMail + Send> Handler<M> {
         CallbackWrap(Box::new(RsaslSessionCallback {
             rule_engine: self.rule_engine.clone(),
             state: self.state.clone(),
+            credentials_provider: self
+                .config
+                .server
+                .smtp
+                .auth
+                .as_ref()
+                .and_then(|auth| auth.scram_credentials.as_ref())
+                .map(|path| {
+                    std::sync::Arc::new(CredentialsFile::new(path))
+                        as std::sync::Arc<dyn CredentialsProvider>
+                }),
         }))
     }
 
@@ -206,6 +250,22 @@ impl<M: OnMail + Send> Handler<M> {
         }
     }
 
+    pub(super) fn on_bearer_token_inner(&mut self, token: BearerToken) -> Result<(), TokenError> {
+        if let Some(validator) = &self.token_validator {
+            validator.validate(&token)?;
+        }
+
+        run_authenticate_stage(
+            &self.rule_engine,
+            &self.state,
+            Credentials::Bearer {
+                authid: token.authid,
+                token: token.token,
+            },
+        )
+        .map_err(|e| TokenError::Invalid(e.to_string()))
+    }
+
     pub(super) fn on_post_auth_inner(
         &mut self,
         ctx: &mut ReceiverContext,
@@ -251,11 +311,16 @@ impl<M: OnMail + Send> Handler<M> {
                 CodeID::AuthClientCanceled
             }
             Err(AuthError::Base64 { .. }) => CodeID::AuthErrorDecode64,
+            Err(AuthError::LineTooLong { .. }) => CodeID::AuthLineTooLong,
             Err(AuthError::SessionError(e)) => {
                 tracing::warn!(%e, "auth error");
                 ctx.deny();
                 CodeID::AuthTempError
             }
+            Err(AuthError::TokenUnavailable(reason)) => {
+                tracing::warn!(reason, "bearer token verification unavailable");
+                CodeID::AuthTempError
+            }
             Err(AuthError::IO(e)) => todo!("{}", e),
             Err(AuthError::ConfigError(e)) => todo!("{}", e),
         };
@@ -311,28 +376,70 @@ pub enum ValidationError {
 struct RsaslSessionCallback {
     rule_engine: std::sync::Arc<RuleEngine>,
     state: std::sync::Arc<RuleState>,
+    credentials_provider: Option<std::sync::Arc<dyn CredentialsProvider>>,
+}
+
+fn run_authenticate_stage(
+    rule_engine: &RuleEngine,
+    state: &RuleState,
+    credentials: Credentials,
+) -> Result<<ValidationVSL as rsasl::validate::Validation>::Value, ValidationError> {
+    state
+        .context()
+        .write()
+        .expect("state poisoned")
+        .with_credentials(credentials)
+        .expect("bad state");
+
+    let mut skipped = None;
+    let result = rule_engine.run_when(state, &mut skipped, ExecutionStage::Authenticate);
+
+    if !matches!(result, Status::Accept(..)) {
+        return Err(ValidationError::NonAcceptCode);
+    }
+
+    Ok(())
 }
 
 impl RsaslSessionCallback {
-    #[allow(clippy::unnecessary_wraps)]
     fn inner_validate(
         &self,
         credentials: Credentials,
     ) -> Result<<ValidationVSL as rsasl::validate::Validation>::Value, ValidationError> {
-        self.state
-            .context()
-            .write()
-            .expect("state poisoned")
-            .with_credentials(credentials)
-            .expect("bad state");
+        run_authenticate_stage(&self.rule_engine, &self.state, credentials)
+    }
 
-        let mut skipped = None;
-        let result =
-            self.rule_engine
-                .run_when(&self.state, &mut skipped, ExecutionStage::Authenticate);
+    /// Provide the salted credentials of the user to the `SCRAM-*` mechanisms,
+    /// which verify the proof sent by the client with them.
+    ///
+    /// If the user is unknown, the request is left unsatisfied and the exchange fails.
+    fn satisfy_scram(
+        &self,
+        mechanism: Mechanism,
+        context: &rsasl::callback::Context<'_>,
+        request: &mut rsasl::callback::Request<'_>,
+    ) -> Result<(), rsasl::prelude::SessionError> {
+        use rsasl::mechanisms::scram::properties::ScramStoredPassword;
+
+        let (provider, authid) = match (
+            &self.credentials_provider,
+            context.get_ref::<rsasl::property::AuthId>(),
+        ) {
+            (Some(provider), Some(authid)) => (provider, authid),
+            _ => return Ok(()),
+        };
 
-        if !matches!(result, Status::Accept(..)) {
-            return Err(ValidationError::NonAcceptCode);
+        match provider.scram_credentials(mechanism, authid) {
+            Ok(Some(credentials)) => {
+                request.satisfy::<ScramStoredPassword>(&ScramStoredPassword::new(
+                    credentials.iterations,
+                    &credentials.salt,
+                    &credentials.stored_key,
+                    &credentials.server_key,
+                ))?;
+            }
+            Ok(None) => tracing::warn!(authid, %mechanism, "No SCRAM credentials found"),
+            Err(error) => tracing::error!(%error, "Failed to get the SCRAM credentials"),
         }
 
         Ok(())
@@ -346,8 +453,15 @@ impl rsasl::callback::SessionCallback for RsaslSessionCallback {
         context: &rsasl::callback::Context<'_>,
         request: &mut rsasl::callback::Request<'_>,
     ) -> Result<(), rsasl::prelude::SessionError> {
-        let _ = (session_data, context, request);
-        Ok(())
+        match session_data.mechanism().mechanism {
+            mech if mech == Mechanism::ScramSha1.as_ref() => {
+                self.satisfy_scram(Mechanism::ScramSha1, context, request)
+            }
+            mech if mech == Mechanism::ScramSha256.as_ref() => {
+                self.satisfy_scram(Mechanism::ScramSha256, context, request)
+            }
+            _ => Ok(()),
+        }
     }
 
     fn validate(
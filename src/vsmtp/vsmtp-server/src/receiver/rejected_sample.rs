@@ -0,0 +1,172 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+use vsmtp_common::{ContextFinished, Reply};
+use vsmtp_config::{field::FieldAppRejectedSamples, Config};
+use vsmtp_mail_parser::MessageBody;
+
+/// The longest prefix of `s` of at most `len` bytes, cut at a character boundary.
+fn truncate(s: &str, len: usize) -> &str {
+    if s.len() <= len {
+        return s;
+    }
+    let mut end = len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// The headers of `message` followed by the beginning of its body.
+fn sample(config: &FieldAppRejectedSamples, message: &MessageBody) -> String {
+    let raw = message.inner();
+    let mut out = raw.headers_lines().collect::<String>();
+    out.push_str("\r\n");
+    if let Some(body) = raw.body() {
+        out.push_str(truncate(body, config.body_size));
+    }
+    truncate(&out, config.size_max).to_string()
+}
+
+/// Delay between two removals of the expired samples.
+const REMOVE_EXPIRED_PERIOD: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Remove the samples older than `config.retention`, the entries which are not files
+/// being left as is.
+async fn remove_expired(config: &FieldAppRejectedSamples) -> std::io::Result<()> {
+    let mut entries = match tokio::fs::read_dir(&config.dirpath).await {
+        Ok(entries) => entries,
+        // NOTE: the folder is created with the first sample.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let expired = entry.metadata().await.map_or(false, |metadata| {
+            metadata.is_file()
+                && metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .map_or(false, |age| age > config.retention)
+        });
+        if expired {
+            if let Err(error) = tokio::fs::remove_file(entry.path()).await {
+                tracing::warn!(
+                    path = %entry.path().display(),
+                    %error,
+                    "Failed to remove an expired sample."
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove the samples of `app.rejected_samples` older than their retention periodically,
+/// outside of the sessions. Never returns if the samples are configured.
+pub async fn remove_expired_periodically(config: std::sync::Arc<Config>) {
+    let samples = match &config.app.rejected_samples {
+        Some(samples) => samples,
+        None => return,
+    };
+
+    let mut interval = tokio::time::interval(REMOVE_EXPIRED_PERIOD);
+    loop {
+        interval.tick().await;
+        if let Err(error) = remove_expired(samples).await {
+            tracing::warn!(%error, "Failed to remove the expired rejected samples.");
+        }
+    }
+}
+
+async fn write_sample(
+    config: &FieldAppRejectedSamples,
+    ctx: &ContextFinished,
+    message: &MessageBody,
+) -> std::io::Result<std::path::PathBuf> {
+    tokio::fs::create_dir_all(&config.dirpath).await?;
+
+    let path = config
+        .dirpath
+        .join(format!("{}.eml", ctx.mail_from.message_uuid));
+    tokio::fs::write(&path, sample(config, message)).await?;
+    Ok(path)
+}
+
+/// Write a sample of the `message` rejected with `reply` if enabled by `app.rejected_samples`.
+///
+/// A failure is logged, and does not change the reply sent to the client.
+pub(super) async fn write(
+    config: &Config,
+    ctx: &ContextFinished,
+    message: &MessageBody,
+    reply: &Reply,
+) {
+    let samples = match &config.app.rejected_samples {
+        Some(samples) if samples.enabled => samples,
+        _ => return,
+    };
+    if fastrand::u32(..samples.sampling.get()) != 0 {
+        return;
+    }
+
+    match write_sample(samples, ctx, message).await {
+        Ok(path) => tracing::info!(
+            path = %path.display(),
+            client = %ctx.connect.client_addr,
+            reply = %reply.fold().trim_end(),
+            "Rejected message sampled."
+        ),
+        Err(error) => tracing::warn!(%error, "Failed to write the sample of a rejected message."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn remove_expired_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FieldAppRejectedSamples {
+            dirpath: dir.path().to_path_buf(),
+            retention: std::time::Duration::ZERO,
+            ..FieldAppRejectedSamples::default()
+        };
+
+        std::fs::write(dir.path().join("sample.eml"), "Subject: spam\r\n\r\n").unwrap();
+        std::fs::create_dir(dir.path().join("not-a-sample")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        remove_expired(&config).await.unwrap();
+
+        assert!(!dir.path().join("sample.eml").exists());
+        assert!(dir.path().join("not-a-sample").exists());
+    }
+
+    #[tokio::test]
+    async fn remove_expired_missing_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FieldAppRejectedSamples {
+            dirpath: dir.path().join("rejected"),
+            ..FieldAppRejectedSamples::default()
+        };
+
+        remove_expired(&config).await.unwrap();
+    }
+}
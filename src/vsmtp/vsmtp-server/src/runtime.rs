@@ -20,8 +20,21 @@ use vsmtp_config::{Config, DnsResolvers};
 use vsmtp_delivery::Sender;
 use vsmtp_rule_engine::RuleEngine;
 
+/// Message received by the main thread, which decides when to stop the server.
+#[derive(Debug)]
+enum Stop {
+    /// The future of the runtime named has returned.
+    RuntimeEnded(String),
+    /// The server must be stopped now.
+    Now,
+}
+
+/// The runtimes waited for after a termination signal, to let the sessions
+/// and the deliveries in progress complete.
+const DRAINED_RUNTIMES: [&str; 2] = ["receiver", "delivery"];
+
 fn init_runtime<F>(
-    sender: tokio::sync::mpsc::Sender<()>,
+    sender: tokio::sync::mpsc::Sender<Stop>,
     name: impl Into<String>,
     worker_thread_count: usize,
     future: F,
@@ -56,13 +69,14 @@ where
 
                 match timeout {
                     Some(duration) => {
-                        tokio::time::timeout(duration, future).await.unwrap_err();
+                        // NOTE: the future returns before the timeout once drained.
+                        let _elapsed = tokio::time::timeout(duration, future).await;
                     }
                     None => future.await,
                 }
             });
 
-            sender.blocking_send(())?;
+            sender.blocking_send(Stop::RuntimeEnded(name))?;
             Ok(())
         })
         .map_err(anyhow::Error::new)
@@ -84,7 +98,14 @@ pub fn start_runtime(
 ) -> anyhow::Result<()> {
     let config = std::sync::Arc::new(config);
 
-    let mut error_handler = tokio::sync::mpsc::channel::<()>(3);
+    #[cfg(not(feature = "mta-sts"))]
+    if config.server.queues.delivery.mta_sts {
+        tracing::warn!(
+            "`server.queues.delivery.mta_sts` is set but vSMTP has been built without the `mta-sts` feature"
+        );
+    }
+
+    let mut error_handler = tokio::sync::mpsc::channel::<Stop>(3);
 
     let (delivery_channel, working_channel) = (
         tokio::sync::mpsc::channel::<ProcessMessage>(config.server.queues.delivery.channel_size),
@@ -105,6 +126,14 @@ pub fn start_runtime(
     )?);
 
     let sender = std::sync::Arc::new(Sender::default());
+    let shutdown = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let shutdown_receiver = shutdown.clone();
+    let shutdown_main = shutdown.clone();
+    let shutdown_grace_period = config.server.system.shutdown_grace_period;
+    let (drain, drain_receiver) = tokio::sync::watch::channel(false);
+    let drain_delivery = drain_receiver.clone();
+    let tls_reload = std::sync::Arc::new(tokio::sync::Notify::new());
+    let tls_reload_receiver = tls_reload.clone();
 
     let _tasks_delivery = init_runtime(
         error_handler.0.clone(),
@@ -116,7 +145,9 @@ pub fn start_runtime(
             resolvers,
             queue_manager.clone(),
             delivery_channel.1,
+            delivery_channel.0.clone(),
             sender,
+            drain_delivery,
         ),
         timeout,
     )?;
@@ -126,6 +157,7 @@ pub fn start_runtime(
         "processing",
         config.server.system.thread_pool.processing,
         processing::start(
+            config.clone(),
             rule_engine.clone(),
             queue_manager.clone(),
             working_channel.1,
@@ -139,14 +171,54 @@ pub fn start_runtime(
         "receiver",
         config.server.system.thread_pool.receiver,
         async move {
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &config.server.metrics {
+                match tokio::net::TcpListener::bind(metrics.addr).await {
+                    Ok(listener) => {
+                        tokio::spawn(crate::metrics::serve(
+                            listener,
+                            queue_manager.clone(),
+                            metrics.queue_refresh_period,
+                        ));
+                    }
+                    Err(error) => tracing::error!(%error, "Metrics endpoint bind failure."),
+                }
+            }
+            #[cfg(not(feature = "metrics"))]
+            if config.server.metrics.is_some() {
+                tracing::warn!(
+                    "`server.metrics` is ignored, vSMTP is built without the `metrics` feature."
+                );
+            }
+
+            if let Some(path) = &config.server.queues.delegation_socket {
+                match crate::unix_socket_bind_anyhow(path).and_then(|listener| {
+                    listener.set_nonblocking(true)?;
+                    Ok(tokio::net::UnixListener::from_std(listener)?)
+                }) {
+                    Ok(listener) => {
+                        tokio::spawn(crate::serve_delegation(
+                            listener,
+                            queue_manager.clone(),
+                            working_channel.0.clone(),
+                            delivery_channel.0.clone(),
+                        ));
+                    }
+                    Err(error) => tracing::error!(%error, "Delegation socket bind failure."),
+                }
+            }
+
             let server = match Server::new(
                 config.clone(),
                 rule_engine.clone(),
                 queue_manager.clone(),
                 working_channel.0.clone(),
                 delivery_channel.0.clone(),
+                shutdown_receiver,
             ) {
-                Ok(server) => server,
+                Ok(server) => server
+                    .with_tls_reload(tls_reload_receiver)
+                    .with_drain(drain_receiver),
                 Err(error) => {
                     tracing::error!(%error, "Receiver build failure.");
                     return;
@@ -165,17 +237,58 @@ pub fn start_runtime(
         signal_hook::consts::SIGTERM,
         // Ctrl+C on a terminal
         signal_hook::consts::SIGINT,
+        // Send by `systemctl reload`, after the renewal of the certificates
+        signal_hook::consts::SIGHUP,
     ])?;
     let _signal_handler = std::thread::spawn(move || {
         for sig in signals.forever() {
-            tracing::warn!(signal = sig, "Stopping vSMTP server.");
-            error_handler_sig
-                .blocking_send(())
-                .expect("failed to send terminating instruction");
+            if sig == signal_hook::consts::SIGHUP {
+                tracing::info!(signal = sig, "Reloading the TLS configuration.");
+                tls_reload.notify_one();
+                continue;
+            }
+
+            // NOTE: the first signal closes the listeners, gives the sessions a `421`
+            //       at their next command and lets the deliveries in progress
+            //       complete, for at most the grace period. The second one stops now.
+            if shutdown.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                tracing::warn!(signal = sig, "Stopping vSMTP server.");
+                error_handler_sig
+                    .blocking_send(Stop::Now)
+                    .expect("failed to send terminating instruction");
+            } else {
+                tracing::warn!(
+                    signal = sig,
+                    grace = ?shutdown_grace_period,
+                    "Stopping vSMTP server, draining the sessions and the deliveries."
+                );
+                drain.send_replace(true);
+                let error_handler_grace = error_handler_sig.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(shutdown_grace_period);
+                    error_handler_grace
+                        .blocking_send(Stop::Now)
+                        .expect("failed to send terminating instruction");
+                });
+            }
         }
     });
 
-    error_handler.1.blocking_recv();
+    let mut drained = std::collections::HashSet::new();
+    loop {
+        match error_handler.1.blocking_recv() {
+            Some(Stop::RuntimeEnded(name))
+                if shutdown_main.load(std::sync::atomic::Ordering::SeqCst) =>
+            {
+                tracing::info!(name, "Runtime drained.");
+                drained.insert(name);
+                if DRAINED_RUNTIMES.iter().all(|name| drained.contains(*name)) {
+                    break;
+                }
+            }
+            Some(Stop::RuntimeEnded(_) | Stop::Now) | None => break,
+        }
+    }
 
     Ok(())
 
@@ -15,26 +15,34 @@
  *
 */
 use crate::{
-    channel_message::ProcessMessage, on_mail::MailHandler, receiver::handler::Handler,
-    ValidationVSL,
+    channel_message::ProcessMessage, on_mail::MailHandler, rate_limiter::RateLimiter,
+    receiver::handler::Handler, ValidationVSL,
 };
 use anyhow::Context;
 use tokio_rustls::rustls;
 use tokio_stream::StreamExt;
 use vqueue::GenericQueueManager;
-use vsmtp_common::CodeID;
-use vsmtp_config::{get_rustls_config, Config};
-use vsmtp_protocol::{AcceptArgs, ConnectionKind};
+use vsmtp_common::{auth::TokenValidator, CodeID, Reply};
+use vsmtp_config::{get_rustls_config, reload_rustls_config, Config};
+use vsmtp_protocol::{AcceptArgs, ArgsPolicy, ConnectionKind};
 use vsmtp_rule_engine::RuleEngine;
 
 /// TCP/IP server
 pub struct Server {
     config: std::sync::Arc<Config>,
-    tls_config: Option<std::sync::Arc<rustls::ServerConfig>>,
+    tls_config: Option<std::sync::Arc<arc_swap::ArcSwap<rustls::ServerConfig>>>,
+    tls_reload: Option<std::sync::Arc<tokio::sync::Notify>>,
+    drain: Option<tokio::sync::watch::Receiver<bool>>,
     rule_engine: std::sync::Arc<RuleEngine>,
     queue_manager: std::sync::Arc<dyn GenericQueueManager>,
     working_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
     delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+    rejected_connection_count: std::sync::atomic::AtomicU64,
+    shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    processing_limit: Option<std::sync::Arc<tokio::sync::Semaphore>>,
+    connection_limit: Option<std::sync::Arc<tokio::sync::Semaphore>>,
+    token_validator: Option<std::sync::Arc<dyn TokenValidator>>,
+    rate_limiter: Option<std::sync::Arc<RateLimiter>>,
 }
 
 /// Create a `TCPListener` ready to be listened to
@@ -56,6 +64,114 @@ pub fn socket_bind_anyhow<A: std::net::ToSocketAddrs + std::fmt::Debug>(
     Ok(socket)
 }
 
+/// Create a unix socket listener at `path`, only accessible by the user running the server.
+///
+/// The socket is bound in a private directory and then moved to `path`, so that
+/// it is never reachable before its permissions are restricted. A stale socket
+/// left at `path` by a previous instance is removed.
+///
+/// # Errors
+///
+/// * failed to remove the stale socket
+/// * failed to bind the socket or to move it to `path`
+pub fn unix_socket_bind_anyhow(
+    path: &std::path::Path,
+) -> anyhow::Result<std::os::unix::net::UnixListener> {
+    if std::fs::symlink_metadata(path).map_or(false, |metadata| {
+        std::os::unix::fs::FileTypeExt::is_socket(&metadata.file_type())
+    }) {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Cannot remove the stale socket '{}'", path.display()))?;
+    }
+
+    let private_dir = path.with_file_name(format!(
+        ".{}.{}",
+        path.file_name()
+            .with_context(|| format!("Invalid socket path '{}'", path.display()))?
+            .to_string_lossy(),
+        std::process::id()
+    ));
+    std::os::unix::fs::DirBuilderExt::mode(&mut std::fs::DirBuilder::new(), 0o700)
+        .create(&private_dir)
+        .with_context(|| format!("Cannot create the directory '{}'", private_dir.display()))?;
+
+    let bind = || -> anyhow::Result<std::os::unix::net::UnixListener> {
+        let private_path = private_dir.join("socket");
+        let listener = std::os::unix::net::UnixListener::bind(&private_path)?;
+        std::fs::set_permissions(
+            &private_path,
+            <std::fs::Permissions as std::os::unix::fs::PermissionsExt>::from_mode(0o600),
+        )?;
+        std::fs::rename(&private_path, path)?;
+        Ok(listener)
+    };
+    let listener = bind();
+    let _removed = std::fs::remove_dir_all(&private_dir);
+
+    listener.with_context(|| format!("Cannot bind the socket '{}'", path.display()))
+}
+
+/// A slot of `server.client_count_max`, released when dropped so that a
+/// session ending with an error or a panic does not leak it.
+struct ConnectionSlot {
+    client_counter: std::sync::Arc<std::sync::atomic::AtomicI64>,
+    _permit: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
+impl ConnectionSlot {
+    fn acquire(
+        client_counter: std::sync::Arc<std::sync::atomic::AtomicI64>,
+        permit: Option<tokio::sync::OwnedSemaphorePermit>,
+    ) -> Self {
+        client_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        #[cfg(feature = "metrics")]
+        crate::metrics::METRICS.record_connection(1);
+        Self {
+            client_counter,
+            _permit: permit,
+        }
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.client_counter
+            .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        #[cfg(feature = "metrics")]
+        crate::metrics::METRICS.record_connection(-1);
+    }
+}
+
+/// Rebuild the TLS configuration from the certificates and private keys on disk,
+/// and swap it atomically: the next handshakes use the new one, while the sessions
+/// already established keep the previous one.
+///
+/// If the new configuration cannot be built, the previous one is kept.
+fn reload_tls_config(
+    config: &Config,
+    tls_config: &arc_swap::ArcSwap<rustls::ServerConfig>,
+) -> bool {
+    let smtps = match &config.server.tls {
+        Some(smtps) => smtps,
+        None => return false,
+    };
+
+    match reload_rustls_config(smtps, &config.server.r#virtual) {
+        Ok(reloaded) => {
+            tls_config.store(std::sync::Arc::new(reloaded));
+            tracing::info!("TLS configuration reloaded.");
+            true
+        }
+        Err(error) => {
+            tracing::error!(
+                error = %format!("{error:#}"),
+                "TLS configuration reload failure, keeping the previous one."
+            );
+            false
+        }
+    }
+}
+
 type ListenerStreamItem = std::io::Result<(tokio::net::TcpStream, std::net::SocketAddr)>;
 
 fn listener_to_stream(
@@ -71,6 +187,9 @@ fn listener_to_stream(
 impl Server {
     /// Create a server with the configuration provided, and the sockets already bound
     ///
+    /// Once `shutdown` is set, the sessions are closed with a
+    /// [`CodeID::ShuttingDown`] at their next command.
+    ///
     /// # Errors
     ///
     /// * `spool_dir` does not exist and failed to be created
@@ -82,6 +201,7 @@ impl Server {
         queue_manager: std::sync::Arc<dyn GenericQueueManager>,
         working_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
         delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+        shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>,
     ) -> anyhow::Result<Self> {
         if !config.server.queues.dirpath.exists() {
             std::fs::DirBuilder::new()
@@ -89,66 +209,199 @@ impl Server {
                 .create(&config.server.queues.dirpath)?;
         }
 
+        let processing_limit = config
+            .server
+            .queues
+            .working
+            .processing_count_max
+            .map(|max| std::sync::Arc::new(tokio::sync::Semaphore::new(max)));
+
+        // NOTE: `-1` (the only negative value allowed) means no limit.
+        let connection_limit = usize::try_from(config.server.client_count_max)
+            .ok()
+            .map(|max| {
+                std::sync::Arc::new(tokio::sync::Semaphore::new(
+                    max.min(tokio::sync::Semaphore::MAX_PERMITS),
+                ))
+            });
+
+        let rate_limiter = config
+            .server
+            .rate_limit
+            .as_ref()
+            .map(|rate_limit| std::sync::Arc::new(RateLimiter::new(rate_limit)));
+
         Ok(Self {
             tls_config: if let Some(smtps) = &config.server.tls {
-                Some(std::sync::Arc::new(get_rustls_config(
-                    smtps,
-                    &config.server.r#virtual,
-                )?))
+                Some(std::sync::Arc::new(arc_swap::ArcSwap::from_pointee(
+                    get_rustls_config(smtps, &config.server.r#virtual)?,
+                )))
             } else {
                 None
             },
+            tls_reload: None,
+            drain: None,
             rule_engine,
             queue_manager,
             config,
             working_sender,
             delivery_sender,
+            rejected_connection_count: std::sync::atomic::AtomicU64::new(0),
+            shutdown,
+            processing_limit,
+            connection_limit,
+            token_validator: None,
+            rate_limiter,
         })
     }
 
+    /// Verify the bearer tokens of the `XOAUTH2` and `OAUTHBEARER` mechanisms
+    /// with `token_validator` (JWT validation, introspection endpoint, ...),
+    /// before running the `authenticate` stage of the rules.
+    #[must_use]
+    pub fn with_token_validator(
+        mut self,
+        token_validator: std::sync::Arc<dyn TokenValidator>,
+    ) -> Self {
+        self.token_validator = Some(token_validator);
+        self
+    }
+
+    /// Rebuild the TLS configuration each time `tls_reload` is notified (on `SIGHUP`),
+    /// to pick up the certificates renewed on disk without restarting the server.
+    #[must_use]
+    pub fn with_tls_reload(mut self, tls_reload: std::sync::Arc<tokio::sync::Notify>) -> Self {
+        self.tls_reload = Some(tls_reload);
+        self
+    }
+
+    /// Stop accepting clients once `drain` is set to `true` (on `SIGTERM`): the listeners
+    /// are closed, and [`Server::listen_and_serve`] returns when the sessions in
+    /// progress are over.
+    #[must_use]
+    pub fn with_drain(mut self, drain: tokio::sync::watch::Receiver<bool>) -> Self {
+        self.drain = Some(drain);
+        self
+    }
+
+    /// Number of connections rejected because `server.client_count_max` was reached.
+    #[must_use]
+    pub fn rejected_connection_count(&self) -> u64 {
+        self.rejected_connection_count
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Build the reply sent when `server.client_count_max` is reached.
+    ///
+    /// The `{client_count}` and `{client_count_max}` placeholders of the
+    /// configured text are replaced, so the reply can hint the client on when to retry.
+    fn connection_max_reached_reply(&self, client_count: i64) -> Reply {
+        let mut reply = self
+            .config
+            .server
+            .smtp
+            .codes
+            .get(&CodeID::ConnectionMaxReached)
+            .expect("ill-formed configuration")
+            .clone();
+
+        reply.set(
+            reply
+                .text()
+                .replace("{client_count}", &client_count.to_string())
+                .replace(
+                    "{client_count_max}",
+                    &self.config.server.client_count_max.to_string(),
+                ),
+        );
+
+        reply
+    }
+
+    /// Send `reply` to a client whose connection is refused, and close it.
+    async fn refuse_connection(mut stream: tokio::net::TcpStream, reply: &Reply) {
+        if let Err(error) =
+            tokio::io::AsyncWriteExt::write_all(&mut stream, reply.fold().as_bytes()).await
+        {
+            tracing::error!(%error, "Code delivery failure.");
+        }
+
+        if let Err(error) = tokio::io::AsyncWriteExt::shutdown(&mut stream).await {
+            tracing::error!(%error, "Closing connection failure.");
+        }
+    }
+
     #[tracing::instrument(name = "handle-client", skip_all, fields(client = %client_addr, server = %server_addr))]
     async fn handle_client(
         &self,
         client_counter: std::sync::Arc<std::sync::atomic::AtomicI64>,
         kind: ConnectionKind,
-        mut stream: tokio::net::TcpStream,
+        stream: tokio::net::TcpStream,
         client_addr: std::net::SocketAddr,
         server_addr: std::net::SocketAddr,
     ) {
         tracing::info!(%kind, "Connection accepted.");
 
-        if self.config.server.client_count_max != -1
-            && client_counter.load(std::sync::atomic::Ordering::SeqCst)
-                >= self.config.server.client_count_max
+        let permit = match self
+            .connection_limit
+            .clone()
+            .map(tokio::sync::Semaphore::try_acquire_owned)
         {
-            tracing::warn!(
-                max = self.config.server.client_count_max,
-                "Connection count max reached, rejecting connection.",
-            );
+            Some(Ok(permit)) => Some(permit),
+            Some(Err(_)) => {
+                let client_count = client_counter.load(std::sync::atomic::Ordering::SeqCst);
+                let rejected = self
+                    .rejected_connection_count
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                    + 1;
 
-            if let Err(error) = tokio::io::AsyncWriteExt::write_all(
-                &mut stream,
-                self.config
-                    .server
-                    .smtp
-                    .codes
-                    .get(&CodeID::ConnectionMaxReached)
-                    .expect("ill-formed configuration")
-                    .fold()
-                    .as_bytes(),
-            )
-            .await
-            {
-                tracing::error!(%error, "Code delivery failure.");
-            }
+                tracing::warn!(
+                    current = client_count,
+                    max = self.config.server.client_count_max,
+                    rejected,
+                    "Connection count max reached, rejecting connection.",
+                );
 
-            if let Err(error) = tokio::io::AsyncWriteExt::shutdown(&mut stream).await {
-                tracing::error!(%error, "Closing connection failure.");
+                Self::refuse_connection(stream, &self.connection_max_reached_reply(client_count))
+                    .await;
+                return;
             }
-            return;
-        }
+            None => None,
+        };
 
-        client_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let rate_limit_guard = match &self.rate_limiter {
+            Some(rate_limiter) => match rate_limiter.acquire_connection(client_addr.ip()) {
+                Some(guard) => Some(guard),
+                None => {
+                    tracing::warn!(
+                        ip = %client_addr.ip(),
+                        "Connection rate limit reached, rejecting connection.",
+                    );
+
+                    Self::refuse_connection(
+                        stream,
+                        self.config
+                            .server
+                            .smtp
+                            .codes
+                            .get(&CodeID::ConnectionRateLimited)
+                            .expect("ill-formed configuration"),
+                    )
+                    .await;
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        let slot = ConnectionSlot::acquire(client_counter, permit);
+        let log_dialog = self
+            .config
+            .server
+            .interfaces
+            .log_dialog
+            .as_ref()
+            .map_or(true, |log_dialog| log_dialog.contains(&server_addr));
 
         let session = Self::run_session(
             AcceptArgs::new(
@@ -159,23 +412,31 @@ impl Server {
                 kind,
             ),
             stream,
-            self.tls_config.clone(),
+            self.tls_config
+                .as_ref()
+                .map(|tls_config| tls_config.load_full()),
             self.config.clone(),
             self.rule_engine.clone(),
             self.queue_manager.clone(),
             self.working_sender.clone(),
             self.delivery_sender.clone(),
+            self.shutdown.clone(),
+            self.processing_limit.clone(),
+            self.token_validator.clone(),
+            self.rate_limiter.clone(),
+            log_dialog,
         );
-        let client_counter_copy = client_counter.clone();
         tokio::spawn(async move {
+            let _slot = slot;
+            let _rate_limit_guard = rate_limit_guard;
             let _err = session.await;
-
-            client_counter_copy.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
         });
     }
 
     /// Main loop of `vSMTP`'s server
     ///
+    /// See [`Server::with_drain`] to stop it.
+    ///
     /// # Errors
     ///
     /// * failed to convert sockets to `[tokio::net::TcpListener]`
@@ -202,6 +463,57 @@ impl Server {
             );
         }
 
+        if let (Some(tls_config), Some(tls_reload)) = (&self.tls_config, &self.tls_reload) {
+            let (config, tls_config, tls_reload) =
+                (self.config.clone(), tls_config.clone(), tls_reload.clone());
+            tokio::spawn(async move {
+                loop {
+                    tls_reload.notified().await;
+                    reload_tls_config(&config, &tls_config);
+                }
+            });
+        }
+
+        if let (Some(tls_config), Some(tls)) = (&self.tls_config, &self.config.server.tls) {
+            if tls.ocsp_response.is_some() {
+                let (config, tls_config) = (self.config.clone(), tls_config.clone());
+                tokio::spawn(async move {
+                    let tls = match &config.server.tls {
+                        Some(tls) => tls,
+                        None => return,
+                    };
+                    // NOTE: the response was read at startup.
+                    loop {
+                        tokio::time::sleep(vsmtp_config::ocsp_refresh_delay(
+                            tls,
+                            std::time::SystemTime::now(),
+                        ))
+                        .await;
+                        reload_tls_config(&config, &tls_config);
+                    }
+                });
+            }
+        }
+
+        if self.config.app.rejected_samples.is_some() {
+            tokio::spawn(
+                crate::receiver::rejected_sample::remove_expired_periodically(self.config.clone()),
+            );
+        }
+
+        if let (Some(rate_limiter), Some(rate_limit)) =
+            (&self.rate_limiter, &self.config.server.rate_limit)
+        {
+            let (rate_limiter, cleanup_period) = (rate_limiter.clone(), rate_limit.cleanup_period);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(cleanup_period);
+                loop {
+                    interval.tick().await;
+                    rate_limiter.cleanup();
+                }
+            });
+        }
+
         let client_counter = std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0));
 
         let (listener, listener_submission, listener_tunneled) = (
@@ -232,9 +544,20 @@ impl Server {
             "Listening for clients.",
         );
 
-        while let Some((server_addr, (kind, client))) =
-            tokio_stream::StreamExt::next(&mut map).await
-        {
+        let mut drain = self.drain.clone();
+        loop {
+            let (server_addr, (kind, client)) = tokio::select! {
+                next = tokio_stream::StreamExt::next(&mut map) => match next {
+                    Some(next) => next,
+                    None => return Ok(()),
+                },
+                () = async {
+                    match drain.as_mut() {
+                        Some(drain) => crate::drained(drain).await,
+                        None => std::future::pending().await,
+                    }
+                } => break,
+            };
             let (stream, client_addr) = client?;
 
             self.handle_client(
@@ -246,9 +569,24 @@ impl Server {
             )
             .await;
         }
+
+        // NOTE: closing the listeners makes the new connections refused.
+        drop(map);
+        drop((listener, listener_submission, listener_tunneled));
+
+        tracing::info!(
+            sessions = client_counter.load(std::sync::atomic::Ordering::SeqCst),
+            "No longer listening for clients, waiting for the sessions in progress."
+        );
+        while client_counter.load(std::sync::atomic::Ordering::SeqCst) != 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        tracing::info!("All sessions are over.");
         Ok(())
     }
 
+    /// The SMTP dialog is logged at the `trace` level if `log_dialog` is set.
     ///
     /// # Errors
     #[allow(clippy::too_many_arguments)]
@@ -262,6 +600,11 @@ impl Server {
         queue_manager: std::sync::Arc<dyn GenericQueueManager>,
         working_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
         delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+        shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>,
+        processing_limit: Option<std::sync::Arc<tokio::sync::Semaphore>>,
+        token_validator: Option<std::sync::Arc<dyn TokenValidator>>,
+        rate_limiter: Option<std::sync::Arc<RateLimiter>>,
+        log_dialog: bool,
     ) -> anyhow::Result<()> {
         let smtp_handler = Handler::new(
             Box::new(MailHandler {
@@ -272,7 +615,11 @@ impl Server {
             tls_config,
             rule_engine,
             queue_manager,
-        );
+            shutdown,
+        )
+        .with_processing_limit(processing_limit)
+        .with_token_validator(token_validator)
+        .with_rate_limiter(rate_limiter);
         let smtp_receiver = vsmtp_protocol::Receiver::<_, ValidationVSL, _, _>::new(
             tcp_stream,
             args.kind,
@@ -280,7 +627,15 @@ impl Server {
             config.server.smtp.error.soft_count,
             config.server.smtp.error.hard_count,
             config.server.message_size_limit,
-        );
+            ArgsPolicy::new(
+                config.server.smtp.duplicate_params,
+                config.server.smtp.strict_syntax,
+                config.server.smtp.reject_legacy_routing,
+                config.server.smtp.unknown_params,
+            ),
+            config.server.smtp.lenient_quit,
+        )
+        .with_dialog_log(log_dialog);
         let smtp_stream = smtp_receiver.into_stream(
             args.client_addr,
             args.server_addr,
@@ -299,10 +654,14 @@ impl Server {
 #[cfg(test)]
 mod tests {
 
+    use super::{reload_tls_config, ConnectionSlot};
     use crate::{socket_bind_anyhow, ProcessMessage, Server};
-    use vsmtp_config::DnsResolvers;
+    use vsmtp_config::{
+        field::{FieldServerVirtual, FieldServerVirtualTls},
+        get_rustls_config, DnsResolvers,
+    };
     use vsmtp_rule_engine::RuleEngine;
-    use vsmtp_test::config;
+    use vsmtp_test::{config, get_tls_file};
 
     macro_rules! listen_with {
         ($addr:expr, $addr_submission:expr, $addr_submissions:expr, $timeout:expr, $client_count_max:expr) => {{
@@ -337,6 +696,7 @@ mod tests {
                 queue_manager,
                 working.0,
                 delivery.0,
+                std::sync::Arc::default(),
             )
             .unwrap();
 
@@ -377,6 +737,513 @@ mod tests {
         }};
     }
 
+    #[test_log::test(tokio::test)]
+    async fn connection_max_reached() {
+        let config = std::sync::Arc::new({
+            let mut config = config::local_test();
+            config.server.client_count_max = 1;
+            config.server.smtp.codes.insert(
+                vsmtp_common::CodeID::ConnectionMaxReached,
+                "421 4.7.0 Too many connections ({client_count}/{client_count_max}), retry later\r\n"
+                    .parse()
+                    .unwrap(),
+            );
+            config
+        });
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let server = Server::new(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::new(config.clone(), resolvers, queue_manager.clone()).unwrap(),
+            ),
+            queue_manager,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            std::sync::Arc::default(),
+        )
+        .unwrap();
+
+        // NOTE: the only slot is taken, as by another session.
+        let _permit = server
+            .connection_limit
+            .clone()
+            .unwrap()
+            .try_acquire_owned()
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let mut client = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+        let (stream, client_addr) = listener.accept().await.unwrap();
+
+        server
+            .handle_client(
+                std::sync::Arc::new(std::sync::atomic::AtomicI64::new(1)),
+                vsmtp_protocol::ConnectionKind::Relay,
+                stream,
+                client_addr,
+                server_addr,
+            )
+            .await;
+
+        let mut reply = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut reply)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            reply,
+            "421 4.7.0 Too many connections (1/1), retry later\r\n"
+        );
+        assert_eq!(server.rejected_connection_count(), 1);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn connection_count_max() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+        let listener = socket_bind_anyhow("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config = std::sync::Arc::new({
+            let mut config = config::local_test();
+            config.server.interfaces.addr = vec![addr];
+            config.server.interfaces.addr_submission = vec![];
+            config.server.interfaces.addr_submissions = vec![];
+            config.server.client_count_max = 2;
+            config
+        });
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let server = Server::new(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::new(config.clone(), resolvers, queue_manager.clone()).unwrap(),
+            ),
+            queue_manager,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            std::sync::Arc::default(),
+        )
+        .unwrap();
+        tokio::spawn(server.listen_and_serve((vec![listener], vec![], vec![])));
+
+        let mut sessions = vec![];
+        for _ in 0..2 {
+            let mut client =
+                tokio::io::BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+            let mut line = String::new();
+            client.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "220 testserver.com Service ready\r\n");
+            sessions.push(client);
+        }
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "554 Cannot process connection, closing\r\n");
+
+        // NOTE: the slot of a closed session is released.
+        drop(sessions.pop());
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let mut client =
+            tokio::io::BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+        let mut line = String::new();
+        client.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "220 testserver.com Service ready\r\n");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn connection_rate_limited() {
+        let config = std::sync::Arc::new({
+            let mut config = config::local_test();
+            config.server.rate_limit = Some(vsmtp_config::field::FieldServerRateLimit {
+                connection_count_max: Some(1),
+                message_per_minute: None,
+                rcpt_per_hour: None,
+                cleanup_period: std::time::Duration::from_secs(60),
+            });
+            config
+        });
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let server = Server::new(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::new(config.clone(), resolvers, queue_manager.clone()).unwrap(),
+            ),
+            queue_manager,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            std::sync::Arc::default(),
+        )
+        .unwrap();
+
+        // NOTE: the only connection allowed is taken, as by another session of the same client.
+        let _guard = server
+            .rate_limiter
+            .as_ref()
+            .unwrap()
+            .acquire_connection("127.0.0.1".parse().unwrap())
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let mut client = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+        let (stream, client_addr) = listener.accept().await.unwrap();
+
+        server
+            .handle_client(
+                std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)),
+                vsmtp_protocol::ConnectionKind::Relay,
+                stream,
+                client_addr,
+                server_addr,
+            )
+            .await;
+
+        let mut reply = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut reply)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            reply,
+            "421 4.7.0 Too many connections from your address, retry later\r\n"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn processing_count_max_delays_data() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let config = std::sync::Arc::new({
+            let mut config = config::local_test();
+            config.server.queues.working.processing_count_max = Some(1);
+            config
+        });
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let server = Server::new(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::new(config.clone(), resolvers, queue_manager.clone()).unwrap(),
+            ),
+            queue_manager,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            std::sync::Arc::default(),
+        )
+        .unwrap();
+
+        // NOTE: the only slot is taken, as by a message of another session.
+        let permit = server
+            .processing_limit
+            .clone()
+            .unwrap()
+            .acquire_owned()
+            .await
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let client = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+        let (stream, client_addr) = listener.accept().await.unwrap();
+
+        server
+            .handle_client(
+                std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)),
+                vsmtp_protocol::ConnectionKind::Relay,
+                stream,
+                client_addr,
+                server_addr,
+            )
+            .await;
+
+        let mut client = tokio::io::BufReader::new(client);
+        client
+            .write_all(b"HELO foobar\r\nMAIL FROM:<john@doe>\r\nRCPT TO:<aa@bb>\r\nDATA\r\n")
+            .await
+            .unwrap();
+
+        let mut replies = vec![];
+        for _ in 0..4 {
+            let mut line = String::new();
+            client.read_line(&mut line).await.unwrap();
+            replies.push(line);
+        }
+        assert_eq!(
+            replies,
+            [
+                "220 testserver.com Service ready\r\n",
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+            ]
+        );
+
+        let mut line = String::new();
+        tokio::time::timeout(
+            std::time::Duration::from_millis(200),
+            client.read_line(&mut line),
+        )
+        .await
+        .unwrap_err();
+
+        drop(permit);
+
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            client.read_line(&mut line),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(line, "354 Start mail input; end with <CRLF>.<CRLF>\r\n");
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+    async fn drain_refuses_new_connections() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let config = std::sync::Arc::new(config::local_test());
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let (drain, drain_receiver) = tokio::sync::watch::channel(false);
+        let server = Server::new(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::new(config.clone(), resolvers, queue_manager.clone()).unwrap(),
+            ),
+            queue_manager,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            std::sync::Arc::default(),
+        )
+        .unwrap()
+        .with_drain(drain_receiver);
+
+        let listener = socket_bind_anyhow("127.0.0.1:0").unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(server.listen_and_serve((vec![listener], vec![], vec![])));
+
+        let mut client =
+            tokio::io::BufReader::new(tokio::net::TcpStream::connect(server_addr).await.unwrap());
+        let mut line = String::new();
+        client.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "220 testserver.com Service ready\r\n");
+
+        drain.send(true).unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while tokio::net::TcpStream::connect(server_addr).await.is_ok() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!server.is_finished());
+
+        client
+            .write_all(b"HELO foobar\r\nMAIL FROM:<john@doe>\r\nQUIT\r\n")
+            .await
+            .unwrap();
+
+        let mut replies = vec![];
+        for _ in 0..3 {
+            let mut line = String::new();
+            client.read_line(&mut line).await.unwrap();
+            replies.push(line);
+        }
+        assert_eq!(
+            replies,
+            [
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "221 Service closing transmission channel\r\n",
+            ]
+        );
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn log_dialog_per_interface() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+        let captured = Captured::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::TRACE)
+                .with_writer({
+                    let captured = captured.clone();
+                    move || captured.clone()
+                })
+                .finish(),
+        );
+
+        let (listener, listener_submission) = (
+            socket_bind_anyhow("127.0.0.1:0").unwrap(),
+            socket_bind_anyhow("127.0.0.1:0").unwrap(),
+        );
+        let (addr, addr_submission) = (
+            listener.local_addr().unwrap(),
+            listener_submission.local_addr().unwrap(),
+        );
+
+        let config = std::sync::Arc::new({
+            let mut config = config::local_test();
+            config.server.interfaces.addr = vec![addr];
+            config.server.interfaces.addr_submission = vec![addr_submission];
+            config.server.interfaces.addr_submissions = vec![];
+            config.server.interfaces.log_dialog = Some(vec![addr_submission]);
+            config
+        });
+
+        let queue_manager =
+            <vqueue::temp::QueueManager as vqueue::GenericQueueManager>::init(config.clone())
+                .unwrap();
+        let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+        let server = Server::new(
+            config.clone(),
+            std::sync::Arc::new(
+                RuleEngine::new(config.clone(), resolvers, queue_manager.clone()).unwrap(),
+            ),
+            queue_manager,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            tokio::sync::mpsc::channel::<ProcessMessage>(1).0,
+            std::sync::Arc::default(),
+        )
+        .unwrap();
+        tokio::spawn(server.listen_and_serve((vec![listener], vec![listener_submission], vec![])));
+
+        let captured = &captured;
+        let session = |server_addr| async move {
+            let mut client = tokio::io::BufReader::new(
+                tokio::net::TcpStream::connect(server_addr).await.unwrap(),
+            );
+            let mut line = String::new();
+            client.read_line(&mut line).await.unwrap();
+            client.write_all(b"QUIT\r\n").await.unwrap();
+            client.read_to_string(&mut line).await.unwrap();
+            assert_eq!(
+                line,
+                "220 testserver.com Service ready\r\n221 Service closing transmission channel\r\n"
+            );
+            String::from_utf8(std::mem::take(&mut *captured.0.lock().unwrap())).unwrap()
+        };
+
+        let logs = session(addr).await;
+        assert!(!logs.contains("<< Quit"), "{logs}");
+        assert!(!logs.contains(">> \"221"), "{logs}");
+
+        let logs = session(addr_submission).await;
+        assert!(logs.contains("<< Quit"), "{logs}");
+        assert!(logs.contains(">> \"221"), "{logs}");
+    }
+
+    #[tokio::test]
+    async fn slot_released_on_panic() {
+        let client_counter = std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0));
+
+        let slot = ConnectionSlot::acquire(client_counter.clone(), None);
+        assert_eq!(client_counter.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let session = tokio::spawn(async move {
+            let _slot = slot;
+            panic!("session handler panicked");
+        });
+        assert!(session.await.unwrap_err().is_panic());
+
+        assert_eq!(client_counter.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn tls_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let (certificate, private_key) =
+            (dir.path().join("server.crt"), dir.path().join("server.key"));
+        std::fs::write(&certificate, get_tls_file::get_certificate()).unwrap();
+        std::fs::write(&private_key, get_tls_file::get_rsa_key()).unwrap();
+
+        let mut config = config::with_tls();
+        config.server.r#virtual.insert(
+            "testserver.com".to_string(),
+            FieldServerVirtual {
+                tls: Some(
+                    FieldServerVirtualTls::from_path(
+                        certificate.to_str().unwrap(),
+                        private_key.to_str().unwrap(),
+                    )
+                    .unwrap(),
+                ),
+                ..FieldServerVirtual::default()
+            },
+        );
+
+        let tls_config = arc_swap::ArcSwap::from_pointee(
+            get_rustls_config(
+                config.server.tls.as_ref().unwrap(),
+                &config.server.r#virtual,
+            )
+            .unwrap(),
+        );
+        let initial = tls_config.load_full();
+
+        assert!(reload_tls_config(&config, &tls_config));
+        let reloaded = tls_config.load_full();
+        assert!(!std::sync::Arc::ptr_eq(&initial, &reloaded));
+
+        // a broken certificate must not replace the configuration in use
+        std::fs::write(&certificate, "not a certificate").unwrap();
+        assert!(!reload_tls_config(&config, &tls_config));
+        assert!(std::sync::Arc::ptr_eq(&reloaded, &tls_config.load_full()));
+    }
+
     #[tokio::test]
     async fn basic() {
         listen_with![
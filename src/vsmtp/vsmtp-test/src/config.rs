@@ -122,6 +122,11 @@ pub fn local_ctx() -> ContextFinished {
             mail_timestamp: time::OffsetDateTime::now_utc(),
             message_uuid: uuid::Uuid::new_v4(),
             reverse_path: Some("client@client.testserver.com".parse().expect("")),
+            dsn_return: None,
+            envelop_id: None,
+            use_smtputf8: false,
+            auth_mailbox: None,
+            require_tls: false,
         },
         rcpt_to: RcptToProperties {
             forward_paths: vec![],
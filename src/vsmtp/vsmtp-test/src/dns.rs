@@ -0,0 +1,145 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use trust_dns_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    proto::{
+        op::{Message, MessageType, ResponseCode},
+        rr::{Name, RData, Record, RecordType},
+    },
+    TokioAsyncResolver,
+};
+
+/// A dns server on localhost answering with a fixed set of records.
+///
+/// A query for a name without any record is answered with `NXDOMAIN`, and the
+/// aliases (CNAME) are followed as a recursive server would.
+pub struct MockDns {
+    addr: std::net::SocketAddr,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl MockDns {
+    /// Serve `records` on a random port.
+    ///
+    /// # Panics
+    ///
+    /// * the socket cannot be bound
+    pub async fn serve(records: Vec<Record>) -> Self {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut buffer = [0; 512];
+            while let Ok((len, client)) = socket.recv_from(&mut buffer).await {
+                let query = match Message::from_vec(&buffer[..len]) {
+                    Ok(query) => query,
+                    Err(_) => continue,
+                };
+                let response = Self::answer(&records, &query);
+                if let Ok(response) = response.to_vec() {
+                    let _sent = socket.send_to(&response, client).await;
+                }
+            }
+        });
+
+        Self { addr, server }
+    }
+
+    /// A resolver querying only this server, without cache.
+    ///
+    /// # Panics
+    ///
+    /// * the resolver cannot be built
+    #[must_use]
+    pub fn resolver(&self) -> TokioAsyncResolver {
+        let mut opts = ResolverOpts::default();
+        opts.attempts = 1;
+        opts.cache_size = 0;
+        opts.timeout = std::time::Duration::from_secs(1);
+
+        TokioAsyncResolver::tokio(
+            ResolverConfig::from_parts(
+                None,
+                vec![],
+                NameServerConfigGroup::from_ips_clear(&[self.addr.ip()], self.addr.port(), true),
+            ),
+            opts,
+        )
+        .unwrap()
+    }
+
+    /// The address of the server.
+    #[must_use]
+    pub const fn addr(&self) -> std::net::SocketAddr {
+        self.addr
+    }
+
+    fn answer(records: &[Record], query: &Message) -> Message {
+        let mut response = Message::new();
+        response
+            .set_id(query.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(query.op_code())
+            .set_recursion_desired(query.recursion_desired())
+            .set_recursion_available(true);
+
+        for question in query.queries() {
+            response.add_query(question.clone());
+
+            let mut name = question.name().clone();
+            if !records.iter().any(|record| record.name() == &name) {
+                response.set_response_code(ResponseCode::NXDomain);
+                continue;
+            }
+
+            loop {
+                let answers = Self::lookup(records, &name, question.query_type());
+                if !answers.is_empty() {
+                    response.add_answers(answers);
+                    break;
+                }
+
+                match Self::lookup(records, &name, RecordType::CNAME).first() {
+                    Some(alias) => {
+                        response.add_answer(alias.clone());
+                        name = match alias.data() {
+                            Some(RData::CNAME(target)) => target.clone(),
+                            _ => break,
+                        };
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        response
+    }
+
+    fn lookup(records: &[Record], name: &Name, record_type: RecordType) -> Vec<Record> {
+        records
+            .iter()
+            .filter(|record| record.name() == name && record.record_type() == record_type)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for MockDns {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
@@ -42,6 +42,9 @@ pub mod receiver;
 ///
 pub mod get_tls_file;
 
+/// Mock dns server
+pub mod dns;
+
 ///
 pub mod vsl;
 
@@ -48,7 +48,10 @@ macro_rules! run_test {
         $(, config = $config:expr)?
         $(, config_arc = $config_arc:expr)?
         $(, mail_handler = $mail_handler:expr)?
+        $(, token_validator = $token_validator:expr)?
+        $(, greylist_store = $greylist_store:expr)?
         $(, hierarchy_builder = $hierarchy_builder:expr)?
+        $(, shutdown_after = $shutdown_after:expr)?
         $(,)?
     ) => {{
         async fn upgrade_tls(server_name: &str, stream: tokio::net::TcpStream) -> tokio_rustls::client::TlsStream<tokio::net::TcpStream> {
@@ -113,6 +116,9 @@ macro_rules! run_test {
 
         let queue_manager_cloned = std::sync::Arc::clone(&queue_manager);
 
+        let shutdown = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let shutdown_server = shutdown.clone();
+
         let server = tokio::spawn(async move {
             let mail_handler = { // Box<dyn OnMail + Send>
                 let _f = || $crate::receiver::DefaultMailHandler::default();    $(
@@ -140,7 +146,9 @@ macro_rules! run_test {
                     config.clone(), $hierarchy_builder,
                     resolvers.clone(), queue_manager.clone()
                 ).unwrap();                                         )?
-                std::sync::Arc::new(_f())
+                let rule_engine = _f();
+                $( let rule_engine = rule_engine.with_greylist_store($greylist_store); )?
+                std::sync::Arc::new(rule_engine)
             };
 
             let smtp_handler = vsmtp_server::Handler::new(
@@ -168,7 +176,14 @@ macro_rules! run_test {
                 },
                 rule_engine,
                 queue_manager.clone(),
+                shutdown_server,
+            );
+            let smtp_handler = smtp_handler.with_rate_limiter(
+                config.server.rate_limit.as_ref().map(|rate_limit| {
+                    std::sync::Arc::new(vsmtp_server::RateLimiter::new(rate_limit))
+                })
             );
+            $( let smtp_handler = smtp_handler.with_token_validator(Some($token_validator)); )?
             let (client_stream, client_addr) = socket_server.accept().await.unwrap();
 
             let smtp_receiver = vsmtp_protocol::Receiver::<_, vsmtp_server::ValidationVSL, _, _>::new(
@@ -178,6 +193,13 @@ macro_rules! run_test {
                 config.server.smtp.error.soft_count,
                 config.server.smtp.error.hard_count,
                 config.server.message_size_limit,
+                vsmtp_protocol::ArgsPolicy::new(
+                    config.server.smtp.duplicate_params,
+                    config.server.smtp.strict_syntax,
+                    config.server.smtp.reject_legacy_routing,
+                    config.server.smtp.unknown_params,
+                ),
+                config.server.smtp.lenient_quit,
             );
             let smtp_stream = smtp_receiver.into_stream(
                 client_addr,
@@ -215,8 +237,21 @@ macro_rules! run_test {
 
                 output.push(line_received);
                 if output.last().unwrap().chars().nth(3) == Some('-') { continue; }
+                $(
+                    if output.len() == $shutdown_after {
+                        shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+                    }
+                )?
                 match line_to_send.next() {
-                    Some(line) => stream.write_all(line.as_bytes()).await.unwrap(),
+                    // an empty line sends nothing, to wait for the replies of pipelined commands
+                    Some(line) if line.is_empty() => (),
+                    Some(line) => {
+                        stream.write_all(line.as_bytes()).await.unwrap();
+                        // a line without CRLF is the last thing the client sends
+                        if !line.ends_with('\n') {
+                            stream.shutdown().await.unwrap();
+                        }
+                    },
                     None => break,
                 }
             }
@@ -269,7 +304,10 @@ macro_rules! run_test {
         $(, config = $config:expr)?
         $(, config_arc = $config_arc:expr)?
         $(, mail_handler = $mail_handler:expr)?
+        $(, token_validator = $token_validator:expr)?
+        $(, greylist_store = $greylist_store:expr)?
         $(, hierarchy_builder = $hierarchy_builder:expr)?
+        $(, shutdown_after = $shutdown_after:expr)?
         $(,)?
     ) => {
         #[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
@@ -282,7 +320,10 @@ macro_rules! run_test {
                 $(, config = $config)?
                 $(, config_arc = $config_arc)?
                 $(, mail_handler = $mail_handler)?
+                $(, token_validator = $token_validator)?
+                $(, greylist_store = $greylist_store)?
                 $(, hierarchy_builder = $hierarchy_builder)?
+                $(, shutdown_after = $shutdown_after)?
             };
         }
     };
@@ -22,16 +22,41 @@ mod examples {
     mod message;
 }
 mod protocol {
+    mod auth_param;
+    mod bdat;
+    mod case_insensitive;
+    mod before_queue_filter;
     mod clair;
+    mod dsn;
+    mod duplicate_params;
+    mod help;
+    mod legacy_routing;
     mod mail_from;
+    mod null_sender;
     mod message_max_size;
+    mod milter;
+    mod pipelining;
+    mod quit;
+    mod rate_limit;
+    mod rcpt_rejection;
+    mod rejected_samples;
     mod rset;
+    mod shutdown;
+    mod size;
+    mod smtputf8;
+    mod spool_free_space;
+    mod strip_headers;
+    mod transaction_count;
+    mod unknown_params;
+    mod virtual_limits;
     mod vrfy;
+    mod whitespace;
 
     pub mod auth;
     mod helo;
     mod tls {
         //mod cipher_suite;
+        mod requiretls;
         mod starttls;
         mod tunneled;
         mod tunneled_with_auth;
@@ -40,10 +65,16 @@ mod protocol {
 }
 mod rule_engine {
     mod actions;
+    mod envelop;
+    mod fail_policy;
     // mod todo;
     mod getters;
+    mod greylist;
+    mod headers;
+    mod rcpt_lookup;
     mod rule_default;
     mod rule_triage;
+    mod stage_scripts;
 }
 mod rules {
     mod codes;
@@ -35,7 +35,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH \r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "538 5.7.11 Encryption required for requested authentication mechanism\r\n",
     ],
@@ -58,7 +62,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "235 2.7.0 Authentication succeeded\r\n",
         "250 Ok\r\n",
@@ -109,7 +117,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         &format!("334 {}\r\n", STANDARD.encode("User Name\0")),
         &format!("334 {}\r\n", STANDARD.encode("Password\0")),
@@ -160,7 +172,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "235 2.7.0 Authentication succeeded\r\n",
         "250 Ok\r\n",
@@ -209,7 +225,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "235 2.7.0 Authentication succeeded\r\n",
         "250 Ok\r\n",
@@ -254,7 +274,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "535 5.7.8 Authentication credentials invalid\r\n"
     ],
@@ -279,7 +303,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "334 \r\n",
         "501 Authentication canceled by client\r\n",
@@ -309,7 +337,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "501 5.5.2 Invalid, not base64\r\n",
         "221 Service closing transmission channel\r\n"
@@ -317,6 +349,32 @@ run_test! {
     config = unsafe_auth_config()
 }
 
+run_test! {
+    fn plain_in_clair_unsecured_response_too_long,
+    input = [
+        "EHLO client.com\r\n",
+        "AUTH PLAIN\r\n",
+        &format!("{}\r\n", "a".repeat(20000)),
+        "QUIT\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "334 \r\n",
+        "500 5.5.6 Authentication Exchange line is too long\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    config = unsafe_auth_config()
+}
+
 run_test! {
     fn plain_in_clair_unsecured_without_initial_response,
     input = [
@@ -334,7 +392,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         // See https://datatracker.ietf.org/doc/html/rfc4422#section-5 2.a
         "334 \r\n",
@@ -381,7 +443,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "530 5.7.0 Authentication required\r\n",
     ],
@@ -409,7 +475,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "501 5.7.0 Client must not start with this mechanism\r\n"
     ],
@@ -0,0 +1,198 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use vsmtp_common::auth::{BearerToken, Mechanism, TokenError, TokenValidator};
+use vsmtp_config::Config;
+
+fn bearer_auth_config() -> Config {
+    Config::builder()
+        .with_version_str("<1.0.0")
+        .unwrap()
+        .without_path()
+        .with_server_name("testserver.com")
+        .with_user_group_and_default_system("root", "root")
+        .unwrap()
+        .with_ipv4_localhost()
+        .with_default_logs_settings()
+        .with_spool_dir_and_default_queues("./tmp/spool")
+        .without_tls_support()
+        .with_default_smtp_options()
+        .with_default_smtp_error_handler()
+        .with_default_smtp_codes()
+        .with_auth(true, vec![Mechanism::XOAuth2, Mechanism::OAuthBearer], -1)
+        .with_app_at_location("./tmp/app")
+        .with_vsl("./src/template/auth/domain-enabled")
+        .with_default_app_logs()
+        .with_system_dns()
+        .without_virtual_entries()
+        .validate()
+        .unwrap()
+}
+
+fn xoauth2(user: &str, token: &str) -> String {
+    STANDARD.encode(format!("user={user}\x01auth=Bearer {token}\x01\x01"))
+}
+
+const EHLO: [&str; 10] = [
+    "220 testserver.com Service ready\r\n",
+    "250-testserver.com\r\n",
+    "250-AUTH XOAUTH2 OAUTHBEARER\r\n",
+    "250-STARTTLS\r\n",
+    "250-SIZE 10000000\r\n",
+    "250-8BITMIME\r\n",
+    "250-CHUNKING\r\n",
+    "250-PIPELINING\r\n",
+    "250-DSN\r\n",
+    "250 SMTPUTF8\r\n",
+];
+
+run_test! {
+    fn xoauth2_valid_token,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("AUTH XOAUTH2 {}\r\n", xoauth2("hello", "valid-token")),
+        "QUIT\r\n"
+    ],
+    expected = [
+        &EHLO[..],
+        &[
+            "235 2.7.0 Authentication succeeded\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+    ].concat(),
+    config = bearer_auth_config()
+}
+
+run_test! {
+    fn xoauth2_invalid_token,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("AUTH XOAUTH2 {}\r\n", xoauth2("hello", "expired-token")),
+        "\r\n",
+    ],
+    expected = [
+        &EHLO[..],
+        &[
+            &format!(
+                "334 {}\r\n",
+                STANDARD.encode(r#"{"status":"invalid_token","schemes":"bearer"}"#)
+            ) as &str,
+            "535 5.7.8 Authentication credentials invalid\r\n"
+        ]
+    ].concat(),
+    config = bearer_auth_config()
+}
+
+run_test! {
+    fn oauthbearer_without_initial_response,
+    input = [
+        "EHLO client.com\r\n",
+        "AUTH OAUTHBEARER\r\n",
+        &format!(
+            "{}\r\n",
+            STANDARD.encode("n,a=hello,\x01host=testserver.com\x01port=25\x01auth=Bearer valid-token\x01\x01")
+        ),
+        "QUIT\r\n"
+    ],
+    expected = [
+        &EHLO[..],
+        &[
+            "334 \r\n",
+            "235 2.7.0 Authentication succeeded\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+    ].concat(),
+    config = bearer_auth_config()
+}
+
+run_test! {
+    fn oauthbearer_malformed,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("AUTH OAUTHBEARER {}\r\n", STANDARD.encode("n,,\x01host=testserver.com\x01\x01")),
+        "AQ==\r\n",
+    ],
+    expected = [
+        &EHLO[..],
+        &[
+            &format!(
+                "334 {}\r\n",
+                STANDARD.encode(r#"{"status":"invalid_token","schemes":"bearer"}"#)
+            ) as &str,
+            "535 5.7.8 Authentication credentials invalid\r\n"
+        ]
+    ].concat(),
+    config = bearer_auth_config()
+}
+
+struct ScopeValidator;
+
+impl TokenValidator for ScopeValidator {
+    fn validate(&self, token: &BearerToken) -> Result<(), TokenError> {
+        match token.token.as_str() {
+            "valid-token" => Err(TokenError::InsufficientScope {
+                scope: "https://mail.example.com/".to_owned(),
+            }),
+            _ => Err(TokenError::Unavailable(
+                "introspection endpoint unreachable".to_owned(),
+            )),
+        }
+    }
+}
+
+run_test! {
+    fn validator_insufficient_scope,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("AUTH XOAUTH2 {}\r\n", xoauth2("hello", "valid-token")),
+        "\r\n",
+    ],
+    expected = [
+        &EHLO[..],
+        &[
+            &format!(
+                "334 {}\r\n",
+                STANDARD.encode(
+                    r#"{"status":"insufficient_scope","schemes":"bearer","scope":"https://mail.example.com/"}"#
+                )
+            ) as &str,
+            "535 5.7.8 Authentication credentials invalid\r\n"
+        ]
+    ].concat(),
+    config = bearer_auth_config(),
+    token_validator = std::sync::Arc::new(ScopeValidator),
+}
+
+run_test! {
+    fn validator_unavailable,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("AUTH XOAUTH2 {}\r\n", xoauth2("hello", "other-token")),
+        "QUIT\r\n",
+    ],
+    expected = [
+        &EHLO[..],
+        &[
+            "454 4.7.0 Temporary authentication failure\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+    ].concat(),
+    config = bearer_auth_config(),
+    token_validator = std::sync::Arc::new(ScopeValidator),
+}
@@ -77,3 +77,5 @@ pub fn unsafe_auth_config() -> Config {
 }
 
 mod basic;
+mod bearer;
+mod scram;
@@ -0,0 +1,101 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_common::auth::Mechanism;
+use vsmtp_config::Config;
+
+fn scram_auth_config(enable_dangerous_mechanism_in_clair: bool) -> Config {
+    Config::builder()
+        .with_version_str("<1.0.0")
+        .unwrap()
+        .without_path()
+        .with_server_name("testserver.com")
+        .with_user_group_and_default_system("root", "root")
+        .unwrap()
+        .with_ipv4_localhost()
+        .with_default_logs_settings()
+        .with_spool_dir_and_default_queues("./tmp/spool")
+        .without_tls_support()
+        .with_default_smtp_options()
+        .with_default_smtp_error_handler()
+        .with_default_smtp_codes()
+        .with_scram_auth(
+            enable_dangerous_mechanism_in_clair,
+            vec![Mechanism::ScramSha256, Mechanism::ScramSha1],
+            -1,
+            "./tmp/scram_credentials",
+        )
+        .with_app_at_location("./tmp/app")
+        .with_vsl("./src/template/auth/domain-enabled")
+        .with_default_app_logs()
+        .with_system_dns()
+        .without_virtual_entries()
+        .validate()
+        .unwrap()
+}
+
+run_test! {
+    fn scram_in_clair_secured,
+    input = [
+        "EHLO foo\r\n",
+        "AUTH SCRAM-SHA-256\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-AUTH \r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "538 5.7.11 Encryption required for requested authentication mechanism\r\n",
+    ],
+    config = scram_auth_config(false)
+}
+
+run_test! {
+    fn scram_in_clair_unsecured_canceled,
+    input = [
+        "EHLO foo\r\n",
+        "AUTH SCRAM-SHA-256\r\n",
+        "*\r\n",
+        "AUTH SCRAM-SHA-1\r\n",
+        "*\r\n",
+        "QUIT\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-AUTH SCRAM-SHA-256 SCRAM-SHA-1\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "334 \r\n",
+        "501 Authentication canceled by client\r\n",
+        "334 \r\n",
+        "501 Authentication canceled by client\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    config = scram_auth_config(true)
+}
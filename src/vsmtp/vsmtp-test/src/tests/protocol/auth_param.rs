@@ -0,0 +1,156 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::auth::unsafe_auth_config;
+use crate::run_test;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use vqueue::GenericQueueManager;
+use vsmtp_common::CodeID;
+use vsmtp_common::ContextFinished;
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+run_test! {
+    fn auth_parameter_stored,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("AUTH PLAIN {}\r\n", STANDARD.encode(format!("\0{}\0{}", "hello", "world"))),
+        "MAIL FROM:<foo@bar> AUTH=john+2Bdoe@example.com\r\n",
+        "RCPT TO:<joe@doe>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "235 2.7.0 Authentication succeeded\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    config = unsafe_auth_config(),
+    mail_handler = {
+        struct T;
+
+        #[async_trait::async_trait]
+        impl OnMail for T {
+            async fn on_mail(
+                &mut self,
+                ctx: Box<ContextFinished>,
+                _: MessageBody,
+                _: std::sync::Arc<dyn GenericQueueManager>,
+            ) -> CodeID {
+                assert_eq!(
+                    ctx.mail_from.auth_mailbox,
+                    Some("john+doe@example.com".to_string())
+                );
+                CodeID::Ok
+            }
+        }
+
+        T
+    },
+}
+
+run_test! {
+    fn auth_parameter_of_unauthenticated_client,
+    input = [
+        "HELO client.com\r\n",
+        "MAIL FROM:<foo@bar> AUTH=john@example.com\r\n",
+        "RCPT TO:<joe@doe>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    mail_handler = {
+        struct T;
+
+        #[async_trait::async_trait]
+        impl OnMail for T {
+            async fn on_mail(
+                &mut self,
+                ctx: Box<ContextFinished>,
+                _: MessageBody,
+                _: std::sync::Arc<dyn GenericQueueManager>,
+            ) -> CodeID {
+                assert_eq!(ctx.mail_from.auth_mailbox, Some("<>".to_string()));
+                CodeID::Ok
+            }
+        }
+
+        T
+    },
+}
+
+run_test! {
+    fn no_auth_parameter,
+    input = [
+        "HELO client.com\r\n",
+        "MAIL FROM:<foo@bar>\r\n",
+        "RCPT TO:<joe@doe>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    mail_handler = {
+        struct T;
+
+        #[async_trait::async_trait]
+        impl OnMail for T {
+            async fn on_mail(
+                &mut self,
+                ctx: Box<ContextFinished>,
+                _: MessageBody,
+                _: std::sync::Arc<dyn GenericQueueManager>,
+            ) -> CodeID {
+                assert_eq!(ctx.mail_from.auth_mailbox, None);
+                CodeID::Ok
+            }
+        }
+
+        T
+    },
+}
@@ -0,0 +1,268 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+const EHLO: [&str; 9] = [
+    "220 testserver.com Service ready\r\n",
+    "250-testserver.com\r\n",
+    "250-STARTTLS\r\n",
+    "250-SIZE 10000000\r\n",
+    "250-8BITMIME\r\n",
+    "250-CHUNKING\r\n",
+    "250-PIPELINING\r\n",
+    "250-DSN\r\n",
+    "250 SMTPUTF8\r\n",
+];
+
+fn bdat(chunk: &str, last: bool) -> String {
+    format!(
+        "BDAT {}{}\r\n{chunk}",
+        chunk.len(),
+        if last { " LAST" } else { "" }
+    )
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn message_in_chunks() {
+    run_test! {
+        input = [
+            "EHLO foobar\r\n",
+            "MAIL FROM:<john@doe>\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            &bdat("Subject: hello\r\n\r\n", false),
+            "NOOP\r\n",
+            &bdat("..not unstuffed\r\n", true),
+            "QUIT\r\n",
+        ],
+        expected = [
+            EHLO.as_slice(),
+            &[
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "250 2.0.0 18 octets received\r\n",
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "221 Service closing transmission channel\r\n",
+            ],
+        ]
+        .concat(),
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    message: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    if message.get_header("Subject").as_deref() == Some("hello")
+                        && message
+                            .inner()
+                            .body()
+                            .as_ref()
+                            .map_or(false, |body| body.contains("..not unstuffed"))
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+    };
+}
+
+/// Accept the message if its body passes the check.
+struct ExpectBody(fn(&str) -> bool);
+
+#[async_trait::async_trait]
+impl OnMail for ExpectBody {
+    async fn on_mail(
+        &mut self,
+        _: Box<ContextFinished>,
+        message: MessageBody,
+        _: std::sync::Arc<dyn GenericQueueManager>,
+    ) -> CodeID {
+        if message.inner().body().as_deref().map_or(false, self.0) {
+            CodeID::Ok
+        } else {
+            CodeID::Denied
+        }
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn lone_dot_in_chunk() {
+    run_test! {
+        input = [
+            "EHLO foobar\r\n",
+            "MAIL FROM:<john@doe>\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            &bdat("Subject: hello\r\n\r\nbefore\r\n.\r\nafter\r\n", true),
+            "QUIT\r\n",
+        ],
+        expected = [
+            EHLO.as_slice(),
+            &[
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "221 Service closing transmission channel\r\n",
+            ],
+        ]
+        .concat(),
+        mail_handler = ExpectBody(|body| body.contains("before\r\n.\r\nafter\r\n")),
+    };
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn lone_dot_in_data() {
+    run_test! {
+        input = [
+            "EHLO foobar\r\n",
+            "MAIL FROM:<john@doe>\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            "DATA\r\n",
+            "Subject: hello\r\n\r\nbefore\r\n.\r\nafter\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            EHLO.as_slice(),
+            &[
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+                "250 Ok\r\n",
+                "500 Syntax error command unrecognized\r\n",
+                "221 Service closing transmission channel\r\n",
+            ],
+        ]
+        .concat(),
+        mail_handler = ExpectBody(|body| body.contains("before") && !body.contains("after")),
+    };
+}
+
+run_test! {
+    fn interleaving_forbidden,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        &bdat("Subject: hello\r\n", false),
+        "DATA\r\n",
+        "RCPT TO:<cc@dd>\r\n",
+        &bdat("\r\nbody\r\n", true),
+        &bdat("after last\r\n", true),
+        "QUIT\r\n",
+    ],
+    expected = [
+        EHLO.as_slice(),
+        &[
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 2.0.0 16 octets received\r\n",
+            "503 Bad sequence of commands\r\n",
+            "503 Bad sequence of commands\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+    ]
+    .concat(),
+}
+
+run_test! {
+    fn rset_discards_chunks,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        &bdat("Subject: hello\r\n", false),
+        "RSET\r\n",
+        "DATA\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        EHLO.as_slice(),
+        &[
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 2.0.0 16 octets received\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+    ]
+    .concat(),
+}
+
+run_test! {
+    fn size_exceeded,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        &bdat(&"X".repeat(600), false),
+        &bdat(&"X".repeat(600), true),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 2.0.0 600 octets received\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.message_size_limit = 1000;
+        config
+    },
+}
+
+run_test! {
+    fn malformed_size,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "BDAT 1O\r\n",
+        "BDAT -1 LAST\r\n",
+        "BDAT 10 NOTLAST\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
@@ -0,0 +1,142 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_config::field::FieldServerSMTPBeforeQueueFilter;
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+/// A filter adding a header to the message and accepting it.
+async fn mock_filter(listener: tokio::net::TcpListener) -> String {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut stream = tokio::io::BufReader::new(stream);
+
+    stream.write_all(b"220 filter ready\r\n").await.unwrap();
+
+    let mut filtered = String::from("X-Filtered: yes\r\n");
+    let mut in_data = false;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap() == 0 {
+            break;
+        }
+
+        if in_data {
+            if line == ".\r\n" {
+                in_data = false;
+                stream
+                    .write_all(b"250 2.0.0 Ok: filtered and re-injected\r\n")
+                    .await
+                    .unwrap();
+            } else {
+                filtered.push_str(&line);
+            }
+            continue;
+        }
+
+        match line.to_ascii_uppercase().as_str() {
+            ehlo if ehlo.starts_with("EHLO ") => {
+                stream
+                    .write_all(b"250-filter\r\n250 8BITMIME\r\n")
+                    .await
+                    .unwrap();
+            }
+            "DATA\r\n" => {
+                in_data = true;
+                stream.write_all(b"354 go ahead\r\n").await.unwrap();
+            }
+            "QUIT\r\n" => {
+                stream.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            }
+            _ => stream.write_all(b"250 Ok\r\n").await.unwrap(),
+        }
+    }
+
+    filtered
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn filter_modifies_and_accepts() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let filter = tokio::spawn(mock_filter(listener));
+
+    run_test! {
+        input = [
+            "EHLO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "Subject: hello\r\n",
+            "\r\n",
+            "..leading dot\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-STARTTLS\r\n",
+            "250-SIZE 10000000\r\n",
+            "250-8BITMIME\r\n",
+            "250-CHUNKING\r\n",
+            "250-PIPELINING\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 2.0.0 Ok: filtered and re-injected\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        config = {
+            let mut config = crate::config::local_test();
+            config.server.smtp.before_queue_filter = Some(FieldServerSMTPBeforeQueueFilter {
+                address,
+                reinject: "127.0.0.1:10027".parse().unwrap(),
+                timeout: std::time::Duration::from_secs(5),
+            });
+            config
+        },
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    _: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    // the filter took the responsibility of the message.
+                    CodeID::Denied
+                }
+            }
+
+            T
+        },
+    };
+
+    let filtered = filter.await.unwrap();
+    assert!(filtered.starts_with("X-Filtered: yes\r\n"));
+    assert!(filtered.contains("Subject: hello\r\n"));
+    assert!(filtered.ends_with("..leading dot\r\n"));
+}
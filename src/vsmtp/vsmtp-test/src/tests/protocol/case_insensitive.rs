@@ -0,0 +1,195 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::auth::unsafe_auth_config;
+use crate::run_test;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use vqueue::GenericQueueManager;
+use vsmtp_common::{addr, CodeID, ContextFinished};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+/// Accept the message if the case of its addresses has been kept.
+struct ExpectAddresses;
+
+#[async_trait::async_trait]
+impl OnMail for ExpectAddresses {
+    async fn on_mail(
+        &mut self,
+        ctx: Box<ContextFinished>,
+        _: MessageBody,
+        _: std::sync::Arc<dyn GenericQueueManager>,
+    ) -> CodeID {
+        assert_eq!(
+            ctx.mail_from.reverse_path.as_ref().map(ToString::to_string),
+            Some("John.Doe@Example.com".to_string())
+        );
+        assert_eq!(
+            *ctx.rcpt_to.forward_paths,
+            vec![addr!("Jane.Doe@Example.com").into()]
+        );
+        CodeID::Ok
+    }
+}
+
+run_test! {
+    fn verbs_in_mixed_case,
+    input = [
+        "ehLO foo\r\n",
+        "Mail From:<John.Doe@Example.com>\r\n",
+        "rCpT tO:<Jane.Doe@Example.com>\r\n",
+        "NoOp\r\n",
+        "hElP\r\n",
+        "dAtA\r\n",
+        ".\r\n",
+        "rSeT\r\n",
+        "qUiT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "214 joining us https://viridit.com/support\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    mail_handler = ExpectAddresses,
+}
+
+run_test! {
+    fn helo_and_parameters_in_mixed_case,
+    input = [
+        "HeLo foo\r\n",
+        "mail from:<John.Doe@Example.com> body=8bitmime Size=20\r\n",
+        "rcpt to:<Jane.Doe@Example.com> Notify=never\r\n",
+        "Data\r\n",
+        ".\r\n",
+        "quit\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    mail_handler = ExpectAddresses,
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn bdat_in_mixed_case() {
+    run_test! {
+        input = [
+            "HELO foo\r\n",
+            "MAIL FROM:<John.Doe@Example.com>\r\n",
+            "RCPT TO:<Jane.Doe@Example.com>\r\n",
+            "bDaT 18 LaSt\r\nSubject: hello\r\n\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        mail_handler = ExpectAddresses,
+    };
+}
+
+run_test! {
+    fn auth_in_mixed_case,
+    input = [
+        "EHLO client.com\r\n",
+        &format!("aUtH pLaIn {}\r\n", STANDARD.encode(format!("\0{}\0{}", "hello", "world"))),
+        "QUIT\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "235 2.7.0 Authentication succeeded\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    config = unsafe_auth_config(),
+}
+
+run_test! {
+    fn starttls_in_mixed_case,
+    input = [
+        "EHLO client.com\r\n",
+        "StartTls\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "220 TLS go ahead\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    starttls = "testserver.com" => [
+        "QUIT\r\n",
+    ],
+    config = {
+        let mut config = crate::config::with_tls();
+        config.server.r#virtual.insert(
+            "testserver.com".to_string(),
+            vsmtp_config::field::FieldServerVirtual {
+                tls: Some(
+                    vsmtp_config::field::FieldServerVirtualTls::from_path(
+                        "src/template/certs/certificate.crt",
+                        "src/template/certs/private_key.rsa.key",
+                    )
+                    .unwrap(),
+                ),
+                dns: None,
+                dkim: None,
+                rcpt_count_max: None,
+                message_size_limit: None,
+                codes: std::collections::BTreeMap::new(),
+            },
+        );
+        config
+    },
+}
@@ -0,0 +1,124 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::transfer::{DsnReturn, NotifyOn, OriginalRecipient};
+use vsmtp_common::CodeID;
+use vsmtp_common::ContextFinished;
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+run_test! {
+    fn parameters_stored,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<foo@bar> RET=HDRS ENVID=QQ+2B314159\r\n",
+        "RCPT TO:<john@doe> NOTIFY=SUCCESS,DELAY ORCPT=rfc822;john+40example.com\r\n",
+        "RCPT TO:<jane@doe> NOTIFY=NEVER\r\n",
+        "RCPT TO:<jenny@doe>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    mail_handler = {
+        struct T;
+
+        #[async_trait::async_trait]
+        impl OnMail for T {
+            async fn on_mail(
+                &mut self,
+                ctx: Box<ContextFinished>,
+                _: MessageBody,
+                _: std::sync::Arc<dyn GenericQueueManager>,
+            ) -> CodeID {
+                assert_eq!(ctx.mail_from.dsn_return, Some(DsnReturn::Headers));
+                assert_eq!(ctx.mail_from.envelop_id, Some("QQ+314159".to_string()));
+
+                let [john, jane, jenny] = match ctx.rcpt_to.forward_paths.as_slice() {
+                    [john, jane, jenny] => [john, jane, jenny],
+                    otherwise => panic!("unexpected recipients: {otherwise:?}"),
+                };
+                assert_eq!(
+                    john.notify_on,
+                    NotifyOn { success: true, failure: false, delay: true }
+                );
+                assert_eq!(
+                    john.original_forward_path,
+                    Some(OriginalRecipient {
+                        addr_type: "rfc822".to_string(),
+                        mailbox: "john@example.com".to_string(),
+                    })
+                );
+                assert_eq!(jane.notify_on, NotifyOn::never());
+                assert_eq!(jenny.notify_on, NotifyOn::default());
+                assert_eq!(jenny.original_forward_path, None);
+
+                CodeID::Ok
+            }
+        }
+
+        T
+    },
+}
+
+run_test! {
+    fn invalid_parameters,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<foo@bar> RET=BODY\r\n",
+        "MAIL FROM:<foo@bar> ENVID=QQ+2\r\n",
+        &format!("MAIL FROM:<foo@bar> ENVID={}\r\n", "a".repeat(101)),
+        "MAIL FROM:<foo@bar> RET=FULL\r\n",
+        "RCPT TO:<john@doe> NOTIFY=NEVER,SUCCESS\r\n",
+        "RCPT TO:<john@doe> NOTIFY=SOMETIMES\r\n",
+        "RCPT TO:<john@doe> ORCPT=john@example.com\r\n",
+        "RCPT TO:<john@doe> NOTIFY=FAILURE\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
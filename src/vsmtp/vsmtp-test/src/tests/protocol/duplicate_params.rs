@@ -0,0 +1,61 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_common::DuplicateParamsPolicy;
+
+run_test! {
+    fn duplicate_reject,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a@b> BODY=7BIT body=8BITMIME\r\n",
+        "MAIL FROM:<a@b> BODY=8BITMIME\r\n",
+        "RCPT TO:<b@c> NOTIFY=NEVER NOTIFY=SUCCESS\r\n",
+        "RCPT TO:<b@c> NOTIFY=NEVER\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
+
+run_test! {
+    fn duplicate_last_wins,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a@b> BODY=7BIT body=8BITMIME\r\n",
+        "RCPT TO:<b@c> NOTIFY=NEVER NOTIFY=SUCCESS\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.smtp.duplicate_params = DuplicateParamsPolicy::LastWins;
+        config
+    },
+}
@@ -0,0 +1,45 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::config;
+use crate::run_test;
+use vsmtp_common::CodeID;
+
+fn with_multi_line_help() -> vsmtp_config::Config {
+    let mut config = config::local_test();
+    config.server.smtp.codes.insert(
+        CodeID::Help,
+        "214-This server supports the following commands\r\n214 HELO EHLO MAIL RCPT DATA QUIT\r\n"
+            .parse()
+            .unwrap(),
+    );
+    config
+}
+
+run_test! {
+    fn help_multi_line,
+    input = [
+        "HELP\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "214-This server supports the following commands\r\n",
+        "214 HELO EHLO MAIL RCPT DATA QUIT\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = with_multi_line_help(),
+}
@@ -0,0 +1,62 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+
+run_test! {
+    fn legacy_routing_rejected,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a%b@c>\r\n",
+        "MAIL FROM:<\"a%b\"@c>\r\n",
+        "RCPT TO:<h!u@d>\r\n",
+        "RCPT TO:<a%b@c>\r\n",
+        "RCPT TO:<u@d>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "553 5.1.3 Percent-hack and UUCP addresses are not accepted\r\n",
+        "250 Ok\r\n",
+        "553 5.1.3 Percent-hack and UUCP addresses are not accepted\r\n",
+        "553 5.1.3 Percent-hack and UUCP addresses are not accepted\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.smtp.reject_legacy_routing = true;
+        config
+    },
+}
+
+run_test! {
+    fn legacy_routing_accepted_by_default,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a%b@c>\r\n",
+        "RCPT TO:<h!u@d>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
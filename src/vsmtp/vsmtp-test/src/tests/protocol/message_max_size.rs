@@ -34,7 +34,7 @@ run_test! {
         "250 Ok\r\n",
         "250 Ok\r\n",
         "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
-        "552 4.3.1 Message size exceeds fixed maximum message size\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
         "221 Service closing transmission channel\r\n",
     ],
     config = {
@@ -0,0 +1,136 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_config::field::FieldServerSMTPMilter;
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+async fn write_packet(stream: &mut tokio::net::TcpStream, code: u8, data: &[u8]) {
+    stream
+        .write_u32(u32::try_from(data.len() + 1).unwrap())
+        .await
+        .unwrap();
+    stream.write_u8(code).await.unwrap();
+    stream.write_all(data).await.unwrap();
+}
+
+/// A milter asking for every step, and adding a header at the end of the message.
+async fn mock_milter(listener: tokio::net::TcpListener) -> Vec<u8> {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut commands = vec![];
+
+    loop {
+        let len = stream.read_u32().await.unwrap() as usize;
+        let mut packet = vec![0; len];
+        stream.read_exact(&mut packet).await.unwrap();
+        commands.push(packet[0]);
+
+        match packet[0] {
+            b'O' => {
+                let mut negotiation = vec![];
+                negotiation.extend_from_slice(&6u32.to_be_bytes());
+                negotiation.extend_from_slice(&0x01u32.to_be_bytes());
+                negotiation.extend_from_slice(&0u32.to_be_bytes());
+                write_packet(&mut stream, b'O', &negotiation).await;
+            }
+            b'E' => {
+                write_packet(&mut stream, b'h', b"X-Milter\0yes\0").await;
+                write_packet(&mut stream, b'c', &[]).await;
+                return commands;
+            }
+            b'A' | b'D' => (),
+            _ => write_packet(&mut stream, b'c', &[]).await,
+        }
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn milter_adds_header() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let milter = tokio::spawn(mock_milter(listener));
+
+    run_test! {
+        input = [
+            "EHLO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "Subject: hello\r\n",
+            "\r\n",
+            "body\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-STARTTLS\r\n",
+            "250-SIZE 10000000\r\n",
+            "250-8BITMIME\r\n",
+            "250-CHUNKING\r\n",
+            "250-PIPELINING\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        config = {
+            let mut config = crate::config::local_test();
+            config.server.smtp.milters = vec![FieldServerSMTPMilter {
+                address,
+                timeout: std::time::Duration::from_secs(5),
+            }];
+            config
+        },
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    message: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    if message.get_header("X-Milter").as_deref() == Some("yes")
+                        && message.get_header("Subject").as_deref() == Some("hello")
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+    };
+
+    assert_eq!(
+        milter.await.unwrap(),
+        b"OCHMRTLNBE".to_vec(),
+        "the milter must be called at every stage"
+    );
+}
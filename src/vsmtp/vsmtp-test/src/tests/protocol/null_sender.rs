@@ -0,0 +1,146 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms &of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::config;
+use crate::run_test;
+use vsmtp_common::NullSenderPolicy;
+use vsmtp_server::ProcessMessage;
+
+run_test! {
+    fn multiple_rcpt_accepted,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "RCPT TO:<cc@bb>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
+
+run_test! {
+    fn single_rcpt,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "RCPT TO:<cc@bb>\r\n",
+        "RSET\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "RCPT TO:<cc@bb>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "550 5.5.3 Messages with a null sender must have a single recipient\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.smtp.null_sender.policy = NullSenderPolicy::SingleRecipient;
+        config
+    },
+}
+
+run_test! {
+    fn is_bounce,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RSET\r\n",
+        "MAIL FROM:<>\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "550 5.7.1 No bounce here\r\n",
+    ],
+    hierarchy_builder = |builder| {
+        Ok(builder.add_root_filter_rules(r#"#{
+          mail: [
+            rule "no bounce" || {
+              if ctx::is_bounce() { state::deny(code(550, "5.7.1", "No bounce here\r\n")) } else { state::next() }
+            }
+          ],
+        }
+      "#).unwrap().build())
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn quarantine() {
+    let (delivery_sender, _d) = tokio::sync::mpsc::channel::<ProcessMessage>(1);
+    let (working_sender, _w) = tokio::sync::mpsc::channel::<ProcessMessage>(1);
+
+    let queue_manager = run_test! {
+        input = [
+            "HELO foobar\r\n",
+            "MAIL FROM:<>\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            "DATA\r\n",
+            concat!(
+                "from: 'abc'\r\n",
+                "to: 'def'\r\n",
+                ".\r\n",
+            ),
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        config = {
+            let mut config = config::local_test();
+            config.server.smtp.null_sender.quarantine = Some("null_sender".to_string());
+            config
+        },
+        mail_handler = vsmtp_server::MailHandler::new(working_sender, delivery_sender),
+    };
+
+    assert_eq!(
+        std::fs::read_dir(vqueue::FilesystemQueueManagerExt::get_queue_path(
+            &*queue_manager,
+            &vqueue::QueueID::Quarantine {
+                name: "null_sender".to_string()
+            }
+        ))
+        .unwrap()
+        .count(),
+        1
+    );
+}
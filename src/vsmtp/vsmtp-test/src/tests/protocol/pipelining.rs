@@ -0,0 +1,142 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::config::with_tls;
+use crate::run_test;
+use vsmtp_config::field::FieldServerVirtual;
+use vsmtp_config::field::FieldServerVirtualTls;
+
+run_test! {
+    fn mail_rcpt_data_in_one_write,
+    input = [
+        "EHLO foobar\r\n",
+        concat!(
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            "DATA\r\n",
+        ),
+        "",
+        "",
+        concat!(
+            "from: a b <a@b>\r\n",
+            "\r\n",
+            "mail content wow\r\n",
+            ".\r\n",
+        ),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ]
+}
+
+run_test! {
+    fn rejected_commands_in_one_write,
+    input = [
+        "EHLO foobar\r\n",
+        concat!(
+            "RCPT TO:<b@c>\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            "QUIT\r\n",
+        ),
+        "",
+        "",
+        "",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "503 Bad sequence of commands\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ]
+}
+
+run_test! {
+    fn commands_after_starttls_discarded,
+    input = [
+        "EHLO client.com\r\n",
+        "STARTTLS\r\nNOOP\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "220 TLS go ahead\r\n",
+        "250-testserver.com\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250-SMTPUTF8\r\n",
+        "250 REQUIRETLS\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    starttls = "testserver.com" => [
+        "EHLO client.com\r\n",
+        "QUIT\r\n",
+    ],
+    config = {
+      let mut config = with_tls();
+      config.server.r#virtual.insert(
+          "testserver.com".to_string(),
+          FieldServerVirtual {
+              tls: Some(
+                  FieldServerVirtualTls::from_path(
+                      "src/template/certs/certificate.crt",
+                      "src/template/certs/private_key.rsa.key",
+                  )
+                  .unwrap(),
+              ),
+              dns: None,
+              dkim: None,
+              rcpt_count_max: None,
+              message_size_limit: None,
+              codes: std::collections::BTreeMap::new(),
+          },
+      );
+      config
+    }
+}
@@ -0,0 +1,59 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+
+run_test! {
+    fn quit_without_crlf,
+    input = [
+        "HELO foo\r\n",
+        "QUIT",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
+
+run_test! {
+    fn quit_without_crlf_strict,
+    input = [
+        "HELO foo\r\n",
+        "QUIT",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.smtp.lenient_quit = false;
+        config
+    },
+}
+
+run_test! {
+    fn unterminated_command,
+    input = [
+        "HELO foo\r\n",
+        "NOOP",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+    ],
+}
@@ -0,0 +1,76 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_config::field::FieldServerRateLimit;
+
+fn with_rate_limit(
+    message_per_minute: Option<u32>,
+    rcpt_per_hour: Option<u32>,
+) -> vsmtp_config::Config {
+    let mut config = crate::config::local_test();
+    config.server.rate_limit = Some(FieldServerRateLimit {
+        connection_count_max: None,
+        message_per_minute,
+        rcpt_per_hour,
+        cleanup_period: std::time::Duration::from_secs(60),
+    });
+    config
+}
+
+run_test! {
+    fn message_per_minute_exceeded,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RSET\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RSET\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "452 4.7.0 Rate limit exceeded, retry later\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = with_rate_limit(Some(2), None),
+}
+
+run_test! {
+    fn rcpt_per_hour_exceeded,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "RCPT TO:<aa2@bb>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "452 4.7.0 Rate limit exceeded, retry later\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = with_rate_limit(None, Some(1)),
+}
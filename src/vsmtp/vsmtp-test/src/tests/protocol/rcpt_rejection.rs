@@ -0,0 +1,86 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_common::CodeID;
+
+const RULES: &str = r#"#{
+    rcpt: [
+      rule "rejection causes" || {
+        if ctx::rcpt().domain != "testserver.com" {
+          state::deny_for("relay-denied")
+        } else if ctx::rcpt().local_part == "nobody" {
+          state::deny_for("user-unknown")
+        } else {
+          state::next()
+        }
+      }
+    ],
+}
+"#;
+
+fn config() -> vsmtp_config::Config {
+    let mut config = crate::config::local_test();
+    config.server.smtp.codes.insert(
+        CodeID::UnknownRecipient,
+        "550 5.1.1 reason={reason} mailbox does not exist\r\n"
+            .parse()
+            .unwrap(),
+    );
+    config.server.smtp.codes.insert(
+        CodeID::RelayDenied,
+        "554 5.7.1 reason={reason} relaying is not permitted\r\n"
+            .parse()
+            .unwrap(),
+    );
+    config
+}
+
+run_test! {
+    fn rcpt_user_unknown,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<jane@testserver.com>\r\n",
+        "RCPT TO:<nobody@testserver.com>\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "550 5.1.1 reason=user-unknown mailbox does not exist\r\n",
+    ],
+    config = config(),
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(RULES)?.build()),
+}
+
+run_test! {
+    fn rcpt_relay_denied,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<jane@elsewhere.org>\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "554 5.7.1 reason=relay-denied relaying is not permitted\r\n",
+    ],
+    config = config(),
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(RULES)?.build()),
+}
@@ -0,0 +1,119 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_config::field::FieldAppRejectedSamples;
+
+async fn send_spam(rejected_samples: Option<FieldAppRejectedSamples>) {
+    let mut config = crate::config::local_test();
+    config.app.rejected_samples = rejected_samples;
+
+    run_test! {
+        input = [
+            "HELO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "Subject: spam\r\n",
+            "\r\n",
+            "buy now\r\n",
+            ".\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "550 5.7.1 Spam\r\n",
+        ],
+        config = config,
+        hierarchy_builder = |builder| {
+            Ok(builder.add_root_filter_rules(r#"#{
+                preq: [
+                  rule "spam" || {
+                    if msg::get_header("Subject") == "spam" {
+                      state::deny(code(550, "5.7.1", "Spam"))
+                    } else {
+                      state::next()
+                    }
+                  },
+                ],
+              }
+            "#)?.build())
+        },
+    };
+}
+
+fn samples_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("vsmtp-rejected-{}", uuid::Uuid::new_v4()))
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn sample_written() {
+    let dirpath = samples_dir();
+
+    send_spam(Some(FieldAppRejectedSamples {
+        dirpath: dirpath.clone(),
+        body_size: 4,
+        ..FieldAppRejectedSamples::default()
+    }))
+    .await;
+
+    let samples = std::fs::read_dir(&dirpath)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(samples.len(), 1);
+    assert!(samples[0].ends_with("Subject: spam\r\n\r\nbuy "));
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn sample_size_max() {
+    let dirpath = samples_dir();
+
+    send_spam(Some(FieldAppRejectedSamples {
+        dirpath: dirpath.clone(),
+        size_max: 8,
+        ..FieldAppRejectedSamples::default()
+    }))
+    .await;
+
+    let samples = std::fs::read_dir(&dirpath)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].len(), 8);
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn sample_disabled() {
+    let dirpath = samples_dir();
+
+    send_spam(Some(FieldAppRejectedSamples {
+        enabled: false,
+        dirpath: dirpath.clone(),
+        ..FieldAppRejectedSamples::default()
+    }))
+    .await;
+
+    assert!(!dirpath.exists());
+}
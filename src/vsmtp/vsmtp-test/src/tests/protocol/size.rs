@@ -0,0 +1,116 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::config;
+use crate::run_test;
+
+fn with_limit() -> vsmtp_config::Config {
+    let mut config = config::local_test();
+    config.server.message_size_limit = 1000;
+    config
+}
+
+run_test! {
+    fn ehlo_advertise_size,
+    input = [
+        "EHLO foobar\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 1000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+    ],
+    config = with_limit(),
+}
+
+run_test! {
+    fn mail_from_size_too_big,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<john@doe> SIZE=1001\r\n",
+        "MAIL FROM:<john@doe> SIZE=1000\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 1000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "250 Ok\r\n",
+    ],
+    config = with_limit(),
+}
+
+run_test! {
+    fn mail_from_size_unknown,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<john@doe> SIZE=0\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "DATA\r\n",
+        &("X".repeat(1000) + ".\r\n"),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 1000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = with_limit(),
+}
+
+run_test! {
+    fn mail_from_size_invalid,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<john@doe> SIZE=10k\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 1000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+    ],
+    config = with_limit(),
+}
@@ -0,0 +1,143 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+const OK: &str = "250 Ok\r\n";
+const REQUIRED: &str = "553 5.6.7 Non-ASCII addresses require the SMTPUTF8 parameter\r\n";
+
+fn run(mail_from: &str, rcpt_to: &str, smtputf8_strict: bool, expected: [&str; 2]) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let input = vec![
+        "HELO foo\r\n".to_string(),
+        mail_from.to_string(),
+        rcpt_to.to_string(),
+        "QUIT\r\n".to_string(),
+    ];
+    let output = vec![
+        "220 testserver.com Service ready\r\n".to_string(),
+        OK.to_string(),
+        expected[0].to_string(),
+        expected[1].to_string(),
+        "221 Service closing transmission channel\r\n".to_string(),
+    ];
+
+    runtime.block_on(async move {
+        run_test! {
+            input = input,
+            expected = output,
+            config = {
+                let mut config = crate::config::local_test();
+                config.server.smtp.smtputf8_strict = smtputf8_strict;
+                config
+            },
+        };
+    });
+}
+
+#[rstest::rstest]
+#[case::ascii("MAIL FROM:<foo@bar>\r\n", "RCPT TO:<bar@foo>\r\n", [OK, OK], [OK, OK])]
+#[case::utf8_rcpt_declared(
+    "MAIL FROM:<foo@bar> SMTPUTF8\r\n",
+    "RCPT TO:<josé@foo>\r\n",
+    [OK, OK],
+    [OK, OK]
+)]
+#[case::utf8_rcpt_not_declared(
+    "MAIL FROM:<foo@bar>\r\n",
+    "RCPT TO:<josé@foo>\r\n",
+    [OK, OK],
+    [OK, REQUIRED]
+)]
+#[case::utf8_reverse_path_declared(
+    "MAIL FROM:<josé@bar> SMTPUTF8\r\n",
+    "RCPT TO:<bar@foo>\r\n",
+    [OK, OK],
+    [OK, OK]
+)]
+#[case::utf8_reverse_path_not_declared(
+    "MAIL FROM:<josé@bar>\r\n",
+    "RCPT TO:<bar@foo>\r\n",
+    [OK, OK],
+    // NOTE: the recipient is received out of a transaction.
+    [REQUIRED, "503 Bad sequence of commands\r\n"]
+)]
+#[trace]
+fn smtputf8(
+    #[case] mail_from: &str,
+    #[case] rcpt_to: &str,
+    #[case] lenient: [&str; 2],
+    #[case] strict: [&str; 2],
+) {
+    run(mail_from, rcpt_to, false, lenient);
+    run(mail_from, rcpt_to, true, strict);
+}
+
+run_test! {
+    fn parameter_stored,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<foo@bar> SMTPUTF8\r\n",
+        "RCPT TO:<josé@foo>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.smtp.smtputf8_strict = true;
+        config
+    },
+    mail_handler = {
+        struct T;
+
+        #[async_trait::async_trait]
+        impl OnMail for T {
+            async fn on_mail(
+                &mut self,
+                ctx: Box<ContextFinished>,
+                _: MessageBody,
+                _: std::sync::Arc<dyn GenericQueueManager>,
+            ) -> CodeID {
+                assert!(ctx.mail_from.use_smtputf8);
+                assert_eq!(
+                    ctx.rcpt_to.forward_paths[0].address.full(),
+                    "josé@foo"
+                );
+                CodeID::Ok
+            }
+        }
+
+        T
+    },
+}
@@ -0,0 +1,111 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_config::field::FieldServerQueuesMinFreeSpace;
+
+run_test! {
+    fn data_refused_below_threshold,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a@b>\r\n",
+        "RCPT TO:<b@c>\r\n",
+        "DATA\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "452 4.3.1 Insufficient system storage\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.queues.dirpath = std::env::temp_dir();
+        config.server.queues.min_free_space = Some(FieldServerQueuesMinFreeSpace {
+            bytes: u64::MAX,
+            percent: 0,
+        });
+        config
+    },
+}
+
+run_test! {
+    fn data_accepted_above_threshold,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a@b>\r\n",
+        "RCPT TO:<b@c>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.queues.dirpath = std::env::temp_dir();
+        config.server.queues.min_free_space = Some(FieldServerQueuesMinFreeSpace {
+            bytes: 1,
+            percent: 0,
+        });
+        config
+    },
+}
+
+run_test! {
+    fn bdat_refused_below_threshold,
+    input = [
+        "EHLO foo\r\n",
+        "MAIL FROM:<a@b>\r\n",
+        "RCPT TO:<b@c>\r\n",
+        "BDAT 16 LAST\r\nSubject: hello\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "452 4.3.1 Insufficient system storage\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.queues.dirpath = std::env::temp_dir();
+        config.server.queues.min_free_space = Some(FieldServerQueuesMinFreeSpace {
+            bytes: u64::MAX,
+            percent: 0,
+        });
+        config
+    },
+}
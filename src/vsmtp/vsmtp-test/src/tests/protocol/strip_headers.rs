@@ -0,0 +1,198 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn spoofed_authentication_results() {
+    run_test! {
+        input = [
+            "HELO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "Authentication-Results: testserver.com;\r\n",
+            " spf=pass smtp.mailfrom=bar\r\n",
+            "X-Originating-IP: [10.0.0.1]\r\n",
+            "Subject: hello\r\n",
+            "authentication-results: testserver.com; dkim=pass\r\n",
+            "\r\n",
+            "body\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        config = {
+            let mut config = crate::config::local_test();
+            config.server.smtp.strip_headers =
+                vec!["Authentication-Results".to_string(), "X-Originating-IP".to_string()];
+            config
+        },
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    message: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    if message.count_header("Authentication-Results") == 1
+                        && message.get_header("Authentication-Results").as_deref()
+                            == Some("testserver.com; spf=fail")
+                        && message.get_header("X-Originating-IP").is_none()
+                        && message.get_header("Subject").as_deref() == Some("hello")
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+        hierarchy_builder = |builder| {
+            Ok(builder.add_root_filter_rules(r#"#{
+                preq: [
+                  rule "spoofed headers are not seen" || {
+                    if msg::has_header("X-Originating-IP") { state::deny() } else { state::next() }
+                  },
+                  action "add our own results" || msg::prepend_header("Authentication-Results", "testserver.com; spf=fail"),
+                ],
+              }
+            "#)?.build())
+        },
+    };
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn nothing_to_strip() {
+    run_test! {
+        input = [
+            "HELO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "Authentication-Results: example.com; spf=pass\r\n",
+            "\r\n",
+            "body\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    message: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    if message.get_header("Authentication-Results").as_deref()
+                        == Some("example.com; spf=pass")
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+    };
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn trusted_client() {
+    run_test! {
+        input = [
+            "HELO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "Authentication-Results: testserver.com; spf=pass\r\n",
+            "\r\n",
+            "body\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        config = {
+            let mut config = crate::config::local_test();
+            config.server.smtp.strip_headers = vec!["Authentication-Results".to_string()];
+            config.server.trusted_networks = vec!["127.0.0.0/8".parse().unwrap()];
+            config
+        },
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    message: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    if message.get_header("Authentication-Results").as_deref()
+                        == Some("testserver.com; spf=pass")
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+    };
+}
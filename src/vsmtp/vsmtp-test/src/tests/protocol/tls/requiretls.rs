@@ -0,0 +1,129 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::config::with_tls;
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::CodeID;
+use vsmtp_common::ContextFinished;
+use vsmtp_config::field::FieldServerVirtual;
+use vsmtp_config::field::FieldServerVirtualTls;
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+run_test! {
+    fn requiretls_stored,
+    input = [
+        "EHLO client.com\r\n",
+        "STARTTLS\r\n"
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "220 TLS go ahead\r\n",
+        "250-testserver.com\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250-SMTPUTF8\r\n",
+        "250 REQUIRETLS\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    starttls = "testserver.com" => [
+        "EHLO client.com\r\n",
+        "MAIL FROM:<foo@bar> REQUIRETLS\r\n",
+        "RCPT TO:<bar@foo>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+        "QUIT\r\n",
+    ],
+    config = {
+      let mut config = with_tls();
+      config.server.r#virtual.insert(
+          "testserver.com".to_string(),
+          FieldServerVirtual {
+              tls: Some(
+                  FieldServerVirtualTls::from_path(
+                      "src/template/certs/certificate.crt",
+                      "src/template/certs/private_key.rsa.key",
+                  )
+                  .unwrap(),
+              ),
+              dns: None,
+              dkim: None,
+              rcpt_count_max: None,
+              message_size_limit: None,
+              codes: std::collections::BTreeMap::new(),
+          },
+      );
+      config
+    },
+    mail_handler = {
+        struct T;
+
+        #[async_trait::async_trait]
+        impl OnMail for T {
+            async fn on_mail(
+                &mut self,
+                ctx: Box<ContextFinished>,
+                _: MessageBody,
+                _: std::sync::Arc<dyn GenericQueueManager>,
+            ) -> CodeID {
+                assert!(ctx.mail_from.require_tls);
+                CodeID::Ok
+            }
+        }
+
+        T
+    },
+}
+
+run_test! {
+    fn requiretls_in_clair,
+    input = [
+        "EHLO client.com\r\n",
+        "MAIL FROM:<foo@bar> REQUIRETLS\r\n",
+        "MAIL FROM:<foo@bar>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "530 5.7.10 REQUIRETLS needs an encrypted connection\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
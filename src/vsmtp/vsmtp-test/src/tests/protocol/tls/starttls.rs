@@ -29,12 +29,21 @@ run_test! {
         "220 testserver.com Service ready\r\n",
         "250-testserver.com\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "220 TLS go ahead\r\n",
         "250-testserver.com\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
-        "250 SMTPUTF8\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250-SMTPUTF8\r\n",
+        "250 REQUIRETLS\r\n",
         "250 Ok\r\n",
         "250 Ok\r\n",
         "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
@@ -64,6 +73,9 @@ run_test! {
               ),
               dns: None,
               dkim: None,
+              rcpt_count_max: None,
+              message_size_limit: None,
+              codes: std::collections::BTreeMap::new(),
           },
       );
       config
@@ -94,12 +106,21 @@ run_test! {
         "220 testserver.com Service ready\r\n",
         "250-testserver.com\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "220 TLS go ahead\r\n",
         "250-testserver.com\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
-        "250 SMTPUTF8\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250-SMTPUTF8\r\n",
+        "250 REQUIRETLS\r\n",
         "554 5.5.1 Error: TLS already active\r\n",
         "221 Service closing transmission channel\r\n",
     ],
@@ -123,6 +144,9 @@ run_test! {
               ),
               dns: None,
               dkim: None,
+              rcpt_count_max: None,
+              message_size_limit: None,
+              codes: std::collections::BTreeMap::new(),
           },
       );
       config
@@ -150,7 +174,11 @@ run_test! {
         "220 testserver.com Service ready\r\n",
         "250-testserver.com\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "454 TLS not available due to temporary reason\r\n",
         "221 Service closing transmission channel\r\n",
@@ -167,7 +195,11 @@ run_test! {
         "220 testserver.com Service ready\r\n",
         "250-testserver.com\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "451 5.7.3 Must issue a STARTTLS command first\r\n",
     ],
@@ -55,6 +55,9 @@ run_test! {
               ),
               dns: None,
               dkim: None,
+              rcpt_count_max: None,
+              message_size_limit: None,
+              codes: std::collections::BTreeMap::new(),
           },
       );
       config
@@ -100,6 +103,9 @@ run_test! {
               ),
               dns: None,
               dkim: None,
+              rcpt_count_max: None,
+              message_size_limit: None,
+              codes: std::collections::BTreeMap::new(),
           },
       );
       config
@@ -142,6 +148,9 @@ run_test! {
                 ),
                 dns: None,
                 dkim: None,
+                rcpt_count_max: None,
+                message_size_limit: None,
+                codes: std::collections::BTreeMap::new(),
             },
         );
         config
@@ -158,6 +167,60 @@ run_test! {
     }
 }
 
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn ehlo_hostname_from_sni() {
+    let config = std::sync::Arc::new({
+        let mut config = with_tls();
+        config.app.vsl.domain_dir = Some("./src/template/sni".into());
+        for (domain, certificate, private_key) in [
+            (
+                "testserver.com",
+                "src/template/certs/certificate.crt",
+                "src/template/certs/private_key.rsa.key",
+            ),
+            (
+                "second.testserver.com",
+                "src/template/certs/sni/second.certificate.crt",
+                "src/template/certs/sni/second.private_key.rsa.key",
+            ),
+        ] {
+            config.server.r#virtual.insert(
+                domain.to_string(),
+                FieldServerVirtual {
+                    tls: Some(FieldServerVirtualTls::from_path(certificate, private_key).unwrap()),
+                    dns: None,
+                    dkim: None,
+                    rcpt_count_max: None,
+                    message_size_limit: None,
+                    codes: std::collections::BTreeMap::new(),
+                },
+            );
+        }
+        config
+    });
+
+    for server_name in ["testserver.com", "second.testserver.com"] {
+        run_test! {
+            input = [
+                "EHLO client.com\r\n",
+            ],
+            expected = [
+                "220 testserver.com Service ready\r\n".to_string(),
+                format!("250-{server_name}\r\n"),
+                "250-SIZE 10000000\r\n".to_string(),
+                "250-8BITMIME\r\n".to_string(),
+                "250-CHUNKING\r\n".to_string(),
+                "250-PIPELINING\r\n".to_string(),
+                "250-DSN\r\n".to_string(),
+                "250-SMTPUTF8\r\n".to_string(),
+                "250 REQUIRETLS\r\n".to_string(),
+            ],
+            tunnel = server_name,
+            config_arc = config.clone(),
+        };
+    }
+}
+
 #[should_panic]
 #[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
 async fn config_ill_formed() {
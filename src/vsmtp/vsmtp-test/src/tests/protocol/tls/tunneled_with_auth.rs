@@ -64,8 +64,13 @@ run_test! {
         "220 testserver.com Service ready\r\n",
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
-        "250 SMTPUTF8\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250-SMTPUTF8\r\n",
+        "250 REQUIRETLS\r\n",
         "334 \r\n",
         "235 2.7.0 Authentication succeeded\r\n",
         "250 Ok\r\n",
@@ -75,23 +80,101 @@ run_test! {
         "221 Service closing transmission channel\r\n",
     ],
     tunnel = "testserver.com",
-    config = {
-        let mut config = get_tls_auth_config();
-        config.app.vsl.domain_dir = Some("./src/template/sni".into());
-        config.server.r#virtual.insert(
-            "testserver.com".to_string(),
-            FieldServerVirtual {
-                tls: Some(
-                    FieldServerVirtualTls::from_path(
-                        "src/template/certs/certificate.crt",
-                        "src/template/certs/private_key.rsa.key",
-                    )
-                    .unwrap(),
-                ),
-                dns: None,
-                dkim: None,
-            },
-        );
-        config
-    },
+    config = get_tunneled_auth_config(),
+}
+
+fn get_tunneled_auth_config() -> Config {
+    let mut config = get_tls_auth_config();
+    config.app.vsl.domain_dir = Some("./src/template/sni".into());
+    config.server.r#virtual.insert(
+        "testserver.com".to_string(),
+        FieldServerVirtual {
+            tls: Some(
+                FieldServerVirtualTls::from_path(
+                    "src/template/certs/certificate.crt",
+                    "src/template/certs/private_key.rsa.key",
+                )
+                .unwrap(),
+            ),
+            dns: None,
+            dkim: None,
+            rcpt_count_max: None,
+            message_size_limit: None,
+            codes: std::collections::BTreeMap::new(),
+        },
+    );
+    config
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn auth_does_not_leak_to_cleartext_reconnect() {
+    let config = std::sync::Arc::new(get_tunneled_auth_config());
+
+    run_test! {
+        input = [
+            "EHLO client.com\r\n",
+            "AUTH PLAIN\r\n",
+            &format!("{}\r\n", STANDARD.encode("\0hello\0world")),
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            ".\r\n",
+            "EHLO client.com\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5\r\n",
+            "250-SIZE 10000000\r\n",
+            "250-8BITMIME\r\n",
+            "250-CHUNKING\r\n",
+            "250-PIPELINING\r\n",
+            "250-DSN\r\n",
+            "250-SMTPUTF8\r\n",
+            "250 REQUIRETLS\r\n",
+            "334 \r\n",
+            "235 2.7.0 Authentication succeeded\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            // the connection is still under TLS after the first transaction.
+            "250-testserver.com\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5\r\n",
+            "250-SIZE 10000000\r\n",
+            "250-8BITMIME\r\n",
+            "250-CHUNKING\r\n",
+            "250-PIPELINING\r\n",
+            "250-DSN\r\n",
+            "250-SMTPUTF8\r\n",
+            "250 REQUIRETLS\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        tunnel = "testserver.com",
+        config_arc = config.clone(),
+    };
+
+    run_test! {
+        input = [
+            "EHLO client.com\r\n",
+            "AUTH PLAIN\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-AUTH \r\n",
+            "250-STARTTLS\r\n",
+            "250-SIZE 10000000\r\n",
+            "250-8BITMIME\r\n",
+            "250-CHUNKING\r\n",
+            "250-PIPELINING\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "538 5.7.11 Encryption required for requested authentication mechanism\r\n",
+            "250 Ok\r\n",
+        ],
+        config_arc = config,
+    };
 }
@@ -0,0 +1,73 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_common::CodeID;
+
+run_test! {
+    fn transaction_count_on_quit,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john1@doe>\r\n",
+        "RCPT TO:<aa1@bb>\r\n",
+        "DATA\r\n",
+        concat!(
+            "from: john1 doe <john1@doe>\r\n",
+            "date: tue, 30 nov 2021 20:54:27 +0100\r\n",
+            "\r\n",
+            "mail 1\r\n",
+            ".\r\n",
+        ),
+        "MAIL FROM:<john2@doe>\r\n",
+        "RCPT TO:<aa2@bb>\r\n",
+        "RSET\r\n",
+        "MAIL FROM:<john3@doe>\r\n",
+        "RCPT TO:<aa3@bb>\r\n",
+        "DATA\r\n",
+        concat!(
+            "from: john3 doe <john3@doe>\r\n",
+            "date: tue, 30 nov 2021 20:54:27 +0100\r\n",
+            "\r\n",
+            "mail 3\r\n",
+            ".\r\n",
+        ),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Bye, 2 message(s) accepted\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.smtp.codes.insert(
+            CodeID::Closing,
+            "221 Bye, {transaction_count} message(s) accepted\r\n".parse().unwrap(),
+        );
+        config
+    },
+}
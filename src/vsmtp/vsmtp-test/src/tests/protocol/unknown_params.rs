@@ -0,0 +1,71 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_common::UnknownParamsPolicy;
+
+run_test! {
+    fn unknown_reject,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a@b> FUTURE=x\r\n",
+        "MAIL FROM:<a@b> BODY=8BITMIME future\r\n",
+        "MAIL FROM:<a@b> SMTPUTF8=x\r\n",
+        "MAIL FROM:<a@b> BODY=8BITMIME\r\n",
+        "RCPT TO:<b@c> NOTIFY=NEVER X-FUTURE=x\r\n",
+        "RCPT TO:<b@c> ORCPT\r\n",
+        "RCPT TO:<b@c> NOTIFY=NEVER\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "555 5.5.4 Unsupported parameter\r\n",
+        "555 5.5.4 Unsupported parameter\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "555 5.5.4 Unsupported parameter\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+}
+
+run_test! {
+    fn unknown_ignore,
+    input = [
+        "HELO foo\r\n",
+        "MAIL FROM:<a@b> SMTPUTF8=x\r\n",
+        "MAIL FROM:<a@b> FUTURE=x BODY=8BITMIME\r\n",
+        "RCPT TO:<b@c> ORCPT\r\n",
+        "RCPT TO:<b@c> X-FUTURE=x NOTIFY=NEVER\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = crate::config::local_test();
+        config.server.smtp.unknown_params = UnknownParamsPolicy::Ignore;
+        config
+    },
+}
@@ -0,0 +1,286 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::config;
+use crate::run_test;
+use vsmtp_common::CodeID;
+use vsmtp_config::field::FieldServerVirtual;
+
+run_test! {
+    fn rcpt_count_max_override,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "RCPT TO:<aa@example.com>\r\n",
+        "RCPT TO:<bb@example.com>\r\n",
+        "RCPT TO:<cc@bb>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "452 4.5.3 Too many recipients for example.com\r\n",
+        "452 4.5.3 Too many recipients for example.com\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "example.com".to_string(),
+            FieldServerVirtual {
+                rcpt_count_max: Some(2),
+                codes: [(
+                    CodeID::TooManyRecipients,
+                    "452 4.5.3 Too many recipients for example.com\r\n".parse().unwrap(),
+                )]
+                .into_iter()
+                .collect(),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+}
+
+run_test! {
+    fn message_size_limit_most_restrictive,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@example.com>\r\n",
+        "RCPT TO:<aa@small.com>\r\n",
+        "DATA\r\n",
+        &("X".repeat(1_000) + "\r\n.\r\n"),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "example.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(100_000),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config.server.r#virtual.insert(
+            "small.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(500),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+}
+
+run_test! {
+    fn message_size_limit_other_domain,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@small.com>\r\n",
+        "RSET\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@example.com>\r\n",
+        "DATA\r\n",
+        &("X".repeat(1_000) + "\r\n.\r\n"),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "small.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(500),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+}
+
+run_test! {
+    fn message_size_limit_multiline,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@small.com>\r\n",
+        "DATA\r\n",
+        &("X".repeat(300) + "\r\n").repeat(5),
+        ".\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "small.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(500),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+}
+
+run_test! {
+    fn message_size_limit_declared,
+    input = [
+        "EHLO foobar\r\n",
+        "MAIL FROM:<john@doe> SIZE=1000\r\n",
+        "RCPT TO:<aa@example.com>\r\n",
+        "RCPT TO:<aa@small.com>\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250-testserver.com\r\n",
+        "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
+        "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
+        "250 SMTPUTF8\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "small.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(500),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+}
+
+run_test! {
+    fn message_size_limit_refused_rcpt,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@small.com>\r\n",
+        "RCPT TO:<aa@example.com>\r\n",
+        "DATA\r\n",
+        &("X".repeat(1_000) + "\r\n.\r\n"),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "451 4.7.1 please retry later\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "small.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(500),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+    hierarchy_builder = |builder| {
+        Ok(builder.add_root_filter_rules(r#"#{
+            rcpt: [
+              rule "retry later" || {
+                if ctx::rcpt().domain == "small.com" {
+                  state::info("451 4.7.1 please retry later")
+                } else {
+                  state::next()
+                }
+              }
+            ],
+        }"#)?.build())
+    },
+}
+
+run_test! {
+    fn message_size_limit_case_insensitive,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@Small.COM>\r\n",
+        "DATA\r\n",
+        &("X".repeat(1_000) + "\r\n.\r\n"),
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = {
+        let mut config = config::local_test();
+        config.server.r#virtual.insert(
+            "small.com".to_string(),
+            FieldServerVirtual {
+                message_size_limit: Some(500),
+                ..FieldServerVirtual::default()
+            },
+        );
+        config
+    },
+}
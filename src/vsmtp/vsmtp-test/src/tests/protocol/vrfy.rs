@@ -14,20 +14,162 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+use crate::{config::local_test, run_test};
+use vsmtp_common::VerifyPolicy;
+use vsmtp_config::field::FieldServerRateLimit;
 
-use crate::run_test;
+const VERIFY_RULES: &str = r#"
+fn vrfy(query) {
+  if query == "john" { "John Doe <john@doe.com>" }
+}
+
+fn expn(list) {
+  if list == "staff" { ["john@doe.com", "jane@doe.com"] }
+}
+
+#{}
+"#;
+
+const INJECTION_RULES: &str = r#"
+fn vrfy(query) {
+  if query == "john" { "John Doe <john@doe.com>\r\n250 Ok" }
+  else if query == "jane" { "\r\n" }
+}
+
+#{}
+"#;
 
 run_test! {
-    fn vrfy_unimplemented,
+    fn vrfy_default,
     input = [
         "HELO foo\r\n",
         "VRFY foobar\r\n",
+        "EXPN staff\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "252 2.1.5 Cannot VRFY user, but will accept message and attempt delivery\r\n",
+        "502 5.5.1 EXPN command is disabled\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+}
+
+run_test! {
+    fn vrfy_syntax_error,
+    input = [
+        "HELO foo\r\n",
+        "VRFY\r\n",
+        "EXPN   \r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "501 Syntax error in parameters or arguments\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+}
+
+run_test! {
+    fn vrfy_callback,
+    input = [
+        "HELO foo\r\n",
+        "VRFY john\r\n",
+        "VRFY jane\r\n",
+        "EXPN staff\r\n",
+        "EXPN sales\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 2.1.5 John Doe <john@doe.com>\r\n",
+        "550 5.1.1 String does not match anything\r\n",
+        "250-2.1.5 john@doe.com\r\n",
+        "250 2.1.5 jane@doe.com\r\n",
+        "550 5.1.1 String does not match anything\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    hierarchy_builder = |builder| {
+        Ok(builder.add_root_filter_rules(VERIFY_RULES).unwrap().build())
+    }
+}
+
+run_test! {
+    fn vrfy_disabled,
+    input = [
+        "HELO foo\r\n",
+        "VRFY john\r\n",
+        "EXPN staff\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "252 2.1.5 Cannot VRFY user, but will accept message and attempt delivery\r\n",
+        "502 5.5.1 EXPN command is disabled\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    config = {
+        let mut config = local_test();
+        config.server.smtp.vrfy = VerifyPolicy::Disabled;
+        config.server.smtp.expn = VerifyPolicy::Disabled;
+        config
+    },
+    hierarchy_builder = |builder| {
+        Ok(builder.add_root_filter_rules(VERIFY_RULES).unwrap().build())
+    }
+}
+
+run_test! {
+    fn vrfy_line_injection,
+    input = [
+        "HELO foo\r\n",
+        "VRFY john\r\n",
+        "VRFY jane\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 2.1.5 John Doe <john@doe.com>250 Ok\r\n",
+        "550 5.1.1 String does not match anything\r\n",
+        "221 Service closing transmission channel\r\n"
+    ],
+    hierarchy_builder = |builder| {
+        Ok(builder.add_root_filter_rules(INJECTION_RULES).unwrap().build())
+    }
+}
+
+run_test! {
+    fn vrfy_rate_limited,
+    input = [
+        "HELO foo\r\n",
+        "VRFY john\r\n",
+        "EXPN staff\r\n",
         "QUIT\r\n",
     ],
     expected = [
         "220 testserver.com Service ready\r\n",
         "250 Ok\r\n",
-        "502 Command not implemented\r\n",
+        "250 2.1.5 John Doe <john@doe.com>\r\n",
+        "452 4.7.0 Rate limit exceeded, retry later\r\n",
         "221 Service closing transmission channel\r\n"
     ],
+    config = {
+        let mut config = local_test();
+        config.server.rate_limit = Some(FieldServerRateLimit {
+            connection_count_max: None,
+            message_per_minute: None,
+            rcpt_per_hour: Some(1),
+            cleanup_period: std::time::Duration::from_secs(60),
+        });
+        config
+    },
+    hierarchy_builder = |builder| {
+        Ok(builder.add_root_filter_rules(VERIFY_RULES).unwrap().build())
+    }
 }
@@ -0,0 +1,98 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+
+const OK: &str = "250 Ok\r\n";
+const SYNTAX_ERROR: &str = "501 Syntax error in parameters or arguments\r\n";
+const UNSUPPORTED: &str = "555 5.5.4 Unsupported parameter\r\n";
+
+fn run(command: &str, strict_syntax: bool, expected: &str) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let (mut input, mut output) = (
+        vec!["HELO foo\r\n".to_string()],
+        vec![
+            "220 testserver.com Service ready\r\n".to_string(),
+            OK.to_string(),
+        ],
+    );
+    if !command.starts_with("MAIL") {
+        input.push("MAIL FROM:<foo@bar>\r\n".to_string());
+        output.push(OK.to_string());
+    }
+    input.extend([command.to_string(), "QUIT\r\n".to_string()]);
+    output.extend([
+        expected.to_string(),
+        "221 Service closing transmission channel\r\n".to_string(),
+    ]);
+
+    runtime.block_on(async move {
+        run_test! {
+            input = input,
+            expected = output,
+            config = {
+                let mut config = crate::config::local_test();
+                config.server.smtp.strict_syntax = strict_syntax;
+                config
+            },
+        };
+    });
+}
+
+#[rstest::rstest]
+#[case::no_space("RCPT TO:<bar@foo>\r\n", OK, OK)]
+#[case::null_sender("MAIL FROM:<>\r\n", OK, OK)]
+#[case::params("MAIL FROM:<foo@bar> BODY=8BITMIME\r\n", OK, OK)]
+#[case::space_after_colon("RCPT TO: <bar@foo>\r\n", OK, SYNTAX_ERROR)]
+#[case::tab_after_colon("MAIL FROM:\t<foo@bar>\r\n", OK, SYNTAX_ERROR)]
+#[case::trailing_space("RCPT TO:<bar@foo> \r\n", OK, SYNTAX_ERROR)]
+#[case::spaces_between_params("MAIL FROM:<foo@bar>   BODY=7BIT\r\n", OK, SYNTAX_ERROR)]
+#[case::space_in_path("RCPT TO:< bar@foo >\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::no_brackets("RCPT TO:bar@foo\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::no_closing_bracket("MAIL FROM:<foo@bar\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::glued_param("MAIL FROM:<foo@bar>BODY=7BIT\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::space_before_colon(
+    "RCPT TO :<bar@foo>\r\n",
+    "500 Syntax error command unrecognized\r\n",
+    "500 Syntax error command unrecognized\r\n"
+)]
+#[trace]
+fn whitespace(#[case] command: &str, #[case] lenient: &str, #[case] strict: &str) {
+    run(command, false, lenient);
+    run(command, true, strict);
+}
+
+#[rstest::rstest]
+#[case::trailing_space("RCPT TO:<a@b> \r\n", OK, SYNTAX_ERROR)]
+#[case::extra_spaces("RCPT TO:<a@b>  NOTIFY=SUCCESS\r\n", OK, SYNTAX_ERROR)]
+#[case::extra_spaces_after("RCPT TO:<a@b> NOTIFY=SUCCESS  \r\n", OK, SYNTAX_ERROR)]
+#[case::keyword_only("MAIL FROM:<a@b> SMTPUTF8\r\n", OK, OK)]
+// NOTE: the parameters not supported by the server were ignored before, they are
+//       now refused by default (`server.smtp.unknown_params`).
+#[case::keyword_only_unsupported("RCPT TO:<a@b> SMTPUTF8\r\n", UNSUPPORTED, UNSUPPORTED)]
+#[case::empty_value("RCPT TO:<a@b> NOTIFY=\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::empty_keyword("RCPT TO:<a@b> =SUCCESS\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::invalid_keyword("RCPT TO:<a@b> NO_TIFY=SUCCESS\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[case::invalid_value("RCPT TO:<a@b> NOTIFY=SUC=CESS\r\n", SYNTAX_ERROR, SYNTAX_ERROR)]
+#[trace]
+fn params(#[case] command: &str, #[case] lenient: &str, #[case] strict: &str) {
+    run(command, false, lenient);
+    run(command, true, strict);
+}
@@ -0,0 +1,91 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn envelop_rewritten_in_queued_message() {
+    run_test! {
+        input = [
+            "HELO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "RCPT TO:<archive@foo>\r\n",
+            "DATA\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    ctx: Box<ContextFinished>,
+                    _: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    let mut rcpt = ctx
+                        .rcpt_to
+                        .forward_paths
+                        .iter()
+                        .map(|rcpt| rcpt.address.full())
+                        .collect::<Vec<_>>();
+                    rcpt.sort_unstable();
+
+                    if ctx.mail_from.reverse_path.as_ref().map(vsmtp_common::Address::full)
+                        == Some("no-reply@bar")
+                        && rcpt == ["bar@foo", "team@foo"]
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+        hierarchy_builder = |builder| {
+            Ok(builder.add_root_filter_rules(r#"#{
+                preq: [
+                  action "rewrite envelop" || {
+                    ctx::set_mail_from("no-reply@bar");
+                    ctx::add_rcpt("team@foo");
+                    ctx::add_rcpt(address("bar@foo"));
+                    ctx::remove_rcpt("archive@foo");
+                  },
+                ],
+              }
+            "#)?.build())
+        },
+    };
+}
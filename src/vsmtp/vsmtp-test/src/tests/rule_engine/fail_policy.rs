@@ -0,0 +1,73 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{config::local_test, run_test};
+use vsmtp_config::field::RuleEngineFailPolicy;
+
+const BUGGY_RULE: &str = r#"
+#{
+    helo: [
+        rule "buggy" || { this_function_does_not_exist() }
+    ]
+}
+"#;
+
+fn config(fail_policy: RuleEngineFailPolicy) -> vsmtp_config::Config {
+    let mut config = local_test();
+    config.app.vsl.fail_policy = fail_policy;
+    config
+}
+
+run_test! {
+    fn fail_policy_deny,
+    input = [
+        "HELO foobar\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "554 permanent problems with the remote server\r\n",
+    ],
+    config = config(RuleEngineFailPolicy::Deny),
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(BUGGY_RULE)?.build()),
+}
+
+run_test! {
+    fn fail_policy_open,
+    input = [
+        "HELO foobar\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    config = config(RuleEngineFailPolicy::FailOpen),
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(BUGGY_RULE)?.build()),
+}
+
+run_test! {
+    fn fail_policy_closed,
+    input = [
+        "HELO foobar\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "451 4.3.0 Requested action aborted: local error in processing\r\n",
+    ],
+    config = config(RuleEngineFailPolicy::FailClosed),
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(BUGGY_RULE)?.build()),
+}
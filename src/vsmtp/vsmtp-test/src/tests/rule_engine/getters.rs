@@ -37,7 +37,11 @@ run_test! {
         "250-testserver.com\r\n",
         "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
         "250-STARTTLS\r\n",
+        "250-SIZE 10000000\r\n",
         "250-8BITMIME\r\n",
+        "250-CHUNKING\r\n",
+        "250-PIPELINING\r\n",
+        "250-DSN\r\n",
         "250 SMTPUTF8\r\n",
         "235 2.7.0 Authentication succeeded\r\n",
         "250 Ok\r\n",
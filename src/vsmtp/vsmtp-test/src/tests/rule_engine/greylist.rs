@@ -0,0 +1,125 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_rule_engine::greylist::{record_delivery, GreylistStore, MemoryStore};
+
+const RULES: &str = r#"#{
+    rcpt: [
+      rule "greylist" || greylist(),
+    ],
+}
+"#;
+
+fn config(auto_whitelist: Option<u32>) -> vsmtp_config::Config {
+    let mut config = crate::config::local_test();
+    config.app.greylist.delay = std::time::Duration::ZERO;
+    config.app.greylist.auto_whitelist = auto_whitelist;
+    config
+}
+
+macro_rules! session {
+    ($greylist_store:expr, $config:expr, $rcpt:expr => $reply:expr) => {
+        run_test! {
+            input = [
+                "HELO foobar\r\n",
+                "MAIL FROM:<john@doe>\r\n",
+                concat!("RCPT TO:<", $rcpt, ">\r\n"),
+            ],
+            expected = [
+                "220 testserver.com Service ready\r\n",
+                "250 Ok\r\n",
+                "250 Ok\r\n",
+                $reply,
+            ],
+            config = $config,
+            greylist_store = $greylist_store,
+            hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(RULES)?.build()),
+        }
+    };
+}
+
+const GREYLISTED: &str = "451 4.7.1 Greylisted, please retry later\r\n";
+
+run_test! {
+    fn first_contact_greylisted,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "451 4.7.1 Greylisted, please retry later\r\n",
+    ],
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(RULES)?.build()),
+}
+
+run_test! {
+    fn greylisted_rcpt_keeps_session,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john@doe>\r\n",
+        "RCPT TO:<aa@bb>\r\n",
+        "RCPT TO:<cc@dd>\r\n",
+        "DATA\r\n",
+        "QUIT\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "451 4.7.1 Greylisted, please retry later\r\n",
+        "451 4.7.1 Greylisted, please retry later\r\n",
+        "503 Bad sequence of commands\r\n",
+        "221 Service closing transmission channel\r\n",
+    ],
+    hierarchy_builder = |builder| Ok(builder.add_root_filter_rules(RULES)?.build()),
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn retry_after_delay() {
+    let store: std::sync::Arc<dyn GreylistStore> = std::sync::Arc::new(MemoryStore::default());
+
+    let first = store.clone();
+    session!(first, config(None), "aa@bb" => GREYLISTED);
+    let retry = store.clone();
+    session!(retry, config(None), "aa@bb" => "250 Ok\r\n");
+    // another triplet of the same client
+    let other = store.clone();
+    session!(other, config(None), "cc@dd" => GREYLISTED);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn auto_whitelist() {
+    let store: std::sync::Arc<dyn GreylistStore> = std::sync::Arc::new(MemoryStore::default());
+
+    let first = store.clone();
+    session!(first, config(Some(1)), "aa@bb" => GREYLISTED);
+    let retry = store.clone();
+    session!(retry, config(Some(1)), "aa@bb" => "250 Ok\r\n");
+    record_delivery(
+        &*store,
+        &config(Some(1)).app.greylist,
+        std::net::Ipv4Addr::LOCALHOST.into(),
+        std::time::SystemTime::now(),
+    )
+    .unwrap();
+    let other = store.clone();
+    session!(other, config(Some(1)), "cc@dd" => "250 Ok\r\n");
+}
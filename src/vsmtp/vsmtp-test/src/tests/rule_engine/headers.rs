@@ -0,0 +1,85 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vqueue::GenericQueueManager;
+use vsmtp_common::{CodeID, ContextFinished};
+use vsmtp_mail_parser::MessageBody;
+use vsmtp_server::OnMail;
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn headers_modified_in_queued_message() {
+    run_test! {
+        input = [
+            "HELO client.com\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<bar@foo>\r\n",
+            "DATA\r\n",
+            "X-Spam-Score: 10\r\n",
+            "Subject: hello\r\n",
+            "\r\n",
+            "body\r\n",
+            ".\r\n",
+            "QUIT\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ],
+        mail_handler = {
+            struct T;
+
+            #[async_trait::async_trait]
+            impl OnMail for T {
+                async fn on_mail(
+                    &mut self,
+                    _: Box<ContextFinished>,
+                    message: MessageBody,
+                    _: std::sync::Arc<dyn GenericQueueManager>,
+                ) -> CodeID {
+                    if message.get_header("X-Spam-Flag").as_deref() == Some("YES")
+                        && message.get_header("X-Spam-Score").is_none()
+                        && message.get_header("Subject").as_deref() == Some("[SPAM] hello")
+                        && message.inner().to_string().ends_with("\r\nbody\r\n")
+                    {
+                        CodeID::Ok
+                    } else {
+                        CodeID::Denied
+                    }
+                }
+            }
+
+            T
+        },
+        hierarchy_builder = |builder| {
+            Ok(builder.add_root_filter_rules(r#"#{
+                preq: [
+                  action "flag as spam" || {
+                    msg::add_header("X-Spam-Flag", "YES");
+                    msg::set_header("Subject", `[SPAM] ${msg::get_header("Subject")}`);
+                    msg::remove_header("X-Spam-Score");
+                  },
+                ],
+              }
+            "#)?.build())
+        },
+    };
+}
@@ -0,0 +1,68 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::run_test;
+use vsmtp_config::field::FieldAppLookup;
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn unknown_rcpt_denied() {
+    let users = std::env::temp_dir().join(format!("vsmtp-users-{}", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &users,
+        "# users of doe.com\njohn@doe.com\njane@doe.com mailbox/jane\n",
+    )
+    .unwrap();
+
+    let mut config = crate::config::local_test();
+    config.app.rcpt_lookup = Some(FieldAppLookup::File {
+        path: users.clone(),
+    });
+
+    run_test! {
+        input = [
+            "HELO foobar\r\n",
+            "MAIL FROM:<foo@bar>\r\n",
+            "RCPT TO:<john@doe.com>\r\n",
+            "RCPT TO:<jane@doe.com>\r\n",
+            "RCPT TO:<james@doe.com>\r\n",
+        ],
+        expected = [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "550 5.1.1 No such user\r\n",
+        ],
+        config = config,
+        hierarchy_builder = |builder| {
+            Ok(builder.add_root_filter_rules(r#"#{
+                rcpt: [
+                  rule "unknown users" || {
+                    if rcpt_exists(ctx::rcpt()) {
+                      state::next()
+                    } else {
+                      state::deny(code(550, "5.1.1", "No such user"))
+                    }
+                  },
+                ],
+              }
+            "#)?.build())
+        },
+    };
+
+    std::fs::remove_file(users).unwrap();
+}
@@ -0,0 +1,104 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{config::local_test, run_test};
+use vqueue::GenericQueueManager;
+use vsmtp_config::DnsResolvers;
+use vsmtp_rule_engine::RuleEngine;
+
+fn config(stage_scripts: &[(&str, &str)]) -> vsmtp_config::Config {
+    let mut config = local_test();
+    config.app.vsl.stage_scripts = stage_scripts
+        .iter()
+        .map(|(stage, script)| {
+            (
+                (*stage).to_string(),
+                std::path::PathBuf::from_iter([
+                    env!("CARGO_MANIFEST_DIR"),
+                    "src/tests/rule_engine/stage_scripts",
+                    script,
+                ]),
+            )
+        })
+        .collect();
+    config
+}
+
+run_test! {
+    fn each_stage_runs_its_script,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john.doe@example.com>\r\n",
+    ],
+    expected = [
+        "220 connect script\r\n",
+        "250 helo script\r\n",
+        "550 mail script\r\n",
+    ],
+    config = config(&[
+        ("connect", "connect.vsl"),
+        ("helo", "helo.vsl"),
+        ("mail", "mail.vsl"),
+    ]),
+}
+
+run_test! {
+    fn data_stage_runs_the_preq_script,
+    input = [
+        "HELO foobar\r\n",
+        "MAIL FROM:<john.doe@example.com>\r\n",
+        "RCPT TO:<jenny.doe@example.com>\r\n",
+        "DATA\r\n",
+        ".\r\n",
+    ],
+    expected = [
+        "220 testserver.com Service ready\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "250 Ok\r\n",
+        "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+        "550 data script\r\n",
+    ],
+    config = config(&[("data", "data.vsl")]),
+}
+
+fn rule_engine(config: vsmtp_config::Config) -> anyhow::Result<RuleEngine> {
+    let config = std::sync::Arc::new(config);
+    let queue_manager = vqueue::temp::QueueManager::init(config.clone()).unwrap();
+    let resolvers = std::sync::Arc::new(DnsResolvers::from_config(&config).unwrap());
+
+    RuleEngine::new(config, resolvers, queue_manager)
+}
+
+#[test]
+fn unknown_stage() {
+    assert!(rule_engine(config(&[("body", "mail.vsl")])).is_err());
+}
+
+#[test]
+fn data_and_preq_stages() {
+    assert!(rule_engine(config(&[("data", "data.vsl"), ("preq", "data.vsl")])).is_err());
+}
+
+#[test]
+fn missing_script() {
+    assert!(rule_engine(config(&[("helo", "missing.vsl")])).is_err());
+}
+
+#[test]
+fn rules_of_another_stage() {
+    assert!(rule_engine(config(&[("helo", "other_stage.vsl")])).is_err());
+}